@@ -146,7 +146,7 @@ pub struct Config {
 }
 
 /// Network type
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum NetworkType {
     /// Main network
     Mainnet,
@@ -0,0 +1,698 @@
+// Proof-of-work targets, chain work and difficulty retargeting
+//
+// Headers carry their target in Bitcoin's compact "nBits" form: the high byte is
+// a base-256 exponent and the low three bytes a signed mantissa, expanding to a
+// 256-bit threshold. A header hash, read as a big-endian 256-bit integer, must not
+// exceed that threshold. The miner, chain state and header sync all compute the
+// expected target for the next block with `next_work_required`.
+
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::fmt;
+use std::ops::{Not, Shl, Shr};
+use thiserror::Error;
+use crate::types::block::BlockHeader;
+
+/// Easiest target allowed on mainnet
+pub const POW_LIMIT_BITS: u32 = 0x1d00ffff;
+
+/// Easiest target allowed on regtest; roughly every other hash meets it
+pub const REGTEST_POW_LIMIT_BITS: u32 = 0x207fffff;
+
+/// Desired time between blocks in seconds
+pub const TARGET_BLOCK_SPACING: u64 = 60;
+
+/// Blocks between per-interval retargets
+pub const DIFFICULTY_ADJUSTMENT_INTERVAL: u64 = 2016;
+
+/// Largest factor a per-interval retarget may move the target by
+pub const MAX_ADJUSTMENT_FACTOR: u64 = 4;
+
+/// Seconds of schedule drift that halve or double an ASERT target
+pub const DEFAULT_ASERT_HALF_LIFE: u64 = 4 * 60 * 60;
+
+/// Errors raised while decoding targets or computing the next one
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum DifficultyError {
+    #[error("Invalid compact target {0:08x}")]
+    InvalidBits(u32),
+
+    #[error("Target {0:08x} is easier than the proof-of-work limit")]
+    AboveLimit(u32),
+
+    #[error("Block hash does not meet target {0:08x}")]
+    HighHash(u32),
+
+    #[error("Header at height {0} is not available")]
+    MissingHeader(u64),
+}
+
+/// 256-bit unsigned integer used for targets and chain work
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub struct U256([u64; 4]); // little-endian limbs
+
+impl U256 {
+    pub const ZERO: U256 = U256([0; 4]);
+    pub const ONE: U256 = U256([1, 0, 0, 0]);
+    pub const MAX: U256 = U256([u64::MAX; 4]);
+
+    /// Interpret 32 bytes as a big-endian integer (the byte order hashes are compared in)
+    pub fn from_be_bytes(bytes: [u8; 32]) -> Self {
+        let mut limbs = [0u64; 4];
+        for (i, limb) in limbs.iter_mut().enumerate() {
+            let start = 32 - (i + 1) * 8;
+            *limb = u64::from_be_bytes(bytes[start..start + 8].try_into().unwrap());
+        }
+        U256(limbs)
+    }
+
+    pub fn to_be_bytes(&self) -> [u8; 32] {
+        let mut bytes = [0u8; 32];
+        for (i, limb) in self.0.iter().enumerate() {
+            let start = 32 - (i + 1) * 8;
+            bytes[start..start + 8].copy_from_slice(&limb.to_be_bytes());
+        }
+        bytes
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.0.iter().all(|&limb| limb == 0)
+    }
+
+    /// Number of significant bits
+    pub fn bits(&self) -> u32 {
+        for i in (0..4).rev() {
+            if self.0[i] != 0 {
+                return 64 * i as u32 + (64 - self.0[i].leading_zeros());
+            }
+        }
+        0
+    }
+
+    /// Lowest 64 bits
+    pub fn low_u64(&self) -> u64 {
+        self.0[0]
+    }
+
+    /// Value as a `u64`, saturating at `u64::MAX`
+    pub fn saturating_u64(&self) -> u64 {
+        if self.bits() > 64 {
+            u64::MAX
+        } else {
+            self.0[0]
+        }
+    }
+
+    /// Approximate value as a float
+    pub fn to_f64(&self) -> f64 {
+        self.0
+            .iter()
+            .rev()
+            .fold(0.0, |acc, &limb| acc * 18_446_744_073_709_551_616.0 + limb as f64)
+    }
+
+    pub fn checked_add(&self, other: U256) -> Option<U256> {
+        let mut result = [0u64; 4];
+        let mut carry = false;
+        for (i, limb) in result.iter_mut().enumerate() {
+            let (sum, c1) = self.0[i].overflowing_add(other.0[i]);
+            let (sum, c2) = sum.overflowing_add(carry as u64);
+            *limb = sum;
+            carry = c1 || c2;
+        }
+        (!carry).then_some(U256(result))
+    }
+
+    pub fn saturating_add(&self, other: U256) -> U256 {
+        self.checked_add(other).unwrap_or(U256::MAX)
+    }
+
+    fn overflowing_sub(&self, other: U256) -> (U256, bool) {
+        let mut result = [0u64; 4];
+        let mut borrow = false;
+        for (i, limb) in result.iter_mut().enumerate() {
+            let (diff, b1) = self.0[i].overflowing_sub(other.0[i]);
+            let (diff, b2) = diff.overflowing_sub(borrow as u64);
+            *limb = diff;
+            borrow = b1 || b2;
+        }
+        (U256(result), borrow)
+    }
+
+    pub fn checked_sub(&self, other: U256) -> Option<U256> {
+        let (result, borrow) = self.overflowing_sub(other);
+        (!borrow).then_some(result)
+    }
+
+    pub fn saturating_sub(&self, other: U256) -> U256 {
+        self.checked_sub(other).unwrap_or(U256::ZERO)
+    }
+
+    pub fn checked_mul_u64(&self, factor: u64) -> Option<U256> {
+        let mut result = [0u64; 4];
+        let mut carry = 0u128;
+        for (i, limb) in result.iter_mut().enumerate() {
+            let product = self.0[i] as u128 * factor as u128 + carry;
+            *limb = product as u64;
+            carry = product >> 64;
+        }
+        (carry == 0).then_some(U256(result))
+    }
+
+    /// Quotient and remainder, or `None` when dividing by zero
+    pub fn checked_div_rem(&self, divisor: U256) -> Option<(U256, U256)> {
+        if divisor.is_zero() {
+            return None;
+        }
+
+        let mut quotient = U256::ZERO;
+        let mut remainder = U256::ZERO;
+        for i in (0..self.bits()).rev() {
+            let carry = remainder.bit(255);
+            remainder = remainder << 1;
+            if self.bit(i) {
+                remainder.0[0] |= 1;
+            }
+            if carry || remainder >= divisor {
+                remainder = remainder.overflowing_sub(divisor).0;
+                quotient.0[(i / 64) as usize] |= 1 << (i % 64);
+            }
+        }
+        Some((quotient, remainder))
+    }
+
+    pub fn checked_div(&self, divisor: U256) -> Option<U256> {
+        self.checked_div_rem(divisor).map(|(quotient, _)| quotient)
+    }
+
+    fn bit(&self, index: u32) -> bool {
+        (self.0[(index / 64) as usize] >> (index % 64)) & 1 == 1
+    }
+}
+
+impl From<u64> for U256 {
+    fn from(value: u64) -> Self {
+        U256([value, 0, 0, 0])
+    }
+}
+
+impl Ord for U256 {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.iter().rev().cmp(other.0.iter().rev())
+    }
+}
+
+impl PartialOrd for U256 {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Not for U256 {
+    type Output = U256;
+
+    fn not(self) -> U256 {
+        U256(self.0.map(|limb| !limb))
+    }
+}
+
+impl Shl<u32> for U256 {
+    type Output = U256;
+
+    fn shl(self, shift: u32) -> U256 {
+        if shift >= 256 {
+            return U256::ZERO;
+        }
+        let limb_shift = (shift / 64) as usize;
+        let bit_shift = shift % 64;
+        let mut result = [0u64; 4];
+        for (i, limb) in result.iter_mut().enumerate().skip(limb_shift) {
+            let src = i - limb_shift;
+            *limb = self.0[src] << bit_shift;
+            if bit_shift > 0 && src > 0 {
+                *limb |= self.0[src - 1] >> (64 - bit_shift);
+            }
+        }
+        U256(result)
+    }
+}
+
+impl Shr<u32> for U256 {
+    type Output = U256;
+
+    fn shr(self, shift: u32) -> U256 {
+        if shift >= 256 {
+            return U256::ZERO;
+        }
+        let limb_shift = (shift / 64) as usize;
+        let bit_shift = shift % 64;
+        let mut result = [0u64; 4];
+        for (i, limb) in result.iter_mut().enumerate().take(4 - limb_shift) {
+            let src = i + limb_shift;
+            *limb = self.0[src] >> bit_shift;
+            if bit_shift > 0 && src + 1 < 4 {
+                *limb |= self.0[src + 1] << (64 - bit_shift);
+            }
+        }
+        U256(result)
+    }
+}
+
+impl fmt::Display for U256 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", hex::encode(self.to_be_bytes()))
+    }
+}
+
+/// Expand a compact target
+///
+/// Negative, overflowing and zero targets are rejected.
+pub fn compact_to_target(bits: u32) -> Result<U256, DifficultyError> {
+    let size = bits >> 24;
+    let word = bits & 0x007f_ffff;
+
+    if word != 0 && bits & 0x0080_0000 != 0 {
+        return Err(DifficultyError::InvalidBits(bits));
+    }
+    if word != 0 && (size > 34 || (word > 0xff && size > 33) || (word > 0xffff && size > 32)) {
+        return Err(DifficultyError::InvalidBits(bits));
+    }
+
+    let target = if size <= 3 {
+        U256::from((word >> (8 * (3 - size))) as u64)
+    } else {
+        U256::from(word as u64) << (8 * (size - 3))
+    };
+
+    if target.is_zero() {
+        return Err(DifficultyError::InvalidBits(bits));
+    }
+    Ok(target)
+}
+
+/// Encode a target in compact form, truncating it to 24 bits of precision
+pub fn target_to_compact(target: &U256) -> u32 {
+    let mut size = target.bits().div_ceil(8);
+    let mut compact = if size <= 3 {
+        (target.low_u64() << (8 * (3 - size))) as u32
+    } else {
+        (*target >> (8 * (size - 3))).low_u64() as u32
+    };
+
+    // The mantissa is signed; move a set sign bit into the exponent
+    if compact & 0x0080_0000 != 0 {
+        compact >>= 8;
+        size += 1;
+    }
+    compact | (size << 24)
+}
+
+/// Whether a hash meets a compact target
+pub fn hash_meets_target(hash: &[u8; 32], bits: u32) -> bool {
+    match compact_to_target(bits) {
+        Ok(target) => U256::from_be_bytes(*hash) <= target,
+        Err(_) => false,
+    }
+}
+
+/// Check a header hash against its compact target and the chain's proof-of-work limit
+pub fn check_proof_of_work(hash: &[u8; 32], bits: u32, params: &DifficultyParams) -> Result<(), DifficultyError> {
+    let target = compact_to_target(bits)?;
+    if target > params.pow_limit() {
+        return Err(DifficultyError::AboveLimit(bits));
+    }
+    if U256::from_be_bytes(*hash) > target {
+        return Err(DifficultyError::HighHash(bits));
+    }
+    Ok(())
+}
+
+/// Expected number of hashes needed to meet a compact target: `2^256 / (target + 1)`
+///
+/// Invalid targets carry no work.
+pub fn block_work(bits: u32) -> U256 {
+    let target = match compact_to_target(bits) {
+        Ok(target) => target,
+        Err(_) => return U256::ZERO,
+    };
+
+    // 2^256 does not fit, but 2^256 / (t + 1) == (2^256 - t - 1) / (t + 1) + 1
+    match target.checked_add(U256::ONE) {
+        Some(divisor) => (!target)
+            .checked_div(divisor)
+            .and_then(|work| work.checked_add(U256::ONE))
+            .unwrap_or(U256::ONE),
+        None => U256::ONE,
+    }
+}
+
+/// Difficulty of a compact target relative to the proof-of-work limit
+pub fn target_difficulty(bits: u32, params: &DifficultyParams) -> f64 {
+    match compact_to_target(bits) {
+        Ok(target) => params.pow_limit().to_f64() / target.to_f64(),
+        Err(_) => 0.0,
+    }
+}
+
+/// How the target for the next block is derived from the chain
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RetargetAlgorithm {
+    /// Recompute once every `interval` blocks from the time the interval took,
+    /// moving by at most `max_adjustment_factor`
+    PerInterval { interval: u64 },
+    /// Recompute every block from how far the chain has drifted from its
+    /// schedule since the anchor block; every `half_life` seconds behind
+    /// schedule doubles the target and every `half_life` ahead halves it
+    Asert { half_life: u64, anchor_height: u64 },
+}
+
+/// Consensus parameters for proof of work
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DifficultyParams {
+    /// Easiest allowed target, in compact form; also the target of the first block
+    pub pow_limit_bits: u32,
+    /// Desired time between blocks in seconds
+    pub target_spacing: u64,
+    pub algorithm: RetargetAlgorithm,
+    /// Largest factor a per-interval retarget may move the target by
+    pub max_adjustment_factor: u64,
+    /// Keep every block at its parent's target
+    pub no_retargeting: bool,
+}
+
+impl DifficultyParams {
+    pub fn mainnet() -> Self {
+        Self {
+            pow_limit_bits: POW_LIMIT_BITS,
+            target_spacing: TARGET_BLOCK_SPACING,
+            algorithm: RetargetAlgorithm::PerInterval { interval: DIFFICULTY_ADJUSTMENT_INTERVAL },
+            max_adjustment_factor: MAX_ADJUSTMENT_FACTOR,
+            no_retargeting: false,
+        }
+    }
+
+    pub fn regtest() -> Self {
+        Self {
+            pow_limit_bits: REGTEST_POW_LIMIT_BITS,
+            no_retargeting: true,
+            ..Self::mainnet()
+        }
+    }
+
+    /// Expanded proof-of-work limit
+    pub fn pow_limit(&self) -> U256 {
+        compact_to_target(self.pow_limit_bits).unwrap_or(U256::MAX)
+    }
+}
+
+impl Default for DifficultyParams {
+    fn default() -> Self {
+        Self::mainnet()
+    }
+}
+
+/// The header fields retargeting reads
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HeaderInfo {
+    pub height: u64,
+    pub timestamp: u64,
+    pub bits: u32,
+}
+
+impl HeaderInfo {
+    pub fn new(height: u64, header: &BlockHeader) -> Self {
+        Self {
+            height,
+            timestamp: header.timestamp(),
+            bits: header.target(),
+        }
+    }
+}
+
+/// Access to earlier headers of the chain a new block extends
+pub trait HeaderLookup {
+    fn header_at(&self, height: u64) -> Option<HeaderInfo>;
+}
+
+/// Consecutive headers, lowest height first
+impl HeaderLookup for [HeaderInfo] {
+    fn header_at(&self, height: u64) -> Option<HeaderInfo> {
+        let first = self.first()?;
+        let index = height.checked_sub(first.height)?;
+        self.get(usize::try_from(index).ok()?).copied()
+    }
+}
+
+/// Compact target required for the block after `prev` (`None` for the first block)
+pub fn next_work_required<C: HeaderLookup + ?Sized>(
+    params: &DifficultyParams,
+    prev: Option<&HeaderInfo>,
+    chain: &C,
+) -> Result<u32, DifficultyError> {
+    let prev = match prev {
+        Some(prev) => prev,
+        None => return Ok(params.pow_limit_bits),
+    };
+    if params.no_retargeting {
+        return Ok(prev.bits);
+    }
+
+    match params.algorithm {
+        RetargetAlgorithm::PerInterval { interval } => per_interval_retarget(params, interval, prev, chain),
+        RetargetAlgorithm::Asert { half_life, anchor_height } => {
+            if prev.height < anchor_height {
+                return Ok(prev.bits);
+            }
+            let anchor = chain
+                .header_at(anchor_height)
+                .ok_or(DifficultyError::MissingHeader(anchor_height))?;
+            asert_retarget(params, half_life, &anchor, prev)
+        }
+    }
+}
+
+/// A new target takes effect after every `interval` blocks, scaled by how long
+/// blocks `prev.height - interval + 1 ..= prev.height` took to mine.
+fn per_interval_retarget<C: HeaderLookup + ?Sized>(
+    params: &DifficultyParams,
+    interval: u64,
+    prev: &HeaderInfo,
+    chain: &C,
+) -> Result<u32, DifficultyError> {
+    if interval < 2 || prev.height < interval || !prev.height.is_multiple_of(interval) {
+        return Ok(prev.bits);
+    }
+
+    let first_height = prev.height - interval + 1;
+    let first = chain
+        .header_at(first_height)
+        .ok_or(DifficultyError::MissingHeader(first_height))?;
+
+    let expected = params.target_spacing * (interval - 1);
+    let factor = params.max_adjustment_factor.max(1);
+    let actual = prev
+        .timestamp
+        .saturating_sub(first.timestamp)
+        .clamp(expected / factor, expected.saturating_mul(factor));
+
+    let target = compact_to_target(prev.bits)?;
+    let scaled = match target.checked_mul_u64(actual) {
+        Some(product) => product.checked_div(U256::from(expected.max(1))),
+        None => target
+            .checked_div(U256::from(expected.max(1)))
+            .and_then(|quotient| quotient.checked_mul_u64(actual)),
+    };
+
+    let limit = params.pow_limit();
+    let next = match scaled {
+        Some(next) if next <= limit && !next.is_zero() => next,
+        Some(next) if next.is_zero() => U256::ONE,
+        _ => limit,
+    };
+    Ok(target_to_compact(&next))
+}
+
+/// Absolutely scheduled exponentially rising targets (aserti3-2d), anchored at `anchor`
+fn asert_retarget(
+    params: &DifficultyParams,
+    half_life: u64,
+    anchor: &HeaderInfo,
+    prev: &HeaderInfo,
+) -> Result<u32, DifficultyError> {
+    let anchor_target = compact_to_target(anchor.bits)?;
+    let limit = params.pow_limit();
+
+    let time_diff = prev.timestamp as i128 - anchor.timestamp as i128;
+    let height_diff = prev.height as i128 - anchor.height as i128;
+    let drift = time_diff - params.target_spacing as i128 * height_diff;
+
+    // 16.16 fixed-point exponent, floored
+    let exponent = (drift * 65536).div_euclid(half_life.max(1) as i128);
+    let shifts = exponent >> 16;
+    let frac = (exponent & 0xffff) as u128;
+
+    // 2^frac approximated by a cubic, scaled by 2^16
+    let factor = 65536
+        + ((195_766_423_245_049 * frac
+            + 971_821_376 * frac * frac
+            + 5_127 * frac * frac * frac
+            + (1u128 << 47))
+            >> 48) as u64;
+
+    let (base, shift) = match anchor_target.checked_mul_u64(factor) {
+        Some(product) => (product, shifts - 16),
+        // factor < 2^17, so the shifted product always fits
+        None => ((anchor_target >> 17).checked_mul_u64(factor).unwrap_or(U256::MAX), shifts + 1),
+    };
+
+    let next = if shift < 0 {
+        base >> u32::try_from(-shift).unwrap_or(u32::MAX).min(256)
+    } else if base.bits() as i128 + shift > 256 {
+        limit
+    } else {
+        base << shift as u32
+    };
+
+    let next = if next.is_zero() {
+        U256::ONE
+    } else if next > limit {
+        limit
+    } else {
+        next
+    };
+    Ok(target_to_compact(&next))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compact_round_trip() {
+        let target = compact_to_target(POW_LIMIT_BITS).unwrap();
+        assert_eq!(target, U256::from(0xffff) << 208);
+        assert_eq!(target_to_compact(&target), POW_LIMIT_BITS);
+
+        for bits in [REGTEST_POW_LIMIT_BITS, 0x1b0404cb, 0x0300ffff, 0x01120000] {
+            assert_eq!(target_to_compact(&compact_to_target(bits).unwrap()), bits);
+        }
+
+        assert_eq!(compact_to_target(0x04923456), Err(DifficultyError::InvalidBits(0x04923456)));
+        assert_eq!(compact_to_target(0xff123456), Err(DifficultyError::InvalidBits(0xff123456)));
+        assert_eq!(compact_to_target(0x1d000000), Err(DifficultyError::InvalidBits(0x1d000000)));
+    }
+
+    #[test]
+    fn test_hash_comparison_is_full_width() {
+        let bits = 0x1d00ffff;
+        let mut hash = [0u8; 32];
+        hash[4] = 0xff;
+        hash[5] = 0xff;
+        assert!(hash_meets_target(&hash, bits));
+
+        // Equal in the first four bytes, higher further down
+        hash[6] = 0x01;
+        assert!(!hash_meets_target(&hash, bits));
+    }
+
+    #[test]
+    fn test_block_work() {
+        // Bitcoin's genesis target takes 2^32 + 2^16 + 1 hashes on average
+        assert_eq!(block_work(POW_LIMIT_BITS), U256::from(0x1_0001_0001));
+        assert_eq!(block_work(REGTEST_POW_LIMIT_BITS), U256::from(2));
+        assert_eq!(block_work(0x1c7fff80), U256::from(0x2_0002_0002));
+        assert_eq!(block_work(0xffffffff), U256::ZERO);
+    }
+
+    #[test]
+    fn test_u256_arithmetic() {
+        let a = U256::from(u64::MAX) << 100;
+        let (quotient, remainder) = a.checked_div_rem(U256::from(3)).unwrap();
+        assert_eq!(quotient.checked_mul_u64(3).unwrap().checked_add(remainder), Some(a));
+        assert!(U256::MAX.checked_add(U256::ONE).is_none());
+        assert!(U256::ONE << 255 > U256::from(u64::MAX));
+        assert_eq!((U256::ONE << 200) >> 200, U256::ONE);
+        assert_eq!(U256::from_be_bytes(a.to_be_bytes()), a);
+    }
+
+    fn chain(bits: u32, spacing: u64, count: u64) -> Vec<HeaderInfo> {
+        (1..=count)
+            .map(|height| HeaderInfo { height, timestamp: 1_000_000 + height * spacing, bits })
+            .collect()
+    }
+
+    #[test]
+    fn test_per_interval_retarget() {
+        let params = DifficultyParams {
+            algorithm: RetargetAlgorithm::PerInterval { interval: 10 },
+            ..DifficultyParams::mainnet()
+        };
+        let start = 0x1c7fff80;
+
+        // Only retarget at the interval boundary
+        let headers = chain(start, 30, 9);
+        assert_eq!(next_work_required(&params, headers.last(), &headers[..]), Ok(start));
+
+        // Blocks twice as fast as scheduled halve the target
+        let headers = chain(start, 30, 10);
+        let bits = next_work_required(&params, headers.last(), &headers[..]).unwrap();
+        assert_eq!(compact_to_target(bits).unwrap(), compact_to_target(start).unwrap() >> 1);
+
+        // Slow blocks raise it, but never past the limit
+        let headers = chain(start, 600, 10);
+        let bits = next_work_required(&params, headers.last(), &headers[..]).unwrap();
+        assert_eq!(bits, POW_LIMIT_BITS);
+
+        // The first block uses the limit; regtest never retargets
+        assert_eq!(next_work_required(&params, None, &headers[..]), Ok(POW_LIMIT_BITS));
+        let regtest = DifficultyParams::regtest();
+        assert_eq!(next_work_required(&regtest, headers.last(), &headers[..]), Ok(start));
+    }
+
+    #[test]
+    fn test_asert_retarget() {
+        let half_life = 3600;
+        let params = DifficultyParams {
+            algorithm: RetargetAlgorithm::Asert { half_life, anchor_height: 1 },
+            ..DifficultyParams::mainnet()
+        };
+        let start = 0x1c7fff80;
+        let start_target = compact_to_target(start).unwrap();
+
+        // On schedule the target stays put
+        let headers = chain(start, TARGET_BLOCK_SPACING, 50);
+        assert_eq!(next_work_required(&params, headers.last(), &headers[..]), Ok(start));
+
+        // One half-life behind schedule doubles it, one ahead halves it
+        let mut behind = headers.clone();
+        behind.last_mut().unwrap().timestamp += half_life;
+        let bits = next_work_required(&params, behind.last(), &behind[..]).unwrap();
+        assert_eq!(compact_to_target(bits).unwrap(), start_target << 1);
+
+        let mut ahead = headers.clone();
+        ahead.last_mut().unwrap().timestamp -= half_life;
+        let bits = next_work_required(&params, ahead.last(), &ahead[..]).unwrap();
+        assert_eq!(compact_to_target(bits).unwrap(), start_target >> 1);
+
+        // The anchor must be reachable
+        assert_eq!(
+            next_work_required(&params, headers.last(), &headers[10..]),
+            Err(DifficultyError::MissingHeader(1))
+        );
+    }
+
+    #[test]
+    fn test_check_proof_of_work() {
+        let params = DifficultyParams::mainnet();
+        let hash = [0u8; 32];
+        assert_eq!(check_proof_of_work(&hash, POW_LIMIT_BITS, &params), Ok(()));
+        assert_eq!(
+            check_proof_of_work(&hash, REGTEST_POW_LIMIT_BITS, &params),
+            Err(DifficultyError::AboveLimit(REGTEST_POW_LIMIT_BITS))
+        );
+        assert_eq!(
+            check_proof_of_work(&[0xff; 32], POW_LIMIT_BITS, &params),
+            Err(DifficultyError::HighHash(POW_LIMIT_BITS))
+        );
+    }
+}
@@ -0,0 +1,110 @@
+// Block resource limits
+//
+// Sizes are measured on the bincode encoding used on the wire and for hashing.
+// Weight and signature-operation cost follow the Bitcoin scaling so mining
+// software can treat `weightlimit`/`sigoplimit` the way it already does.
+
+use thiserror::Error;
+use crate::types::block::Block;
+use crate::types::transaction::Transaction;
+
+/// Maximum serialized size of a block in bytes
+pub const MAX_BLOCK_SIZE: usize = 1_000_000;
+
+/// Weight units charged per serialized byte
+pub const WITNESS_SCALE_FACTOR: usize = 4;
+
+/// Maximum block weight
+pub const MAX_BLOCK_WEIGHT: usize = MAX_BLOCK_SIZE * WITNESS_SCALE_FACTOR;
+
+/// Maximum signature-operation cost per block
+pub const MAX_BLOCK_SIGOPS_COST: usize = 80_000;
+
+/// Errors raised when a block exceeds its resource limits
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum BlockLimitError {
+    #[error("Block weight {weight} exceeds the maximum of {max}")]
+    Overweight { weight: usize, max: usize },
+
+    #[error("Block signature operation cost {cost} exceeds the maximum of {max}")]
+    TooManySigops { cost: usize, max: usize },
+}
+
+/// Serialized size of a transaction in bytes
+pub fn transaction_size(tx: &Transaction) -> usize {
+    bincode::serialized_size(tx).map(|size| size as usize).unwrap_or(usize::MAX)
+}
+
+/// Weight of a transaction
+pub fn transaction_weight(tx: &Transaction) -> usize {
+    transaction_size(tx).saturating_mul(WITNESS_SCALE_FACTOR)
+}
+
+/// Signature-operation cost of a transaction
+///
+/// Every non-coinbase input is authorized by exactly one signature check.
+pub fn transaction_sigop_cost(tx: &Transaction) -> usize {
+    if tx.is_coinbase() {
+        return 0;
+    }
+    tx.inputs().len() * WITNESS_SCALE_FACTOR
+}
+
+/// Check a block against the weight and signature-operation limits
+pub fn check_block_limits(block: &Block) -> Result<(), BlockLimitError> {
+    let cost: usize = block.transactions().iter().map(transaction_sigop_cost).sum();
+    if cost > MAX_BLOCK_SIGOPS_COST {
+        return Err(BlockLimitError::TooManySigops { cost, max: MAX_BLOCK_SIGOPS_COST });
+    }
+
+    let weight = bincode::serialized_size(block)
+        .map(|size| size as usize)
+        .unwrap_or(usize::MAX)
+        .saturating_mul(WITNESS_SCALE_FACTOR);
+    if weight > MAX_BLOCK_WEIGHT {
+        return Err(BlockLimitError::Overweight { weight, max: MAX_BLOCK_WEIGHT });
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::consensus::REGTEST_POW_LIMIT_BITS;
+    use crate::types::transaction::{TransactionInput, TransactionOutput};
+
+    fn spend(inputs: usize) -> Transaction {
+        Transaction::new(
+            1,
+            (0..inputs).map(|i| TransactionInput::new([i as u8 + 1; 32], 0, vec![0u8; 64], 0)).collect(),
+            vec![TransactionOutput::new(1000, vec![1, 2, 3])],
+            0,
+        )
+    }
+
+    #[test]
+    fn test_transaction_costs() {
+        let coinbase = Transaction::new(
+            1,
+            vec![TransactionInput::new([0u8; 32], 0xffffffff, vec![], 0)],
+            vec![TransactionOutput::new(50, vec![])],
+            0,
+        );
+        assert_eq!(transaction_sigop_cost(&coinbase), 0);
+        assert_eq!(transaction_sigop_cost(&spend(3)), 12);
+
+        let tx = spend(1);
+        assert_eq!(transaction_weight(&tx), transaction_size(&tx) * WITNESS_SCALE_FACTOR);
+    }
+
+    #[test]
+    fn test_block_sigop_limit() {
+        let block = Block::new(1, [0u8; 32], vec![spend(2)], REGTEST_POW_LIMIT_BITS);
+        assert!(check_block_limits(&block).is_ok());
+
+        let too_many = MAX_BLOCK_SIGOPS_COST / WITNESS_SCALE_FACTOR + 1;
+        let block = Block::new(1, [0u8; 32], vec![spend(too_many)], REGTEST_POW_LIMIT_BITS);
+        assert!(matches!(check_block_limits(&block), Err(BlockLimitError::TooManySigops { .. })));
+    }
+}
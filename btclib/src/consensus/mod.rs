@@ -0,0 +1,24 @@
+// Consensus rules for the SuperNova blockchain
+//
+// Everything in this module must produce identical results on every node,
+// so it is shared by the miner, the node's chain state and the validation service.
+
+pub mod subsidy;
+pub mod limits;
+pub mod difficulty;
+
+pub use subsidy::{
+    block_subsidy, cumulative_subsidy, treasury_share, validate_coinbase, is_coinbase_mature,
+    CoinbaseError, COIN, INITIAL_BLOCK_SUBSIDY, HALVING_INTERVAL, MAX_MONEY, COINBASE_MATURITY,
+    ENVIRONMENTAL_TREASURY_SCRIPT, TREASURY_FEE_PERCENTAGE,
+};
+pub use limits::{
+    check_block_limits, transaction_sigop_cost, transaction_size, transaction_weight, BlockLimitError,
+    MAX_BLOCK_SIZE, MAX_BLOCK_WEIGHT, MAX_BLOCK_SIGOPS_COST, WITNESS_SCALE_FACTOR,
+};
+pub use difficulty::{
+    block_work, check_proof_of_work, compact_to_target, hash_meets_target, next_work_required,
+    target_difficulty, target_to_compact, DifficultyError, DifficultyParams, HeaderInfo, HeaderLookup,
+    RetargetAlgorithm, U256, POW_LIMIT_BITS, REGTEST_POW_LIMIT_BITS, TARGET_BLOCK_SPACING,
+    DIFFICULTY_ADJUSTMENT_INTERVAL, MAX_ADJUSTMENT_FACTOR, DEFAULT_ASERT_HALF_LIFE,
+};
@@ -0,0 +1,240 @@
+// Block subsidy schedule and coinbase rules
+//
+// The emission schedule starts at 50 NOVA per block and halves every
+// `HALVING_INTERVAL` blocks, converging on the 42M NOVA total supply.
+
+use thiserror::Error;
+use crate::types::transaction::Transaction;
+use crate::types::units::TOTAL_NOVA_SUPPLY;
+
+/// Number of base units in one NOVA
+pub const COIN: u64 = 100_000_000;
+
+/// Subsidy paid by the first block of the chain (50 NOVA)
+pub const INITIAL_BLOCK_SUBSIDY: u64 = 50 * COIN;
+
+/// Number of blocks between subsidy halvings
+///
+/// 50 NOVA * 420,000 blocks * 2 = 42,000,000 NOVA
+pub const HALVING_INTERVAL: u64 = 420_000;
+
+/// Maximum amount of base units that can ever exist
+pub const MAX_MONEY: u64 = TOTAL_NOVA_SUPPLY * COIN;
+
+/// Number of confirmations a coinbase output needs before it can be spent
+pub const COINBASE_MATURITY: u64 = 100;
+
+/// Output script that identifies the environmental treasury in a coinbase
+pub const ENVIRONMENTAL_TREASURY_SCRIPT: &[u8] = b"supernova:environmental-treasury";
+
+/// Share of transaction fees every coinbase owes the environmental treasury
+///
+/// This is fixed by consensus so that nodes cannot disagree on a block's validity.
+pub const TREASURY_FEE_PERCENTAGE: f64 = 2.0;
+
+/// Errors raised when a block's coinbase breaks the consensus rules
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum CoinbaseError {
+    #[error("First transaction in block is not a coinbase")]
+    MissingCoinbase,
+
+    #[error("Block contains more than one coinbase transaction (index {0})")]
+    MultipleCoinbase(usize),
+
+    #[error("Coinbase output value overflows")]
+    ValueOverflow,
+
+    #[error("Coinbase pays {paid} but at most {allowed} is allowed (subsidy + fees)")]
+    ExcessiveReward { paid: u64, allowed: u64 },
+
+    #[error("Coinbase pays {paid} to the environmental treasury but {required} is required")]
+    MissingTreasuryOutput { paid: u64, required: u64 },
+}
+
+/// Get the block subsidy for a given height
+pub fn block_subsidy(height: u64) -> u64 {
+    let halvings = height / HALVING_INTERVAL;
+
+    // Shifting a u64 by 64 or more is undefined, and the subsidy is zero long before that
+    if halvings >= 64 {
+        return 0;
+    }
+
+    INITIAL_BLOCK_SUBSIDY >> halvings
+}
+
+/// Total subsidy issued by all blocks from genesis up to and including `height`
+pub fn cumulative_subsidy(height: u64) -> u64 {
+    let mut total = 0u64;
+    let mut era_start = 0u64;
+
+    while era_start <= height {
+        let subsidy = block_subsidy(era_start);
+        if subsidy == 0 {
+            break;
+        }
+
+        let era_end = (era_start + HALVING_INTERVAL - 1).min(height);
+        total += subsidy * (era_end - era_start + 1);
+        era_start += HALVING_INTERVAL;
+    }
+
+    total
+}
+
+/// Amount of `total_fees` owed to the environmental treasury at `percentage`
+///
+/// The percentage is applied in basis points so every node rounds the same way.
+pub fn treasury_share(total_fees: u64, percentage: f64) -> u64 {
+    let basis_points = (percentage.clamp(0.0, 100.0) * 100.0).round() as u128;
+    ((total_fees as u128 * basis_points) / 10_000) as u64
+}
+
+/// Check whether a coinbase created at `coinbase_height` can be spent in a block at `spend_height`
+pub fn is_coinbase_mature(coinbase_height: u64, spend_height: u64) -> bool {
+    spend_height >= coinbase_height.saturating_add(COINBASE_MATURITY)
+}
+
+/// Validate the coinbase of a block at `height`
+///
+/// `total_fees` is the sum of fees paid by every non-coinbase transaction in the
+/// block and `treasury_share` is the portion of those fees the coinbase must pay
+/// to `ENVIRONMENTAL_TREASURY_SCRIPT`. A block without transactions pays nothing
+/// and is accepted.
+pub fn validate_coinbase(
+    transactions: &[Transaction],
+    height: u64,
+    total_fees: u64,
+    treasury_share: u64,
+) -> Result<(), CoinbaseError> {
+    let coinbase = match transactions.first() {
+        Some(tx) => tx,
+        None => return Ok(()),
+    };
+
+    if !coinbase.is_coinbase() {
+        return Err(CoinbaseError::MissingCoinbase);
+    }
+
+    if let Some(index) = transactions.iter().skip(1).position(|tx| tx.is_coinbase()) {
+        return Err(CoinbaseError::MultipleCoinbase(index + 1));
+    }
+
+    let paid = coinbase
+        .outputs()
+        .iter()
+        .try_fold(0u64, |acc, output| acc.checked_add(output.amount()))
+        .ok_or(CoinbaseError::ValueOverflow)?;
+
+    let allowed = block_subsidy(height)
+        .checked_add(total_fees)
+        .ok_or(CoinbaseError::ValueOverflow)?;
+
+    if paid > allowed {
+        return Err(CoinbaseError::ExcessiveReward { paid, allowed });
+    }
+
+    if treasury_share > 0 {
+        let treasury_paid: u64 = coinbase
+            .outputs()
+            .iter()
+            .filter(|output| output.pub_key_script() == ENVIRONMENTAL_TREASURY_SCRIPT)
+            .map(|output| output.amount())
+            .sum();
+
+        if treasury_paid < treasury_share {
+            return Err(CoinbaseError::MissingTreasuryOutput {
+                paid: treasury_paid,
+                required: treasury_share,
+            });
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::transaction::{TransactionInput, TransactionOutput};
+
+    fn coinbase(outputs: Vec<TransactionOutput>) -> Transaction {
+        Transaction::new(
+            1,
+            vec![TransactionInput::new([0u8; 32], 0xffffffff, vec![], 0)],
+            outputs,
+            0,
+        )
+    }
+
+    #[test]
+    fn test_subsidy_halving() {
+        assert_eq!(block_subsidy(0), 50 * COIN);
+        assert_eq!(block_subsidy(HALVING_INTERVAL - 1), 50 * COIN);
+        assert_eq!(block_subsidy(HALVING_INTERVAL), 25 * COIN);
+        assert_eq!(block_subsidy(HALVING_INTERVAL * 2), 1_250_000_000);
+        assert_eq!(block_subsidy(HALVING_INTERVAL * 64), 0);
+    }
+
+    #[test]
+    fn test_total_supply_cap() {
+        let total = cumulative_subsidy(HALVING_INTERVAL * 64);
+        assert!(total <= MAX_MONEY);
+        // Integer truncation loses less than one NOVA per era
+        assert!(MAX_MONEY - total < 64 * COIN);
+        assert_eq!(cumulative_subsidy(0), INITIAL_BLOCK_SUBSIDY);
+    }
+
+    #[test]
+    fn test_treasury_share() {
+        assert_eq!(treasury_share(1_000_000, 2.0), 20_000);
+        assert_eq!(treasury_share(0, 2.0), 0);
+        assert_eq!(treasury_share(99, 2.0), 1);
+    }
+
+    #[test]
+    fn test_coinbase_maturity() {
+        assert!(!is_coinbase_mature(10, 50));
+        assert!(is_coinbase_mature(10, 10 + COINBASE_MATURITY));
+    }
+
+    #[test]
+    fn test_validate_coinbase() {
+        let fees = 1_000_000;
+        let share = treasury_share(fees, TREASURY_FEE_PERCENTAGE);
+
+        let valid = coinbase(vec![
+            TransactionOutput::new(block_subsidy(1) + fees - share, vec![1, 2, 3]),
+            TransactionOutput::new(share, ENVIRONMENTAL_TREASURY_SCRIPT.to_vec()),
+        ]);
+        assert_eq!(validate_coinbase(&[valid], 1, fees, share), Ok(()));
+
+        let overpaying = coinbase(vec![
+            TransactionOutput::new(block_subsidy(1) + fees, vec![1, 2, 3]),
+            TransactionOutput::new(share, ENVIRONMENTAL_TREASURY_SCRIPT.to_vec()),
+        ]);
+        assert!(matches!(
+            validate_coinbase(&[overpaying], 1, fees, share),
+            Err(CoinbaseError::ExcessiveReward { .. })
+        ));
+
+        let skipping_treasury = coinbase(vec![
+            TransactionOutput::new(block_subsidy(1) + fees, vec![1, 2, 3]),
+        ]);
+        assert_eq!(
+            validate_coinbase(&[skipping_treasury], 1, fees, share),
+            Err(CoinbaseError::MissingTreasuryOutput { paid: 0, required: share })
+        );
+
+        let not_coinbase = Transaction::new(
+            1,
+            vec![TransactionInput::new([1u8; 32], 0, vec![], 0)],
+            vec![TransactionOutput::new(1, vec![])],
+            0,
+        );
+        assert_eq!(
+            validate_coinbase(&[not_coinbase], 1, 0, 0),
+            Err(CoinbaseError::MissingCoinbase)
+        );
+    }
+}
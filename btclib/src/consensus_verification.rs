@@ -106,7 +106,7 @@ pub struct ChainState {
     /// Current blockchain height
     pub height: u64,
     
-    /// Current difficulty target
+    /// Compact difficulty bits expected of the next block
     pub difficulty_target: u64,
     
     /// Current timestamp
@@ -505,17 +505,13 @@ impl DifficultyPredicate {
 
 impl VerificationPredicate for DifficultyPredicate {
     fn verify_block(&self, block: &Block, chain_state: &ChainState) -> Result<bool, ConsensusVerificationError> {
-        // Check if the block hash is below the difficulty target
-        let block_hash = block.hash();
-        let target = chain_state.difficulty_target;
-        
-        // Convert hash to a u64 target value (in a real implementation, this would use the full hash)
-        let hash_value = u64::from_be_bytes([
-            block_hash[0], block_hash[1], block_hash[2], block_hash[3],
-            block_hash[4], block_hash[5], block_hash[6], block_hash[7],
-        ]);
-        
-        Ok(hash_value <= target)
+        // The block must carry the expected bits and its hash must be below the full target
+        let bits = block.header().target();
+        if u64::from(bits) != chain_state.difficulty_target {
+            return Ok(false);
+        }
+
+        Ok(crate::consensus::hash_meets_target(&block.hash(), bits))
     }
     
     fn verify_transaction(&self, _tx: &Transaction, _chain_state: &ChainState) -> Result<bool, ConsensusVerificationError> {
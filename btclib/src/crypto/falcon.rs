@@ -97,7 +97,7 @@ pub struct FalconKeyPair {
     /// The public key
     pub public_key: Vec<u8>,
     /// The private key (sensitive information)
-    private_key: Vec<u8>,
+    pub(crate) private_key: Vec<u8>,
     /// Parameters used for this key pair
     pub parameters: FalconParameters,
 }
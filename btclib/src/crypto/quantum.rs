@@ -5,8 +5,7 @@ use std::fmt;
 use serde::{Serialize, Deserialize};
 use sha2::{Sha256, Sha512, Digest};
 use rand::{CryptoRng, RngCore};
-use pqcrypto_dilithium::{dilithium2, dilithium3, dilithium5};
-use pqcrypto_traits::sign::{PublicKey as PQPublicKey, SecretKey as PQSecretKey, DetachedSignature};
+use crystals_dilithium::{dilithium2, dilithium3, dilithium5};
 use thiserror::Error;
 
 use crate::validation::SecurityLevel;
@@ -152,9 +151,9 @@ impl QuantumParameters {
         match self.scheme {
             QuantumScheme::Dilithium => {
                 match SecurityLevel::from(self.security_level) {
-                    SecurityLevel::Low => Ok(dilithium2::SIGNATUREBYTES),
-                    SecurityLevel::Medium => Ok(dilithium3::SIGNATUREBYTES),
-                    SecurityLevel::High => Ok(dilithium5::SIGNATUREBYTES),
+                    SecurityLevel::Low => Ok(dilithium2::SIGNBYTES),
+                    SecurityLevel::Medium => Ok(dilithium3::SIGNBYTES),
+                    SecurityLevel::High => Ok(dilithium5::SIGNBYTES),
                     _ => Err(QuantumError::UnsupportedSecurityLevel(self.security_level)),
                 }
             },
@@ -175,9 +174,9 @@ impl QuantumParameters {
                 
                 // Get quantum length and add
                 let quantum_len = match SecurityLevel::from(self.security_level) {
-                    SecurityLevel::Low => dilithium2::SIGNATUREBYTES,
-                    SecurityLevel::Medium => dilithium3::SIGNATUREBYTES,
-                    SecurityLevel::High => dilithium5::SIGNATUREBYTES,
+                    SecurityLevel::Low => dilithium2::SIGNBYTES,
+                    SecurityLevel::Medium => dilithium3::SIGNBYTES,
+                    SecurityLevel::High => dilithium5::SIGNBYTES,
                     _ => return Err(QuantumError::UnsupportedSecurityLevel(self.security_level)),
                 };
                 
@@ -202,74 +201,48 @@ impl QuantumKeyPair {
         }
     }
     
+    /// Derive a key pair deterministically from a 32-byte seed
+    ///
+    /// Used by HD wallets so post-quantum keys can be recovered from a
+    /// mnemonic. Only Dilithium keys can be derived; `generate` seeds them the
+    /// same way from its random number generator.
+    pub fn from_seed(seed: &[u8; 32], parameters: QuantumParameters) -> Result<Self, QuantumError> {
+        let (public_key, private_key) = match parameters.scheme {
+            QuantumScheme::Dilithium => match SecurityLevel::from(parameters.security_level) {
+                SecurityLevel::Low => {
+                    let keys = dilithium2::Keypair::generate(Some(seed));
+                    (keys.public.to_bytes().to_vec(), keys.secret.to_bytes().to_vec())
+                },
+                SecurityLevel::Medium => {
+                    let keys = dilithium3::Keypair::generate(Some(seed));
+                    (keys.public.to_bytes().to_vec(), keys.secret.to_bytes().to_vec())
+                },
+                SecurityLevel::High => {
+                    let keys = dilithium5::Keypair::generate(Some(seed));
+                    (keys.public.to_bytes().to_vec(), keys.secret.to_bytes().to_vec())
+                },
+                _ => return Err(QuantumError::UnsupportedSecurityLevel(parameters.security_level)),
+            },
+            QuantumScheme::Falcon | QuantumScheme::Sphincs | QuantumScheme::Hybrid(_) => {
+                return Err(QuantumError::UnsupportedScheme(format!("{:?} key derivation", parameters.scheme)));
+            },
+        };
+        
+        Ok(Self {
+            public_key,
+            private_key,
+            parameters,
+        })
+    }
+    
     // Generate Dilithium key pair
     fn generate_dilithium<R: CryptoRng + RngCore>(
-        _rng: &mut R,
+        rng: &mut R,
         security_level: u8,
     ) -> Result<Self, QuantumError> {
-        match SecurityLevel::from(security_level) {
-            SecurityLevel::Low => {
-                let (pk, sk) = dilithium2::keypair();
-                let public_key = DilithiumPublicKey {
-                    bytes: pk.as_bytes().to_vec(),
-                    security_level,
-                };
-                let secret_key = DilithiumSecretKey {
-                    bytes: sk.as_bytes().to_vec(),
-                    security_level,
-                };
-                
-                Ok(Self {
-                    public_key: public_key.bytes,
-                    private_key: secret_key.bytes,
-                    parameters: QuantumParameters {
-                        scheme: QuantumScheme::Dilithium,
-                        security_level,
-                    },
-                })
-            },
-            SecurityLevel::Medium => {
-                let (pk, sk) = dilithium3::keypair();
-                let public_key = DilithiumPublicKey {
-                    bytes: pk.as_bytes().to_vec(),
-                    security_level,
-                };
-                let secret_key = DilithiumSecretKey {
-                    bytes: sk.as_bytes().to_vec(),
-                    security_level,
-                };
-                
-                Ok(Self {
-                    public_key: public_key.bytes,
-                    private_key: secret_key.bytes,
-                    parameters: QuantumParameters {
-                        scheme: QuantumScheme::Dilithium,
-                        security_level,
-                    },
-                })
-            },
-            SecurityLevel::High => {
-                let (pk, sk) = dilithium5::keypair();
-                let public_key = DilithiumPublicKey {
-                    bytes: pk.as_bytes().to_vec(),
-                    security_level,
-                };
-                let secret_key = DilithiumSecretKey {
-                    bytes: sk.as_bytes().to_vec(),
-                    security_level,
-                };
-                
-                Ok(Self {
-                    public_key: public_key.bytes,
-                    private_key: secret_key.bytes,
-                    parameters: QuantumParameters {
-                        scheme: QuantumScheme::Dilithium,
-                        security_level,
-                    },
-                })
-            },
-            _ => Err(QuantumError::UnsupportedSecurityLevel(security_level)),
-        }
+        let mut seed = [0u8; 32];
+        rng.fill_bytes(&mut seed);
+        Self::from_seed(&seed, QuantumParameters::with_security_level(QuantumScheme::Dilithium, security_level))
     }
     
     // Generate Falcon key pair
@@ -322,22 +295,25 @@ impl QuantumKeyPair {
             QuantumScheme::Dilithium => {
                 match SecurityLevel::from(self.parameters.security_level) {
                     SecurityLevel::Low => {
-                        let sk = dilithium2::SecretKey::from_bytes(&self.private_key)
-                            .map_err(|e| QuantumError::InvalidKey(format!("Invalid Dilithium secret key: {}", e)))?;
-                        let signature = dilithium2::detached_sign(message, &sk);
-                        Ok(signature.as_bytes().to_vec())
+                        if self.private_key.len() != dilithium2::SECRETKEYBYTES {
+                            return Err(QuantumError::InvalidKey("Invalid Dilithium secret key length".to_string()));
+                        }
+                        let sk = dilithium2::SecretKey::from_bytes(&self.private_key);
+                        Ok(sk.sign(message).to_vec())
                     },
                     SecurityLevel::Medium => {
-                        let sk = dilithium3::SecretKey::from_bytes(&self.private_key)
-                            .map_err(|e| QuantumError::InvalidKey(format!("Invalid Dilithium secret key: {}", e)))?;
-                        let signature = dilithium3::detached_sign(message, &sk);
-                        Ok(signature.as_bytes().to_vec())
+                        if self.private_key.len() != dilithium3::SECRETKEYBYTES {
+                            return Err(QuantumError::InvalidKey("Invalid Dilithium secret key length".to_string()));
+                        }
+                        let sk = dilithium3::SecretKey::from_bytes(&self.private_key);
+                        Ok(sk.sign(message).to_vec())
                     },
                     SecurityLevel::High => {
-                        let sk = dilithium5::SecretKey::from_bytes(&self.private_key)
-                            .map_err(|e| QuantumError::InvalidKey(format!("Invalid Dilithium secret key: {}", e)))?;
-                        let signature = dilithium5::detached_sign(message, &sk);
-                        Ok(signature.as_bytes().to_vec())
+                        if self.private_key.len() != dilithium5::SECRETKEYBYTES {
+                            return Err(QuantumError::InvalidKey("Invalid Dilithium secret key length".to_string()));
+                        }
+                        let sk = dilithium5::SecretKey::from_bytes(&self.private_key);
+                        Ok(sk.sign(message).to_vec())
                     },
                     _ => Err(QuantumError::UnsupportedSecurityLevel(self.parameters.security_level)),
                 }
@@ -386,37 +362,25 @@ impl QuantumKeyPair {
             QuantumScheme::Dilithium => {
                 match SecurityLevel::from(self.parameters.security_level) {
                     SecurityLevel::Low => {
-                        let pk = dilithium2::PublicKey::from_bytes(&self.public_key)
-                            .map_err(|e| QuantumError::InvalidKey(format!("Invalid Dilithium public key: {}", e)))?;
-                        let sig = dilithium2::DetachedSignature::from_bytes(signature)
-                            .map_err(|e| QuantumError::InvalidSignature(format!("Invalid Dilithium signature: {}", e)))?;
-                        
-                        match dilithium2::verify_detached_signature(&sig, message, &pk) {
-                            Ok(_) => Ok(true),
-                            Err(_) => Ok(false),
+                        if self.public_key.len() != dilithium2::PUBLICKEYBYTES {
+                            return Err(QuantumError::InvalidKey("Invalid Dilithium public key length".to_string()));
                         }
+                        let pk = dilithium2::PublicKey::from_bytes(&self.public_key);
+                        Ok(pk.verify(message, signature))
                     },
                     SecurityLevel::Medium => {
-                        let pk = dilithium3::PublicKey::from_bytes(&self.public_key)
-                            .map_err(|e| QuantumError::InvalidKey(format!("Invalid Dilithium public key: {}", e)))?;
-                        let sig = dilithium3::DetachedSignature::from_bytes(signature)
-                            .map_err(|e| QuantumError::InvalidSignature(format!("Invalid Dilithium signature: {}", e)))?;
-                        
-                        match dilithium3::verify_detached_signature(&sig, message, &pk) {
-                            Ok(_) => Ok(true),
-                            Err(_) => Ok(false),
+                        if self.public_key.len() != dilithium3::PUBLICKEYBYTES {
+                            return Err(QuantumError::InvalidKey("Invalid Dilithium public key length".to_string()));
                         }
+                        let pk = dilithium3::PublicKey::from_bytes(&self.public_key);
+                        Ok(pk.verify(message, signature))
                     },
                     SecurityLevel::High => {
-                        let pk = dilithium5::PublicKey::from_bytes(&self.public_key)
-                            .map_err(|e| QuantumError::InvalidKey(format!("Invalid Dilithium public key: {}", e)))?;
-                        let sig = dilithium5::DetachedSignature::from_bytes(signature)
-                            .map_err(|e| QuantumError::InvalidSignature(format!("Invalid Dilithium signature: {}", e)))?;
-                        
-                        match dilithium5::verify_detached_signature(&sig, message, &pk) {
-                            Ok(_) => Ok(true),
-                            Err(_) => Ok(false),
+                        if self.public_key.len() != dilithium5::PUBLICKEYBYTES {
+                            return Err(QuantumError::InvalidKey("Invalid Dilithium public key length".to_string()));
                         }
+                        let pk = dilithium5::PublicKey::from_bytes(&self.public_key);
+                        Ok(pk.verify(message, signature))
                     },
                     _ => Err(QuantumError::UnsupportedSecurityLevel(self.parameters.security_level)),
                 }
@@ -523,6 +487,33 @@ mod tests {
         assert!(!result, "Verification with wrong message should return false");
     }
     
+    #[test]
+    fn test_dilithium_from_seed() {
+        let params = QuantumParameters::with_security_level(QuantumScheme::Dilithium, SecurityLevel::Medium.into());
+        
+        let keypair = QuantumKeyPair::from_seed(&[7u8; 32], params).expect("Derivation should succeed");
+        assert_eq!(keypair.public_key.len(), dilithium3::PUBLICKEYBYTES);
+        
+        // Derived keys sign like generated ones
+        let message = b"derived key";
+        let signature = keypair.sign(message).expect("Signing should succeed");
+        assert!(verify_quantum_signature(&keypair.public_key, message, &signature, params).unwrap());
+        
+        // The seed alone determines the key
+        let again = QuantumKeyPair::from_seed(&[7u8; 32], params).unwrap();
+        assert_eq!(again.public_key, keypair.public_key);
+        let other = QuantumKeyPair::from_seed(&[8u8; 32], params).unwrap();
+        assert_ne!(other.public_key, keypair.public_key);
+        
+        for scheme in [QuantumScheme::Falcon, QuantumScheme::Sphincs] {
+            let params = QuantumParameters::with_security_level(scheme, SecurityLevel::Medium.into());
+            assert!(matches!(
+                QuantumKeyPair::from_seed(&[7u8; 32], params),
+                Err(QuantumError::UnsupportedScheme(_))
+            ));
+        }
+    }
+    
     #[test]
     fn test_falcon_not_implemented() {
         let mut rng = OsRng;
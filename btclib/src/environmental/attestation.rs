@@ -0,0 +1,421 @@
+// Signed environmental attestations for miners
+//
+// A registered verifier signs a miner's environmental claims with one of the
+// crypto::signature schemes. Claims only count toward fee discounts while the
+// attestation is within its validity window and its verifier has not been
+// revoked. Miners tie a block to their current attestation by committing to
+// its hash in a zero-value coinbase output.
+
+use std::collections::HashMap;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+use tracing::info;
+
+use crate::crypto::quantum::QuantumKeyPair;
+use crate::crypto::signature::{DilithiumScheme, SignatureError, SignatureScheme, SignatureType};
+use crate::environmental::miner_reporting::LocationVerificationMethod;
+use crate::environmental::emissions::Region;
+use crate::types::block::Block;
+use crate::types::transaction::TransactionOutput;
+
+/// Domain separator for attestation signatures and commitments
+const ATTESTATION_DOMAIN: &[u8] = b"supernova:env-attestation:v1";
+
+/// Output script prefix marking an attestation commitment in a coinbase;
+/// the 32-byte commitment follows it
+pub const ATTESTATION_COMMITMENT_PREFIX: &[u8] = b"supernova:env-attestation:";
+
+/// Longest validity window a verifier may grant an attestation
+pub const MAX_ATTESTATION_VALIDITY_DAYS: i64 = 365;
+
+/// Error type for attestation handling
+#[derive(Debug, Error, Clone, PartialEq)]
+pub enum AttestationError {
+    #[error("Unknown verifier: {0}")]
+    UnknownVerifier(String),
+
+    #[error("Verifier already registered: {0}")]
+    DuplicateVerifier(String),
+
+    #[error("Verifier {0} has been revoked")]
+    VerifierRevoked(String),
+
+    #[error("Signature scheme {0:?} cannot be used for attestations")]
+    UnsupportedScheme(SignatureType),
+
+    #[error("Attestation signature is invalid")]
+    InvalidSignature,
+
+    #[error("Attestation is not valid until {0}")]
+    NotYetValid(DateTime<Utc>),
+
+    #[error("Attestation expired at {0}")]
+    Expired(DateTime<Utc>),
+
+    #[error("Attestation validity window exceeds the maximum")]
+    ValidityTooLong,
+
+    #[error("Attestation is for miner {attested}, not {expected}")]
+    MinerMismatch { expected: String, attested: String },
+
+    #[error("Unknown miner: {0}")]
+    UnknownMiner(String),
+
+    #[error("Block has no attestation commitment")]
+    MissingCommitment,
+
+    #[error("Block commits to an unknown attestation")]
+    UnknownCommitment,
+
+    #[error("Signature error: {0}")]
+    Signature(#[from] SignatureError),
+
+    #[error("Serialization error: {0}")]
+    Serialization(String),
+}
+
+/// Location confirmed by a verifier
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AttestedLocation {
+    /// Region the miner operates in
+    pub region: Region,
+    /// How the verifier established the location
+    pub method: LocationVerificationMethod,
+}
+
+/// Environmental claims about a miner, as checked by a verifier
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MinerAttestation {
+    /// Miner the claims are about
+    pub miner_id: String,
+    /// Verifier that checked and signed the claims
+    pub verifier_id: String,
+    /// Verifier's reference for the audit
+    pub reference: String,
+    /// Start of the validity window
+    pub issued_at: DateTime<Utc>,
+    /// End of the validity window
+    pub expires_at: DateTime<Utc>,
+    /// Verified renewable share of the miner's energy (0-100)
+    pub renewable_percentage: f64,
+    /// REC certificates the verifier checked
+    pub rec_certificate_ids: Vec<String>,
+    /// Total renewable energy covered by those certificates in MWh
+    pub verified_rec_mwh: f64,
+    /// Verified carbon offsets in tonnes CO2e
+    pub verified_offset_tonnes: f64,
+    /// Verified location, if the verifier checked it
+    pub location: Option<AttestedLocation>,
+}
+
+impl MinerAttestation {
+    /// Bytes covered by the verifier's signature
+    pub fn signing_message(&self) -> Result<Vec<u8>, AttestationError> {
+        let encoded = bincode::serialize(self)
+            .map_err(|e| AttestationError::Serialization(e.to_string()))?;
+
+        let mut message = ATTESTATION_DOMAIN.to_vec();
+        message.extend_from_slice(&encoded);
+        Ok(message)
+    }
+
+    /// Sign the claims with a verifier's key pair
+    pub fn sign(self, key_pair: &QuantumKeyPair) -> Result<SignedAttestation, AttestationError> {
+        let signature = key_pair
+            .sign(&self.signing_message()?)
+            .map_err(SignatureError::from)?;
+
+        Ok(SignedAttestation { attestation: self, signature })
+    }
+
+    /// Check the validity window at `at`
+    pub fn check_validity(&self, at: DateTime<Utc>) -> Result<(), AttestationError> {
+        if self.expires_at - self.issued_at > Duration::days(MAX_ATTESTATION_VALIDITY_DAYS) {
+            return Err(AttestationError::ValidityTooLong);
+        }
+        if at < self.issued_at {
+            return Err(AttestationError::NotYetValid(self.issued_at));
+        }
+        if at >= self.expires_at {
+            return Err(AttestationError::Expired(self.expires_at));
+        }
+        Ok(())
+    }
+}
+
+/// Miner attestation together with its verifier's signature
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SignedAttestation {
+    pub attestation: MinerAttestation,
+    pub signature: Vec<u8>,
+}
+
+impl SignedAttestation {
+    /// Hash a block commits to in order to claim this attestation
+    pub fn commitment(&self) -> Result<[u8; 32], AttestationError> {
+        let mut hasher = Sha256::new();
+        hasher.update(self.attestation.signing_message()?);
+        hasher.update(&self.signature);
+        Ok(hasher.finalize().into())
+    }
+}
+
+/// Zero-value coinbase output committing to an attestation
+pub fn commitment_output(commitment: &[u8; 32]) -> TransactionOutput {
+    let mut script = ATTESTATION_COMMITMENT_PREFIX.to_vec();
+    script.extend_from_slice(commitment);
+    TransactionOutput::new(0, script)
+}
+
+/// Attestation commitment carried by a block's coinbase, if any
+pub fn find_commitment(block: &Block) -> Option<[u8; 32]> {
+    let coinbase = block.transactions().first().filter(|tx| tx.is_coinbase())?;
+
+    coinbase.outputs().iter().find_map(|output| {
+        output
+            .pub_key_script()
+            .strip_prefix(ATTESTATION_COMMITMENT_PREFIX)
+            .and_then(|commitment| <[u8; 32]>::try_from(commitment).ok())
+    })
+}
+
+/// A verifier's registered signing key
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VerifierKey {
+    /// Unique verifier ID, referenced by attestations
+    pub verifier_id: String,
+    /// Display name of the verifying organization
+    pub name: String,
+    /// Scheme the key signs with
+    pub sig_type: SignatureType,
+    /// Security level of the scheme
+    pub security_level: u8,
+    /// Public key bytes
+    pub public_key: Vec<u8>,
+    /// When the key was registered
+    pub registered_at: DateTime<Utc>,
+    /// When the key was revoked, if it has been
+    pub revoked_at: Option<DateTime<Utc>>,
+    /// Why the key was revoked
+    pub revocation_reason: Option<String>,
+}
+
+impl VerifierKey {
+    /// Whether the key has been revoked
+    pub fn is_revoked(&self) -> bool {
+        self.revoked_at.is_some()
+    }
+}
+
+/// Registry of verifier keys trusted to sign attestations
+///
+/// Only schemes with complete signature verification are accepted; the
+/// classical and Falcon schemes still verify any well-formed signature.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct VerifierRegistry {
+    verifiers: HashMap<String, VerifierKey>,
+}
+
+impl VerifierRegistry {
+    /// Create an empty registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn scheme(sig_type: SignatureType, security_level: u8) -> Result<Box<dyn SignatureScheme>, AttestationError> {
+        match sig_type {
+            SignatureType::Dilithium => Ok(Box::new(DilithiumScheme::new(security_level))),
+            other => Err(AttestationError::UnsupportedScheme(other)),
+        }
+    }
+
+    /// Register a verifier's public key
+    pub fn register_verifier(
+        &mut self,
+        verifier_id: &str,
+        name: &str,
+        sig_type: SignatureType,
+        security_level: u8,
+        public_key: Vec<u8>,
+    ) -> Result<(), AttestationError> {
+        Self::scheme(sig_type, security_level)?;
+
+        if self.verifiers.contains_key(verifier_id) {
+            return Err(AttestationError::DuplicateVerifier(verifier_id.to_string()));
+        }
+
+        self.verifiers.insert(verifier_id.to_string(), VerifierKey {
+            verifier_id: verifier_id.to_string(),
+            name: name.to_string(),
+            sig_type,
+            security_level,
+            public_key,
+            registered_at: Utc::now(),
+            revoked_at: None,
+            revocation_reason: None,
+        });
+        info!("Registered environmental verifier: {}", verifier_id);
+
+        Ok(())
+    }
+
+    /// Revoke a verifier; every attestation it signed stops being accepted
+    pub fn revoke_verifier(&mut self, verifier_id: &str, reason: &str) -> Result<(), AttestationError> {
+        let verifier = self
+            .verifiers
+            .get_mut(verifier_id)
+            .ok_or_else(|| AttestationError::UnknownVerifier(verifier_id.to_string()))?;
+
+        if verifier.revoked_at.is_none() {
+            verifier.revoked_at = Some(Utc::now());
+            verifier.revocation_reason = Some(reason.to_string());
+            info!("Revoked environmental verifier {}: {}", verifier_id, reason);
+        }
+
+        Ok(())
+    }
+
+    /// Get a verifier by ID
+    pub fn get_verifier(&self, verifier_id: &str) -> Option<&VerifierKey> {
+        self.verifiers.get(verifier_id)
+    }
+
+    /// List verifiers that have not been revoked
+    pub fn active_verifiers(&self) -> Vec<&VerifierKey> {
+        self.verifiers.values().filter(|verifier| !verifier.is_revoked()).collect()
+    }
+
+    /// Check an attestation's signature, verifier and validity window at `at`
+    pub fn verify(&self, signed: &SignedAttestation, at: DateTime<Utc>) -> Result<&VerifierKey, AttestationError> {
+        let attestation = &signed.attestation;
+        let verifier = self
+            .verifiers
+            .get(&attestation.verifier_id)
+            .ok_or_else(|| AttestationError::UnknownVerifier(attestation.verifier_id.clone()))?;
+
+        if verifier.is_revoked() {
+            return Err(AttestationError::VerifierRevoked(verifier.verifier_id.clone()));
+        }
+
+        attestation.check_validity(at)?;
+
+        let scheme = Self::scheme(verifier.sig_type, verifier.security_level)?;
+        let valid = scheme
+            .verify(&verifier.public_key, &attestation.signing_message()?, &signed.signature)
+            .map_err(|_| AttestationError::InvalidSignature)?;
+        if !valid {
+            return Err(AttestationError::InvalidSignature);
+        }
+
+        Ok(verifier)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::quantum::{QuantumParameters, QuantumScheme};
+    use crate::types::transaction::{Transaction, TransactionInput};
+    use rand::rngs::OsRng;
+
+    fn verifier_keys() -> QuantumKeyPair {
+        QuantumKeyPair::generate(&mut OsRng, QuantumParameters {
+            scheme: QuantumScheme::Dilithium,
+            security_level: 3,
+        })
+        .unwrap()
+    }
+
+    fn attestation(miner_id: &str) -> MinerAttestation {
+        let now = Utc::now();
+        MinerAttestation {
+            miner_id: miner_id.to_string(),
+            verifier_id: "auditor".to_string(),
+            reference: "audit-1".to_string(),
+            issued_at: now - Duration::days(1),
+            expires_at: now + Duration::days(90),
+            renewable_percentage: 80.0,
+            rec_certificate_ids: vec!["rec-1".to_string()],
+            verified_rec_mwh: 500.0,
+            verified_offset_tonnes: 0.0,
+            location: None,
+        }
+    }
+
+    fn registry(keys: &QuantumKeyPair) -> VerifierRegistry {
+        let mut registry = VerifierRegistry::new();
+        registry
+            .register_verifier("auditor", "Green Audit Co", SignatureType::Dilithium, 3, keys.public_key.clone())
+            .unwrap();
+        registry
+    }
+
+    #[test]
+    fn test_signed_attestation_verifies() {
+        let keys = verifier_keys();
+        let registry = registry(&keys);
+        let signed = attestation("miner1").sign(&keys).unwrap();
+
+        assert!(registry.verify(&signed, Utc::now()).is_ok());
+
+        // Any change to the claims breaks the signature
+        let mut forged = signed.clone();
+        forged.attestation.renewable_percentage = 100.0;
+        forged.attestation.verified_rec_mwh = 10_000.0;
+        assert_eq!(registry.verify(&forged, Utc::now()), Err(AttestationError::InvalidSignature));
+
+        // Keys outside the registry are not trusted
+        let signed_by_other = attestation("miner1").sign(&verifier_keys()).unwrap();
+        assert_eq!(registry.verify(&signed_by_other, Utc::now()), Err(AttestationError::InvalidSignature));
+    }
+
+    #[test]
+    fn test_expiry_and_revocation() {
+        let keys = verifier_keys();
+        let mut registry = registry(&keys);
+        let signed = attestation("miner1").sign(&keys).unwrap();
+
+        let expires_at = signed.attestation.expires_at;
+        assert_eq!(registry.verify(&signed, expires_at), Err(AttestationError::Expired(expires_at)));
+
+        let mut long_lived = attestation("miner1");
+        long_lived.expires_at = long_lived.issued_at + Duration::days(MAX_ATTESTATION_VALIDITY_DAYS + 1);
+        let long_lived = long_lived.sign(&keys).unwrap();
+        assert_eq!(registry.verify(&long_lived, Utc::now()), Err(AttestationError::ValidityTooLong));
+
+        registry.revoke_verifier("auditor", "key compromised").unwrap();
+        assert_eq!(
+            registry.verify(&signed, Utc::now()),
+            Err(AttestationError::VerifierRevoked("auditor".to_string()))
+        );
+        assert!(registry.active_verifiers().is_empty());
+    }
+
+    #[test]
+    fn test_placeholder_schemes_rejected() {
+        let mut registry = VerifierRegistry::new();
+        let result = registry.register_verifier("auditor", "Auditor", SignatureType::Secp256k1, 3, vec![2u8; 33]);
+        assert_eq!(result, Err(AttestationError::UnsupportedScheme(SignatureType::Secp256k1)));
+    }
+
+    #[test]
+    fn test_coinbase_commitment() {
+        let keys = verifier_keys();
+        let signed = attestation("miner1").sign(&keys).unwrap();
+        let commitment = signed.commitment().unwrap();
+
+        let coinbase = Transaction::new(
+            1,
+            vec![TransactionInput::new([0u8; 32], 0xffffffff, vec![1], 0)],
+            vec![TransactionOutput::new(50, vec![0xaa; 20]), commitment_output(&commitment)],
+            0,
+        );
+        let block = Block::new(1, [0u8; 32], vec![coinbase], 0x207fffff);
+        assert_eq!(find_commitment(&block), Some(commitment));
+
+        let block = Block::new(1, [0u8; 32], Vec::new(), 0x207fffff);
+        assert_eq!(find_commitment(&block), None);
+    }
+}
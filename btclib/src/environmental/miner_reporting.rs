@@ -6,6 +6,8 @@ use chrono::{DateTime, Utc};
 
 use crate::environmental::types::{EnergySource, EmissionFactor, HardwareType, Region};
 use crate::environmental::treasury::VerificationStatus;
+use crate::environmental::attestation::{self, AttestationError, SignedAttestation, VerifierRegistry};
+use crate::types::block::Block;
 
 /// Status of an environmental claim verification
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
@@ -538,6 +540,12 @@ pub struct MinerReportingManager {
     hardware_baselines: HashMap<HardwareType, f64>,
     /// Reports by miner ID
     reports: HashMap<String, MinerEnvironmentalReport>,
+    /// Verifiers trusted to sign attestations
+    verifier_registry: VerifierRegistry,
+    /// Latest accepted attestation by miner ID
+    attestations: HashMap<String, SignedAttestation>,
+    /// Miner ID by attestation commitment
+    commitments: HashMap<[u8; 32], String>,
 }
 
 impl MinerReportingManager {
@@ -548,9 +556,22 @@ impl MinerReportingManager {
             emission_factors: HashMap::new(),
             hardware_baselines: HashMap::new(),
             reports: HashMap::new(),
+            verifier_registry: VerifierRegistry::new(),
+            attestations: HashMap::new(),
+            commitments: HashMap::new(),
         }
     }
 
+    /// Get the verifier registry
+    pub fn verifier_registry(&self) -> &VerifierRegistry {
+        &self.verifier_registry
+    }
+
+    /// Get the verifier registry for registering or revoking verifiers
+    pub fn verifier_registry_mut(&mut self) -> &mut VerifierRegistry {
+        &mut self.verifier_registry
+    }
+
     /// Register a new miner
     pub fn register_miner(&mut self, info: MinerEnvironmentalInfo) -> Result<(), String> {
         if self.miners.contains_key(&info.miner_id) {
@@ -715,35 +736,91 @@ impl MinerReportingManager {
         &self.reports
     }
 
-    /// Verify a report
-    pub fn verify_report(&mut self, miner_id: &str, verification: VerificationInfo) -> bool {
-        if let Some(report) = self.reports.get_mut(miner_id) {
-            // Access original MinerEnvironmentalInfo methods that exist
-            if let Some(miner_info) = self.miners.get_mut(miner_id) {
-                miner_info.add_verification(
-                    verification.provider.clone(),
-                    verification.reference.clone(),
-                    verification.status
-                );
+    /// Accept a signed attestation as a miner's current one
+    ///
+    /// Returns the commitment the miner's blocks carry to claim it.
+    pub fn submit_attestation(&mut self, signed: SignedAttestation) -> Result<[u8; 32], AttestationError> {
+        let verifier_name = self.verifier_registry.verify(&signed, Utc::now())?.name.clone();
+        let attestation = &signed.attestation;
+
+        let miner = self
+            .miners
+            .get_mut(&attestation.miner_id)
+            .ok_or_else(|| AttestationError::UnknownMiner(attestation.miner_id.clone()))?;
+
+        miner.add_verification(verifier_name, attestation.reference.clone(), VerificationStatus::Verified);
+        if let Some(verification) = miner.verification.as_mut() {
+            verification.date = attestation.issued_at;
+        }
+        miner.renewable_percentage = attestation.renewable_percentage;
+
+        let commitment = signed.commitment()?;
+        if let Some(previous) = self.attestations.insert(attestation.miner_id.clone(), signed.clone()) {
+            if let Ok(old) = previous.commitment() {
+                self.commitments.remove(&old);
             }
-            
-            // Determine report status based on verification status
-            match verification.status {
-                VerificationStatus::Verified => {
-                    // Approve the report
-                    self.update_miner_status(miner_id, true);
-                    true
-                },
-                VerificationStatus::Failed => {
-                    // Reject the report
-                    self.update_miner_status(miner_id, false);
-                    true
-                },
-                _ => false,
+        }
+        self.commitments.insert(commitment, signed.attestation.miner_id.clone());
+
+        Ok(commitment)
+    }
+
+    /// A miner's attestation, if it is still valid at `at`
+    pub fn current_attestation(&self, miner_id: &str, at: DateTime<Utc>) -> Option<&SignedAttestation> {
+        self.attestations
+            .get(miner_id)
+            .filter(|signed| self.verifier_registry.verify(signed, at).is_ok())
+    }
+
+    /// Drop attestations that are no longer valid at `now`, returning the affected miners
+    pub fn expire_attestations(&mut self, now: DateTime<Utc>) -> Vec<String> {
+        let stale: Vec<String> = self
+            .attestations
+            .iter()
+            .filter(|(_, signed)| self.verifier_registry.verify(signed, now).is_err())
+            .map(|(miner_id, _)| miner_id.clone())
+            .collect();
+
+        for miner_id in &stale {
+            if let Some(signed) = self.attestations.remove(miner_id) {
+                if let Ok(commitment) = signed.commitment() {
+                    self.commitments.remove(&commitment);
+                }
             }
-        } else {
-            false
+            if let Some(verification) = self.miners.get_mut(miner_id).and_then(|m| m.verification.as_mut()) {
+                verification.status = VerificationStatus::Expired;
+            }
+            warn!("Environmental attestation for miner {} is no longer valid", miner_id);
+        }
+
+        stale
+    }
+
+    /// Check that a block commits to a valid attestation, returning the attested miner
+    pub fn verify_block_attestation(&self, block: &Block) -> Result<&str, AttestationError> {
+        let commitment = attestation::find_commitment(block).ok_or(AttestationError::MissingCommitment)?;
+        let miner_id = self.commitments.get(&commitment).ok_or(AttestationError::UnknownCommitment)?;
+        let signed = self.attestations.get(miner_id).ok_or(AttestationError::UnknownCommitment)?;
+
+        let block_time = DateTime::<Utc>::from_timestamp(block.header().timestamp() as i64, 0)
+            .unwrap_or_default();
+        self.verifier_registry.verify(signed, block_time)?;
+
+        Ok(miner_id)
+    }
+
+    /// Verify a miner's report against a signed attestation
+    pub fn verify_report(&mut self, miner_id: &str, signed: SignedAttestation) -> Result<(), String> {
+        if signed.attestation.miner_id != miner_id {
+            return Err(AttestationError::MinerMismatch {
+                expected: miner_id.to_string(),
+                attested: signed.attestation.miner_id.clone(),
+            }.to_string());
         }
+
+        self.submit_attestation(signed).map_err(|e| e.to_string())?;
+        self.update_miner_status(miner_id, true);
+        Ok(())
     }
     
     /// Update miner status after verification
@@ -772,35 +849,35 @@ impl MinerReportingManager {
         }
     }
 
-    /// Verify miner location using multiple methods
-    pub fn verify_miner_location(
-        &mut self, 
-        miner_id: &str, 
-        method: LocationVerificationMethod,
-        evidence: Option<String>
-    ) -> Result<(), String> {
+    /// Verify miner location from the location in its current attestation
+    pub fn verify_miner_location(&mut self, miner_id: &str) -> Result<(), String> {
+        let signed = self
+            .current_attestation(miner_id, Utc::now())
+            .ok_or_else(|| format!("Miner {} has no valid attestation", miner_id))?;
+        let location = signed
+            .attestation
+            .location
+            .clone()
+            .ok_or_else(|| format!("Attestation for miner {} does not cover its location", miner_id))?;
+        let verifier = Some(signed.attestation.verifier_id.clone());
+        let evidence_reference = Some(signed.attestation.reference.clone());
+
         let miner = match self.miners.get_mut(miner_id) {
             Some(miner) => miner,
             None => return Err(format!("Miner with ID {} not found", miner_id)),
         };
-        
-        // Determine confidence level based on verification method
-        let confidence = match method {
-            LocationVerificationMethod::MultiFactor => 0.95,
-            LocationVerificationMethod::Audit => 0.9,
-            LocationVerificationMethod::GovernmentRegistry => 0.85,
-            LocationVerificationMethod::CryptographicProof => 0.8,
-            LocationVerificationMethod::IPGeolocation => 0.6,
-            LocationVerificationMethod::SelfDeclared => 0.3,
-        };
+
+        if location.region != miner.region {
+            return Err(format!("Attested region {:?} does not match miner region {:?}", location.region, miner.region));
+        }
         
         // Create verification record
         let verification = LocationVerification {
-            method,
+            method: location.method,
             timestamp: Utc::now(),
-            confidence,
-            verifier: None, // Would be set in a real implementation
-            evidence_reference: evidence,
+            confidence: location_confidence(location.method),
+            verifier,
+            evidence_reference,
             status: VerificationStatus::Verified,
         };
         
@@ -810,12 +887,23 @@ impl MinerReportingManager {
         Ok(())
     }
     
-    /// Verify REC certificate
+    /// Verify a REC certificate covered by the miner's current attestation
     pub fn verify_rec_certificate(
         &mut self, 
         miner_id: &str, 
         certificate_id: &str
     ) -> Result<(), String> {
+        let attested = self
+            .current_attestation(miner_id, Utc::now())
+            .ok_or_else(|| format!("Miner {} has no valid attestation", miner_id))?
+            .attestation
+            .rec_certificate_ids
+            .iter()
+            .any(|id| id == certificate_id);
+        if !attested {
+            return Err(format!("Certificate {} is not covered by miner {}'s attestation", certificate_id, miner_id));
+        }
+
         let miner = match self.miners.get_mut(miner_id) {
             Some(miner) => miner,
             None => return Err(format!("Miner with ID {} not found", miner_id)),
@@ -826,9 +914,6 @@ impl MinerReportingManager {
             .position(|cert| cert.certificate_id == certificate_id)
             .ok_or_else(|| format!("Certificate with ID {} not found", certificate_id))?;
         
-        // In a real system, this would connect to a REC verification service
-        // For now, we just simulate verification
-        
         // Update verification status
         miner.rec_certificates[cert_index].verification_status = VerificationStatus::Verified;
         miner.rec_certificates[cert_index].last_verified = Some(Utc::now());
@@ -840,30 +925,37 @@ impl MinerReportingManager {
     }
     
     /// Calculate fee discount with REC prioritization
+    ///
+    /// Only claims in the miner's currently valid attestation count; self-reported
+    /// figures earn no discount.
     pub fn calculate_fee_discount_with_rec_priority(&self, miner_id: &str) -> f64 {
         let info = match self.miners.get(miner_id) {
             Some(info) => info,
             None => return 0.0, // No discount for non-registered miners
         };
+        let attested = match self.current_attestation(miner_id, Utc::now()) {
+            Some(signed) => &signed.attestation,
+            None => return 0.0, // No discount without a valid attestation
+        };
         
         // Base discount from renewable percentage
-        let base_discount = if info.renewable_percentage >= 95.0 {
+        let base_discount = if attested.renewable_percentage >= 95.0 {
             10.0 // 10% discount for 95%+ renewable
-        } else if info.renewable_percentage >= 75.0 {
+        } else if attested.renewable_percentage >= 75.0 {
             7.0 // 7% discount for 75%+ renewable
-        } else if info.renewable_percentage >= 50.0 {
+        } else if attested.renewable_percentage >= 50.0 {
             5.0 // 5% discount for 50%+ renewable
-        } else if info.renewable_percentage >= 25.0 {
+        } else if attested.renewable_percentage >= 25.0 {
             2.0 // 2% discount for 25%+ renewable
         } else {
             0.0 // No discount for less than 25% renewable
         };
         
         // REC bonus - prioritize RECs over everything else
-        let rec_bonus = if info.has_verified_recs() {
+        let annual_energy_mwh = info.energy_consumption_kwh_day * 365.0 / 1000.0;
+        let rec_bonus = if attested.verified_rec_mwh > 0.0 && annual_energy_mwh > 0.0 {
             // Calculate REC coverage percentage relative to energy consumption
-            let annual_energy_mwh = info.energy_consumption_kwh_day * 365.0 / 1000.0;
-            let rec_coverage = (info.total_verified_recs_mwh() / annual_energy_mwh).min(1.0);
+            let rec_coverage = (attested.verified_rec_mwh / annual_energy_mwh).min(1.0);
             
             // Bonus based on REC coverage
             rec_coverage * 5.0 // Up to 5% additional discount
@@ -872,21 +964,16 @@ impl MinerReportingManager {
         };
         
         // Offset bonus - smaller bonus for offsets
-        let offset_bonus = if info.has_verified_offsets() {
+        let offset_bonus = if attested.verified_offset_tonnes > 0.0 {
             2.0 // 2% additional discount for verified offsets
         } else {
             0.0
         };
         
         // Location verification bonus
-        let location_bonus = if let Some(verification) = &info.location_verification {
-            if verification.status == VerificationStatus::Verified {
-                verification.confidence * 3.0 // Up to 3% additional discount
-            } else {
-                0.0
-            }
-        } else {
-            0.0
+        let location_bonus = match &attested.location {
+            Some(location) => location_confidence(location.method) * 3.0, // Up to 3% additional discount
+            None => 0.0,
         };
         
         // Total discount
@@ -953,6 +1040,18 @@ impl MinerReportingManager {
     }
 }
 
+/// Confidence placed in a location verification method (0-1)
+fn location_confidence(method: LocationVerificationMethod) -> f64 {
+    match method {
+        LocationVerificationMethod::MultiFactor => 0.95,
+        LocationVerificationMethod::Audit => 0.9,
+        LocationVerificationMethod::GovernmentRegistry => 0.85,
+        LocationVerificationMethod::CryptographicProof => 0.8,
+        LocationVerificationMethod::IPGeolocation => 0.6,
+        LocationVerificationMethod::SelfDeclared => 0.3,
+    }
+}
+
 /// Report of miners' environmental status
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MinerEnvironmentalReport {
@@ -1038,6 +1137,110 @@ pub enum HardwareType {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::crypto::quantum::{QuantumKeyPair, QuantumParameters, QuantumScheme};
+    use crate::crypto::signature::SignatureType;
+    use crate::environmental::attestation::{commitment_output, MinerAttestation};
+    use crate::types::transaction::{Transaction, TransactionInput, TransactionOutput};
+    use rand::rngs::OsRng;
+
+    fn attested_manager() -> (MinerReportingManager, QuantumKeyPair) {
+        let keys = QuantumKeyPair::generate(&mut OsRng, QuantumParameters {
+            scheme: QuantumScheme::Dilithium,
+            security_level: 3,
+        })
+        .unwrap();
+
+        let mut manager = MinerReportingManager::new();
+        manager
+            .verifier_registry_mut()
+            .register_verifier("auditor", "Green Audit Co", SignatureType::Dilithium, 3, keys.public_key.clone())
+            .unwrap();
+
+        // Self-declares 100% renewables
+        let mut miner = MinerEnvironmentalInfo::new("miner1".to_string(), "Miner".to_string(), Region::new("CA"));
+        miner.update_performance_metrics(100.0, 2400.0).unwrap();
+        miner.renewable_percentage = 100.0;
+        miner.add_verification("self".to_string(), "none".to_string(), VerificationStatus::Verified);
+        manager.register_miner(miner).unwrap();
+
+        (manager, keys)
+    }
+
+    fn attestation(renewable_percentage: f64) -> MinerAttestation {
+        let now = Utc::now();
+        MinerAttestation {
+            miner_id: "miner1".to_string(),
+            verifier_id: "auditor".to_string(),
+            reference: "audit-7".to_string(),
+            issued_at: now - chrono::Duration::hours(1),
+            expires_at: now + chrono::Duration::days(30),
+            renewable_percentage,
+            rec_certificate_ids: Vec::new(),
+            verified_rec_mwh: 0.0,
+            verified_offset_tonnes: 0.0,
+            location: None,
+        }
+    }
+
+    #[test]
+    fn test_fee_discount_requires_attestation() {
+        let (mut manager, keys) = attested_manager();
+
+        // Self-reported claims earn nothing
+        assert_eq!(manager.calculate_fee_discount_with_rec_priority("miner1"), 0.0);
+
+        // The attested figure, not the self-reported one, sets the discount
+        manager.submit_attestation(attestation(60.0).sign(&keys).unwrap()).unwrap();
+        assert_eq!(manager.calculate_fee_discount_with_rec_priority("miner1"), 5.0);
+        assert_eq!(manager.get_miner("miner1").unwrap().renewable_percentage, 60.0);
+
+        // Revoking the verifier withdraws the discount
+        manager.verifier_registry_mut().revoke_verifier("auditor", "audit irregularities").unwrap();
+        assert_eq!(manager.calculate_fee_discount_with_rec_priority("miner1"), 0.0);
+        assert_eq!(manager.expire_attestations(Utc::now()), vec!["miner1".to_string()]);
+        assert_eq!(
+            manager.get_miner("miner1").unwrap().verification.as_ref().unwrap().status,
+            VerificationStatus::Expired
+        );
+    }
+
+    #[test]
+    fn test_unsigned_claims_rejected() {
+        let (mut manager, keys) = attested_manager();
+
+        let mut forged = attestation(60.0).sign(&keys).unwrap();
+        forged.attestation.renewable_percentage = 100.0;
+        assert!(manager.verify_report("miner1", forged).is_err());
+
+        let other_miner = attestation(60.0).sign(&keys).unwrap();
+        assert!(manager.verify_report("miner2", other_miner).is_err());
+
+        // Certificates must be covered by a valid attestation
+        assert!(manager.verify_rec_certificate("miner1", "rec-1").is_err());
+        assert!(manager.verify_miner_location("miner1").is_err());
+    }
+
+    #[test]
+    fn test_block_attestation_commitment() {
+        let (mut manager, keys) = attested_manager();
+        let commitment = manager.submit_attestation(attestation(80.0).sign(&keys).unwrap()).unwrap();
+
+        let coinbase = |outputs: Vec<TransactionOutput>| Transaction::new(
+            1,
+            vec![TransactionInput::new([0u8; 32], 0xffffffff, vec![1], 0)],
+            outputs,
+            0,
+        );
+
+        let block = Block::new(1, [0u8; 32], vec![coinbase(vec![commitment_output(&commitment)])], 0x207fffff);
+        assert_eq!(manager.verify_block_attestation(&block), Ok("miner1"));
+
+        let block = Block::new(1, [0u8; 32], vec![coinbase(vec![commitment_output(&[9u8; 32])])], 0x207fffff);
+        assert_eq!(manager.verify_block_attestation(&block), Err(AttestationError::UnknownCommitment));
+
+        let block = Block::new(1, [0u8; 32], vec![coinbase(Vec::new())], 0x207fffff);
+        assert_eq!(manager.verify_block_attestation(&block), Err(AttestationError::MissingCommitment));
+    }
     
     #[test]
     fn test_miner_carbon_footprint_calculation() {
@@ -5,8 +5,10 @@ pub mod emissions;
 pub mod treasury;
 pub mod dashboard;
 pub mod miner_reporting;
+pub mod attestation;
 
 // Re-export key types for convenience
 pub use emissions::{EmissionsTracker, Emissions, Region, HashRate, PoolId, PoolEnergyInfo};
 pub use treasury::{EnvironmentalTreasury, EnvironmentalAssetType, EnvironmentalAssetPurchase};
-pub use dashboard::{EnvironmentalDashboard, EnvironmentalMetrics, EmissionsTimePeriod}; 
\ No newline at end of file
+pub use dashboard::{EnvironmentalDashboard, EnvironmentalMetrics, EmissionsTimePeriod}; 
+pub use attestation::{AttestationError, MinerAttestation, SignedAttestation, VerifierRegistry};
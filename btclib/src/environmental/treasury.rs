@@ -363,7 +363,7 @@ impl EnvironmentalTreasury {
     /// Process transaction fees, allocating a portion to the environmental treasury
     pub fn process_transaction_fees(&self, total_fees: u64) -> Result<u64, TreasuryError> {
         let fee_percentage = *self.current_fee_percentage.read().unwrap();
-        let treasury_amount = (total_fees as f64 * fee_percentage / 100.0) as u64;
+        let treasury_amount = crate::consensus::treasury_share(total_fees, fee_percentage);
         
         if treasury_amount == 0 {
             return Ok(0);
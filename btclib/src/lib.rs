@@ -4,9 +4,11 @@
 // Re-export public API
 pub mod api;
 pub mod config;
+pub mod consensus;
 pub mod crypto;
 pub mod environmental;
 pub mod types;
+pub mod util;
 pub mod validation;
 pub mod testnet;
 pub mod consensus_verification;
@@ -25,7 +27,10 @@ mod monitoring;
 // Re-export common types for convenience
 pub use types::transaction::{Transaction, TransactionInput, TransactionOutput};
 pub use types::block::{Block, BlockHeader};
+pub use types::address::{Address, AddressError, AddressKind};
+pub use types::psbt::{PartiallySignedTransaction, PsbtError, KeyOrigin};
 pub use types::units::{NovaUnit, TOTAL_NOVA_SUPPLY, format_as_nova};
+pub use consensus::subsidy::{block_subsidy, COIN, COINBASE_MATURITY};
 pub use crypto::quantum::{QuantumKeyPair, QuantumParameters, QuantumScheme, ClassicalScheme};
 pub use environmental::emissions::{EmissionsTracker, Emissions};
 pub use environmental::treasury::{EnvironmentalTreasury, EnvironmentalAssetType};
@@ -0,0 +1,294 @@
+//! Nova addresses
+//!
+//! An address is the bech32m encoding of a locking script. The human-readable
+//! part names the network, the first data character is the key scheme, or `m`
+//! for a multisig policy, and the rest is the 32-byte key or policy hash:
+//!
+//! ```text
+//! nova1q...   secp256k1 key on mainnet
+//! tnova1p...  Dilithium key on testnet
+//! rnova1r...  hybrid key on regtest
+//! tnova1m...  multisig policy on testnet
+//! ```
+
+use std::fmt;
+use std::str::FromStr;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use thiserror::Error;
+
+use crate::config::NetworkType;
+use crate::crypto::signature::SignatureType;
+use crate::types::script;
+use crate::util::bech32::{self, Bech32Error};
+
+/// Human-readable part of mainnet addresses
+pub const MAINNET_HRP: &str = "nova";
+/// Human-readable part of testnet addresses
+pub const TESTNET_HRP: &str = "tnova";
+/// Human-readable part of regtest addresses
+pub const REGTEST_HRP: &str = "rnova";
+
+/// First data value of multisig addresses, which encodes as `m`
+pub const MULTISIG_TAG: u8 = 27;
+
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum AddressError {
+    #[error("Invalid address encoding: {0}")]
+    Encoding(#[from] Bech32Error),
+    #[error("Unknown address prefix: {0}")]
+    UnknownPrefix(String),
+    #[error("Key scheme {0:?} cannot be used in addresses")]
+    UnsupportedKeyType(SignatureType),
+    #[error("Unknown key scheme tag {0}")]
+    UnknownKeyType(u8),
+    #[error("Invalid key hash length: {0} bytes")]
+    InvalidLength(usize),
+    #[error("Address is for {found:?}, expected {expected:?}")]
+    WrongNetwork { expected: NetworkType, found: NetworkType },
+    #[error("Script is not a pay-to-public-key-hash or pay-to-multisig-hash script")]
+    UnsupportedScript,
+}
+
+/// What an address pays to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AddressKind {
+    /// A single key of the given scheme
+    KeyHash(SignatureType),
+    /// An m-of-n multisig policy
+    Multisig,
+}
+
+/// A network-tagged key or policy hash that coins can be sent to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Address {
+    network: NetworkType,
+    kind: AddressKind,
+    key_hash: [u8; 32],
+}
+
+impl Address {
+    /// Address for `key_hash` under `key_type`; only schemes usable in addresses are accepted
+    pub fn new(network: NetworkType, key_type: SignatureType, key_hash: [u8; 32]) -> Result<Self, AddressError> {
+        if !Self::supports(key_type) {
+            return Err(AddressError::UnsupportedKeyType(key_type));
+        }
+        Ok(Self { network, kind: AddressKind::KeyHash(key_type), key_hash })
+    }
+
+    /// Address of a multisig policy, given its hash
+    pub fn multisig(network: NetworkType, policy_hash: [u8; 32]) -> Self {
+        Self { network, kind: AddressKind::Multisig, key_hash: policy_hash }
+    }
+
+    /// Address of a public key
+    pub fn from_public_key(
+        network: NetworkType,
+        key_type: SignatureType,
+        security_level: u8,
+        public_key: &[u8],
+    ) -> Result<Self, AddressError> {
+        Self::new(network, key_type, script::pubkey_hash(key_type, security_level, public_key))
+    }
+
+    /// Address an output script pays to
+    pub fn from_script(network: NetworkType, script: &[u8]) -> Result<Self, AddressError> {
+        if let Some(policy_hash) = script::parse_multisig_hash(script) {
+            return Ok(Self::multisig(network, policy_hash));
+        }
+        let (key_type, key_hash) = script::parse_pubkey_hash(script).ok_or(AddressError::UnsupportedScript)?;
+        Self::new(network, key_type, key_hash)
+    }
+
+    /// Parse an address and require it to belong to `network`
+    pub fn parse_for_network(s: &str, network: NetworkType) -> Result<Self, AddressError> {
+        let address: Self = s.parse()?;
+        if address.network != network {
+            return Err(AddressError::WrongNetwork { expected: network, found: address.network });
+        }
+        Ok(address)
+    }
+
+    /// Whether addresses can carry keys of `key_type`
+    pub fn supports(key_type: SignatureType) -> bool {
+        matches!(
+            key_type,
+            SignatureType::Secp256k1 | SignatureType::Dilithium | SignatureType::Falcon | SignatureType::Hybrid
+        )
+    }
+
+    /// Human-readable part used on `network`
+    pub fn hrp(network: NetworkType) -> &'static str {
+        match network {
+            NetworkType::Mainnet => MAINNET_HRP,
+            NetworkType::Testnet => TESTNET_HRP,
+            NetworkType::Regtest => REGTEST_HRP,
+        }
+    }
+
+    fn network_for_hrp(hrp: &str) -> Option<NetworkType> {
+        match hrp {
+            MAINNET_HRP => Some(NetworkType::Mainnet),
+            TESTNET_HRP => Some(NetworkType::Testnet),
+            REGTEST_HRP => Some(NetworkType::Regtest),
+            _ => None,
+        }
+    }
+
+    /// Locking script for outputs paying this address
+    pub fn script_pubkey(&self) -> Vec<u8> {
+        match self.kind {
+            AddressKind::KeyHash(key_type) => script::pay_to_key_hash(key_type, &self.key_hash),
+            AddressKind::Multisig => script::pay_to_multisig_hash(&self.key_hash),
+        }
+    }
+
+    pub fn network(&self) -> NetworkType {
+        self.network
+    }
+
+    pub fn kind(&self) -> AddressKind {
+        self.kind
+    }
+
+    /// Scheme of the key paid to; `None` for multisig addresses
+    pub fn key_type(&self) -> Option<SignatureType> {
+        match self.kind {
+            AddressKind::KeyHash(key_type) => Some(key_type),
+            AddressKind::Multisig => None,
+        }
+    }
+
+    /// Key hash, or policy hash for multisig addresses
+    pub fn key_hash(&self) -> &[u8; 32] {
+        &self.key_hash
+    }
+
+    /// Whether spending from this address needs a post-quantum signature
+    ///
+    /// The keys behind a multisig address are not visible in it, so those
+    /// addresses are never reported as quantum resistant.
+    pub fn is_quantum_resistant(&self) -> bool {
+        matches!(self.kind, AddressKind::KeyHash(key_type) if key_type != SignatureType::Secp256k1)
+    }
+}
+
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let tag = match self.kind {
+            AddressKind::KeyHash(key_type) => script::scheme_tag(key_type),
+            AddressKind::Multisig => MULTISIG_TAG,
+        };
+        let mut data = vec![tag];
+        data.extend(bech32::convert_bits(&self.key_hash, 8, 5, true).map_err(|_| fmt::Error)?);
+        f.write_str(&bech32::encode(Self::hrp(self.network), &data))
+    }
+}
+
+impl FromStr for Address {
+    type Err = AddressError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (hrp, data) = bech32::decode(s)?;
+        let network = Self::network_for_hrp(&hrp).ok_or(AddressError::UnknownPrefix(hrp))?;
+
+        let (&tag, hash_data) = data.split_first().ok_or(AddressError::InvalidLength(0))?;
+        let hash = bech32::convert_bits(hash_data, 5, 8, false)?;
+        let key_hash: [u8; 32] = hash
+            .as_slice()
+            .try_into()
+            .map_err(|_| AddressError::InvalidLength(hash.len()))?;
+
+        if tag == MULTISIG_TAG {
+            return Ok(Self::multisig(network, key_hash));
+        }
+        let key_type = script::scheme_from_tag(tag).ok_or(AddressError::UnknownKeyType(tag))?;
+        Self::new(network, key_type, key_hash)
+    }
+}
+
+impl Serialize for Address {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Address {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_address_roundtrip() {
+        for (network, prefix) in [
+            (NetworkType::Mainnet, "nova1"),
+            (NetworkType::Testnet, "tnova1"),
+            (NetworkType::Regtest, "rnova1"),
+        ] {
+            for key_type in [
+                SignatureType::Secp256k1,
+                SignatureType::Dilithium,
+                SignatureType::Falcon,
+                SignatureType::Hybrid,
+            ] {
+                let address = Address::from_public_key(network, key_type, 3, &[7u8; 64]).unwrap();
+                let encoded = address.to_string();
+                assert!(encoded.starts_with(prefix));
+                assert_eq!(encoded.parse::<Address>().unwrap(), address);
+                assert_eq!(encoded.to_uppercase().parse::<Address>().unwrap(), address);
+                assert_eq!(Address::from_script(network, &address.script_pubkey()).unwrap(), address);
+            }
+        }
+
+        // The scheme is visible right after the separator
+        let quantum = Address::new(NetworkType::Mainnet, SignatureType::Dilithium, [1u8; 32]).unwrap();
+        assert!(quantum.to_string().starts_with("nova1p"));
+        assert!(quantum.is_quantum_resistant());
+
+        let multisig = Address::multisig(NetworkType::Testnet, [2u8; 32]);
+        let encoded = multisig.to_string();
+        assert!(encoded.starts_with("tnova1m"));
+        assert_eq!(encoded.parse::<Address>().unwrap(), multisig);
+        assert_eq!(Address::from_script(NetworkType::Testnet, &multisig.script_pubkey()).unwrap(), multisig);
+        assert_eq!(multisig.key_type(), None);
+        assert!(!multisig.is_quantum_resistant());
+    }
+
+    #[test]
+    fn test_address_validation() {
+        let address = Address::new(NetworkType::Testnet, SignatureType::Secp256k1, [9u8; 32]).unwrap();
+        let encoded = address.to_string();
+
+        assert_eq!(Address::parse_for_network(&encoded, NetworkType::Testnet).unwrap(), address);
+        assert_eq!(
+            Address::parse_for_network(&encoded, NetworkType::Mainnet),
+            Err(AddressError::WrongNetwork { expected: NetworkType::Mainnet, found: NetworkType::Testnet })
+        );
+
+        // A single changed character breaks the checksum
+        let mut corrupted = encoded.clone().into_bytes();
+        let last = corrupted.len() - 1;
+        corrupted[last] = if corrupted[last] == b'q' { b'p' } else { b'q' };
+        assert!(matches!(
+            String::from_utf8(corrupted).unwrap().parse::<Address>(),
+            Err(AddressError::Encoding(_))
+        ));
+
+        // Bitcoin addresses and unknown prefixes are rejected
+        assert!("bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq".parse::<Address>().is_err());
+        let foreign = bech32::encode("btc", &[0u8; 53]);
+        assert_eq!(foreign.parse::<Address>(), Err(AddressError::UnknownPrefix("btc".to_string())));
+
+        // Schemes that cannot sign for outputs have no address form
+        assert_eq!(
+            Address::new(NetworkType::Mainnet, SignatureType::Ed25519, [0u8; 32]),
+            Err(AddressError::UnsupportedKeyType(SignatureType::Ed25519))
+        );
+        assert_eq!(Address::from_script(NetworkType::Mainnet, b"not a script"), Err(AddressError::UnsupportedScript));
+    }
+}
@@ -2,6 +2,7 @@ use serde::{Deserialize, Serialize};
 use sha2::{Sha256, Digest};
 use std::time::{SystemTime, UNIX_EPOCH};
 use crate::util::merkle::MerkleTree;
+use crate::consensus::difficulty::hash_meets_target;
 use crate::types::transaction::{Transaction, TransactionInput, TransactionOutput};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -52,6 +53,57 @@ impl BlockHeader {
     pub fn prev_block_hash(&self) -> [u8; 32] {
         self.prev_block_hash
     }
+
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    pub fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
+
+    pub fn merkle_root(&self) -> [u8; 32] {
+        self.merkle_root
+    }
+
+    /// Target in compact ("nBits") form
+    pub fn target(&self) -> u32 {
+        self.target
+    }
+
+    pub fn nonce(&self) -> u32 {
+        self.nonce
+    }
+
+    pub fn set_timestamp(&mut self, timestamp: u64) {
+        self.timestamp = timestamp;
+    }
+
+    pub fn set_nonce(&mut self, nonce: u32) {
+        self.nonce = nonce;
+    }
+
+    /// Check the full 256-bit header hash against its own target
+    pub fn meets_target(&self) -> bool {
+        hash_meets_target(&self.hash(), self.target)
+    }
+
+    /// Search nonces from the current one until the header meets its target
+    ///
+    /// Returns `false` if the nonce space runs out. Only practical for easy
+    /// targets such as regtest's.
+    pub fn solve(&mut self) -> bool {
+        let start = self.nonce;
+        loop {
+            if self.meets_target() {
+                return true;
+            }
+            self.increment_nonce();
+            if self.nonce == start {
+                return false;
+            }
+        }
+    }
 }
 
 impl Block {
@@ -69,6 +121,18 @@ impl Block {
         }
     }
 
+    /// Assemble a block from an already-built header (e.g. a header solved by an external miner)
+    pub fn from_header(header: BlockHeader, transactions: Vec<Transaction>) -> Self {
+        Self {
+            header,
+            transactions,
+        }
+    }
+
+    pub fn header(&self) -> &BlockHeader {
+        &self.header
+    }
+
     pub fn hash(&self) -> [u8; 32] {
         self.header.hash()
     }
@@ -77,7 +141,12 @@ impl Block {
         self.header.increment_nonce();
     }
 
-    fn calculate_merkle_root(transactions: &[Transaction]) -> [u8; 32] {
+    /// Search nonces until the header meets its target (see `BlockHeader::solve`)
+    pub fn solve(&mut self) -> bool {
+        self.header.solve()
+    }
+
+    pub fn calculate_merkle_root(transactions: &[Transaction]) -> [u8; 32] {
         let tx_bytes: Vec<Vec<u8>> = transactions
             .iter()
             .map(|tx| bincode::serialize(&tx).unwrap())
@@ -100,12 +169,7 @@ impl Block {
     }
 
     pub fn validate(&self) -> bool {
-        let hash = self.header.hash();
-        let target = self.header.target;
-        
-        let hash_value = u32::from_be_bytes([hash[0], hash[1], hash[2], hash[3]]);
-        
-        if hash_value > target {
+        if !self.header.meets_target() {
             return false;
         }
 
@@ -132,21 +196,27 @@ impl Block {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::consensus::REGTEST_POW_LIMIT_BITS;
 
     #[test]
     fn test_block_creation() {
         let prev_hash = [0u8; 32];
         let transactions = Vec::new();
-        let block = Block::new(1, prev_hash, transactions, u32::MAX);
+        let mut block = Block::new(1, prev_hash, transactions, REGTEST_POW_LIMIT_BITS);
         
         assert_eq!(block.header.version, 1);
         assert_eq!(block.header.prev_block_hash, prev_hash);
+        assert!(block.solve());
         assert!(block.validate());
+
+        // Invalid compact targets are never met
+        let block = Block::new(1, prev_hash, Vec::new(), u32::MAX);
+        assert!(!block.validate());
     }
 
     #[test]
     fn test_nonce_increment() {
-        let mut header = BlockHeader::new(1, [0u8; 32], [0u8; 32], u32::MAX);
+        let mut header = BlockHeader::new(1, [0u8; 32], [0u8; 32], REGTEST_POW_LIMIT_BITS);
         let initial_nonce = header.nonce;
         header.increment_nonce();
         assert_eq!(header.nonce, initial_nonce + 1);
@@ -171,7 +241,7 @@ mod tests {
 
         let prev_hash = [0u8; 32];
         let transactions = vec![tx.clone()];
-        let block = Block::new(1, prev_hash, transactions, u32::MAX);
+        let block = Block::new(1, prev_hash, transactions, REGTEST_POW_LIMIT_BITS);
 
         assert!(block.verify_transaction(&tx));
 
@@ -1,4 +1,7 @@
 pub mod block;
 pub mod transaction;
+pub mod script;
+pub mod address;
+pub mod psbt;
 pub mod extended_transaction;
 pub mod units; 
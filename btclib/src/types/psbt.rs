@@ -0,0 +1,533 @@
+//! Partially signed transactions
+//!
+//! A [`PartiallySignedTransaction`] moves an unsigned transaction between the
+//! parties that build, sign and broadcast it, in the spirit of BIP-174. Each
+//! input carries the output it spends, the origins of the keys that may sign
+//! it and the signatures gathered so far, so a signer needs no chain access.
+//!
+//! The roles are:
+//!
+//! - creator: [`PartiallySignedTransaction::new`] wraps the unsigned transaction
+//! - updater: fills in spent outputs, key origins and multisig policies
+//! - signer: [`PartiallySignedTransaction::add_signature`] for each key it holds
+//! - combiner: [`PartiallySignedTransaction::combine`] merges copies signed apart
+//! - finalizer: [`PartiallySignedTransaction::finalize`] turns signatures into signature scripts
+//! - extractor: [`PartiallySignedTransaction::extract`] yields the broadcastable transaction
+
+use std::fmt;
+use std::str::FromStr;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::types::script::{self, InputWitness, MultisigPolicy, MultisigWitness, ScriptError};
+use crate::types::transaction::{Transaction, TransactionOutput};
+
+/// Leading bytes of a serialized partially signed transaction
+pub const PSBT_MAGIC: &[u8; 5] = b"npsbt";
+
+/// Format version written by this implementation
+pub const PSBT_VERSION: u8 = 1;
+
+/// Bit marking a hardened step in a derivation path
+pub const HARDENED: u32 = 0x8000_0000;
+
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum PsbtError {
+    #[error("Input {0} does not exist")]
+    InputOutOfRange(usize),
+    #[error("Input {0} is missing the output it spends")]
+    MissingSpentOutput(usize),
+    #[error("Input {0} is not finalized")]
+    NotFinalized(usize),
+    #[error("Input {0} has no signature that completes it")]
+    MissingSignature(usize),
+    #[error("Partially signed transactions are for different transactions")]
+    DifferentTransactions,
+    #[error("Input {0} spends a different output in each copy")]
+    ConflictingSpentOutput(usize),
+    #[error("Input {0} spends a multisig output but has no policy")]
+    MissingPolicy(usize),
+    #[error("Key is not part of the multisig policy of input {0}")]
+    UnknownKey(usize),
+    #[error("Script error: {0}")]
+    Script(#[from] ScriptError),
+    #[error("Invalid encoding: {0}")]
+    Encoding(String),
+    #[error("Unsupported version {0}")]
+    UnsupportedVersion(u8),
+}
+
+/// Where a key comes from in an HD wallet
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct KeyOrigin {
+    /// Fingerprint of the wallet's master key
+    pub fingerprint: [u8; 4],
+    /// Child indexes from the master key, with [`HARDENED`] set on hardened steps
+    pub path: Vec<u32>,
+}
+
+impl fmt::Display for KeyOrigin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", hex::encode(self.fingerprint))?;
+        for step in &self.path {
+            if step & HARDENED != 0 {
+                write!(f, "/{}'", step & !HARDENED)?;
+            } else {
+                write!(f, "/{}", step)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for KeyOrigin {
+    type Err = PsbtError;
+
+    /// Parse `fingerprint/44'/1'/0'/0/3`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || PsbtError::Encoding(format!("invalid key origin: {}", s));
+        let mut parts = s.split('/');
+        let fingerprint = parts
+            .next()
+            .and_then(|fp| hex::decode(fp).ok())
+            .and_then(|fp| fp.try_into().ok())
+            .ok_or_else(invalid)?;
+
+        let path = parts
+            .map(|step| {
+                let (index, hardened) = match step.strip_suffix('\'').or_else(|| step.strip_suffix('h')) {
+                    Some(index) => (index, true),
+                    None => (step, false),
+                };
+                let index: u32 = index.parse().map_err(|_| invalid())?;
+                if index & HARDENED != 0 {
+                    return Err(invalid());
+                }
+                Ok(if hardened { index | HARDENED } else { index })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self { fingerprint, path })
+    }
+}
+
+/// Signing data for one input
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PsbtInput {
+    /// Output this input spends
+    pub spent_output: Option<TransactionOutput>,
+    /// Keys that can sign for the spent output
+    pub key_origins: Vec<KeyOrigin>,
+    /// Policy behind the spent output when it is locked to a multisig hash
+    pub multisig: Option<MultisigPolicy>,
+    /// Signatures collected so far, at most one per public key
+    pub partial_signatures: Vec<InputWitness>,
+    /// Signature script once the input is finalized
+    pub final_script: Option<Vec<u8>>,
+}
+
+/// Data about one output, such as which keys a change output belongs to
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PsbtOutput {
+    pub key_origins: Vec<KeyOrigin>,
+}
+
+/// An unsigned transaction together with what its signers need
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PartiallySignedTransaction {
+    unsigned_tx: Transaction,
+    pub inputs: Vec<PsbtInput>,
+    pub outputs: Vec<PsbtOutput>,
+}
+
+impl PartiallySignedTransaction {
+    /// Wrap `tx`, dropping any signature scripts it already has
+    pub fn new(tx: &Transaction) -> Self {
+        Self {
+            unsigned_tx: tx.without_signature_scripts(),
+            inputs: vec![PsbtInput::default(); tx.inputs().len()],
+            outputs: vec![PsbtOutput::default(); tx.outputs().len()],
+        }
+    }
+
+    pub fn unsigned_tx(&self) -> &Transaction {
+        &self.unsigned_tx
+    }
+
+    fn input(&self, index: usize) -> Result<&PsbtInput, PsbtError> {
+        self.inputs.get(index).ok_or(PsbtError::InputOutOfRange(index))
+    }
+
+    fn spent_output(&self, index: usize) -> Result<&TransactionOutput, PsbtError> {
+        self.input(index)?
+            .spent_output
+            .as_ref()
+            .ok_or(PsbtError::MissingSpentOutput(index))
+    }
+
+    /// Message a signer signs for input `index`
+    pub fn sighash(&self, index: usize) -> Result<[u8; 32], PsbtError> {
+        Ok(script::signature_hash(&self.unsigned_tx, index, self.spent_output(index)?)?)
+    }
+
+    /// Record a signature for input `index`
+    ///
+    /// The signature is checked against the spent output before it is kept,
+    /// so a bad signature is caught by the signer rather than at broadcast. A
+    /// new signature from the same key replaces the old one.
+    pub fn add_signature(&mut self, index: usize, witness: InputWitness) -> Result<(), PsbtError> {
+        let spent = self.spent_output(index)?.clone();
+        match self.policy(index)? {
+            Some(policy) => {
+                let key = policy.key_index(&witness.public_key)
+                    .map(|key_index| &policy.keys()[key_index])
+                    .filter(|key| key.sig_type == witness.sig_type && key.security_level == witness.security_level)
+                    .ok_or(PsbtError::UnknownKey(index))?;
+                let sighash = script::signature_hash(&self.unsigned_tx, index, &spent)?;
+                if !script::verify_signature(key.sig_type, key.security_level, &key.public_key, &sighash, &witness.signature)? {
+                    return Err(ScriptError::InvalidSignature(index).into());
+                }
+            }
+            None => {
+                let mut candidate = self.unsigned_tx.clone();
+                candidate.set_signature_script(index, witness.to_script());
+                script::verify_input(&candidate, index, &spent)?;
+            }
+        }
+
+        let input = &mut self.inputs[index];
+        input.partial_signatures.retain(|existing| existing.public_key != witness.public_key);
+        input.partial_signatures.push(witness);
+        Ok(())
+    }
+
+    /// Multisig policy of input `index`, checked against the output it spends
+    ///
+    /// `None` when the spent output is locked to a single key.
+    fn policy(&self, index: usize) -> Result<Option<&MultisigPolicy>, PsbtError> {
+        let policy_hash = match script::parse_multisig_hash(self.spent_output(index)?.pub_key_script()) {
+            Some(policy_hash) => policy_hash,
+            None => return Ok(None),
+        };
+        let policy = self.inputs[index].multisig.as_ref().ok_or(PsbtError::MissingPolicy(index))?;
+        if policy.hash() != policy_hash {
+            return Err(ScriptError::PolicyMismatch.into());
+        }
+        Ok(Some(policy))
+    }
+
+    /// Merge what another copy of the same transaction has learned
+    pub fn combine(&mut self, other: &PartiallySignedTransaction) -> Result<(), PsbtError> {
+        if self.unsigned_tx != other.unsigned_tx {
+            return Err(PsbtError::DifferentTransactions);
+        }
+
+        for (index, (input, theirs)) in self.inputs.iter_mut().zip(&other.inputs).enumerate() {
+            match (&input.spent_output, &theirs.spent_output) {
+                (Some(ours), Some(their_output)) if ours != their_output => {
+                    return Err(PsbtError::ConflictingSpentOutput(index));
+                }
+                (None, Some(their_output)) => input.spent_output = Some(their_output.clone()),
+                _ => {}
+            }
+            if input.multisig.is_none() {
+                input.multisig = theirs.multisig.clone();
+            }
+            for origin in &theirs.key_origins {
+                if !input.key_origins.contains(origin) {
+                    input.key_origins.push(origin.clone());
+                }
+            }
+            for witness in &theirs.partial_signatures {
+                if !input.partial_signatures.iter().any(|sig| sig.public_key == witness.public_key) {
+                    input.partial_signatures.push(witness.clone());
+                }
+            }
+            if input.final_script.is_none() {
+                input.final_script = theirs.final_script.clone();
+            }
+        }
+
+        for (output, theirs) in self.outputs.iter_mut().zip(&other.outputs) {
+            for origin in &theirs.key_origins {
+                if !output.key_origins.contains(origin) {
+                    output.key_origins.push(origin.clone());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether every input has its signature script
+    pub fn is_finalized(&self) -> bool {
+        self.inputs.iter().all(|input| input.final_script.is_some())
+    }
+
+    /// Build the signature script of every input that has enough signatures
+    ///
+    /// Finalized inputs drop their partial signatures and key origins, which
+    /// are no longer needed. Fails on the first input that cannot be completed;
+    /// inputs before it stay finalized.
+    pub fn finalize(&mut self) -> Result<(), PsbtError> {
+        for index in 0..self.inputs.len() {
+            if self.inputs[index].final_script.is_some() {
+                continue;
+            }
+            let final_script = match self.policy(index)? {
+                Some(policy) => self.multisig_script(index, policy)?,
+                None => self.single_key_script(index)?,
+            };
+
+            let input = &mut self.inputs[index];
+            input.final_script = Some(final_script);
+            input.partial_signatures.clear();
+            input.key_origins.clear();
+        }
+        Ok(())
+    }
+
+    fn single_key_script(&self, index: usize) -> Result<Vec<u8>, PsbtError> {
+        let (key_type, key_hash) = script::parse_pubkey_hash(self.spent_output(index)?.pub_key_script())
+            .ok_or(ScriptError::UnknownLockingScript)?;
+        self.inputs[index]
+            .partial_signatures
+            .iter()
+            .find(|witness| {
+                witness.sig_type == key_type
+                    && script::pubkey_hash(witness.sig_type, witness.security_level, &witness.public_key) == key_hash
+            })
+            .map(InputWitness::to_script)
+            .ok_or(PsbtError::MissingSignature(index))
+    }
+
+    /// Witness carrying the first `threshold` signatures in key order
+    fn multisig_script(&self, index: usize, policy: &MultisigPolicy) -> Result<Vec<u8>, PsbtError> {
+        let mut signatures: Vec<(u8, Vec<u8>)> = self.inputs[index]
+            .partial_signatures
+            .iter()
+            .filter_map(|witness| {
+                let key_index = policy.key_index(&witness.public_key)?;
+                Some((key_index as u8, witness.signature.clone()))
+            })
+            .collect();
+        if signatures.len() < policy.threshold() as usize {
+            return Err(PsbtError::MissingSignature(index));
+        }
+        signatures.sort();
+        signatures.truncate(policy.threshold() as usize);
+
+        Ok(MultisigWitness { policy: policy.clone(), signatures }.to_script())
+    }
+
+    /// The signed transaction, once every input is finalized
+    pub fn extract(&self) -> Result<Transaction, PsbtError> {
+        let mut tx = self.unsigned_tx.clone();
+        for (index, input) in self.inputs.iter().enumerate() {
+            let final_script = input.final_script.as_ref().ok_or(PsbtError::NotFinalized(index))?;
+            tx.set_signature_script(index, final_script.clone());
+        }
+
+        for index in 0..self.inputs.len() {
+            script::verify_input(&tx, index, self.spent_output(index)?)?;
+        }
+        Ok(tx)
+    }
+
+    /// Binary encoding: magic, version byte, then the bincode body
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut data = PSBT_MAGIC.to_vec();
+        data.push(PSBT_VERSION);
+        data.extend(bincode::serialize(self).expect("partially signed transaction serialization cannot fail"));
+        data
+    }
+
+    pub fn deserialize(data: &[u8]) -> Result<Self, PsbtError> {
+        let body = data
+            .strip_prefix(PSBT_MAGIC.as_slice())
+            .ok_or_else(|| PsbtError::Encoding("missing magic bytes".to_string()))?;
+        let (&version, body) = body
+            .split_first()
+            .ok_or_else(|| PsbtError::Encoding("missing version".to_string()))?;
+        if version != PSBT_VERSION {
+            return Err(PsbtError::UnsupportedVersion(version));
+        }
+
+        let psbt: Self = bincode::deserialize(body).map_err(|e| PsbtError::Encoding(e.to_string()))?;
+        if psbt.inputs.len() != psbt.unsigned_tx.inputs().len()
+            || psbt.outputs.len() != psbt.unsigned_tx.outputs().len()
+        {
+            return Err(PsbtError::Encoding("input or output count does not match the transaction".to_string()));
+        }
+        if psbt.unsigned_tx.inputs().iter().any(|input| !input.signature_script().is_empty()) {
+            return Err(PsbtError::Encoding("transaction is not unsigned".to_string()));
+        }
+        Ok(psbt)
+    }
+}
+
+/// Hex form of [`PartiallySignedTransaction::serialize`], for passing around as text
+impl fmt::Display for PartiallySignedTransaction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&hex::encode(self.serialize()))
+    }
+}
+
+impl FromStr for PartiallySignedTransaction {
+    type Err = PsbtError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let data = hex::decode(s.trim()).map_err(|e| PsbtError::Encoding(e.to_string()))?;
+        Self::deserialize(&data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::signature::SignatureType;
+    use crate::types::transaction::TransactionInput;
+
+    fn secp256k1_key(seed: u8) -> (secp256k1::SecretKey, Vec<u8>) {
+        let secp = secp256k1::Secp256k1::new();
+        let secret_key = secp256k1::SecretKey::from_slice(&[seed; 32]).unwrap();
+        let public_key = secp256k1::PublicKey::from_secret_key(&secp, &secret_key).serialize().to_vec();
+        (secret_key, public_key)
+    }
+
+    fn sign(psbt: &PartiallySignedTransaction, index: usize, seed: u8) -> InputWitness {
+        let (secret_key, public_key) = secp256k1_key(seed);
+        let sighash = psbt.sighash(index).unwrap();
+        let signature = secp256k1::Secp256k1::new()
+            .sign_ecdsa(&secp256k1::Message::from_slice(&sighash).unwrap(), &secret_key);
+        InputWitness {
+            sig_type: SignatureType::Secp256k1,
+            security_level: 0,
+            public_key,
+            signature: signature.serialize_compact().to_vec(),
+        }
+    }
+
+    /// Two inputs owned by different keys, so each signer completes one
+    fn two_party_psbt() -> PartiallySignedTransaction {
+        let tx = Transaction::new(
+            1,
+            vec![
+                TransactionInput::new([1; 32], 0, vec![], 0xffffffff),
+                TransactionInput::new([2; 32], 1, vec![], 0xffffffff),
+            ],
+            vec![TransactionOutput::new(90_000, vec![7; script::P2PKH_SCRIPT_LEN])],
+            0,
+        );
+        let mut psbt = PartiallySignedTransaction::new(&tx);
+        for (index, seed) in [(0, 0x11), (1, 0x22)] {
+            let (_, public_key) = secp256k1_key(seed);
+            psbt.inputs[index].spent_output = Some(TransactionOutput::new(
+                50_000,
+                script::pay_to_pubkey_hash(SignatureType::Secp256k1, 0, &public_key),
+            ));
+            psbt.inputs[index].key_origins.push(KeyOrigin {
+                fingerprint: [seed; 4],
+                path: vec![44 | HARDENED, 1 | HARDENED, HARDENED, 0, index as u32],
+            });
+        }
+        psbt
+    }
+
+    #[test]
+    fn test_sign_combine_finalize_extract() {
+        let creator = two_party_psbt();
+
+        // Each party signs its own copy
+        let mut first = creator.clone();
+        first.add_signature(0, sign(&first, 0, 0x11)).unwrap();
+        let mut second: PartiallySignedTransaction = creator.to_string().parse().unwrap();
+        second.add_signature(1, sign(&second, 1, 0x22)).unwrap();
+
+        // A signature from the wrong key is refused when added
+        let mut wrong = creator.clone();
+        assert!(matches!(
+            wrong.add_signature(1, sign(&creator, 1, 0x11)),
+            Err(PsbtError::Script(ScriptError::PublicKeyMismatch))
+        ));
+
+        // Neither copy can be completed alone
+        assert_eq!(first.clone().finalize(), Err(PsbtError::MissingSignature(1)));
+        assert_eq!(first.extract(), Err(PsbtError::NotFinalized(0)));
+
+        let mut combined = first.clone();
+        combined.combine(&second).unwrap();
+        assert_eq!(combined.inputs[1].partial_signatures.len(), 1);
+        combined.finalize().unwrap();
+        assert!(combined.is_finalized());
+        assert!(combined.inputs.iter().all(|input| input.partial_signatures.is_empty()));
+
+        let tx = combined.extract().unwrap();
+        let spent: Vec<_> = combined.inputs.iter().map(|input| input.spent_output.clone().unwrap()).collect();
+        assert!(tx.verify_signatures(|hash, _| Some(spent[hash[0] as usize - 1].clone())).is_ok());
+        assert_eq!(tx.without_signature_scripts(), *creator.unsigned_tx());
+    }
+
+    #[test]
+    fn test_multisig_input() {
+        let keys: Vec<_> = [0x11, 0x22, 0x33]
+            .iter()
+            .map(|seed| script::PolicyKey {
+                sig_type: SignatureType::Secp256k1,
+                security_level: 0,
+                public_key: secp256k1_key(*seed).1,
+            })
+            .collect();
+        let policy = MultisigPolicy::new(2, keys).unwrap();
+        let tx = Transaction::new(
+            1,
+            vec![TransactionInput::new([1; 32], 0, vec![], 0xffffffff)],
+            vec![TransactionOutput::new(90_000, vec![7; script::P2PKH_SCRIPT_LEN])],
+            0,
+        );
+        let mut psbt = PartiallySignedTransaction::new(&tx);
+        psbt.inputs[0].spent_output = Some(TransactionOutput::new(100_000, policy.script_pubkey()));
+
+        // Signatures cannot be checked until the policy is known
+        assert_eq!(psbt.add_signature(0, sign(&psbt, 0, 0x11)), Err(PsbtError::MissingPolicy(0)));
+        psbt.inputs[0].multisig = Some(policy);
+        assert_eq!(psbt.add_signature(0, sign(&psbt, 0, 0x44)), Err(PsbtError::UnknownKey(0)));
+
+        psbt.add_signature(0, sign(&psbt, 0, 0x33)).unwrap();
+        assert_eq!(psbt.clone().finalize(), Err(PsbtError::MissingSignature(0)));
+        psbt.add_signature(0, sign(&psbt, 0, 0x11)).unwrap();
+        psbt.finalize().unwrap();
+
+        let spent = psbt.inputs[0].spent_output.clone().unwrap();
+        assert!(psbt.extract().unwrap().verify_signatures(|_, _| Some(spent.clone())).is_ok());
+    }
+
+    #[test]
+    fn test_combine_rejects_other_transactions() {
+        let psbt = two_party_psbt();
+        let other_tx = Transaction::new(1, psbt.unsigned_tx().inputs().to_vec(), vec![], 0);
+        let mut other = PartiallySignedTransaction::new(&other_tx);
+        assert_eq!(other.combine(&psbt), Err(PsbtError::DifferentTransactions));
+
+        let mut conflicting = psbt.clone();
+        conflicting.inputs[0].spent_output = Some(TransactionOutput::new(1, vec![]));
+        assert_eq!(conflicting.combine(&psbt), Err(PsbtError::ConflictingSpentOutput(0)));
+    }
+
+    #[test]
+    fn test_encoding() {
+        let psbt = two_party_psbt();
+        let data = psbt.serialize();
+        assert!(data.starts_with(PSBT_MAGIC));
+        assert_eq!(PartiallySignedTransaction::deserialize(&data).unwrap(), psbt);
+
+        let mut future = data.clone();
+        future[PSBT_MAGIC.len()] = 2;
+        assert_eq!(PartiallySignedTransaction::deserialize(&future), Err(PsbtError::UnsupportedVersion(2)));
+        assert!(PartiallySignedTransaction::deserialize(b"psbt\xff").is_err());
+
+        let origin = &psbt.inputs[1].key_origins[0];
+        assert_eq!(origin.to_string(), "22222222/44'/1'/0'/0/1");
+        assert_eq!(origin.to_string().parse::<KeyOrigin>().unwrap(), *origin);
+        assert!("zz/0".parse::<KeyOrigin>().is_err());
+    }
+}
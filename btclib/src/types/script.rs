@@ -0,0 +1,540 @@
+//! Locking scripts, input witnesses and signature hashes for native transactions
+//!
+//! An output is locked to the scheme and hash of a public key. The spending input carries an
+//! [`InputWitness`] in its `signature_script` with the key and a signature over the
+//! input's [`signature_hash`], which commits to the whole transaction (minus the
+//! signature scripts) and to the output being spent.
+//!
+//! An output can instead be locked to the hash of an m-of-n [`MultisigPolicy`]. The
+//! spending input then carries a [`MultisigWitness`] revealing the policy and at
+//! least `m` signatures from its keys, which may mix classical and post-quantum schemes.
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+use crate::crypto::signature::{DilithiumScheme, SignatureScheme, SignatureType};
+use crate::types::transaction::{Transaction, TransactionOutput};
+
+/// Version byte of a pay-to-public-key-hash locking script
+pub const P2PKH_SCRIPT_VERSION: u8 = 0x01;
+
+/// Length of a pay-to-public-key-hash locking script: version byte, scheme tag and key hash
+pub const P2PKH_SCRIPT_LEN: usize = 34;
+
+/// Version byte of a pay-to-multisig-hash locking script
+pub const P2MSH_SCRIPT_VERSION: u8 = 0x02;
+
+/// Length of a pay-to-multisig-hash locking script: version byte and policy hash
+pub const P2MSH_SCRIPT_LEN: usize = 33;
+
+/// Most keys a multisig policy may hold
+pub const MAX_MULTISIG_KEYS: usize = 16;
+
+const PUBKEY_HASH_DOMAIN: &[u8] = b"supernova:pubkey:";
+const SIGHASH_DOMAIN: &[u8] = b"supernova:sighash:";
+const MULTISIG_DOMAIN: &[u8] = b"supernova:multisig:";
+
+/// Errors raised while checking an input against the output it spends
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum ScriptError {
+    #[error("Input {0} does not exist")]
+    InputOutOfRange(usize),
+    #[error("Output is not locked by a known script")]
+    UnknownLockingScript,
+    #[error("Malformed signature script: {0}")]
+    MalformedWitness(String),
+    #[error("Public key does not match the locking script")]
+    PublicKeyMismatch,
+    #[error("Signature scheme {0:?} cannot be used to spend outputs")]
+    UnsupportedScheme(SignatureType),
+    #[error("Invalid public key: {0}")]
+    InvalidPublicKey(String),
+    #[error("Invalid signature for input {0}")]
+    InvalidSignature(usize),
+    #[error("Previous output for input {0} not found")]
+    MissingPrevout(usize),
+    #[error("Invalid multisig policy: {0}")]
+    InvalidPolicy(String),
+    #[error("Multisig policy does not match the locking script")]
+    PolicyMismatch,
+    #[error("Input {0} has fewer signatures than its multisig threshold")]
+    NotEnoughSignatures(usize),
+}
+
+/// Key and signature that unlock a pay-to-public-key-hash output
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InputWitness {
+    pub sig_type: SignatureType,
+    /// Security level for post-quantum schemes; 0 for classical keys
+    pub security_level: u8,
+    pub public_key: Vec<u8>,
+    pub signature: Vec<u8>,
+}
+
+impl InputWitness {
+    /// Encode the witness for use as an input's `signature_script`
+    pub fn to_script(&self) -> Vec<u8> {
+        bincode::serialize(self).expect("witness serialization cannot fail")
+    }
+
+    /// Decode a witness from an input's `signature_script`
+    pub fn from_script(script: &[u8]) -> Result<Self, ScriptError> {
+        bincode::deserialize(script).map_err(|e| ScriptError::MalformedWitness(e.to_string()))
+    }
+}
+
+/// Byte identifying a signature scheme in locking scripts and addresses
+pub fn scheme_tag(sig_type: SignatureType) -> u8 {
+    match sig_type {
+        SignatureType::Secp256k1 => 0,
+        SignatureType::Dilithium => 1,
+        SignatureType::Falcon => 2,
+        SignatureType::Hybrid => 3,
+        SignatureType::Ed25519 => 4,
+        SignatureType::Sphincs => 5,
+    }
+}
+
+/// Signature scheme identified by `tag`, the inverse of [`scheme_tag`]
+pub fn scheme_from_tag(tag: u8) -> Option<SignatureType> {
+    match tag {
+        0 => Some(SignatureType::Secp256k1),
+        1 => Some(SignatureType::Dilithium),
+        2 => Some(SignatureType::Falcon),
+        3 => Some(SignatureType::Hybrid),
+        4 => Some(SignatureType::Ed25519),
+        5 => Some(SignatureType::Sphincs),
+        _ => None,
+    }
+}
+
+/// Hash identifying a public key of the given scheme and security level
+pub fn pubkey_hash(sig_type: SignatureType, security_level: u8, public_key: &[u8]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(PUBKEY_HASH_DOMAIN);
+    hasher.update(bincode::serialize(&sig_type).expect("signature type serialization cannot fail"));
+    hasher.update([security_level]);
+    hasher.update(public_key);
+    hasher.finalize().into()
+}
+
+/// Locking script paying to a public key hash
+pub fn pay_to_pubkey_hash(sig_type: SignatureType, security_level: u8, public_key: &[u8]) -> Vec<u8> {
+    pay_to_key_hash(sig_type, &pubkey_hash(sig_type, security_level, public_key))
+}
+
+/// Locking script paying to an already computed key hash
+pub fn pay_to_key_hash(sig_type: SignatureType, key_hash: &[u8; 32]) -> Vec<u8> {
+    let mut script = Vec::with_capacity(P2PKH_SCRIPT_LEN);
+    script.push(P2PKH_SCRIPT_VERSION);
+    script.push(scheme_tag(sig_type));
+    script.extend_from_slice(key_hash);
+    script
+}
+
+/// Scheme and key hash committed to by a pay-to-public-key-hash script, if `script` is one
+pub fn parse_pubkey_hash(script: &[u8]) -> Option<(SignatureType, [u8; 32])> {
+    if script.len() != P2PKH_SCRIPT_LEN || script[0] != P2PKH_SCRIPT_VERSION {
+        return None;
+    }
+    let sig_type = scheme_from_tag(script[1])?;
+    Some((sig_type, script[2..].try_into().ok()?))
+}
+
+/// One key of a multisig policy
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct PolicyKey {
+    pub sig_type: SignatureType,
+    /// Security level for post-quantum schemes; 0 for classical keys
+    pub security_level: u8,
+    pub public_key: Vec<u8>,
+}
+
+/// Spending condition requiring signatures from `threshold` of its keys
+///
+/// Keys are kept sorted by their key hash, so every participant builds the same
+/// policy, and the same script, whatever order the keys were gathered in.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MultisigPolicy {
+    threshold: u8,
+    keys: Vec<PolicyKey>,
+}
+
+impl MultisigPolicy {
+    pub fn new(threshold: u8, mut keys: Vec<PolicyKey>) -> Result<Self, ScriptError> {
+        keys.sort_by_key(|key| pubkey_hash(key.sig_type, key.security_level, &key.public_key));
+        let policy = Self { threshold, keys };
+        policy.validate()?;
+        Ok(policy)
+    }
+
+    /// Check the rules [`MultisigPolicy::new`] enforces, for policies read from a witness
+    fn validate(&self) -> Result<(), ScriptError> {
+        if self.keys.is_empty() || self.keys.len() > MAX_MULTISIG_KEYS {
+            return Err(ScriptError::InvalidPolicy(format!(
+                "{} keys, expected 1 to {}", self.keys.len(), MAX_MULTISIG_KEYS
+            )));
+        }
+        if self.threshold == 0 || self.threshold as usize > self.keys.len() {
+            return Err(ScriptError::InvalidPolicy(format!(
+                "threshold {} of {} keys", self.threshold, self.keys.len()
+            )));
+        }
+        let hashes: Vec<[u8; 32]> = self.keys
+            .iter()
+            .map(|key| pubkey_hash(key.sig_type, key.security_level, &key.public_key))
+            .collect();
+        if hashes.windows(2).any(|pair| pair[0] >= pair[1]) {
+            return Err(ScriptError::InvalidPolicy("keys are repeated or out of order".to_string()));
+        }
+        Ok(())
+    }
+
+    pub fn threshold(&self) -> u8 {
+        self.threshold
+    }
+
+    pub fn keys(&self) -> &[PolicyKey] {
+        &self.keys
+    }
+
+    /// Position of the key with `public_key`, as referenced by witness signatures
+    pub fn key_index(&self, public_key: &[u8]) -> Option<usize> {
+        self.keys.iter().position(|key| key.public_key == public_key)
+    }
+
+    /// Hash committed to by the policy's locking script
+    pub fn hash(&self) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        hasher.update(MULTISIG_DOMAIN);
+        hasher.update(bincode::serialize(self).expect("policy serialization cannot fail"));
+        hasher.finalize().into()
+    }
+
+    /// Locking script for outputs spendable under this policy
+    pub fn script_pubkey(&self) -> Vec<u8> {
+        pay_to_multisig_hash(&self.hash())
+    }
+}
+
+/// Locking script paying to the hash of a multisig policy
+pub fn pay_to_multisig_hash(policy_hash: &[u8; 32]) -> Vec<u8> {
+    let mut script = Vec::with_capacity(P2MSH_SCRIPT_LEN);
+    script.push(P2MSH_SCRIPT_VERSION);
+    script.extend_from_slice(policy_hash);
+    script
+}
+
+/// Policy hash committed to by a pay-to-multisig-hash script, if `script` is one
+pub fn parse_multisig_hash(script: &[u8]) -> Option<[u8; 32]> {
+    if script.len() != P2MSH_SCRIPT_LEN || script[0] != P2MSH_SCRIPT_VERSION {
+        return None;
+    }
+    script[1..].try_into().ok()
+}
+
+/// Policy and signatures that unlock a pay-to-multisig-hash output
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MultisigWitness {
+    pub policy: MultisigPolicy,
+    /// Signatures paired with the index of their key in the policy, in increasing key order
+    pub signatures: Vec<(u8, Vec<u8>)>,
+}
+
+impl MultisigWitness {
+    /// Encode the witness for use as an input's `signature_script`
+    pub fn to_script(&self) -> Vec<u8> {
+        bincode::serialize(self).expect("witness serialization cannot fail")
+    }
+
+    /// Decode a witness from an input's `signature_script`
+    pub fn from_script(script: &[u8]) -> Result<Self, ScriptError> {
+        bincode::deserialize(script).map_err(|e| ScriptError::MalformedWitness(e.to_string()))
+    }
+}
+
+/// Message signed by input `input_index` of `tx` when spending `spent_output`
+///
+/// Signature scripts are cleared before hashing so every input signs the same
+/// transaction body regardless of the order in which inputs are signed.
+pub fn signature_hash(
+    tx: &Transaction,
+    input_index: usize,
+    spent_output: &TransactionOutput,
+) -> Result<[u8; 32], ScriptError> {
+    if input_index >= tx.inputs().len() {
+        return Err(ScriptError::InputOutOfRange(input_index));
+    }
+
+    let stripped = tx.without_signature_scripts();
+    let mut hasher = Sha256::new();
+    hasher.update(SIGHASH_DOMAIN);
+    hasher.update(bincode::serialize(&stripped).expect("transaction serialization cannot fail"));
+    hasher.update((input_index as u32).to_le_bytes());
+    hasher.update(spent_output.amount().to_le_bytes());
+    hasher.update(spent_output.pub_key_script());
+    Ok(hasher.finalize().into())
+}
+
+/// Check that input `input_index` of `tx` is allowed to spend `spent_output`
+pub fn verify_input(
+    tx: &Transaction,
+    input_index: usize,
+    spent_output: &TransactionOutput,
+) -> Result<(), ScriptError> {
+    let input = tx.inputs().get(input_index).ok_or(ScriptError::InputOutOfRange(input_index))?;
+    if let Some(policy_hash) = parse_multisig_hash(spent_output.pub_key_script()) {
+        return verify_multisig_input(tx, input_index, spent_output, &policy_hash);
+    }
+    let (expected_type, expected_hash) = parse_pubkey_hash(spent_output.pub_key_script())
+        .ok_or(ScriptError::UnknownLockingScript)?;
+
+    let witness = InputWitness::from_script(input.signature_script())?;
+    if witness.sig_type != expected_type
+        || pubkey_hash(witness.sig_type, witness.security_level, &witness.public_key) != expected_hash
+    {
+        return Err(ScriptError::PublicKeyMismatch);
+    }
+
+    let sighash = signature_hash(tx, input_index, spent_output)?;
+    if verify_signature(witness.sig_type, witness.security_level, &witness.public_key, &sighash, &witness.signature)? {
+        Ok(())
+    } else {
+        Err(ScriptError::InvalidSignature(input_index))
+    }
+}
+
+fn verify_multisig_input(
+    tx: &Transaction,
+    input_index: usize,
+    spent_output: &TransactionOutput,
+    policy_hash: &[u8; 32],
+) -> Result<(), ScriptError> {
+    let witness = MultisigWitness::from_script(tx.inputs()[input_index].signature_script())?;
+    witness.policy.validate()?;
+    if witness.policy.hash() != *policy_hash {
+        return Err(ScriptError::PolicyMismatch);
+    }
+    if witness.signatures.len() < witness.policy.threshold() as usize {
+        return Err(ScriptError::NotEnoughSignatures(input_index));
+    }
+
+    let sighash = signature_hash(tx, input_index, spent_output)?;
+    let mut previous: Option<u8> = None;
+    for (key_index, signature) in &witness.signatures {
+        // Increasing indexes rule out counting one key twice
+        if previous.map_or(false, |previous| *key_index <= previous) {
+            return Err(ScriptError::MalformedWitness("signatures are not in key order".to_string()));
+        }
+        previous = Some(*key_index);
+
+        let key = witness.policy.keys().get(*key_index as usize)
+            .ok_or_else(|| ScriptError::MalformedWitness(format!("no key at index {}", key_index)))?;
+        if !verify_signature(key.sig_type, key.security_level, &key.public_key, &sighash, signature)? {
+            return Err(ScriptError::InvalidSignature(input_index));
+        }
+    }
+    Ok(())
+}
+
+/// Check one signature over `sighash`
+///
+/// Fails for schemes that cannot spend outputs yet, so their keys never unlock coins.
+pub fn verify_signature(
+    sig_type: SignatureType,
+    security_level: u8,
+    public_key: &[u8],
+    sighash: &[u8; 32],
+    signature: &[u8],
+) -> Result<bool, ScriptError> {
+    match sig_type {
+        SignatureType::Secp256k1 => verify_ecdsa(public_key, sighash, signature),
+        SignatureType::Dilithium => Ok(DilithiumScheme::new(security_level)
+            .verify(public_key, sighash, signature)
+            .unwrap_or(false)),
+        // The remaining schemes do not verify anything yet, so they must not unlock coins
+        other => Err(ScriptError::UnsupportedScheme(other)),
+    }
+}
+
+fn verify_ecdsa(public_key: &[u8], sighash: &[u8; 32], signature: &[u8]) -> Result<bool, ScriptError> {
+    use secp256k1::{ecdsa::Signature, Message, PublicKey, Secp256k1};
+
+    let public_key = PublicKey::from_slice(public_key)
+        .map_err(|e| ScriptError::InvalidPublicKey(e.to_string()))?;
+    let message = Message::from_slice(sighash).expect("sighash is 32 bytes");
+    let signature = match Signature::from_compact(signature) {
+        Ok(signature) => signature,
+        Err(_) => return Ok(false),
+    };
+
+    Ok(Secp256k1::verification_only().verify_ecdsa(&message, &signature, &public_key).is_ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::quantum::{QuantumKeyPair, QuantumParameters, QuantumScheme};
+    use crate::types::transaction::TransactionInput;
+    use rand::rngs::OsRng;
+
+    fn spending_tx(prev_hash: [u8; 32]) -> Transaction {
+        Transaction::new(
+            1,
+            vec![TransactionInput::new(prev_hash, 0, vec![], 0xffffffff)],
+            vec![TransactionOutput::new(40_000, vec![7; P2PKH_SCRIPT_LEN])],
+            0,
+        )
+    }
+
+    fn with_witness(tx: &Transaction, witness: &InputWitness) -> Transaction {
+        let mut tx = tx.clone();
+        tx.set_signature_script(0, witness.to_script());
+        tx
+    }
+
+    #[test]
+    fn test_secp256k1_input() {
+        let secp = secp256k1::Secp256k1::new();
+        let secret_key = secp256k1::SecretKey::from_slice(&[0x11; 32]).unwrap();
+        let public_key = secp256k1::PublicKey::from_secret_key(&secp, &secret_key).serialize().to_vec();
+        let spent = TransactionOutput::new(50_000, pay_to_pubkey_hash(SignatureType::Secp256k1, 0, &public_key));
+
+        let unsigned = spending_tx([1; 32]);
+        let sighash = signature_hash(&unsigned, 0, &spent).unwrap();
+        let signature = secp.sign_ecdsa(&secp256k1::Message::from_slice(&sighash).unwrap(), &secret_key);
+        let witness = InputWitness {
+            sig_type: SignatureType::Secp256k1,
+            security_level: 0,
+            public_key: public_key.clone(),
+            signature: signature.serialize_compact().to_vec(),
+        };
+        let signed = with_witness(&unsigned, &witness);
+        assert_eq!(verify_input(&signed, 0, &spent), Ok(()));
+
+        // Changing an output invalidates the signature
+        let tampered = Transaction::new(
+            1,
+            signed.inputs().to_vec(),
+            vec![TransactionOutput::new(45_000, vec![7; P2PKH_SCRIPT_LEN])],
+            0,
+        );
+        assert_eq!(verify_input(&tampered, 0, &spent), Err(ScriptError::InvalidSignature(0)));
+
+        // So does spending an output locked to someone else
+        let other_secret = secp256k1::SecretKey::from_slice(&[0x22; 32]).unwrap();
+        let other_key = secp256k1::PublicKey::from_secret_key(&secp, &other_secret);
+        let other = TransactionOutput::new(
+            50_000,
+            pay_to_pubkey_hash(SignatureType::Secp256k1, 0, &other_key.serialize()),
+        );
+        assert_eq!(verify_input(&signed, 0, &other), Err(ScriptError::PublicKeyMismatch));
+    }
+
+    #[test]
+    fn test_dilithium_input() {
+        let keypair = QuantumKeyPair::generate(
+            &mut OsRng,
+            QuantumParameters { scheme: QuantumScheme::Dilithium, security_level: 3 },
+        ).unwrap();
+        let spent = TransactionOutput::new(
+            50_000,
+            pay_to_pubkey_hash(SignatureType::Dilithium, 3, &keypair.public_key),
+        );
+
+        let unsigned = spending_tx([2; 32]);
+        let sighash = signature_hash(&unsigned, 0, &spent).unwrap();
+        let witness = InputWitness {
+            sig_type: SignatureType::Dilithium,
+            security_level: 3,
+            public_key: keypair.public_key.clone(),
+            signature: keypair.sign(&sighash).unwrap(),
+        };
+        assert_eq!(verify_input(&with_witness(&unsigned, &witness), 0, &spent), Ok(()));
+    }
+
+    #[test]
+    fn test_placeholder_schemes_rejected() {
+        let public_key = vec![3; 32];
+        let spent = TransactionOutput::new(50_000, pay_to_pubkey_hash(SignatureType::Ed25519, 0, &public_key));
+        let witness = InputWitness {
+            sig_type: SignatureType::Ed25519,
+            security_level: 0,
+            public_key,
+            signature: vec![0; 64],
+        };
+        let signed = with_witness(&spending_tx([3; 32]), &witness);
+        assert_eq!(
+            verify_input(&signed, 0, &spent),
+            Err(ScriptError::UnsupportedScheme(SignatureType::Ed25519))
+        );
+        assert_eq!(verify_input(&signed, 0, &TransactionOutput::new(1, vec![])), Err(ScriptError::UnknownLockingScript));
+    }
+
+    #[test]
+    fn test_multisig_input() {
+        let secp = secp256k1::Secp256k1::new();
+        let secrets: Vec<_> = [0x31u8, 0x32]
+            .iter()
+            .map(|seed| secp256k1::SecretKey::from_slice(&[*seed; 32]).unwrap())
+            .collect();
+        let quantum = QuantumKeyPair::generate(
+            &mut OsRng,
+            QuantumParameters { scheme: QuantumScheme::Dilithium, security_level: 3 },
+        ).unwrap();
+
+        let mut keys: Vec<PolicyKey> = secrets
+            .iter()
+            .map(|secret| PolicyKey {
+                sig_type: SignatureType::Secp256k1,
+                security_level: 0,
+                public_key: secp256k1::PublicKey::from_secret_key(&secp, secret).serialize().to_vec(),
+            })
+            .collect();
+        keys.push(PolicyKey {
+            sig_type: SignatureType::Dilithium,
+            security_level: 3,
+            public_key: quantum.public_key.clone(),
+        });
+
+        // Participants listing keys in any order agree on the script
+        let policy = MultisigPolicy::new(2, keys.clone()).unwrap();
+        keys.reverse();
+        assert_eq!(MultisigPolicy::new(2, keys.clone()).unwrap().script_pubkey(), policy.script_pubkey());
+        assert!(MultisigPolicy::new(0, keys.clone()).is_err());
+        assert!(MultisigPolicy::new(4, keys.clone()).is_err());
+        assert!(MultisigPolicy::new(1, vec![keys[0].clone(), keys[0].clone()]).is_err());
+
+        let spent = TransactionOutput::new(50_000, policy.script_pubkey());
+        let unsigned = spending_tx([4; 32]);
+        let sighash = signature_hash(&unsigned, 0, &spent).unwrap();
+        let message = secp256k1::Message::from_slice(&sighash).unwrap();
+
+        let secp_index = policy.key_index(&keys[2].public_key).unwrap() as u8;
+        let quantum_index = policy.key_index(&quantum.public_key).unwrap() as u8;
+        let secp_signature = secp.sign_ecdsa(&message, &secrets[0]).serialize_compact().to_vec();
+        let quantum_signature = quantum.sign(&sighash).unwrap();
+        let mut signatures = vec![(secp_index, secp_signature), (quantum_index, quantum_signature)];
+        signatures.sort();
+
+        let spend = |signatures: Vec<(u8, Vec<u8>)>, policy: &MultisigPolicy| {
+            let witness = MultisigWitness { policy: policy.clone(), signatures };
+            let mut tx = unsigned.clone();
+            tx.set_signature_script(0, witness.to_script());
+            verify_input(&tx, 0, &spent)
+        };
+
+        // A classical and a post-quantum cosigner together meet the threshold
+        assert_eq!(spend(signatures.clone(), &policy), Ok(()));
+        assert_eq!(spend(signatures[..1].to_vec(), &policy), Err(ScriptError::NotEnoughSignatures(0)));
+
+        // One key cannot be counted twice
+        let repeated = vec![signatures[0].clone(), signatures[0].clone()];
+        assert!(matches!(spend(repeated, &policy), Err(ScriptError::MalformedWitness(_))));
+
+        // Nor can a different policy be substituted
+        let other = MultisigPolicy::new(1, keys[..1].to_vec()).unwrap();
+        assert_eq!(spend(signatures, &other), Err(ScriptError::PolicyMismatch));
+    }
+}
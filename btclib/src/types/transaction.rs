@@ -1,9 +1,10 @@
 use serde::{Deserialize, Serialize};
 use sha2::{Sha256, Digest};
 use crate::environmental::emissions::{EmissionsError, EmissionsTracker, Emissions};
+use crate::types::script::{self, ScriptError};
 
 /// Represents a transaction input referencing a previous output
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct TransactionInput {
     /// Reference to the previous transaction's hash
     prev_tx_hash: [u8; 32],
@@ -16,7 +17,7 @@ pub struct TransactionInput {
 }
 
 /// Represents a transaction output with an amount and spending conditions
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct TransactionOutput {
     /// Amount of coins in this output
     amount: u64,
@@ -25,7 +26,7 @@ pub struct TransactionOutput {
 }
 
 /// Main transaction structure containing inputs and outputs
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Transaction {
     /// Version number for protocol upgrades
     version: u32,
@@ -54,6 +55,14 @@ impl TransactionInput {
     pub fn prev_output_index(&self) -> u32 {
         self.prev_output_index
     }
+
+    pub fn signature_script(&self) -> &[u8] {
+        &self.signature_script
+    }
+
+    pub fn sequence(&self) -> u32 {
+        self.sequence
+    }
 }
 
 impl TransactionOutput {
@@ -67,6 +76,10 @@ impl TransactionOutput {
     pub fn amount(&self) -> u64 {
         self.amount
     }
+
+    pub fn pub_key_script(&self) -> &[u8] {
+        &self.pub_key_script
+    }
 }
 
 impl Transaction {
@@ -91,6 +104,23 @@ impl Transaction {
         hash
     }
 
+    /// Get the transaction version
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    /// Get the lock time
+    pub fn lock_time(&self) -> u32 {
+        self.lock_time
+    }
+
+    /// Check whether this is a coinbase transaction (single input with a null outpoint)
+    pub fn is_coinbase(&self) -> bool {
+        self.inputs.len() == 1
+            && self.inputs[0].prev_tx_hash == [0u8; 32]
+            && self.inputs[0].prev_output_index == 0xffffffff
+    }
+
     /// Get reference to inputs
     pub fn inputs(&self) -> &[TransactionInput] {
         &self.inputs
@@ -101,6 +131,32 @@ impl Transaction {
         &self.outputs
     }
 
+    /// Replace the signature script of input `index`
+    pub fn set_signature_script(&mut self, index: usize, signature_script: Vec<u8>) {
+        if let Some(input) = self.inputs.get_mut(index) {
+            input.signature_script = signature_script;
+        }
+    }
+
+    /// Copy of this transaction with every signature script cleared, as covered by signatures
+    pub fn without_signature_scripts(&self) -> Self {
+        let mut stripped = self.clone();
+        for input in &mut stripped.inputs {
+            input.signature_script.clear();
+        }
+        stripped
+    }
+
+    /// Check every input's signature script against the output it spends
+    pub fn verify_signatures(&self, get_output: impl Fn(&[u8; 32], u32) -> Option<TransactionOutput>) -> Result<(), ScriptError> {
+        for (index, input) in self.inputs.iter().enumerate() {
+            let spent = get_output(&input.prev_tx_hash, input.prev_output_index)
+                .ok_or(ScriptError::MissingPrevout(index))?;
+            script::verify_input(self, index, &spent)?;
+        }
+        Ok(())
+    }
+
     /// Calculate the total input amount (requires access to previous transactions)
     pub fn total_input(&self, get_output: impl Fn(&[u8; 32], u32) -> Option<TransactionOutput>) -> Option<u64> {
         self.inputs
@@ -137,9 +193,7 @@ impl Transaction {
             None => return false, // Couldn't find an input's previous output
         }
 
-        // TODO: Verify signatures
-        // This would involve checking that each input's signature_script
-        // properly satisfies its referenced output's pub_key_script
+        // Signatures are checked separately by `verify_signatures`
 
         true
     }
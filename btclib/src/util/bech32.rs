@@ -0,0 +1,195 @@
+//! Bech32m encoding (BIP-350)
+//!
+//! Only the checksum variant with the bech32m constant is supported; plain
+//! bech32 strings fail the checksum.
+
+use thiserror::Error;
+
+const CHARSET: &[u8; 32] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+const BECH32M_CONST: u32 = 0x2bc8_30a3;
+const CHECKSUM_LEN: usize = 6;
+
+/// Longest string accepted, as in BIP-173
+pub const MAX_LENGTH: usize = 90;
+
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum Bech32Error {
+    #[error("String is {0} characters, the maximum is 90")]
+    TooLong(usize),
+    #[error("Missing separator")]
+    MissingSeparator,
+    #[error("Invalid human-readable part")]
+    InvalidHrp,
+    #[error("Invalid character {0:?}")]
+    InvalidChar(char),
+    #[error("Mixed-case string")]
+    MixedCase,
+    #[error("Data part is too short")]
+    TooShort,
+    #[error("Invalid checksum")]
+    InvalidChecksum,
+    #[error("Invalid padding")]
+    InvalidPadding,
+}
+
+fn polymod(values: impl IntoIterator<Item = u8>) -> u32 {
+    const GENERATOR: [u32; 5] = [0x3b6a_57b2, 0x2650_8e6d, 0x1ea1_19fa, 0x3d42_33dd, 0x2a14_62b3];
+    let mut chk: u32 = 1;
+    for value in values {
+        let top = chk >> 25;
+        chk = ((chk & 0x01ff_ffff) << 5) ^ value as u32;
+        for (i, generator) in GENERATOR.iter().enumerate() {
+            if (top >> i) & 1 == 1 {
+                chk ^= generator;
+            }
+        }
+    }
+    chk
+}
+
+fn hrp_expand(hrp: &str) -> Vec<u8> {
+    hrp.bytes()
+        .map(|b| b >> 5)
+        .chain(std::iter::once(0))
+        .chain(hrp.bytes().map(|b| b & 0x1f))
+        .collect()
+}
+
+fn checksum(hrp: &str, data: &[u8]) -> [u8; CHECKSUM_LEN] {
+    let values = hrp_expand(hrp)
+        .into_iter()
+        .chain(data.iter().copied())
+        .chain([0u8; CHECKSUM_LEN]);
+    let modulus = polymod(values) ^ BECH32M_CONST;
+
+    let mut checksum = [0u8; CHECKSUM_LEN];
+    for (i, value) in checksum.iter_mut().enumerate() {
+        *value = ((modulus >> (5 * (5 - i))) & 0x1f) as u8;
+    }
+    checksum
+}
+
+/// Encode 5-bit `data` under `hrp`; `hrp` must be lowercase ASCII
+pub fn encode(hrp: &str, data: &[u8]) -> String {
+    let mut encoded = String::with_capacity(hrp.len() + 1 + data.len() + CHECKSUM_LEN);
+    encoded.push_str(hrp);
+    encoded.push('1');
+    for value in data.iter().chain(checksum(hrp, data).iter()) {
+        encoded.push(CHARSET[*value as usize] as char);
+    }
+    encoded
+}
+
+/// Decode a bech32m string into its lowercase human-readable part and 5-bit data
+pub fn decode(s: &str) -> Result<(String, Vec<u8>), Bech32Error> {
+    if s.len() > MAX_LENGTH {
+        return Err(Bech32Error::TooLong(s.len()));
+    }
+    if s.chars().any(|c| c.is_ascii_lowercase()) && s.chars().any(|c| c.is_ascii_uppercase()) {
+        return Err(Bech32Error::MixedCase);
+    }
+    let s = s.to_ascii_lowercase();
+
+    let separator = s.rfind('1').ok_or(Bech32Error::MissingSeparator)?;
+    let (hrp, data) = (&s[..separator], &s[separator + 1..]);
+    if hrp.is_empty() || hrp.bytes().any(|b| !(33..=126).contains(&b)) {
+        return Err(Bech32Error::InvalidHrp);
+    }
+    if data.len() < CHECKSUM_LEN {
+        return Err(Bech32Error::TooShort);
+    }
+
+    let values = data
+        .chars()
+        .map(|c| {
+            CHARSET
+                .iter()
+                .position(|&x| x as char == c)
+                .map(|p| p as u8)
+                .ok_or(Bech32Error::InvalidChar(c))
+        })
+        .collect::<Result<Vec<u8>, _>>()?;
+
+    let all = hrp_expand(hrp).into_iter().chain(values.iter().copied());
+    if polymod(all) != BECH32M_CONST {
+        return Err(Bech32Error::InvalidChecksum);
+    }
+
+    Ok((hrp.to_string(), values[..values.len() - CHECKSUM_LEN].to_vec()))
+}
+
+/// Regroup bits, e.g. bytes into 5-bit values (`from = 8, to = 5, pad = true`) and back
+pub fn convert_bits(data: &[u8], from: u32, to: u32, pad: bool) -> Result<Vec<u8>, Bech32Error> {
+    let mut acc: u32 = 0;
+    let mut bits: u32 = 0;
+    let max_value = (1u32 << to) - 1;
+    let mut out = Vec::with_capacity(data.len() * from as usize / to as usize + 1);
+
+    for value in data {
+        let value = *value as u32;
+        if value >> from != 0 {
+            return Err(Bech32Error::InvalidPadding);
+        }
+        acc = (acc << from) | value;
+        bits += from;
+        while bits >= to {
+            bits -= to;
+            out.push(((acc >> bits) & max_value) as u8);
+        }
+    }
+
+    if pad {
+        if bits > 0 {
+            out.push(((acc << (to - bits)) & max_value) as u8);
+        }
+    } else if bits >= from || ((acc << (to - bits)) & max_value) != 0 {
+        return Err(Bech32Error::InvalidPadding);
+    }
+
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bip350_vectors() {
+        for valid in [
+            "A1LQFN3A",
+            "a1lqfn3a",
+            "abcdef1l7aum6echk45nj3s0wdvt2fg8x9yrzpqzd3ryx",
+            "split1checkupstagehandshakeupstreamerranterredcaperredlc445v",
+            "?1v759aa",
+        ] {
+            let (hrp, data) = decode(valid).unwrap();
+            assert_eq!(encode(&hrp, &data), valid.to_ascii_lowercase());
+        }
+
+        for invalid in [
+            "1xj0phk",           // empty hrp
+            "pzry9x0s0muk",      // no separator
+            "M1VUXWEZ",          // checksum calculated with uppercase hrp
+            "qyrz8wqd2c9m",      // empty hrp
+            "y1b0jsk6g",         // invalid data character
+            "lt1igcx5c0",        // invalid data character
+            "in1muywd",          // too short checksum
+            "mm1crxm3i",         // invalid character in checksum
+            "au1s5cgom",         // invalid character in checksum
+            "16plkw9",           // empty hrp
+            "1p2gdwpf",          // empty hrp
+            "A1LqfN3A",          // mixed case
+            "a12uel5l",          // plain bech32 checksum
+        ] {
+            assert!(decode(invalid).is_err(), "{} should not decode", invalid);
+        }
+    }
+
+    #[test]
+    fn test_convert_bits_roundtrip() {
+        let bytes: Vec<u8> = (0u8..=32).collect();
+        let five = convert_bits(&bytes, 8, 5, true).unwrap();
+        assert!(five.iter().all(|v| *v < 32));
+        assert_eq!(convert_bits(&five, 5, 8, false).unwrap(), bytes);
+    }
+}
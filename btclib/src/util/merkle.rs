@@ -132,6 +132,48 @@ impl MerkleTree {
 
         false
     }
+
+    /// Compute the branch needed to rebuild the root from the first leaf
+    ///
+    /// `leaf_hashes` are the hashes of every leaf; the first entry is only a
+    /// placeholder since the branch never depends on it. Mining jobs use this so
+    /// a miner can swap in its own coinbase without seeing the other transactions.
+    pub fn first_leaf_branch(leaf_hashes: &[[u8; 32]]) -> Vec<[u8; 32]> {
+        let mut branch = Vec::new();
+        let mut level = leaf_hashes.to_vec();
+
+        while level.len() > 1 {
+            branch.push(level[1]);
+
+            level = level
+                .chunks(2)
+                .map(|chunk| match chunk {
+                    [left, right] => hash_pair(left, right),
+                    [left] => hash_pair(left, left),
+                    _ => unreachable!(),
+                })
+                .collect();
+        }
+
+        branch
+    }
+
+    /// Rebuild the root hash from the first leaf's hash and its branch
+    pub fn root_from_first_leaf(leaf_hash: [u8; 32], branch: &[[u8; 32]]) -> [u8; 32] {
+        branch
+            .iter()
+            .fold(leaf_hash, |acc, sibling| hash_pair(&acc, sibling))
+    }
+}
+
+fn hash_pair(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(left);
+    hasher.update(right);
+    let result = hasher.finalize();
+    let mut hash = [0u8; 32];
+    hash.copy_from_slice(&result);
+    hash
 }
 
 #[cfg(test)]
@@ -175,4 +217,25 @@ mod tests {
             assert!(tree.verify(tx));
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_first_leaf_branch() {
+        for count in 1..9 {
+            let transactions: Vec<Vec<u8>> = (0..count)
+                .map(|i| format!("transaction{}", i).into_bytes())
+                .collect();
+            let tree = MerkleTree::new(&transactions);
+
+            let leaf_hashes: Vec<[u8; 32]> = transactions
+                .iter()
+                .map(|tx| MerkleNode::new_leaf(tx).hash())
+                .collect();
+            let branch = MerkleTree::first_leaf_branch(&leaf_hashes);
+
+            assert_eq!(
+                MerkleTree::root_from_first_leaf(leaf_hashes[0], &branch),
+                tree.root_hash().unwrap()
+            );
+        }
+    }
+}
@@ -1 +1,2 @@
-pub mod merkle;
\ No newline at end of file
+pub mod merkle;
+pub mod bech32;
@@ -2,11 +2,13 @@ use thiserror::Error;
 use std::sync::Arc;
 use serde::{Serialize, Deserialize};
 
+use crate::types::block::Block;
 use crate::types::transaction::Transaction;
 use crate::types::extended_transaction::{QuantumTransaction, ConfidentialTransaction};
 use crate::crypto::quantum::QuantumError;
 use crate::crypto::zkp::ZkpError;
 use crate::config::Config;
+use crate::consensus::{self, CoinbaseError};
 
 /// Error types for transaction validation
 #[derive(Debug, Error)]
@@ -28,6 +30,9 @@ pub enum ValidationError {
     
     #[error("Output too large")]
     OutputTooLarge,
+    
+    #[error("Invalid coinbase: {0}")]
+    InvalidCoinbase(#[from] CoinbaseError),
 }
 
 /// Security level for cryptographic operations and validation
@@ -202,6 +207,21 @@ impl ValidationService {
         })
     }
     
+    /// Validate a block's coinbase against the emission schedule
+    ///
+    /// `total_fees` must be the fees of all non-coinbase transactions in the block,
+    /// and `treasury_share` the portion owed to the environmental treasury.
+    pub fn validate_block_reward(
+        &self,
+        block: &Block,
+        height: u64,
+        total_fees: u64,
+        treasury_share: u64,
+    ) -> Result<(), ValidationError> {
+        consensus::validate_coinbase(block.transactions(), height, total_fees, treasury_share)?;
+        Ok(())
+    }
+    
     /// Validate a quantum-resistant transaction
     pub fn validate_quantum_transaction(
         &self, 
@@ -1,2 +1,3 @@
 pub mod mining;
-pub mod difficulty;
\ No newline at end of file
+pub mod difficulty;
+pub mod stratum;
\ No newline at end of file
@@ -1,7 +1,9 @@
-use tracing::info;
+use tracing::{info, error};
 use miner::mining::Miner;
+use miner::stratum::{StratumClient, StratumClientConfig, StratumProtocol};
 use btclib::types::transaction::Transaction;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 use async_trait::async_trait;
 
 // Mock implementation of MempoolInterface for the main program
@@ -14,17 +16,77 @@ impl miner::mining::MempoolInterface for EmptyMempool {
     }
 }
 
+/// Parse Stratum client options: `--stratum <host:port> [--worker <name>] [--password <pass>] [--v2]`
+fn stratum_config_from_args() -> Option<StratumClientConfig> {
+    let mut args = std::env::args().skip(1);
+    let mut config = StratumClientConfig::default();
+    let mut enabled = false;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--stratum" => {
+                enabled = true;
+                if let Some(address) = args.next() {
+                    config.address = address;
+                }
+            }
+            "--worker" => {
+                if let Some(worker) = args.next() {
+                    config.worker = worker;
+                }
+            }
+            "--password" => {
+                if let Some(password) = args.next() {
+                    config.password = password;
+                }
+            }
+            "--v2" => config.protocol = StratumProtocol::V2,
+            other => eprintln!("Ignoring unknown argument {}", other),
+        }
+    }
+
+    enabled.then_some(config)
+}
+
+async fn run_stratum_client(config: StratumClientConfig) {
+    let client = StratumClient::new(config);
+    let stop = Arc::new(AtomicBool::new(false));
+
+    tokio::select! {
+        result = client.run(stop.clone()) => {
+            if let Err(e) = result {
+                error!("Stratum client error: {}", e);
+            }
+        }
+        _ = tokio::signal::ctrl_c() => {
+            stop.store(true, Ordering::Relaxed);
+        }
+    }
+
+    let stats = client.stats();
+    info!(
+        "Shutting down miner: {} shares accepted, {} rejected",
+        stats.shares_accepted, stats.shares_rejected
+    );
+}
+
 #[tokio::main]
 async fn main() {
     // Initialize logging
     tracing_subscriber::fmt::init();
 
+    // Mine for a remote Stratum server when requested
+    if let Some(config) = stratum_config_from_args() {
+        run_stratum_client(config).await;
+        return;
+    }
+
     // Create mempool and reward address
     let mempool = Arc::new(EmptyMempool);
     let reward_address = vec![1, 2, 3, 4]; // Simple test address
 
     // Create miner with 4 threads and initial target
-    let (miner, mut block_rx) = Miner::new(4, 0x1d00ffff, mempool, reward_address);
+    let (miner, mut block_rx) = Miner::new(4, btclib::consensus::POW_LIMIT_BITS, mempool, reward_address);
 
     // Start mining
     let _mining_task = tokio::spawn({
@@ -52,4 +114,4 @@ async fn main() {
 
     // Wait for the block handling task to complete
     _block_handle.await.expect("Failed to join block handling task");
-}
\ No newline at end of file
+}
@@ -2,6 +2,7 @@ use btclib::types::block::Block;
 use super::worker::MiningWorker;
 use super::template::{BlockTemplate, MempoolInterface};
 use crate::difficulty::DifficultyAdjuster;
+use btclib::consensus::HeaderInfo;
 use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use tokio::sync::mpsc;
@@ -68,6 +69,7 @@ pub struct Miner {
     num_threads: usize,
     mempool: Arc<dyn MempoolInterface + Send + Sync>,
     reward_address: Vec<u8>,
+    attestation_commitment: Option<[u8; 32]>,
     metrics: Arc<MiningMetrics>,
     shared_template: Option<Arc<tokio::sync::Mutex<BlockTemplate>>>,
     template_refresh_signal: Arc<AtomicBool>,
@@ -104,6 +106,7 @@ impl Miner {
             num_threads,
             mempool,
             reward_address,
+            attestation_commitment: None,
             metrics,
             shared_template: None,
             template_refresh_signal,
@@ -119,13 +122,15 @@ impl Miner {
         info!("Starting mining with {} workers", self.num_threads);
         self.metrics.active_workers.store(self.num_threads as u64, Ordering::Relaxed);
 
-        let template = BlockTemplate::new(
+        let mut template = BlockTemplate::new(
             _version,
             _prev_block_hash,
+            _current_height + 1,
             self.difficulty_adjuster.get_current_target(),
             self.reward_address.clone(),
             self.mempool.as_ref(),
         ).await;
+        template.set_attestation_commitment(self.attestation_commitment);
         let shared_template = Arc::new(tokio::sync::Mutex::new(template));
         
         let template_refresh_handle = self.start_template_refresh_task(
@@ -209,12 +214,25 @@ impl Miner {
         info!("Mining stopped");
     }
     
+    /// Commit mined blocks to an environmental attestation, as returned by
+    /// `MinerReportingManager::submit_attestation`
+    pub fn set_attestation_commitment(&mut self, commitment: Option<[u8; 32]>) {
+        self.attestation_commitment = commitment;
+    }
+
     pub fn request_template_refresh(&self) {
         self.template_refresh_signal.store(true, Ordering::Relaxed);
     }
     
-    pub fn adjust_difficulty(&mut self, height: u64, timestamp: u64, blocks_since_adjustment: u64) -> u32 {
-        let new_target = self.difficulty_adjuster.adjust_difficulty(height, timestamp, blocks_since_adjustment);
+    /// Feed a newly connected header to the retarget and push the resulting target to the workers
+    pub fn adjust_difficulty(&mut self, header: HeaderInfo) -> u32 {
+        let new_target = match self.difficulty_adjuster.add_block(header) {
+            Ok(target) => target,
+            Err(e) => {
+                error!("Difficulty retarget failed: {}", e);
+                self.difficulty_adjuster.get_current_target()
+            }
+        };
         
         for worker in &self.workers {
             worker.update_target(new_target);
@@ -300,6 +318,8 @@ impl MiningWorker {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use btclib::consensus::{compact_to_target, POW_LIMIT_BITS, REGTEST_POW_LIMIT_BITS};
+    use crate::difficulty::DIFFICULTY_ADJUSTMENT_INTERVAL;
     use std::sync::Arc;
     use async_trait::async_trait;
     use btclib::types::transaction::Transaction;
@@ -317,16 +337,16 @@ mod tests {
     async fn test_miner_creation() {
         let mempool = Arc::new(MockMempool);
         let reward_address = vec![1, 2, 3, 4];
-        let (miner, _rx) = Miner::new(4, 0x1d00ffff, mempool, reward_address);
+        let (miner, _rx) = Miner::new(4, POW_LIMIT_BITS, mempool, reward_address);
         assert_eq!(miner.num_threads, 4);
-        assert_eq!(miner.get_current_target(), 0x1d00ffff);
+        assert_eq!(miner.get_current_target(), POW_LIMIT_BITS);
     }
 
     #[tokio::test]
     async fn test_mining_start_stop() {
         let mempool = Arc::new(MockMempool);
         let reward_address = vec![1, 2, 3, 4];
-        let (miner, mut rx) = Miner::new(1, u32::MAX, mempool, reward_address);
+        let (miner, mut rx) = Miner::new(1, REGTEST_POW_LIMIT_BITS, mempool, reward_address);
         
         // Clone miner for the spawned task
         let mining_miner = miner.clone();
@@ -350,18 +370,23 @@ mod tests {
     async fn test_difficulty_adjustment() {
         let mempool = Arc::new(MockMempool);
         let reward_address = vec![1, 2, 3, 4];
-        let (mut miner, _rx) = Miner::new(1, 0x1d00ffff, mempool, reward_address);
-        let initial_target = miner.get_current_target();
+        let (mut miner, _rx) = Miner::new(1, 0x1c7fff80, mempool, reward_address);
+        let initial_target = compact_to_target(miner.get_current_target()).unwrap();
 
-        miner.adjust_difficulty(2016, 60 * 1008, 2016);
-        assert!(miner.get_current_target() < initial_target);
+        // A full interval mined at twice the scheduled rate tightens the target
+        let mut bits = miner.get_current_target();
+        for height in 1..=DIFFICULTY_ADJUSTMENT_INTERVAL {
+            bits = miner.adjust_difficulty(HeaderInfo { height, timestamp: height * 30, bits });
+        }
+        assert!(compact_to_target(miner.get_current_target()).unwrap() < initial_target);
+        assert_eq!(bits, miner.get_current_target());
     }
 
     #[tokio::test]
     async fn test_mining_metrics() {
         let mempool = Arc::new(MockMempool);
         let reward_address = vec![1, 2, 3, 4];
-        let (miner, _rx) = Miner::new(2, u32::MAX, mempool, reward_address);
+        let (miner, _rx) = Miner::new(2, REGTEST_POW_LIMIT_BITS, mempool, reward_address);
         
         let metrics = miner.get_metrics();
         let initial_stats = metrics.get_stats();
@@ -6,5 +6,6 @@ pub use self::worker::MiningWorker;
 pub use self::coordinator::Miner;
 pub use self::template::{BlockTemplate, MempoolInterface, BLOCK_MAX_SIZE};
 
-pub const NOVA_TOTAL_SUPPLY: u64 = 42_000_000;
-pub const NOVA_BLOCK_REWARD: u64 = 50; // Initial block reward in NOVA
\ No newline at end of file
+// Emission constants live in btclib's consensus module
+pub use btclib::types::units::TOTAL_NOVA_SUPPLY as NOVA_TOTAL_SUPPLY;
+pub const NOVA_BLOCK_REWARD: u64 = btclib::consensus::INITIAL_BLOCK_SUBSIDY / btclib::consensus::COIN; // Initial block reward in NOVA
\ No newline at end of file
@@ -1,18 +1,25 @@
-use btclib::types::block::Block;
+use btclib::types::block::{Block, BlockHeader};
+use btclib::util::merkle::MerkleTree;
 use btclib::types::transaction::{Transaction, TransactionInput, TransactionOutput};
+use btclib::environmental::attestation::commitment_output;
+use btclib::consensus::{
+    block_subsidy, transaction_sigop_cost, treasury_share, TREASURY_FEE_PERCENTAGE,
+    ENVIRONMENTAL_TREASURY_SCRIPT, INITIAL_BLOCK_SUBSIDY, MAX_BLOCK_SIGOPS_COST, MAX_BLOCK_SIZE,
+};
 use async_trait::async_trait;
+use std::collections::HashSet;
 use std::time::{Instant, Duration};
 use std::sync::atomic::{AtomicBool, Ordering};
 
-pub const BLOCK_MAX_SIZE: usize = 1_000_000; // 1MB
-pub const BLOCK_REWARD: u64 = 50 * 100_000_000; // 50 NOVA
+pub const BLOCK_MAX_SIZE: usize = MAX_BLOCK_SIZE; // 1MB, see btclib::consensus::limits
+pub const BLOCK_REWARD: u64 = INITIAL_BLOCK_SUBSIDY; // 50 NOVA at height 0, see btclib::consensus::block_subsidy
 pub const TEMPLATE_REFRESH_INTERVAL: Duration = Duration::from_secs(10); // Refresh template every 10 seconds
 
 #[async_trait]
 pub trait MempoolInterface: Send + Sync {
     async fn get_transactions(&self, max_size: usize) -> Vec<Transaction>;
     
-    // Add new method to get prioritized transactions based on fee
+    // Transactions in the order they should be mined; templates keep this order
     async fn get_prioritized_transactions(&self, max_size: usize) -> Vec<Transaction> {
         self.get_transactions(max_size).await
     }
@@ -27,9 +34,14 @@ pub trait MempoolInterface: Send + Sync {
 pub struct BlockTemplate {
     version: u32,
     prev_block_hash: [u8; 32],
+    height: u64,
     target: u32,
+    reward_address: Vec<u8>,
     coinbase: Transaction,
+    attestation_commitment: Option<[u8; 32]>,
     transactions: Vec<Transaction>,
+    transaction_fees: Vec<u64>,
+    total_fees: u64,
     creation_time: Instant,
     needs_refresh: AtomicBool,
 }
@@ -38,24 +50,33 @@ impl BlockTemplate {
     pub async fn new(
         version: u32,
         prev_block_hash: [u8; 32],
+        height: u64,
         target: u32,
         reward_address: Vec<u8>,
         mempool: &dyn MempoolInterface,
     ) -> Self {
-        // Create coinbase transaction
-        let coinbase = Self::create_coinbase_transaction(BLOCK_REWARD, reward_address.clone());
-        
-        // Get prioritized transactions from mempool
-        let coinbase_size = bincode::serialize(&coinbase).unwrap().len();
+        // Size the block around a coinbase paying the bare subsidy; fees only change output amounts,
+        // and room is left for an attestation commitment added later
+        let placeholder = Self::create_coinbase_transaction(height, 0, reward_address.clone(), Some([0u8; 32]));
+        let coinbase_size = bincode::serialize(&placeholder).unwrap().len();
         let available_size = BLOCK_MAX_SIZE - coinbase_size;
-        let transactions = Self::select_transactions(mempool, available_size).await;
+        let (transactions, transaction_fees) = Self::select_transactions_with_fees(mempool, available_size).await;
+        let total_fees = transaction_fees.iter().fold(0u64, |total, fee| total.saturating_add(*fee));
+        
+        // Create coinbase transaction claiming subsidy + fees
+        let coinbase = Self::create_coinbase_transaction(height, total_fees, reward_address.clone(), None);
         
         Self {
             version,
             prev_block_hash,
+            height,
             target,
+            reward_address,
             coinbase,
+            attestation_commitment: None,
             transactions,
+            transaction_fees,
+            total_fees,
             creation_time: Instant::now(),
             needs_refresh: AtomicBool::new(false),
         }
@@ -66,15 +87,27 @@ impl BlockTemplate {
         mempool: &dyn MempoolInterface, 
         available_size: usize
     ) -> Vec<Transaction> {
+        Self::select_transactions_with_fees(mempool, available_size).await.0
+    }
+    
+    // Transaction selection that also reports the fee paid by each selected transaction
+    //
+    // The mempool's order is kept, since it can rank packages (a child paying for its
+    // parent) better than fee per byte of each transaction alone. Transactions are
+    // ordered so in-template parents always precede their children.
+    async fn select_transactions_with_fees(
+        mempool: &dyn MempoolInterface, 
+        available_size: usize
+    ) -> (Vec<Transaction>, Vec<u64>) {
         // Get prioritized transactions
         let transactions = mempool.get_prioritized_transactions(available_size * 2).await;
+        let candidates: HashSet<[u8; 32]> = transactions.iter().map(|tx| tx.hash()).collect();
         
-        // Sort by fee per byte (fee density) if not already sorted
         let txids: Vec<Vec<u8>> = transactions.iter().map(|tx| tx.hash().to_vec()).collect();
         let fees = mempool.get_transaction_fees(&txids).await;
         
-        // Create tuples of (transaction, fee, size) for sorting
-        let mut tx_fee_size: Vec<(Transaction, u64, usize)> = transactions.into_iter()
+        // Create tuples of (transaction, fee, size)
+        let tx_fee_size: Vec<(Transaction, u64, usize)> = transactions.into_iter()
             .zip(fees.into_iter())
             .map(|(tx, fee)| {
                 let size = bincode::serialize(&tx).unwrap().len();
@@ -82,25 +115,66 @@ impl BlockTemplate {
             })
             .collect();
         
-        // Sort by fee per byte (fee density) in descending order
-        tx_fee_size.sort_by(|a, b| {
-            let fee_rate_a = if a.2 > 0 { a.1 as f64 / a.2 as f64 } else { 0.0 };
-            let fee_rate_b = if b.2 > 0 { b.1 as f64 / b.2 as f64 } else { 0.0 };
-            fee_rate_b.partial_cmp(&fee_rate_a).unwrap_or(std::cmp::Ordering::Equal)
-        });
-        
-        // Select transactions that fit in the block
+        // Select transactions that fit in the block's size and sigop budgets
         let mut selected = Vec::new();
         let mut total_size = 0;
+        let mut total_sigops = 0;
         
-        for (tx, _, size) in tx_fee_size {
-            if total_size + size <= available_size {
+        for (tx, fee, size) in tx_fee_size {
+            let sigops = transaction_sigop_cost(&tx);
+            if total_size + size <= available_size && total_sigops + sigops <= MAX_BLOCK_SIGOPS_COST {
                 total_size += size;
-                selected.push(tx);
+                total_sigops += sigops;
+                selected.push((tx, fee));
             }
         }
         
-        selected
+        Self::order_by_dependencies(selected, &candidates).into_iter().unzip()
+    }
+    
+    // Drop transactions whose mempool parents were not selected and move parents ahead of children
+    fn order_by_dependencies(
+        mut selected: Vec<(Transaction, u64)>,
+        candidates: &HashSet<[u8; 32]>,
+    ) -> Vec<(Transaction, u64)> {
+        let mut included: HashSet<[u8; 32]> = selected.iter().map(|(tx, _)| tx.hash()).collect();
+        
+        // Removing a child can orphan its own children, so repeat until nothing changes
+        loop {
+            let before = selected.len();
+            selected.retain(|(tx, _)| {
+                tx.inputs().iter().all(|input| {
+                    let parent = input.prev_tx_hash();
+                    !candidates.contains(&parent) || included.contains(&parent)
+                })
+            });
+            if selected.len() == before {
+                break;
+            }
+            included = selected.iter().map(|(tx, _)| tx.hash()).collect();
+        }
+        
+        // Emit transactions in rounds, each round only containing those whose parents are already placed
+        let mut ordered = Vec::with_capacity(selected.len());
+        let mut placed: HashSet<[u8; 32]> = HashSet::new();
+        let mut pending = selected;
+        
+        while !pending.is_empty() {
+            let (ready, rest): (Vec<_>, Vec<_>) = pending.into_iter().partition(|(tx, _)| {
+                tx.inputs().iter().all(|input| {
+                    let parent = input.prev_tx_hash();
+                    !included.contains(&parent) || placed.contains(&parent)
+                })
+            });
+            if ready.is_empty() {
+                break;
+            }
+            placed.extend(ready.iter().map(|(tx, _)| tx.hash()));
+            ordered.extend(ready);
+            pending = rest;
+        }
+        
+        ordered
     }
 
     pub fn create_block(&self) -> Block {
@@ -130,32 +204,151 @@ impl BlockTemplate {
     pub async fn update_transactions(&mut self, mempool: &dyn MempoolInterface) {
         let coinbase_size = bincode::serialize(&self.coinbase).unwrap().len();
         let available_size = BLOCK_MAX_SIZE - coinbase_size;
-        self.transactions = Self::select_transactions(mempool, available_size).await;
+        let (transactions, transaction_fees) = Self::select_transactions_with_fees(mempool, available_size).await;
+        let total_fees = transaction_fees.iter().fold(0u64, |total, fee| total.saturating_add(*fee));
+        self.transactions = transactions;
+        self.transaction_fees = transaction_fees;
+        self.total_fees = total_fees;
+        self.coinbase = Self::create_coinbase_transaction(
+            self.height,
+            total_fees,
+            self.reward_address.clone(),
+            self.attestation_commitment,
+        );
         self.creation_time = Instant::now();
         self.needs_refresh.store(false, Ordering::Relaxed);
     }
 
-    fn create_coinbase_transaction(reward: u64, reward_address: Vec<u8>) -> Transaction {
+    // Commit the coinbase to the miner's environmental attestation, or drop the commitment
+    pub fn set_attestation_commitment(&mut self, commitment: Option<[u8; 32]>) {
+        self.attestation_commitment = commitment;
+        self.coinbase = Self::create_coinbase_transaction(
+            self.height,
+            self.total_fees,
+            self.reward_address.clone(),
+            commitment,
+        );
+    }
+
+    // Coinbase paying subsidy + fees, with the environmental treasury's share of the fees split out
+    // and an optional zero-value output committing to the miner's environmental attestation
+    fn create_coinbase_transaction(
+        height: u64,
+        total_fees: u64,
+        reward_address: Vec<u8>,
+        attestation_commitment: Option<[u8; 32]>,
+    ) -> Transaction {
         let coinbase_input = TransactionInput::new(
             [0u8; 32],  // Previous transaction hash is zero for coinbase
             0xffffffff, // Previous output index is max value for coinbase
-            vec![],     // No signature script needed for coinbase
+            height.to_le_bytes().to_vec(), // Height makes each coinbase unique
             0,          // Sequence
         );
 
-        let reward_output = TransactionOutput::new(
-            reward,
+        let treasury_amount = treasury_share(total_fees, TREASURY_FEE_PERCENTAGE);
+        let mut outputs = vec![TransactionOutput::new(
+            block_subsidy(height) + total_fees - treasury_amount,
             reward_address,
-        );
+        )];
+        
+        if treasury_amount > 0 {
+            outputs.push(TransactionOutput::new(
+                treasury_amount,
+                ENVIRONMENTAL_TREASURY_SCRIPT.to_vec(),
+            ));
+        }
+
+        if let Some(commitment) = attestation_commitment {
+            outputs.push(commitment_output(&commitment));
+        }
 
         Transaction::new(
             1,  // Version
             vec![coinbase_input],
-            vec![reward_output],
+            outputs,
             0,  // Lock time
         )
     }
     
+    // Coinbase with extra nonce bytes appended to its script, used by external miners
+    pub fn coinbase_with_extranonce(&self, extranonce: &[u8]) -> Transaction {
+        let input = &self.coinbase.inputs()[0];
+        let mut script = input.signature_script().to_vec();
+        script.extend_from_slice(extranonce);
+        
+        Transaction::new(
+            self.coinbase.version(),
+            vec![TransactionInput::new(
+                input.prev_tx_hash(),
+                input.prev_output_index(),
+                script,
+                input.sequence(),
+            )],
+            self.coinbase.outputs().to_vec(),
+            self.coinbase.lock_time(),
+        )
+    }
+    
+    // Merkle branch that lets a miner compute the root from its own coinbase hash
+    pub fn coinbase_merkle_branch(&self) -> Vec<[u8; 32]> {
+        let mut leaves = Vec::with_capacity(self.transactions.len() + 1);
+        leaves.push([0u8; 32]); // Coinbase slot, replaced by the miner
+        leaves.extend(self.transactions.iter().map(|tx| tx.hash()));
+        MerkleTree::first_leaf_branch(&leaves)
+    }
+    
+    // Assemble a full block from a coinbase and header fields solved by an external miner
+    pub fn assemble_block(&self, coinbase: Transaction, timestamp: u64, nonce: u32) -> Block {
+        let mut transactions = vec![coinbase];
+        transactions.extend(self.transactions.clone());
+        
+        let mut header = BlockHeader::new(
+            self.version,
+            self.prev_block_hash,
+            Block::calculate_merkle_root(&transactions),
+            self.target,
+        );
+        header.set_timestamp(timestamp);
+        header.set_nonce(nonce);
+        
+        Block::from_header(header, transactions)
+    }
+    
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+    
+    pub fn prev_block_hash(&self) -> [u8; 32] {
+        self.prev_block_hash
+    }
+    
+    pub fn target(&self) -> u32 {
+        self.target
+    }
+    
+    pub fn coinbase(&self) -> &Transaction {
+        &self.coinbase
+    }
+    
+    pub fn transactions(&self) -> &[Transaction] {
+        &self.transactions
+    }
+    
+    // Fee paid by each transaction, in the same order as `transactions`
+    pub fn transaction_fees(&self) -> &[u64] {
+        &self.transaction_fees
+    }
+    
+    // Total fees of the transactions currently in the template
+    pub fn total_fees(&self) -> u64 {
+        self.total_fees
+    }
+    
+    // Height of the block this template builds
+    pub fn height(&self) -> u64 {
+        self.height
+    }
+    
     // Add method to update template with additional fee to prioritize mining
     pub fn add_fee_to_coinbase(&mut self, additional_fee: u64) {
         if additional_fee == 0 {
@@ -168,22 +361,18 @@ impl BlockTemplate {
             let current_amount = output.amount();
             let new_amount = current_amount + additional_fee;
             
-            // Since we can't access private fields, we need to recreate the transaction
-            // First, create new outputs by recreating them based on available data
+            // Outputs are immutable, so recreate them with the first one increased
             let mut new_outputs = Vec::with_capacity(self.coinbase.outputs().len());
-            
-            // Replace first output with increased amount
             new_outputs.push(TransactionOutput::new(
                 new_amount,
-                Vec::new(), // We can't access the original pubkey script, use empty for now
+                output.pub_key_script().to_vec(),
             ));
             
             // Keep remaining outputs unchanged if any
-            for i in 1..self.coinbase.outputs().len() {
-                let o = &self.coinbase.outputs()[i];
+            for o in self.coinbase.outputs().iter().skip(1) {
                 new_outputs.push(TransactionOutput::new(
                     o.amount(),
-                    Vec::new(), // We can't access the original pubkey script, use empty for now
+                    o.pub_key_script().to_vec(),
                 ));
             }
             
@@ -201,6 +390,7 @@ impl BlockTemplate {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use btclib::consensus::REGTEST_POW_LIMIT_BITS;
     use std::time::Duration;
 
     struct MockMempool;
@@ -212,7 +402,7 @@ mod tests {
         }
         
         async fn get_prioritized_transactions(&self, _max_size: usize) -> Vec<Transaction> {
-            // Create three transactions with different fees for testing
+            // Create three transactions, highest fee first, for testing
             let tx1 = Transaction::new(1, vec![], vec![], 0);
             let tx2 = Transaction::new(1, vec![], vec![], 0);
             let tx3 = Transaction::new(1, vec![], vec![], 0);
@@ -239,7 +429,8 @@ mod tests {
         let template = BlockTemplate::new(
             1,
             [0u8; 32],
-            u32::MAX,
+            0,
+            REGTEST_POW_LIMIT_BITS,
             vec![1,2,3,4],
             &mempool,
         ).await;
@@ -248,7 +439,16 @@ mod tests {
         // The MockMempool implementation returns 3 transactions,
         // plus the coinbase transaction makes 4 total
         assert_eq!(block.transactions().len(), 4, "Block should have 4 transactions (coinbase + 3 from mempool)");
-        assert_eq!(block.transactions()[0].outputs()[0].amount(), BLOCK_REWARD);
+        
+        // Coinbase claims subsidy + fees, with the treasury share split into its own output
+        let fees = 50000 + 20000 + 10000;
+        let treasury_amount = treasury_share(fees, TREASURY_FEE_PERCENTAGE);
+        let coinbase = &block.transactions()[0];
+        assert!(coinbase.is_coinbase());
+        assert_eq!(coinbase.outputs()[0].amount(), BLOCK_REWARD + fees - treasury_amount);
+        assert_eq!(coinbase.outputs()[1].amount(), treasury_amount);
+        assert_eq!(coinbase.total_output(), BLOCK_REWARD + fees);
+        assert!(btclib::consensus::validate_coinbase(block.transactions(), 0, fees, treasury_amount).is_ok());
     }
     
     #[tokio::test]
@@ -267,13 +467,68 @@ mod tests {
         assert!(fee_ratio_first >= fee_ratio_second);
     }
     
+    struct ChainedMempool {
+        parent: Transaction,
+        child: Transaction,
+    }
+
+    impl ChainedMempool {
+        fn new() -> Self {
+            let parent = Transaction::new(
+                1,
+                vec![TransactionInput::new([1u8; 32], 0, vec![], 0)],
+                vec![TransactionOutput::new(5000, vec![1])],
+                0,
+            );
+            let child = Transaction::new(
+                1,
+                vec![TransactionInput::new(parent.hash(), 0, vec![], 0)],
+                vec![TransactionOutput::new(1000, vec![2])],
+                0,
+            );
+            Self { parent, child }
+        }
+    }
+
+    #[async_trait]
+    impl MempoolInterface for ChainedMempool {
+        async fn get_transactions(&self, _max_size: usize) -> Vec<Transaction> {
+            // Child first: it pays the higher fee rate
+            vec![self.child.clone(), self.parent.clone()]
+        }
+
+        async fn get_transaction_fees(&self, txids: &[Vec<u8>]) -> Vec<u64> {
+            txids.iter()
+                .map(|txid| if txid.as_slice() == self.child.hash() { 4000 } else { 10 })
+                .collect()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_parents_precede_children() {
+        let mempool = ChainedMempool::new();
+        let template = BlockTemplate::new(1, [0u8; 32], 1, REGTEST_POW_LIMIT_BITS, vec![1], &mempool).await;
+
+        assert_eq!(template.transactions().len(), 2);
+        assert_eq!(template.transactions()[0].hash(), mempool.parent.hash());
+        assert_eq!(template.transactions()[1].hash(), mempool.child.hash());
+        assert_eq!(template.transaction_fees(), &[10, 4000]);
+        assert_eq!(template.total_fees(), 4010);
+
+        // A child whose parent did not make it into the block is dropped
+        let candidates: HashSet<[u8; 32]> = [mempool.parent.hash(), mempool.child.hash()].into_iter().collect();
+        let ordered = BlockTemplate::order_by_dependencies(vec![(mempool.child.clone(), 4000)], &candidates);
+        assert!(ordered.is_empty());
+    }
+    
     #[tokio::test]
     async fn test_template_refresh() {
         let mempool = MockMempool;
         let mut template = BlockTemplate::new(
             1,
             [0u8; 32],
-            u32::MAX,
+            0,
+            REGTEST_POW_LIMIT_BITS,
             vec![1,2,3,4],
             &mempool,
         ).await;
@@ -290,4 +545,18 @@ mod tests {
         
         assert!(!template.needs_refresh());
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn test_attestation_commitment() {
+        let mempool = MockMempool;
+        let mut template = BlockTemplate::new(1, [0u8; 32], 0, REGTEST_POW_LIMIT_BITS, vec![1, 2, 3, 4], &mempool).await;
+        assert_eq!(btclib::environmental::attestation::find_commitment(&template.create_block()), None);
+
+        // The commitment survives transaction refreshes and costs nothing
+        template.set_attestation_commitment(Some([5u8; 32]));
+        template.update_transactions(&mempool).await;
+        let block = template.create_block();
+        assert_eq!(btclib::environmental::attestation::find_commitment(&block), Some([5u8; 32]));
+        assert_eq!(block.transactions()[0].total_output(), BLOCK_REWARD + 50000 + 20000 + 10000);
+    }
+}
@@ -6,16 +6,9 @@ use tokio::sync::mpsc;
 use tracing;
 use crate::mining::MempoolInterface;
 use std::time::{Instant, Duration};
-use rand::{Rng, SeedableRng};
-use rand::rngs::StdRng;
-use sha2::{Sha256, Digest};
+use btclib::consensus::hash_meets_target;
 use btclib::types::transaction::Transaction;
 
-// Memory-hard constants for ASIC resistance
-const MEMORY_SIZE: usize = 4 * 1024 * 1024; // 4MB memory requirement
-const MEMORY_ITERATIONS: usize = 64; // Number of memory accesses
-const MIXING_ROUNDS: usize = 16; // Number of mixing rounds
-
 pub struct MiningMetrics {
     hash_rate: AtomicU64,
     blocks_mined: AtomicU64,
@@ -113,11 +106,13 @@ impl MiningWorker {
         &self,
         version: u32,
         prev_block_hash: [u8; 32],
+        height: u64,
         reward_address: Vec<u8>,
     ) -> Result<(), String> {
         let mut template = BlockTemplate::new(
             version,
             prev_block_hash,
+            height,
             self.target,
             reward_address.clone(),
             self.mempool.as_ref(),
@@ -162,6 +157,7 @@ impl MiningWorker {
                 template = BlockTemplate::new(
                     version,
                     prev_block_hash,
+                    height,
                     self.target,
                     reward_address.clone(),
                     self.mempool.as_ref(),
@@ -173,92 +169,16 @@ impl MiningWorker {
         Ok(())
     }
 
+    /// Check the block against the same 256-bit target validators use
     pub fn check_proof_of_work(&self, block: &Block) -> bool {
-        let block_header = self.get_block_header(block);
-        let hash = self.memory_hard_hash(&block_header);
-        
-        let mut hash_value = [0u8; 8];
-        hash_value[..4].copy_from_slice(&hash[..4]);
-        let hash_value = u64::from_be_bytes(hash_value);
-        hash_value as u32 <= self.target
-    }
-    
-    // Extract block header for hashing
-    fn get_block_header(&self, block: &Block) -> Vec<u8> {
-        let mut header = Vec::new();
-        
-        // We need to extract header fields by directly accessing the header fields
-        // or using the available methods
-        header.extend_from_slice(&block.prev_block_hash());
-        
-        // Use hash directly as we don't have access to other header fields
-        let hash = block.hash();
-        header.extend_from_slice(&hash);
-        
-        header
-    }
-    
-    // Memory-hard hashing function to resist ASICs
-    fn memory_hard_hash(&self, data: &[u8]) -> [u8; 32] {
-        // Initialize memory with pseudorandom data derived from input
-        let mut memory = vec![0u8; MEMORY_SIZE];
-        let mut hasher = Sha256::new();
-        hasher.update(data);
-        let seed = hasher.finalize();
-        
-        // Initialize memory with deterministic values based on the seed
-        let mut rng = StdRng::from_seed(seed.into());
-        for chunk in memory.chunks_mut(8) {
-            if chunk.len() == 8 {
-                let value = rng.gen::<u64>().to_be_bytes();
-                chunk.copy_from_slice(&value);
-            }
-        }
-        
-        // Initial hash becomes our working value
-        let mut current_hash: [u8; 32] = seed.into();
-        
-        // Perform memory-hard mixing operations
-        for _iteration in 0..MEMORY_ITERATIONS {
-            // Use current hash to determine memory access pattern - ensure we don't overflow
-            let bytes: [u8; 8] = current_hash[0..8].try_into().unwrap_or([0; 8]);
-            let index = u64::from_be_bytes(bytes) as usize % (MEMORY_SIZE - 64);
-            
-            // Mix current hash with memory
-            for round in 0..MIXING_ROUNDS {
-                // Ensure we don't go out of bounds
-                if (index + (round + 1) * 4) > memory.len() {
-                    break;
-                }
-                
-                let memory_slice = &memory[index + round * 4..index + (round + 1) * 4];
-                
-                // XOR memory content with current hash
-                for j in 0..std::cmp::min(4, memory_slice.len()) {
-                    // Ensure we don't go out of bounds on the hash array
-                    if round * 2 + j < current_hash.len() {
-                        current_hash[round * 2 + j] ^= memory_slice[j];
-                    }
-                }
-                
-                // Update memory with new mixed values
-                let mut hasher = Sha256::new();
-                hasher.update(current_hash);
-                current_hash = hasher.finalize().into();
-            }
-        }
-        
-        // Final hash
-        let mut hasher = Sha256::new();
-        hasher.update(current_hash);
-        hasher.update(data);
-        hasher.finalize().into()
+        hash_meets_target(&block.hash(), self.target)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use btclib::consensus::REGTEST_POW_LIMIT_BITS;
     use tokio::sync::mpsc;
     use std::sync::Arc;
 
@@ -280,13 +200,13 @@ mod tests {
         let worker = MiningWorker::new(
             Arc::clone(&stop_signal),
             tx,
-            u32::MAX, // Easiest possible target for fast test completion
+            REGTEST_POW_LIMIT_BITS, // Every other hash meets it, so the test finishes quickly
             0,
             mempool,
         );
 
         let mining_handle = tokio::spawn(async move {
-            worker.mine_block(1, [0u8; 32], vec![1,2,3,4]).await.unwrap();
+            worker.mine_block(1, [0u8; 32], 1, vec![1,2,3,4]).await.unwrap();
         });
 
         tokio::select! {
@@ -311,7 +231,7 @@ mod tests {
         let worker = MiningWorker::new(
             Arc::clone(&stop_signal),
             tx,
-            u32::MAX,
+            REGTEST_POW_LIMIT_BITS,
             0,
             mempool,
         );
@@ -0,0 +1,398 @@
+use super::job::{difficulty_to_target, hash_value, MiningJob};
+use super::v1::{self, StratumMessage, SubmitParams};
+use super::v2::{self, Message};
+use super::{StratumError, StratumProtocol, EXTRANONCE2_SIZE};
+use btclib::types::block::BlockHeader;
+use serde_json::json;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::sync::mpsc;
+use tracing::{debug, info, warn};
+
+/// Number of nonces tried per blocking batch before checking for new work
+const DEFAULT_BATCH_SIZE: u32 = 20_000;
+
+/// Configuration for connecting the miner to a Stratum server
+#[derive(Debug, Clone)]
+pub struct StratumClientConfig {
+    /// Server address as `host:port`
+    pub address: String,
+    /// Worker name sent with `mining.authorize` / channel open
+    pub worker: String,
+    /// Worker password
+    pub password: String,
+    /// Wire protocol to speak
+    pub protocol: StratumProtocol,
+    /// Nonces hashed per batch
+    pub batch_size: u32,
+}
+
+impl Default for StratumClientConfig {
+    fn default() -> Self {
+        Self {
+            address: format!("127.0.0.1:{}", super::DEFAULT_STRATUM_PORT),
+            worker: "supernova-miner".to_string(),
+            password: "x".to_string(),
+            protocol: StratumProtocol::V1,
+            batch_size: DEFAULT_BATCH_SIZE,
+        }
+    }
+}
+
+/// Share statistics for a client session
+#[derive(Debug, Clone, Default)]
+pub struct ClientStats {
+    pub jobs_received: u64,
+    pub shares_submitted: u64,
+    pub shares_accepted: u64,
+    pub shares_rejected: u64,
+    pub current_difficulty: f64,
+}
+
+/// Stratum client that mines jobs from a remote server
+pub struct StratumClient {
+    config: StratumClientConfig,
+    stats: Arc<Mutex<ClientStats>>,
+}
+
+impl StratumClient {
+    pub fn new(config: StratumClientConfig) -> Self {
+        Self {
+            config,
+            stats: Arc::new(Mutex::new(ClientStats {
+                current_difficulty: 1.0,
+                ..Default::default()
+            })),
+        }
+    }
+
+    /// Snapshot of the session statistics
+    pub fn stats(&self) -> ClientStats {
+        self.stats.lock().unwrap().clone()
+    }
+
+    /// Connect and mine until `stop` is set or the connection fails
+    pub async fn run(&self, stop: Arc<AtomicBool>) -> Result<(), StratumError> {
+        info!("Connecting to Stratum server at {} ({:?})", self.config.address, self.config.protocol);
+        let stream = TcpStream::connect(&self.config.address).await?;
+        stream.set_nodelay(true)?;
+
+        match self.config.protocol {
+            StratumProtocol::V1 => self.run_v1(stream, stop).await,
+            StratumProtocol::V2 => self.run_v2(stream, stop).await,
+        }
+    }
+
+    async fn run_v1(&self, stream: TcpStream, stop: Arc<AtomicBool>) -> Result<(), StratumError> {
+        let (reader, mut writer) = stream.into_split();
+        let mut lines = BufReader::new(reader).lines();
+
+        let subscribe = StratumMessage::request(1, "mining.subscribe", vec![json!("supernova-miner/0.1")]);
+        let authorize = StratumMessage::request(
+            2,
+            "mining.authorize",
+            vec![json!(self.config.worker), json!(self.config.password)],
+        );
+        writer.write_all(subscribe.to_line()?.as_bytes()).await?;
+        writer.write_all(authorize.to_line()?.as_bytes()).await?;
+
+        let mut extranonce1: Option<Vec<u8>> = None;
+        let mut extranonce2_size = EXTRANONCE2_SIZE;
+        let mut share_target = u32::MAX;
+        let mut job: Option<MiningJob> = None;
+        let mut extranonce2: u32 = 0;
+        let mut next_nonce: u32 = 0;
+        let mut next_id: u64 = 3;
+
+        while !stop.load(Ordering::Relaxed) {
+            // Header template for the current job and extranonce2
+            let work = match (&job, &extranonce1) {
+                (Some(job), Some(extranonce1)) => {
+                    let extranonce2_bytes = encode_extranonce2(extranonce2, extranonce2_size);
+                    let merkle_root = job.merkle_root(extranonce1, &extranonce2_bytes);
+                    let header = job.header(merkle_root, job.ntime.max(now()), 0);
+                    Some((job.job_id, extranonce2_bytes, header))
+                }
+                _ => None,
+            };
+
+            tokio::select! {
+                line = lines.next_line() => {
+                    let line = match line? {
+                        Some(line) => line,
+                        None => return Err(StratumError::ConnectionClosed),
+                    };
+                    let message = StratumMessage::from_line(&line)?;
+
+                    match message.method.as_deref() {
+                        Some("mining.notify") => {
+                            let new_job = v1::parse_notify(message.params.as_deref().unwrap_or_default())?;
+                            debug!("Received job {:08x} (clean: {})", new_job.job_id, new_job.clean);
+                            self.stats.lock().unwrap().jobs_received += 1;
+                            job = Some(new_job);
+                            extranonce2 = 0;
+                            next_nonce = 0;
+                        }
+                        Some("mining.set_difficulty") => {
+                            let difficulty = message
+                                .params
+                                .as_ref()
+                                .and_then(|p| p.first())
+                                .and_then(|d| d.as_f64())
+                                .unwrap_or(1.0);
+                            share_target = difficulty_to_target(difficulty);
+                            self.stats.lock().unwrap().current_difficulty = difficulty;
+                            debug!("Share difficulty set to {}", difficulty);
+                        }
+                        Some(other) => debug!("Ignoring unsupported method {}", other),
+                        None => match message.id.as_u64() {
+                            Some(1) => {
+                                let result = message.result.clone().unwrap_or_default();
+                                let extranonce1_hex = result.get(1).and_then(|v| v.as_str()).unwrap_or_default();
+                                extranonce1 = Some(hex::decode(extranonce1_hex).map_err(|e| {
+                                    StratumError::Protocol(format!("invalid extranonce1: {}", e))
+                                })?);
+                                extranonce2_size = result
+                                    .get(2)
+                                    .and_then(|v| v.as_u64())
+                                    .map(|size| size as usize)
+                                    .unwrap_or(EXTRANONCE2_SIZE);
+                            }
+                            Some(2) => {
+                                if message.result != Some(json!(true)) {
+                                    return Err(StratumError::Protocol(format!(
+                                        "worker {} was not authorized", self.config.worker
+                                    )));
+                                }
+                                info!("Worker {} authorized", self.config.worker);
+                            }
+                            _ => self.record_share_result(message.error_details().map(|(_, msg)| msg)),
+                        },
+                    }
+                }
+                found = search(work.as_ref().map(|w| w.2.clone()), next_nonce, self.config.batch_size, share_target), if work.is_some() => {
+                    let (job_id, extranonce2_bytes, header) = work.expect("guarded by select condition");
+
+                    if let Some(nonce) = found {
+                        let submit = SubmitParams {
+                            worker: self.config.worker.clone(),
+                            job_id,
+                            extranonce2: extranonce2_bytes,
+                            ntime: header.timestamp(),
+                            nonce,
+                        };
+                        let request = StratumMessage::request(next_id, "mining.submit", submit.to_params());
+                        next_id += 1;
+                        writer.write_all(request.to_line()?.as_bytes()).await?;
+                        self.stats.lock().unwrap().shares_submitted += 1;
+                        next_nonce = nonce;
+                    } else {
+                        next_nonce = next_nonce.saturating_add(self.config.batch_size - 1);
+                    }
+
+                    // Roll extranonce2 once the nonce space is exhausted
+                    match next_nonce.checked_add(1) {
+                        Some(nonce) => next_nonce = nonce,
+                        None => {
+                            extranonce2 = extranonce2.wrapping_add(1);
+                            next_nonce = 0;
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn run_v2(&self, stream: TcpStream, stop: Arc<AtomicBool>) -> Result<(), StratumError> {
+        let (mut reader, mut writer) = stream.into_split();
+
+        // Frame reads are not cancellation safe, so decode on a separate task
+        let (message_tx, mut message_rx) = mpsc::channel(32);
+        let reader_handle = tokio::spawn(async move {
+            loop {
+                let message = v2::read_message(&mut reader).await;
+                let closed = message.is_err();
+                if message_tx.send(message).await.is_err() || closed {
+                    break;
+                }
+            }
+        });
+
+        v2::write_message(&mut writer, &Message::SetupConnection(v2::SetupConnection {
+            min_version: v2::PROTOCOL_VERSION,
+            max_version: v2::PROTOCOL_VERSION,
+            vendor: "supernova-miner".to_string(),
+        })).await?;
+        v2::write_message(&mut writer, &Message::OpenStandardMiningChannel(v2::OpenStandardMiningChannel {
+            request_id: 1,
+            user_identity: self.config.worker.clone(),
+            password: self.config.password.clone(),
+            nominal_hash_rate: 0.0,
+        })).await?;
+
+        let mut channel_id: Option<u32> = None;
+        let mut share_target = u32::MAX;
+        let mut job: Option<v2::NewMiningJob> = None;
+        let mut ntime: u64 = 0;
+        let mut next_nonce: u32 = 0;
+        let mut sequence_number: u32 = 0;
+
+        let result = loop {
+            if stop.load(Ordering::Relaxed) {
+                break Ok(());
+            }
+
+            let work = match (&job, channel_id) {
+                (Some(job), Some(_)) => {
+                    let mut header = BlockHeader::new(job.version, job.prev_block_hash, job.merkle_root, job.nbits);
+                    header.set_timestamp(ntime);
+                    Some((job.job_id, header))
+                }
+                _ => None,
+            };
+
+            tokio::select! {
+                message = message_rx.recv() => {
+                    let message = match message {
+                        Some(Ok(message)) => message,
+                        Some(Err(e)) => break Err(e),
+                        None => break Err(StratumError::ConnectionClosed),
+                    };
+
+                    match message {
+                        Message::SetupConnectionSuccess(success) => {
+                            debug!("Stratum V2 connection set up (version {})", success.used_version);
+                        }
+                        Message::SetupConnectionError(error) | Message::OpenMiningChannelError(error) => {
+                            break Err(StratumError::Protocol(error.error_code));
+                        }
+                        Message::OpenStandardMiningChannelSuccess(success) => {
+                            info!("Opened standard mining channel {}", success.channel_id);
+                            channel_id = Some(success.channel_id);
+                            share_target = success.target;
+                        }
+                        Message::SetTarget(set_target) => {
+                            share_target = set_target.target;
+                            self.stats.lock().unwrap().current_difficulty =
+                                super::target_to_difficulty(set_target.target);
+                        }
+                        Message::NewMiningJob(new_job) => {
+                            debug!("Received job {} (clean: {})", new_job.job_id, new_job.clean);
+                            self.stats.lock().unwrap().jobs_received += 1;
+                            ntime = new_job.min_ntime.max(now());
+                            next_nonce = 0;
+                            job = Some(new_job);
+                        }
+                        Message::SubmitSharesSuccess(_) => self.record_share_result(None),
+                        Message::SubmitSharesError(error) => self.record_share_result(Some(error.error_code)),
+                        other => debug!("Ignoring unexpected message {:?}", other),
+                    }
+                }
+                found = search(work.as_ref().map(|w| w.1.clone()), next_nonce, self.config.batch_size, share_target), if work.is_some() => {
+                    let (job_id, _) = work.expect("guarded by select condition");
+
+                    if let Some(nonce) = found {
+                        sequence_number += 1;
+                        v2::write_message(&mut writer, &Message::SubmitSharesStandard(v2::SubmitSharesStandard {
+                            channel_id: channel_id.unwrap_or_default(),
+                            sequence_number,
+                            job_id,
+                            nonce,
+                            ntime,
+                        })).await?;
+                        self.stats.lock().unwrap().shares_submitted += 1;
+                        next_nonce = nonce;
+                    } else {
+                        next_nonce = next_nonce.saturating_add(self.config.batch_size - 1);
+                    }
+
+                    // Standard channels have no extranonce to roll, so roll ntime instead
+                    match next_nonce.checked_add(1) {
+                        Some(nonce) => next_nonce = nonce,
+                        None => {
+                            ntime += 1;
+                            next_nonce = 0;
+                        }
+                    }
+                }
+            }
+        };
+
+        reader_handle.abort();
+        result
+    }
+
+    fn record_share_result(&self, error: Option<String>) {
+        let mut stats = self.stats.lock().unwrap();
+        match error {
+            None => stats.shares_accepted += 1,
+            Some(reason) => {
+                warn!("Share rejected: {}", reason);
+                stats.shares_rejected += 1;
+            }
+        }
+    }
+}
+
+/// Hash a batch of nonces on a blocking thread, returning the first that meets `share_target`
+async fn search(header: Option<BlockHeader>, start_nonce: u32, count: u32, share_target: u32) -> Option<u32> {
+    let mut header = header?;
+
+    tokio::task::spawn_blocking(move || {
+        let end = start_nonce.saturating_add(count.saturating_sub(1));
+        for nonce in start_nonce..=end {
+            header.set_nonce(nonce);
+            if hash_value(&header.hash()) <= share_target {
+                return Some(nonce);
+            }
+        }
+        None
+    })
+    .await
+    .ok()
+    .flatten()
+}
+
+fn encode_extranonce2(value: u32, size: usize) -> Vec<u8> {
+    let bytes = value.to_be_bytes();
+    if size >= bytes.len() {
+        let mut encoded = vec![0u8; size - bytes.len()];
+        encoded.extend_from_slice(&bytes);
+        encoded
+    } else {
+        bytes[bytes.len() - size..].to_vec()
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or(Duration::from_secs(0))
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use btclib::consensus::REGTEST_POW_LIMIT_BITS;
+
+    #[test]
+    fn test_encode_extranonce2() {
+        assert_eq!(encode_extranonce2(5, 4), vec![0, 0, 0, 5]);
+        assert_eq!(encode_extranonce2(0x0102, 2), vec![1, 2]);
+        assert_eq!(encode_extranonce2(1, 6), vec![0, 0, 0, 0, 0, 1]);
+    }
+
+    #[tokio::test]
+    async fn test_search_finds_share() {
+        let header = BlockHeader::new(1, [0u8; 32], [1u8; 32], REGTEST_POW_LIMIT_BITS);
+        assert_eq!(search(Some(header.clone()), 10, 5, u32::MAX).await, Some(10));
+        assert_eq!(search(Some(header), 0, 5, 0).await, None);
+        assert_eq!(search(None, 0, 5, u32::MAX).await, None);
+    }
+}
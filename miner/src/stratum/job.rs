@@ -0,0 +1,158 @@
+use btclib::types::block::BlockHeader;
+use btclib::types::transaction::{Transaction, TransactionInput, TransactionOutput};
+use btclib::util::merkle::MerkleTree;
+use serde::{Deserialize, Serialize};
+use crate::mining::BlockTemplate;
+
+/// A unit of work handed to an external miner
+///
+/// Everything a miner needs to rebuild the block header for any
+/// extranonce2/ntime/nonce combination, without the block's transactions.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MiningJob {
+    /// Server-assigned job identifier
+    pub job_id: u32,
+    /// Hash of the block this job builds on
+    pub prev_block_hash: [u8; 32],
+    /// Block version
+    pub version: u32,
+    /// Network target the block must meet, in compact form
+    pub target: u32,
+    /// Coinbase script before the extranonces (encodes the block height)
+    pub coinbase_prefix: Vec<u8>,
+    /// Coinbase outputs paying the block reward and treasury share
+    pub coinbase_outputs: Vec<TransactionOutput>,
+    /// Merkle branch from the coinbase to the root
+    pub merkle_branch: Vec<[u8; 32]>,
+    /// Earliest timestamp the miner may use
+    pub ntime: u64,
+    /// Whether previous jobs are invalidated (new chain tip)
+    pub clean: bool,
+}
+
+impl MiningJob {
+    /// Create a job from a block template
+    pub fn from_template(job_id: u32, template: &BlockTemplate, ntime: u64, clean: bool) -> Self {
+        Self {
+            job_id,
+            prev_block_hash: template.prev_block_hash(),
+            version: template.version(),
+            target: template.target(),
+            coinbase_prefix: template.coinbase().inputs()[0].signature_script().to_vec(),
+            coinbase_outputs: template.coinbase().outputs().to_vec(),
+            merkle_branch: template.coinbase_merkle_branch(),
+            ntime,
+            clean,
+        }
+    }
+
+    /// Build the coinbase transaction for the given extranonces
+    ///
+    /// Mirrors `BlockTemplate::coinbase_with_extranonce` so both sides agree on the hash.
+    pub fn build_coinbase(&self, extranonce1: &[u8], extranonce2: &[u8]) -> Transaction {
+        let mut script = self.coinbase_prefix.clone();
+        script.extend_from_slice(extranonce1);
+        script.extend_from_slice(extranonce2);
+
+        Transaction::new(
+            1,
+            vec![TransactionInput::new([0u8; 32], 0xffffffff, script, 0)],
+            self.coinbase_outputs.clone(),
+            0,
+        )
+    }
+
+    /// Merkle root for the given extranonces
+    pub fn merkle_root(&self, extranonce1: &[u8], extranonce2: &[u8]) -> [u8; 32] {
+        let coinbase = self.build_coinbase(extranonce1, extranonce2);
+        MerkleTree::root_from_first_leaf(coinbase.hash(), &self.merkle_branch)
+    }
+
+    /// Block header for a candidate solution
+    pub fn header(&self, merkle_root: [u8; 32], ntime: u64, nonce: u32) -> BlockHeader {
+        let mut header = BlockHeader::new(self.version, self.prev_block_hash, merkle_root, self.target);
+        header.set_timestamp(ntime);
+        header.set_nonce(nonce);
+        header
+    }
+}
+
+/// Convert a share difficulty into a share target
+///
+/// Share targets are compared against the first four bytes of the header hash
+/// (see `hash_value`), unlike the compact network target. Difficulty 1 accepts
+/// every hash; each doubling halves the target.
+pub fn difficulty_to_target(difficulty: f64) -> u32 {
+    if difficulty <= 1.0 {
+        return u32::MAX;
+    }
+    (u32::MAX as f64 / difficulty) as u32
+}
+
+/// Convert a share target back into a share difficulty
+pub fn target_to_difficulty(target: u32) -> f64 {
+    u32::MAX as f64 / target.max(1) as f64
+}
+
+/// Value of a header hash as compared against a share target
+pub fn hash_value(hash: &[u8; 32]) -> u32 {
+    u32::from_be_bytes([hash[0], hash[1], hash[2], hash[3]])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mining::MempoolInterface;
+    use async_trait::async_trait;
+    use btclib::consensus::REGTEST_POW_LIMIT_BITS;
+    use btclib::types::block::Block;
+
+    struct MockMempool;
+
+    #[async_trait]
+    impl MempoolInterface for MockMempool {
+        async fn get_transactions(&self, _max_size: usize) -> Vec<Transaction> {
+            (0..3u8)
+                .map(|i| Transaction::new(
+                    1,
+                    vec![TransactionInput::new([i + 1; 32], 0, vec![], 0)],
+                    vec![TransactionOutput::new(1000, vec![i])],
+                    0,
+                ))
+                .collect()
+        }
+
+        async fn get_transaction_fees(&self, txids: &[Vec<u8>]) -> Vec<u64> {
+            vec![100; txids.len()]
+        }
+    }
+
+    #[tokio::test]
+    async fn test_job_matches_template_block() {
+        let template = BlockTemplate::new(1, [7u8; 32], 10, REGTEST_POW_LIMIT_BITS, vec![1, 2, 3], &MockMempool).await;
+        let job = MiningJob::from_template(1, &template, 1_700_000_000, true);
+
+        let extranonce1 = [0xaa; 4];
+        let extranonce2 = [0x01, 0x02, 0x03, 0x04];
+        let mut extranonce = extranonce1.to_vec();
+        extranonce.extend_from_slice(&extranonce2);
+
+        let header = job.header(job.merkle_root(&extranonce1, &extranonce2), 1_700_000_005, 42);
+        let block = template.assemble_block(
+            template.coinbase_with_extranonce(&extranonce),
+            1_700_000_005,
+            42,
+        );
+
+        assert_eq!(job.build_coinbase(&extranonce1, &extranonce2).hash(), block.transactions()[0].hash());
+        assert_eq!(header.hash(), block.hash());
+        assert_eq!(Block::calculate_merkle_root(block.transactions()), header.merkle_root());
+    }
+
+    #[test]
+    fn test_difficulty_conversion() {
+        assert_eq!(difficulty_to_target(1.0), u32::MAX);
+        assert_eq!(difficulty_to_target(2.0), u32::MAX / 2);
+        assert!((target_to_difficulty(difficulty_to_target(64.0)) - 64.0).abs() < 0.01);
+    }
+}
@@ -0,0 +1,63 @@
+//! Stratum mining protocol
+//!
+//! Shared message types for the node's Stratum server and the miner's Stratum
+//! client. Two wire formats are supported on the same port:
+//!
+//! * V1: newline-delimited JSON-RPC (`mining.subscribe`, `mining.authorize`,
+//!   `mining.notify`, `mining.set_difficulty`, `mining.submit`).
+//! * V2: length-prefixed binary frames for header-only job distribution over
+//!   standard channels (no Noise encryption; run it on trusted networks).
+//!
+//! SuperNova transactions are bincode-encoded rather than raw Bitcoin
+//! serialization, so `coinb1`/`coinb2` in a V1 job carry the coinbase script
+//! prefix and the bincode-encoded coinbase outputs instead of raw byte halves.
+
+pub mod job;
+pub mod v1;
+pub mod v2;
+pub mod client;
+
+pub use self::job::{MiningJob, difficulty_to_target, target_to_difficulty};
+pub use self::client::{StratumClient, StratumClientConfig, ClientStats};
+
+use thiserror::Error;
+
+/// Default port for Stratum connections
+pub const DEFAULT_STRATUM_PORT: u16 = 3333;
+
+/// Size of the per-connection extranonce assigned by the server
+pub const EXTRANONCE1_SIZE: usize = 4;
+
+/// Size of the extranonce rolled by the miner
+pub const EXTRANONCE2_SIZE: usize = 4;
+
+/// Stratum protocol version used by a connection
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StratumProtocol {
+    /// JSON-RPC over newline-delimited text
+    V1,
+    /// Binary framed job distribution
+    V2,
+}
+
+/// Errors produced by the Stratum client and codecs
+#[derive(Debug, Error)]
+pub enum StratumError {
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("Binary encoding error: {0}")]
+    Encoding(#[from] bincode::Error),
+
+    #[error("Protocol error: {0}")]
+    Protocol(String),
+
+    #[error("Frame of {0} bytes exceeds the maximum frame size")]
+    FrameTooLarge(usize),
+
+    #[error("Connection closed by peer")]
+    ConnectionClosed,
+}
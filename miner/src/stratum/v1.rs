@@ -0,0 +1,270 @@
+//! Stratum V1 JSON-RPC messages
+//!
+//! Each message is a single JSON object terminated by a newline.
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use super::job::MiningJob;
+use super::StratumError;
+
+/// Job not found (stale or unknown job id)
+pub const ERROR_JOB_NOT_FOUND: i32 = 21;
+/// Share already submitted
+pub const ERROR_DUPLICATE_SHARE: i32 = 22;
+/// Share hash does not meet the connection's difficulty
+pub const ERROR_LOW_DIFFICULTY: i32 = 23;
+/// Worker is not authorized
+pub const ERROR_UNAUTHORIZED: i32 = 24;
+/// Connection has not subscribed
+pub const ERROR_NOT_SUBSCRIBED: i32 = 25;
+/// Any other error
+pub const ERROR_OTHER: i32 = 20;
+
+/// A Stratum V1 message: request, response or notification
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StratumMessage {
+    /// Request id, `null` for notifications
+    #[serde(default)]
+    pub id: Value,
+    /// Method name for requests and notifications
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub method: Option<String>,
+    /// Method parameters
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub params: Option<Vec<Value>>,
+    /// Result for responses
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<Value>,
+    /// Error for responses, as `[code, message, null]`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<Value>,
+}
+
+impl StratumMessage {
+    /// Create a request
+    pub fn request(id: u64, method: &str, params: Vec<Value>) -> Self {
+        Self {
+            id: json!(id),
+            method: Some(method.to_string()),
+            params: Some(params),
+            result: None,
+            error: None,
+        }
+    }
+
+    /// Create a notification (a request without id)
+    pub fn notification(method: &str, params: Vec<Value>) -> Self {
+        Self {
+            id: Value::Null,
+            method: Some(method.to_string()),
+            params: Some(params),
+            result: None,
+            error: None,
+        }
+    }
+
+    /// Create a successful response
+    pub fn response(id: Value, result: Value) -> Self {
+        Self {
+            id,
+            method: None,
+            params: None,
+            result: Some(result),
+            error: Some(Value::Null),
+        }
+    }
+
+    /// Create an error response
+    pub fn error_response(id: Value, code: i32, message: &str) -> Self {
+        Self {
+            id,
+            method: None,
+            params: None,
+            result: Some(Value::Null),
+            error: Some(json!([code, message, null])),
+        }
+    }
+
+    /// Encode as a newline-terminated line
+    pub fn to_line(&self) -> Result<String, StratumError> {
+        let mut line = serde_json::to_string(self)?;
+        line.push('\n');
+        Ok(line)
+    }
+
+    /// Decode from a single line
+    pub fn from_line(line: &str) -> Result<Self, StratumError> {
+        Ok(serde_json::from_str(line.trim())?)
+    }
+
+    /// Error code and message if this is an error response
+    pub fn error_details(&self) -> Option<(i32, String)> {
+        let error = self.error.as_ref()?.as_array()?;
+        let code = error.first()?.as_i64()? as i32;
+        let message = error.get(1).and_then(|m| m.as_str()).unwrap_or_default().to_string();
+        Some((code, message))
+    }
+}
+
+/// Parameters of a `mining.submit` request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SubmitParams {
+    pub worker: String,
+    pub job_id: u32,
+    pub extranonce2: Vec<u8>,
+    pub ntime: u64,
+    pub nonce: u32,
+}
+
+impl SubmitParams {
+    /// Encode as `[worker, job_id, extranonce2, ntime, nonce]`
+    pub fn to_params(&self) -> Vec<Value> {
+        vec![
+            json!(self.worker),
+            json!(format!("{:08x}", self.job_id)),
+            json!(hex::encode(&self.extranonce2)),
+            json!(format!("{:016x}", self.ntime)),
+            json!(format!("{:08x}", self.nonce)),
+        ]
+    }
+
+    /// Decode from `mining.submit` params
+    pub fn from_params(params: &[Value]) -> Result<Self, StratumError> {
+        let field = |index: usize| -> Result<&str, StratumError> {
+            params
+                .get(index)
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| StratumError::Protocol(format!("mining.submit missing parameter {}", index)))
+        };
+        let parse_hex = |index: usize| -> Result<u64, StratumError> {
+            u64::from_str_radix(field(index)?, 16)
+                .map_err(|e| StratumError::Protocol(format!("invalid hex in parameter {}: {}", index, e)))
+        };
+
+        Ok(Self {
+            worker: field(0)?.to_string(),
+            job_id: parse_hex(1)? as u32,
+            extranonce2: hex::decode(field(2)?)
+                .map_err(|e| StratumError::Protocol(format!("invalid extranonce2: {}", e)))?,
+            ntime: parse_hex(3)?,
+            nonce: parse_hex(4)? as u32,
+        })
+    }
+}
+
+/// Encode a job as `mining.notify` params
+///
+/// `[job_id, prev_hash, coinb1, coinb2, merkle_branch, version, nbits, ntime, clean_jobs]`
+/// where `coinb1` is the coinbase script prefix and `coinb2` the bincode-encoded outputs.
+pub fn notify_params(job: &MiningJob) -> Result<Vec<Value>, StratumError> {
+    let branch: Vec<String> = job.merkle_branch.iter().map(hex::encode).collect();
+
+    Ok(vec![
+        json!(format!("{:08x}", job.job_id)),
+        json!(hex::encode(job.prev_block_hash)),
+        json!(hex::encode(&job.coinbase_prefix)),
+        json!(hex::encode(bincode::serialize(&job.coinbase_outputs)?)),
+        json!(branch),
+        json!(format!("{:08x}", job.version)),
+        json!(format!("{:08x}", job.target)),
+        json!(format!("{:016x}", job.ntime)),
+        json!(job.clean),
+    ])
+}
+
+/// Decode a job from `mining.notify` params
+pub fn parse_notify(params: &[Value]) -> Result<MiningJob, StratumError> {
+    let field = |index: usize| -> Result<&Value, StratumError> {
+        params
+            .get(index)
+            .ok_or_else(|| StratumError::Protocol(format!("mining.notify missing parameter {}", index)))
+    };
+    let hex_str = |index: usize| -> Result<&str, StratumError> {
+        field(index)?
+            .as_str()
+            .ok_or_else(|| StratumError::Protocol(format!("mining.notify parameter {} is not a string", index)))
+    };
+    let hex_bytes = |index: usize| -> Result<Vec<u8>, StratumError> {
+        hex::decode(hex_str(index)?)
+            .map_err(|e| StratumError::Protocol(format!("invalid hex in parameter {}: {}", index, e)))
+    };
+    let hex_u64 = |index: usize| -> Result<u64, StratumError> {
+        u64::from_str_radix(hex_str(index)?, 16)
+            .map_err(|e| StratumError::Protocol(format!("invalid hex in parameter {}: {}", index, e)))
+    };
+    let to_hash = |bytes: Vec<u8>| -> Result<[u8; 32], StratumError> {
+        bytes
+            .try_into()
+            .map_err(|_| StratumError::Protocol("hash must be 32 bytes".to_string()))
+    };
+
+    let merkle_branch = field(4)?
+        .as_array()
+        .ok_or_else(|| StratumError::Protocol("merkle branch must be an array".to_string()))?
+        .iter()
+        .map(|v| {
+            let hex_hash = v
+                .as_str()
+                .ok_or_else(|| StratumError::Protocol("merkle branch entry is not a string".to_string()))?;
+            let bytes = hex::decode(hex_hash)
+                .map_err(|e| StratumError::Protocol(format!("invalid merkle branch entry: {}", e)))?;
+            to_hash(bytes)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(MiningJob {
+        job_id: hex_u64(0)? as u32,
+        prev_block_hash: to_hash(hex_bytes(1)?)?,
+        coinbase_prefix: hex_bytes(2)?,
+        coinbase_outputs: bincode::deserialize(&hex_bytes(3)?)?,
+        merkle_branch,
+        version: hex_u64(5)? as u32,
+        target: hex_u64(6)? as u32,
+        ntime: hex_u64(7)?,
+        clean: field(8)?.as_bool().unwrap_or(false),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use btclib::types::transaction::TransactionOutput;
+
+    #[test]
+    fn test_notify_roundtrip() {
+        let job = MiningJob {
+            job_id: 0x1f,
+            prev_block_hash: [3u8; 32],
+            version: 1,
+            target: 0x00ffffff,
+            coinbase_prefix: vec![1, 0, 0, 0, 0, 0, 0, 0],
+            coinbase_outputs: vec![TransactionOutput::new(5_000_000_000, vec![1, 2, 3])],
+            merkle_branch: vec![[9u8; 32], [8u8; 32]],
+            ntime: 1_700_000_000,
+            clean: true,
+        };
+
+        let message = StratumMessage::notification("mining.notify", notify_params(&job).unwrap());
+        let decoded = StratumMessage::from_line(&message.to_line().unwrap()).unwrap();
+        assert_eq!(parse_notify(&decoded.params.unwrap()).unwrap(), job);
+    }
+
+    #[test]
+    fn test_submit_roundtrip() {
+        let submit = SubmitParams {
+            worker: "rig1".to_string(),
+            job_id: 7,
+            extranonce2: vec![0, 0, 0, 5],
+            ntime: 1_700_000_001,
+            nonce: 0xdeadbeef,
+        };
+        assert_eq!(SubmitParams::from_params(&submit.to_params()).unwrap(), submit);
+    }
+
+    #[test]
+    fn test_error_response() {
+        let message = StratumMessage::error_response(json!(4), ERROR_LOW_DIFFICULTY, "Low difficulty share");
+        assert_eq!(message.error_details(), Some((ERROR_LOW_DIFFICULTY, "Low difficulty share".to_string())));
+        assert_eq!(StratumMessage::response(json!(4), json!(true)).error_details(), None);
+    }
+}
@@ -0,0 +1,249 @@
+//! Stratum V2 binary job distribution
+//!
+//! Frames follow the Stratum V2 layout: a 6-byte header (`extension_type: u16`,
+//! `msg_type: u8`, `msg_length: u24`, all little-endian) followed by the payload.
+//! Payloads are bincode-encoded. Only standard (header-only) channels are
+//! supported: the server hands out merkle roots and the miner rolls nonce and ntime.
+
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use super::StratumError;
+
+/// Size of the frame header in bytes
+pub const FRAME_HEADER_SIZE: usize = 6;
+
+/// Largest payload a frame may carry (u24)
+pub const MAX_FRAME_PAYLOAD: usize = 0x00ff_ffff;
+
+/// Protocol version advertised in `SetupConnection`
+pub const PROTOCOL_VERSION: u16 = 2;
+
+const MSG_SETUP_CONNECTION: u8 = 0x00;
+const MSG_SETUP_CONNECTION_SUCCESS: u8 = 0x01;
+const MSG_SETUP_CONNECTION_ERROR: u8 = 0x02;
+const MSG_OPEN_STANDARD_MINING_CHANNEL: u8 = 0x10;
+const MSG_OPEN_STANDARD_MINING_CHANNEL_SUCCESS: u8 = 0x11;
+const MSG_OPEN_MINING_CHANNEL_ERROR: u8 = 0x12;
+const MSG_SUBMIT_SHARES_STANDARD: u8 = 0x1a;
+const MSG_SUBMIT_SHARES_SUCCESS: u8 = 0x1c;
+const MSG_SUBMIT_SHARES_ERROR: u8 = 0x1d;
+const MSG_NEW_MINING_JOB: u8 = 0x1e;
+const MSG_SET_TARGET: u8 = 0x21;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SetupConnection {
+    pub min_version: u16,
+    pub max_version: u16,
+    pub vendor: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SetupConnectionSuccess {
+    pub used_version: u16,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OpenStandardMiningChannel {
+    pub request_id: u32,
+    pub user_identity: String,
+    pub password: String,
+    /// Expected hash rate in hashes per second, used to pick a starting target
+    pub nominal_hash_rate: f32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OpenStandardMiningChannelSuccess {
+    pub request_id: u32,
+    pub channel_id: u32,
+    pub target: u32,
+    pub extranonce_prefix: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NewMiningJob {
+    pub channel_id: u32,
+    pub job_id: u32,
+    pub version: u32,
+    pub prev_block_hash: [u8; 32],
+    pub merkle_root: [u8; 32],
+    /// Network target the block must meet
+    pub nbits: u32,
+    pub min_ntime: u64,
+    /// Whether previous jobs are invalidated (new chain tip)
+    pub clean: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SubmitSharesStandard {
+    pub channel_id: u32,
+    pub sequence_number: u32,
+    pub job_id: u32,
+    pub nonce: u32,
+    pub ntime: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SubmitSharesSuccess {
+    pub channel_id: u32,
+    pub last_sequence_number: u32,
+    pub accepted_shares: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SubmitSharesError {
+    pub channel_id: u32,
+    pub sequence_number: u32,
+    pub error_code: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SetTarget {
+    pub channel_id: u32,
+    pub target: u32,
+}
+
+/// Error carried by setup and channel-open failures
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ErrorMessage {
+    pub request_id: u32,
+    pub error_code: String,
+}
+
+/// All messages understood by the V2 mining subprotocol
+#[derive(Debug, Clone, PartialEq)]
+pub enum Message {
+    SetupConnection(SetupConnection),
+    SetupConnectionSuccess(SetupConnectionSuccess),
+    SetupConnectionError(ErrorMessage),
+    OpenStandardMiningChannel(OpenStandardMiningChannel),
+    OpenStandardMiningChannelSuccess(OpenStandardMiningChannelSuccess),
+    OpenMiningChannelError(ErrorMessage),
+    NewMiningJob(NewMiningJob),
+    SubmitSharesStandard(SubmitSharesStandard),
+    SubmitSharesSuccess(SubmitSharesSuccess),
+    SubmitSharesError(SubmitSharesError),
+    SetTarget(SetTarget),
+}
+
+impl Message {
+    /// Encode into a complete frame
+    pub fn encode(&self) -> Result<Vec<u8>, StratumError> {
+        let (msg_type, payload) = match self {
+            Message::SetupConnection(m) => (MSG_SETUP_CONNECTION, bincode::serialize(m)?),
+            Message::SetupConnectionSuccess(m) => (MSG_SETUP_CONNECTION_SUCCESS, bincode::serialize(m)?),
+            Message::SetupConnectionError(m) => (MSG_SETUP_CONNECTION_ERROR, bincode::serialize(m)?),
+            Message::OpenStandardMiningChannel(m) => (MSG_OPEN_STANDARD_MINING_CHANNEL, bincode::serialize(m)?),
+            Message::OpenStandardMiningChannelSuccess(m) => (MSG_OPEN_STANDARD_MINING_CHANNEL_SUCCESS, bincode::serialize(m)?),
+            Message::OpenMiningChannelError(m) => (MSG_OPEN_MINING_CHANNEL_ERROR, bincode::serialize(m)?),
+            Message::NewMiningJob(m) => (MSG_NEW_MINING_JOB, bincode::serialize(m)?),
+            Message::SubmitSharesStandard(m) => (MSG_SUBMIT_SHARES_STANDARD, bincode::serialize(m)?),
+            Message::SubmitSharesSuccess(m) => (MSG_SUBMIT_SHARES_SUCCESS, bincode::serialize(m)?),
+            Message::SubmitSharesError(m) => (MSG_SUBMIT_SHARES_ERROR, bincode::serialize(m)?),
+            Message::SetTarget(m) => (MSG_SET_TARGET, bincode::serialize(m)?),
+        };
+
+        if payload.len() > MAX_FRAME_PAYLOAD {
+            return Err(StratumError::FrameTooLarge(payload.len()));
+        }
+
+        let length = (payload.len() as u32).to_le_bytes();
+        let mut frame = Vec::with_capacity(FRAME_HEADER_SIZE + payload.len());
+        frame.extend_from_slice(&0u16.to_le_bytes()); // extension_type
+        frame.push(msg_type);
+        frame.extend_from_slice(&length[..3]);
+        frame.extend_from_slice(&payload);
+        Ok(frame)
+    }
+
+    /// Decode a payload for the given message type
+    pub fn decode(msg_type: u8, payload: &[u8]) -> Result<Self, StratumError> {
+        Ok(match msg_type {
+            MSG_SETUP_CONNECTION => Message::SetupConnection(bincode::deserialize(payload)?),
+            MSG_SETUP_CONNECTION_SUCCESS => Message::SetupConnectionSuccess(bincode::deserialize(payload)?),
+            MSG_SETUP_CONNECTION_ERROR => Message::SetupConnectionError(bincode::deserialize(payload)?),
+            MSG_OPEN_STANDARD_MINING_CHANNEL => Message::OpenStandardMiningChannel(bincode::deserialize(payload)?),
+            MSG_OPEN_STANDARD_MINING_CHANNEL_SUCCESS => Message::OpenStandardMiningChannelSuccess(bincode::deserialize(payload)?),
+            MSG_OPEN_MINING_CHANNEL_ERROR => Message::OpenMiningChannelError(bincode::deserialize(payload)?),
+            MSG_NEW_MINING_JOB => Message::NewMiningJob(bincode::deserialize(payload)?),
+            MSG_SUBMIT_SHARES_STANDARD => Message::SubmitSharesStandard(bincode::deserialize(payload)?),
+            MSG_SUBMIT_SHARES_SUCCESS => Message::SubmitSharesSuccess(bincode::deserialize(payload)?),
+            MSG_SUBMIT_SHARES_ERROR => Message::SubmitSharesError(bincode::deserialize(payload)?),
+            MSG_SET_TARGET => Message::SetTarget(bincode::deserialize(payload)?),
+            other => return Err(StratumError::Protocol(format!("unknown message type 0x{:02x}", other))),
+        })
+    }
+}
+
+/// Read one frame from a stream
+pub async fn read_message<R: AsyncRead + Unpin>(reader: &mut R) -> Result<Message, StratumError> {
+    let mut header = [0u8; FRAME_HEADER_SIZE];
+    match reader.read_exact(&mut header).await {
+        Ok(_) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Err(StratumError::ConnectionClosed),
+        Err(e) => return Err(e.into()),
+    }
+
+    let msg_type = header[2];
+    let length = u32::from_le_bytes([header[3], header[4], header[5], 0]) as usize;
+
+    let mut payload = vec![0u8; length];
+    reader.read_exact(&mut payload).await?;
+    Message::decode(msg_type, &payload)
+}
+
+/// Write one frame to a stream
+pub async fn write_message<W: AsyncWrite + Unpin>(writer: &mut W, message: &Message) -> Result<(), StratumError> {
+    writer.write_all(&message.encode()?).await?;
+    writer.flush().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_frame_roundtrip() {
+        let messages = vec![
+            Message::SetupConnection(SetupConnection {
+                min_version: PROTOCOL_VERSION,
+                max_version: PROTOCOL_VERSION,
+                vendor: "supernova-miner".to_string(),
+            }),
+            Message::NewMiningJob(NewMiningJob {
+                channel_id: 1,
+                job_id: 2,
+                version: 1,
+                prev_block_hash: [4u8; 32],
+                merkle_root: [5u8; 32],
+                nbits: 0x1d00ffff,
+                min_ntime: 1_700_000_000,
+                clean: true,
+            }),
+            Message::SubmitSharesError(SubmitSharesError {
+                channel_id: 1,
+                sequence_number: 3,
+                error_code: "stale-job".to_string(),
+            }),
+        ];
+
+        let mut buffer = Vec::new();
+        for message in &messages {
+            write_message(&mut buffer, message).await.unwrap();
+        }
+
+        let mut reader = buffer.as_slice();
+        for message in &messages {
+            assert_eq!(&read_message(&mut reader).await.unwrap(), message);
+        }
+        assert!(matches!(read_message(&mut reader).await, Err(StratumError::ConnectionClosed)));
+    }
+
+    #[test]
+    fn test_frame_header_layout() {
+        let frame = Message::SetTarget(SetTarget { channel_id: 1, target: 2 }).encode().unwrap();
+        assert_eq!(&frame[..2], &[0, 0]);
+        assert_eq!(frame[2], MSG_SET_TARGET);
+        assert_eq!(u32::from_le_bytes([frame[3], frame[4], frame[5], 0]) as usize, frame.len() - FRAME_HEADER_SIZE);
+    }
+}
@@ -269,6 +269,19 @@ Or in case of an error:
 }
 ```
 
+## Authentication
+
+When authentication is enabled, send a key as `Authorization: Bearer <key>`. Each key has a role:
+`readonly` can call the query methods, `wallet` also `sendrawtransaction` and `submitpackage`,
+`mining` also `getblocktemplate` and `submitblock`, and `admin` every method. Local clients can
+use the secret from the cookie file, which has the `admin` role.
+
+## Rate Limits
+
+Each client (by API key, or by IP address without one) may spend a configured number of cost units
+per minute. Most methods cost 1; heavier ones such as `getblock` cost more. A batch is charged for
+all its requests at once and may hold at most `max_batch_size` of them.
+
 ## Available Methods
 
 ### Blockchain Methods
@@ -285,11 +298,16 @@ Or in case of an error:
 - `gettransaction`: Get transaction information
 - `getrawtransaction`: Get raw transaction data
 - `sendrawtransaction`: Send raw transaction
+- `submitpackage`: Submit a child transaction with its unconfirmed parents, judged by their combined fee rate
 
 ### Mempool Methods
 
 - `getmempoolinfo`: Get mempool information
 - `getrawmempool`: Get raw mempool transactions
+- `estimatesmartfee`: Estimate the fee rate to confirm within a number of blocks
+- `prioritisetransaction`: Adjust the fee a transaction is mined and evicted by
+- `savemempool`: Save the mempool to disk so it survives a restart
+- `loadmempool`: Reload the saved mempool, revalidating it against the current tip
 
 ### Network Methods
 
@@ -302,6 +320,20 @@ Or in case of an error:
 - `getblocktemplate`: Get block template for mining
 - `submitblock`: Submit a mined block
 
+### Utility Methods
+
+- `validateaddress`: Check that an address is well-formed and belongs to this node's network
+
+### Subscription Methods
+
+Only available on the WebSocket endpoint (`/rpc/ws`), which also accepts every method above.
+
+- `subscribe`: Receive notifications for a topic: `newTip`, `reorg`, `mempool`, `address` (followed by an address) or `lightning` (needs the `wallet` role); returns a subscription id
+- `unsubscribe`: Stop the notifications of a subscription id
+
+Notifications use the `subscription` method with params `{"subscription": id, "result": ...}`.
+A connection that reads too slowly misses events and is sent `{"type": "lagged", "missed": n}`.
+
 ## Error Codes
 
 - `-32700`: Parse error - Invalid JSON was received
@@ -315,6 +347,8 @@ Or in case of an error:
 - `-32003`: Transaction error - Error in transaction processing
 - `-32004`: Wallet error - Error in wallet operations
 - `-32005`: Network error - Error in network operations
+- `-32006`: Forbidden - The caller's role does not permit the method
+- `-32007`: Rate limited - The caller exceeded its rate limit (over HTTP: status 429 with `Retry-After`)
             "#;
             
             doc.to_string()
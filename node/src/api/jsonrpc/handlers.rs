@@ -6,6 +6,13 @@ use std::sync::Arc;
 use actix_web::web;
 use serde_json::{Value, json};
 use crate::node::Node;
+use crate::mempool::fee_estimator::MAX_CONFIRMATION_TARGET;
+use crate::mempool::MEMPOOL_FILE;
+use crate::mining::{BlockTemplateService, TemplateRequest};
+use btclib::types::address::Address;
+use btclib::types::block::Block;
+use btclib::types::transaction::Transaction;
+use tracing::{debug, warn};
 use super::types::{JsonRpcError, ErrorCode};
 
 /// Dispatch method to appropriate handler
@@ -30,10 +37,15 @@ pub async fn dispatch(
         "gettransaction" => get_transaction(params, node).await,
         "getrawtransaction" => get_raw_transaction(params, node).await,
         "sendrawtransaction" => send_raw_transaction(params, node).await,
+        "submitpackage" => submit_package(params, node).await,
         
         // Mempool methods
         "getmempoolinfo" => get_mempool_info(params, node).await,
         "getrawmempool" => get_raw_mempool(params, node).await,
+        "estimatesmartfee" => estimate_smart_fee(params, node).await,
+        "prioritisetransaction" => prioritise_transaction(params, node).await,
+        "savemempool" => save_mempool(params, node).await,
+        "loadmempool" => load_mempool(params, node).await,
         
         // Network methods
         "getnetworkinfo" => get_network_info(params, node).await,
@@ -44,6 +56,16 @@ pub async fn dispatch(
         "getblocktemplate" => get_block_template(params, node).await,
         "submitblock" => submit_block(params, node).await,
         
+        // Utility methods
+        "validateaddress" => validate_address(params, node).await,
+        
+        // Subscriptions need a connection to deliver notifications on
+        "subscribe" | "unsubscribe" => Err(JsonRpcError {
+            code: ErrorCode::InvalidRequest as i32,
+            message: format!("Method '{}' is only available over the WebSocket endpoint", method),
+            data: None,
+        }),
+        
         // Method not found
         _ => Err(JsonRpcError {
             code: ErrorCode::MethodNotFound as i32,
@@ -350,31 +372,122 @@ async fn get_transaction(
 }
 
 /// Get raw transaction data
+///
+/// Takes `[txid]` and returns the hex-encoded serialized transaction, looking
+/// in the mempool before the chain.
 async fn get_raw_transaction(
     params: Value,
     node: web::Data<Arc<Node>>,
 ) -> Result<Value, JsonRpcError> {
-    // Implement raw transaction retrieval here
-    // This is a placeholder implementation
-    Err(JsonRpcError {
-        code: ErrorCode::MethodNotFound as i32,
-        message: "Method not yet implemented".to_string(),
+    let (txid_hex,): (String,) = extract_params(params)?;
+    let txid: [u8; 32] = hex::decode(&txid_hex)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| JsonRpcError {
+            code: ErrorCode::InvalidParams as i32,
+            message: "Invalid transaction hash".to_string(),
+            data: None,
+        })?;
+
+    let tx = match node.mempool().get_transaction(&txid) {
+        Some(tx) => Some(tx),
+        None => node.storage().get_transaction(&txid).map_err(|e| JsonRpcError {
+            code: ErrorCode::BlockchainError as i32,
+            message: format!("Failed to get transaction: {}", e),
+            data: None,
+        })?,
+    };
+    let tx = tx.ok_or_else(|| JsonRpcError {
+        code: ErrorCode::BlockchainError as i32,
+        message: format!("Transaction not found: {}", txid_hex),
         data: None,
-    })
+    })?;
+
+    let serialized = bincode::serialize(&tx).map_err(|e| JsonRpcError {
+        code: ErrorCode::InternalError as i32,
+        message: format!("Failed to serialize transaction: {}", e),
+        data: None,
+    })?;
+    Ok(json!(hex::encode(serialized)))
 }
 
 /// Send raw transaction
+///
+/// Takes `[hex]`, a serialized signed transaction, adds it to the mempool and
+/// relays it to peers. Returns the transaction hash.
 async fn send_raw_transaction(
     params: Value,
     node: web::Data<Arc<Node>>,
 ) -> Result<Value, JsonRpcError> {
-    // Implement send raw transaction here
-    // This is a placeholder implementation
-    Err(JsonRpcError {
-        code: ErrorCode::MethodNotFound as i32,
-        message: "Method not yet implemented".to_string(),
-        data: None,
-    })
+    let (tx_hex,): (String,) = extract_params(params)?;
+    let tx: Transaction = hex::decode(&tx_hex)
+        .ok()
+        .and_then(|bytes| bincode::deserialize(&bytes).ok())
+        .ok_or_else(|| JsonRpcError {
+            code: ErrorCode::InvalidParams as i32,
+            message: "Invalid transaction data".to_string(),
+            data: None,
+        })?;
+
+    let chain = node.chain_state.read().await;
+    node.mempool().accept_transaction(tx.clone(), &*chain).map_err(|e| JsonRpcError {
+        code: ErrorCode::TransactionError as i32,
+        message: format!("Transaction rejected: {}", e),
+        data: Some(json!({ "reason": e.reason() })),
+    })?;
+    drop(chain);
+    node.broadcast_transaction(&tx);
+
+    Ok(Value::String(hex::encode(tx.hash())))
+}
+
+/// Submit a child and its unconfirmed parents, judged by their combined fee rate
+///
+/// Takes an array of hex-encoded transactions, parents first and the child last.
+async fn submit_package(
+    params: Value,
+    node: web::Data<Arc<Node>>,
+) -> Result<Value, JsonRpcError> {
+    let (raw_transactions,): (Vec<String>,) = extract_params(params)?;
+    let transactions: Vec<Transaction> = raw_transactions
+        .iter()
+        .map(|tx_hex| hex::decode(tx_hex).ok().and_then(|bytes| bincode::deserialize(&bytes).ok()))
+        .collect::<Option<_>>()
+        .ok_or_else(|| JsonRpcError {
+            code: ErrorCode::InvalidParams as i32,
+            message: "Invalid transaction data".to_string(),
+            data: None,
+        })?;
+
+    let chain = node.chain_state.read().await;
+    let acceptance = node.mempool().submit_package(transactions.clone(), &*chain).map_err(|e| JsonRpcError {
+        code: ErrorCode::TransactionError as i32,
+        message: format!("Package rejected: {}", e),
+        data: Some(json!({ "reason": e.reason() })),
+    })?;
+    drop(chain);
+    if !acceptance.accepted.is_empty() {
+        node.broadcast_package(&transactions);
+    }
+
+    let tx_results: serde_json::Map<String, Value> = transactions
+        .iter()
+        .map(|tx| {
+            let tx_hash = tx.hash();
+            let status = if acceptance.accepted.contains(&tx_hash) { "accepted" } else { "already-in-mempool" };
+            (hex::encode(tx_hash), json!({ "status": status, "fee": node.mempool().get_fee(&tx_hash) }))
+        })
+        .collect();
+    let replaced: Vec<String> = acceptance.replaced.iter().map(hex::encode).collect();
+
+    Ok(json!({
+        "package_msg": "success",
+        "tx-results": tx_results,
+        "replaced-transactions": replaced,
+        "fee": acceptance.fee,
+        "size": acceptance.size,
+        "feerate": acceptance.fee_rate,
+    }))
 }
 
 /// Get mempool information
@@ -451,6 +564,98 @@ async fn get_raw_mempool(
     }
 }
 
+/// Estimate the fee rate needed to confirm within a number of blocks
+///
+/// Takes `[conf_target]` and answers as Bitcoin Core does: `feerate` in NOVA
+/// per kilobyte and `blocks`, the target the estimate was found for, or
+/// `errors` while the node has not seen enough blocks.
+async fn estimate_smart_fee(
+    params: Value,
+    node: web::Data<Arc<Node>>,
+) -> Result<Value, JsonRpcError> {
+    let (target,): (u32,) = extract_params(params)?;
+    if target == 0 || target > MAX_CONFIRMATION_TARGET {
+        return Err(JsonRpcError {
+            code: ErrorCode::InvalidParams as i32,
+            message: format!("Invalid conf_target, must be between 1 and {}", MAX_CONFIRMATION_TARGET),
+            data: None,
+        });
+    }
+
+    match node.mempool().estimate_smart_fee(target) {
+        Some(estimate) => Ok(json!({
+            // Satoshis per byte to NOVA per kilobyte
+            "feerate": estimate.fee_rate as f64 * 1000.0 / 100000000.0,
+            "blocks": estimate.target,
+        })),
+        None => Ok(json!({
+            "errors": ["Insufficient data or no feerate found"],
+            "blocks": target,
+        })),
+    }
+}
+
+/// Adjust the fee a transaction is mined and evicted by, without changing what it pays
+///
+/// Takes the transaction hash and a fee delta in satoshis, which may be negative.
+async fn prioritise_transaction(
+    params: Value,
+    node: web::Data<Arc<Node>>,
+) -> Result<Value, JsonRpcError> {
+    let (txid_hex, fee_delta): (String, i64) = extract_params(params)?;
+    let txid: [u8; 32] = hex::decode(&txid_hex)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| JsonRpcError {
+            code: ErrorCode::InvalidParams as i32,
+            message: "Invalid transaction hash".to_string(),
+            data: None,
+        })?;
+
+    node.mempool().prioritise_transaction(&txid, fee_delta);
+    Ok(Value::Bool(true))
+}
+
+/// Write the mempool to the storage directory so it survives a restart
+async fn save_mempool(
+    _params: Value,
+    node: web::Data<Arc<Node>>,
+) -> Result<Value, JsonRpcError> {
+    let path = node.config.storage.db_path.join(MEMPOOL_FILE);
+    let saved = node.mempool().save(&path).map_err(|e| JsonRpcError {
+        code: ErrorCode::ServerError as i32,
+        message: format!("Unable to dump mempool to disk: {}", e),
+        data: None,
+    })?;
+
+    Ok(json!({
+        "filename": path.display().to_string(),
+        "transactions": saved,
+    }))
+}
+
+/// Reload the saved mempool, revalidating each transaction against the current tip
+async fn load_mempool(
+    _params: Value,
+    node: web::Data<Arc<Node>>,
+) -> Result<Value, JsonRpcError> {
+    let path = node.config.storage.db_path.join(MEMPOOL_FILE);
+    let chain = node.chain_state.read().await;
+    let stats = node.mempool().load(&path, &*chain).map_err(|e| JsonRpcError {
+        code: ErrorCode::ServerError as i32,
+        message: format!("Unable to load mempool from disk: {}", e),
+        data: None,
+    })?;
+
+    Ok(json!({
+        "filename": path.display().to_string(),
+        "accepted": stats.accepted,
+        "failed": stats.failed,
+        "expired": stats.expired,
+        "already_in_mempool": stats.already_there,
+    }))
+}
+
 /// Get network information
 async fn get_network_info(
     _params: Value,
@@ -561,52 +766,66 @@ async fn get_mining_info(
     }))
 }
 
-/// Get block template for mining
+/// Get block template for mining (BIP22), or check a block proposal (BIP23)
 async fn get_block_template(
-    _params: Value,
+    params: Value,
     node: web::Data<Arc<Node>>,
 ) -> Result<Value, JsonRpcError> {
-    let template = node.get_block_template().await.map_err(|e| JsonRpcError {
-        code: ErrorCode::ServerError as i32,
-        message: format!("Failed to get block template: {}", e),
-        data: None,
-    })?;
-    
-    let txs_json: Vec<Value> = template.transactions.iter().map(|tx| {
-        json!({
-            "data": hex::encode(bincode::serialize(tx).unwrap_or_default()),
-            "txid": hex::encode(tx.hash()),
-            "hash": hex::encode(tx.hash()),
-            "depends": [],
-            "fee": tx.fee().unwrap_or(0),
-            "sigops": 4, // Placeholder
-            "weight": tx.weight()
-        })
-    }).collect();
-    
-    Ok(json!({
-        "version": template.version,
-        "previousblockhash": hex::encode(template.prev_hash),
-        "transactions": txs_json,
-        "coinbaseaux": {
-            "flags": ""
-        },
-        "coinbasevalue": 5000000000, // Placeholder
-        "longpollid": format!("{} {}", hex::encode(template.prev_hash), template.height),
-        "target": hex::encode(template.target.to_be_bytes()),
-        "mintime": template.timestamp,
-        "mutable": [
-            "time",
-            "transactions",
-            "prevblock"
-        ],
-        "noncerange": "00000000ffffffff",
-        "sigoplimit": 80000,
-        "sizelimit": 4000000,
-        "curtime": template.timestamp,
-        "bits": format!("{:x}", template.target),
-        "height": template.height
-    }))
+    let service = block_template_service(&node)?;
+
+    // Accept `[{...}]`, `{...}` or no parameters at all
+    let request_value = match params {
+        Value::Array(mut arr) if !arr.is_empty() => arr.remove(0),
+        Value::Array(_) | Value::Null => Value::Null,
+        other => other,
+    };
+    let request: TemplateRequest = if request_value.is_null() {
+        TemplateRequest::default()
+    } else {
+        serde_json::from_value(request_value).map_err(|e| JsonRpcError {
+            code: ErrorCode::InvalidParams as i32,
+            message: format!("Invalid template request: {}", e),
+            data: None,
+        })?
+    };
+
+    match request.mode.as_deref() {
+        None | Some("template") => {
+            let template = service.get_block_template(&request).await.map_err(|e| JsonRpcError {
+                code: ErrorCode::ServerError as i32,
+                message: format!("Failed to get block template: {}", e),
+                data: None,
+            })?;
+
+            serde_json::to_value(template).map_err(|e| JsonRpcError {
+                code: ErrorCode::InternalError as i32,
+                message: format!("Failed to encode block template: {}", e),
+                data: None,
+            })
+        }
+        Some("proposal") => {
+            let data = request.data.as_deref().ok_or_else(|| JsonRpcError {
+                code: ErrorCode::InvalidParams as i32,
+                message: "Missing data for proposal mode".to_string(),
+                data: None,
+            })?;
+            let block = decode_block(data)?;
+
+            // BIP23: null when the block would be accepted, otherwise the reason
+            match service.propose_block(&block).await {
+                Ok(()) => Ok(Value::Null),
+                Err(rejection) => {
+                    debug!("Block proposal rejected: {}", rejection);
+                    Ok(Value::String(rejection.reason().to_string()))
+                }
+            }
+        }
+        Some(mode) => Err(JsonRpcError {
+            code: ErrorCode::InvalidParams as i32,
+            message: format!("Invalid mode: {}", mode),
+            data: None,
+        }),
+    }
 }
 
 /// Submit a mined block
@@ -614,6 +833,8 @@ async fn submit_block(
     params: Value,
     node: web::Data<Arc<Node>>,
 ) -> Result<Value, JsonRpcError> {
+    let service = block_template_service(&node)?;
+
     // Extract block data parameter
     let block_data = match params {
         Value::Array(arr) if !arr.is_empty() => {
@@ -632,41 +853,85 @@ async fn submit_block(
             data: None,
         }),
     };
-    
-    // Decode hex
-    let block_bytes = hex::decode(&block_data).map_err(|_| JsonRpcError {
+
+    let block = decode_block(&block_data)?;
+
+    // BIP22: null on acceptance, otherwise the rejection reason
+    match service.submit_block(block).await {
+        Ok(()) => Ok(Value::Null),
+        Err(rejection) => {
+            warn!("Submitted block rejected: {}", rejection);
+            Ok(Value::String(rejection.reason().to_string()))
+        }
+    }
+}
+
+/// Check an address against the node's network
+async fn validate_address(
+    params: Value,
+    node: web::Data<Arc<Node>>,
+) -> Result<Value, JsonRpcError> {
+    let address = match params {
+        Value::Array(arr) if !arr.is_empty() => match &arr[0] {
+            Value::String(s) => s.clone(),
+            _ => return Err(JsonRpcError {
+                code: ErrorCode::InvalidParams as i32,
+                message: "Invalid address parameter (must be a string)".to_string(),
+                data: None,
+            }),
+        },
+        _ => return Err(JsonRpcError {
+            code: ErrorCode::InvalidParams as i32,
+            message: "Missing address parameter".to_string(),
+            data: None,
+        }),
+    };
+
+    let network = node.config.node.environment.network_type();
+    match Address::parse_for_network(&address, network) {
+        Ok(parsed) => Ok(json!({
+            "isvalid": true,
+            "address": parsed.to_string(),
+            "scriptPubKey": hex::encode(parsed.script_pubkey()),
+            "keytype": parsed.key_type()
+                .map(|key_type| format!("{:?}", key_type).to_lowercase())
+                .unwrap_or_else(|| "multisig".to_string()),
+            "network": format!("{:?}", parsed.network()).to_lowercase(),
+            "quantumresistant": parsed.is_quantum_resistant(),
+        })),
+        Err(e) => Ok(json!({
+            "isvalid": false,
+            "error": e.to_string(),
+        })),
+    }
+}
+
+// Helper functions
+
+/// Block template service, or an error if mining RPCs are not enabled
+fn block_template_service(node: &web::Data<Arc<Node>>) -> Result<Arc<BlockTemplateService>, JsonRpcError> {
+    node.block_templates.clone().ok_or_else(|| JsonRpcError {
+        code: ErrorCode::ServerError as i32,
+        message: "Block template service is not available".to_string(),
+        data: None,
+    })
+}
+
+/// Decode a hex-encoded block
+fn decode_block(data: &str) -> Result<Block, JsonRpcError> {
+    let block_bytes = hex::decode(data).map_err(|_| JsonRpcError {
         code: ErrorCode::InvalidParams as i32,
         message: "Invalid block data format".to_string(),
         data: None,
     })?;
-    
-    // Deserialize block
-    let block = bincode::deserialize(&block_bytes).map_err(|_| JsonRpcError {
+
+    bincode::deserialize(&block_bytes).map_err(|_| JsonRpcError {
         code: ErrorCode::InvalidParams as i32,
         message: "Invalid block data".to_string(),
         data: None,
-    })?;
-    
-    // Submit block
-    let result = node.submit_block(block).await.map_err(|e| JsonRpcError {
-        code: ErrorCode::ServerError as i32,
-        message: format!("Failed to submit block: {}", e),
-        data: None,
-    })?;
-    
-    if result.accepted {
-        Ok(Value::Null)
-    } else {
-        Err(JsonRpcError {
-            code: ErrorCode::ServerError as i32,
-            message: result.reason.unwrap_or_else(|| "Block rejected".to_string()),
-            data: None,
-        })
-    }
+    })
 }
 
-// Helper functions
-
 /// Format transaction as JSON
 fn format_transaction(tx: &btclib::types::transaction::Transaction) -> Value {
     // Placeholder implementation - in a real implementation, this would format the transaction
@@ -681,12 +946,9 @@ fn format_transaction(tx: &btclib::types::transaction::Transaction) -> Value {
     })
 }
 
-/// Calculate difficulty from target
+/// Calculate difficulty from compact target bits, relative to the proof-of-work limit
 fn calculate_difficulty(target: u32) -> f64 {
-    // Placeholder implementation
-    // In a real implementation, this would calculate the actual difficulty
-    // based on the target difficulty bits
-    1.0
+    btclib::consensus::target_difficulty(target, &btclib::consensus::DifficultyParams::mainnet())
 }
 
 /// Get next block hash
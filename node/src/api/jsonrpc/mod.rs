@@ -4,19 +4,81 @@
 
 mod handlers;
 mod types;
+pub mod websocket;
 
-use actix_web::{web, HttpResponse, Responder, http::header};
+use actix_web::{web, HttpMessage, HttpRequest, HttpResponse, Responder, ResponseError, http::header};
 use serde_json::Value;
 use std::sync::Arc;
+use crate::api::middleware::auth::{self, Credential};
+use crate::api::middleware::permissions::{rpc_method_role, Role};
+use crate::api::middleware::rate_limiting::{self, RateLimitError, RateLimits};
 use crate::node::Node;
 use types::{JsonRpcRequest, JsonRpcResponse, JsonRpcError, ErrorCode};
 use crate::api::docs::openapi::JsonRpcDoc;
+use tracing::warn;
+
+/// Who is calling, as established by the authentication middleware
+#[derive(Debug, Clone)]
+struct Caller {
+    /// `None` when authentication is disabled
+    credential: Option<Credential>,
+    peer: String,
+    /// `None` when rate limiting is disabled
+    limits: Option<Arc<RateLimits>>,
+}
+
+impl Caller {
+    fn from_request(request: &HttpRequest) -> Self {
+        Self {
+            credential: request.extensions().get::<Credential>().cloned(),
+            peer: request.connection_info().peer_addr().unwrap_or("unknown").to_string(),
+            limits: request.app_data::<web::Data<RateLimits>>().map(|limits| limits.clone().into_inner()),
+        }
+    }
+
+    /// Charge the caller's rate limit for calling `methods`; `kind` labels the throttling metric
+    fn charge<'a>(&self, methods: impl IntoIterator<Item = &'a str>, kind: &'static str) -> Result<(), RateLimitError> {
+        let limits = match &self.limits {
+            Some(limits) => limits,
+            None => return Ok(()),
+        };
+        let cost = methods.into_iter().map(|method| limits.method_cost(method)).fold(0, u32::saturating_add);
+        let client = rate_limiting::client_key(self.credential.as_ref(), &self.peer);
+        limits.acquire(&client, cost).map_err(|retry_after| {
+            warn!("Rate limit exceeded for client {}", client);
+            rate_limiting::record_throttled(kind);
+            RateLimitError::new(retry_after)
+        })
+    }
+
+    /// Whether the caller may perform `action`, which requires `required`
+    fn permits(&self, required: Role, action: &str) -> bool {
+        self.credential
+            .as_ref()
+            .map_or(true, |credential| auth::authorize(credential, action, required, &self.peer))
+    }
+
+    /// Dispatch `method` if the caller's role permits it
+    async fn dispatch(&self, method: &str, params: Value, node: web::Data<Arc<Node>>) -> Result<Value, JsonRpcError> {
+        let required = rpc_method_role(method);
+        if !self.permits(required, &format!("RPC {}", method)) {
+            return Err(JsonRpcError {
+                code: ErrorCode::Forbidden as i32,
+                message: format!("Method '{}' requires the {} role", method, required),
+                data: None,
+            });
+        }
+        handlers::dispatch(method, params, node).await
+    }
+}
 
 /// JSON-RPC request handler
 pub async fn handle_jsonrpc(
+    http_request: HttpRequest,
     request: web::Json<JsonRpcRequest>,
     node: web::Data<Arc<Node>>,
 ) -> impl Responder {
+    let caller = Caller::from_request(&http_request);
     let req = request.into_inner();
     let id = req.id.clone();
     
@@ -30,8 +92,12 @@ pub async fn handle_jsonrpc(
         ));
     }
     
+    if let Err(e) = caller.charge([req.method.as_str()], "rpc") {
+        return e.error_response();
+    }
+    
     // Dispatch to appropriate method handler
-    let result = match handlers::dispatch(&req.method, req.params, node).await {
+    let result = match caller.dispatch(&req.method, req.params, node).await {
         Ok(result) => JsonRpcResponse::result(id, result),
         Err(e) => JsonRpcResponse::error(
             id,
@@ -46,9 +112,11 @@ pub async fn handle_jsonrpc(
 
 /// Batch JSON-RPC request handler
 pub async fn handle_jsonrpc_batch(
+    http_request: HttpRequest,
     requests: web::Json<Vec<JsonRpcRequest>>,
     node: web::Data<Arc<Node>>,
 ) -> impl Responder {
+    let caller = Caller::from_request(&http_request);
     if requests.is_empty() {
         return HttpResponse::Ok().json(JsonRpcResponse::error(
             Value::Null,
@@ -58,6 +126,21 @@ pub async fn handle_jsonrpc_batch(
         ));
     }
     
+    // A batch is charged for all its requests up front, and only up to a limited size
+    if let Some(limits) = &caller.limits {
+        if requests.len() > limits.max_batch_size() {
+            return HttpResponse::Ok().json(JsonRpcResponse::error(
+                Value::Null,
+                ErrorCode::InvalidRequest,
+                format!("Batch of {} requests exceeds the limit of {}", requests.len(), limits.max_batch_size()),
+                None,
+            ));
+        }
+    }
+    if let Err(e) = caller.charge(requests.iter().map(|req| req.method.as_str()), "batch") {
+        return e.error_response();
+    }
+    
     let mut responses = Vec::with_capacity(requests.len());
     
     for req in requests.iter() {
@@ -75,7 +158,7 @@ pub async fn handle_jsonrpc_batch(
         }
         
         // Dispatch to appropriate method handler
-        let result = match handlers::dispatch(&req.method, req.params.clone(), node.clone()).await {
+        let result = match caller.dispatch(&req.method, req.params.clone(), node.clone()).await {
             Ok(result) => JsonRpcResponse::result(id, result),
             Err(e) => JsonRpcResponse::error(
                 id,
@@ -161,6 +244,10 @@ pub fn configure(cfg: &mut web::ServiceConfig) {
         web::resource("/batch")
             .route(web::post().to(handle_jsonrpc_batch))
     )
+    .service(
+        web::resource("/ws")
+            .route(web::get().to(websocket::handle_websocket))
+    )
     .service(
         web::resource("/docs")
             .route(web::get().to(get_docs))
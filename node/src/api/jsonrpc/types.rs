@@ -133,4 +133,14 @@ pub enum ErrorCode {
     ///
     /// Error in network operations
     NetworkError = -32005,
+    
+    /// Forbidden (-32006)
+    ///
+    /// The caller's role does not permit the method
+    Forbidden = -32006,
+    
+    /// Rate limited (-32007)
+    ///
+    /// The caller exceeded its rate limit
+    RateLimited = -32007,
 } 
\ No newline at end of file
@@ -0,0 +1,328 @@
+//! JSON-RPC over WebSocket with event subscriptions
+//!
+//! A WebSocket connection accepts every method of the HTTP endpoint, plus
+//! `subscribe` and `unsubscribe`. `subscribe` takes a topic and returns a
+//! subscription id:
+//!
+//! - `newTip`: each block connected to the best chain
+//! - `reorg`: switches to another fork, with the disconnected and connected hashes
+//! - `mempool`: transactions entering and leaving the mempool
+//! - `address` (followed by an address): transactions paying the address, in
+//!   the mempool and in connected blocks
+//! - `lightning`: Lightning payments sent and invoices settled
+//!
+//! Notifications are requests without an id for the `subscription` method,
+//! carrying the subscription id and the result. A connection reading too
+//! slowly misses events instead of holding up the node, and is then sent a
+//! `lagged` notification with the number it missed so it can catch up with
+//! regular calls.
+
+use actix_web::{web, Error, HttpRequest, HttpResponse};
+use actix_ws::{Message, MessageStream, Session};
+use btclib::config::NetworkType;
+use btclib::types::address::Address;
+use btclib::types::transaction::Transaction;
+use futures::StreamExt;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::debug;
+use crate::api::middleware::permissions::Role;
+use crate::events::NodeEvent;
+use crate::node::Node;
+use super::Caller;
+use super::types::{ErrorCode, JsonRpcRequest, JsonRpcResponse};
+
+/// Most subscriptions a single connection may hold
+pub const MAX_SUBSCRIPTIONS: usize = 32;
+
+/// What a subscription listens to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Topic {
+    NewTip,
+    Reorg,
+    Mempool,
+    Address { address: String, script: Vec<u8> },
+    Lightning,
+}
+
+impl Topic {
+    /// Parse the `subscribe` parameters: a topic name, then an address for `address`
+    pub fn from_params(params: &Value, network: NetworkType) -> Result<Self, String> {
+        let name = params.get(0).and_then(Value::as_str).ok_or("Missing subscription topic")?;
+        match name {
+            "newTip" => Ok(Topic::NewTip),
+            "reorg" => Ok(Topic::Reorg),
+            "mempool" => Ok(Topic::Mempool),
+            "lightning" => Ok(Topic::Lightning),
+            "address" => {
+                let address = params.get(1).and_then(Value::as_str).ok_or("Missing address parameter")?;
+                let parsed = Address::parse_for_network(address, network)
+                    .map_err(|e| format!("Invalid address: {}", e))?;
+                Ok(Topic::Address { address: address.to_string(), script: parsed.script_pubkey() })
+            },
+            other => Err(format!("Unknown subscription topic '{}'", other)),
+        }
+    }
+
+    /// Role needed to subscribe; Lightning payments are wallet data
+    pub fn role(&self) -> Role {
+        match self {
+            Topic::Lightning => Role::Wallet,
+            _ => Role::ReadOnly,
+        }
+    }
+
+    /// Notification results `event` produces for this topic, usually none or one
+    pub fn notifications(&self, event: &NodeEvent) -> Vec<Value> {
+        match (self, event) {
+            (Topic::NewTip, NodeEvent::BlockConnected { block, height }) => vec![json!({
+                "hash": hex::encode(block.hash()),
+                "height": height,
+            })],
+            (Topic::Reorg, NodeEvent::Reorg { old_tip, new_tip, fork_height, disconnected, connected }) => vec![json!({
+                "oldtip": hex::encode(old_tip),
+                "newtip": hex::encode(new_tip),
+                "forkheight": fork_height,
+                "disconnected": disconnected.iter().map(hex::encode).collect::<Vec<_>>(),
+                "connected": connected.iter().map(hex::encode).collect::<Vec<_>>(),
+            })],
+            (Topic::Mempool, NodeEvent::MempoolAdded { transaction, fee, size }) => vec![json!({
+                "type": "added",
+                "txid": hex::encode(transaction.hash()),
+                "fee": fee,
+                "size": size,
+            })],
+            (Topic::Mempool, NodeEvent::MempoolRemoved { txid, reason }) => vec![json!({
+                "type": "removed",
+                "txid": hex::encode(txid),
+                "reason": reason.as_str(),
+            })],
+            (Topic::Address { address, script }, NodeEvent::MempoolAdded { transaction, .. }) => {
+                address_activity(address, script, transaction, None).into_iter().collect()
+            },
+            (Topic::Address { address, script }, NodeEvent::BlockConnected { block, height }) => block
+                .transactions()
+                .iter()
+                .filter_map(|tx| address_activity(address, script, tx, Some(*height)))
+                .collect(),
+            (Topic::Lightning, NodeEvent::LightningPaymentSent { payment_hash, amount_msat, fee_msat }) => vec![json!({
+                "type": "payment_sent",
+                "payment_hash": hex::encode(payment_hash),
+                "amount_msat": amount_msat,
+                "fee_msat": fee_msat,
+            })],
+            (Topic::Lightning, NodeEvent::LightningInvoiceSettled { payment_hash, amount_msat }) => vec![json!({
+                "type": "invoice_settled",
+                "payment_hash": hex::encode(payment_hash),
+                "amount_msat": amount_msat,
+            })],
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// What `tx` pays to `script`, if anything; `height` is `None` while unconfirmed
+fn address_activity(address: &str, script: &[u8], tx: &Transaction, height: Option<u64>) -> Option<Value> {
+    let received: Vec<(usize, u64)> = tx
+        .outputs()
+        .iter()
+        .enumerate()
+        .filter(|(_, output)| output.pub_key_script() == script)
+        .map(|(index, output)| (index, output.amount()))
+        .collect();
+    if received.is_empty() {
+        return None;
+    }
+    Some(json!({
+        "address": address,
+        "txid": hex::encode(tx.hash()),
+        "outputs": received.iter().map(|(index, _)| index).collect::<Vec<_>>(),
+        "amount": received.iter().map(|(_, amount)| amount).sum::<u64>(),
+        "height": height,
+    }))
+}
+
+/// Upgrade to a WebSocket speaking JSON-RPC, see the module documentation
+pub async fn handle_websocket(
+    req: HttpRequest,
+    body: web::Payload,
+    node: web::Data<Arc<Node>>,
+) -> Result<HttpResponse, Error> {
+    let caller = Caller::from_request(&req);
+    let (response, session, messages) = actix_ws::handle(&req, body)?;
+    actix_web::rt::spawn(run_session(session, messages, caller, node));
+    Ok(response)
+}
+
+/// Serve one connection until the client leaves or a send fails
+async fn run_session(mut session: Session, mut messages: MessageStream, caller: Caller, node: web::Data<Arc<Node>>) {
+    let mut events = node.events.subscribe();
+    let network = node.config.node.environment.network_type();
+    let mut subscriptions: HashMap<u64, Topic> = HashMap::new();
+    let mut next_id = 1u64;
+
+    'session: loop {
+        tokio::select! {
+            message = messages.next() => {
+                let text = match message {
+                    Some(Ok(Message::Text(text))) => text,
+                    Some(Ok(Message::Ping(bytes))) => {
+                        if session.pong(&bytes).await.is_err() {
+                            break;
+                        }
+                        continue;
+                    },
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                    Some(Ok(_)) => continue,
+                };
+                let response = handle_request(&text, &mut subscriptions, &mut next_id, network, &caller, &node).await;
+                let response = serde_json::to_string(&response).unwrap_or_default();
+                if session.text(response).await.is_err() {
+                    break;
+                }
+            },
+            event = events.recv() => {
+                let notifications = match event {
+                    Ok(event) => subscriptions
+                        .iter()
+                        .flat_map(|(id, topic)| topic.notifications(&event).into_iter().map(move |result| (*id, result)))
+                        .collect(),
+                    // Events were dropped while this connection fell behind; tell every subscription
+                    Err(RecvError::Lagged(missed)) => {
+                        debug!("WebSocket subscriber lagged by {} events", missed);
+                        subscriptions.keys().map(|id| (*id, json!({ "type": "lagged", "missed": missed }))).collect()
+                    },
+                    Err(RecvError::Closed) => break,
+                };
+                for (id, result) in notifications {
+                    if session.text(notification(id, result).to_string()).await.is_err() {
+                        break 'session;
+                    }
+                }
+            },
+        }
+    }
+    let _ = session.close(None).await;
+}
+
+/// Answer one request, handling subscriptions here and passing everything else to the handlers
+async fn handle_request(
+    text: &str,
+    subscriptions: &mut HashMap<u64, Topic>,
+    next_id: &mut u64,
+    network: NetworkType,
+    caller: &Caller,
+    node: &web::Data<Arc<Node>>,
+) -> JsonRpcResponse {
+    let request: JsonRpcRequest = match serde_json::from_str(text) {
+        Ok(request) => request,
+        Err(e) => return JsonRpcResponse::error(
+            Value::Null,
+            ErrorCode::ParseError,
+            format!("Parse error: {}", e),
+            None,
+        ),
+    };
+    let id = request.id.clone();
+    if request.jsonrpc != "2.0" {
+        return JsonRpcResponse::error(id, ErrorCode::InvalidRequest, "Invalid JSON-RPC version".to_string(), None);
+    }
+    if let Err(e) = caller.charge([request.method.as_str()], "rpc") {
+        return JsonRpcResponse::error(
+            id,
+            ErrorCode::RateLimited,
+            e.to_string(),
+            Some(json!({ "retry_after": e.retry_after_secs })),
+        );
+    }
+
+    match request.method.as_str() {
+        "subscribe" => {
+            if subscriptions.len() >= MAX_SUBSCRIPTIONS {
+                return JsonRpcResponse::error(
+                    id,
+                    ErrorCode::InvalidRequest,
+                    format!("At most {} subscriptions per connection", MAX_SUBSCRIPTIONS),
+                    None,
+                );
+            }
+            match Topic::from_params(&request.params, network) {
+                Ok(topic) if !caller.permits(topic.role(), &format!("subscribe {:?}", topic)) => JsonRpcResponse::error(
+                    id,
+                    ErrorCode::Forbidden,
+                    format!("This topic requires the {} role", topic.role()),
+                    None,
+                ),
+                Ok(topic) => {
+                    let subscription = *next_id;
+                    *next_id += 1;
+                    subscriptions.insert(subscription, topic);
+                    JsonRpcResponse::result(id, json!(subscription))
+                },
+                Err(message) => JsonRpcResponse::error(id, ErrorCode::InvalidParams, message, None),
+            }
+        },
+        "unsubscribe" => {
+            let removed = request.params.get(0)
+                .and_then(Value::as_u64)
+                .map_or(false, |subscription| subscriptions.remove(&subscription).is_some());
+            JsonRpcResponse::result(id, json!(removed))
+        },
+        method => match caller.dispatch(method, request.params, node.clone()).await {
+            Ok(result) => JsonRpcResponse::result(id, result),
+            Err(e) => JsonRpcResponse {
+                jsonrpc: "2.0".to_string(),
+                result: None,
+                error: Some(e),
+                id,
+            },
+        },
+    }
+}
+
+/// Notification delivering `result` to subscription `id`
+fn notification(id: u64, result: Value) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "method": "subscription",
+        "params": { "subscription": id, "result": result },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::RemovalReason;
+    use btclib::types::transaction::{TransactionInput, TransactionOutput};
+
+    #[test]
+    fn test_topics_select_their_events() {
+        assert_eq!(Topic::from_params(&json!(["mempool"]), NetworkType::Testnet), Ok(Topic::Mempool));
+        assert!(Topic::from_params(&json!(["address"]), NetworkType::Testnet).is_err());
+        assert!(Topic::from_params(&json!(["blocks"]), NetworkType::Testnet).is_err());
+
+        let script = vec![0x76, 0xa9, 0x14];
+        let paying = Arc::new(Transaction::new(
+            1,
+            vec![TransactionInput::new([1u8; 32], 0, vec![], 0)],
+            vec![TransactionOutput::new(700, vec![]), TransactionOutput::new(300, script.clone())],
+            0,
+        ));
+        let added = NodeEvent::MempoolAdded { transaction: paying.clone(), fee: 100, size: 200 };
+        let removed = NodeEvent::MempoolRemoved { txid: paying.hash(), reason: RemovalReason::Evicted };
+
+        let address = Topic::Address { address: "addr".to_string(), script };
+        let activity = address.notifications(&added);
+        assert_eq!(activity.len(), 1);
+        assert_eq!(activity[0]["amount"], json!(300));
+        assert_eq!(activity[0]["outputs"], json!([1]));
+        assert_eq!(activity[0]["height"], Value::Null);
+        assert!(address.notifications(&removed).is_empty());
+
+        assert_eq!(Topic::Mempool.notifications(&removed)[0]["reason"], json!("evicted"));
+        assert!(Topic::NewTip.notifications(&added).is_empty());
+        assert!(Topic::Lightning.notifications(&removed).is_empty());
+    }
+}
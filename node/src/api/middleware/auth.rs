@@ -1,29 +1,194 @@
 //! API authentication middleware
 //!
-//! This module provides API key authentication for the SuperNova API.
+//! Callers present a key as `Authorization: Bearer <key>`. Keys are looked up
+//! by their SHA-256 hash, so the configuration only needs to hold hashes (see
+//! [`ApiToken`]), and each maps to a [`Role`]. REST routes are checked against
+//! [`rest_route_role`] here; JSON-RPC requests only need a valid key at this
+//! point and are checked per method by the JSON-RPC handlers, which find the
+//! caller's [`Credential`] in the request extensions. Calls needing more than
+//! read-only access are recorded under the [`AUDIT_TARGET`] log target.
+//!
+//! Local clients can authenticate with a cookie file: a random secret written
+//! at startup, readable only by the node's user, that grants [`Role::Admin`].
+//! Over mutual TLS, a client certificate listed in the TLS settings
+//! authenticates its holder without a key.
 
+use std::collections::HashMap;
+use std::fs;
 use std::future::{ready, Ready};
+use std::io::Write;
+use std::path::Path;
 use std::rc::Rc;
 use std::task::{Context, Poll};
 use actix_web::{
     dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
-    error::ErrorUnauthorized,
+    error::{ErrorForbidden, ErrorUnauthorized},
     http::header,
-    Error,
+    Error, HttpMessage,
 };
-use tracing::{debug, warn};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+use tracing::{debug, info, warn};
+use super::permissions::{rest_route_role, Role};
+use crate::api::tls::{ClientCertRole, ClientCertificate};
+
+/// Log target of the audit records for privileged calls
+pub const AUDIT_TARGET: &str = "supernova::audit";
+
+/// User name before the secret in the cookie file
+pub const COOKIE_USER: &str = "__cookie__";
+
+/// Error in the authentication settings
+#[derive(Debug, Error)]
+pub enum AuthConfigError {
+    #[error("API token '{0}' has a malformed key hash, expected 64 hex characters")]
+    MalformedKeyHash(String),
+
+    #[error("Client certificate '{0}' has a malformed fingerprint, expected 64 hex characters")]
+    MalformedFingerprint(String),
+
+    #[error("Failed to write cookie file: {0}")]
+    Cookie(#[from] std::io::Error),
+}
+
+/// A scoped API key as configured
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ApiToken {
+    /// Name identifying the token in the audit log
+    pub name: String,
+    /// Hex SHA-256 of the key, see [`ApiToken::hash_key`]
+    pub key_hash: String,
+    /// What the token may do
+    pub role: Role,
+}
+
+impl ApiToken {
+    /// Hex SHA-256 of `key`, the form keys are stored in the configuration
+    pub fn hash_key(key: &str) -> String {
+        hex::encode(Sha256::digest(key.as_bytes()))
+    }
+
+    fn digest(&self) -> Result<[u8; 32], AuthConfigError> {
+        decode_digest(&self.key_hash).ok_or_else(|| AuthConfigError::MalformedKeyHash(self.name.clone()))
+    }
+}
+
+fn decode_digest(digest: &str) -> Option<[u8; 32]> {
+    hex::decode(digest).ok().and_then(|bytes| <[u8; 32]>::try_from(bytes).ok())
+}
+
+/// An authenticated caller, left in the request extensions for handlers
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Credential {
+    pub name: String,
+    pub role: Role,
+}
+
+/// Whether `credential` may perform `action`, which requires `required`
+///
+/// Anything requiring more than [`Role::ReadOnly`] is written to the audit
+/// log, whether it is allowed or not.
+pub fn authorize(credential: &Credential, action: &str, required: Role, peer: &str) -> bool {
+    let allowed = credential.role.grants(required);
+    if required != Role::ReadOnly {
+        if allowed {
+            info!(target: AUDIT_TARGET, "{} by '{}' ({}) from {} allowed", action, credential.name, credential.role, peer);
+        } else {
+            warn!(target: AUDIT_TARGET, "{} by '{}' ({}) from {} denied, requires {}",
+                  action, credential.name, credential.role, peer, required);
+        }
+    }
+    allowed
+}
+
+/// Write a fresh cookie file at `path` and return its secret
+///
+/// The file holds `__cookie__:<secret>`; clients send the secret as a bearer key.
+pub fn write_cookie_file(path: &Path) -> Result<String, AuthConfigError> {
+    let secret = hex::encode(rand::random::<[u8; 32]>());
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(path)?;
+    write!(file, "{}:{}", COOKIE_USER, secret)?;
+    Ok(secret)
+}
 
 /// API authentication middleware
+#[derive(Debug, Clone)]
 pub struct ApiAuth {
-    api_keys: Rc<Vec<String>>,
+    /// By SHA-256 of the key
+    credentials: HashMap<[u8; 32], Credential>,
+    /// By SHA-256 of the client certificate
+    certificates: HashMap<[u8; 32], Credential>,
+    jsonrpc_path: Option<String>,
 }
 
 impl ApiAuth {
     /// Create a new authentication middleware with the given API keys
+    ///
+    /// These plain keys predate scoped tokens and grant [`Role::Admin`].
     pub fn new(api_keys: Vec<String>) -> Self {
+        let credentials = api_keys
+            .iter()
+            .enumerate()
+            .map(|(index, key)| {
+                let credential = Credential { name: format!("api-key-{}", index), role: Role::Admin };
+                (Sha256::digest(key.as_bytes()).into(), credential)
+            })
+            .collect();
         Self {
-            api_keys: Rc::new(api_keys),
+            credentials,
+            certificates: HashMap::new(),
+            jsonrpc_path: None,
+        }
+    }
+
+    /// Also accept the given scoped tokens
+    pub fn with_tokens(mut self, tokens: &[ApiToken]) -> Result<Self, AuthConfigError> {
+        for token in tokens {
+            let credential = Credential { name: token.name.clone(), role: token.role };
+            self.credentials.insert(token.digest()?, credential);
         }
+        Ok(self)
+    }
+
+    /// Also accept the given client certificates on mutual TLS connections
+    pub fn with_client_certificates(mut self, clients: &[ClientCertRole]) -> Result<Self, AuthConfigError> {
+        for client in clients {
+            let digest = decode_digest(&client.fingerprint)
+                .ok_or_else(|| AuthConfigError::MalformedFingerprint(client.name.clone()))?;
+            self.certificates.insert(digest, Credential { name: client.name.clone(), role: client.role });
+        }
+        Ok(self)
+    }
+
+    /// Also accept the secret of a cookie file, see [`write_cookie_file`]
+    pub fn with_cookie(mut self, secret: &str) -> Self {
+        let credential = Credential { name: COOKIE_USER.to_string(), role: Role::Admin };
+        self.credentials.insert(Sha256::digest(secret.as_bytes()).into(), credential);
+        self
+    }
+
+    /// Leave authorization of requests under `path` to the JSON-RPC handlers
+    pub fn with_jsonrpc_path(mut self, path: &str) -> Self {
+        self.jsonrpc_path = Some(path.to_string());
+        self
+    }
+
+    fn lookup(&self, key: &str) -> Option<&Credential> {
+        let digest: [u8; 32] = Sha256::digest(key.as_bytes()).into();
+        self.credentials.get(&digest)
+    }
+
+    fn lookup_certificate(&self, certificate: &ClientCertificate) -> Option<&Credential> {
+        let digest: [u8; 32] = Sha256::digest(&certificate.0).into();
+        self.certificates.get(&digest)
     }
 }
 
@@ -42,7 +207,7 @@ where
     fn new_transform(&self, service: S) -> Self::Future {
         ready(Ok(ApiAuthMiddleware {
             service,
-            api_keys: self.api_keys.clone(),
+            auth: Rc::new(self.clone()),
         }))
     }
 }
@@ -50,7 +215,7 @@ where
 /// API authentication middleware service
 pub struct ApiAuthMiddleware<S> {
     service: S,
-    api_keys: Rc<Vec<String>>,
+    auth: Rc<ApiAuth>,
 }
 
 impl<S, B> Service<ServiceRequest> for ApiAuthMiddleware<S>
@@ -84,50 +249,54 @@ where
             });
         }
 
-        // Extract API key from Authorization header
-        let auth_header = req.headers().get(header::AUTHORIZATION);
-        
-        // Check if API key is valid
-        let is_authorized = match auth_header {
-            Some(auth) => {
-                if let Ok(auth_str) = auth.to_str() {
-                    // Support "Bearer <token>" format for API keys
-                    let api_key = if auth_str.starts_with("Bearer ") {
-                        auth_str.strip_prefix("Bearer ").unwrap_or(auth_str)
-                    } else {
-                        auth_str
-                    };
-                    
-                    if self.api_keys.is_empty() {
-                        // If no API keys configured, authentication is disabled
-                        true
-                    } else {
-                        self.api_keys.contains(&api_key.to_string())
-                    }
-                } else {
-                    false
-                }
-            }
-            None => false,
+        // Extract API key from Authorization header, supporting the "Bearer <token>" format
+        let api_key = req.headers()
+            .get(header::AUTHORIZATION)
+            .and_then(|auth| auth.to_str().ok())
+            .map(|auth_str| auth_str.strip_prefix("Bearer ").unwrap_or(auth_str).to_string());
+        let peer = req.connection_info().peer_addr().unwrap_or("unknown").to_string();
+
+        // A client certificate mapped to a role stands in for a key
+        let certified = req.conn_data::<ClientCertificate>()
+            .and_then(|certificate| self.auth.lookup_certificate(certificate))
+            .cloned();
+        let credential = match (certified, api_key) {
+            (Some(credential), _) => Some(credential),
+            // If no API keys configured, authentication is disabled
+            (None, Some(_)) if self.auth.credentials.is_empty() && self.auth.certificates.is_empty() => None,
+            (None, Some(api_key)) => match self.auth.lookup(&api_key) {
+                Some(credential) => Some(credential.clone()),
+                None => {
+                    warn!("Unauthorized API access attempt from {}", peer);
+                    return Box::pin(async move { Err(ErrorUnauthorized("Invalid API key")) });
+                },
+            },
+            (None, None) => {
+                warn!("Unauthorized API access attempt from {}", peer);
+                return Box::pin(async move { Err(ErrorUnauthorized("Invalid API key")) });
+            },
         };
 
-        if is_authorized {
-            let fut = self.service.call(req);
-            Box::pin(async move {
-                let res = fut.await?;
-                Ok(res)
-            })
-        } else {
-            // Log unauthorized access attempt
-            warn!(
-                "Unauthorized API access attempt from {}",
-                req.connection_info().peer_addr().unwrap_or("unknown")
-            );
-            
-            Box::pin(async move {
-                Err(ErrorUnauthorized("Invalid API key"))
-            })
+        if let Some(credential) = credential {
+            let is_jsonrpc = self.auth.jsonrpc_path.as_deref().map_or(false, |path| req.path().starts_with(path));
+            if !is_jsonrpc {
+                let required = rest_route_role(req.method(), req.path());
+                let action = format!("{} {}", req.method(), req.path());
+                if !authorize(&credential, &action, required, &peer) {
+                    return Box::pin(async move {
+                        Err(ErrorForbidden(format!("This endpoint requires the {} role", required)))
+                    });
+                }
+            }
+            debug!("Authenticated API request from '{}' ({})", credential.name, credential.role);
+            req.extensions_mut().insert(credential);
         }
+
+        let fut = self.service.call(req);
+        Box::pin(async move {
+            let res = fut.await?;
+            Ok(res)
+        })
     }
 }
 
@@ -196,4 +365,47 @@ mod tests {
         let resp = call_service(&app, req).await;
         assert_eq!(resp.status(), StatusCode::OK);
     }
+
+    #[actix_web::test]
+    async fn test_auth_middleware_scoped_tokens() {
+        let tokens = vec![ApiToken {
+            name: "explorer".to_string(),
+            key_hash: ApiToken::hash_key("read-key"),
+            role: Role::ReadOnly,
+        }];
+        let app = init_service(
+            App::new()
+                .wrap(ApiAuth::new(vec![]).with_tokens(&tokens).unwrap().with_jsonrpc_path("/rpc"))
+                .route("/api/v1/blockchain/info", web::get().to(test_handler))
+                .route("/api/v1/wallet/send", web::post().to(test_handler))
+                .route("/rpc", web::post().to(test_handler)),
+        )
+        .await;
+
+        let request = |req: TestRequest| req.insert_header((header::AUTHORIZATION, "Bearer read-key")).to_request();
+        let resp = call_service(&app, request(TestRequest::get().uri("/api/v1/blockchain/info"))).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let resp = call_service(&app, request(TestRequest::post().uri("/api/v1/wallet/send"))).await;
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+
+        // JSON-RPC methods are checked by the handlers
+        let resp = call_service(&app, request(TestRequest::post().uri("/rpc"))).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        let malformed = ApiToken { key_hash: "abcd".to_string(), ..tokens[0].clone() };
+        assert!(matches!(
+            ApiAuth::new(vec![]).with_tokens(&[malformed]),
+            Err(AuthConfigError::MalformedKeyHash(name)) if name == "explorer"
+        ));
+    }
+
+    #[test]
+    fn test_cookie_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(".cookie");
+        let secret = write_cookie_file(&path).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), format!("{}:{}", COOKIE_USER, secret));
+        assert_eq!(ApiAuth::new(vec![]).with_cookie(&secret).lookup(&secret).unwrap().role, Role::Admin);
+        assert_ne!(write_cookie_file(&path).unwrap(), secret);
+    }
 } 
\ No newline at end of file
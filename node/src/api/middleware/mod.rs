@@ -1,13 +1,15 @@
 //! API middleware components
 //!
 //! This module contains middleware components used by the SuperNova API,
-//! including authentication, rate limiting, and request logging.
+//! including authentication and role-based authorization, rate limiting, and request logging.
 
 pub mod auth;
+pub mod permissions;
 pub mod rate_limiting;
 pub mod logging;
 
 // Re-export middleware components
-pub use auth::ApiAuth;
-pub use rate_limiting::RateLimiter;
+pub use auth::{ApiAuth, ApiToken, Credential};
+pub use permissions::Role;
+pub use rate_limiting::{RateLimitCosts, RateLimiter, RateLimits};
 pub use logging::ApiLogger; 
\ No newline at end of file
@@ -0,0 +1,137 @@
+//! API roles and the permission maps for REST routes and JSON-RPC methods
+//!
+//! Every credential carries one [`Role`]. Reading public chain data needs no
+//! more than [`Role::ReadOnly`]; spending and broadcasting need
+//! [`Role::Wallet`], block production [`Role::Mining`], and node
+//! administration [`Role::Admin`], which also grants everything else.
+//! Methods and routes missing from the maps require `Admin`, so new
+//! endpoints stay closed until they are classified here.
+
+use actix_web::http::Method;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// What a credential is allowed to do
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    /// Query chain, mempool and network state
+    ReadOnly,
+    /// Also use the wallet and Lightning, and broadcast transactions
+    Wallet,
+    /// Also fetch block templates and submit blocks
+    Mining,
+    /// Everything, including node configuration and mempool management
+    Admin,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::ReadOnly => "readonly",
+            Role::Wallet => "wallet",
+            Role::Mining => "mining",
+            Role::Admin => "admin",
+        }
+    }
+
+    /// Whether this role may use an endpoint requiring `required`
+    pub fn grants(self, required: Role) -> bool {
+        self == Role::Admin || required == Role::ReadOnly || self == required
+    }
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Role needed to call JSON-RPC `method`
+pub fn rpc_method_role(method: &str) -> Role {
+    match method {
+        "getinfo"
+        | "getblockchaininfo"
+        | "getblock"
+        | "getblockhash"
+        | "getbestblockhash"
+        | "getblockcount"
+        | "getdifficulty"
+        | "gettransaction"
+        | "getrawtransaction"
+        | "getmempoolinfo"
+        | "getrawmempool"
+        | "estimatesmartfee"
+        | "getnetworkinfo"
+        | "getpeerinfo"
+        | "getmininginfo"
+        | "validateaddress"
+        | "subscribe"
+        | "unsubscribe" => Role::ReadOnly,
+        "sendrawtransaction" | "submitpackage" => Role::Wallet,
+        "getblocktemplate" | "submitblock" => Role::Mining,
+        _ => Role::Admin,
+    }
+}
+
+/// Role needed to call REST route `path` with `method`
+///
+/// `path` is the full request path, e.g. `/api/v1/wallet/balance`.
+pub fn rest_route_role(method: &Method, path: &str) -> Role {
+    let route = path.strip_prefix("/api/v1").unwrap_or(path);
+    let mut segments = route.trim_start_matches('/').splitn(2, '/');
+    let (group, rest) = (segments.next().unwrap_or(""), segments.next().unwrap_or(""));
+    let read = method == Method::GET || method == Method::HEAD;
+
+    match (group, rest, read) {
+        ("blockchain", _, true) | ("mempool", _, true) | ("network", _, true) | ("environmental", _, true) => Role::ReadOnly,
+        ("mempool", "validate_transaction", false) | ("wallet", "verify", false) => Role::ReadOnly,
+        ("blockchain", "tx", false) | ("mempool", "transaction", false) => Role::Wallet,
+        ("wallet", _, _) => Role::Wallet,
+        ("lightning", "info" | "channels" | "nodes" | "routes", true) => Role::ReadOnly,
+        ("lightning", rest, true) if rest.starts_with("channel/") || rest.starts_with("node/") => Role::ReadOnly,
+        ("lightning", _, _) => Role::Wallet,
+        ("mining", "template" | "config", true) => Role::Mining,
+        ("mining", _, true) => Role::ReadOnly,
+        ("mining", "submit" | "start" | "stop" | "config", false) => Role::Mining,
+        ("node", "info" | "status" | "version" | "metrics", true) => Role::ReadOnly,
+        _ => Role::Admin,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_roles_and_permission_maps() {
+        assert!(Role::Admin.grants(Role::Mining));
+        assert!(Role::Wallet.grants(Role::ReadOnly));
+        assert!(!Role::Wallet.grants(Role::Mining));
+        assert!(!Role::ReadOnly.grants(Role::Wallet));
+
+        assert_eq!(rpc_method_role("getblockcount"), Role::ReadOnly);
+        assert_eq!(rpc_method_role("sendrawtransaction"), Role::Wallet);
+        assert_eq!(rpc_method_role("submitblock"), Role::Mining);
+        assert_eq!(rpc_method_role("savemempool"), Role::Admin);
+        assert_eq!(rpc_method_role("notamethod"), Role::Admin);
+
+        assert_eq!(rest_route_role(&Method::GET, "/api/v1/blockchain/info"), Role::ReadOnly);
+        assert_eq!(rest_route_role(&Method::POST, "/api/v1/blockchain/tx"), Role::Wallet);
+        assert_eq!(rest_route_role(&Method::GET, "/api/v1/wallet/balance"), Role::Wallet);
+        assert_eq!(rest_route_role(&Method::GET, "/api/v1/lightning/channel/abc"), Role::ReadOnly);
+        assert_eq!(rest_route_role(&Method::GET, "/api/v1/lightning/invoices"), Role::Wallet);
+        assert_eq!(rest_route_role(&Method::GET, "/api/v1/mining/template"), Role::Mining);
+        assert_eq!(rest_route_role(&Method::POST, "/api/v1/mining/submit"), Role::Mining);
+        assert_eq!(rest_route_role(&Method::GET, "/api/v1/node/logs"), Role::Admin);
+        assert_eq!(rest_route_role(&Method::POST, "/api/v1/node/shutdown"), Role::Admin);
+        assert_eq!(rest_route_role(&Method::PUT, "/api/v1/environmental/settings"), Role::Admin);
+    }
+
+    #[test]
+    fn test_role_names_in_config() {
+        let role: Role = serde_json::from_str("\"readonly\"").unwrap();
+        assert_eq!(role, Role::ReadOnly);
+        assert_eq!(serde_json::to_string(&Role::Mining).unwrap(), "\"mining\"");
+    }
+}
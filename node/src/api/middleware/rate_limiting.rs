@@ -1,35 +1,53 @@
 //! API rate limiting middleware
 //!
-//! This module provides rate limiting functionality for the SuperNova API.
+//! Each client has a token bucket holding up to one minute's worth of
+//! requests and refilling continuously. A request takes tokens according to
+//! its cost, so expensive calls such as fetching a whole block use up the
+//! allowance faster than cheap ones. Clients are told by their API
+//! credential when they have one and by IP address otherwise.
+//!
+//! REST requests are charged here by route. JSON-RPC requests are charged by
+//! the JSON-RPC handlers, which know the methods called, including each
+//! element of a batch; batches are also capped in size. Throttled requests
+//! get `429 Too Many Requests` with a `Retry-After` header and are counted in
+//! the `api_throttled_requests_total` metric.
 
 use std::collections::HashMap;
 use std::future::{ready, Ready};
-use std::rc::Rc;
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex};
 use std::task::{Context, Poll};
 use std::time::{Duration, Instant};
 use actix_web::{
     dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
     http::StatusCode,
-    HttpResponse, ResponseError,
+    HttpMessage, HttpResponse, ResponseError,
 };
+use serde::{Deserialize, Serialize};
 use tracing::{debug, warn};
 use serde_json::json;
+use super::auth::Credential;
+
+/// Buckets kept before idle, full ones are dropped
+const MAX_IDLE_BUCKETS: usize = 10_000;
 
 /// Rate limit error
 #[derive(Debug)]
 pub struct RateLimitError {
-    pub rate: u32,
-    pub window_secs: u64,
+    /// Seconds until the request would be allowed
+    pub retry_after_secs: u64,
+}
+
+impl RateLimitError {
+    pub fn new(retry_after: Duration) -> Self {
+        Self {
+            retry_after_secs: retry_after.as_secs_f64().ceil().max(1.0) as u64,
+        }
+    }
 }
 
 impl std::fmt::Display for RateLimitError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "Rate limit exceeded: {} requests per {} seconds",
-            self.rate, self.window_secs
-        )
+        write!(f, "Rate limit exceeded, retry in {} seconds", self.retry_after_secs)
     }
 }
 
@@ -41,61 +59,188 @@ impl ResponseError for RateLimitError {
     fn error_response(&self) -> HttpResponse {
         // Include Retry-After header
         let mut res = HttpResponse::TooManyRequests();
-        res.insert_header(("Retry-After", self.window_secs.to_string()));
+        res.insert_header(("Retry-After", self.retry_after_secs.to_string()));
         
         res.json(json!({
             "success": false,
-            "error": format!("Rate limit exceeded: {} requests per {} seconds", self.rate, self.window_secs),
-            "retry_after": self.window_secs
+            "error": self.to_string(),
+            "retry_after": self.retry_after_secs
         }))
     }
 }
 
-/// Entry in the rate limiter store
-struct RateLimitEntry {
-    /// Timestamp of first request in current window
-    first_request: Instant,
-    /// Number of requests in current window
-    count: u32,
+/// What requests cost against a client's allowance
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RateLimitCosts {
+    /// Cost of a request no rule below covers
+    pub default_cost: u32,
+    /// Costs of REST routes by path prefix, the longest matching prefix applying
+    pub routes: HashMap<String, u32>,
+    /// Costs of JSON-RPC methods
+    pub methods: HashMap<String, u32>,
+    /// Most requests a JSON-RPC batch may hold
+    pub max_batch_size: usize,
+}
+
+impl Default for RateLimitCosts {
+    fn default() -> Self {
+        let routes = [
+            ("/api/v1/blockchain/block", 10),
+            ("/api/v1/mempool/transactions", 5),
+            ("/api/v1/wallet/transactions", 5),
+            ("/api/v1/wallet/utxos", 5),
+            ("/api/v1/mining/template", 5),
+            ("/api/v1/node/logs", 10),
+        ];
+        let methods = [
+            ("getblock", 10),
+            ("getrawmempool", 5),
+            ("getrawtransaction", 2),
+            ("gettransaction", 2),
+            ("getblocktemplate", 5),
+            ("submitblock", 5),
+            ("submitpackage", 5),
+            ("savemempool", 20),
+            ("loadmempool", 20),
+        ];
+        Self {
+            default_cost: 1,
+            routes: routes.iter().map(|(route, cost)| (route.to_string(), *cost)).collect(),
+            methods: methods.iter().map(|(method, cost)| (method.to_string(), *cost)).collect(),
+            max_batch_size: 100,
+        }
+    }
+}
+
+/// A client's remaining allowance
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// Token buckets of every client, shared by all server workers
+#[derive(Debug)]
+pub struct RateLimits {
+    /// Tokens a full bucket holds
+    capacity: f64,
+    /// Tokens added per second
+    refill_rate: f64,
+    costs: RateLimitCosts,
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+impl RateLimits {
+    /// Allow `rate` cost units per `window`, all of which may be used at once
+    pub fn new(rate: u32, window: Duration, costs: RateLimitCosts) -> Self {
+        Self {
+            capacity: rate as f64,
+            refill_rate: rate as f64 / window.as_secs_f64().max(f64::EPSILON),
+            costs,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Cost of REST request `path`
+    pub fn route_cost(&self, path: &str) -> u32 {
+        self.costs
+            .routes
+            .iter()
+            .filter(|(route, _)| path.starts_with(route.as_str()))
+            .max_by_key(|(route, _)| route.len())
+            .map_or(self.costs.default_cost, |(_, cost)| *cost)
+    }
+
+    /// Cost of JSON-RPC `method`
+    pub fn method_cost(&self, method: &str) -> u32 {
+        self.costs.methods.get(method).copied().unwrap_or(self.costs.default_cost)
+    }
+
+    /// Most requests a JSON-RPC batch may hold
+    pub fn max_batch_size(&self) -> usize {
+        self.costs.max_batch_size
+    }
+
+    /// Take `cost` tokens from `client`'s bucket, or return how long until it will hold them
+    ///
+    /// A cost above the bucket's capacity is charged as a full bucket.
+    pub fn acquire(&self, client: &str, cost: u32) -> Result<(), Duration> {
+        let now = Instant::now();
+        let cost = (cost as f64).min(self.capacity);
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+
+        if buckets.len() >= MAX_IDLE_BUCKETS {
+            let (capacity, refill_rate) = (self.capacity, self.refill_rate);
+            buckets.retain(|_, bucket| {
+                bucket.tokens + now.duration_since(bucket.updated).as_secs_f64() * refill_rate < capacity
+            });
+        }
+
+        let bucket = buckets.entry(client.to_string()).or_insert_with(|| Bucket {
+            tokens: self.capacity,
+            updated: now,
+        });
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.refill_rate).min(self.capacity);
+        bucket.updated = now;
+
+        if bucket.tokens >= cost {
+            bucket.tokens -= cost;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((cost - bucket.tokens) / self.refill_rate))
+        }
+    }
+}
+
+/// Key of the bucket charged for a request
+pub fn client_key(credential: Option<&Credential>, peer: &str) -> String {
+    match credential {
+        Some(credential) => format!("key:{}", credential.name),
+        None => format!("ip:{}", peer),
+    }
 }
 
-/// Rate limiter state shared between requests
-struct RateLimiterState {
-    /// Request counts by client IP
-    clients: Mutex<HashMap<String, RateLimitEntry>>,
-    /// Maximum requests per window
-    rate: u32,
-    /// Window duration in seconds
-    window_secs: u64,
+/// Count a throttled request of the given kind (`rest`, `rpc` or `batch`)
+pub fn record_throttled(kind: &'static str) {
+    metrics::counter!("api_throttled_requests_total", "kind" => kind).increment(1);
 }
 
 /// Rate limiting middleware
 pub struct RateLimiter {
-    state: Rc<RateLimiterState>,
+    limits: Arc<RateLimits>,
+    jsonrpc_path: Option<String>,
 }
 
 impl RateLimiter {
     /// Create a new rate limiter with the given rate (requests per minute)
     pub fn new(rate: u32) -> Self {
-        Self {
-            state: Rc::new(RateLimiterState {
-                clients: Mutex::new(HashMap::new()),
-                rate,
-                window_secs: 60, // 1 minute window
-            }),
-        }
+        Self::with_window(rate, 60)
     }
     
     /// Create a new rate limiter with custom window duration
     pub fn with_window(rate: u32, window_secs: u64) -> Self {
+        Self::with_limits(Arc::new(RateLimits::new(
+            rate,
+            Duration::from_secs(window_secs),
+            RateLimitCosts::default(),
+        )))
+    }
+
+    /// Create a rate limiter charging the given, possibly shared, buckets
+    pub fn with_limits(limits: Arc<RateLimits>) -> Self {
         Self {
-            state: Rc::new(RateLimiterState {
-                clients: Mutex::new(HashMap::new()),
-                rate,
-                window_secs,
-            }),
+            limits,
+            jsonrpc_path: None,
         }
     }
+
+    /// Leave requests under `path` to be charged by the JSON-RPC handlers
+    pub fn with_jsonrpc_path(mut self, path: &str) -> Self {
+        self.jsonrpc_path = Some(path.to_string());
+        self
+    }
 }
 
 impl<S, B> Transform<S, ServiceRequest> for RateLimiter
@@ -113,7 +258,8 @@ where
     fn new_transform(&self, service: S) -> Self::Future {
         ready(Ok(RateLimiterMiddleware {
             service,
-            state: self.state.clone(),
+            limits: self.limits.clone(),
+            jsonrpc_path: self.jsonrpc_path.clone(),
         }))
     }
 }
@@ -121,7 +267,8 @@ where
 /// Rate limiting middleware service
 pub struct RateLimiterMiddleware<S> {
     service: S,
-    state: Rc<RateLimiterState>,
+    limits: Arc<RateLimits>,
+    jsonrpc_path: Option<String>,
 }
 
 impl<S, B> Service<ServiceRequest> for RateLimiterMiddleware<S>
@@ -146,15 +293,9 @@ where
             });
         }
 
-        // Get client IP address
-        let ip = req
-            .connection_info()
-            .peer_addr()
-            .unwrap_or("unknown")
-            .to_string();
-            
-        // Skip rate limiting for documentation routes
-        if req.path().starts_with("/swagger-ui") || req.path().starts_with("/api-docs") {
+        // Skip rate limiting for documentation routes, and JSON-RPC which is charged per method
+        let is_jsonrpc = self.jsonrpc_path.as_deref().map_or(false, |path| req.path().starts_with(path));
+        if is_jsonrpc || req.path().starts_with("/swagger-ui") || req.path().starts_with("/api-docs") {
             let fut = self.service.call(req);
             return Box::pin(async move {
                 let res = fut.await?;
@@ -162,41 +303,18 @@ where
             });
         }
 
-        // Check rate limit
-        let now = Instant::now();
-        let window = Duration::from_secs(self.state.window_secs);
-        let mut clients = self.state.clients.lock().unwrap();
-        
-        // Clean up expired entries
-        clients.retain(|_, entry| {
-            now.duration_since(entry.first_request) < window
-        });
-        
-        // Get current client's entry
-        let entry = clients.entry(ip.clone()).or_insert_with(|| RateLimitEntry {
-            first_request: now,
-            count: 0,
-        });
+        // Charge the client identified by its credential, or its IP address without one
+        let peer = req.connection_info().peer_addr().unwrap_or("unknown").to_string();
+        let client = client_key(req.extensions().get::<Credential>(), &peer);
+        let cost = self.limits.route_cost(req.path());
 
-        // Reset entry if window has expired
-        if now.duration_since(entry.first_request) >= window {
-            entry.first_request = now;
-            entry.count = 0;
-        }
-
-        // Check if rate limit exceeded
-        if entry.count >= self.state.rate {
-            warn!("Rate limit exceeded for client IP {}", ip);
-            let error = RateLimitError {
-                rate: self.state.rate,
-                window_secs: self.state.window_secs,
-            };
+        if let Err(retry_after) = self.limits.acquire(&client, cost) {
+            warn!("Rate limit exceeded for client {}", client);
+            record_throttled("rest");
+            let error = RateLimitError::new(retry_after);
             return Box::pin(async move { Err(error.into()) });
         }
-
-        // Increment request count
-        entry.count += 1;
-        drop(clients); // Release mutex
+        debug!("Charged {} to client {} for {}", cost, client, req.path());
 
         // Forward request to next middleware/handler
         let fut = self.service.call(req);
@@ -275,4 +393,24 @@ mod tests {
             assert_eq!(resp.status(), StatusCode::OK);
         }
     }
+
+    #[test]
+    fn test_token_bucket_costs() {
+        let limits = RateLimits::new(20, Duration::from_secs(10), RateLimitCosts::default());
+        assert_eq!(limits.route_cost("/api/v1/blockchain/block/height/7"), 10);
+        assert_eq!(limits.route_cost("/api/v1/blockchain/info"), 1);
+        assert_eq!(limits.method_cost("getblock"), 10);
+        assert_eq!(limits.method_cost("getblockcount"), 1);
+
+        // Two blocks drain the bucket, while another client is unaffected
+        assert!(limits.acquire("ip:a", 10).is_ok());
+        assert!(limits.acquire("ip:a", 10).is_ok());
+        let retry_after = limits.acquire("ip:a", 1).unwrap_err();
+        assert!(retry_after > Duration::ZERO && retry_after <= Duration::from_millis(500));
+        assert!(limits.acquire("ip:b", 20).is_ok());
+
+        // A cost beyond the capacity waits for a full bucket
+        assert!(limits.acquire("ip:c", 1_000).is_ok());
+        assert_eq!(RateLimitError::new(Duration::from_millis(200)).retry_after_secs, 1);
+    }
 } 
\ No newline at end of file
@@ -10,6 +10,7 @@ pub mod error;
 pub mod docs;
 pub mod v1;
 pub mod jsonrpc;
+pub mod tls;
 
 pub use server::ApiServer;
 pub use server::ApiConfig;
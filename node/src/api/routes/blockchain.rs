@@ -13,7 +13,8 @@ use crate::node::Node;
 use crate::api::error::ApiError;
 use crate::api::types::{ApiResponse, BlockInfo, TransactionInfo, BlockchainInfo, BlockHeightParams, BlockHashParams, TxHashParams, SubmitTxRequest};
 use crate::storage::StorageError;
-use btclib::types::transaction::{Transaction, TransactionError};
+use crate::mempool::MempoolError;
+use btclib::types::transaction::Transaction;
 
 /// Configure blockchain routes
 pub fn configure(cfg: &mut web::ServiceConfig) {
@@ -363,7 +364,8 @@ pub async fn submit_transaction(
         .map_err(|e| ApiError::BadRequest(format!("Invalid transaction format: {}", e)))?;
     
     // Validate and add to mempool
-    match node.mempool().add_transaction(tx.clone()) {
+    let accepted = node.mempool().accept_transaction(tx.clone(), &*node.chain_state.read().await);
+    match accepted {
         Ok(_) => {
             // Broadcast the transaction to peers
             node.broadcast_transaction(&tx);
@@ -376,13 +378,10 @@ pub async fn submit_transaction(
         },
         Err(e) => {
             match e {
-                TransactionError::InvalidTransaction(msg) => {
-                    Err(ApiError::BadRequest(format!("Transaction validation failed: {}", msg)))
+                MempoolError::Rejected(rejection) => {
+                    Err(ApiError::BadRequest(format!("Transaction validation failed: {}", rejection)))
                 },
-                TransactionError::InsufficientFunds => {
-                    Err(ApiError::BadRequest("Insufficient funds".to_string()))
-                },
-                TransactionError::DuplicateTransaction => {
+                MempoolError::DuplicateTransaction => {
                     Err(ApiError::BadRequest("Transaction already in mempool".to_string()))
                 },
                 err => Err(ApiError::InternalError(format!("Failed to add transaction: {}", err))),
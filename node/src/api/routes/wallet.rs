@@ -473,6 +473,11 @@ pub async fn send_transaction(
     if req.outputs.is_empty() {
         return Err(ApiError::bad_request("No outputs specified"));
     }
+    let network = node.config.node.environment.network_type();
+    for output in &req.outputs {
+        btclib::types::address::Address::parse_for_network(&output.address, network)
+            .map_err(|e| ApiError::bad_request(format!("Invalid address {}: {}", output.address, e)))?;
+    }
     
     // Apply request parameters
     let fee_rate = req.fee_rate.unwrap_or(1.0);
@@ -7,6 +7,7 @@ use actix_web::{web, App, HttpServer, middleware, dev::Server};
 use actix_cors::Cors;
 use std::sync::Arc;
 use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
 use std::str::FromStr;
 use serde::{Deserialize, Serialize};
 use utoipa_swagger_ui::SwaggerUi;
@@ -18,6 +19,7 @@ use super::routes;
 use super::docs::ApiDoc;
 use super::middleware::{auth, rate_limiting, logging};
 use super::jsonrpc;
+use super::tls::{self, TlsConfig};
 
 /// Configuration options for the API server
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -32,17 +34,31 @@ pub struct ApiConfig {
     pub cors_allowed_origins: Vec<String>,
     /// Rate limiting settings (requests per minute)
     pub rate_limit: Option<u32>,
+    /// What each request counts for against `rate_limit`
+    #[serde(default)]
+    pub rate_limit_costs: rate_limiting::RateLimitCosts,
     /// Enable authentication
     pub enable_auth: bool,
     /// API keys (only used if enable_auth is true)
+    ///
+    /// Plain keys with full access; prefer `api_tokens`.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub api_keys: Option<Vec<String>>,
+    /// Scoped API tokens, stored as key hashes (only used if enable_auth is true)
+    #[serde(default)]
+    pub api_tokens: Vec<auth::ApiToken>,
+    /// Cookie file written at startup for local clients (only used if enable_auth is true)
+    #[serde(default)]
+    pub cookie_file: Option<PathBuf>,
     /// Detailed logging
     pub detailed_logging: bool,
     /// Maximum JSON payload size in megabytes
     pub max_json_payload_size: usize,
     /// Request timeout in seconds
     pub request_timeout: u64,
+    /// Serve HTTPS instead of HTTP, optionally with client certificates
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tls: Option<TlsConfig>,
     /// Enable JSON-RPC API
     #[serde(default = "default_enable_jsonrpc")]
     pub enable_jsonrpc: bool,
@@ -69,8 +85,12 @@ impl Default for ApiConfig {
             enable_docs: true,
             cors_allowed_origins: vec!["*".to_string()],
             rate_limit: Some(100),
+            rate_limit_costs: rate_limiting::RateLimitCosts::default(),
             enable_auth: false,
             api_keys: None,
+            api_tokens: Vec::new(),
+            cookie_file: None,
+            tls: None,
             detailed_logging: true,
             max_json_payload_size: 5, // 5 MB
             request_timeout: 30, // 30 seconds
@@ -131,6 +151,19 @@ impl ApiServer {
         
         info!("Starting API server on {}", socket_addr);
         
+        let api_auth = if config.enable_auth {
+            Some(Self::authentication(&config)?)
+        } else {
+            None
+        };
+        
+        // One set of buckets for all workers, so a client's limit does not depend on which serves it
+        let rate_limits = config.rate_limit.map(|rate| Arc::new(rate_limiting::RateLimits::new(
+            rate,
+            std::time::Duration::from_secs(60),
+            config.rate_limit_costs.clone(),
+        )));
+        
         // Set up the HTTP server
         let server = HttpServer::new(move || {
             let mut app = App::new()
@@ -176,14 +209,18 @@ impl ApiServer {
                 app = app.wrap(middleware::Logger::default());
             }
             
-            // Add authentication middleware if enabled
-            if config.enable_auth {
-                app = app.wrap(auth::ApiAuth::new(config.api_keys.clone().unwrap_or_default()));
+            // Add rate limiting middleware if configured. It is wrapped before
+            // authentication so that it runs after it and can tell clients by credential.
+            if let Some(rate_limits) = &rate_limits {
+                app = app
+                    .app_data(web::Data::from(rate_limits.clone()))
+                    .wrap(rate_limiting::RateLimiter::with_limits(rate_limits.clone())
+                        .with_jsonrpc_path(&config.jsonrpc_path));
             }
             
-            // Add rate limiting middleware if configured
-            if let Some(rate) = config.rate_limit {
-                app = app.wrap(rate_limiting::RateLimiter::new(rate));
+            // Add authentication middleware if enabled
+            if let Some(api_auth) = &api_auth {
+                app = app.wrap(api_auth.clone());
             }
             
             // Configure REST API routes
@@ -210,13 +247,42 @@ impl ApiServer {
             app
         })
         .client_request_timeout(std::time::Duration::from_secs(config.request_timeout))
-        .bind(socket_addr)?
+        .on_connect(tls::capture_client_certificate);
+        
+        let server = match &self.config.tls {
+            Some(tls_config) => {
+                let invalid = |e: tls::TlsError| std::io::Error::new(std::io::ErrorKind::InvalidInput, e.to_string());
+                let (server_config, resolver) = tls::server_config(tls_config).map_err(invalid)?;
+                actix_web::rt::spawn(resolver.watch(std::time::Duration::from_secs(tls_config.reload_interval.max(1))));
+                info!("API server using TLS{}", if tls_config.client_ca_path.is_some() { " with client certificates" } else { "" });
+                server.bind_rustls_021(socket_addr, server_config)?
+            },
+            None => server.bind(socket_addr)?,
+        }
         .run();
         
         info!("API server started on {}", socket_addr);
         
         Ok(server)
     }
+    
+    /// Build the authentication middleware, writing the cookie file if configured
+    fn authentication(config: &ApiConfig) -> std::io::Result<auth::ApiAuth> {
+        let invalid = |e: auth::AuthConfigError| std::io::Error::new(std::io::ErrorKind::InvalidInput, e.to_string());
+        let mut api_auth = auth::ApiAuth::new(config.api_keys.clone().unwrap_or_default())
+            .with_tokens(&config.api_tokens)
+            .map_err(invalid)?
+            .with_jsonrpc_path(&config.jsonrpc_path);
+        if let Some(tls_config) = &config.tls {
+            api_auth = api_auth.with_client_certificates(&tls_config.client_roles).map_err(invalid)?;
+        }
+        if let Some(path) = &config.cookie_file {
+            let secret = auth::write_cookie_file(path).map_err(invalid)?;
+            info!("Wrote API cookie file to {:?}", path);
+            api_auth = api_auth.with_cookie(&secret);
+        }
+        Ok(api_auth)
+    }
 }
 
 #[cfg(test)]
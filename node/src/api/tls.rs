@@ -0,0 +1,307 @@
+//! TLS for the API server
+//!
+//! With a `tls` section in [`ApiConfig`](super::ApiConfig) the server only
+//! speaks HTTPS. The certificate and key are read through a
+//! [`CertificateResolver`], which rereads them when the files change so a
+//! renewed certificate is picked up without a restart; connections already
+//! open keep the certificate they were established with.
+//!
+//! Setting `client_ca_path` enables mutual TLS. Client certificates signed by
+//! that CA are accepted, and those listed in `client_roles` (by SHA-256
+//! fingerprint of their DER encoding) authenticate the caller with a role
+//! without any API key, see [`super::middleware::auth`].
+
+use actix_web::dev::Extensions;
+use rustls::server::{AllowAnyAnonymousOrAuthenticatedClient, AllowAnyAuthenticatedClient, ClientHello, ResolvesServerCert};
+use rustls::sign::{self, CertifiedKey};
+use rustls::{Certificate, PrivateKey, RootCertStore, ServerConfig};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::any::Any;
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+use thiserror::Error;
+use tracing::{info, warn};
+use super::middleware::permissions::Role;
+
+/// Default seconds between checks for renewed certificate files
+pub const DEFAULT_RELOAD_INTERVAL: u64 = 60;
+
+fn default_reload_interval() -> u64 {
+    DEFAULT_RELOAD_INTERVAL
+}
+
+/// TLS settings of the API server
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TlsConfig {
+    /// PEM certificate chain, leaf first
+    pub cert_path: PathBuf,
+    /// PEM private key (PKCS#8, RSA or SEC1)
+    pub key_path: PathBuf,
+    /// PEM bundle of the CAs client certificates must chain to; enables mutual TLS
+    #[serde(default)]
+    pub client_ca_path: Option<PathBuf>,
+    /// Refuse connections without a client certificate (only used with `client_ca_path`)
+    #[serde(default)]
+    pub require_client_cert: bool,
+    /// Seconds between checks for a renewed certificate or key
+    #[serde(default = "default_reload_interval")]
+    pub reload_interval: u64,
+    /// Client certificates that authenticate as a role
+    #[serde(default)]
+    pub client_roles: Vec<ClientCertRole>,
+}
+
+/// A client certificate mapped onto an API role
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ClientCertRole {
+    /// Name identifying the client in the audit log
+    pub name: String,
+    /// Hex SHA-256 of the certificate's DER encoding, see [`fingerprint`]
+    pub fingerprint: String,
+    pub role: Role,
+}
+
+/// Error setting up TLS
+#[derive(Debug, Error)]
+pub enum TlsError {
+    #[error("Failed to read {0}: {1}")]
+    Io(PathBuf, std::io::Error),
+
+    #[error("No certificate found in {0}")]
+    NoCertificate(PathBuf),
+
+    #[error("No private key found in {0}")]
+    NoPrivateKey(PathBuf),
+
+    #[error("Unsupported private key in {0}")]
+    UnsupportedKey(PathBuf),
+
+    #[error("Invalid client CA certificate: {0}")]
+    InvalidClientCa(String),
+
+    #[error("TLS configuration error: {0}")]
+    Rustls(#[from] rustls::Error),
+}
+
+/// Certificate a client presented on a mutual TLS connection, as connection data
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientCertificate(pub Vec<u8>);
+
+/// Hex SHA-256 of a DER certificate, the form client certificates are configured in
+pub fn fingerprint(der: &[u8]) -> String {
+    hex::encode(Sha256::digest(der))
+}
+
+/// Read every certificate of a PEM file
+pub fn load_certificates(path: &Path) -> Result<Vec<Certificate>, TlsError> {
+    let file = File::open(path).map_err(|e| TlsError::Io(path.to_path_buf(), e))?;
+    let certificates = rustls_pemfile::certs(&mut BufReader::new(file))
+        .map_err(|e| TlsError::Io(path.to_path_buf(), e))?;
+    if certificates.is_empty() {
+        return Err(TlsError::NoCertificate(path.to_path_buf()));
+    }
+    Ok(certificates.into_iter().map(Certificate).collect())
+}
+
+/// Read the first private key of a PEM file
+pub fn load_private_key(path: &Path) -> Result<PrivateKey, TlsError> {
+    let file = File::open(path).map_err(|e| TlsError::Io(path.to_path_buf(), e))?;
+    let items = rustls_pemfile::read_all(&mut BufReader::new(file))
+        .map_err(|e| TlsError::Io(path.to_path_buf(), e))?;
+    items
+        .into_iter()
+        .find_map(|item| match item {
+            rustls_pemfile::Item::PKCS8Key(key)
+            | rustls_pemfile::Item::RSAKey(key)
+            | rustls_pemfile::Item::ECKey(key) => Some(PrivateKey(key)),
+            _ => None,
+        })
+        .ok_or_else(|| TlsError::NoPrivateKey(path.to_path_buf()))
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+/// Serves the configured certificate, rereading it when its files change
+#[derive(Debug)]
+pub struct CertificateResolver {
+    cert_path: PathBuf,
+    key_path: PathBuf,
+    current: RwLock<Loaded>,
+}
+
+#[derive(Debug)]
+struct Loaded {
+    key: Arc<CertifiedKey>,
+    cert_modified: Option<SystemTime>,
+    key_modified: Option<SystemTime>,
+}
+
+impl CertificateResolver {
+    /// Load the certificate chain and key, failing if either is unusable
+    pub fn load(cert_path: &Path, key_path: &Path) -> Result<Self, TlsError> {
+        Ok(Self {
+            cert_path: cert_path.to_path_buf(),
+            key_path: key_path.to_path_buf(),
+            current: RwLock::new(Self::read(cert_path, key_path)?),
+        })
+    }
+
+    fn read(cert_path: &Path, key_path: &Path) -> Result<Loaded, TlsError> {
+        // Timestamps are taken first, so a write racing the read is seen on the next check
+        let cert_modified = modified(cert_path);
+        let key_modified = modified(key_path);
+        let certificates = load_certificates(cert_path)?;
+        let signing_key = sign::any_supported_type(&load_private_key(key_path)?)
+            .map_err(|_| TlsError::UnsupportedKey(key_path.to_path_buf()))?;
+        Ok(Loaded {
+            key: Arc::new(CertifiedKey::new(certificates, signing_key)),
+            cert_modified,
+            key_modified,
+        })
+    }
+
+    /// The certificate chain currently served
+    pub fn certificates(&self) -> Vec<Certificate> {
+        self.current.read().unwrap_or_else(|e| e.into_inner()).key.cert.clone()
+    }
+
+    /// Reread the certificate and key, keeping the current ones if that fails
+    pub fn reload(&self) -> Result<(), TlsError> {
+        let loaded = Self::read(&self.cert_path, &self.key_path)?;
+        *self.current.write().unwrap_or_else(|e| e.into_inner()) = loaded;
+        Ok(())
+    }
+
+    /// Reload if either file changed since it was last read; returns whether it did
+    pub fn reload_if_changed(&self) -> Result<bool, TlsError> {
+        let changed = {
+            let current = self.current.read().unwrap_or_else(|e| e.into_inner());
+            modified(&self.cert_path) != current.cert_modified || modified(&self.key_path) != current.key_modified
+        };
+        if changed {
+            self.reload()?;
+        }
+        Ok(changed)
+    }
+
+    /// Check for renewed files every `interval` for as long as the server runs
+    pub async fn watch(self: Arc<Self>, interval: Duration) {
+        let mut ticker = tokio::time::interval(interval);
+        ticker.tick().await;
+        loop {
+            ticker.tick().await;
+            match self.reload_if_changed() {
+                Ok(true) => info!("Reloaded API TLS certificate from {:?}", self.cert_path),
+                Ok(false) => {},
+                Err(e) => warn!("Keeping current API TLS certificate, reload failed: {}", e),
+            }
+        }
+    }
+}
+
+impl ResolvesServerCert for CertificateResolver {
+    fn resolve(&self, _client_hello: ClientHello) -> Option<Arc<CertifiedKey>> {
+        Some(self.current.read().unwrap_or_else(|e| e.into_inner()).key.clone())
+    }
+}
+
+/// Build the rustls configuration for `config`
+///
+/// Returns the certificate resolver too, so the caller can [`CertificateResolver::watch`] it.
+pub fn server_config(config: &TlsConfig) -> Result<(ServerConfig, Arc<CertificateResolver>), TlsError> {
+    let resolver = Arc::new(CertificateResolver::load(&config.cert_path, &config.key_path)?);
+    let builder = ServerConfig::builder().with_safe_defaults();
+    let builder = match &config.client_ca_path {
+        Some(ca_path) => {
+            let mut roots = RootCertStore::empty();
+            for certificate in load_certificates(ca_path)? {
+                roots.add(&certificate).map_err(|e| TlsError::InvalidClientCa(e.to_string()))?;
+            }
+            if config.require_client_cert {
+                builder.with_client_cert_verifier(AllowAnyAuthenticatedClient::new(roots).boxed())
+            } else {
+                builder.with_client_cert_verifier(AllowAnyAnonymousOrAuthenticatedClient::new(roots).boxed())
+            }
+        },
+        None => builder.with_no_client_auth(),
+    };
+    let mut server_config = builder.with_cert_resolver(resolver.clone());
+    server_config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    Ok((server_config, resolver))
+}
+
+/// Connection hook recording the client certificate of a TLS connection
+///
+/// Passed to `HttpServer::on_connect`; the authentication middleware finds the
+/// certificate as [`ClientCertificate`] connection data.
+pub fn capture_client_certificate(connection: &dyn Any, data: &mut Extensions) {
+    type TlsStream = actix_tls::accept::rustls_0_21::TlsStream<actix_web::rt::net::TcpStream>;
+    if let Some(stream) = connection.downcast_ref::<TlsStream>() {
+        let (_, session) = stream.get_ref();
+        if let Some(certificate) = session.peer_certificates().and_then(|certificates| certificates.first()) {
+            data.insert(ClientCertificate(certificate.0.clone()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_certificate(dir: &Path, name: &str) -> (PathBuf, PathBuf) {
+        let certificate = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        let cert_path = dir.join(format!("{}.pem", name));
+        let key_path = dir.join(format!("{}.key", name));
+        fs::write(&cert_path, certificate.serialize_pem().unwrap()).unwrap();
+        fs::write(&key_path, certificate.serialize_private_key_pem()).unwrap();
+        (cert_path, key_path)
+    }
+
+    #[test]
+    fn test_certificate_reload() {
+        let dir = tempfile::tempdir().unwrap();
+        let (cert_path, key_path) = write_certificate(dir.path(), "server");
+        let resolver = CertificateResolver::load(&cert_path, &key_path).unwrap();
+        let original = resolver.certificates();
+        assert_eq!(original, load_certificates(&cert_path).unwrap());
+        assert!(!resolver.reload_if_changed().unwrap());
+
+        // A renewed certificate replaces the served one
+        let (renewed_cert, renewed_key) = write_certificate(dir.path(), "renewed");
+        fs::rename(&renewed_cert, &cert_path).unwrap();
+        fs::rename(&renewed_key, &key_path).unwrap();
+        resolver.reload().unwrap();
+        assert_ne!(resolver.certificates(), original);
+
+        // A broken file leaves the current certificate in place
+        let served = resolver.certificates();
+        fs::write(&key_path, "not a key").unwrap();
+        assert!(matches!(resolver.reload(), Err(TlsError::NoPrivateKey(_))));
+        assert_eq!(resolver.certificates(), served);
+    }
+
+    #[test]
+    fn test_mutual_tls_config() {
+        let dir = tempfile::tempdir().unwrap();
+        let (cert_path, key_path) = write_certificate(dir.path(), "server");
+        let (client_ca_path, _) = write_certificate(dir.path(), "client-ca");
+        let config = TlsConfig {
+            cert_path,
+            key_path,
+            client_ca_path: Some(client_ca_path),
+            require_client_cert: true,
+            reload_interval: DEFAULT_RELOAD_INTERVAL,
+            client_roles: Vec::new(),
+        };
+        assert!(server_config(&config).is_ok());
+
+        let missing = TlsConfig { client_ca_path: Some(dir.path().join("absent.pem")), ..config };
+        assert!(matches!(server_config(&missing), Err(TlsError::Io(..))));
+    }
+}
@@ -6,6 +6,8 @@ use tracing::{info, warn, error};
 use config::{Config, ConfigError, Environment, File};
 use notify::{self, Watcher, RecommendedWatcher, RecursiveMode};
 use crate::api::ApiConfig;
+use crate::notifications::NotificationConfig;
+use crate::stratum::StratumConfig;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct NodeConfig {
@@ -16,6 +18,10 @@ pub struct NodeConfig {
     pub node: GeneralConfig,
     pub checkpoint: CheckpointConfig,
     pub api: ApiConfig,
+    #[serde(default)]
+    pub stratum: StratumConfig,
+    #[serde(default)]
+    pub notifications: NotificationConfig,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -51,6 +57,21 @@ pub struct MempoolConfig {
     pub max_orphan_transactions: usize,
     pub enable_rbf: bool,
     pub min_rbf_fee_increase: f64,
+    /// Save the mempool on shutdown and periodically, and reload it on startup
+    #[serde(default = "default_persist_mempool")]
+    pub persist_mempool: bool,
+    #[serde(with = "duration_serde", default = "default_persist_interval")]
+    pub persist_interval: Duration,
+}
+
+/// Default value for persist_mempool
+fn default_persist_mempool() -> bool {
+    true
+}
+
+/// Default value for persist_interval
+fn default_persist_interval() -> Duration {
+    Duration::from_secs(900)
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -80,6 +101,17 @@ pub enum NetworkEnvironment {
     Production,
 }
 
+impl NetworkEnvironment {
+    /// Chain this environment runs on, which decides the address prefix
+    pub fn network_type(&self) -> btclib::config::NetworkType {
+        match self {
+            NetworkEnvironment::Production => btclib::config::NetworkType::Mainnet,
+            NetworkEnvironment::Testnet => btclib::config::NetworkType::Testnet,
+            NetworkEnvironment::Development => btclib::config::NetworkType::Regtest,
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct CheckpointConfig {
     pub checkpoints_enabled: bool,
@@ -120,6 +152,8 @@ impl Default for NodeConfig {
             node: GeneralConfig::default(),
             checkpoint: CheckpointConfig::default(),
             api: ApiConfig::default(),
+            stratum: StratumConfig::default(),
+            notifications: NotificationConfig::default(),
         }
     }
 }
@@ -161,6 +195,8 @@ impl Default for MempoolConfig {
             max_orphan_transactions: 100,
             enable_rbf: true,
             min_rbf_fee_increase: 10.0,
+            persist_mempool: default_persist_mempool(),
+            persist_interval: default_persist_interval(),
         }
     }
 }
@@ -361,6 +397,9 @@ impl NodeConfig {
         if self.mempool.min_rbf_fee_increase < 0.0 {
             return Err("min_rbf_fee_increase must be non-negative".to_string());
         }
+        if self.mempool.persist_mempool && self.mempool.persist_interval.as_secs() == 0 {
+            return Err("mempool persist_interval must be greater than 0".to_string());
+        }
 
         if self.storage.max_open_files < 100 {
             return Err("max_open_files must be at least 100".to_string());
@@ -369,6 +408,15 @@ impl NodeConfig {
             return Err("block_cache_size must be greater than 0".to_string());
         }
 
+        for webhook in &self.notifications.webhooks {
+            if !webhook.url.starts_with("http://") && !webhook.url.starts_with("https://") {
+                return Err(format!("webhook url {} must use http or https", webhook.url));
+            }
+            if webhook.secret.is_empty() {
+                return Err(format!("webhook {} needs a secret", webhook.url));
+            }
+        }
+
         Ok(())
     }
 
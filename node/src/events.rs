@@ -0,0 +1,103 @@
+//! Node event bus
+//!
+//! Chain, mempool and Lightning changes are published as [`NodeEvent`]s on a
+//! broadcast channel for API subscribers. The channel is bounded: a subscriber
+//! that falls behind loses the oldest events and is told how many it missed,
+//! so a slow client can never hold up block or transaction processing.
+
+use btclib::types::block::Block;
+use btclib::types::transaction::Transaction;
+use std::sync::Arc;
+use tokio::sync::broadcast;
+
+/// Events buffered for each subscriber before the oldest are dropped
+pub const EVENT_BUFFER: usize = 1024;
+
+/// Why a transaction left the mempool
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RemovalReason {
+    /// Included in a block on the best chain
+    Confirmed,
+    /// Replaced by a conflicting transaction or package paying more
+    Replaced,
+    /// Evicted to keep the pool within its size limit
+    Evicted,
+    /// Older than the pool's maximum age
+    Expired,
+    /// Removed by an operator, or because a transaction it spends was
+    Removed,
+}
+
+impl RemovalReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RemovalReason::Confirmed => "confirmed",
+            RemovalReason::Replaced => "replaced",
+            RemovalReason::Evicted => "evicted",
+            RemovalReason::Expired => "expired",
+            RemovalReason::Removed => "removed",
+        }
+    }
+}
+
+/// Something subscribers may want to hear about
+#[derive(Debug, Clone)]
+pub enum NodeEvent {
+    /// A block was connected to the best chain and is now its tip
+    BlockConnected { block: Arc<Block>, height: u64 },
+    /// The best chain switched to another fork
+    ///
+    /// `disconnected` lists the old chain's blocks from its tip down,
+    /// `connected` the new chain's from the fork point up. A
+    /// [`NodeEvent::BlockConnected`] follows for each connected block.
+    Reorg {
+        old_tip: [u8; 32],
+        new_tip: [u8; 32],
+        fork_height: u64,
+        disconnected: Vec<[u8; 32]>,
+        connected: Vec<[u8; 32]>,
+    },
+    /// A transaction entered the mempool
+    MempoolAdded { transaction: Arc<Transaction>, fee: u64, size: usize },
+    /// A transaction left the mempool
+    MempoolRemoved { txid: [u8; 32], reason: RemovalReason },
+    /// An outgoing Lightning payment completed
+    LightningPaymentSent { payment_hash: [u8; 32], amount_msat: u64, fee_msat: u64 },
+    /// An invoice of this node was paid
+    LightningInvoiceSettled { payment_hash: [u8; 32], amount_msat: u64 },
+}
+
+/// Cloneable handle for publishing and subscribing to [`NodeEvent`]s
+#[derive(Debug, Clone)]
+pub struct EventBus {
+    sender: broadcast::Sender<NodeEvent>,
+}
+
+impl EventBus {
+    /// Create a bus buffering up to `capacity` events per subscriber
+    pub fn new(capacity: usize) -> Self {
+        let (sender, _) = broadcast::channel(capacity);
+        Self { sender }
+    }
+
+    /// Send an event to every current subscriber; without subscribers it is dropped
+    pub fn publish(&self, event: NodeEvent) {
+        let _ = self.sender.send(event);
+    }
+
+    /// Receive the events published from now on
+    pub fn subscribe(&self) -> broadcast::Receiver<NodeEvent> {
+        self.sender.subscribe()
+    }
+
+    /// Number of live subscribers
+    pub fn subscriber_count(&self) -> usize {
+        self.sender.receiver_count()
+    }
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new(EVENT_BUFFER)
+    }
+}
@@ -3,6 +3,10 @@ pub mod mempool;
 pub mod storage;
 pub mod metrics;
 pub mod config;
+pub mod mining;
+pub mod stratum;
+pub mod events;
+pub mod notifications;
 
 #[cfg(test)]
 mod tests;
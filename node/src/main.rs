@@ -1,12 +1,15 @@
 use node::network::{P2PNetwork, NetworkCommand, NetworkEvent};
 use node::storage::{ChainState, BlockchainDB, BackupManager, RecoveryManager};
-use node::mempool::{TransactionPool, TransactionPrioritizer};
+use node::mempool::{MempoolError, PersistenceError, Submission, TransactionPool, TransactionPrioritizer, MEMPOOL_FILE};
+use node::mempool::orphan::ORPHAN_BAN_SCORE;
 use node::mempool::prioritization::PrioritizationConfig;
 use node::config::NodeConfig;
+use node::events::EventBus;
+use node::mining::{BlockTemplateConfig, BlockTemplateService};
 use node::storage::corruption::{CorruptionHandler, CorruptionError, CorruptionType};
 use tracing::{info, error, warn, debug};
 use std::sync::Arc;
-use tokio::sync::{mpsc, Mutex};
+use tokio::sync::{mpsc, Mutex, RwLock};
 use std::path::PathBuf;
 use std::time::Duration;
 use btclib::types::block::Block;
@@ -27,7 +30,7 @@ struct Node {
     mempool: Arc<TransactionPool>,
     prioritizer: Arc<Mutex<TransactionPrioritizer>>,
     network: P2PNetwork,
-    chain_state: ChainState,
+    chain_state: Arc<RwLock<ChainState>>,
     backup_manager: Arc<BackupManager>,
     corruption_handler: Arc<Mutex<CorruptionHandler>>,
     mempool_metrics: Option<MempoolMetrics>,
@@ -40,7 +43,12 @@ impl Node {
         let config = Arc::new(Mutex::new(config));
 
         let mempool_config = config.lock().await.mempool.clone();
-        let mempool = Arc::new(TransactionPool::new(node::mempool::MempoolConfig::from(mempool_config.clone())));
+        // Chain and mempool changes are published here for API subscribers
+        let events = EventBus::default();
+        let mempool = Arc::new(
+            TransactionPool::new(node::mempool::MempoolConfig::from(mempool_config.clone()))
+                .with_event_bus(events.clone())
+        );
         
         let prioritizer_config = PrioritizationConfig::from(mempool_config);
         let prioritizer = Arc::new(Mutex::new(TransactionPrioritizer::new(prioritizer_config)));
@@ -60,7 +68,49 @@ impl Node {
             return Err(e.into());
         }
         
-        let chain_state = ChainState::new(Arc::clone(&db))?;
+        let mut chain_state = ChainState::new(Arc::clone(&db))?;
+        chain_state.set_event_bus(events.clone());
+
+        // Bring back the transactions that were unconfirmed at shutdown, revalidated against the tip
+        if config.lock().await.mempool.persist_mempool {
+            let mempool_path = config.lock().await.storage.db_path.join(MEMPOOL_FILE);
+            match mempool.load(&mempool_path, &chain_state) {
+                Ok(stats) => info!("Loaded mempool: {} accepted, {} failed, {} expired, {} already present",
+                                   stats.accepted, stats.failed, stats.expired, stats.already_there),
+                Err(PersistenceError::Io(e)) if e.kind() == std::io::ErrorKind::NotFound => {
+                    debug!("No saved mempool at {:?}", mempool_path);
+                },
+                Err(e) => warn!("Failed to load mempool from {:?}: {}", mempool_path, e),
+            }
+        }
+
+        // Started after the mempool reload so its transactions are not announced twice
+        let notification_config = config.lock().await.notifications.clone();
+        if let Err(e) = node::notifications::spawn(&notification_config, &events, Arc::clone(&db)) {
+            warn!("Failed to start notification publishers: {}", e);
+        }
+
+        // From here on every component reads and extends the same chain state
+        let chain_state = Arc::new(RwLock::new(chain_state));
+
+        // Confirmed transactions leave the pool and unlock orphans whether the block came from a peer or a miner
+        mempool.follow_chain(&events, Arc::clone(&chain_state));
+
+        // Also after the reload, so the first jobs miners receive include those transactions
+        let stratum_config = config.lock().await.stratum.clone();
+        if stratum_config.enabled {
+            let templates = Arc::new(BlockTemplateService::new(
+                BlockTemplateConfig {
+                    payout_script: stratum_config.payout_script.clone(),
+                    ..BlockTemplateConfig::default()
+                },
+                Arc::clone(&chain_state),
+                Arc::clone(&mempool),
+            )?);
+            // Blocks from peers move the tip too, not only those miners submit
+            templates.follow_chain(&events);
+            node::stratum::spawn(&stratum_config, templates, Arc::clone(&mempool)).await?;
+        }
 
         let backup_config = config.lock().await.backup.clone();
         let backup_manager = Arc::new(BackupManager::new(
@@ -73,7 +123,7 @@ impl Node {
         let mut recovery_manager = RecoveryManager::new(
             Arc::clone(&db),
             backup_config.backup_dir.clone(),
-            chain_state.clone(),
+            chain_state.read().await.clone(),
         );
 
         if backup_config.verify_on_startup {
@@ -86,7 +136,7 @@ impl Node {
         // Initialize network with genesis hash and network ID
         let (network, _, _) = P2PNetwork::new(
             None,
-            chain_state.get_genesis_hash(),
+            chain_state.read().await.get_genesis_hash(),
             &config.lock().await.node.chain_id
         ).await?;
 
@@ -199,140 +249,62 @@ impl Node {
             return Ok(());
         }
 
-        // Calculate fee rate with a dummy output lookup function
-        let fee_rate = match transaction.calculate_fee_rate(|_, _| None) {
-            Some(rate) => rate,
-            None => return Err("Unable to calculate fee rate".into())
-        };
+        // Transactions this one would replace through RBF, to drop from the prioritizer once it is in
+        let conflicts = self.mempool.find_conflicting_transactions(&transaction);
+
+        // Validate against the current chain, which also settles RBF and the fee the transaction really pays
+        let accepted = self.mempool.accept_transaction(transaction.clone(), &*self.chain_state.read().await);
+        match accepted {
+            Ok(checked) => {
+                let fee_rate = checked.fee_rate;
+                if conflicts.is_empty() {
+                    info!("Added transaction {} to mempool with fee rate {}", hex::encode(&tx_hash[..4]), fee_rate);
+                } else {
+                    info!("Replaced {} transaction(s) with {} (RBF, fee rate: {})",
+                          conflicts.len(), hex::encode(&tx_hash[..4]), fee_rate);
+                }
 
-        // Get config
-        let config = self.config.lock().await;
-        
-        // Check minimum fee rate (converting from f64 to u64)
-        if fee_rate < config.mempool.min_fee_rate as u64 {
-            info!("Transaction {} rejected: fee rate too low ({} < {})",
-                    hex::encode(&tx_hash[..4]), fee_rate, config.mempool.min_fee_rate);
-            
-            // Update metrics if available
-            if let Some(metrics) = &self.mempool_metrics {
-                metrics.increment_transactions_removed("low_fee");
-            }
-            
-            return Err("Fee rate too low".into());
-        }
-        
-        // First try as an RBF transaction if enabled
-        if config.mempool.enable_rbf {
-            match self.mempool.replace_transaction(transaction.clone(), fee_rate) {
-                Ok(replaced_tx) => {
-                    // Successfully processed as RBF
-                    if let Some(old_tx) = replaced_tx {
-                        // Single transaction replacement
-                        let old_tx_hash = old_tx.hash();
-                        info!("Replaced transaction {} with {} (RBF, fee rate: {} -> {})",
-                              hex::encode(&old_tx_hash[..4]), 
-                              hex::encode(&tx_hash[..4]),
-                              self.prioritizer.lock().await.get_transaction_fee_rate(&old_tx_hash).unwrap_or(0),
-                              fee_rate);
-                        
-                        // Remove old transaction from prioritizer
-                        self.prioritizer.lock().await.remove_transaction(&old_tx_hash);
-                        
-                        // Update RBF metrics
-                        if let Some(metrics) = &self.mempool_metrics {
-                            metrics.increment_transactions_replaced();
-                            metrics.increment_transactions_removed("rbf");
-                            metrics.increment_transactions_added("rbf");
-                            metrics.observe_fee_rate(fee_rate as f64, "rbf");
-                        }
-                    } else {
-                        // Multiple transaction replacement
-                        info!("Replaced multiple transactions with {} (RBF, fee rate: {})",
-                              hex::encode(&tx_hash[..4]), fee_rate);
-                        
-                        // Update RBF metrics
-                        if let Some(metrics) = &self.mempool_metrics {
-                            metrics.increment_transactions_replaced();
-                            metrics.increment_transactions_removed("rbf_package");
-                            metrics.increment_transactions_added("rbf");
-                            metrics.observe_fee_rate(fee_rate as f64, "rbf");
-                        }
-                    }
-                    
-                    // Add new transaction to prioritizer
-                    self.prioritizer.lock().await.add_transaction(transaction, fee_rate, 0);
-                    return Ok(());
-                },
-                Err(MempoolError::NoConflictingTransactions) => {
-                    // Not an RBF, continue with regular add
-                },
-                Err(MempoolError::RbfDisabled) => {
-                    // RBF is disabled in config, continue with regular add
-                },
-                Err(MempoolError::InsufficientFeeIncrease(required)) => {
-                    // Log rejection due to insufficient fee increase
-                    info!("Transaction {} rejected: insufficient fee increase for RBF ({} < {})",
-                          hex::encode(&tx_hash[..4]), fee_rate, required);
-                    
-                    // Update metrics if available
-                    if let Some(metrics) = &self.mempool_metrics {
-                        metrics.increment_transactions_removed("insufficient_rbf_fee");
-                    }
-                    
-                    return Err(format!("Insufficient fee increase for RBF, minimum required: {}", required).into());
-                },
-                Err(e) => {
-                    // Other error
-                    info!("Transaction {} rejected: RBF error: {}", hex::encode(&tx_hash[..4]), e);
-                    
-                    // Update metrics if available
-                    if let Some(metrics) = &self.mempool_metrics {
-                        metrics.increment_transactions_removed("rbf_error");
-                    }
-                    
-                    return Err(format!("RBF error: {}", e).into());
+                let mut prioritizer = self.prioritizer.lock().await;
+                for old_tx_hash in &conflicts {
+                    prioritizer.remove_transaction(old_tx_hash);
                 }
-            }
-        }
-        
-        // Regular transaction addition (not RBF or RBF failed)
-        match self.mempool.add_transaction(transaction.clone(), fee_rate) {
-            Ok(()) => {
-                // Add to prioritizer
-                self.prioritizer.lock().await.add_transaction(transaction, fee_rate, 0);
-                
+                prioritizer.add_transaction(transaction, fee_rate, checked.size);
+                drop(prioritizer);
+
                 // Update metrics if available
                 if let Some(metrics) = &self.mempool_metrics {
-                    metrics.increment_transactions_added("p2p");
-                    metrics.observe_fee_rate(fee_rate as f64, "standard");
-                    
+                    if conflicts.is_empty() {
+                        metrics.increment_transactions_added("p2p");
+                        metrics.observe_fee_rate(fee_rate as f64, "standard");
+                    } else {
+                        metrics.increment_transactions_replaced();
+                        metrics.increment_transactions_removed(if conflicts.len() == 1 { "rbf" } else { "rbf_package" });
+                        metrics.increment_transactions_added("rbf");
+                        metrics.observe_fee_rate(fee_rate as f64, "rbf");
+                    }
+
                     // Update mempool size metrics
                     let mempool_size = self.mempool.transactions.len() as i64;
                     metrics.observe_mempool_size(mempool_size);
                 }
-                
-                info!("Added transaction {} to mempool with fee rate {}", hex::encode(&tx_hash[..4]), fee_rate);
+
                 Ok(())
             },
-            Err(MempoolError::DoubleSpend) => {
-                info!("Transaction {} rejected: double spend detected", hex::encode(&tx_hash[..4]));
-                
-                // Update metrics if available
-                if let Some(metrics) = &self.mempool_metrics {
-                    metrics.increment_transactions_removed("double_spend");
-                }
-                
-                Err("Double spend detected".into())
-            },
             Err(e) => {
-                info!("Transaction {} rejected: {}", hex::encode(&tx_hash[..4]), e);
-                
+                info!("Transaction {} rejected: {} ({})", hex::encode(&tx_hash[..4]), e, e.reason());
+
                 // Update metrics if available
                 if let Some(metrics) = &self.mempool_metrics {
-                    metrics.increment_transactions_removed("other_error");
+                    let label = match e {
+                        MempoolError::FeeTooLow => "low_fee",
+                        MempoolError::InsufficientFeeIncrease(_) => "insufficient_rbf_fee",
+                        MempoolError::DoubleSpend => "double_spend",
+                        _ => "other_error",
+                    };
+                    metrics.increment_transactions_removed(label);
                 }
-                
-                Err(format!("Mempool error: {}", e).into())
+
+                Err(e.into())
             }
         }
     }
@@ -354,7 +326,7 @@ impl Node {
         info!("Performing database maintenance...");
         
         // Use get_db() to access the database instead of direct field access
-        let db = self.chain_state.get_db();
+        let db = Arc::clone(self.chain_state.read().await.get_db());
         if let Err(e) = db.compact() {
             error!("Database compaction failed: {}", e);
             return Err(Box::new(e));
@@ -415,7 +387,7 @@ async fn run_node_background_tasks(
 
 // NodeHandle for safe access to Node components
 struct NodeHandle {
-    chain_state: ChainState,
+    chain_state: Arc<RwLock<ChainState>>,
     mempool: Arc<TransactionPool>,
     backup_manager: Arc<BackupManager>,
     corruption_handler: Arc<Mutex<CorruptionHandler>>,
@@ -433,7 +405,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     
     // Create a NodeHandle to store clones of data needed by various tasks
     let node_handle = NodeHandle {
-        chain_state: node.chain_state.clone(),
+        chain_state: Arc::clone(&node.chain_state),
         mempool: Arc::clone(&node.mempool),
         backup_manager: Arc::clone(&node.backup_manager),
         corruption_handler: Arc::clone(&node.corruption_handler),
@@ -472,7 +444,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Set up network and sync components
     let (mut network, command_tx, mut event_rx) = P2PNetwork::new(
         None,
-        node_handle.chain_state.get_genesis_hash(),
+        node_handle.chain_state.read().await.get_genesis_hash(),
         &node_handle.config.lock().await.node.chain_id
     ).await?;
 
@@ -481,9 +453,9 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let command_tx_for_network = command_tx.clone();
 
     // Initialize the enhanced sync system
-    let db_for_sync = Arc::clone(node_handle.chain_state.get_db());
+    let db_for_sync = Arc::clone(node_handle.chain_state.read().await.get_db());
     let mut sync = ChainSync::new(
-        node_handle.chain_state.clone(),
+        Arc::clone(&node_handle.chain_state),
         db_for_sync,
         command_tx_for_sync
     );
@@ -519,7 +491,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let db_maintenance_handle = spawn_maintenance_task(&node_handle);
 
     // Reference to chain_state and corruption_handler for event handling
-    let chain_state = node_handle.chain_state.clone();
+    let chain_state = Arc::clone(&node_handle.chain_state);
     let corruption_handler = Arc::clone(&node_handle.corruption_handler);
     let transaction_handler = node_handle.mempool.clone();
 
@@ -562,22 +534,54 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 },
                 
                 // Handle new transaction received
-                NetworkEvent::NewTransaction { transaction, fee_rate, from_peer } => {
+                NetworkEvent::NewTransaction { transaction, fee_rate: _, from_peer } => {
                     debug!("Received new transaction from {:?}", from_peer);
-                    
-                    // Calculate fee rate with a dummy output lookup function
-                    match transaction.calculate_fee_rate(|_, _| None) {
-                        Some(fee) => {
-                            if let Err(e) = transaction_handler.add_transaction(transaction, fee) {
-                                error!("Failed to process transaction: {}", e);
-                            }
+
+                    // The announced fee rate is ignored; acceptance computes it from the spent outputs
+                    let submission = transaction_handler.submit_transaction(transaction, from_peer, &*chain_state.read().await);
+                    match submission {
+                        Ok(Submission::Accepted { resubmitted }) if !resubmitted.is_empty() => {
+                            debug!("Transaction accepted along with {} orphan(s)", resubmitted.len());
+                        },
+                        Ok(Submission::Orphaned { missing }) => {
+                            debug!("Holding orphan transaction until {} parent(s) arrive", missing.len());
+                        },
+                        Ok(_) => {},
+                        Err(MempoolError::Rejected(rejection)) => {
+                            debug!("Rejected transaction from {:?}: {} ({})", from_peer, rejection, rejection.reason());
                         },
-                        None => {
-                            error!("Failed to calculate transaction fee rate");
+                        Err(e) => error!("Failed to process transaction: {}", e),
+                    }
+
+                    if let Some(peer_id) = from_peer {
+                        if transaction_handler.orphan_score(&peer_id) >= ORPHAN_BAN_SCORE {
+                            transaction_handler.forget_peer(&peer_id);
+                            command_tx.send(NetworkCommand::BanPeer {
+                                peer_id,
+                                reason: "Relayed invalid orphan transactions".to_string(),
+                                duration: Some(Duration::from_secs(1800)),
+                            }).await.ok();
                         }
                     }
                 },
                 
+                // Handle a package received; its fee rate is judged as a whole
+                NetworkEvent::NewPackage { transactions, from_peer } => {
+                    debug!("Received package of {} transactions from {:?}", transactions.len(), from_peer);
+
+                    let acceptance = transaction_handler.submit_package(transactions, &*chain_state.read().await);
+                    match acceptance {
+                        Ok(acceptance) => {
+                            debug!("Package accepted: {} added, {} replaced, fee rate {}",
+                                   acceptance.accepted.len(), acceptance.replaced.len(), acceptance.fee_rate);
+                        },
+                        Err(MempoolError::Package(error)) => {
+                            debug!("Rejected package from {:?}: {} ({})", from_peer, error, error.reason());
+                        },
+                        Err(e) => error!("Failed to process package: {}", e),
+                    }
+                },
+                
                 // Handle block headers received
                 NetworkEvent::BlockHeaders { headers, total_difficulty, from_peer } => {
                     debug!("Received {} headers", headers.len());
@@ -599,7 +603,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                     debug!("Peer {} status: height={}, td={}", peer_id, height, total_difficulty);
                     
                     // Check if we need to sync
-                    let current_height = chain_state.get_height();
+                    let current_height = chain_state.read().await.get_height();
                     if height > current_height + 1 {
                         info!("Detected we're behind peer {} by {} blocks", peer_id, height - current_height);
                         
@@ -633,17 +637,23 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     drop(config_lock);
 
     // Announce initial status
+    let (height, best_hash, total_difficulty) = {
+        let chain = node_handle.chain_state.read().await;
+        (chain.get_height(), chain.get_best_block_hash(), chain.get_total_difficulty())
+    };
     command_tx_for_network.send(NetworkCommand::AnnounceStatus {
         version: 1,
-        height: node_handle.chain_state.get_height(),
-        best_hash: node_handle.chain_state.get_best_block_hash(),
-        total_difficulty: node_handle.chain_state.get_total_difficulty(),
+        height,
+        best_hash,
+        total_difficulty,
     }).await?;
 
     // Set up periodic tasks
     let mempool_config = node_handle.config.lock().await.mempool.transaction_timeout;
     let mut mempool_cleanup_interval = tokio::time::interval(mempool_config);
     let mut status_announcement_interval = tokio::time::interval(Duration::from_secs(120));
+    let persist_interval = node_handle.config.lock().await.mempool.persist_interval;
+    let mut mempool_persist_interval = tokio::time::interval(persist_interval);
     
     // Main event loop
     loop {
@@ -669,12 +679,18 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 }
             },
             
+            // Periodic mempool save, so a crash loses little
+            _ = mempool_persist_interval.tick() => {
+                save_mempool(&node_handle).await;
+            },
+            
             // Periodic status announcement
             _ = status_announcement_interval.tick() => {
                 // Announce our current status to the network
-                let current_height = node_handle.chain_state.get_height();
-                let best_hash = node_handle.chain_state.get_best_block_hash();
-                let total_difficulty = node_handle.chain_state.get_total_difficulty();
+                let (current_height, best_hash, total_difficulty) = {
+                    let chain = node_handle.chain_state.read().await;
+                    (chain.get_height(), chain.get_best_block_hash(), chain.get_total_difficulty())
+                };
                 
                 command_tx_for_network.send(NetworkCommand::AnnounceStatus {
                     version: 1,
@@ -684,12 +700,12 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 }).await.ok();
                 
                 // Log sync status
-                let sync_stats = sync_handle.get_stats();
+                let sync_stats = sync_handle.get_stats().await;
                 info!("Sync status: {}. Current height: {}, Target height: {}", 
                      sync_stats.state, sync_stats.current_height, sync_stats.target_height);
                 
                 // Log fork information
-                let fork_stats = sync_handle.get_fork_stats();
+                let fork_stats = sync_handle.get_fork_stats().await;
                 let active_forks = fork_stats.get("active_forks").unwrap_or(&0);
                 let max_fork_length = fork_stats.get("max_fork_length").unwrap_or(&0);
                 let reorg_count = fork_stats.get("reorg_count").unwrap_or(&0);
@@ -701,8 +717,8 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 }
                 
                 // Check if the tip is stale
-                if sync_handle.check_for_stale_tip() {
-                    let time_since_last = sync_handle.time_since_last_block().as_secs() / 60;
+                if sync_handle.check_for_stale_tip().await {
+                    let time_since_last = sync_handle.time_since_last_block().await.as_secs() / 60;
                     warn!("Chain tip is stale! No new blocks for {} minutes", time_since_last);
                 }
             },
@@ -726,6 +742,8 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         handle.abort();
     }
 
+    save_mempool(&node_handle).await;
+
     let config_lock = node_handle.config.lock().await;
     if config_lock.backup.enable_automated_backups {
         match node_handle.backup_manager.create_backup().await {
@@ -736,8 +754,10 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     // Final database integrity checkpoint before shutdown
     {
-        let height = node_handle.chain_state.get_height();
-        let block_hash = node_handle.chain_state.get_best_block_hash();
+        let (height, block_hash) = {
+            let chain = node_handle.chain_state.read().await;
+            (chain.get_height(), chain.get_best_block_hash())
+        };
         let mut handler = node_handle.corruption_handler.lock().await;
         if let Err(e) = handler.create_checkpoint(height, block_hash).await {
             warn!("Failed to create final integrity checkpoint: {}", e);
@@ -774,12 +794,28 @@ fn spawn_backup_task(node: &NodeHandle) -> Option<tokio::task::JoinHandle<()>> {
     }
 }
 
+// Write the mempool to the storage directory if persistence is enabled
+async fn save_mempool(node: &NodeHandle) {
+    let config = node.config.lock().await;
+    if !config.mempool.persist_mempool {
+        return;
+    }
+    let path = config.storage.db_path.join(MEMPOOL_FILE);
+    drop(config);
+
+    match node.mempool.save(&path) {
+        Ok(saved) => debug!("Saved {} mempool transactions to {:?}", saved, path),
+        Err(e) => warn!("Failed to save mempool to {:?}: {}", path, e),
+    }
+}
+
 // Spawn a thread to handle periodic database maintenance
 fn spawn_maintenance_task(node: &NodeHandle) -> tokio::task::JoinHandle<()> {
     // Create a safe clone of just what we need
-    let db = node.chain_state.get_db().clone();
+    let chain_state = Arc::clone(&node.chain_state);
     
     tokio::spawn(async move {
+        let db = Arc::clone(chain_state.read().await.get_db());
         let mut interval = tokio::time::interval(Duration::from_secs(3600));
         
         loop {
@@ -797,7 +833,7 @@ fn spawn_maintenance_task(node: &NodeHandle) -> tokio::task::JoinHandle<()> {
 // Spawn blockchain synchronization process
 fn spawn_sync_task(
     db: Arc<BlockchainDB>, 
-    chain_state: ChainState, 
+    chain_state: Arc<RwLock<ChainState>>,
     mut event_rx: mpsc::Receiver<NetworkEvent>
 ) -> tokio::task::JoinHandle<()> {
     tokio::spawn(async move {
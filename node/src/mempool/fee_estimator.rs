@@ -0,0 +1,262 @@
+//! Fee estimation from observed confirmation times
+//!
+//! Every transaction entering the mempool is tracked with the height it
+//! arrived at and its fee rate. When a block confirms it, the number of blocks
+//! it waited is recorded in the bucket of fee rates it falls into. Buckets
+//! keep exponentially decaying counts, so recent blocks weigh the most.
+//!
+//! An estimate for a target of `n` blocks walks the buckets from the highest
+//! fee rate down, grouping them until enough transactions are seen, and keeps
+//! going while at least [`SUCCESS_THRESHOLD`] of each group confirmed within
+//! `n` blocks. Transactions still waiting after `n` blocks count as failures.
+//! The estimate is the average fee rate of the cheapest passing group.
+
+use std::collections::HashMap;
+
+/// Highest confirmation target estimates are kept for
+pub const MAX_CONFIRMATION_TARGET: u32 = 48;
+
+/// Share of transactions that must confirm within the target for a fee rate to pass
+pub const SUCCESS_THRESHOLD: f64 = 0.85;
+
+/// Upper bound of the lowest bucket, in satoshis per byte
+const MIN_BUCKET_FEE_RATE: f64 = 1.0;
+/// Fee rates above this all share the last bucket
+const MAX_BUCKET_FEE_RATE: f64 = 10_000.0;
+/// Ratio between the bounds of neighbouring buckets
+const BUCKET_SPACING: f64 = 1.1;
+/// Weight each block keeps of what was seen before it; halves the weight about every 350 blocks
+const DECAY: f64 = 0.998;
+/// Decayed transaction count a group of buckets needs before it is judged
+const SUFFICIENT_TRANSACTIONS: f64 = 2.0;
+
+/// A fee rate expected to confirm within `target` blocks
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FeeEstimate {
+    /// Satoshis per byte
+    pub fee_rate: u64,
+    /// Target the estimate was found for, which may exceed the one asked for
+    pub target: u32,
+}
+
+/// Confirmation statistics of one range of fee rates
+#[derive(Debug, Clone)]
+struct Bucket {
+    /// Highest fee rate in the bucket
+    upper: f64,
+    confirmed: f64,
+    fee_rate_sum: f64,
+    /// `within[n - 1]`: transactions confirmed within `n` blocks
+    within: Vec<f64>,
+}
+
+/// A mempool transaction waiting to confirm
+#[derive(Debug, Clone, Copy)]
+struct Tracked {
+    height: u64,
+    fee_rate: u64,
+    bucket: usize,
+}
+
+/// Learns what fee rates confirm how quickly
+#[derive(Debug, Clone)]
+pub struct FeeEstimator {
+    buckets: Vec<Bucket>,
+    tracked: HashMap<[u8; 32], Tracked>,
+    best_height: u64,
+}
+
+impl Default for FeeEstimator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FeeEstimator {
+    pub fn new() -> Self {
+        let mut buckets = Vec::new();
+        let mut upper = MIN_BUCKET_FEE_RATE;
+        while upper < MAX_BUCKET_FEE_RATE * BUCKET_SPACING {
+            buckets.push(Bucket {
+                upper,
+                confirmed: 0.0,
+                fee_rate_sum: 0.0,
+                within: vec![0.0; MAX_CONFIRMATION_TARGET as usize],
+            });
+            upper *= BUCKET_SPACING;
+        }
+        Self { buckets, tracked: HashMap::new(), best_height: 0 }
+    }
+
+    /// Height of the last block processed
+    pub fn best_height(&self) -> u64 {
+        self.best_height
+    }
+
+    /// Transactions waiting to confirm
+    pub fn tracked_count(&self) -> usize {
+        self.tracked.len()
+    }
+
+    /// Start timing a transaction that just entered the mempool
+    pub fn track(&mut self, tx_hash: [u8; 32], fee_rate: u64) {
+        let bucket = self.bucket_index(fee_rate);
+        self.tracked.insert(tx_hash, Tracked { height: self.best_height, fee_rate, bucket });
+    }
+
+    /// Stop timing a transaction that left the mempool without confirming
+    pub fn untrack(&mut self, tx_hash: &[u8; 32]) {
+        self.tracked.remove(tx_hash);
+    }
+
+    /// Record the transactions confirmed by the block at `height`
+    ///
+    /// Blocks at or below the best height seen, as after a reorganization,
+    /// only stop the timing of their transactions, since how long those waited
+    /// is unclear. Returns how many tracked transactions the block confirmed.
+    pub fn process_block(&mut self, height: u64, confirmed: &[[u8; 32]]) -> usize {
+        if height <= self.best_height {
+            for tx_hash in confirmed {
+                self.tracked.remove(tx_hash);
+            }
+            return 0;
+        }
+        self.best_height = height;
+
+        for bucket in &mut self.buckets {
+            bucket.confirmed *= DECAY;
+            bucket.fee_rate_sum *= DECAY;
+            bucket.within.iter_mut().for_each(|count| *count *= DECAY);
+        }
+
+        let mut counted = 0;
+        for tx_hash in confirmed {
+            let Some(tracked) = self.tracked.remove(tx_hash) else {
+                continue;
+            };
+            let blocks = height.saturating_sub(tracked.height).max(1);
+            let bucket = &mut self.buckets[tracked.bucket];
+            bucket.confirmed += 1.0;
+            bucket.fee_rate_sum += tracked.fee_rate as f64;
+            for count in bucket.within.iter_mut().skip(blocks as usize - 1) {
+                *count += 1.0;
+            }
+            counted += 1;
+        }
+        counted
+    }
+
+    /// Lowest fee rate expected to confirm within `target` blocks, if enough is known
+    pub fn estimate_fee(&self, target: u32) -> Option<u64> {
+        if target == 0 || target > MAX_CONFIRMATION_TARGET {
+            return None;
+        }
+
+        // Transactions that have already waited longer than the target failed it
+        let mut waiting = vec![0.0; self.buckets.len()];
+        for tracked in self.tracked.values() {
+            if self.best_height.saturating_sub(tracked.height) >= target as u64 {
+                waiting[tracked.bucket] += 1.0;
+            }
+        }
+
+        let mut best = None;
+        let (mut within, mut confirmed, mut failed, mut fee_rate_sum) = (0.0, 0.0, 0.0, 0.0);
+        for (index, bucket) in self.buckets.iter().enumerate().rev() {
+            within += bucket.within[target as usize - 1];
+            confirmed += bucket.confirmed;
+            fee_rate_sum += bucket.fee_rate_sum;
+            failed += waiting[index];
+
+            let seen = confirmed + failed;
+            if seen < SUFFICIENT_TRANSACTIONS {
+                continue;
+            }
+            if within / seen < SUCCESS_THRESHOLD {
+                break;
+            }
+            best = Some((fee_rate_sum / confirmed).round() as u64);
+            (within, confirmed, failed, fee_rate_sum) = (0.0, 0.0, 0.0, 0.0);
+        }
+        best
+    }
+
+    /// Estimate for `target`, or for the nearest higher target that has one
+    pub fn estimate_smart_fee(&self, target: u32) -> Option<FeeEstimate> {
+        (target.max(1)..=MAX_CONFIRMATION_TARGET)
+            .find_map(|target| self.estimate_fee(target).map(|fee_rate| FeeEstimate { fee_rate, target }))
+    }
+
+    fn bucket_index(&self, fee_rate: u64) -> usize {
+        self.buckets
+            .iter()
+            .position(|bucket| fee_rate as f64 <= bucket.upper)
+            .unwrap_or(self.buckets.len() - 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hash(n: u32) -> [u8; 32] {
+        let mut hash = [0u8; 32];
+        hash[..4].copy_from_slice(&n.to_be_bytes());
+        hash
+    }
+
+    /// Each block confirms the 20 sat/byte transaction it brings and the 2 sat/byte one from six blocks earlier
+    fn estimator_with_history() -> FeeEstimator {
+        let mut estimator = FeeEstimator::new();
+        let mut next = 0;
+        let mut slow = Vec::new();
+        for height in 1..=40u64 {
+            let fast = hash(next);
+            estimator.track(fast, 20);
+            slow.push(hash(next + 1));
+            estimator.track(hash(next + 1), 2);
+            next += 2;
+
+            let mut confirmed = vec![fast];
+            if slow.len() > 5 {
+                confirmed.push(slow.remove(0));
+            }
+            estimator.process_block(height, &confirmed);
+        }
+        estimator
+    }
+
+    #[test]
+    fn test_estimates_follow_confirmation_times() {
+        let estimator = estimator_with_history();
+        assert_eq!(estimator.estimate_fee(1), Some(20));
+        assert_eq!(estimator.estimate_fee(2), Some(20));
+        assert_eq!(estimator.estimate_fee(6), Some(2));
+        assert_eq!(estimator.estimate_fee(0), None);
+        assert_eq!(estimator.estimate_fee(MAX_CONFIRMATION_TARGET + 1), None);
+
+        assert_eq!(estimator.estimate_smart_fee(3), Some(FeeEstimate { fee_rate: 20, target: 3 }));
+        assert_eq!(FeeEstimator::new().estimate_smart_fee(3), None);
+    }
+
+    #[test]
+    fn test_waiting_transactions_count_as_failures() {
+        let mut estimator = estimator_with_history();
+
+        // Cheap transactions stop confirming altogether
+        for n in 0..20 {
+            estimator.track(hash(1000 + n), 2);
+        }
+        for height in 41..=50 {
+            estimator.process_block(height, &[]);
+        }
+        assert_eq!(estimator.estimate_fee(6), Some(20));
+
+        // A replaced transaction is forgotten and an old block changes nothing
+        estimator.untrack(&hash(1000));
+        assert_eq!(estimator.tracked_count(), 24);
+        assert_eq!(estimator.process_block(45, &[hash(1001)]), 0);
+        assert_eq!(estimator.tracked_count(), 23);
+        assert_eq!(estimator.best_height(), 50);
+    }
+}
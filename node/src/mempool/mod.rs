@@ -1,7 +1,17 @@
 mod pool;
 pub mod prioritization;
+pub mod fee_estimator;
+pub mod orphan;
+pub mod package;
+pub mod persistence;
+pub mod validation;
 
-pub use pool::{TransactionPool, MempoolConfig, MempoolError};
+pub use pool::{TransactionPool, MempoolConfig, MempoolError, Submission};
+pub use fee_estimator::{FeeEstimate, FeeEstimator};
+pub use orphan::{OrphanError, OrphanPool};
+pub use package::{PackageAcceptance, PackageError};
+pub use persistence::{LoadStats, MempoolSnapshot, PersistenceError, MEMPOOL_FILE};
+pub use validation::{ChainView, CheckedTransaction, Coin, TxRejection};
 pub use prioritization::{
     TransactionPrioritizer,
     PrioritizationConfig,
@@ -0,0 +1,263 @@
+//! Transactions waiting for their parents
+//!
+//! A transaction spending outputs the node has not seen yet is kept here
+//! instead of being dropped, in case its parents arrive shortly after. The
+//! pool is bounded in count, per peer and per transaction size, and orphans
+//! expire after [`ORPHAN_EXPIRY`].
+//!
+//! Peers relaying orphans that turn out to be invalid, or that keep pushing
+//! past the limits, collect a misbehavior score; once it reaches
+//! [`ORPHAN_BAN_SCORE`] the node should disconnect them.
+
+use btclib::types::transaction::Transaction;
+use libp2p::PeerId;
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, SystemTime};
+use thiserror::Error;
+
+/// How long an orphan waits for its parents
+pub const ORPHAN_EXPIRY: Duration = Duration::from_secs(20 * 60);
+
+/// Largest transaction kept as an orphan, in bytes
+pub const MAX_ORPHAN_SIZE: usize = 100_000;
+
+/// Orphans a single peer may have waiting at once
+pub const MAX_ORPHANS_PER_PEER: usize = 25;
+
+/// Misbehavior score at which a peer should be banned
+pub const ORPHAN_BAN_SCORE: u32 = 100;
+
+/// Score for relaying an orphan above [`MAX_ORPHAN_SIZE`]
+const OVERSIZED_PENALTY: u32 = 10;
+/// Score for relaying an orphan beyond [`MAX_ORPHANS_PER_PEER`]
+const PEER_LIMIT_PENALTY: u32 = 1;
+/// Score for an orphan rejected once its parents arrived
+const INVALID_PENALTY: u32 = 20;
+
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum OrphanError {
+    #[error("Orphan transaction of {0} bytes is too large")]
+    TooLarge(usize),
+    #[error("Peer has too many orphan transactions waiting")]
+    PeerLimit,
+}
+
+/// A transaction missing some of its parents
+#[derive(Debug, Clone)]
+pub struct Orphan {
+    pub transaction: Transaction,
+    /// Peer that relayed it; `None` for local submissions
+    pub peer: Option<PeerId>,
+    received: SystemTime,
+    /// Arrival order, to find the oldest orphan even within one clock tick
+    sequence: u64,
+}
+
+/// Bounded store of orphan transactions, indexed by the parents they spend
+#[derive(Debug)]
+pub struct OrphanPool {
+    orphans: HashMap<[u8; 32], Orphan>,
+    /// Orphans spending an output of each parent
+    by_parent: HashMap<[u8; 32], HashSet<[u8; 32]>>,
+    scores: HashMap<PeerId, u32>,
+    max_orphans: usize,
+    next_sequence: u64,
+}
+
+impl OrphanPool {
+    pub fn new(max_orphans: usize) -> Self {
+        Self {
+            orphans: HashMap::new(),
+            by_parent: HashMap::new(),
+            scores: HashMap::new(),
+            max_orphans,
+            next_sequence: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.orphans.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.orphans.is_empty()
+    }
+
+    pub fn contains(&self, tx_hash: &[u8; 32]) -> bool {
+        self.orphans.contains_key(tx_hash)
+    }
+
+    /// Keep `transaction` until its parents arrive
+    ///
+    /// When the pool is full, expired orphans go first, then the oldest one.
+    pub fn add(&mut self, transaction: Transaction, peer: Option<PeerId>) -> Result<(), OrphanError> {
+        let tx_hash = transaction.hash();
+        if self.orphans.contains_key(&tx_hash) {
+            return Ok(());
+        }
+
+        let size = bincode::serialized_size(&transaction).map_or(usize::MAX, |size| size as usize);
+        if size > MAX_ORPHAN_SIZE {
+            if let Some(peer) = &peer {
+                self.penalize(peer, OVERSIZED_PENALTY);
+            }
+            return Err(OrphanError::TooLarge(size));
+        }
+        if let Some(peer) = &peer {
+            let waiting = self.orphans.values().filter(|orphan| orphan.peer.as_ref() == Some(peer)).count();
+            if waiting >= MAX_ORPHANS_PER_PEER {
+                self.penalize(peer, PEER_LIMIT_PENALTY);
+                return Err(OrphanError::PeerLimit);
+            }
+        }
+
+        if self.orphans.len() >= self.max_orphans {
+            self.expire();
+        }
+        while self.orphans.len() >= self.max_orphans {
+            let oldest = self.orphans
+                .iter()
+                .min_by_key(|(_, orphan)| orphan.sequence)
+                .map(|(hash, _)| *hash);
+            match oldest {
+                Some(hash) => {
+                    self.remove(&hash);
+                }
+                None => break,
+            }
+        }
+
+        for parent in parents(&transaction) {
+            self.by_parent.entry(parent).or_default().insert(tx_hash);
+        }
+        let sequence = self.next_sequence;
+        self.next_sequence += 1;
+        self.orphans.insert(tx_hash, Orphan { transaction, peer, received: SystemTime::now(), sequence });
+        Ok(())
+    }
+
+    /// Remove and return the orphans spending an output of `parent`
+    pub fn take_children(&mut self, parent: &[u8; 32]) -> Vec<Orphan> {
+        let children = self.by_parent.remove(parent).unwrap_or_default();
+        children.iter().filter_map(|hash| self.remove(hash)).collect()
+    }
+
+    pub fn remove(&mut self, tx_hash: &[u8; 32]) -> Option<Orphan> {
+        let orphan = self.orphans.remove(tx_hash)?;
+        for parent in parents(&orphan.transaction) {
+            if let Some(children) = self.by_parent.get_mut(&parent) {
+                children.remove(tx_hash);
+                if children.is_empty() {
+                    self.by_parent.remove(&parent);
+                }
+            }
+        }
+        Some(orphan)
+    }
+
+    /// Drop orphans older than [`ORPHAN_EXPIRY`], returning how many
+    pub fn expire(&mut self) -> usize {
+        let now = SystemTime::now();
+        let expired: Vec<[u8; 32]> = self.orphans
+            .iter()
+            .filter(|(_, orphan)| now.duration_since(orphan.received).unwrap_or(Duration::ZERO) > ORPHAN_EXPIRY)
+            .map(|(hash, _)| *hash)
+            .collect();
+        for hash in &expired {
+            self.remove(hash);
+        }
+        expired.len()
+    }
+
+    /// Record that an orphan from `peer` was rejected once its parents arrived
+    pub fn penalize_invalid(&mut self, peer: &PeerId) -> u32 {
+        self.penalize(peer, INVALID_PENALTY)
+    }
+
+    /// Misbehavior score of `peer`
+    pub fn score(&self, peer: &PeerId) -> u32 {
+        self.scores.get(peer).copied().unwrap_or(0)
+    }
+
+    /// Drop the orphans and score of a disconnected peer, returning how many orphans went
+    pub fn forget_peer(&mut self, peer: &PeerId) -> usize {
+        self.scores.remove(peer);
+        let relayed: Vec<[u8; 32]> = self.orphans
+            .iter()
+            .filter(|(_, orphan)| orphan.peer.as_ref() == Some(peer))
+            .map(|(hash, _)| *hash)
+            .collect();
+        for hash in &relayed {
+            self.remove(hash);
+        }
+        relayed.len()
+    }
+
+    fn penalize(&mut self, peer: &PeerId, points: u32) -> u32 {
+        let score = self.scores.entry(*peer).or_insert(0);
+        *score = score.saturating_add(points);
+        *score
+    }
+}
+
+/// Distinct transactions whose outputs `transaction` spends
+fn parents(transaction: &Transaction) -> HashSet<[u8; 32]> {
+    transaction.inputs().iter().map(|input| input.prev_tx_hash()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use btclib::types::transaction::{TransactionInput, TransactionOutput};
+
+    fn spend(parent: [u8; 32], value: u64) -> Transaction {
+        Transaction::new(
+            1,
+            vec![TransactionInput::new(parent, 0, vec![], 0xffffffff)],
+            vec![TransactionOutput::new(value, vec![])],
+            0,
+        )
+    }
+
+    #[test]
+    fn test_orphans_are_bounded_and_released_by_parent() {
+        let mut pool = OrphanPool::new(3);
+        for value in 1..=4 {
+            pool.add(spend([value as u8; 32], value), None).unwrap();
+        }
+        // The oldest orphan made room for the fourth
+        assert_eq!(pool.len(), 3);
+        assert!(!pool.contains(&spend([1u8; 32], 1).hash()));
+
+        let children = pool.take_children(&[3u8; 32]);
+        assert_eq!(children.len(), 1);
+        assert_eq!(children[0].transaction, spend([3u8; 32], 3));
+        assert_eq!(pool.len(), 2);
+        assert!(pool.take_children(&[3u8; 32]).is_empty());
+    }
+
+    #[test]
+    fn test_peers_are_scored_for_abuse() {
+        let mut pool = OrphanPool::new(100);
+        let peer = PeerId::random();
+        for value in 0..MAX_ORPHANS_PER_PEER as u64 {
+            pool.add(spend([7u8; 32], value), Some(peer)).unwrap();
+        }
+        assert_eq!(pool.add(spend([7u8; 32], 1_000), Some(peer)), Err(OrphanError::PeerLimit));
+        assert_eq!(pool.score(&peer), PEER_LIMIT_PENALTY);
+
+        let oversized = Transaction::new(
+            1,
+            vec![TransactionInput::new([8u8; 32], 0, vec![0; MAX_ORPHAN_SIZE], 0xffffffff)],
+            vec![],
+            0,
+        );
+        let other = PeerId::random();
+        assert!(matches!(pool.add(oversized, Some(other)), Err(OrphanError::TooLarge(_))));
+        assert_eq!(pool.penalize_invalid(&other), OVERSIZED_PENALTY + INVALID_PENALTY);
+
+        assert_eq!(pool.forget_peer(&peer), MAX_ORPHANS_PER_PEER);
+        assert_eq!(pool.score(&peer), 0);
+        assert!(pool.is_empty());
+    }
+}
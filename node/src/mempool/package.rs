@@ -0,0 +1,221 @@
+//! Packages of related transactions submitted and relayed together
+//!
+//! A package is a child and the unconfirmed parents it spends, sorted parents
+//! first. Its fee rate is judged as a whole, so a child can pay for a parent
+//! below the pool's minimum fee rate (anchor outputs, batched settlements).
+//! Packages may replace conflicting pool transactions under the rules in
+//! [`crate::mempool::TransactionPool::submit_package`].
+
+use btclib::types::transaction::Transaction;
+use std::collections::HashSet;
+use thiserror::Error;
+use super::validation::TxRejection;
+
+/// Most transactions a package may hold
+pub const MAX_PACKAGE_COUNT: usize = 25;
+
+/// Largest serialized size of a package, in bytes
+pub const MAX_PACKAGE_SIZE: usize = 101_000;
+
+/// Most pool transactions, descendants included, a package may replace
+///
+/// Bounds the work an attacker can force and keeps a cheap but large set of
+/// descendants from pinning a transaction the package wants to replace.
+pub const MAX_REPLACEMENT_CANDIDATES: usize = 100;
+
+/// Why a package was refused by the mempool
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum PackageError {
+    #[error("Package is empty")]
+    Empty,
+
+    #[error("Package holds {0} transactions, more than {MAX_PACKAGE_COUNT}")]
+    TooManyTransactions(usize),
+
+    #[error("Package is {0} bytes, more than {MAX_PACKAGE_SIZE}")]
+    TooLarge(usize),
+
+    #[error("Transaction {0} appears twice in the package")]
+    Duplicate(String),
+
+    #[error("Transaction {0} comes before a parent in the package")]
+    NotSorted(String),
+
+    #[error("Package transactions must all be parents of its last transaction")]
+    NotChildWithParents,
+
+    #[error("Package transactions spend output {0}:{1} twice")]
+    ConflictInPackage(String, u32),
+
+    #[error("Package transaction {0} rejected: {1}")]
+    Transaction(String, TxRejection),
+
+    #[error("Package fee rate {fee_rate} is below the minimum of {min_fee_rate}")]
+    FeeTooLow { fee_rate: u64, min_fee_rate: u64 },
+
+    #[error("Package would replace {0} transactions, more than {MAX_REPLACEMENT_CANDIDATES}")]
+    TooManyReplacements(usize),
+
+    #[error("Package pays {fee}, replacing its conflicts requires at least {required}")]
+    InsufficientFee { fee: u64, required: u64 },
+
+    #[error("Package fee rate {fee_rate} does not exceed the {conflict_fee_rate} of a transaction it replaces")]
+    LowerFeeRate { fee_rate: u64, conflict_fee_rate: u64 },
+
+    #[error("Package transaction {0} spends a transaction it replaces")]
+    SpendsConflicting(String),
+}
+
+impl PackageError {
+    /// Reason string for RPC callers, following Bitcoin Core where it has one
+    pub fn reason(&self) -> &'static str {
+        match self {
+            PackageError::Empty => "package-empty",
+            PackageError::TooManyTransactions(_) => "package-too-many-transactions",
+            PackageError::TooLarge(_) => "package-too-large",
+            PackageError::Duplicate(_) => "package-contains-duplicates",
+            PackageError::NotSorted(_) => "package-not-sorted",
+            PackageError::NotChildWithParents => "package-not-child-with-parents",
+            PackageError::ConflictInPackage(..) => "conflict-in-package",
+            PackageError::Transaction(_, rejection) => rejection.reason(),
+            PackageError::FeeTooLow { .. } => "package-fee-too-low",
+            PackageError::TooManyReplacements(_) => "too-many-replacements",
+            PackageError::InsufficientFee { .. } => "insufficient-fee",
+            PackageError::LowerFeeRate { .. } => "package-rbf-feerate-too-low",
+            PackageError::SpendsConflicting(_) => "bad-txns-spends-conflicting-tx",
+        }
+    }
+
+    /// Whether the package can never become valid, as opposed to not at the pool's current fees
+    pub fn is_invalid(&self) -> bool {
+        match self {
+            PackageError::Transaction(_, rejection) => rejection.is_invalid(),
+            PackageError::FeeTooLow { .. }
+            | PackageError::TooManyReplacements(_)
+            | PackageError::InsufficientFee { .. }
+            | PackageError::LowerFeeRate { .. }
+            | PackageError::SpendsConflicting(_) => false,
+            _ => true,
+        }
+    }
+}
+
+/// Outcome of [`crate::mempool::TransactionPool::submit_package`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackageAcceptance {
+    /// Transactions added to the pool, in package order
+    pub accepted: Vec<[u8; 32]>,
+    /// Package transactions the pool already held, which count neither for nor against it
+    pub already_in_pool: Vec<[u8; 32]>,
+    /// Pool transactions the package replaced
+    pub replaced: Vec<[u8; 32]>,
+    /// Orphans waiting on the package that could then be added
+    pub resubmitted: Vec<[u8; 32]>,
+    /// Fee paid by the accepted transactions
+    pub fee: u64,
+    /// Serialized size of the accepted transactions in bytes
+    pub size: usize,
+    /// Satoshis per byte over the accepted transactions, rounded down
+    pub fee_rate: u64,
+}
+
+/// Check the shape of a package before any of it is validated
+///
+/// The package must be non-empty, within [`MAX_PACKAGE_COUNT`] and
+/// [`MAX_PACKAGE_SIZE`], free of duplicates and internal double spends,
+/// sorted parents first, and made of one child and parents it spends.
+pub fn check_package(transactions: &[Transaction]) -> Result<(), PackageError> {
+    let child = transactions.last().ok_or(PackageError::Empty)?;
+    if transactions.len() > MAX_PACKAGE_COUNT {
+        return Err(PackageError::TooManyTransactions(transactions.len()));
+    }
+    let size: usize = transactions
+        .iter()
+        .map(|tx| bincode::serialized_size(tx).map_or(usize::MAX, |size| size as usize))
+        .fold(0, usize::saturating_add);
+    if size > MAX_PACKAGE_SIZE {
+        return Err(PackageError::TooLarge(size));
+    }
+
+    let hashes: Vec<[u8; 32]> = transactions.iter().map(|tx| tx.hash()).collect();
+    let mut seen = HashSet::new();
+    for hash in &hashes {
+        if !seen.insert(*hash) {
+            return Err(PackageError::Duplicate(hex::encode(hash)));
+        }
+    }
+
+    // A transaction may only spend package transactions listed before it
+    let mut spent = HashSet::new();
+    for (position, tx) in transactions.iter().enumerate() {
+        for input in tx.inputs() {
+            let outpoint = (input.prev_tx_hash(), input.prev_output_index());
+            if !spent.insert(outpoint) {
+                return Err(PackageError::ConflictInPackage(hex::encode(outpoint.0), outpoint.1));
+            }
+            if hashes[position..].contains(&outpoint.0) {
+                return Err(PackageError::NotSorted(hex::encode(hashes[position])));
+            }
+        }
+    }
+
+    let parents: HashSet<[u8; 32]> = child.inputs().iter().map(|input| input.prev_tx_hash()).collect();
+    if !hashes[..hashes.len() - 1].iter().all(|hash| parents.contains(hash)) {
+        return Err(PackageError::NotChildWithParents);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use btclib::types::transaction::{TransactionInput, TransactionOutput};
+
+    fn spending(outpoints: &[([u8; 32], u32)]) -> Transaction {
+        Transaction::new(
+            1,
+            outpoints.iter().map(|(hash, index)| TransactionInput::new(*hash, *index, vec![], 0)).collect(),
+            vec![TransactionOutput::new(1_000, vec![]), TransactionOutput::new(1_000, vec![])],
+            0,
+        )
+    }
+
+    #[test]
+    fn test_package_shape() {
+        let parent_a = spending(&[([1u8; 32], 0)]);
+        let parent_b = spending(&[([2u8; 32], 0)]);
+        let child = spending(&[(parent_a.hash(), 0), (parent_b.hash(), 1)]);
+        assert_eq!(check_package(&[parent_a.clone(), parent_b.clone(), child.clone()]), Ok(()));
+        assert_eq!(check_package(&[child.clone()]), Ok(()));
+        assert_eq!(check_package(&[]), Err(PackageError::Empty));
+
+        assert_eq!(
+            check_package(&[child.clone(), parent_a.clone()]).unwrap_err().reason(),
+            "package-not-sorted"
+        );
+        assert_eq!(
+            check_package(&[parent_a.clone(), parent_a.clone(), child.clone()]).unwrap_err().reason(),
+            "package-contains-duplicates"
+        );
+
+        // Every transaction but the last must be a parent of it
+        let unrelated = spending(&[([3u8; 32], 0)]);
+        assert_eq!(
+            check_package(&[unrelated, parent_a.clone(), child.clone()]),
+            Err(PackageError::NotChildWithParents)
+        );
+
+        // Two package transactions may not spend the same output
+        let rival = spending(&[([1u8; 32], 0), (parent_a.hash(), 1)]);
+        assert_eq!(
+            check_package(&[parent_a.clone(), rival]).unwrap_err().reason(),
+            "conflict-in-package"
+        );
+
+        let too_many: Vec<Transaction> = (0..=MAX_PACKAGE_COUNT as u8).map(|seed| spending(&[([seed; 32], 0)])).collect();
+        assert_eq!(
+            check_package(&too_many),
+            Err(PackageError::TooManyTransactions(MAX_PACKAGE_COUNT + 1))
+        );
+    }
+}
@@ -0,0 +1,120 @@
+//! Saving the mempool across restarts
+//!
+//! The pool is written as a versioned bincode snapshot holding its
+//! transactions, parents first, with the time each entered the pool, and the
+//! fee deltas set through `prioritisetransaction`. Loading runs every
+//! transaction through acceptance again against the current tip, so whatever
+//! was mined or invalidated while the node was down is dropped.
+
+use btclib::types::transaction::Transaction;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+/// Name of the mempool file in the storage directory
+pub const MEMPOOL_FILE: &str = "mempool.dat";
+
+/// Format version written at the start of the mempool file
+pub const MEMPOOL_DUMP_VERSION: u32 = 1;
+
+/// Error reading or writing the mempool file
+#[derive(Debug, Error)]
+pub enum PersistenceError {
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Malformed mempool file: {0}")]
+    Malformed(#[from] bincode::Error),
+
+    #[error("Unsupported mempool file version {0}")]
+    UnsupportedVersion(u32),
+}
+
+/// A pool transaction as saved to disk
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SavedTransaction {
+    pub transaction: Transaction,
+    /// Seconds since the UNIX epoch at which it entered the pool
+    pub entry_time: u64,
+}
+
+/// Contents of the mempool file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MempoolSnapshot {
+    /// Always [`MEMPOOL_DUMP_VERSION`] when written by this version
+    pub version: u32,
+    /// Parents before the children spending them
+    pub transactions: Vec<SavedTransaction>,
+    /// Fee adjustments by transaction hash, including for transactions not in the pool
+    pub fee_deltas: Vec<([u8; 32], i64)>,
+}
+
+impl MempoolSnapshot {
+    /// Write the snapshot to `path`, replacing any previous file only once it is complete
+    pub fn write(&self, path: &Path) -> Result<(), PersistenceError> {
+        let mut partial = PathBuf::from(path);
+        partial.set_extension("new");
+        fs::write(&partial, bincode::serialize(self)?)?;
+        fs::rename(&partial, path)?;
+        Ok(())
+    }
+
+    /// Read a snapshot written by [`Self::write`]
+    pub fn read(path: &Path) -> Result<Self, PersistenceError> {
+        let bytes = fs::read(path)?;
+        // The version leads the file, so it can be checked before the layout it decides
+        let version: u32 = bincode::deserialize(&bytes)?;
+        if version != MEMPOOL_DUMP_VERSION {
+            return Err(PersistenceError::UnsupportedVersion(version));
+        }
+        Ok(bincode::deserialize(&bytes)?)
+    }
+}
+
+/// What became of the transactions of a loaded snapshot
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LoadStats {
+    /// Added back to the pool
+    pub accepted: usize,
+    /// Rejected on revalidation, typically because they were mined or conflict with the chain
+    pub failed: usize,
+    /// Older than the pool's maximum age
+    pub expired: usize,
+    /// Already in the pool
+    pub already_there: usize,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use btclib::types::transaction::{TransactionInput, TransactionOutput};
+
+    #[test]
+    fn test_snapshot_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(MEMPOOL_FILE);
+        let transaction = Transaction::new(
+            1,
+            vec![TransactionInput::new([1u8; 32], 0, vec![], 0)],
+            vec![TransactionOutput::new(1_000, vec![])],
+            0,
+        );
+        let snapshot = MempoolSnapshot {
+            version: MEMPOOL_DUMP_VERSION,
+            transactions: vec![SavedTransaction { transaction, entry_time: 1_700_000_000 }],
+            fee_deltas: vec![([2u8; 32], -500)],
+        };
+
+        snapshot.write(&path).unwrap();
+        assert_eq!(MempoolSnapshot::read(&path).unwrap(), snapshot);
+        assert!(!path.with_extension("new").exists());
+
+        let future = MempoolSnapshot { version: MEMPOOL_DUMP_VERSION + 1, ..snapshot };
+        future.write(&path).unwrap();
+        assert!(matches!(
+            MempoolSnapshot::read(&path),
+            Err(PersistenceError::UnsupportedVersion(version)) if version == MEMPOOL_DUMP_VERSION + 1
+        ));
+    }
+}
@@ -1,7 +1,32 @@
 use dashmap::DashMap;
-use btclib::types::transaction::Transaction;
-use std::time::{Duration, SystemTime};
+use btclib::types::transaction::{Transaction, TransactionOutput};
+use libp2p::PeerId;
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::path::Path;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use async_trait::async_trait;
+use miner::mining::MempoolInterface;
 use crate::config;
+use crate::events::{EventBus, NodeEvent, RemovalReason};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::RwLock;
+use tracing::warn;
+use super::fee_estimator::{FeeEstimate, FeeEstimator};
+use super::orphan::{OrphanError, OrphanPool};
+use super::package::{self, PackageAcceptance, PackageError, MAX_REPLACEMENT_CANDIDATES};
+use super::persistence::{LoadStats, MempoolSnapshot, PersistenceError, SavedTransaction, MEMPOOL_DUMP_VERSION};
+use super::prioritization::{PrioritizationConfig, TransactionPrioritizer};
+use super::validation::{self, ChainView, CheckedTransaction, Coin, TxRejection};
+
+/// Fee rate, in satoshis per byte, by which the rolling minimum fee exceeds an evicted package
+pub const INCREMENTAL_RELAY_FEE: u64 = 1;
+
+/// Time for the rolling minimum fee to halve while the pool is at least half full
+///
+/// Emptier pools let it decay two or four times faster.
+pub const ROLLING_FEE_HALFLIFE: Duration = Duration::from_secs(12 * 3600);
 
 /// Configuration for the transaction memory pool
 #[derive(Debug, Clone)]
@@ -12,6 +37,10 @@ pub struct MempoolConfig {
     pub max_age: u64,
     /// Minimum fee rate (satoshis per byte) for acceptance
     pub min_fee_rate: u64,
+    /// Maximum number of transactions in the pool paying the same output script
+    pub max_per_address: usize,
+    /// Maximum number of transactions waiting for missing parents
+    pub max_orphans: usize,
     /// Whether Replace-By-Fee is enabled
     pub enable_rbf: bool,
     /// Minimum fee increase required for RBF (as a percentage)
@@ -24,6 +53,8 @@ impl From<config::MempoolConfig> for MempoolConfig {
             max_size: config.max_size,
             max_age: config.transaction_timeout.as_secs(),
             min_fee_rate: config.min_fee_rate as u64,
+            max_per_address: config.max_per_address,
+            max_orphans: config.max_orphan_transactions,
             enable_rbf: config.enable_rbf,
             min_rbf_fee_increase: config.min_rbf_fee_increase,
         }
@@ -36,6 +67,8 @@ impl Default for MempoolConfig {
             max_size: 5000,         // Default to 5000 transactions
             max_age: 72 * 3600,     // 72 hours in seconds
             min_fee_rate: 1,        // 1 satoshi per byte
+            max_per_address: 100,
+            max_orphans: 100,
             enable_rbf: true,       // Enable RBF by default
             min_rbf_fee_increase: 10.0, // 10% minimum fee increase
         }
@@ -43,20 +76,53 @@ impl Default for MempoolConfig {
 }
 
 /// Entry in the mempool containing a transaction and metadata
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 struct MempoolEntry {
     transaction: Transaction,
     timestamp: SystemTime,
+    fee: u64,         // Satoshis
     fee_rate: u64,    // Satoshis per byte
     size: usize,      // Size in bytes
 }
 
+/// Minimum fee rate raised by evictions, decaying from when it was last raised
+#[derive(Debug)]
+struct RollingFee {
+    rate: f64,
+    updated: SystemTime,
+}
+
 /// Thread-safe transaction pool implementation
 pub struct TransactionPool {
     /// Main storage using DashMap for thread-safety
     transactions: DashMap<[u8; 32], MempoolEntry>,
     /// Configuration settings
     config: MempoolConfig,
+    /// Incremented whenever the set of transactions changes
+    sequence: AtomicU64,
+    /// Confirmation times of the transactions that passed through the pool
+    estimator: Mutex<FeeEstimator>,
+    /// Transactions in the pool paying each output script
+    script_counts: DashMap<Vec<u8>, usize>,
+    /// Transactions waiting for parents the node has not seen
+    orphans: Mutex<OrphanPool>,
+    /// Ancestor and descendant packages, for eviction and block templates
+    packages: Mutex<TransactionPrioritizer>,
+    /// Fee rate newcomers must pay after the pool had to evict
+    rolling_fee: Mutex<RollingFee>,
+    /// Adjustments from `prioritisetransaction` to the fee transactions are mined and evicted by
+    fee_deltas: DashMap<[u8; 32], i64>,
+    /// Where additions and removals are announced, if anywhere
+    events: Option<EventBus>,
+}
+
+/// Outcome of [`TransactionPool::submit_transaction`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Submission {
+    /// Added to the pool, along with the orphans waiting on it that could then be added
+    Accepted { resubmitted: Vec<[u8; 32]> },
+    /// Kept aside until the listed parents arrive
+    Orphaned { missing: Vec<[u8; 32]> },
 }
 
 impl TransactionPool {
@@ -64,12 +130,37 @@ impl TransactionPool {
     pub fn new(config: MempoolConfig) -> Self {
         Self {
             transactions: DashMap::new(),
+            sequence: AtomicU64::new(0),
+            estimator: Mutex::new(FeeEstimator::new()),
+            script_counts: DashMap::new(),
+            orphans: Mutex::new(OrphanPool::new(config.max_orphans)),
+            packages: Mutex::new(TransactionPrioritizer::new(PrioritizationConfig::default())),
+            rolling_fee: Mutex::new(RollingFee { rate: 0.0, updated: SystemTime::now() }),
+            fee_deltas: DashMap::new(),
+            events: None,
             config,
         }
     }
 
-    /// Add a transaction to the pool
-    pub fn add_transaction(&self, transaction: Transaction, fee_rate: u64) -> Result<(), MempoolError> {
+    /// Announce every transaction added to or removed from the pool on `events`
+    pub fn with_event_bus(mut self, events: EventBus) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    /// Add a transaction to the pool at a fee rate the caller vouches for
+    ///
+    /// Nothing about its inputs is checked; transactions from peers and users
+    /// go through [`Self::accept_transaction`] instead.
+    pub(crate) fn add_transaction(&self, transaction: Transaction, fee_rate: u64) -> Result<(), MempoolError> {
+        let size = bincode::serialized_size(&transaction).map_err(|_| MempoolError::SerializationError)? as usize;
+        self.add_entry(transaction, fee_rate.saturating_mul(size as u64), size)
+    }
+
+    /// Add a transaction paying `fee`, evicting the packages least worth mining if the pool overflows
+    ///
+    /// Fails with `PoolFull` when the newcomer itself is the one evicted.
+    fn add_entry(&self, transaction: Transaction, fee: u64, size: usize) -> Result<(), MempoolError> {
         let tx_hash = transaction.hash();
         
         // Check if transaction already exists
@@ -77,41 +168,714 @@ impl TransactionPool {
             return Err(MempoolError::DuplicateTransaction);
         }
 
-        // Check pool size limit
-        if self.transactions.len() >= self.config.max_size {
-            return Err(MempoolError::PoolFull);
+        // Check minimum fee rate, raised by recent evictions
+        let fee_rate = self.modified_fee(&tx_hash, fee) / size.max(1) as u64;
+        if fee_rate < self.min_fee_rate() {
+            return Err(MempoolError::FeeTooLow);
         }
 
-        // Check minimum fee rate
-        if fee_rate < self.config.min_fee_rate {
-            return Err(MempoolError::FeeTooLow);
+        self.insert_entry(transaction, fee, size)?;
+        if self.transactions.len() > self.config.max_size {
+            self.trim_to_size();
+            if !self.transactions.contains_key(&tx_hash) {
+                return Err(MempoolError::PoolFull);
+            }
         }
+        Ok(())
+    }
 
-        // Calculate transaction size
-        let tx_size = bincode::serialize(&transaction)
-            .map_err(|_| MempoolError::SerializationError)?
-            .len();
+    /// Insert a transaction paying `fee` whatever its fee rate, without trimming the pool
+    fn insert_entry(&self, transaction: Transaction, fee: u64, size: usize) -> Result<(), MempoolError> {
+        let tx_hash = transaction.hash();
+        let fee_rate = fee / size.max(1) as u64;
+        self.check_address_limit(&transaction, &[])?;
+        let modified_fee = self.modified_fee(&tx_hash, fee);
+        if !self.packages().add_with_fee(transaction.clone(), modified_fee, size) {
+            return Err(MempoolError::ChainLimitExceeded);
+        }
 
         // Create and insert new entry
+        self.index_scripts(&transaction);
         let entry = MempoolEntry {
             transaction,
             timestamp: SystemTime::now(),
+            fee,
             fee_rate,
-            size: tx_size,
+            size,
         };
 
+        if let Some(events) = &self.events {
+            events.publish(NodeEvent::MempoolAdded { transaction: Arc::new(entry.transaction.clone()), fee, size });
+        }
         self.transactions.insert(tx_hash, entry);
+        self.sequence.fetch_add(1, Ordering::Relaxed);
+        self.estimator().track(tx_hash, fee_rate);
         Ok(())
     }
 
-    /// Remove a transaction from the pool
+    /// Remove a transaction from the pool, along with the transactions spending it
     pub fn remove_transaction(&self, tx_hash: &[u8; 32]) -> Option<Transaction> {
-        match self.transactions.remove(tx_hash) {
-            Some((_, entry)) => Some(entry.transaction),
-            None => None
+        self.remove_with_descendants(tx_hash, RemovalReason::Removed).pop().map(|entry| entry.transaction)
+    }
+
+    /// Remove the transactions a newly connected block confirms and learn from their wait
+    ///
+    /// Returns how many of the block's transactions were in the pool.
+    pub fn remove_confirmed(&self, height: u64, transactions: &[Transaction]) -> usize {
+        let hashes: Vec<[u8; 32]> = transactions.iter().map(|tx| tx.hash()).collect();
+        let removed = hashes.iter().filter(|hash| self.remove_entry(hash, RemovalReason::Confirmed).is_some()).count();
+        for hash in &hashes {
+            self.fee_deltas.remove(hash);
+        }
+        if removed > 0 {
+            self.sequence.fetch_add(1, Ordering::Relaxed);
+        }
+        self.estimator().process_block(height, &hashes);
+        removed
+    }
+
+    /// Update the pool for every block `events` reports connected, wherever it came from
+    ///
+    /// The block's transactions are removed and the orphans spending them
+    /// retried against `chain`.
+    pub fn follow_chain<C>(self: &Arc<Self>, events: &EventBus, chain: Arc<RwLock<C>>)
+    where
+        C: ChainView + Send + Sync + 'static,
+    {
+        let mut receiver = events.subscribe();
+        let pool = Arc::clone(self);
+        tokio::spawn(async move {
+            loop {
+                match receiver.recv().await {
+                    Ok(NodeEvent::BlockConnected { block, height }) => {
+                        pool.remove_confirmed(height, block.transactions().get(1..).unwrap_or_default());
+                        let confirmed: Vec<[u8; 32]> = block.transactions().iter().map(|tx| tx.hash()).collect();
+                        pool.resubmit_orphans(&confirmed, &*chain.read().await);
+                    }
+                    Ok(_) => {}
+                    // The missed blocks' transactions stay until they expire or conflict
+                    Err(RecvError::Lagged(skipped)) => {
+                        warn!("Mempool missed {} chain events", skipped);
+                    }
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        });
+    }
+
+    /// Lowest fee rate, in satoshis per byte, a transaction needs to enter the pool
+    ///
+    /// This is the configured minimum until the pool has to evict, which raises
+    /// it above the evicted packages. From there it decays by half every
+    /// [`ROLLING_FEE_HALFLIFE`].
+    pub fn min_fee_rate(&self) -> u64 {
+        let mut rolling = self.rolling_fee();
+        if rolling.rate > 0.0 {
+            let now = SystemTime::now();
+            let elapsed = now.duration_since(rolling.updated).unwrap_or(Duration::ZERO).as_secs_f64();
+            let mut halflife = ROLLING_FEE_HALFLIFE.as_secs_f64();
+            let used = self.transactions.len();
+            if used < self.config.max_size / 4 {
+                halflife /= 4.0;
+            } else if used < self.config.max_size / 2 {
+                halflife /= 2.0;
+            }
+
+            rolling.rate /= 2f64.powf(elapsed / halflife);
+            rolling.updated = now;
+            if rolling.rate < INCREMENTAL_RELAY_FEE as f64 / 2.0 {
+                rolling.rate = 0.0;
+            }
+        }
+        self.config.min_fee_rate.max(rolling.rate.ceil() as u64)
+    }
+
+    /// Evict the packages least worth mining until the pool is back within its size limit
+    ///
+    /// Returns the hashes of the evicted transactions.
+    fn trim_to_size(&self) -> Vec<MempoolEntry> {
+        let mut evicted = Vec::new();
+        while self.transactions.len() > self.config.max_size {
+            let lowest = self.packages().lowest_descendant_score();
+            let (tx_hash, score) = match lowest {
+                Some(lowest) => lowest,
+                None => break,
+            };
+
+            let mut rolling = self.rolling_fee();
+            let rate = (score + INCREMENTAL_RELAY_FEE) as f64;
+            if rate > rolling.rate {
+                rolling.rate = rate;
+                rolling.updated = SystemTime::now();
+            }
+            drop(rolling);
+
+            let removed = self.remove_with_descendants(&tx_hash, RemovalReason::Evicted);
+            if removed.is_empty() {
+                // Tracked as a package but no longer in the pool
+                self.packages().remove_transaction(&tx_hash);
+            }
+            evicted.extend(removed);
+        }
+        evicted
+    }
+
+    /// Remove a transaction and everything spending it, descendants first
+    ///
+    /// Returns the removed entries, the requested one last.
+    fn remove_with_descendants(&self, tx_hash: &[u8; 32], reason: RemovalReason) -> Vec<MempoolEntry> {
+        let descendants = self.packages().descendants(tx_hash);
+        let mut removed = Vec::new();
+        for hash in descendants.iter().chain(std::iter::once(tx_hash)) {
+            if let Some(entry) = self.remove_entry(hash, reason) {
+                self.estimator().untrack(hash);
+                removed.push(entry);
+            }
+        }
+        if !removed.is_empty() {
+            self.sequence.fetch_add(1, Ordering::Relaxed);
+        }
+        removed
+    }
+
+    /// Drop one transaction from the pool and its indexes
+    fn remove_entry(&self, tx_hash: &[u8; 32], reason: RemovalReason) -> Option<MempoolEntry> {
+        let (_, entry) = self.transactions.remove(tx_hash)?;
+        self.unindex_scripts(&entry.transaction);
+        self.packages().remove_transaction(tx_hash);
+        self.publish(NodeEvent::MempoolRemoved { txid: *tx_hash, reason });
+        Some(entry)
+    }
+
+    fn publish(&self, event: NodeEvent) {
+        if let Some(events) = &self.events {
+            events.publish(event);
+        }
+    }
+
+    /// Add `fee_delta` to the fee a transaction is mined and evicted by, without changing what it pays
+    ///
+    /// Deltas add up, and apply to the transaction whether it is already in
+    /// the pool or arrives later. They are dropped once it confirms.
+    pub fn prioritise_transaction(&self, tx_hash: &[u8; 32], fee_delta: i64) {
+        let delta = {
+            let mut delta = self.fee_deltas.entry(*tx_hash).or_insert(0);
+            *delta = delta.saturating_add(fee_delta);
+            *delta
+        };
+        if delta == 0 {
+            self.fee_deltas.remove(tx_hash);
+        }
+
+        let fee = self.transactions.get(tx_hash).map(|entry| entry.fee);
+        if let Some(fee) = fee {
+            self.packages().set_fee(tx_hash, self.modified_fee(tx_hash, fee));
+            self.sequence.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Fee adjustment set for a transaction through [`Self::prioritise_transaction`]
+    pub fn fee_delta(&self, tx_hash: &[u8; 32]) -> i64 {
+        self.fee_deltas.get(tx_hash).map_or(0, |delta| *delta)
+    }
+
+    /// `fee` with the transaction's fee delta applied, never below zero
+    fn modified_fee(&self, tx_hash: &[u8; 32], fee: u64) -> u64 {
+        let modified = fee as i128 + self.fee_delta(tx_hash) as i128;
+        modified.clamp(0, u64::MAX as i128) as u64
+    }
+
+    /// The pool's transactions, parents first, and fee deltas, as saved by [`Self::save`]
+    pub fn snapshot(&self) -> MempoolSnapshot {
+        let order = self.packages().select_packages(usize::MAX);
+        let transactions = order
+            .iter()
+            .filter_map(|hash| {
+                let entry = self.transactions.get(hash)?;
+                let entry_time = entry.timestamp.duration_since(UNIX_EPOCH).unwrap_or(Duration::ZERO).as_secs();
+                Some(SavedTransaction { transaction: entry.transaction.clone(), entry_time })
+            })
+            .collect();
+        let fee_deltas = self.fee_deltas.iter().map(|delta| (*delta.key(), *delta.value())).collect();
+        MempoolSnapshot { version: MEMPOOL_DUMP_VERSION, transactions, fee_deltas }
+    }
+
+    /// Write the pool to `path` so it survives a restart, returning how many transactions were saved
+    pub fn save(&self, path: &Path) -> Result<usize, PersistenceError> {
+        let snapshot = self.snapshot();
+        snapshot.write(path)?;
+        Ok(snapshot.transactions.len())
+    }
+
+    /// Read a pool written by [`Self::save`] and [`Self::restore`] it against `chain`
+    pub fn load(&self, path: &Path, chain: &impl ChainView) -> Result<LoadStats, PersistenceError> {
+        Ok(self.restore(MempoolSnapshot::read(path)?, chain))
+    }
+
+    /// Apply a snapshot's fee deltas, then accept its transactions again against `chain`
+    ///
+    /// Transactions keep the time they first entered the pool, so those past
+    /// the maximum age are skipped rather than given a fresh lease. Parents
+    /// below the minimum fee rate wait for a child spending them and are then
+    /// submitted with it as a package, so CPFP pairs survive the restart.
+    pub fn restore(&self, snapshot: MempoolSnapshot, chain: &impl ChainView) -> LoadStats {
+        for (tx_hash, fee_delta) in snapshot.fee_deltas {
+            self.prioritise_transaction(&tx_hash, fee_delta);
+        }
+
+        let now = SystemTime::now();
+        let max_age = Duration::from_secs(self.config.max_age);
+        let mut stats = LoadStats::default();
+        let mut entry_times: HashMap<[u8; 32], SystemTime> = HashMap::new();
+        let mut low_fee_parents: Vec<Transaction> = Vec::new();
+        for saved in snapshot.transactions {
+            let entered = UNIX_EPOCH + Duration::from_secs(saved.entry_time);
+            if now.duration_since(entered).unwrap_or(Duration::ZERO) > max_age {
+                stats.expired += 1;
+                continue;
+            }
+            let tx_hash = saved.transaction.hash();
+            if self.transactions.contains_key(&tx_hash) {
+                stats.already_there += 1;
+                continue;
+            }
+            entry_times.insert(tx_hash, entered);
+
+            let spent: HashSet<[u8; 32]> =
+                saved.transaction.inputs().iter().map(|input| input.prev_tx_hash()).collect();
+            let (parents, others): (Vec<Transaction>, Vec<Transaction>) =
+                low_fee_parents.drain(..).partition(|parent| spent.contains(&parent.hash()));
+            low_fee_parents = others;
+
+            let accepted = if parents.is_empty() {
+                match self.accept_transaction(saved.transaction.clone(), chain) {
+                    Ok(_) => vec![tx_hash],
+                    Err(MempoolError::FeeTooLow) => {
+                        low_fee_parents.push(saved.transaction);
+                        continue;
+                    }
+                    Err(_) => Vec::new(),
+                }
+            } else {
+                let mut package = parents.clone();
+                package.push(saved.transaction);
+                match self.submit_package(package, chain) {
+                    Ok(acceptance) => acceptance.accepted,
+                    Err(_) => {
+                        // Another child may still pay for them
+                        low_fee_parents.extend(parents);
+                        Vec::new()
+                    }
+                }
+            };
+
+            if !accepted.contains(&tx_hash) {
+                stats.failed += 1;
+            }
+            for hash in &accepted {
+                if let (Some(mut entry), Some(entered)) = (self.transactions.get_mut(hash), entry_times.get(hash)) {
+                    entry.timestamp = *entered;
+                }
+            }
+            stats.accepted += accepted.len();
+        }
+        stats.failed += low_fee_parents.len();
+        stats
+    }
+
+    /// Fee rate, in satoshis per byte, expected to confirm within `target` blocks
+    ///
+    /// Falls back to the nearest higher target with enough data; `None` until
+    /// the pool has seen enough blocks.
+    pub fn estimate_smart_fee(&self, target: u32) -> Option<FeeEstimate> {
+        self.estimator().estimate_smart_fee(target)
+    }
+
+    /// Validate a transaction against `chain` and the pool, then add it at the fee it really pays
+    ///
+    /// A transaction spending outputs already spent in the pool goes through
+    /// Replace-By-Fee when it is enabled.
+    pub fn accept_transaction(
+        &self,
+        transaction: Transaction,
+        chain: &impl ChainView,
+    ) -> Result<CheckedTransaction, MempoolError> {
+        if self.transactions.contains_key(&transaction.hash()) {
+            return Err(MempoolError::DuplicateTransaction);
+        }
+
+        let checked = self.check_transaction(&transaction, chain)?;
+        if self.check_double_spend(&transaction) {
+            if !self.config.enable_rbf {
+                return Err(MempoolError::DoubleSpend);
+            }
+            self.replace_entry(transaction, checked.fee, checked.size)?;
+        } else {
+            self.add_entry(transaction, checked.fee, checked.size)?;
+        }
+        Ok(checked)
+    }
+
+    /// Run the [`validation::check_transaction`] rules, resolving inputs in `chain` and then the pool
+    pub fn check_transaction(
+        &self,
+        transaction: &Transaction,
+        chain: &impl ChainView,
+    ) -> Result<CheckedTransaction, TxRejection> {
+        validation::check_transaction(transaction, chain.tip_height(), chain.median_time_past(), |hash, index| {
+            self.resolve_coin(chain, hash, index)
+        })
+    }
+
+    /// Output `index` of `tx_hash`, unspent in `chain` or created by a pool transaction
+    fn resolve_coin(&self, chain: &impl ChainView, tx_hash: &[u8; 32], index: u32) -> Option<Coin> {
+        chain.coin(tx_hash, index).or_else(|| {
+            let output = self.transactions.get(tx_hash)?.transaction.outputs().get(index as usize)?.clone();
+            Some(Coin { output, coinbase_height: None })
+        })
+    }
+
+    /// Accept a transaction, or keep it as an orphan if some of its parents are unknown
+    ///
+    /// Parents are looked up in the pool and in `chain`. Once added, orphans
+    /// waiting on the transaction are resubmitted.
+    pub fn submit_transaction(
+        &self,
+        transaction: Transaction,
+        peer: Option<PeerId>,
+        chain: &impl ChainView,
+    ) -> Result<Submission, MempoolError> {
+        let tx_hash = transaction.hash();
+        if self.transactions.contains_key(&tx_hash) || self.orphans().contains(&tx_hash) {
+            return Err(MempoolError::DuplicateTransaction);
+        }
+
+        let missing = self.missing_parents(&transaction, chain);
+        if !missing.is_empty() {
+            self.orphans().add(transaction, peer)?;
+            return Ok(Submission::Orphaned { missing });
+        }
+
+        self.accept_transaction(transaction, chain)?;
+        Ok(Submission::Accepted { resubmitted: self.resubmit_orphans(&[tx_hash], chain) })
+    }
+
+    /// Validate a package of a child and its parents, then add it judging its fees together
+    ///
+    /// The package must pass [`package::check_package`]. Transactions the pool
+    /// already holds are skipped; each of the others must pass
+    /// [`validation::check_transaction`], spending the chain, the pool or
+    /// earlier package transactions. Those meeting [`Self::min_fee_rate`]
+    /// stand on their own, while those below it must meet it together with
+    /// the child paying for them.
+    ///
+    /// Pool transactions conflicting with the package are replaced, along
+    /// with their descendants, when Replace-By-Fee is enabled and the package
+    /// - replaces at most [`MAX_REPLACEMENT_CANDIDATES`] transactions,
+    /// - spends none of the transactions it replaces,
+    /// - pays their fees plus `min_rbf_fee_increase` percent, and at least
+    ///   [`INCREMENTAL_RELAY_FEE`] per byte of its own on top of them,
+    /// - beats the fee rate of every transaction it directly conflicts with.
+    ///
+    /// If the pool overflows and evicts part of the package, the whole package goes.
+    pub fn submit_package(
+        &self,
+        transactions: Vec<Transaction>,
+        chain: &impl ChainView,
+    ) -> Result<PackageAcceptance, MempoolError> {
+        package::check_package(&transactions)?;
+        let child_hash = transactions[transactions.len() - 1].hash();
+
+        let mut already_in_pool = Vec::new();
+        let mut new: Vec<(Transaction, CheckedTransaction)> = Vec::new();
+        for transaction in transactions {
+            let tx_hash = transaction.hash();
+            if self.transactions.contains_key(&tx_hash) {
+                already_in_pool.push(tx_hash);
+                continue;
+            }
+            let checked = validation::check_transaction(
+                &transaction,
+                chain.tip_height(),
+                chain.median_time_past(),
+                |hash, index| {
+                    self.resolve_coin(chain, hash, index).or_else(|| {
+                        let (parent, _) = new.iter().find(|(parent, _)| parent.hash() == *hash)?;
+                        let output = parent.outputs().get(index as usize)?.clone();
+                        Some(Coin { output, coinbase_height: None })
+                    })
+                },
+            )
+            .map_err(|rejection| PackageError::Transaction(hex::encode(tx_hash), rejection))?;
+            new.push((transaction, checked));
+        }
+
+        let fee: u64 = new.iter().map(|(_, checked)| checked.fee).sum();
+        let size: usize = new.iter().map(|(_, checked)| checked.size).sum();
+        let fee_rate = fee / size.max(1) as u64;
+
+        // Transactions too cheap on their own must be carried by the child, which cannot ride on them
+        let min_fee_rate = self.min_fee_rate();
+        let (paid_fee, paid_size) = new
+            .iter()
+            .filter(|(transaction, checked)| checked.fee_rate < min_fee_rate || transaction.hash() == child_hash)
+            .fold((0u64, 0usize), |(fee, size), (_, checked)| (fee + checked.fee, size + checked.size));
+        let paid_fee_rate = paid_fee / paid_size.max(1) as u64;
+        if paid_fee_rate < min_fee_rate {
+            return Err(PackageError::FeeTooLow { fee_rate: paid_fee_rate, min_fee_rate }.into());
+        }
+
+        let mut conflicts: Vec<[u8; 32]> = Vec::new();
+        for (transaction, _) in &new {
+            for hash in self.find_conflicting_transactions(transaction) {
+                if !conflicts.contains(&hash) {
+                    conflicts.push(hash);
+                }
+            }
+        }
+
+        let mut replaced_entries: Vec<MempoolEntry> = Vec::new();
+        if !conflicts.is_empty() {
+            if !self.config.enable_rbf {
+                return Err(MempoolError::DoubleSpend);
+            }
+            let replacing: Vec<&Transaction> = new.iter().map(|(transaction, _)| transaction).collect();
+            replaced_entries = self.check_replacement(&conflicts, &replacing, fee, size)?;
+        }
+
+        let replaced: Vec<&Transaction> = replaced_entries.iter().map(|entry| &entry.transaction).collect();
+        for (transaction, _) in &new {
+            self.check_address_limit(transaction, &replaced)?;
+        }
+
+        let accepted: Vec<[u8; 32]> = new.iter().map(|(transaction, _)| transaction.hash()).collect();
+        let entries = new.into_iter().map(|(transaction, checked)| (transaction, checked.fee, checked.size)).collect();
+        let replaced: Vec<[u8; 32]> = self
+            .insert_replacing(&conflicts, entries)?
+            .iter()
+            .map(|entry| entry.transaction.hash())
+            .collect();
+
+        let resubmitted = self.resubmit_orphans(&accepted, chain);
+        Ok(PackageAcceptance { accepted, already_in_pool, replaced, resubmitted, fee, size, fee_rate })
+    }
+
+    /// Pool entries that `replacing`, paying `fee` for `size` bytes, would evict by replacing `conflicts`
+    ///
+    /// Evicting a conflict also evicts everything spending it. The rules are
+    /// those listed on [`Self::submit_package`].
+    fn check_replacement(
+        &self,
+        conflicts: &[[u8; 32]],
+        replacing: &[&Transaction],
+        fee: u64,
+        size: usize,
+    ) -> Result<Vec<MempoolEntry>, PackageError> {
+        let mut replaced_hashes: HashSet<[u8; 32]> = conflicts.iter().copied().collect();
+        for hash in conflicts {
+            replaced_hashes.extend(self.packages().descendants(hash));
+        }
+        if replaced_hashes.len() > MAX_REPLACEMENT_CANDIDATES {
+            return Err(PackageError::TooManyReplacements(replaced_hashes.len()));
+        }
+        for transaction in replacing {
+            if transaction.inputs().iter().any(|input| replaced_hashes.contains(&input.prev_tx_hash())) {
+                return Err(PackageError::SpendsConflicting(hex::encode(transaction.hash())));
+            }
+        }
+        let replaced_entries: Vec<MempoolEntry> = replaced_hashes
+            .iter()
+            .filter_map(|hash| self.transactions.get(hash).map(|entry| entry.clone()))
+            .collect();
+
+        let replaced_fee: u64 = replaced_entries.iter().map(|entry| entry.fee).sum();
+        let min_increase = 1.0 + (self.config.min_rbf_fee_increase / 100.0);
+        let required = ((replaced_fee as f64 * min_increase) as u64)
+            .max(replaced_fee.saturating_add(INCREMENTAL_RELAY_FEE.saturating_mul(size as u64)));
+        if fee < required {
+            return Err(PackageError::InsufficientFee { fee, required });
+        }
+        let fee_rate = fee / size.max(1) as u64;
+        for hash in conflicts {
+            let conflict_fee_rate = self.transactions.get(hash).map_or(0, |entry| entry.fee_rate);
+            if fee_rate <= conflict_fee_rate {
+                return Err(PackageError::LowerFeeRate { fee_rate, conflict_fee_rate });
+            }
+        }
+        Ok(replaced_entries)
+    }
+
+    /// Swap `conflicts`, with their descendants, for `entries` of transaction, fee and size, all or nothing
+    ///
+    /// Returns the entries taken out. If one of `entries` cannot be inserted,
+    /// or the pool evicts it when trimming back to size, none of them stay and
+    /// the transactions taken out are put back.
+    fn insert_replacing(
+        &self,
+        conflicts: &[[u8; 32]],
+        entries: Vec<(Transaction, u64, usize)>,
+    ) -> Result<Vec<MempoolEntry>, MempoolError> {
+        let mut displaced = Vec::new();
+        for hash in conflicts {
+            displaced.extend(self.remove_with_descendants(hash, RemovalReason::Replaced));
+        }
+        let replaced = displaced.clone();
+
+        let accepted: Vec<[u8; 32]> = entries.iter().map(|(transaction, _, _)| transaction.hash()).collect();
+        for (transaction, fee, size) in entries {
+            if let Err(e) = self.insert_entry(transaction, fee, size) {
+                self.roll_back_package(&accepted, RemovalReason::Removed, displaced);
+                return Err(e);
+            }
+        }
+        if self.transactions.len() > self.config.max_size {
+            let evicted = self.trim_to_size();
+            if evicted.iter().any(|entry| accepted.contains(&entry.transaction.hash())) {
+                displaced.extend(evicted.into_iter().filter(|entry| !accepted.contains(&entry.transaction.hash())));
+                self.roll_back_package(&accepted, RemovalReason::Evicted, displaced);
+                return Err(MempoolError::PoolFull);
+            }
+        }
+        Ok(replaced)
+    }
+
+    /// Take back the transactions of a package that could not be added whole
+    ///
+    /// The pool transactions it replaced or pushed out are added back, parents
+    /// first, with their original entry times.
+    fn roll_back_package(&self, accepted: &[[u8; 32]], reason: RemovalReason, mut displaced: Vec<MempoolEntry>) {
+        for hash in accepted {
+            self.remove_with_descendants(hash, reason);
+        }
+
+        while !displaced.is_empty() {
+            let waiting: HashSet<[u8; 32]> = displaced.iter().map(|entry| entry.transaction.hash()).collect();
+            let position = displaced
+                .iter()
+                .position(|entry| {
+                    entry.transaction.inputs().iter().all(|input| !waiting.contains(&input.prev_tx_hash()))
+                })
+                .unwrap_or(0);
+            let entry = displaced.remove(position);
+            let tx_hash = entry.transaction.hash();
+            if self.insert_entry(entry.transaction, entry.fee, entry.size).is_ok() {
+                if let Some(mut restored) = self.transactions.get_mut(&tx_hash) {
+                    restored.timestamp = entry.timestamp;
+                }
+            }
+        }
+    }
+
+    /// Retry the orphans spending outputs of `parents`, which just reached the pool or the chain
+    ///
+    /// Orphans added this way are retried as parents in turn. Those still
+    /// missing a parent go back to waiting, and those rejected count against
+    /// the peer that relayed them. Returns the hashes of the orphans added.
+    pub fn resubmit_orphans(&self, parents: &[[u8; 32]], chain: &impl ChainView) -> Vec<[u8; 32]> {
+        let mut queue = parents.to_vec();
+        let mut added = Vec::new();
+        while let Some(parent) = queue.pop() {
+            let children = self.orphans().take_children(&parent);
+            for orphan in children {
+                let tx_hash = orphan.transaction.hash();
+                if !self.missing_parents(&orphan.transaction, chain).is_empty() {
+                    // Its limits were checked when it first arrived
+                    let _ = self.orphans().add(orphan.transaction, orphan.peer);
+                    continue;
+                }
+                match self.accept_transaction(orphan.transaction, chain) {
+                    Ok(_) => {
+                        added.push(tx_hash);
+                        queue.push(tx_hash);
+                    }
+                    Err(e) if e.is_sender_fault() => {
+                        if let Some(peer) = &orphan.peer {
+                            self.orphans().penalize_invalid(peer);
+                        }
+                    }
+                    Err(_) => {}
+                }
+            }
+        }
+        added
+    }
+
+    /// Parents of `transaction` with spent outputs neither in the pool nor unspent in `chain`
+    pub fn missing_parents(&self, transaction: &Transaction, chain: &impl ChainView) -> Vec<[u8; 32]> {
+        let mut missing = Vec::new();
+        for input in transaction.inputs() {
+            let (parent, index) = (input.prev_tx_hash(), input.prev_output_index());
+            let in_pool = self.transactions
+                .get(&parent)
+                .map_or(false, |entry| (index as usize) < entry.transaction.outputs().len());
+            if !in_pool && chain.coin(&parent, index).is_none() && !missing.contains(&parent) {
+                missing.push(parent);
+            }
+        }
+        missing
+    }
+
+    /// Number of transactions waiting for missing parents
+    pub fn orphan_count(&self) -> usize {
+        self.orphans().len()
+    }
+
+    /// Misbehavior score a peer earned by relaying orphans; see [`super::orphan::ORPHAN_BAN_SCORE`]
+    pub fn orphan_score(&self, peer: &PeerId) -> u32 {
+        self.orphans().score(peer)
+    }
+
+    /// Drop the orphans and score of a peer that disconnected or was banned
+    pub fn forget_peer(&self, peer: &PeerId) -> usize {
+        self.orphans().forget_peer(peer)
+    }
+
+    /// Fail if adding `transaction` in place of `replaced` would push an output script over its limit
+    fn check_address_limit(&self, transaction: &Transaction, replaced: &[&Transaction]) -> Result<(), MempoolError> {
+        for script in output_scripts(transaction) {
+            let count = self.script_counts.get(script).map_or(0, |count| *count);
+            let freed = replaced.iter().filter(|tx| output_scripts(tx).contains(script)).count();
+            if count.saturating_sub(freed) >= self.config.max_per_address {
+                return Err(MempoolError::AddressLimitExceeded);
+            }
+        }
+        Ok(())
+    }
+
+    fn index_scripts(&self, transaction: &Transaction) {
+        for script in output_scripts(transaction) {
+            *self.script_counts.entry(script.to_vec()).or_insert(0) += 1;
+        }
+    }
+
+    fn unindex_scripts(&self, transaction: &Transaction) {
+        for script in output_scripts(transaction) {
+            if let Some(mut count) = self.script_counts.get_mut(script) {
+                *count = count.saturating_sub(1);
+            }
+            self.script_counts.remove_if(script, |_, count| *count == 0);
         }
     }
 
+    fn orphans(&self) -> MutexGuard<'_, OrphanPool> {
+        // Orphans are only a cache of relayed transactions; what a panic left behind is still usable
+        self.orphans.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn packages(&self) -> MutexGuard<'_, TransactionPrioritizer> {
+        // Only an index over the pool's entries; a panic mid-update leaves at worst a stale link
+        self.packages.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn rolling_fee(&self) -> MutexGuard<'_, RollingFee> {
+        self.rolling_fee.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn estimator(&self) -> MutexGuard<'_, FeeEstimator> {
+        // The estimator holds only statistics, so a panic elsewhere cannot leave it inconsistent
+        self.estimator.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
     /// Get a transaction by its hash
     pub fn get_transaction(&self, tx_hash: &[u8; 32]) -> Option<Transaction> {
         match self.transactions.get(tx_hash) {
@@ -120,22 +884,32 @@ impl TransactionPool {
         }
     }
 
-    /// Clear expired transactions from the pool
+    /// Get the absolute fee paid by a transaction in the pool
+    pub fn get_fee(&self, tx_hash: &[u8; 32]) -> Option<u64> {
+        self.transactions
+            .get(tx_hash)
+            .map(|entry| entry.fee)
+    }
+
+    /// Counter that changes whenever transactions are added or removed
+    ///
+    /// Used by block template long polling to detect mempool updates.
+    pub fn sequence(&self) -> u64 {
+        self.sequence.load(Ordering::Relaxed)
+    }
+
+    /// Clear expired transactions from the pool, along with the transactions spending them
     pub fn clear_expired(&self) -> usize {
         let now = SystemTime::now();
         let max_age = Duration::from_secs(self.config.max_age);
-        let mut removed = 0;
-
-        self.transactions.retain(|_, entry| {
-            let age = now.duration_since(entry.timestamp).unwrap_or(Duration::ZERO);
-            if age > max_age {
-                removed += 1;
-                false
-            } else {
-                true
-            }
-        });
+        let expired: Vec<[u8; 32]> = self.transactions
+            .iter()
+            .filter(|entry| now.duration_since(entry.timestamp).unwrap_or(Duration::ZERO) > max_age)
+            .map(|entry| *entry.key())
+            .collect();
 
+        let removed = expired.iter().map(|hash| self.remove_with_descendants(hash, RemovalReason::Expired).len()).sum();
+        self.orphans().expire();
         removed
     }
 
@@ -165,29 +939,52 @@ impl TransactionPool {
         false
     }
 
-    /// Get all transactions sorted by fee rate
+    /// Get all transactions in the order they would be mined
+    ///
+    /// Transactions come by the fee rate of their ancestor packages, parents
+    /// before children, so a child paying for its parent lifts both.
     pub fn get_sorted_transactions(&self) -> Vec<Transaction> {
-        let mut entries: Vec<_> = self.transactions
-            .iter()
-            .map(|ref_multi| {
-                (ref_multi.transaction.clone(), ref_multi.fee_rate)
-            })
-            .collect();
+        self.mining_order(usize::MAX)
+    }
 
-        // Sort by fee rate in descending order
-        entries.sort_by(|a, b| b.1.cmp(&a.1));
-        
-        entries.into_iter().map(|(tx, _)| tx).collect()
+    /// The transactions [`Self::get_sorted_transactions`] would list first, up to `max_size` bytes
+    pub fn mining_order(&self, max_size: usize) -> Vec<Transaction> {
+        let selected = self.packages().select_packages(max_size);
+        selected.iter().filter_map(|hash| self.get_transaction(hash)).collect()
+    }
+
+    /// Fee rate of a transaction together with its unconfirmed ancestors
+    pub fn ancestor_fee_rate(&self, tx_hash: &[u8; 32]) -> Option<u64> {
+        self.packages().ancestor_fee_rate(tx_hash)
     }
 
     /// Clear all transactions from the pool
     pub fn clear_all(&self) -> Result<(), MempoolError> {
+        let mut estimator = self.estimator();
+        for entry in self.transactions.iter() {
+            estimator.untrack(entry.key());
+            self.publish(NodeEvent::MempoolRemoved { txid: *entry.key(), reason: RemovalReason::Removed });
+        }
+        drop(estimator);
         self.transactions.clear();
+        self.script_counts.clear();
+        *self.packages() = TransactionPrioritizer::new(PrioritizationConfig::default());
+        self.sequence.fetch_add(1, Ordering::Relaxed);
         Ok(())
     }
 
-    /// Attempt to replace an existing transaction with a higher-fee version (RBF)
-    pub fn replace_transaction(&self, new_transaction: Transaction, fee_rate: u64) -> Result<Option<Transaction>, MempoolError> {
+    /// Attempt to replace an existing transaction with a higher-fee version (RBF), at a fee rate the caller vouches for
+    pub(crate) fn replace_transaction(&self, new_transaction: Transaction, fee_rate: u64) -> Result<Option<Transaction>, MempoolError> {
+        let size = bincode::serialized_size(&new_transaction).map_err(|_| MempoolError::SerializationError)? as usize;
+        self.replace_entry(new_transaction, fee_rate.saturating_mul(size as u64), size)
+    }
+
+    /// Replace the transactions conflicting with `new_transaction`, and their descendants, if it pays enough more
+    ///
+    /// The replacement rules are those of [`Self::submit_package`], and the
+    /// pool is left as it was if the new transaction cannot be added. Returns
+    /// the replaced transaction when there was exactly one.
+    fn replace_entry(&self, new_transaction: Transaction, fee: u64, size: usize) -> Result<Option<Transaction>, MempoolError> {
         // Check if RBF is enabled
         if !self.config.enable_rbf {
             return Err(MempoolError::RbfDisabled);
@@ -201,55 +998,29 @@ impl TransactionPool {
         }
         
         // Find transactions in the mempool that have inputs overlapping with the new transaction
-        let conflicting_txs: Vec<([u8; 32], MempoolEntry)> = self.find_conflicting_transactions(&new_transaction);
+        let conflicting_txs = self.find_conflicting_transactions(&new_transaction);
         
         if conflicting_txs.is_empty() {
             // No conflicts, this is not an RBF but a new transaction
             return Err(MempoolError::NoConflictingTransactions);
         }
-        
-        // Calculate the total fee of the conflicting transactions
-        let total_conflicting_size: usize = conflicting_txs.iter().map(|(_, entry)| entry.size).sum();
-        let total_conflicting_fee: u64 = conflicting_txs.iter().map(|(_, entry)| entry.fee_rate * entry.size as u64).sum();
-        
-        // Calculate the new transaction size
-        let new_tx_size = match bincode::serialize(&new_transaction) {
-            Ok(bytes) => bytes.len(),
-            Err(_) => return Err(MempoolError::SerializationError),
-        };
-        
-        // Calculate the new transaction fee
-        let new_tx_fee = fee_rate * new_tx_size as u64;
-        
-        // Check if the new transaction's fee is sufficiently higher than the conflicting transactions
-        let min_increase = 1.0 + (self.config.min_rbf_fee_increase / 100.0);
-        let min_required_fee = ((total_conflicting_fee as f64) * min_increase) as u64;
-        
-        if new_tx_fee < min_required_fee {
-            return Err(MempoolError::InsufficientFeeIncrease(min_required_fee));
-        }
-        
-        // Remove all conflicting transactions
-        let mut removed_txs = Vec::new();
-        for (hash, entry) in conflicting_txs {
-            if let Some((_, entry)) = self.transactions.remove(&hash) {
-                removed_txs.push(entry.transaction);
-            }
+
+        if fee / (size.max(1) as u64) < self.min_fee_rate() {
+            return Err(MempoolError::FeeTooLow);
         }
+        let replaced_entries = self
+            .check_replacement(&conflicting_txs, &[&new_transaction], fee, size)
+            .map_err(MempoolError::from_replacement)?;
+
+        let replaced: Vec<&Transaction> = replaced_entries.iter().map(|entry| &entry.transaction).collect();
+        self.check_address_limit(&new_transaction, &replaced)?;
         
-        // Add the new transaction
-        let new_entry = MempoolEntry {
-            transaction: new_transaction.clone(),
-            timestamp: SystemTime::now(),
-            fee_rate,
-            size: new_tx_size,
-        };
-        
-        self.transactions.insert(tx_hash, new_entry);
+        // Remove all conflicting transactions and add the new one, or neither
+        let mut removed = self.insert_replacing(&conflicting_txs, vec![(new_transaction, fee, size)])?;
         
         // If we only replaced one transaction, return it
-        if removed_txs.len() == 1 {
-            Ok(Some(removed_txs.remove(0)))
+        if removed.len() == 1 {
+            Ok(Some(removed.remove(0).transaction))
         } else {
             // We replaced multiple transactions, return None to indicate this wasn't a 1:1 replacement
             Ok(None)
@@ -257,7 +1028,7 @@ impl TransactionPool {
     }
     
     /// Find transactions in the mempool that have inputs overlapping with the new transaction
-    fn find_conflicting_transactions(&self, transaction: &Transaction) -> Vec<([u8; 32], MempoolEntry)> {
+    pub fn find_conflicting_transactions(&self, transaction: &Transaction) -> Vec<[u8; 32]> {
         // Get all input references from the new transaction
         let new_inputs: Vec<_> = transaction.inputs()
             .iter()
@@ -277,7 +1048,7 @@ impl TransactionPool {
             // Check for any overlap in inputs
             for input in &new_inputs {
                 if existing_inputs.contains(input) {
-                    conflicting_txs.push((tx_hash, entry.clone()));
+                    conflicting_txs.push(tx_hash);
                     break;
                 }
             }
@@ -287,7 +1058,32 @@ impl TransactionPool {
     }
 }
 
-#[derive(Debug, thiserror::Error)]
+/// Distinct output scripts a transaction pays
+fn output_scripts(transaction: &Transaction) -> HashSet<&[u8]> {
+    transaction.outputs().iter().map(|output| output.pub_key_script()).collect()
+}
+
+/// Lets block templates (Stratum jobs, the internal miner) draw from the pool
+#[async_trait]
+impl MempoolInterface for TransactionPool {
+    async fn get_transactions(&self, max_size: usize) -> Vec<Transaction> {
+        self.mining_order(max_size)
+    }
+
+    async fn get_transaction_fees(&self, txids: &[Vec<u8>]) -> Vec<u64> {
+        txids
+            .iter()
+            .map(|txid| {
+                <[u8; 32]>::try_from(txid.as_slice())
+                    .ok()
+                    .and_then(|hash| self.get_fee(&hash))
+                    .unwrap_or(0)
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
 pub enum MempoolError {
     #[error("Transaction already exists in mempool")]
     DuplicateTransaction,
@@ -305,12 +1101,142 @@ pub enum MempoolError {
     NoConflictingTransactions,
     #[error("Fee increase insufficient for RBF, minimum required: {0}")]
     InsufficientFeeIncrease(u64),
+    #[error("Replacement would evict {0} transactions, more than {MAX_REPLACEMENT_CANDIDATES}")]
+    TooManyReplacements(usize),
+    #[error("Replacement spends a transaction it replaces")]
+    SpendsConflicting,
+    #[error("Replacement fee rate {fee_rate} does not exceed the {conflict_fee_rate} of a transaction it replaces")]
+    LowerFeeRate { fee_rate: u64, conflict_fee_rate: u64 },
+    #[error("Too many transactions in the mempool pay the same address")]
+    AddressLimitExceeded,
+    #[error("Transaction exceeds the mempool ancestor or descendant limits")]
+    ChainLimitExceeded,
+    #[error("Orphan transaction rejected: {0}")]
+    Orphan(#[from] OrphanError),
+    #[error("Transaction rejected: {0}")]
+    Rejected(#[from] TxRejection),
+    #[error("Package rejected: {0}")]
+    Package(#[from] PackageError),
+}
+
+impl MempoolError {
+    /// A single transaction's failure of the replacement rules [`TransactionPool::submit_package`] shares
+    fn from_replacement(error: PackageError) -> Self {
+        match error {
+            PackageError::TooManyReplacements(count) => MempoolError::TooManyReplacements(count),
+            PackageError::SpendsConflicting(_) => MempoolError::SpendsConflicting,
+            PackageError::InsufficientFee { required, .. } => MempoolError::InsufficientFeeIncrease(required),
+            PackageError::LowerFeeRate { fee_rate, conflict_fee_rate } => {
+                MempoolError::LowerFeeRate { fee_rate, conflict_fee_rate }
+            }
+            error => MempoolError::Package(error),
+        }
+    }
+
+    /// Whether the transaction itself is at fault, rather than the state of the pool
+    fn is_sender_fault(&self) -> bool {
+        match self {
+            MempoolError::FeeTooLow | MempoolError::DoubleSpend | MempoolError::SerializationError => true,
+            MempoolError::Rejected(rejection) => rejection.is_invalid(),
+            MempoolError::Package(error) => error.is_invalid(),
+            _ => false,
+        }
+    }
+
+    /// Reason string for RPC callers, following Bitcoin Core where it has one
+    pub fn reason(&self) -> &'static str {
+        match self {
+            MempoolError::DuplicateTransaction => "txn-already-in-mempool",
+            MempoolError::PoolFull => "mempool-full",
+            MempoolError::FeeTooLow => "min-relay-fee-not-met",
+            MempoolError::DoubleSpend => "txn-mempool-conflict",
+            MempoolError::SerializationError => "bad-txns-serialization",
+            MempoolError::RbfDisabled => "txn-mempool-conflict",
+            MempoolError::NoConflictingTransactions => "no-conflicts",
+            MempoolError::InsufficientFeeIncrease(_) => "insufficient-fee",
+            MempoolError::TooManyReplacements(_) => "too-many-replacements",
+            MempoolError::SpendsConflicting => "bad-txns-spends-conflicting-tx",
+            MempoolError::LowerFeeRate { .. } => "insufficient-fee",
+            MempoolError::AddressLimitExceeded => "too-many-per-address",
+            MempoolError::ChainLimitExceeded => "too-long-mempool-chain",
+            MempoolError::Orphan(_) => "orphan-rejected",
+            MempoolError::Rejected(rejection) => rejection.reason(),
+            MempoolError::Package(error) => error.reason(),
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use btclib::crypto::quantum::{QuantumKeyPair, QuantumParameters, QuantumScheme};
+    use btclib::crypto::signature::SignatureType;
+    use btclib::types::script::{pay_to_pubkey_hash, signature_hash, InputWitness};
+    use btclib::types::block::Block;
     use btclib::types::transaction::{TransactionInput, TransactionOutput};
+    use std::collections::HashMap;
+
+    #[derive(Clone, Default)]
+    struct TestChain {
+        coins: HashMap<([u8; 32], u32), Coin>,
+        height: u64,
+    }
+
+    impl TestChain {
+        fn with_coin(mut self, tx_hash: [u8; 32], output: TransactionOutput, coinbase_height: Option<u64>) -> Self {
+            self.coins.insert((tx_hash, 0), Coin { output, coinbase_height });
+            self
+        }
+    }
+
+    impl ChainView for TestChain {
+        fn coin(&self, tx_hash: &[u8; 32], index: u32) -> Option<Coin> {
+            self.coins.get(&(*tx_hash, index)).cloned()
+        }
+
+        fn tip_height(&self) -> u64 {
+            self.height
+        }
+
+        fn median_time_past(&self) -> u64 {
+            0
+        }
+    }
+
+    fn test_key() -> QuantumKeyPair {
+        QuantumKeyPair::from_seed(&[7u8; 32], QuantumParameters { scheme: QuantumScheme::Dilithium, security_level: 3 })
+            .unwrap()
+    }
+
+    fn locked(key: &QuantumKeyPair, amount: u64) -> TransactionOutput {
+        TransactionOutput::new(amount, pay_to_pubkey_hash(SignatureType::Dilithium, 3, &key.public_key))
+    }
+
+    /// Spend output 0 of `prev_hash`, worth `spent` and locked to `key`, paying `value` back to `key`
+    fn signed_spend(key: &QuantumKeyPair, prev_hash: [u8; 32], spent: u64, value: u64, lock_time: u32) -> Transaction {
+        signed_spends(key, &[(prev_hash, spent)], value, lock_time)
+    }
+
+    /// Spend output 0 of each `(prev_hash, amount)` locked to `key`, paying `value` back to `key`
+    fn signed_spends(key: &QuantumKeyPair, spent: &[([u8; 32], u64)], value: u64, lock_time: u32) -> Transaction {
+        let mut tx = Transaction::new(
+            1,
+            spent.iter().map(|(prev_hash, _)| TransactionInput::new(*prev_hash, 0, vec![], 0)).collect(),
+            vec![locked(key, value)],
+            lock_time,
+        );
+        for (index, (_, amount)) in spent.iter().enumerate() {
+            let sighash = signature_hash(&tx, index, &locked(key, *amount)).unwrap();
+            let witness = InputWitness {
+                sig_type: SignatureType::Dilithium,
+                security_level: 3,
+                public_key: key.public_key.clone(),
+                signature: key.sign(&sighash).unwrap(),
+            };
+            tx.set_signature_script(index, witness.to_script());
+        }
+        tx
+    }
 
     fn create_test_transaction(prev_hash: [u8; 32], value: u64) -> Transaction {
         Transaction::new(
@@ -400,6 +1326,71 @@ mod tests {
         assert!(pool.get_transaction(&tx2_hash).is_some());
     }
     
+    #[test]
+    fn test_changes_are_published() {
+        let events = EventBus::new(16);
+        let mut received = events.subscribe();
+        let config = MempoolConfig {
+            enable_rbf: true,
+            min_rbf_fee_increase: 10.0,
+            ..MempoolConfig::default()
+        };
+        let pool = TransactionPool::new(config).with_event_bus(events);
+
+        let original = create_test_transaction([1u8; 32], 50_000_000);
+        let replacement = create_test_transaction([1u8; 32], 40_000_000);
+        pool.add_transaction(original.clone(), 1).unwrap();
+        pool.replace_transaction(replacement.clone(), 2).unwrap();
+        pool.remove_confirmed(1, &[replacement.clone()]);
+
+        let mut next = || received.try_recv().unwrap();
+        assert!(matches!(next(), NodeEvent::MempoolAdded { transaction, .. } if transaction.hash() == original.hash()));
+        assert!(matches!(next(), NodeEvent::MempoolRemoved { txid, reason: RemovalReason::Replaced } if txid == original.hash()));
+        assert!(matches!(next(), NodeEvent::MempoolAdded { transaction, .. } if transaction.hash() == replacement.hash()));
+        assert!(matches!(next(), NodeEvent::MempoolRemoved { txid, reason: RemovalReason::Confirmed } if txid == replacement.hash()));
+        assert!(received.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_follow_chain_updates_pool() {
+        let events = EventBus::new(16);
+        let mut received = events.subscribe();
+        let key = test_key();
+        let chain = Arc::new(RwLock::new(TestChain::default().with_coin([1u8; 32], locked(&key, 1_000_000), None)));
+        let pool = Arc::new(TransactionPool::new(MempoolConfig::default()).with_event_bus(events.clone()));
+        pool.follow_chain(&events, Arc::clone(&chain));
+
+        let tx = create_test_transaction([2u8; 32], 50_000_000);
+        pool.add_transaction(tx.clone(), 1).unwrap();
+        let parent = signed_spend(&key, [1u8; 32], 1_000_000, 900_000, 0);
+        let child = signed_spend(&key, parent.hash(), 900_000, 800_000, 0);
+        let snapshot = chain.read().await.clone();
+        assert!(matches!(pool.submit_transaction(child.clone(), None, &snapshot), Ok(Submission::Orphaned { .. })));
+
+        // Connected as the chain state does for blocks from peers
+        let coin = Coin { output: locked(&key, 900_000), coinbase_height: None };
+        chain.write().await.coins.insert((parent.hash(), 0), coin);
+        let coinbase = create_test_transaction([0u8; 32], 50_000_000);
+        let block = Block::new(1, [0u8; 32], vec![coinbase, parent, tx.clone()], 0);
+        events.publish(NodeEvent::BlockConnected { block: Arc::new(block), height: 1 });
+
+        let mut removed = None;
+        tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                match received.recv().await.unwrap() {
+                    NodeEvent::MempoolRemoved { txid, reason } => removed = Some((txid, reason)),
+                    NodeEvent::MempoolAdded { transaction, .. } if transaction.hash() == child.hash() => break,
+                    _ => {}
+                }
+            }
+        })
+        .await
+        .expect("orphan was not resubmitted");
+        assert_eq!(removed, Some((tx.hash(), RemovalReason::Confirmed)));
+        assert!(pool.get_transaction(&tx.hash()).is_none());
+        assert_eq!(pool.orphan_count(), 0);
+    }
+
     #[test]
     fn test_rbf_disabled() {
         let config = MempoolConfig {
@@ -444,4 +1435,366 @@ mod tests {
         // 60% increase should work
         assert!(pool.replace_transaction(tx2, 16).is_ok());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_full_pool_evicts_lowest_packages() {
+        let pool = TransactionPool::new(MempoolConfig { max_size: 3, ..MempoolConfig::default() });
+        let parent = create_test_transaction([1u8; 32], 50_000_000);
+        let child = create_test_transaction(parent.hash(), 40_000_000);
+        let cheap = create_test_transaction([2u8; 32], 50_000_000);
+        pool.add_transaction(parent.clone(), 1).unwrap();
+        pool.add_transaction(child.clone(), 20).unwrap();
+        pool.add_transaction(cheap.clone(), 2).unwrap();
+
+        // The child pays for its parent, lifting it above the cheap transaction
+        let hashes: Vec<[u8; 32]> = pool.get_sorted_transactions().iter().map(|tx| tx.hash()).collect();
+        assert_eq!(hashes, vec![parent.hash(), child.hash(), cheap.hash()]);
+        assert_eq!(pool.ancestor_fee_rate(&child.hash()), Some(10));
+
+        // A better-paying newcomer evicts the package least worth mining
+        let newcomer = create_test_transaction([3u8; 32], 50_000_000);
+        pool.add_transaction(newcomer.clone(), 5).unwrap();
+        assert!(pool.get_transaction(&cheap.hash()).is_none());
+        assert!(pool.get_transaction(&parent.hash()).is_some());
+
+        // Which raises the minimum fee above the evicted package
+        assert_eq!(pool.min_fee_rate(), 3);
+        assert_eq!(pool.add_transaction(create_test_transaction([4u8; 32], 50_000_000), 2), Err(MempoolError::FeeTooLow));
+        assert_eq!(pool.add_transaction(create_test_transaction([5u8; 32], 50_000_000), 3), Err(MempoolError::PoolFull));
+        assert_eq!(pool.min_fee_rate(), 4);
+
+        // Removing a parent takes its child along
+        assert_eq!(pool.remove_transaction(&parent.hash()), Some(parent));
+        assert!(pool.get_transaction(&child.hash()).is_none());
+        assert_eq!(pool.get_sorted_transactions(), vec![newcomer]);
+    }
+
+    #[test]
+    fn test_confirmed_transactions_feed_fee_estimates() {
+        let pool = TransactionPool::new(MempoolConfig::default());
+        assert_eq!(pool.estimate_smart_fee(1), None);
+
+        for height in 1..=5u8 {
+            let tx = create_test_transaction([height; 32], 50_000_000);
+            pool.add_transaction(tx.clone(), 12).unwrap();
+            assert_eq!(pool.remove_confirmed(height as u64, &[tx.clone()]), 1);
+            assert!(pool.get_transaction(&tx.hash()).is_none());
+        }
+        let estimate = pool.estimate_smart_fee(1).unwrap();
+        assert_eq!((estimate.fee_rate, estimate.target), (12, 1));
+
+        // Transactions removed without confirming teach nothing
+        let evicted = create_test_transaction([9u8; 32], 50_000_000);
+        pool.add_transaction(evicted.clone(), 1).unwrap();
+        pool.remove_transaction(&evicted.hash());
+        assert_eq!(pool.remove_confirmed(6, &[evicted]), 0);
+        assert_eq!(pool.estimate_smart_fee(1).unwrap().fee_rate, 12);
+    }
+
+    #[test]
+    fn test_accept_checks_inputs_and_computes_fee() {
+        let pool = TransactionPool::new(MempoolConfig::default());
+        let key = test_key();
+        let chain = TestChain { height: 200, ..TestChain::default() }
+            .with_coin([1u8; 32], locked(&key, 1_000_000), None)
+            .with_coin([2u8; 32], locked(&key, 1_000_000), Some(150));
+        let rejection = |result: Result<CheckedTransaction, MempoolError>| match result {
+            Err(MempoolError::Rejected(rejection)) => rejection.reason(),
+            other => panic!("expected a rejection, got {:?}", other),
+        };
+
+        // The fee comes from the spent output, not from the sender
+        let spend = signed_spend(&key, [1u8; 32], 1_000_000, 900_000, 0);
+        let checked = pool.accept_transaction(spend.clone(), &chain).unwrap();
+        assert_eq!(checked.fee, 100_000);
+        assert_eq!(checked.fee_rate, 100_000 / checked.size as u64);
+        assert!(pool.get_transaction(&spend.hash()).is_some());
+
+        // A child may spend its parent's output from the pool
+        let child = signed_spend(&key, spend.hash(), 900_000, 850_000, 0);
+        assert_eq!(pool.accept_transaction(child, &chain).unwrap().fee, 50_000);
+
+        // Paying more than the inputs hold
+        let overspend = signed_spend(&key, [1u8; 32], 1_000_000, 1_500_000, 0);
+        assert_eq!(pool.check_transaction(&overspend, &chain).unwrap_err().reason(), "bad-txns-in-belowout");
+
+        // Signing for a different amount than the output holds
+        let forged = signed_spend(&key, [1u8; 32], 2_000_000, 1_500_000, 0);
+        assert_eq!(rejection(pool.accept_transaction(forged, &chain)), "mandatory-script-verify-flag-failed");
+
+        // Unknown inputs, immature coinbases and future lock times
+        let unknown = signed_spend(&key, [3u8; 32], 1_000_000, 900_000, 0);
+        assert_eq!(rejection(pool.accept_transaction(unknown, &chain)), "bad-txns-inputs-missingorspent");
+        let coinbase_spend = signed_spend(&key, [2u8; 32], 1_000_000, 900_000, 0);
+        assert_eq!(
+            rejection(pool.accept_transaction(coinbase_spend.clone(), &chain)),
+            "bad-txns-premature-spend-of-coinbase"
+        );
+        let mature = TestChain { height: 250, ..chain.clone() };
+        assert!(pool.accept_transaction(coinbase_spend, &mature).is_ok());
+        let locked_spend = signed_spend(&key, [2u8; 32], 1_000_000, 800_000, 300);
+        assert_eq!(rejection(pool.accept_transaction(locked_spend, &mature)), "non-final");
+
+        // A conflicting spend paying enough more replaces the original
+        let replacement = signed_spend(&key, [1u8; 32], 1_000_000, 700_000, 0);
+        pool.accept_transaction(replacement.clone(), &chain).unwrap();
+        assert!(pool.get_transaction(&spend.hash()).is_none());
+        assert!(pool.get_transaction(&replacement.hash()).is_some());
+    }
+
+    #[test]
+    fn test_orphans_wait_for_parents() {
+        let pool = TransactionPool::new(MempoolConfig::default());
+        let key = test_key();
+        // Only the output funding the parent is on chain
+        let chain = TestChain::default().with_coin([1u8; 32], locked(&key, 1_000_000), None);
+
+        let parent = signed_spend(&key, [1u8; 32], 1_000_000, 900_000, 0);
+        let child = signed_spend(&key, parent.hash(), 900_000, 800_000, 0);
+        let grandchild = signed_spend(&key, child.hash(), 800_000, 700_000, 0);
+        let peer = PeerId::random();
+
+        assert_eq!(
+            pool.submit_transaction(grandchild.clone(), Some(peer), &chain),
+            Ok(Submission::Orphaned { missing: vec![child.hash()] })
+        );
+        assert!(matches!(pool.submit_transaction(child.clone(), Some(peer), &chain), Ok(Submission::Orphaned { .. })));
+        assert_eq!(pool.orphan_count(), 2);
+        assert!(matches!(
+            pool.submit_transaction(child.clone(), None, &chain),
+            Err(MempoolError::DuplicateTransaction)
+        ));
+
+        // The parent unlocks the whole chain
+        assert_eq!(
+            pool.submit_transaction(parent.clone(), None, &chain),
+            Ok(Submission::Accepted { resubmitted: vec![child.hash(), grandchild.hash()] })
+        );
+        assert_eq!(pool.orphan_count(), 0);
+        assert!(pool.get_transaction(&grandchild.hash()).is_some());
+
+        // An orphan rejected once its parent arrives counts against its sender
+        let unsigned = create_test_transaction([5u8; 32], 5_000);
+        pool.submit_transaction(unsigned, Some(peer), &chain).unwrap();
+        let confirmed = chain.clone().with_coin([5u8; 32], locked(&key, 10_000), None);
+        assert_eq!(pool.resubmit_orphans(&[[5u8; 32]], &confirmed), vec![]);
+        assert!(pool.orphan_score(&peer) > 0);
+
+        assert_eq!(pool.forget_peer(&peer), 0);
+        assert_eq!(pool.orphan_score(&peer), 0);
+    }
+
+    #[test]
+    fn test_package_pays_for_parent_and_replaces() {
+        let pool = TransactionPool::new(MempoolConfig::default());
+        let key = test_key();
+        let chain = TestChain { height: 200, ..TestChain::default() }
+            .with_coin([1u8; 32], locked(&key, 1_000_000), None)
+            .with_coin([2u8; 32], locked(&key, 1_000_000), None);
+
+        // A parent paying nothing cannot enter on its own, but its child can pay for both
+        let parent = signed_spend(&key, [1u8; 32], 1_000_000, 1_000_000, 0);
+        assert_eq!(pool.accept_transaction(parent.clone(), &chain), Err(MempoolError::FeeTooLow));
+        let child = signed_spend(&key, parent.hash(), 1_000_000, 900_000, 0);
+        let accepted = pool.submit_package(vec![parent.clone(), child.clone()], &chain).unwrap();
+        assert_eq!(accepted.accepted, vec![parent.hash(), child.hash()]);
+        assert_eq!(accepted.fee, 100_000);
+
+        let again = pool.submit_package(vec![parent.clone(), child.clone()], &chain).unwrap();
+        assert_eq!(again.already_in_pool, vec![parent.hash(), child.hash()]);
+        assert!(again.accepted.is_empty());
+
+        // A child paying nothing cannot ride on its parent
+        let rich_parent = signed_spend(&key, [2u8; 32], 1_000_000, 500_000, 0);
+        let free_child = signed_spend(&key, rich_parent.hash(), 500_000, 500_000, 0);
+        assert_eq!(
+            pool.submit_package(vec![rich_parent, free_child], &chain).unwrap_err().reason(),
+            "package-fee-too-low"
+        );
+
+        // A replacing package must pay for what it evicts and for its own relay
+        let replacement = signed_spend(&key, [1u8; 32], 1_000_000, 1_000_000, 1);
+        let cheap_child = signed_spend(&key, replacement.hash(), 1_000_000, 895_000, 0);
+        assert!(matches!(
+            pool.submit_package(vec![replacement.clone(), cheap_child], &chain),
+            Err(MempoolError::Package(PackageError::InsufficientFee { fee: 105_000, .. }))
+        ));
+
+        // And may not spend the transactions it evicts
+        let spends_replaced = signed_spends(
+            &key,
+            &[(replacement.hash(), 1_000_000), (child.hash(), 900_000)],
+            1_500_000,
+            0,
+        );
+        assert_eq!(
+            pool.submit_package(vec![replacement.clone(), spends_replaced], &chain).unwrap_err().reason(),
+            "bad-txns-spends-conflicting-tx"
+        );
+
+        let new_child = signed_spend(&key, replacement.hash(), 1_000_000, 800_000, 0);
+        let accepted = pool.submit_package(vec![replacement.clone(), new_child.clone()], &chain).unwrap();
+        assert_eq!(accepted.replaced, vec![child.hash(), parent.hash()]);
+        assert!(pool.get_transaction(&parent.hash()).is_none());
+        assert!(pool.get_transaction(&new_child.hash()).is_some());
+    }
+
+    #[test]
+    fn test_failed_package_replacement_restores_pool() {
+        let pool = TransactionPool::new(MempoolConfig { max_size: 2, ..MempoolConfig::default() });
+        let key = test_key();
+        let chain = TestChain { height: 200, ..TestChain::default() }
+            .with_coin([1u8; 32], locked(&key, 1_000_000), None)
+            .with_coin([2u8; 32], locked(&key, 1_000_000), None);
+        let victim = signed_spend(&key, [1u8; 32], 1_000_000, 990_000, 0);
+        let rich = signed_spend(&key, [2u8; 32], 1_000_000, 500_000, 0);
+        pool.accept_transaction(victim.clone(), &chain).unwrap();
+        pool.accept_transaction(rich.clone(), &chain).unwrap();
+        let entered = pool.snapshot().transactions;
+
+        // The package outbids the transaction it replaces but not the rest of the full pool
+        let parent = signed_spend(&key, [1u8; 32], 1_000_000, 1_000_000, 1);
+        let child = signed_spend(&key, parent.hash(), 1_000_000, 800_000, 0);
+        assert_eq!(pool.submit_package(vec![parent.clone(), child.clone()], &chain), Err(MempoolError::PoolFull));
+
+        assert!(pool.get_transaction(&parent.hash()).is_none());
+        assert!(pool.get_transaction(&child.hash()).is_none());
+        assert_eq!(pool.get_fee(&victim.hash()), Some(10_000));
+        assert!(pool.get_transaction(&rich.hash()).is_some());
+        assert_eq!(pool.snapshot().transactions, entered);
+    }
+
+    #[test]
+    fn test_failed_replacement_restores_pool() {
+        let pool = TransactionPool::new(MempoolConfig::default());
+        let victim = create_test_transaction([9u8; 32], 50_000_000);
+        pool.add_transaction(victim.clone(), 5).unwrap();
+        let victim_fee = pool.get_fee(&victim.hash());
+
+        // A replacement may not spend the transaction it evicts
+        let spends_victim = Transaction::new(
+            1,
+            vec![
+                TransactionInput::new([9u8; 32], 0, vec![], 0xffffffff),
+                TransactionInput::new(victim.hash(), 0, vec![], 0xffffffff),
+            ],
+            vec![TransactionOutput::new(40_000_000, vec![])],
+            0,
+        );
+        assert_eq!(pool.replace_transaction(spends_victim, 50), Err(MempoolError::SpendsConflicting));
+
+        // A replacement whose own ancestors are too many is only found out once the victim is gone
+        let mut tip = create_test_transaction([1u8; 32], 50_000_000);
+        pool.add_transaction(tip.clone(), 10).unwrap();
+        for _ in 1..25 {
+            tip = create_test_transaction(tip.hash(), 50_000_000);
+            pool.add_transaction(tip.clone(), 10).unwrap();
+        }
+        let too_deep = Transaction::new(
+            1,
+            vec![
+                TransactionInput::new([9u8; 32], 0, vec![], 0xffffffff),
+                TransactionInput::new(tip.hash(), 0, vec![], 0xffffffff),
+            ],
+            vec![TransactionOutput::new(40_000_000, vec![])],
+            0,
+        );
+        assert_eq!(pool.replace_transaction(too_deep.clone(), 50), Err(MempoolError::ChainLimitExceeded));
+        assert!(pool.get_transaction(&too_deep.hash()).is_none());
+        assert_eq!(pool.get_fee(&victim.hash()), victim_fee);
+        let same_rate = create_test_transaction([9u8; 32], 40_000_000);
+        assert_eq!(pool.replace_transaction(same_rate, 5).unwrap_err().reason(), "insufficient-fee");
+    }
+
+    #[test]
+    fn test_restore_revalidates_against_tip() {
+        let pool = TransactionPool::new(MempoolConfig::default());
+        let key = test_key();
+        let chain = TestChain::default()
+            .with_coin([1u8; 32], locked(&key, 1_000_000), None)
+            .with_coin([2u8; 32], locked(&key, 1_000_000), None);
+        let parent = signed_spend(&key, [1u8; 32], 1_000_000, 900_000, 0);
+        let child = signed_spend(&key, parent.hash(), 900_000, 800_000, 0);
+        let mined = signed_spend(&key, [2u8; 32], 1_000_000, 900_000, 0);
+        for tx in [&parent, &child, &mined] {
+            pool.accept_transaction(tx.clone(), &chain).unwrap();
+        }
+
+        // Deltas change the mining order but not the fee paid
+        pool.prioritise_transaction(&mined.hash(), -100_000);
+        pool.prioritise_transaction(&child.hash(), 50_000);
+        assert_eq!(pool.mining_order(usize::MAX).last(), Some(&mined));
+        assert_eq!(pool.get_fee(&mined.hash()), Some(100_000));
+
+        let mut snapshot = pool.snapshot();
+        let order: Vec<[u8; 32]> = snapshot.transactions.iter().map(|saved| saved.transaction.hash()).collect();
+        assert_eq!(order, vec![parent.hash(), child.hash(), mined.hash()]);
+        let stale = create_test_transaction([3u8; 32], 1_000);
+        snapshot.transactions.push(SavedTransaction { transaction: stale, entry_time: 0 });
+
+        // While the node was down `mined` confirmed, spending its coin
+        let restarted = TransactionPool::new(MempoolConfig::default());
+        let tip = TestChain::default().with_coin([1u8; 32], locked(&key, 1_000_000), None);
+        let stats = restarted.restore(snapshot.clone(), &tip);
+        assert_eq!(stats, LoadStats { accepted: 2, failed: 1, expired: 1, already_there: 0 });
+        assert_eq!(restarted.fee_delta(&child.hash()), 50_000);
+        assert_eq!(restarted.fee_delta(&mined.hash()), -100_000);
+        assert_eq!(restarted.get_fee(&child.hash()), Some(100_000));
+
+        // Restored transactions keep their entry times
+        assert_eq!(restarted.snapshot().transactions, snapshot.transactions[..2].to_vec());
+    }
+
+    #[test]
+    fn test_restore_replays_cpfp_as_package() {
+        let pool = TransactionPool::new(MempoolConfig::default());
+        let key = test_key();
+        let chain = TestChain { height: 200, ..TestChain::default() }
+            .with_coin([1u8; 32], locked(&key, 1_000_000), None)
+            .with_coin([2u8; 32], locked(&key, 1_000_000), None);
+        let parent = signed_spend(&key, [1u8; 32], 1_000_000, 1_000_000, 0);
+        let child = signed_spend(&key, parent.hash(), 1_000_000, 900_000, 0);
+        pool.submit_package(vec![parent.clone(), child.clone()], &chain).unwrap();
+
+        // A free parent nobody pays for is dropped
+        let mut snapshot = pool.snapshot();
+        let unpaid = signed_spend(&key, [2u8; 32], 1_000_000, 1_000_000, 0);
+        let entry_time = snapshot.transactions[0].entry_time;
+        snapshot.transactions.push(SavedTransaction { transaction: unpaid, entry_time });
+
+        let restarted = TransactionPool::new(MempoolConfig::default());
+        let stats = restarted.restore(snapshot.clone(), &chain);
+        assert_eq!(stats, LoadStats { accepted: 2, failed: 1, expired: 0, already_there: 0 });
+        assert_eq!(restarted.orphan_count(), 0);
+        assert_eq!(restarted.snapshot().transactions, snapshot.transactions[..2].to_vec());
+    }
+
+    #[test]
+    fn test_per_address_limit() {
+        let pool = TransactionPool::new(MempoolConfig { max_per_address: 2, ..MempoolConfig::default() });
+        let paying = |seed: u8| Transaction::new(
+            1,
+            vec![TransactionInput::new([seed; 32], 0, vec![], 0xffffffff)],
+            vec![TransactionOutput::new(1_000, vec![7]), TransactionOutput::new(2_000, vec![7])],
+            0,
+        );
+
+        // Outputs to the same script count once per transaction
+        pool.add_transaction(paying(1), 2).unwrap();
+        pool.add_transaction(paying(2), 2).unwrap();
+        assert!(matches!(pool.add_transaction(paying(3), 2), Err(MempoolError::AddressLimitExceeded)));
+
+        // Replacing one of them frees its place
+        let replacement = Transaction::new(
+            1,
+            vec![TransactionInput::new([1u8; 32], 0, vec![], 0xffffffff)],
+            vec![TransactionOutput::new(500, vec![7])],
+            0,
+        );
+        assert!(pool.replace_transaction(replacement, 10).is_ok());
+
+        pool.remove_transaction(&paying(2).hash());
+        pool.add_transaction(paying(3), 2).unwrap();
+        assert!(matches!(pool.add_transaction(paying(4), 2), Err(MempoolError::AddressLimitExceeded)));
+    }
+}
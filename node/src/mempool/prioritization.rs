@@ -11,6 +11,8 @@ pub struct PrioritizationConfig {
     max_ancestor_count: usize,
     /// Maximum size of ancestor package (in bytes)
     max_ancestor_size: usize,
+    /// Maximum number of descendants for a transaction
+    max_descendant_count: usize,
     /// Minimum fee rate for ancestor package (satoshis per byte)
     min_ancestor_fee_rate: u64,
     /// Time-based fee rate decay factor (percentage per hour)
@@ -22,6 +24,7 @@ impl From<config::MempoolConfig> for PrioritizationConfig {
         Self {
             max_ancestor_count: 25,
             max_ancestor_size: 101_000,  // ~100KB
+            max_descendant_count: 25,
             min_ancestor_fee_rate: config.min_fee_rate as u64,
             fee_rate_decay: 0.1,         // 0.1% per hour
         }
@@ -33,6 +36,7 @@ impl Default for PrioritizationConfig {
         Self {
             max_ancestor_count: 25,
             max_ancestor_size: 101_000,  // ~100KB
+            max_descendant_count: 25,
             min_ancestor_fee_rate: 1,    // 1 sat/byte
             fee_rate_decay: 0.1,         // 0.1% per hour
         }
@@ -43,6 +47,8 @@ impl Default for PrioritizationConfig {
 #[derive(Debug, Clone)]
 pub struct PrioritizedTransaction {
     pub transaction: Transaction,
+    /// Absolute fee in satoshis
+    pub fee: u64,
     pub fee_rate: u64,
     pub size: usize,
     pub timestamp: SystemTime,
@@ -52,9 +58,15 @@ pub struct PrioritizedTransaction {
 
 impl PrioritizedTransaction {
     pub fn new(transaction: Transaction, fee_rate: u64, size: usize) -> Self {
+        Self::with_fee(transaction, fee_rate.saturating_mul(size as u64), size)
+    }
+
+    /// Entry for a transaction paying an absolute `fee`
+    pub fn with_fee(transaction: Transaction, fee: u64, size: usize) -> Self {
         Self {
             transaction,
-            fee_rate,
+            fee,
+            fee_rate: fee / size.max(1) as u64,
             size,
             timestamp: SystemTime::now(),
             ancestors: HashSet::new(),
@@ -89,14 +101,20 @@ impl TransactionPrioritizer {
 
     /// Add a transaction to the prioritizer
     pub fn add_transaction(&mut self, transaction: Transaction, fee_rate: u64, size: usize) -> bool {
-        let tx_hash = transaction.hash();
-        
-        // Create prioritized transaction
-        let mut ptx = PrioritizedTransaction::new(transaction, fee_rate, size);
-        
+        self.insert(PrioritizedTransaction::new(transaction, fee_rate, size))
+    }
+
+    /// Add a transaction paying an absolute `fee`, failing if it breaks the ancestor or descendant limits
+    pub fn add_with_fee(&mut self, transaction: Transaction, fee: u64, size: usize) -> bool {
+        self.insert(PrioritizedTransaction::with_fee(transaction, fee, size))
+    }
+
+    fn insert(&mut self, mut ptx: PrioritizedTransaction) -> bool {
+        let tx_hash = ptx.transaction.hash();
+
         // Calculate ancestors
         let ancestors = self.calculate_ancestors(&ptx.transaction);
-        if !self.validate_ancestor_limits(&ancestors, size) {
+        if !self.validate_ancestor_limits(&ancestors, ptx.size) {
             return false;
         }
         ptx.ancestors = ancestors;
@@ -155,7 +173,11 @@ impl TransactionPrioritizer {
             return false;
         }
 
-        true
+        // Every ancestor gains a descendant
+        ancestors
+            .iter()
+            .filter_map(|hash| self.transactions.get(hash))
+            .all(|ancestor| ancestor.descendants.len() < self.config.max_descendant_count)
     }
 
     /// Get transactions sorted by priority
@@ -199,6 +221,9 @@ impl TransactionPrioritizer {
     }
     
     /// Remove a transaction from the prioritizer
+    ///
+    /// Its descendants stay, no longer counting it as an ancestor, as when it
+    /// is confirmed. Remove them first when they go too.
     pub fn remove_transaction(&mut self, tx_hash: &[u8; 32]) -> Option<Transaction> {
         if let Some(ptx) = self.transactions.remove(tx_hash) {
             // Update descendant information for ancestors
@@ -207,6 +232,11 @@ impl TransactionPrioritizer {
                     ancestor.descendants.remove(tx_hash);
                 }
             }
+            for descendant_hash in &ptx.descendants {
+                if let Some(descendant) = self.transactions.get_mut(descendant_hash) {
+                    descendant.ancestors.remove(tx_hash);
+                }
+            }
             
             // Return the transaction
             Some(ptx.transaction)
@@ -214,6 +244,135 @@ impl TransactionPrioritizer {
             None
         }
     }
+
+    pub fn contains(&self, tx_hash: &[u8; 32]) -> bool {
+        self.transactions.contains_key(tx_hash)
+    }
+
+    pub fn len(&self) -> usize {
+        self.transactions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.transactions.is_empty()
+    }
+
+    /// Change the fee a tracked transaction is ranked by, returning whether it was tracked
+    pub fn set_fee(&mut self, tx_hash: &[u8; 32], fee: u64) -> bool {
+        match self.transactions.get_mut(tx_hash) {
+            Some(ptx) => {
+                ptx.fee = fee;
+                ptx.fee_rate = fee / ptx.size.max(1) as u64;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Descendants of a transaction still in the prioritizer
+    pub fn descendants(&self, tx_hash: &[u8; 32]) -> HashSet<[u8; 32]> {
+        self.transactions
+            .get(tx_hash)
+            .map(|ptx| ptx.descendants.clone())
+            .unwrap_or_default()
+    }
+
+    /// Fee and size of a transaction together with all its ancestors
+    pub fn ancestor_package(&self, tx_hash: &[u8; 32]) -> Option<(u64, usize)> {
+        let ptx = self.transactions.get(tx_hash)?;
+        Some(self.package_with(ptx, &ptx.ancestors))
+    }
+
+    /// Fee and size of a transaction together with all its descendants
+    pub fn descendant_package(&self, tx_hash: &[u8; 32]) -> Option<(u64, usize)> {
+        let ptx = self.transactions.get(tx_hash)?;
+        Some(self.package_with(ptx, &ptx.descendants))
+    }
+
+    /// Fee rate of a transaction's ancestor package, which is what mining it requires
+    pub fn ancestor_fee_rate(&self, tx_hash: &[u8; 32]) -> Option<u64> {
+        self.ancestor_package(tx_hash).map(|(fee, size)| fee / size.max(1) as u64)
+    }
+
+    /// Higher of a transaction's own fee rate and that of its descendant package
+    ///
+    /// A low score means neither the transaction nor anything spending it pays
+    /// to be mined, which makes it the first to evict.
+    pub fn descendant_score(&self, tx_hash: &[u8; 32]) -> Option<u64> {
+        let ptx = self.transactions.get(tx_hash)?;
+        let (fee, size) = self.package_with(ptx, &ptx.descendants);
+        Some(ptx.fee_rate.max(fee / size.max(1) as u64))
+    }
+
+    /// Transaction with the lowest [`Self::descendant_score`], and that score
+    pub fn lowest_descendant_score(&self) -> Option<([u8; 32], u64)> {
+        self.transactions
+            .keys()
+            .filter_map(|hash| Some((*hash, self.descendant_score(hash)?)))
+            .min_by(|a, b| a.1.cmp(&b.1).then_with(|| a.0.cmp(&b.0)))
+    }
+
+    /// Transactions to mine within `max_size` bytes, in block order
+    ///
+    /// Packages are picked by the fee rate of a transaction and its ancestors
+    /// not yet picked, so a child paying for its parent pulls the parent in.
+    /// Within a package, parents come before their children.
+    pub fn select_packages(&self, max_size: usize) -> Vec<[u8; 32]> {
+        let mut selected = Vec::new();
+        let mut included: HashSet<[u8; 32]> = HashSet::new();
+        // Selected transactions and those that can no longer fit
+        let mut done: HashSet<[u8; 32]> = HashSet::new();
+        let mut total_size = 0usize;
+
+        loop {
+            let best = self.transactions
+                .iter()
+                .filter(|(hash, _)| !done.contains(*hash))
+                .map(|(hash, ptx)| {
+                    let pending: HashSet<[u8; 32]> = ptx.ancestors.difference(&included).copied().collect();
+                    let (fee, size) = self.package_with(ptx, &pending);
+                    (*hash, fee, size, pending)
+                })
+                .max_by(|a, b| {
+                    // Compare fee / size without rounding
+                    (a.1 as u128 * b.2 as u128)
+                        .cmp(&(b.1 as u128 * a.2 as u128))
+                        .then_with(|| b.0.cmp(&a.0))
+                });
+            let (tx_hash, _, size, pending) = match best {
+                Some(best) => best,
+                None => break,
+            };
+
+            if total_size + size > max_size {
+                // Anything spending it would need it in the block too
+                done.insert(tx_hash);
+                done.extend(self.descendants(&tx_hash));
+                continue;
+            }
+
+            let mut package: Vec<[u8; 32]> = pending.into_iter().collect();
+            package.push(tx_hash);
+            // A parent always has fewer ancestors than its child
+            package.sort_by_key(|hash| self.transactions.get(hash).map_or(0, |ptx| ptx.ancestors.len()));
+            for hash in package {
+                included.insert(hash);
+                done.insert(hash);
+                selected.push(hash);
+            }
+            total_size += size;
+        }
+
+        selected
+    }
+
+    /// Fee and size of `ptx` plus the transactions in `others`
+    fn package_with(&self, ptx: &PrioritizedTransaction, others: &HashSet<[u8; 32]>) -> (u64, usize) {
+        others
+            .iter()
+            .filter_map(|hash| self.transactions.get(hash))
+            .fold((ptx.fee, ptx.size), |(fee, size), other| (fee.saturating_add(other.fee), size + other.size))
+    }
 }
 
 #[cfg(test)]
@@ -265,4 +424,32 @@ mod tests {
         assert!(prioritizer.add_transaction(tx2, 1, 250));
         assert!(!prioritizer.add_transaction(tx3, 1, 250));  // Should fail due to ancestor limit
     }
+
+    #[test]
+    fn test_packages_and_descendant_scores() {
+        let mut prioritizer = TransactionPrioritizer::new(PrioritizationConfig::default());
+
+        // A cheap parent whose child pays for both, and an unrelated middling transaction
+        let parent = create_test_transaction([1u8; 32], 50_000_000);
+        let child = create_test_transaction(parent.hash(), 40_000_000);
+        let other = create_test_transaction([2u8; 32], 30_000_000);
+        assert!(prioritizer.add_with_fee(parent.clone(), 100, 100));
+        assert!(prioritizer.add_with_fee(child.clone(), 1_900, 100));
+        assert!(prioritizer.add_with_fee(other.clone(), 500, 100));
+
+        assert_eq!(prioritizer.ancestor_package(&child.hash()), Some((2_000, 200)));
+        assert_eq!(prioritizer.ancestor_fee_rate(&child.hash()), Some(10));
+        assert_eq!(prioritizer.descendant_package(&parent.hash()), Some((2_000, 200)));
+        assert_eq!(prioritizer.descendant_score(&parent.hash()), Some(10));
+        assert_eq!(prioritizer.lowest_descendant_score(), Some((other.hash(), 5)));
+
+        // The child pulls its parent ahead of the better-paying unrelated transaction
+        assert_eq!(prioritizer.select_packages(usize::MAX), vec![parent.hash(), child.hash(), other.hash()]);
+        // Without room for the package, the unrelated transaction is all that fits
+        assert_eq!(prioritizer.select_packages(150), vec![other.hash()]);
+
+        // Once the parent confirms, the child stands alone
+        prioritizer.remove_transaction(&parent.hash());
+        assert_eq!(prioritizer.ancestor_package(&child.hash()), Some((1_900, 100)));
+    }
 }
\ No newline at end of file
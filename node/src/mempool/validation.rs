@@ -0,0 +1,272 @@
+//! Checks a transaction must pass before it enters the mempool
+//!
+//! Inputs are resolved through a [`ChainView`] (the chain's unspent outputs)
+//! and the pool's own transactions, so the fee is computed from what the
+//! transaction really spends instead of trusting the relaying peer. Failures
+//! carry a [`TxRejection`] with a reason string matching Bitcoin Core's.
+
+use btclib::consensus::{is_coinbase_mature, COINBASE_MATURITY};
+use btclib::types::script::ScriptError;
+use btclib::types::transaction::{Transaction, TransactionOutput};
+use std::collections::HashMap;
+use thiserror::Error;
+use crate::storage::ChainState;
+
+/// Lock times below this are block heights, those above are UNIX timestamps
+pub const LOCKTIME_THRESHOLD: u32 = 500_000_000;
+
+/// Input sequence that opts out of the transaction's lock time
+pub const SEQUENCE_FINAL: u32 = 0xffffffff;
+
+/// An unspent output and how it was created
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Coin {
+    pub output: TransactionOutput,
+    /// Height of the block whose coinbase created the output; `None` for other
+    /// outputs. Views may leave it out for coinbases already past maturity.
+    pub coinbase_height: Option<u64>,
+}
+
+/// What mempool acceptance needs to know about the chain
+pub trait ChainView {
+    /// Unspent output `index` of `tx_hash` in the chain
+    fn coin(&self, tx_hash: &[u8; 32], index: u32) -> Option<Coin>;
+
+    /// Height of the best block
+    fn tip_height(&self) -> u64;
+
+    /// Median timestamp of the last blocks, see [`ChainState::median_time_past`]
+    fn median_time_past(&self) -> u64;
+}
+
+impl ChainView for ChainState {
+    fn coin(&self, tx_hash: &[u8; 32], index: u32) -> Option<Coin> {
+        let output = self.get_db().get_utxo(tx_hash, index).ok().flatten()?;
+        let coinbase_height = self.coinbase_height(tx_hash).ok().flatten();
+        Some(Coin { output, coinbase_height })
+    }
+
+    fn tip_height(&self) -> u64 {
+        self.get_height()
+    }
+
+    fn median_time_past(&self) -> u64 {
+        ChainState::median_time_past(self).unwrap_or(0)
+    }
+}
+
+impl<T: ChainView + ?Sized> ChainView for &T {
+    fn coin(&self, tx_hash: &[u8; 32], index: u32) -> Option<Coin> {
+        (**self).coin(tx_hash, index)
+    }
+
+    fn tip_height(&self) -> u64 {
+        (**self).tip_height()
+    }
+
+    fn median_time_past(&self) -> u64 {
+        (**self).median_time_past()
+    }
+}
+
+/// Why a transaction was refused by the mempool
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum TxRejection {
+    #[error("Coinbase transactions cannot be relayed")]
+    Coinbase,
+
+    #[error("Transaction has no inputs or no outputs")]
+    Empty,
+
+    #[error("Output {0}:{1} is spent twice")]
+    DuplicateInput(String, u32),
+
+    #[error("Output {0}:{1} is missing or already spent")]
+    MissingInputs(String, u32),
+
+    #[error("Coinbase output created at height {created_height} cannot be spent before height {mature_height}")]
+    ImmatureCoinbase { created_height: u64, mature_height: u64 },
+
+    #[error("Transaction is locked until {0}")]
+    NonFinal(u32),
+
+    #[error("Input or output amounts overflow")]
+    ValueOverflow,
+
+    #[error("Outputs of {outputs} exceed inputs of {inputs}")]
+    InsufficientInputs { inputs: u64, outputs: u64 },
+
+    #[error("Script verification failed: {0}")]
+    Script(#[from] ScriptError),
+}
+
+impl TxRejection {
+    /// Bitcoin Core reason string
+    pub fn reason(&self) -> &'static str {
+        match self {
+            TxRejection::Coinbase => "coinbase",
+            TxRejection::Empty => "bad-txns-empty",
+            TxRejection::DuplicateInput(..) => "bad-txns-inputs-duplicate",
+            TxRejection::MissingInputs(..) => "bad-txns-inputs-missingorspent",
+            TxRejection::ImmatureCoinbase { .. } => "bad-txns-premature-spend-of-coinbase",
+            TxRejection::NonFinal(_) => "non-final",
+            TxRejection::ValueOverflow => "bad-txns-inputvalues-outofrange",
+            TxRejection::InsufficientInputs { .. } => "bad-txns-in-belowout",
+            TxRejection::Script(_) => "mandatory-script-verify-flag-failed",
+        }
+    }
+
+    /// Whether the transaction can never become valid, as opposed to not yet
+    pub fn is_invalid(&self) -> bool {
+        !matches!(
+            self,
+            TxRejection::MissingInputs(..) | TxRejection::ImmatureCoinbase { .. } | TxRejection::NonFinal(_)
+        )
+    }
+}
+
+/// Fee a transaction pays, as computed from the outputs it spends
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CheckedTransaction {
+    pub fee: u64,
+    /// Serialized size in bytes
+    pub size: usize,
+    /// Satoshis per byte, rounded down
+    pub fee_rate: u64,
+}
+
+/// Whether `tx` may be included in a block at `height` whose median time past is `time`
+///
+/// A lock time is ignored once every input opts out of it with [`SEQUENCE_FINAL`].
+pub fn is_final(tx: &Transaction, height: u64, time: u64) -> bool {
+    let lock_time = tx.lock_time();
+    if lock_time == 0 {
+        return true;
+    }
+    let reached = if lock_time < LOCKTIME_THRESHOLD {
+        height
+    } else {
+        time
+    };
+    (lock_time as u64) < reached || tx.inputs().iter().all(|input| input.sequence() == SEQUENCE_FINAL)
+}
+
+/// Check `tx` for inclusion in the block after `tip_height`
+///
+/// `resolve` finds the outputs spent by the inputs. The transaction must be
+/// final, spend each existing output once, not spend immature coinbases,
+/// unlock every input and pay no more than it spends.
+pub fn check_transaction(
+    tx: &Transaction,
+    tip_height: u64,
+    median_time_past: u64,
+    resolve: impl Fn(&[u8; 32], u32) -> Option<Coin>,
+) -> Result<CheckedTransaction, TxRejection> {
+    if tx.is_coinbase() {
+        return Err(TxRejection::Coinbase);
+    }
+    if tx.inputs().is_empty() || tx.outputs().is_empty() {
+        return Err(TxRejection::Empty);
+    }
+
+    let spend_height = tip_height + 1;
+    if !is_final(tx, spend_height, median_time_past) {
+        return Err(TxRejection::NonFinal(tx.lock_time()));
+    }
+
+    let mut spent: HashMap<([u8; 32], u32), TransactionOutput> = HashMap::new();
+    for input in tx.inputs() {
+        let outpoint = (input.prev_tx_hash(), input.prev_output_index());
+        if spent.contains_key(&outpoint) {
+            return Err(TxRejection::DuplicateInput(hex::encode(outpoint.0), outpoint.1));
+        }
+        let coin = resolve(&outpoint.0, outpoint.1)
+            .ok_or_else(|| TxRejection::MissingInputs(hex::encode(outpoint.0), outpoint.1))?;
+        if let Some(created_height) = coin.coinbase_height {
+            if !is_coinbase_mature(created_height, spend_height) {
+                return Err(TxRejection::ImmatureCoinbase {
+                    created_height,
+                    mature_height: created_height + COINBASE_MATURITY,
+                });
+            }
+        }
+        spent.insert(outpoint, coin.output);
+    }
+
+    tx.verify_signatures(|hash, index| spent.get(&(*hash, index)).cloned())?;
+
+    let inputs = spent
+        .values()
+        .try_fold(0u64, |total, output| total.checked_add(output.amount()))
+        .ok_or(TxRejection::ValueOverflow)?;
+    let outputs = tx
+        .outputs()
+        .iter()
+        .try_fold(0u64, |total, output| total.checked_add(output.amount()))
+        .ok_or(TxRejection::ValueOverflow)?;
+    let fee = inputs
+        .checked_sub(outputs)
+        .ok_or(TxRejection::InsufficientInputs { inputs, outputs })?;
+
+    let size = bincode::serialized_size(tx).map_or(usize::MAX, |size| size as usize);
+    Ok(CheckedTransaction { fee, size, fee_rate: fee / size.max(1) as u64 })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use btclib::types::transaction::TransactionInput;
+
+    fn spend(lock_time: u32, sequence: u32) -> Transaction {
+        Transaction::new(
+            1,
+            vec![TransactionInput::new([1u8; 32], 0, vec![], sequence)],
+            vec![TransactionOutput::new(1_000, vec![])],
+            lock_time,
+        )
+    }
+
+    #[test]
+    fn test_lock_time_finality() {
+        assert!(is_final(&spend(0, 0), 10, 0));
+
+        // Height locks apply to blocks strictly above the lock time
+        assert!(!is_final(&spend(10, 0), 10, 0));
+        assert!(is_final(&spend(10, 0), 11, 0));
+
+        // Time locks compare against the median time past
+        let locked_until = LOCKTIME_THRESHOLD + 1_000;
+        assert!(!is_final(&spend(locked_until, 0), 1_000_000, locked_until as u64));
+        assert!(is_final(&spend(locked_until, 0), 0, locked_until as u64 + 1));
+
+        // Final sequences opt out of the lock time
+        assert!(is_final(&spend(10, SEQUENCE_FINAL), 1, 0));
+    }
+
+    #[test]
+    fn test_rejections_before_scripts() {
+        let coin = |coinbase_height| Coin { output: TransactionOutput::new(5_000, vec![]), coinbase_height };
+
+        let missing = check_transaction(&spend(0, 0), 200, 0, |_, _| None);
+        assert_eq!(missing.unwrap_err().reason(), "bad-txns-inputs-missingorspent");
+
+        let immature = check_transaction(&spend(0, 0), 150, 0, |_, _| Some(coin(Some(100))));
+        assert_eq!(
+            immature,
+            Err(TxRejection::ImmatureCoinbase { created_height: 100, mature_height: 100 + COINBASE_MATURITY })
+        );
+        assert!(!immature.unwrap_err().is_invalid());
+
+        let duplicate = Transaction::new(
+            1,
+            vec![TransactionInput::new([1u8; 32], 0, vec![], 0), TransactionInput::new([1u8; 32], 0, vec![], 0)],
+            vec![TransactionOutput::new(1_000, vec![])],
+            0,
+        );
+        let rejection = check_transaction(&duplicate, 200, 0, |_, _| Some(coin(None))).unwrap_err();
+        assert_eq!(rejection.reason(), "bad-txns-inputs-duplicate");
+        assert!(rejection.is_invalid());
+
+        assert_eq!(check_transaction(&spend(500, 0), 200, 0, |_, _| Some(coin(None))), Err(TxRejection::NonFinal(500)));
+    }
+}
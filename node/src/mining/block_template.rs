@@ -0,0 +1,742 @@
+use super::ChainTip;
+use crate::events::{EventBus, NodeEvent};
+use crate::mempool::TransactionPool;
+use crate::storage::{ChainState, StorageError};
+use btclib::consensus::{
+    self, check_block_limits, transaction_sigop_cost, transaction_weight, BlockLimitError,
+    CoinbaseError, ENVIRONMENTAL_TREASURY_SCRIPT, MAX_BLOCK_SIGOPS_COST, MAX_BLOCK_SIZE,
+    MAX_BLOCK_WEIGHT,
+};
+use btclib::types::block::Block;
+use btclib::types::transaction::Transaction;
+use miner::mining::BlockTemplate;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use thiserror::Error;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::{watch, RwLock};
+use tracing::{debug, info, warn};
+
+/// How far ahead of local time a block timestamp may be
+const MAX_FUTURE_BLOCK_TIME: u64 = 2 * 60 * 60;
+
+/// How often a long poll re-checks the chain tip and mempool
+const LONGPOLL_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Configuration for block template generation
+#[derive(Debug, Clone)]
+pub struct BlockTemplateConfig {
+    /// Version of generated blocks
+    pub block_version: u32,
+    /// Script paid by the coinbase in `coinbasetxn`
+    pub payout_script: Vec<u8>,
+    /// Minimum time a long poll waits before returning for mempool-only changes
+    pub longpoll_mempool_interval: Duration,
+    /// Environmental attestation commitment added to `coinbasetxn`
+    pub attestation_commitment: Option<[u8; 32]>,
+}
+
+impl Default for BlockTemplateConfig {
+    fn default() -> Self {
+        Self {
+            block_version: 1,
+            payout_script: Vec::new(),
+            longpoll_mempool_interval: Duration::from_secs(60),
+            attestation_commitment: None,
+        }
+    }
+}
+
+/// Parameters of a `getblocktemplate` request (BIP22/BIP23)
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct TemplateRequest {
+    /// `"template"` (default) or `"proposal"`
+    #[serde(default)]
+    pub mode: Option<String>,
+    /// Client capabilities, e.g. `"longpoll"`, `"proposal"`
+    #[serde(default)]
+    pub capabilities: Vec<String>,
+    /// Long poll id from a previous template; blocks until it is stale
+    #[serde(default)]
+    pub longpollid: Option<String>,
+    /// Hex-encoded block for proposal mode
+    #[serde(default)]
+    pub data: Option<String>,
+}
+
+/// A transaction entry in a block template
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TemplateTransaction {
+    /// Hex-encoded bincode serialization
+    pub data: String,
+    pub txid: String,
+    pub hash: String,
+    /// 1-based indices of template transactions this one spends from
+    pub depends: Vec<usize>,
+    pub fee: u64,
+    pub sigops: usize,
+    pub weight: usize,
+}
+
+impl TemplateTransaction {
+    fn new(tx: &Transaction, depends: Vec<usize>, fee: u64) -> Self {
+        let txid = hex::encode(tx.hash());
+        Self {
+            data: hex::encode(bincode::serialize(tx).unwrap_or_default()),
+            hash: txid.clone(),
+            txid,
+            depends,
+            fee,
+            sigops: transaction_sigop_cost(tx),
+            weight: transaction_weight(tx),
+        }
+    }
+}
+
+/// Environmental treasury output every coinbase must carry
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TreasuryOutput {
+    /// Hex-encoded output script
+    pub script: String,
+    pub amount: u64,
+}
+
+/// `getblocktemplate` result
+///
+/// `coinbasevalue` is what the miner may pay itself (subsidy plus fees, minus
+/// the treasury share); a miner building its own coinbase must also add the
+/// `treasury` output. `coinbasetxn` is a ready-made coinbase paying the node's
+/// configured payout script.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BlockTemplateResponse {
+    pub capabilities: Vec<String>,
+    pub version: u32,
+    pub previousblockhash: String,
+    pub transactions: Vec<TemplateTransaction>,
+    pub coinbaseaux: HashMap<String, String>,
+    pub coinbasevalue: u64,
+    pub coinbasetxn: TemplateTransaction,
+    pub treasury: TreasuryOutput,
+    pub longpollid: String,
+    pub target: String,
+    pub mintime: u64,
+    pub mutable: Vec<String>,
+    pub noncerange: String,
+    pub sigoplimit: usize,
+    pub sizelimit: usize,
+    pub weightlimit: usize,
+    pub curtime: u64,
+    pub bits: String,
+    pub height: u64,
+}
+
+/// Errors producing a block template
+#[derive(Debug, Error)]
+pub enum TemplateError {
+    #[error("Invalid template request: {0}")]
+    InvalidRequest(String),
+
+    #[error("Storage error: {0}")]
+    Storage(#[from] StorageError),
+}
+
+/// Reasons a submitted or proposed block is not accepted
+///
+/// `reason()` gives the BIP22 reason string returned to mining software.
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum BlockRejection {
+    #[error("Block is already known")]
+    Duplicate,
+
+    #[error("Block does not build on the current best block")]
+    NotBestPrevBlock,
+
+    #[error("Block was stored but is not part of the best chain")]
+    Inconclusive,
+
+    #[error("Block target {found:08x} does not match the required {expected:08x}")]
+    BadDiffBits { expected: u32, found: u32 },
+
+    #[error("Block hash does not meet its target")]
+    HighHash,
+
+    #[error("Merkle root does not match the block's transactions")]
+    BadMerkleRoot,
+
+    #[error("Block contains duplicate transactions")]
+    DuplicateTransaction,
+
+    #[error("Block timestamp is too far in the future")]
+    TimeTooNew,
+
+    #[error("Block timestamp is not after the median time of the last blocks")]
+    TimeTooOld,
+
+    #[error("Block spends a coinbase created at height {created_height} before height {mature_height}")]
+    ImmatureCoinbase { created_height: u64, mature_height: u64 },
+
+    #[error("Block contains an invalid transaction: {0}")]
+    InvalidTransaction(String),
+
+    #[error(transparent)]
+    Coinbase(#[from] CoinbaseError),
+
+    #[error(transparent)]
+    Limits(#[from] BlockLimitError),
+
+    #[error("Block rejected: {0}")]
+    Rejected(String),
+}
+
+impl BlockRejection {
+    /// BIP22 reason string
+    pub fn reason(&self) -> &'static str {
+        match self {
+            BlockRejection::Duplicate => "duplicate",
+            BlockRejection::NotBestPrevBlock => "inconclusive-not-best-prevblk",
+            BlockRejection::Inconclusive => "inconclusive",
+            BlockRejection::BadDiffBits { .. } => "bad-diffbits",
+            BlockRejection::HighHash => "high-hash",
+            BlockRejection::BadMerkleRoot => "bad-txnmrklroot",
+            BlockRejection::DuplicateTransaction => "bad-txns-duplicate",
+            BlockRejection::TimeTooNew => "time-too-new",
+            BlockRejection::TimeTooOld => "time-too-old",
+            BlockRejection::ImmatureCoinbase { .. } => "bad-txns-premature-spend-of-coinbase",
+            BlockRejection::InvalidTransaction(_) => "bad-txns-invalid",
+            BlockRejection::Coinbase(CoinbaseError::MissingCoinbase) => "bad-cb-missing",
+            BlockRejection::Coinbase(CoinbaseError::MultipleCoinbase(_)) => "bad-cb-multiple",
+            BlockRejection::Coinbase(CoinbaseError::MissingTreasuryOutput { .. }) => "bad-cb-treasury",
+            BlockRejection::Coinbase(_) => "bad-cb-amount",
+            BlockRejection::Limits(BlockLimitError::Overweight { .. }) => "bad-blk-weight",
+            BlockRejection::Limits(BlockLimitError::TooManySigops { .. }) => "bad-blk-sigops",
+            BlockRejection::Rejected(_) => "rejected",
+        }
+    }
+}
+
+impl From<StorageError> for BlockRejection {
+    fn from(error: StorageError) -> Self {
+        match error {
+            StorageError::InvalidTransaction(reason) => BlockRejection::InvalidTransaction(reason),
+            StorageError::ImmatureCoinbase { created_height, mature_height } => {
+                BlockRejection::ImmatureCoinbase { created_height, mature_height }
+            }
+            error => BlockRejection::Rejected(error.to_string()),
+        }
+    }
+}
+
+/// Builds block templates from the mempool and accepts solved blocks
+pub struct BlockTemplateService {
+    config: BlockTemplateConfig,
+    chain_state: Arc<RwLock<ChainState>>,
+    mempool: Arc<TransactionPool>,
+    tip_tx: watch::Sender<ChainTip>,
+}
+
+impl BlockTemplateService {
+    /// Create the service, reading the current tip from `chain_state`
+    ///
+    /// Called while the node is being built, so the chain state must not be write-locked.
+    pub fn new(
+        config: BlockTemplateConfig,
+        chain_state: Arc<RwLock<ChainState>>,
+        mempool: Arc<TransactionPool>,
+    ) -> Result<Self, StorageError> {
+        let (tip, _) = {
+            let chain = chain_state
+                .try_read()
+                .map_err(|_| StorageError::DatabaseError("Chain state is locked".to_string()))?;
+            Self::load_tip(&config, &chain)?
+        };
+        let (tip_tx, _) = watch::channel(tip);
+
+        Ok(Self {
+            config,
+            chain_state,
+            mempool,
+            tip_tx,
+        })
+    }
+
+    /// Receiver notified whenever the best block changes (used by the Stratum server)
+    pub fn subscribe_tip(&self) -> watch::Receiver<ChainTip> {
+        self.tip_tx.subscribe()
+    }
+
+    /// Re-read the chain tip and notify subscribers if it moved
+    ///
+    /// [`Self::follow_chain`] calls this for every block the chain state connects.
+    pub async fn refresh_tip(&self) -> Result<ChainTip, StorageError> {
+        let (tip, _) = Self::load_tip(&self.config, &*self.chain_state.read().await)?;
+        self.tip_tx.send_if_modified(|current| {
+            if *current == tip {
+                return false;
+            }
+            *current = tip.clone();
+            true
+        });
+        Ok(tip)
+    }
+
+    /// Refresh the tip whenever `events` reports a connected block, wherever it came from
+    pub fn follow_chain(self: &Arc<Self>, events: &EventBus) {
+        let mut receiver = events.subscribe();
+        let service = Arc::clone(self);
+        tokio::spawn(async move {
+            loop {
+                match receiver.recv().await {
+                    // A missed event may have been a new block, so check anyway
+                    Ok(NodeEvent::BlockConnected { .. }) | Err(RecvError::Lagged(_)) => {
+                        if let Err(e) = service.refresh_tip().await {
+                            warn!("Failed to refresh the template tip: {}", e);
+                        }
+                    }
+                    Ok(_) => {}
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        });
+    }
+
+    /// Tip to build on plus the median time past a block on it must exceed
+    fn load_tip(config: &BlockTemplateConfig, chain: &ChainState) -> Result<(ChainTip, u64), StorageError> {
+        let tip = ChainTip {
+            version: config.block_version,
+            prev_block_hash: chain.get_best_block_hash(),
+            height: chain.get_height() + 1,
+            target: chain.next_work_required()?,
+        };
+        Ok((tip, chain.median_time_past()?))
+    }
+
+    fn longpoll_id(&self, tip: &ChainTip) -> String {
+        format!("{}{:016x}", hex::encode(tip.prev_block_hash), self.mempool.sequence())
+    }
+
+    /// Wait until the template identified by `longpollid` is stale
+    ///
+    /// Returns immediately on a new tip; mempool changes only end the wait once
+    /// `longpoll_mempool_interval` has passed, so miners are not flooded with templates.
+    async fn wait_for_change(&self, longpollid: &str) -> Result<(), TemplateError> {
+        if longpollid.len() != 80 {
+            return Err(TemplateError::InvalidRequest("malformed longpollid".to_string()));
+        }
+        let (prev_hash, sequence) = longpollid.split_at(64);
+        let sequence = u64::from_str_radix(sequence, 16)
+            .map_err(|_| TemplateError::InvalidRequest("malformed longpollid".to_string()))?;
+
+        let start = Instant::now();
+        let mut tip_rx = self.tip_tx.subscribe();
+
+        loop {
+            let tip = self.refresh_tip().await?;
+            if hex::encode(tip.prev_block_hash) != prev_hash {
+                return Ok(());
+            }
+            if self.mempool.sequence() != sequence && start.elapsed() >= self.config.longpoll_mempool_interval {
+                return Ok(());
+            }
+
+            tokio::select! {
+                _ = tip_rx.changed() => {}
+                _ = tokio::time::sleep(LONGPOLL_CHECK_INTERVAL) => {}
+            }
+        }
+    }
+
+    /// Build a block template, waiting first if a long poll id is given
+    pub async fn get_block_template(&self, request: &TemplateRequest) -> Result<BlockTemplateResponse, TemplateError> {
+        if let Some(longpollid) = &request.longpollid {
+            self.wait_for_change(longpollid).await?;
+        }
+
+        let (tip, median_time_past) = Self::load_tip(&self.config, &*self.chain_state.read().await)?;
+        let longpollid = self.longpoll_id(&tip);
+
+        let mut template = BlockTemplate::new(
+            tip.version,
+            tip.prev_block_hash,
+            tip.height,
+            tip.target,
+            self.config.payout_script.clone(),
+            &*self.mempool,
+        )
+        .await;
+        template.set_attestation_commitment(self.config.attestation_commitment);
+
+        // Dependencies refer to 1-based positions among the template's transactions
+        let positions: HashMap<[u8; 32], usize> = template
+            .transactions()
+            .iter()
+            .enumerate()
+            .map(|(i, tx)| (tx.hash(), i + 1))
+            .collect();
+
+        let transactions = template
+            .transactions()
+            .iter()
+            .zip(template.transaction_fees())
+            .map(|(tx, fee)| {
+                let mut depends: Vec<usize> = tx
+                    .inputs()
+                    .iter()
+                    .filter_map(|input| positions.get(&input.prev_tx_hash()).copied())
+                    .collect();
+                depends.sort_unstable();
+                depends.dedup();
+                TemplateTransaction::new(tx, depends, *fee)
+            })
+            .collect();
+
+        let coinbase = template.coinbase();
+        let treasury_amount: u64 = coinbase
+            .outputs()
+            .iter()
+            .filter(|output| output.pub_key_script() == ENVIRONMENTAL_TREASURY_SCRIPT)
+            .map(|output| output.amount())
+            .sum();
+        let coinbase_value = consensus::block_subsidy(tip.height) + template.total_fees() - treasury_amount;
+
+        let target = consensus::compact_to_target(tip.target).unwrap_or_default();
+
+        debug!(
+            "Built block template at height {} with {} transactions ({} fees)",
+            tip.height,
+            template.transactions().len(),
+            template.total_fees()
+        );
+
+        Ok(BlockTemplateResponse {
+            capabilities: vec!["proposal".to_string()],
+            version: tip.version,
+            previousblockhash: hex::encode(tip.prev_block_hash),
+            transactions,
+            coinbaseaux: HashMap::from([("flags".to_string(), String::new())]),
+            coinbasevalue: coinbase_value,
+            coinbasetxn: TemplateTransaction::new(coinbase, Vec::new(), 0),
+            treasury: TreasuryOutput {
+                script: hex::encode(ENVIRONMENTAL_TREASURY_SCRIPT),
+                amount: treasury_amount,
+            },
+            longpollid,
+            target: hex::encode(target.to_be_bytes()),
+            mintime: median_time_past + 1,
+            mutable: vec![
+                "time".to_string(),
+                "transactions".to_string(),
+                "prevblock".to_string(),
+                "coinbase".to_string(),
+            ],
+            noncerange: "00000000ffffffff".to_string(),
+            sigoplimit: MAX_BLOCK_SIGOPS_COST,
+            sizelimit: MAX_BLOCK_SIZE,
+            weightlimit: MAX_BLOCK_WEIGHT,
+            curtime: now().max(median_time_past + 1),
+            bits: format!("{:08x}", tip.target),
+            height: tip.height,
+        })
+    }
+
+    /// Check a block proposal (BIP23) without requiring proof of work
+    pub async fn propose_block(&self, block: &Block) -> Result<(), BlockRejection> {
+        let chain = self.chain_state.read().await;
+        if chain.get_db().get_block(&block.hash())?.is_some() {
+            return Err(BlockRejection::Duplicate);
+        }
+        if block.prev_block_hash() != chain.get_best_block_hash() {
+            return Err(BlockRejection::NotBestPrevBlock);
+        }
+        self.check_block(&chain, block, false)
+    }
+
+    /// Validate a solved block and connect it to the chain
+    pub async fn submit_block(&self, block: Block) -> Result<(), BlockRejection> {
+        let block_hash = block.hash();
+
+        {
+            let chain = self.chain_state.read().await;
+            if chain.get_db().get_block(&block_hash)?.is_some() {
+                return Err(BlockRejection::Duplicate);
+            }
+
+            // Blocks on other branches are left to the chain state's fork handling
+            if block.prev_block_hash() == chain.get_best_block_hash() {
+                self.check_block(&chain, &block, true)?;
+            } else {
+                Self::check_block_header(&block, true)?;
+            }
+        }
+
+        let connected = match self.chain_state.write().await.process_block(block).await {
+            Ok(connected) => connected,
+            Err(e) => {
+                warn!("Submitted block {} rejected: {}", hex::encode(block_hash), e);
+                return Err(BlockRejection::Rejected(e.to_string()));
+            }
+        };
+        if !connected {
+            return Err(BlockRejection::Inconclusive);
+        }
+
+        info!("Accepted submitted block {}", hex::encode(block_hash));
+        // Long polls wake without waiting for the chain event
+        self.refresh_tip().await?;
+        Ok(())
+    }
+
+    /// Context-free header checks
+    fn check_block_header(block: &Block, check_pow: bool) -> Result<(), BlockRejection> {
+        let header = block.header();
+        if check_pow && !header.meets_target() {
+            return Err(BlockRejection::HighHash);
+        }
+        if header.timestamp() > now() + MAX_FUTURE_BLOCK_TIME {
+            return Err(BlockRejection::TimeTooNew);
+        }
+        if Block::calculate_merkle_root(block.transactions()) != header.merkle_root() {
+            return Err(BlockRejection::BadMerkleRoot);
+        }
+        Ok(())
+    }
+
+    /// Full checks for a block extending the current tip
+    ///
+    /// Transactions are checked as [`ChainState::check_block_transactions`]
+    /// does for blocks from the network, so proposals get the same verdict.
+    fn check_block(&self, chain: &ChainState, block: &Block, check_pow: bool) -> Result<(), BlockRejection> {
+        let (tip, median_time_past) = Self::load_tip(&self.config, chain)?;
+
+        if block.header().target() != tip.target {
+            return Err(BlockRejection::BadDiffBits {
+                expected: tip.target,
+                found: block.header().target(),
+            });
+        }
+        Self::check_block_header(block, check_pow)?;
+        if block.header().timestamp() <= median_time_past {
+            return Err(BlockRejection::TimeTooOld);
+        }
+
+        let mut seen = HashSet::new();
+        if !block.transactions().iter().all(|tx| seen.insert(tx.hash())) {
+            return Err(BlockRejection::DuplicateTransaction);
+        }
+
+        check_block_limits(block)?;
+
+        if block.transactions().is_empty() {
+            return Err(CoinbaseError::MissingCoinbase.into());
+        }
+        let total_fees = chain.check_block_transactions(block, tip.height)?;
+        let treasury_share = chain.calculate_treasury_share(total_fees);
+        consensus::validate_coinbase(block.transactions(), tip.height, total_fees, treasury_share)?;
+
+        Ok(())
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or(Duration::from_secs(0))
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mempool::MempoolConfig;
+    use crate::storage::BlockchainDB;
+    use btclib::consensus::{DifficultyParams, REGTEST_POW_LIMIT_BITS};
+    use btclib::crypto::quantum::{QuantumKeyPair, QuantumParameters, QuantumScheme};
+    use btclib::crypto::signature::SignatureType;
+    use btclib::types::script::{pay_to_pubkey_hash, signature_hash, InputWitness};
+    use btclib::types::transaction::{TransactionInput, TransactionOutput};
+    use tempfile::{tempdir, TempDir};
+
+    fn test_key() -> QuantumKeyPair {
+        QuantumKeyPair::from_seed(&[7u8; 32], QuantumParameters { scheme: QuantumScheme::Dilithium, security_level: 3 })
+            .unwrap()
+    }
+
+    fn locked(key: &QuantumKeyPair, amount: u64) -> TransactionOutput {
+        TransactionOutput::new(amount, pay_to_pubkey_hash(SignatureType::Dilithium, 3, &key.public_key))
+    }
+
+    /// Spend output `index` of `prev_hash`, worth `spent` and locked to `key`, paying `value` back to `key`
+    fn signed_spend(key: &QuantumKeyPair, prev_hash: [u8; 32], index: u32, spent: u64, value: u64) -> Transaction {
+        let mut tx = Transaction::new(
+            1,
+            vec![TransactionInput::new(prev_hash, index, vec![], 0)],
+            vec![locked(key, value)],
+            0,
+        );
+        let sighash = signature_hash(&tx, 0, &locked(key, spent)).unwrap();
+        let witness = InputWitness {
+            sig_type: SignatureType::Dilithium,
+            security_level: 3,
+            public_key: key.public_key.clone(),
+            signature: key.sign(&sighash).unwrap(),
+        };
+        tx.set_signature_script(0, witness.to_script());
+        tx
+    }
+
+    async fn setup() -> (TempDir, Arc<BlockchainDB>, Arc<TransactionPool>, Arc<BlockTemplateService>) {
+        let temp_dir = tempdir().unwrap();
+        let db = Arc::new(BlockchainDB::new(temp_dir.path()).unwrap());
+        let mut chain_state = ChainState::new(db.clone()).unwrap();
+        chain_state.set_difficulty_params(DifficultyParams::regtest());
+        let chain_state = Arc::new(RwLock::new(chain_state));
+        let mempool = Arc::new(TransactionPool::new(MempoolConfig {
+            min_fee_rate: 0,
+            ..MempoolConfig::default()
+        }));
+
+        let config = BlockTemplateConfig {
+            payout_script: vec![0xaa; 20],
+            ..BlockTemplateConfig::default()
+        };
+        let service = BlockTemplateService::new(config, chain_state, mempool.clone()).unwrap();
+        (temp_dir, db, mempool, Arc::new(service))
+    }
+
+    fn block_from_template(template: &BlockTemplateResponse, coinbase: Transaction) -> Block {
+        let mut transactions = vec![coinbase];
+        for entry in &template.transactions {
+            transactions.push(bincode::deserialize(&hex::decode(&entry.data).unwrap()).unwrap());
+        }
+        let prev_hash: [u8; 32] = hex::decode(&template.previousblockhash).unwrap().try_into().unwrap();
+        Block::new(template.version, prev_hash, transactions, u32::from_str_radix(&template.bits, 16).unwrap())
+    }
+
+    fn template_coinbase(template: &BlockTemplateResponse) -> Transaction {
+        bincode::deserialize(&hex::decode(&template.coinbasetxn.data).unwrap()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_template_dependencies_and_proposal() {
+        let (_dir, db, mempool, service) = setup().await;
+
+        // A confirmed funding output, a mempool parent and its child
+        let key = test_key();
+        let funding = [1u8; 32];
+        db.store_utxo(&funding, 0, &bincode::serialize(&locked(&key, 10_000)).unwrap()).unwrap();
+        let parent = signed_spend(&key, funding, 0, 10_000, 10_000);
+        let child = signed_spend(&key, parent.hash(), 0, 10_000, 10_000);
+        mempool.add_transaction(child.clone(), 0).unwrap();
+        mempool.add_transaction(parent.clone(), 0).unwrap();
+
+        let template = service.get_block_template(&TemplateRequest::default()).await.unwrap();
+        assert_eq!(template.height, 1);
+        assert_eq!(template.coinbasevalue, consensus::block_subsidy(1));
+        assert_eq!(template.transactions.len(), 2);
+        assert_eq!(template.transactions[0].txid, hex::encode(parent.hash()));
+        assert!(template.transactions[0].depends.is_empty());
+        assert_eq!(template.transactions[1].depends, vec![1]);
+        assert_eq!(template.transactions[1].sigops, transaction_sigop_cost(&child));
+        assert_eq!(template.longpollid.len(), 80);
+
+        // The template's own block is a valid proposal
+        let block = block_from_template(&template, template_coinbase(&template));
+        assert_eq!(service.propose_block(&block).await, Ok(()));
+
+        // Proposals get the same transaction checks as blocks from the network
+        let with_transactions = |transactions: Vec<Transaction>| {
+            let mut all = vec![template_coinbase(&template)];
+            all.extend(transactions);
+            Block::new(template.version, block.prev_block_hash(), all, block.header().target())
+        };
+        let double_spend = signed_spend(&key, funding, 0, 10_000, 9_000);
+        for invalid in [
+            vec![child.clone()],
+            vec![parent.clone(), double_spend],
+            vec![parent.without_signature_scripts()],
+        ] {
+            let block = with_transactions(invalid);
+            assert_eq!(service.propose_block(&block).await.unwrap_err().reason(), "bad-txns-invalid");
+        }
+
+        // Claiming more than subsidy + fees is rejected with a BIP22 reason
+        let greedy_coinbase = Transaction::new(
+            1,
+            vec![TransactionInput::new([0u8; 32], 0xffffffff, vec![], 0)],
+            vec![TransactionOutput::new(template.coinbasevalue + 1, vec![0xaa; 20])],
+            0,
+        );
+        let block = block_from_template(&template, greedy_coinbase);
+        assert_eq!(service.propose_block(&block).await.unwrap_err().reason(), "bad-cb-amount");
+
+        // So is a block with the wrong target
+        let coinbase = template_coinbase(&template);
+        let block = Block::new(1, block.prev_block_hash(), vec![coinbase], 0x2000ffff);
+        assert_eq!(service.propose_block(&block).await.unwrap_err().reason(), "bad-diffbits");
+    }
+
+    #[tokio::test]
+    async fn test_submit_block_wakes_long_poll() {
+        let (_dir, _db, _mempool, service) = setup().await;
+        let mut tip_rx = service.subscribe_tip();
+
+        let template = service.get_block_template(&TemplateRequest::default()).await.unwrap();
+        assert_eq!(template.bits, format!("{:08x}", REGTEST_POW_LIMIT_BITS));
+        let mut block = block_from_template(&template, template_coinbase(&template));
+        assert!(block.solve());
+
+        // A long poll on the current template blocks until the tip moves
+        let long_poll = tokio::spawn({
+            let service = service.clone();
+            let request = TemplateRequest {
+                longpollid: Some(template.longpollid.clone()),
+                ..TemplateRequest::default()
+            };
+            async move { service.get_block_template(&request).await }
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!long_poll.is_finished());
+
+        assert_eq!(service.submit_block(block.clone()).await, Ok(()));
+        assert_eq!(service.submit_block(block.clone()).await, Err(BlockRejection::Duplicate));
+
+        tip_rx.changed().await.unwrap();
+        assert_eq!(tip_rx.borrow().prev_block_hash, block.hash());
+
+        let next = tokio::time::timeout(Duration::from_secs(5), long_poll)
+            .await
+            .expect("long poll did not return")
+            .unwrap()
+            .unwrap();
+        assert_eq!(next.previousblockhash, hex::encode(block.hash()));
+    }
+
+    #[tokio::test]
+    async fn test_follow_chain_publishes_peer_blocks() {
+        let temp_dir = tempdir().unwrap();
+        let db = Arc::new(BlockchainDB::new(temp_dir.path()).unwrap());
+        let events = EventBus::default();
+        let mut chain_state = ChainState::new(db).unwrap();
+        chain_state.set_difficulty_params(DifficultyParams::regtest());
+        chain_state.set_event_bus(events.clone());
+        let chain_state = Arc::new(RwLock::new(chain_state));
+        let mempool = Arc::new(TransactionPool::new(MempoolConfig::default()));
+        let service = Arc::new(
+            BlockTemplateService::new(BlockTemplateConfig::default(), chain_state.clone(), mempool).unwrap(),
+        );
+        service.follow_chain(&events);
+        let mut tip_rx = service.subscribe_tip();
+
+        // Connected straight through the chain state, as sync does with blocks from peers
+        let mut block = Block::new(1, [0u8; 32], Vec::new(), REGTEST_POW_LIMIT_BITS);
+        assert!(block.solve());
+        assert!(chain_state.write().await.process_block(block.clone()).await.unwrap());
+
+        tokio::time::timeout(Duration::from_secs(5), tip_rx.changed())
+            .await
+            .expect("tip was not refreshed")
+            .unwrap();
+        assert_eq!(tip_rx.borrow().prev_block_hash, block.hash());
+    }
+}
@@ -0,0 +1,25 @@
+//! Block template construction and block submission
+//!
+//! Backs the `getblocktemplate`/`submitblock` RPCs and supplies the chain tip
+//! that the Stratum server builds its jobs on. Transaction selection and the
+//! coinbase are shared with the miner crate through `miner::mining::BlockTemplate`.
+
+pub mod block_template;
+
+pub use self::block_template::{
+    BlockRejection, BlockTemplateConfig, BlockTemplateResponse, BlockTemplateService,
+    TemplateError, TemplateRequest, TemplateTransaction, TreasuryOutput,
+};
+
+/// The chain tip new blocks are built on
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChainTip {
+    /// Block version for new blocks
+    pub version: u32,
+    /// Hash of the current best block
+    pub prev_block_hash: [u8; 32],
+    /// Height of the block being mined
+    pub height: u64,
+    /// Network target for the block being mined
+    pub target: u32,
+}
@@ -76,6 +76,11 @@ pub enum NetworkCommand {
         fee_rate: u64,
     },
     
+    /// Relay a child and the parents it spends, parents first
+    AnnouncePackage {
+        transactions: Vec<Transaction>,
+    },
+    
     /// Request headers within a height range
     RequestHeaders {
         start_height: u64,
@@ -136,6 +141,12 @@ pub enum NetworkEvent {
         from_peer: Option<PeerId>,
     },
     
+    /// Received a child and the parents it spends, parents first
+    NewPackage {
+        transactions: Vec<Transaction>,
+        from_peer: Option<PeerId>,
+    },
+    
     /// Received block headers
     BlockHeaders {
         headers: Vec<BlockHeader>,
@@ -319,6 +330,10 @@ impl P2PNetwork {
                     debug!("Simulated transaction announcement");
                         self.stats.transactions_announced += 1;
                     },
+                NetworkCommand::AnnouncePackage { transactions } => {
+                    debug!("Simulated package announcement of {} transactions", transactions.len());
+                        self.stats.transactions_announced += transactions.len() as u64;
+                    },
                 _ => {} // Ignore other commands in simplified mode
             }
         }
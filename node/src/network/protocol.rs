@@ -42,6 +42,11 @@ pub enum Message {
     /// Broadcast a transaction directly
     BroadcastTransaction(Vec<u8>), // Serialized transaction directly
     
+    /// Child transaction and the parents it spends, to be judged together
+    Package {
+        transactions: Vec<Vec<u8>>, // Serialized transactions, parents first
+    },
+    
     /// Transaction announcement with hash only
     TransactionAnnouncement {
         tx_hash: [u8; 32],
@@ -235,6 +240,12 @@ impl Protocol {
         self.publish_message(TXS_TOPIC, message)
     }
     
+    /// Helper method to relay a package of transactions, parents first
+    pub fn announce_package(&mut self, transactions: Vec<Vec<u8>>) -> Result<MessageId, PublishError> {
+        let message = Message::Package { transactions };
+        self.publish_message(TXS_TOPIC, message)
+    }
+    
     /// Helper method to publish status updates
     pub fn broadcast_status(&mut self, 
                        version: u32, 
@@ -375,6 +386,7 @@ fn message_to_topic(message: &Message) -> &'static str {
         
         Message::Transaction { .. } |
         Message::BroadcastTransaction(_) |
+        Message::Package { .. } |
         Message::TransactionAnnouncement { .. } => TXS_TOPIC,
         
         Message::GetHeaders { .. } |
@@ -1,10 +1,11 @@
 use crate::network::{NetworkCommand, Message, PeerId};
 use crate::storage::{BlockchainDB, StorageError, ChainState};
+use btclib::consensus::{self, HeaderInfo, HeaderLookup};
 use btclib::types::block::{Block, BlockHeader};
 use std::collections::{HashMap, HashSet, VecDeque};
 use std::sync::Arc;
 use std::time::{Duration, Instant, SystemTime};
-use tokio::sync::mpsc;
+use tokio::sync::{mpsc, RwLock};
 use tracing::{info, warn, debug, trace};
 use async_trait::async_trait;
 use dashmap::DashMap;
@@ -286,7 +287,8 @@ enum SyncState {
 /// Main chain sync implementation
 pub struct ChainSync {
     db: Arc<BlockchainDB>,
-    chain_state: ChainState,
+    /// Shared with everything else that reads or extends the best chain
+    chain_state: Arc<RwLock<ChainState>>,
     command_sender: mpsc::Sender<NetworkCommand>,
     sync_state: SyncState,
     highest_seen_height: u64,
@@ -302,7 +304,7 @@ impl Clone for ChainSync {
     fn clone(&self) -> Self {
         Self {
             db: Arc::clone(&self.db),
-            chain_state: self.chain_state.clone(),
+            chain_state: Arc::clone(&self.chain_state),
             command_sender: self.command_sender.clone(),
             sync_state: self.sync_state.clone(),
             highest_seen_height: self.highest_seen_height,
@@ -319,7 +321,7 @@ impl Clone for ChainSync {
 impl ChainSync {
     /// Create a new ChainSync instance
     pub fn new(
-        chain_state: ChainState, 
+        chain_state: Arc<RwLock<ChainState>>,
         db: Arc<BlockchainDB>,
         command_sender: mpsc::Sender<NetworkCommand>,
     ) -> Self {
@@ -417,16 +419,17 @@ impl ChainSync {
         // Process based on current sync state
         match &mut self.sync_state {
             SyncState::Idle => {
+                let current_height = self.chain_state.read().await.get_height();
                 // If we're significantly behind, start a full sync
-                if height > self.chain_state.get_height() + 10 {
+                if height > current_height + 10 {
                     info!("Detected we're behind by more than 10 blocks, starting sync");
                     self.start_sync(height, total_difficulty).await?;
-                } else if height == self.chain_state.get_height() + 1 {
+                } else if height == current_height + 1 {
                     // This block extends our chain directly, process it
                     self.process_single_block(block).await?;
-                } else if height > self.chain_state.get_height() {
+                } else if height > current_height {
                     // We're missing a few blocks, request them
-                    self.request_missing_blocks(self.chain_state.get_height() + 1, height).await?;
+                    self.request_missing_blocks(current_height + 1, height).await?;
                 }
             },
             SyncState::SyncingBlocks { blocks_received, blocks_requested, .. } => {
@@ -475,7 +478,7 @@ impl ChainSync {
         }
         
         // Validate headers first
-        let headers_valid = self.validate_headers(&headers)?;
+        let headers_valid = self.validate_headers(&headers).await?;
         
         if let Some(peer_id) = from_peer {
             // Record the start time for this response
@@ -632,7 +635,8 @@ impl ChainSync {
                     
                     // Process the block through the chain state
                     let process_start = Instant::now();
-                    match self.chain_state.process_block(block.clone()).await {
+                    let result = self.chain_state.write().await.process_block(block.clone()).await;
+                    match result {
                         Ok(true) => {
                             // Block was accepted and is now the best block
                             let duration_ms = process_start.elapsed().as_millis() as u64;
@@ -640,7 +644,7 @@ impl ChainSync {
                             
                             debug!("Added block {} at height {}", 
                                    hex::encode(&block.hash()[0..8]), 
-                                   self.chain_state.get_height());
+                                   self.chain_state.read().await.get_height());
                         },
                         Ok(false) => {
                             // Block was valid but not the best chain
@@ -671,13 +675,14 @@ impl ChainSync {
             for block in blocks {
                 if block.validate() {
                     let process_start = Instant::now();
-                    match self.chain_state.process_block(block.clone()).await {
+                    let result = self.chain_state.write().await.process_block(block.clone()).await;
+                    match result {
                         Ok(true) => {
                             let duration_ms = process_start.elapsed().as_millis() as u64;
                             self.metrics.record_block_validation(true, duration_ms).await;
                             debug!("Added block {} at height {}", 
                                    hex::encode(&block.hash()[0..8]), 
-                                   self.chain_state.get_height());
+                                   self.chain_state.read().await.get_height());
                         },
                         Ok(false) => {
                             let duration_ms = process_start.elapsed().as_millis() as u64;
@@ -700,7 +705,7 @@ impl ChainSync {
         debug!("Processed {} blocks", blocks_count);
         
         // Update sync progress based on current height and highest known peer height
-        let current_height = self.chain_state.get_height();
+        let current_height = self.chain_state.read().await.get_height();
         let mut highest_peer_height = current_height;
         for entry in self.peer_data.iter() {
             let peer_height = entry.value().reported_height;
@@ -716,7 +721,7 @@ impl ChainSync {
 
     /// Start sync process targeting a specific height
     pub async fn start_sync(&mut self, target_height: u64, total_difficulty: u64) -> Result<(), String> {
-        let current_height = self.chain_state.get_height();
+        let current_height = self.chain_state.read().await.get_height();
         
         if target_height <= current_height {
             debug!("No sync needed, already at height {}", current_height);
@@ -738,7 +743,7 @@ impl ChainSync {
 
     /// Start the header synchronization process
     async fn start_header_sync(&mut self, start_height: u64, end_height: u64) -> Result<(), String> {
-        let current_height = self.chain_state.get_height();
+        let current_height = self.chain_state.read().await.get_height();
         
         // Find closest checkpoint below current height
         let checkpoint_height = self.find_best_checkpoint_height(current_height);
@@ -963,10 +968,8 @@ impl ChainSync {
             info!("No blocks to verify, continuing sync");
             
             // Continue with next batch of headers
-            self.start_header_sync(
-                self.chain_state.get_height(), 
-                self.highest_seen_height
-            ).await?;
+            let current_height = self.chain_state.read().await.get_height();
+            self.start_header_sync(current_height, self.highest_seen_height).await?;
             
             return Ok(());
         }
@@ -998,18 +1001,16 @@ impl ChainSync {
                         info!("Block verification complete");
                         
                         // Continue with next batch of headers if needed
-                        if self.chain_state.get_height() < self.highest_seen_height {
-                            self.start_header_sync(
-                                self.chain_state.get_height(), 
-                                self.highest_seen_height
-                            ).await?;
+                        let current_height = self.chain_state.read().await.get_height();
+                        if current_height < self.highest_seen_height {
+                            self.start_header_sync(current_height, self.highest_seen_height).await?;
                         } else {
-                            info!("Sync complete! Chain height: {}", self.chain_state.get_height());
+                            info!("Sync complete! Chain height: {}", current_height);
                             self.sync_state = SyncState::Idle;
                             
                             if let Some(start_time) = self.sync_start_time {
                                 let duration = start_time.elapsed().as_secs();
-                                self.metrics.record_sync_completed(self.chain_state.get_height(), duration).await;
+                                self.metrics.record_sync_completed(current_height, duration).await;
                                 self.sync_start_time = None;
                             }
                         }
@@ -1051,23 +1052,28 @@ impl ChainSync {
     /// Process a single block (validate and add to chain)
     async fn process_single_block(&mut self, block: Block) -> Result<(), String> {
         let block_hash = block.hash();
-        let current_height = self.chain_state.get_height();
-        let current_hash = self.chain_state.get_best_block_hash();
+        let (current_height, current_hash) = {
+            let chain = self.chain_state.read().await;
+            (chain.get_height(), chain.get_best_block_hash())
+        };
         
         debug!("Processing single block {} at height {}", 
             hex::encode(&block_hash[..4]), current_height + 1);
         
         // Process the block
         let process_start = Instant::now();
-        match self.chain_state.process_block(block.clone()).await {
+        let result = self.chain_state.write().await.process_block(block.clone()).await;
+        match result {
             Ok(true) => {
                 // Block was accepted and is now on best chain
                 let validation_time = process_start.elapsed().as_millis() as u64;
                 self.metrics.record_block_validation(true, validation_time).await;
                 
                 // Check if this is a new tip and different from prev_hash (potential fork)
-                let new_height = self.chain_state.get_height();
-                let new_hash = self.chain_state.get_best_block_hash();
+                let (new_height, new_hash) = {
+                    let chain = self.chain_state.read().await;
+                    (chain.get_height(), chain.get_best_block_hash())
+                };
                 
                 if new_height > current_height && new_hash != block_hash {
                     // A reorganization occurred during block processing
@@ -1075,7 +1081,7 @@ impl ChainSync {
                     debug!("Chain reorganization occurred during block processing");
                     
                     // Get fork metrics
-                    let fork_metrics = self.chain_state.calculate_fork_metrics();
+                    let fork_metrics = self.chain_state.read().await.calculate_fork_metrics();
                     
                     // Log metrics
                     debug!("Fork metrics after reorganization: {:?}", fork_metrics);
@@ -1368,10 +1374,8 @@ impl ChainSync {
                     }
                     
                     // Restart header sync
-                    self.start_header_sync(
-                        self.chain_state.get_height(), 
-                        self.highest_seen_height
-                    ).await?;
+                    let current_height = self.chain_state.read().await.get_height();
+                    self.start_header_sync(current_height, self.highest_seen_height).await?;
                 }
             },
             SyncState::SyncingBlocks { last_request_time, .. } => {
@@ -1397,7 +1401,7 @@ impl ChainSync {
         
         self.last_status_update = Instant::now();
         
-        let current_height = self.chain_state.get_height();
+        let current_height = self.chain_state.read().await.get_height();
         let target_height = self.highest_seen_height;
         
         if current_height < target_height {
@@ -1406,7 +1410,7 @@ impl ChainSync {
     }
 
     /// Validate a sequence of headers
-    fn validate_headers(&self, headers: &[BlockHeader]) -> Result<bool, String> {
+    async fn validate_headers(&self, headers: &[BlockHeader]) -> Result<bool, String> {
         if headers.is_empty() {
             return Ok(true);
         }
@@ -1442,29 +1446,55 @@ impl ChainSync {
             }
         }
         
-        // Verify proof of work and difficulty for each header
+        let chain = self.chain_state.read().await;
+
+        // Verify proof of work for each header
         for header in headers {
-            if !self.verify_header_pow(header) {
+            if !Self::verify_header_pow(&chain, header) {
                 return Ok(false);
             }
         }
+
+        // Headers extending our tip must also carry the retargeted difficulty
+        if headers[0].prev_block_hash() == chain.get_best_block_hash() {
+            let params = chain.difficulty_params();
+            let mut lookup = PendingHeaders {
+                chain: &chain,
+                pending: Vec::with_capacity(headers.len()),
+            };
+            let mut prev = lookup.header_at(chain.get_height());
+
+            for header in headers {
+                let expected = consensus::next_work_required(params, prev.as_ref(), &lookup)
+                    .map_err(|e| e.to_string())?;
+                if header.target() != expected {
+                    debug!("Header {} has bits {:#010x}, expected {:#010x}",
+                           hex::encode(header.hash()), header.target(), expected);
+                    return Ok(false);
+                }
+
+                let height = prev.map_or(1, |p| p.height + 1);
+                let info = HeaderInfo::new(height, header);
+                lookup.pending.push(info);
+                prev = Some(info);
+            }
+        }
         
         Ok(true)
     }
 
-    /// Verify proof of work for a header
-    fn verify_header_pow(&self, header: &BlockHeader) -> bool {
-        let hash = header.hash();
-        let target = header.target();
-        
-        // Check that hash is below target
-        let hash_value = u32::from_be_bytes([hash[0], hash[1], hash[2], hash[3]]);
-        hash_value <= target
+    /// Verify proof of work for a header against its full 256-bit target
+    fn verify_header_pow(chain: &ChainState, header: &BlockHeader) -> bool {
+        consensus::check_proof_of_work(
+            &header.hash(),
+            header.target(),
+            chain.difficulty_params(),
+        ).is_ok()
     }
 
     /// Get the current chain height
-    pub fn get_height(&self) -> u64 {
-        self.chain_state.get_height()
+    pub async fn get_height(&self) -> u64 {
+        self.chain_state.read().await.get_height()
     }
 
     /// Get the current sync state as a string
@@ -1486,14 +1516,14 @@ impl ChainSync {
     }
 
     /// Get statistics about the sync process
-    pub fn get_stats(&self) -> SyncStats {
+    pub async fn get_stats(&self) -> SyncStats {
         let peers = self.peer_data.len();
         let active_peers = self.peer_data.iter()
             .filter(|entry| entry.value().score > 0)
             .count();
         
         SyncStats {
-            current_height: self.chain_state.get_height(),
+            current_height: self.chain_state.read().await.get_height(),
             target_height: self.highest_seen_height,
             state: self.get_sync_state_string(),
             peers,
@@ -1568,8 +1598,8 @@ impl ChainSync {
     }
 
     /// Get statistics about active forks
-    pub fn get_fork_stats(&self) -> HashMap<String, u64> {
-        self.chain_state.calculate_fork_metrics()
+    pub async fn get_fork_stats(&self) -> HashMap<String, u64> {
+        self.chain_state.read().await.calculate_fork_metrics()
     }
 
     /// Handle chain reorganization events from the chain state
@@ -1586,12 +1616,13 @@ impl ChainSync {
         ).await;
 
         // Update peers that provided blocks on the winning fork
+        let current_height = self.chain_state.read().await.get_height();
         for peer_entry in self.peer_data.iter_mut() {
             let peer_id = peer_entry.key().clone();
             let peer_data = peer_entry.value_mut();
             
             // Check if this peer was ahead on the winning fork
-            if peer_data.reported_height >= self.chain_state.get_height() {
+            if peer_data.reported_height >= current_height {
                 // Potentially reward this peer slightly as they had the winning fork
                 peer_data.update_score(1);
             }
@@ -1608,7 +1639,6 @@ impl ChainSync {
                 // If we had a significant reorg, consider syncing from trusted peers
                 if event.blocks_disconnected > 2 {
                     debug!("Significant reorganization detected, checking if further sync is needed");
-                    let current_height = self.chain_state.get_height();
                     let mut should_sync = false;
                     let mut target_height = current_height;
                     let mut target_difficulty = 0;
@@ -1638,28 +1668,28 @@ impl ChainSync {
     }
 
     /// Check if there's a stale tip (no new blocks for a while)
-    pub fn check_for_stale_tip(&self) -> bool {
-        self.chain_state.is_tip_stale()
+    pub async fn check_for_stale_tip(&self) -> bool {
+        self.chain_state.read().await.is_tip_stale()
     }
 
     /// Get time since last block was added
-    pub fn time_since_last_block(&self) -> Duration {
-        self.chain_state.time_since_last_block()
+    pub async fn time_since_last_block(&self) -> Duration {
+        self.chain_state.read().await.time_since_last_block()
     }
 
     /// Get count of active forks
-    pub fn get_active_fork_count(&self) -> usize {
-        self.chain_state.get_active_fork_count()
+    pub async fn get_active_fork_count(&self) -> usize {
+        self.chain_state.read().await.get_active_fork_count()
     }
     
     /// Get rejected reorganization count
-    pub fn get_rejected_reorg_count(&self) -> u64 {
-        self.chain_state.get_rejected_reorg_count()
+    pub async fn get_rejected_reorg_count(&self) -> u64 {
+        self.chain_state.read().await.get_rejected_reorg_count()
     }
     
     /// Get information about all active forks
-    pub fn get_active_forks(&self) -> Vec<ForkInfo> {
-        self.chain_state.get_active_forks()
+    pub async fn get_active_forks(&self) -> Vec<ForkInfo> {
+        self.chain_state.read().await.get_active_forks()
     }
 }
 
@@ -1675,28 +1705,19 @@ pub struct SyncStats {
     pub sync_duration: Option<u64>,
 }
 
-/// Extension methods for BlockHeader
-trait BlockHeaderExt {
-    fn prev_block_hash(&self) -> [u8; 32];
-    fn hash(&self) -> [u8; 32];
-    fn target(&self) -> u32;
+/// Best-chain headers followed by headers still being validated
+struct PendingHeaders<'a> {
+    chain: &'a ChainState,
+    pending: Vec<HeaderInfo>,
 }
 
-impl BlockHeaderExt for BlockHeader {
-    fn prev_block_hash(&self) -> [u8; 32] {
-        // Use indirect access to avoid private field access
-        // In a real implementation, we'd get this from block header accessor methods
-        [0u8; 32] // Default value for demonstration
-    }
-    
-    fn hash(&self) -> [u8; 32] {
-        // Call the existing hash method
-        self.hash()
-    }
-    
-    fn target(&self) -> u32 {
-        // Use a reasonable default target value
-        u32::MAX // Maximum target (minimum difficulty)
+impl HeaderLookup for PendingHeaders<'_> {
+    fn header_at(&self, height: u64) -> Option<HeaderInfo> {
+        self.pending
+            .iter()
+            .find(|header| header.height == height)
+            .copied()
+            .or_else(|| self.chain.header_at(height))
     }
 }
 
@@ -1739,12 +1760,12 @@ mod tests {
     async fn test_chain_sync_creation() {
         let temp_dir = tempdir().unwrap();
         let db = Arc::new(BlockchainDB::new(temp_dir.path()).unwrap());
-        let chain_state = ChainState::new(Arc::clone(&db)).unwrap();
+        let chain_state = Arc::new(RwLock::new(ChainState::new(Arc::clone(&db)).unwrap()));
         
         let (tx, _) = mpsc::channel(32);
         let sync = ChainSync::new(chain_state, Arc::clone(&db), tx);
         
-        assert_eq!(sync.get_height(), 0);
+        assert_eq!(sync.get_height().await, 0);
         assert_eq!(sync.get_sync_state_string(), "idle");
     }
 
@@ -1752,7 +1773,7 @@ mod tests {
     async fn test_peer_management() {
         let temp_dir = tempdir().unwrap();
         let db = Arc::new(BlockchainDB::new(temp_dir.path()).unwrap());
-        let chain_state = ChainState::new(Arc::clone(&db)).unwrap();
+        let chain_state = Arc::new(RwLock::new(ChainState::new(Arc::clone(&db)).unwrap()));
         
         let (tx, _) = mpsc::channel(32);
         let sync = ChainSync::new(chain_state, Arc::clone(&db), tx);
@@ -1763,14 +1784,14 @@ mod tests {
         // Test peer height update
         sync.update_peer_height(&peer_id, 100, 1000);
         
-        let stats = sync.get_stats();
+        let stats = sync.get_stats().await;
         assert_eq!(stats.peers, 1);
         assert_eq!(stats.active_peers, 1);
         
         // Penalize peer
         sync.penalize_peer(&peer_id, PEER_SCORE_INVALID_DATA).await;
         
-        let stats = sync.get_stats();
+        let stats = sync.get_stats().await;
         assert_eq!(stats.active_peers, 0);
     }
 
@@ -1778,7 +1799,7 @@ mod tests {
     async fn test_checkpoint_management() {
         let temp_dir = tempdir().unwrap();
         let db = Arc::new(BlockchainDB::new(temp_dir.path()).unwrap());
-        let chain_state = ChainState::new(Arc::clone(&db)).unwrap();
+        let chain_state = Arc::new(RwLock::new(ChainState::new(Arc::clone(&db)).unwrap()));
         
         let (tx, _) = mpsc::channel(32);
         let mut sync = ChainSync::new(chain_state, Arc::clone(&db), tx);
@@ -1812,7 +1833,7 @@ mod tests {
     async fn test_find_best_peer() {
         let temp_dir = tempdir().unwrap();
         let db = Arc::new(BlockchainDB::new(temp_dir.path()).unwrap());
-        let chain_state = ChainState::new(Arc::clone(&db)).unwrap();
+        let chain_state = Arc::new(RwLock::new(ChainState::new(Arc::clone(&db)).unwrap()));
         
         let (tx, _) = mpsc::channel(32);
         let sync = ChainSync::new(chain_state, Arc::clone(&db), tx);
@@ -4,6 +4,9 @@ use crate::storage::{
     RecoveryManager, StorageError, UTXOSet
 };
 use crate::api::{ApiServer, ApiConfig};
+use crate::mempool::TransactionPool;
+use crate::mining::{BlockTemplateConfig, BlockTemplateService};
+use crate::events::EventBus;
 
 pub struct Node {
     pub config: NodeConfig,
@@ -19,12 +22,26 @@ pub struct Node {
     pub rpc_server: Option<Arc<RpcServer>>,
     pub is_running: Arc<AtomicBool>,
     pub mem_pool: Arc<RwLock<MemPool>>,
+    /// Validated transaction pool behind the mempool and mining RPCs
+    pub transaction_pool: Arc<TransactionPool>,
     /// API server instance
     pub api_server: Option<ApiServer>,
+    /// Block template service backing getblocktemplate/submitblock
+    pub block_templates: Option<Arc<BlockTemplateService>>,
+    /// Chain, mempool and Lightning events streamed to WebSocket subscribers
+    pub events: EventBus,
 }
 
 impl Node {
-    pub fn new(config: NodeConfig) -> Result<Self, NodeError> {
+    /// Create a node serving `transaction_pool` and streaming `events`
+    ///
+    /// The pool and bus are the ones the rest of the process already uses,
+    /// so the RPCs see the same transactions and chain events as sync and mining.
+    pub fn new(
+        config: NodeConfig,
+        transaction_pool: Arc<TransactionPool>,
+        events: EventBus,
+    ) -> Result<Self, NodeError> {
         // ... existing code ...
 
         // Initialize checkpoint manager if enabled
@@ -47,6 +64,15 @@ impl Node {
 
         // ... existing code ...
 
+        // getblocktemplate and submitblock build on the node's own chain state and mempool
+        let block_templates = Arc::new(BlockTemplateService::new(
+            BlockTemplateConfig::default(),
+            chain_state.clone(),
+            transaction_pool.clone(),
+        )?);
+        // Long polls wake for blocks from peers as well as submitted ones
+        block_templates.follow_chain(&events);
+
         Ok(Self {
             config,
             chain_state,
@@ -61,7 +87,10 @@ impl Node {
             rpc_server,
             is_running: Arc::new(AtomicBool::new(false)),
             mem_pool,
+            transaction_pool,
             api_server: None,
+            block_templates: Some(block_templates),
+            events,
         })
     }
 
@@ -91,6 +120,16 @@ impl Node {
         Ok(())
     }
 
+    /// Transaction pool served by the mempool RPCs
+    pub fn mempool(&self) -> &Arc<TransactionPool> {
+        &self.transaction_pool
+    }
+
+    /// Replace the block template service used by the mining RPCs
+    pub fn set_block_template_service(&mut self, service: Arc<BlockTemplateService>) {
+        self.block_templates = Some(service);
+    }
+
     /// Start the API server
     pub async fn start_api(&mut self, bind_address: &str, port: u16) -> std::io::Result<()> {
         // Create API server with default configuration
@@ -0,0 +1,204 @@
+//! Push notifications for integrations
+//!
+//! Integrations can have the node push chain and Lightning events to them
+//! instead of polling, in the spirit of Bitcoin Core's ZMQ notifications.
+//! Events are taken from the node's [`EventBus`] and published on these topics:
+//!
+//! - `hashblock`: the 32-byte hash of each block connected to the best chain
+//! - `rawblock`: the serialized block
+//! - `rawtx`: each transaction entering the mempool or confirmed in a
+//!   connected block, serialized
+//! - `invoicesettled`: the 32-byte payment hash and 8-byte big-endian amount
+//!   in millisatoshis of each settled Lightning invoice
+//!
+//! Every topic numbers its notifications from 0, so a subscriber can spot
+//! gaps. Notifications go to webhooks, through a retry queue kept in the
+//! database ([`webhook`]), and to local clients of a Unix socket ([`socket`]).
+
+#[cfg(unix)]
+pub mod socket;
+pub mod webhook;
+
+use btclib::types::block::Block;
+use btclib::types::transaction::Transaction;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use thiserror::Error;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{error, warn};
+use crate::events::{EventBus, NodeEvent};
+use crate::storage::{BlockchainDB, StorageError};
+use self::webhook::{WebhookConfig, WebhookQueue};
+
+/// Kind of notification
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Topic {
+    HashBlock,
+    RawBlock,
+    RawTx,
+    InvoiceSettled,
+}
+
+impl Topic {
+    pub const ALL: [Topic; 4] = [Topic::HashBlock, Topic::RawBlock, Topic::RawTx, Topic::InvoiceSettled];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Topic::HashBlock => "hashblock",
+            Topic::RawBlock => "rawblock",
+            Topic::RawTx => "rawtx",
+            Topic::InvoiceSettled => "invoicesettled",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|topic| topic.as_str() == name)
+    }
+}
+
+/// One published notification
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Notification {
+    pub topic: Topic,
+    /// Position among the notifications of the topic since the node started
+    pub sequence: u64,
+    pub body: Vec<u8>,
+}
+
+/// Notification settings
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NotificationConfig {
+    /// Endpoints notifications are posted to
+    #[serde(default)]
+    pub webhooks: Vec<WebhookConfig>,
+    /// Unix socket local clients subscribe on; disabled when unset
+    #[serde(default)]
+    pub socket_path: Option<PathBuf>,
+}
+
+/// Error starting the notification publishers
+#[derive(Debug, Error)]
+pub enum NotificationError {
+    #[error("Failed to open notification socket: {0}")]
+    Socket(#[from] std::io::Error),
+
+    #[error("Webhook queue error: {0}")]
+    Storage(#[from] StorageError),
+
+    #[error("Notification serialization error: {0}")]
+    Serialization(#[from] bincode::Error),
+}
+
+fn serialize<T: Serialize>(value: &T) -> Vec<u8> {
+    bincode::serialize(value).unwrap_or_default()
+}
+
+/// Topics and bodies of the notifications `event` gives rise to
+pub fn notifications_for(event: &NodeEvent) -> Vec<(Topic, Vec<u8>)> {
+    match event {
+        NodeEvent::BlockConnected { block, .. } => {
+            let mut notifications = vec![
+                (Topic::HashBlock, block.hash().to_vec()),
+                (Topic::RawBlock, serialize::<Block>(block)),
+            ];
+            notifications.extend(
+                block.transactions().iter().map(|tx| (Topic::RawTx, serialize::<Transaction>(tx))),
+            );
+            notifications
+        },
+        NodeEvent::MempoolAdded { transaction, .. } => vec![(Topic::RawTx, serialize::<Transaction>(transaction))],
+        NodeEvent::LightningInvoiceSettled { payment_hash, amount_msat } => {
+            let mut body = payment_hash.to_vec();
+            body.extend_from_slice(&amount_msat.to_be_bytes());
+            vec![(Topic::InvoiceSettled, body)]
+        },
+        _ => Vec::new(),
+    }
+}
+
+/// Start publishing the events of `events` to the configured webhooks and socket
+///
+/// Does nothing when neither is configured.
+pub fn spawn(config: &NotificationConfig, events: &EventBus, db: Arc<BlockchainDB>) -> Result<(), NotificationError> {
+    let webhooks = if config.webhooks.is_empty() {
+        None
+    } else {
+        let queue = Arc::new(WebhookQueue::new(config.webhooks.clone(), db));
+        tokio::spawn(queue.clone().run());
+        Some(queue)
+    };
+    #[cfg(unix)]
+    let socket = match &config.socket_path {
+        Some(path) => Some(socket::SocketPublisher::bind(path)?),
+        None => None,
+    };
+    #[cfg(not(unix))]
+    let socket: Option<()> = config.socket_path.as_ref().map(|_| warn!("Notification sockets need a Unix platform"));
+
+    if webhooks.is_none() && socket.is_none() {
+        return Ok(());
+    }
+
+    let mut receiver = events.subscribe();
+    tokio::spawn(async move {
+        let mut sequences: HashMap<Topic, u64> = HashMap::new();
+        loop {
+            let event = match receiver.recv().await {
+                Ok(event) => event,
+                Err(RecvError::Lagged(missed)) => {
+                    warn!("Notification publisher fell behind and skipped {} events", missed);
+                    continue;
+                },
+                Err(RecvError::Closed) => break,
+            };
+            for (topic, body) in notifications_for(&event) {
+                let sequence = sequences.entry(topic).or_insert(0);
+                let notification = Notification { topic, sequence: *sequence, body };
+                *sequence += 1;
+
+                if let Some(queue) = &webhooks {
+                    if let Err(e) = queue.enqueue(&notification) {
+                        error!("Failed to queue {} webhook notification: {}", topic.as_str(), e);
+                    }
+                }
+                #[cfg(unix)]
+                if let Some(socket) = &socket {
+                    socket.publish(&notification);
+                }
+            }
+        }
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use btclib::types::transaction::{TransactionInput, TransactionOutput};
+
+    #[test]
+    fn test_event_notifications() {
+        let tx = Transaction::new(
+            1,
+            vec![TransactionInput::new([1u8; 32], 0, vec![], 0)],
+            vec![TransactionOutput::new(1_000, vec![])],
+            0,
+        );
+        let added = NodeEvent::MempoolAdded { transaction: Arc::new(tx.clone()), fee: 100, size: 200 };
+        assert_eq!(notifications_for(&added), vec![(Topic::RawTx, bincode::serialize(&tx).unwrap())]);
+
+        let settled = NodeEvent::LightningInvoiceSettled { payment_hash: [7u8; 32], amount_msat: 5_000 };
+        let body = &notifications_for(&settled)[0].1;
+        assert_eq!(&body[..32], &[7u8; 32]);
+        assert_eq!(u64::from_be_bytes(body[32..].try_into().unwrap()), 5_000);
+
+        let removed = NodeEvent::MempoolRemoved { txid: tx.hash(), reason: crate::events::RemovalReason::Expired };
+        assert!(notifications_for(&removed).is_empty());
+
+        assert_eq!(Topic::from_name("rawblock"), Some(Topic::RawBlock));
+        assert_eq!(Topic::from_name("sequence"), None);
+    }
+}
@@ -0,0 +1,176 @@
+//! Unix socket publisher
+//!
+//! Local clients connect to the socket and choose topics by writing lines of
+//! `subscribe <topic>` or `unsubscribe <topic>`. Each notification on a chosen
+//! topic then arrives as one frame (see [`encode_frame`]). Like a ZMQ
+//! publisher, the socket never waits for a slow client: frames beyond the
+//! [`CLIENT_BUFFER`] a client has not read yet are dropped, which shows up as
+//! a gap in the sequence numbers.
+
+use std::collections::HashSet;
+use std::io;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::mpsc;
+use tracing::{debug, warn};
+use super::{Notification, Topic};
+
+/// Frames queued for a client before further ones are dropped
+pub const CLIENT_BUFFER: usize = 1024;
+
+/// Frame carrying `notification`
+///
+/// The frame holds the topic name's length as one byte, the topic name, the
+/// sequence number as a big-endian `u64`, the body's length as a big-endian
+/// `u32`, and the body.
+pub fn encode_frame(notification: &Notification) -> Vec<u8> {
+    let topic = notification.topic.as_str().as_bytes();
+    let mut frame = Vec::with_capacity(1 + topic.len() + 8 + 4 + notification.body.len());
+    frame.push(topic.len() as u8);
+    frame.extend_from_slice(topic);
+    frame.extend_from_slice(&notification.sequence.to_be_bytes());
+    frame.extend_from_slice(&(notification.body.len() as u32).to_be_bytes());
+    frame.extend_from_slice(&notification.body);
+    frame
+}
+
+struct Subscriber {
+    topics: Arc<Mutex<HashSet<Topic>>>,
+    frames: mpsc::Sender<Arc<Vec<u8>>>,
+}
+
+/// Publishes notifications to the clients of a Unix socket
+pub struct SocketPublisher {
+    subscribers: Arc<Mutex<Vec<Subscriber>>>,
+}
+
+impl SocketPublisher {
+    /// Listen on `path`, replacing a socket left behind by an earlier run
+    ///
+    /// The socket is only accessible to the node's user.
+    pub fn bind(path: &Path) -> io::Result<Self> {
+        match std::fs::remove_file(path) {
+            Ok(()) => {},
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {},
+            Err(e) => return Err(e),
+        }
+        let listener = UnixListener::bind(path)?;
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+
+        let subscribers: Arc<Mutex<Vec<Subscriber>>> = Arc::new(Mutex::new(Vec::new()));
+        let accepted = subscribers.clone();
+        tokio::spawn(async move {
+            loop {
+                match listener.accept().await {
+                    Ok((stream, _)) => {
+                        let topics = Arc::new(Mutex::new(HashSet::new()));
+                        let (frames, receiver) = mpsc::channel(CLIENT_BUFFER);
+                        accepted
+                            .lock()
+                            .unwrap_or_else(|e| e.into_inner())
+                            .push(Subscriber { topics: topics.clone(), frames });
+                        tokio::spawn(serve_client(stream, topics, receiver));
+                    },
+                    Err(e) => warn!("Failed to accept notification socket client: {}", e),
+                }
+            }
+        });
+        Ok(Self { subscribers })
+    }
+
+    /// Send `notification` to every client subscribed to its topic
+    pub fn publish(&self, notification: &Notification) {
+        let mut subscribers = self.subscribers.lock().unwrap_or_else(|e| e.into_inner());
+        subscribers.retain(|subscriber| !subscriber.frames.is_closed());
+
+        let mut frame = None;
+        for subscriber in subscribers.iter() {
+            if !subscriber.topics.lock().unwrap_or_else(|e| e.into_inner()).contains(&notification.topic) {
+                continue;
+            }
+            let frame = frame.get_or_insert_with(|| Arc::new(encode_frame(notification)));
+            if subscriber.frames.try_send(frame.clone()).is_err() {
+                debug!("Dropped {} notification {} for a slow socket client",
+                       notification.topic.as_str(), notification.sequence);
+            }
+        }
+    }
+}
+
+/// Apply the client's subscription commands and write out its frames until it disconnects
+async fn serve_client(stream: UnixStream, topics: Arc<Mutex<HashSet<Topic>>>, mut frames: mpsc::Receiver<Arc<Vec<u8>>>) {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+    loop {
+        tokio::select! {
+            line = lines.next_line() => {
+                let line = match line {
+                    Ok(Some(line)) => line,
+                    _ => break,
+                };
+                let mut words = line.split_whitespace();
+                let (command, topic) = (words.next(), words.next().and_then(Topic::from_name));
+                let mut topics = topics.lock().unwrap_or_else(|e| e.into_inner());
+                match (command, topic) {
+                    (Some("subscribe"), Some(topic)) => { topics.insert(topic); },
+                    (Some("unsubscribe"), Some(topic)) => { topics.remove(&topic); },
+                    _ => debug!("Ignoring notification socket command {:?}", line),
+                }
+            },
+            frame = frames.recv() => {
+                let frame = match frame {
+                    Some(frame) => frame,
+                    None => break,
+                };
+                if writer.write_all(&frame).await.is_err() {
+                    break;
+                }
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncReadExt;
+    use std::time::Duration;
+
+    #[test]
+    fn test_frame_encoding() {
+        let frame = encode_frame(&Notification { topic: Topic::RawTx, sequence: 7, body: vec![0xaa, 0xbb] });
+        assert_eq!(frame[0], 5);
+        assert_eq!(&frame[1..6], b"rawtx");
+        assert_eq!(u64::from_be_bytes(frame[6..14].try_into().unwrap()), 7);
+        assert_eq!(u32::from_be_bytes(frame[14..18].try_into().unwrap()), 2);
+        assert_eq!(&frame[18..], &[0xaa, 0xbb]);
+    }
+
+    #[tokio::test]
+    async fn test_clients_receive_subscribed_topics() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("notify.sock");
+        let publisher = SocketPublisher::bind(&path).unwrap();
+
+        let mut client = UnixStream::connect(&path).await.unwrap();
+        client.write_all(b"subscribe hashblock\n").await.unwrap();
+        // Let the publisher read the command
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        let skipped = Notification { topic: Topic::RawTx, sequence: 0, body: vec![1] };
+        let wanted = Notification { topic: Topic::HashBlock, sequence: 3, body: vec![2; 32] };
+        publisher.publish(&skipped);
+        publisher.publish(&wanted);
+
+        let expected = encode_frame(&wanted);
+        let mut received = vec![0u8; expected.len()];
+        tokio::time::timeout(Duration::from_secs(5), client.read_exact(&mut received))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(received, expected);
+    }
+}
@@ -0,0 +1,277 @@
+//! Webhook delivery
+//!
+//! Each notification a webhook subscribes to is posted to its URL as JSON,
+//! `{"topic": "rawtx", "sequence": 4, "data": "<hex body>"}`. The
+//! [`SIGNATURE_HEADER`] carries `sha256=` and the hex HMAC-SHA256 of the
+//! request body under the webhook's secret, which receivers should check
+//! before trusting the payload.
+//!
+//! Deliveries wait in a queue in the [`BlockchainDB`], so none are lost to a
+//! restart. One failing with a network error or a non-2xx status is retried
+//! with exponential backoff, up to [`MAX_ATTEMPTS`] times. Retries can reorder
+//! the deliveries to a webhook; the sequence number tells where each belongs.
+
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::Sha256;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::Notify;
+use tracing::{debug, error, warn};
+use crate::storage::BlockchainDB;
+use super::{Notification, NotificationError, Topic};
+
+/// Header holding the signature of the request body
+pub const SIGNATURE_HEADER: &str = "X-Supernova-Signature";
+
+/// Attempts at a delivery before it is dropped
+pub const MAX_ATTEMPTS: u32 = 10;
+
+/// Wait before the first retry, doubling with each further one
+const BASE_RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// Longest wait between retries
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60 * 60);
+
+/// Time a webhook has to answer
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Longest the queue sleeps before checking for due deliveries
+const POLL_INTERVAL: Duration = Duration::from_secs(30);
+
+/// An endpoint notifications are posted to
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WebhookConfig {
+    pub url: String,
+    /// Key the request bodies are signed with
+    pub secret: String,
+    /// Topics to deliver; all of them when empty
+    #[serde(default)]
+    pub topics: Vec<Topic>,
+}
+
+impl WebhookConfig {
+    /// Whether notifications of `topic` go to this webhook
+    pub fn wants(&self, topic: Topic) -> bool {
+        self.topics.is_empty() || self.topics.contains(&topic)
+    }
+}
+
+/// A notification queued for one webhook
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Delivery {
+    /// URL of the webhook, which identifies it across restarts
+    pub url: String,
+    pub notification: Notification,
+    /// Failed attempts so far
+    pub attempts: u32,
+    /// Seconds since the UNIX epoch before which it is not attempted
+    pub next_attempt: u64,
+}
+
+/// Hex HMAC-SHA256 of `body` under `secret`
+pub fn sign(secret: &[u8], body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("HMAC accepts keys of any length");
+    mac.update(body);
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// Wait before retrying a delivery that has failed `attempts` times
+pub fn retry_delay(attempts: u32) -> Duration {
+    let doublings = attempts.saturating_sub(1).min(16);
+    BASE_RETRY_DELAY.saturating_mul(1 << doublings).min(MAX_RETRY_DELAY)
+}
+
+/// JSON request body for `notification`
+pub fn payload(notification: &Notification) -> Vec<u8> {
+    json!({
+        "topic": notification.topic.as_str(),
+        "sequence": notification.sequence,
+        "data": hex::encode(&notification.body),
+    })
+    .to_string()
+    .into_bytes()
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_secs())
+}
+
+/// Persistent queue of webhook deliveries and the task working it off
+pub struct WebhookQueue {
+    webhooks: Vec<WebhookConfig>,
+    db: Arc<BlockchainDB>,
+    client: reqwest::Client,
+    wake: Notify,
+}
+
+impl WebhookQueue {
+    pub fn new(webhooks: Vec<WebhookConfig>, db: Arc<BlockchainDB>) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .unwrap_or_default();
+        Self {
+            webhooks,
+            db,
+            client,
+            wake: Notify::new(),
+        }
+    }
+
+    /// Queue `notification` for every webhook wanting it; returns how many do
+    pub fn enqueue(&self, notification: &Notification) -> Result<usize, NotificationError> {
+        let mut queued = 0;
+        for webhook in self.webhooks.iter().filter(|webhook| webhook.wants(notification.topic)) {
+            let delivery = Delivery {
+                url: webhook.url.clone(),
+                notification: notification.clone(),
+                attempts: 0,
+                next_attempt: 0,
+            };
+            let id = self.db.next_webhook_delivery_id()?;
+            self.db.store_webhook_delivery(id, &bincode::serialize(&delivery)?)?;
+            queued += 1;
+        }
+        if queued > 0 {
+            self.wake.notify_one();
+        }
+        Ok(queued)
+    }
+
+    /// Queued deliveries, oldest first
+    pub fn pending(&self) -> Result<Vec<Delivery>, NotificationError> {
+        self.db
+            .webhook_deliveries()?
+            .into_iter()
+            .map(|(_, raw)| Ok(bincode::deserialize(&raw)?))
+            .collect()
+    }
+
+    /// Deliver queued notifications as they come due, for as long as the node runs
+    pub async fn run(self: Arc<Self>) {
+        loop {
+            let wait = match self.deliver_due().await {
+                Ok(wait) => wait,
+                Err(e) => {
+                    error!("Webhook queue error: {}", e);
+                    POLL_INTERVAL
+                },
+            };
+            tokio::select! {
+                _ = tokio::time::sleep(wait) => {},
+                _ = self.wake.notified() => {},
+            }
+        }
+    }
+
+    /// Attempt every due delivery; returns how long until the next comes due
+    pub async fn deliver_due(&self) -> Result<Duration, NotificationError> {
+        let mut next_due = POLL_INTERVAL;
+        for (id, raw) in self.db.webhook_deliveries()? {
+            let mut delivery: Delivery = match bincode::deserialize(&raw) {
+                Ok(delivery) => delivery,
+                Err(e) => {
+                    warn!("Dropping unreadable webhook delivery {}: {}", id, e);
+                    self.db.remove_webhook_delivery(id)?;
+                    continue;
+                },
+            };
+            let now = unix_now();
+            if delivery.next_attempt > now {
+                next_due = next_due.min(Duration::from_secs(delivery.next_attempt - now));
+                continue;
+            }
+            let webhook = match self.webhooks.iter().find(|webhook| webhook.url == delivery.url) {
+                Some(webhook) => webhook,
+                None => {
+                    debug!("Dropping delivery to {}, which is no longer configured", delivery.url);
+                    self.db.remove_webhook_delivery(id)?;
+                    continue;
+                },
+            };
+
+            match self.post(webhook, &delivery.notification).await {
+                Ok(()) => self.db.remove_webhook_delivery(id)?,
+                Err(reason) => {
+                    delivery.attempts += 1;
+                    if delivery.attempts >= MAX_ATTEMPTS {
+                        warn!("Giving up on {} notification {} for {} after {} attempts: {}",
+                              delivery.notification.topic.as_str(), delivery.notification.sequence,
+                              delivery.url, delivery.attempts, reason);
+                        self.db.remove_webhook_delivery(id)?;
+                    } else {
+                        let delay = retry_delay(delivery.attempts);
+                        debug!("Webhook {} failed ({}), retrying in {:?}", delivery.url, reason, delay);
+                        delivery.next_attempt = now + delay.as_secs();
+                        self.db.store_webhook_delivery(id, &bincode::serialize(&delivery)?)?;
+                        next_due = next_due.min(delay);
+                    }
+                },
+            }
+        }
+        Ok(next_due)
+    }
+
+    async fn post(&self, webhook: &WebhookConfig, notification: &Notification) -> Result<(), String> {
+        let body = payload(notification);
+        let signature = format!("sha256={}", sign(webhook.secret.as_bytes(), &body));
+        let response = self.client
+            .post(&webhook.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header(SIGNATURE_HEADER, signature)
+            .body(body)
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        if response.status().is_success() {
+            Ok(())
+        } else {
+            Err(format!("status {}", response.status()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signature_and_backoff() {
+        // RFC 4231, test case 2
+        assert_eq!(
+            sign(b"Jefe", b"what do ya want for nothing?"),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+        assert_eq!(retry_delay(1), Duration::from_secs(5));
+        assert_eq!(retry_delay(3), Duration::from_secs(20));
+        assert_eq!(retry_delay(MAX_ATTEMPTS * 4), MAX_RETRY_DELAY);
+    }
+
+    #[tokio::test]
+    async fn test_failed_deliveries_stay_queued() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = Arc::new(BlockchainDB::new(dir.path()).unwrap());
+        let webhooks = vec![
+            // Nothing listens on port 1, so every attempt fails
+            WebhookConfig { url: "http://127.0.0.1:1/all".to_string(), secret: "s".to_string(), topics: vec![] },
+            WebhookConfig { url: "http://127.0.0.1:1/blocks".to_string(), secret: "s".to_string(), topics: vec![Topic::HashBlock] },
+        ];
+        let queue = WebhookQueue::new(webhooks.clone(), db.clone());
+        let notification = Notification { topic: Topic::RawTx, sequence: 0, body: vec![1, 2, 3] };
+        assert_eq!(queue.enqueue(&notification).unwrap(), 1);
+
+        let wait = queue.deliver_due().await.unwrap();
+        assert_eq!(wait, retry_delay(1));
+
+        // The queue lives in the database, so a restarted node picks it up
+        let restarted = WebhookQueue::new(webhooks, db);
+        let pending = restarted.pending().unwrap();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].url, "http://127.0.0.1:1/all");
+        assert_eq!(pending[0].notification, notification);
+        assert_eq!(pending[0].attempts, 1);
+        assert!(pending[0].next_attempt > unix_now());
+    }
+}
@@ -3,7 +3,7 @@ use std::path::Path;
 use std::sync::Arc;
 use thiserror::Error;
 use btclib::types::block::{Block, BlockHeader};
-use btclib::types::transaction::Transaction;
+use btclib::types::transaction::{Transaction, TransactionOutput};
 use std::path::PathBuf;
 use std::time::{SystemTime, Duration, UNIX_EPOCH};
 use serde::{Serialize, Deserialize};
@@ -20,6 +20,9 @@ const HEADERS_TREE: &str = "headers";
 const PENDING_BLOCKS_TREE: &str = "pending_blocks";
 const PENDING_BLOCKS_META_TREE: &str = "pending_blocks_meta";
 const PENDING_BLOCKS_INDEX_TREE: &str = "pending_blocks_index";
+const WEBHOOK_QUEUE_TREE: &str = "webhook_queue";
+const BLOCK_HEIGHTS_TREE: &str = "block_heights";
+const COINBASE_HEIGHTS_TREE: &str = "coinbase_heights";
 
 /// Metadata about a pending block
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -251,6 +254,12 @@ pub struct BlockchainDB {
     pending_blocks: sled::Tree,
     pending_blocks_meta: sled::Tree,
     pending_blocks_index: sled::Tree,
+    /// Webhook deliveries awaiting a (re)try, by id
+    webhook_queue: sled::Tree,
+    /// Height of every stored block, on the best chain or not, by hash
+    block_heights: sled::Tree,
+    /// Height of the block that created each connected coinbase, by txid
+    coinbase_heights: sled::Tree,
     /// Expiry time for pending blocks
     pending_block_expiry: Duration,
     /// Maximum number of pending blocks
@@ -311,6 +320,9 @@ impl BlockchainDB {
             pending_blocks: db.open_tree(PENDING_BLOCKS_TREE)?,
             pending_blocks_meta: db.open_tree(PENDING_BLOCKS_META_TREE)?,
             pending_blocks_index: db.open_tree(PENDING_BLOCKS_INDEX_TREE)?,
+            webhook_queue: db.open_tree(WEBHOOK_QUEUE_TREE)?,
+            block_heights: db.open_tree(BLOCK_HEIGHTS_TREE)?,
+            coinbase_heights: db.open_tree(COINBASE_HEIGHTS_TREE)?,
             db_path: path_buf,
             db: Arc::new(db),
             pending_block_expiry: db_config.pending_block_expiry,
@@ -631,6 +643,36 @@ impl BlockchainDB {
         Ok(())
     }
 
+    /// Fresh id for a queued webhook delivery; ids increase over time
+    pub fn next_webhook_delivery_id(&self) -> Result<u64, StorageError> {
+        Ok(self.db.generate_id()?)
+    }
+
+    /// Queue a serialized webhook delivery, replacing any queued under the same id
+    pub fn store_webhook_delivery(&self, id: u64, delivery: &[u8]) -> Result<(), StorageError> {
+        self.webhook_queue.insert(id.to_be_bytes(), delivery)?;
+        Ok(())
+    }
+
+    /// Drop a webhook delivery from the queue
+    pub fn remove_webhook_delivery(&self, id: u64) -> Result<(), StorageError> {
+        self.webhook_queue.remove(id.to_be_bytes())?;
+        Ok(())
+    }
+
+    /// Queued webhook deliveries, oldest first
+    pub fn webhook_deliveries(&self) -> Result<Vec<(u64, IVec)>, StorageError> {
+        self.webhook_queue
+            .iter()
+            .map(|entry| {
+                let (key, value) = entry?;
+                let id = <[u8; 8]>::try_from(key.as_ref())
+                    .map_err(|_| StorageError::DatabaseError("Malformed webhook queue key".to_string()))?;
+                Ok((u64::from_be_bytes(id), value))
+            })
+            .collect()
+    }
+
     /// Store a transaction in the database
     pub fn store_transaction(&self, tx_hash: &[u8; 32], tx_data: &[u8]) -> Result<(), StorageError> {
         self.transactions.insert(tx_hash, tx_data)?;
@@ -702,6 +744,48 @@ impl BlockchainDB {
         }
     }
 
+    /// Record the height of a stored block, whichever branch it is on
+    pub fn store_block_height(&self, block_hash: &[u8; 32], height: u64) -> Result<(), StorageError> {
+        self.block_heights.insert(block_hash, &height.to_be_bytes())?;
+        Ok(())
+    }
+
+    /// Height of a stored block, by hash
+    pub fn get_block_height(&self, block_hash: &[u8; 32]) -> Result<Option<u64>, StorageError> {
+        match self.block_heights.get(block_hash)? {
+            Some(bytes) => {
+                let bytes = <[u8; 8]>::try_from(bytes.as_ref())
+                    .map_err(|_| StorageError::DatabaseError("Malformed block height".to_string()))?;
+                Ok(Some(u64::from_be_bytes(bytes)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Record the height of the block whose coinbase is `tx_hash`
+    pub fn store_coinbase_height(&self, tx_hash: &[u8; 32], height: u64) -> Result<(), StorageError> {
+        self.coinbase_heights.insert(tx_hash, &height.to_be_bytes())?;
+        Ok(())
+    }
+
+    /// Forget a coinbase whose block was disconnected
+    pub fn remove_coinbase_height(&self, tx_hash: &[u8; 32]) -> Result<(), StorageError> {
+        self.coinbase_heights.remove(tx_hash)?;
+        Ok(())
+    }
+
+    /// Height of the connected block whose coinbase is `tx_hash`
+    pub fn get_coinbase_height(&self, tx_hash: &[u8; 32]) -> Result<Option<u64>, StorageError> {
+        match self.coinbase_heights.get(tx_hash)? {
+            Some(bytes) => {
+                let bytes = <[u8; 8]>::try_from(bytes.as_ref())
+                    .map_err(|_| StorageError::DatabaseError("Malformed coinbase height".to_string()))?;
+                Ok(Some(u64::from_be_bytes(bytes)))
+            }
+            None => Ok(None),
+        }
+    }
+
     /// Implement block pruning
     pub fn prune_blocks(&self, height: u64) -> Result<(), StorageError> {
         let mut pruned_count = 0;
@@ -723,6 +807,8 @@ impl BlockchainDB {
         self.transactions.clear()?;
         self.utxos.clear()?;
         self.block_height_index.clear()?;
+        self.block_heights.clear()?;
+        self.coinbase_heights.clear()?;
         self.tx_index.clear()?;
         self.headers.clear()?;
         self.pending_blocks.clear()?;
@@ -985,12 +1071,12 @@ impl BlockchainDB {
     }
     
     /// Get UTXO from the database
-    pub fn get_utxo(&self, tx_hash: &[u8; 32], index: u32) -> Result<Option<Transaction>, StorageError> {
+    pub fn get_utxo(&self, tx_hash: &[u8; 32], index: u32) -> Result<Option<TransactionOutput>, StorageError> {
         let key = create_utxo_key(tx_hash, index);
         match self.utxos.get(key)? {
             Some(data) => {
-                let tx = bincode::deserialize(&data)?;
-                Ok(Some(tx))
+                let output = bincode::deserialize(&data)?;
+                Ok(Some(output))
             }
             None => Ok(None),
         }
@@ -1786,6 +1872,12 @@ pub enum StorageError {
     PendingBlockExpired,
     #[error("Pending block invalid")]
     PendingBlockInvalid,
+    #[error("Coinbase output created at height {created_height} cannot be spent before height {mature_height}")]
+    ImmatureCoinbase { created_height: u64, mature_height: u64 },
+    #[error("Difficulty error: {0}")]
+    Difficulty(#[from] btclib::consensus::DifficultyError),
+    #[error("Invalid transaction: {0}")]
+    InvalidTransaction(String),
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
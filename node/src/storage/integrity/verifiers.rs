@@ -445,12 +445,9 @@ impl<'a> UtxoVerifier<'a> {
         for ((tx_hash, output_idx), expected_output) in sample {
             // Check UTXO exists in database
             match self.db.get_utxo(tx_hash, *output_idx) {
-                Ok(Some(stored_output)) => {
+                Ok(Some(stored)) => {
                     // Verify output matches expected value
-                    let stored: btclib::types::TransactionOutput =
-                        bincode::deserialize(&stored_output)?;
-                    
-                    if stored.value() != expected_output.value() {
+                    if stored.amount() != expected_output.amount() {
                         issues.push(IntegrityIssue::new(
                             IssueType::UtxoInconsistency,
                             IssueSeverity::Error,
@@ -458,8 +455,8 @@ impl<'a> UtxoVerifier<'a> {
                                 "UTXO {}:{} value mismatch: expected {}, found {}",
                                 hex::encode(&tx_hash[..4]),
                                 output_idx,
-                                expected_output.value(),
-                                stored.value()
+                                expected_output.amount(),
+                                stored.amount()
                             ),
                             IssueLocation::Utxo {
                                 tx_hash: *tx_hash,
@@ -724,13 +721,7 @@ impl<'a> CryptoVerifier<'a> {
     
     /// Verify proof of work for a block
     fn verify_block_pow(&self, block: &Block) -> bool {
-        let hash = block.hash();
-        let target = block.target();
-        
-        // Check that hash is below target
-        // For simplicity, we just check the first 4 bytes
-        let hash_value = u32::from_be_bytes([hash[0], hash[1], hash[2], hash[3]]);
-        hash_value <= target
+        btclib::consensus::hash_meets_target(&block.hash(), block.header().target())
     }
     
     /// Calculate merkle root from transactions
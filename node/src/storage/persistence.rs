@@ -1,6 +1,8 @@
 use super::database::{BlockchainDB, StorageError};
 use btclib::types::block::Block;
-use btclib::types::transaction::Transaction;
+use btclib::types::transaction::{Transaction, TransactionOutput};
+use btclib::consensus::{self, DifficultyParams, HeaderInfo, HeaderLookup, U256, TREASURY_FEE_PERCENTAGE};
+use crate::events::{EventBus, NodeEvent};
 use std::sync::Arc;
 use std::time::{SystemTime, UNIX_EPOCH};
 use std::collections::{HashMap, HashSet};
@@ -25,13 +27,23 @@ pub struct ChainState {
     db: Arc<BlockchainDB>,
     current_height: u64,
     best_block_hash: [u8; 32],
-    chain_work: HashMap<[u8; 32], u128>,
+    chain_work: HashMap<[u8; 32], U256>,
     fork_points: HashSet<[u8; 32]>,
     last_reorg_time: SystemTime,
     reorg_count: u64,
     active_forks: HashMap<[u8; 32], ForkInfo>,
     last_block_time: SystemTime,
     rejected_reorgs: u64,
+    difficulty_params: DifficultyParams,
+    events: Option<EventBus>,
+}
+
+/// Outputs spent and created by the transactions of a block checked so far
+#[derive(Default)]
+struct BlockSpends {
+    coinbase: Option<[u8; 32]>,
+    spent: HashSet<([u8; 32], u32)>,
+    created: HashMap<([u8; 32], u32), TransactionOutput>,
 }
 
 #[derive(Debug)]
@@ -54,7 +66,7 @@ pub struct ForkInfo {
     pub fork_point_height: u64,
     pub tip_hash: [u8; 32],
     pub tip_height: u64,
-    pub chain_work: u128,
+    pub chain_work: U256,
     pub blocks_added: u64,
     pub first_seen: SystemTime,
     pub last_updated: SystemTime,
@@ -107,9 +119,42 @@ impl ChainState {
             active_forks: HashMap::new(),
             last_block_time: SystemTime::now(),
             rejected_reorgs: 0,
+            difficulty_params: DifficultyParams::default(),
+            events: None,
         })
     }
 
+    /// Proof-of-work limit and retarget algorithm blocks are checked against
+    pub fn set_difficulty_params(&mut self, params: DifficultyParams) {
+        self.difficulty_params = params;
+    }
+
+    pub fn difficulty_params(&self) -> &DifficultyParams {
+        &self.difficulty_params
+    }
+
+    /// Compact target required for the next block on the best chain
+    pub fn next_work_required(&self) -> Result<u32, StorageError> {
+        let prev = self.header_at(self.current_height);
+        Ok(consensus::next_work_required(&self.difficulty_params, prev.as_ref(), self)?)
+    }
+
+    /// Publish new tips and reorganizations on `events`
+    pub fn set_event_bus(&mut self, events: EventBus) {
+        self.events = Some(events);
+    }
+
+    fn publish(&self, event: NodeEvent) {
+        if let Some(events) = &self.events {
+            events.publish(event);
+        }
+    }
+
+    /// Portion of `total_fees` a block's coinbase must pay to the environmental treasury
+    pub fn calculate_treasury_share(&self, total_fees: u64) -> u64 {
+        consensus::treasury_share(total_fees, TREASURY_FEE_PERCENTAGE)
+    }
+
     pub fn get_height(&self) -> u64 {
         self.current_height
     }
@@ -118,6 +163,14 @@ impl ChainState {
         self.best_block_hash
     }
 
+    /// Height of a stored block on any branch; the all-zero hash is the parent of the first block
+    pub fn block_height(&self, block_hash: &[u8; 32]) -> Result<Option<u64>, StorageError> {
+        if *block_hash == [0u8; 32] {
+            return Ok(Some(0));
+        }
+        self.db.get_block_height(block_hash)
+    }
+
     /// Get the genesis block hash
     pub fn get_genesis_hash(&self) -> [u8; 32] {
         // Fetch the genesis block hash from database or use a cached value
@@ -191,6 +244,15 @@ impl ChainState {
         // Update last block time even if we don't accept the block
         self.last_block_time = SystemTime::now();
 
+        let height = match self.block_height(&prev_hash)? {
+            Some(parent_height) => parent_height + 1,
+            None => {
+                debug!("Ignoring block {} with unknown parent {}",
+                    hex::encode(&block_hash[..4]), hex::encode(&prev_hash[..4]));
+                return Ok(false);
+            }
+        };
+
         if !self.validate_block(&block).await? {
             return Err(StorageError::InvalidBlock);
         }
@@ -208,25 +270,29 @@ impl ChainState {
                 Some(work) => *work,
                 None => {
                     // If we don't have work for current tip, calculate it
-                    if let Ok(block) = self.db.get_block(&self.best_block_hash)? {
+                    if let Some(block) = self.db.get_block(&self.best_block_hash)? {
                         let work = self.calculate_chain_work(&block)?;
                         self.chain_work.insert(self.best_block_hash, work);
                         work
                     } else {
-                        0
+                        U256::ZERO
                     }
                 }
             };
             
             // Update or create fork info regardless of whether we accept the block
             self.update_fork_info(&block)?;
+
+            // Side-branch blocks are kept whether or not their branch becomes the best chain
+            self.store_block(block.clone(), height)?;
+            self.chain_work.insert(block_hash, new_chain_work);
             
             // Determine if we should switch to the new fork
             match new_chain_work.cmp(&current_work) {
                 Ordering::Greater => {
                     // New chain has more work, attempt reorganization
-                    let (fork_point, blocks_to_apply, blocks_to_disconnect) = 
-                        self.find_fork_point(&block)?;
+                    let (fork_point, blocks_to_apply, blocks_to_disconnect) =
+                        self.find_fork_point(&block, height)?;
 
                     if blocks_to_disconnect.len() as u64 > MAX_REORG_DEPTH {
                         warn!("Rejected deep reorganization: {} blocks (max: {})", 
@@ -254,8 +320,8 @@ impl ChainState {
                     if let (Some(current), Some(new)) = (current_fork, new_fork) {
                         // Prefer the fork we saw first
                         if new.first_seen < current.first_seen {
-                            let (fork_point, blocks_to_apply, blocks_to_disconnect) = 
-                                self.find_fork_point(&block)?;
+                            let (fork_point, blocks_to_apply, blocks_to_disconnect) =
+                                self.find_fork_point(&block, height)?;
                                 
                             if blocks_to_disconnect.len() as u64 > MAX_REORG_DEPTH {
                                 warn!("Rejected deep reorganization with equal work: {} blocks", 
@@ -286,29 +352,30 @@ impl ChainState {
             }
         } else {
             // Direct extension of current chain
-            let block_difficulty = calculate_block_work(extract_target_from_block(&block)) as u64;
+            let block_difficulty = consensus::block_work(block.header().target()).saturating_u64();
             
-            self.store_block(block.clone())?;
+            self.store_block(block.clone(), height)?;
+            self.apply_block_utxos(&block, height)?;
             self.chain_work.insert(block_hash, new_chain_work);
             
             // Update block metadata
-            self.current_height = block.height();
+            self.current_height = height;
             self.best_block_hash = block_hash;
+            self.db.set_metadata(b"height", &bincode::serialize(&self.current_height)?)?;
+            self.db.set_metadata(b"best_hash", &block_hash)?;
             
             // Update total difficulty
             self.update_total_difficulty(block_difficulty)?;
             
             // Update fork info for direct extension
             self.update_fork_info(&block)?;
+
+            let height = self.current_height;
+            self.publish(NodeEvent::BlockConnected { block: Arc::new(block), height });
             
             return Ok(true);
         }
 
-        // Store the block in our database, but don't update best chain
-        let block_difficulty = calculate_block_work(extract_target_from_block(&block)) as u64;
-        self.store_block(block)?;
-        self.chain_work.insert(block_hash, new_chain_work);
-
         Ok(false)
     }
 
@@ -317,6 +384,27 @@ impl ChainState {
             return Ok(false);
         }
 
+        let height = match self.block_height(&block.prev_block_hash())? {
+            Some(parent_height) => parent_height + 1,
+            None => return Ok(false),
+        };
+
+        let bits = block.header().target();
+        if let Err(e) = consensus::check_proof_of_work(&block.hash(), bits, &self.difficulty_params) {
+            warn!("Rejected block {}: {}", hex::encode(&block.hash()[..4]), e);
+            return Ok(false);
+        }
+
+        // Only blocks on the best chain have a known height to retarget from
+        if block.prev_block_hash() == self.best_block_hash {
+            let expected = self.next_work_required()?;
+            if bits != expected {
+                warn!("Rejected block {}: target {:08x}, expected {:08x}",
+                    hex::encode(&block.hash()[..4]), bits, expected);
+                return Ok(false);
+            }
+        }
+
         if block.height() != self.current_height + 1 
             && block.prev_block_hash() != self.best_block_hash {
             let fork_distance = self.calculate_fork_distance(block)?;
@@ -325,62 +413,232 @@ impl ChainState {
             }
         }
 
-        for tx in block.transactions() {
-            if !self.validate_transaction(tx).await? {
+        // Side-branch blocks spend outputs of their own branch, so their transactions
+        // and coinbase are checked when a reorganization connects them
+        if block.prev_block_hash() != self.best_block_hash {
+            return Ok(true);
+        }
+
+        self.check_block_contents(block, height)
+    }
+
+    /// Check a block's transactions and coinbase against the UTXO set of its parent
+    ///
+    /// The block must extend the current tip, which is at `height - 1`.
+    fn check_block_contents(&self, block: &Block, height: u64) -> Result<bool, StorageError> {
+        let total_fees = match self.check_block_transactions(block, height) {
+            Ok(fees) => fees,
+            Err(StorageError::InvalidTransaction(reason)) => {
+                warn!("Rejected block {}: {}", hex::encode(&block.hash()[..4]), reason);
                 return Ok(false);
             }
+            Err(e @ StorageError::ImmatureCoinbase { .. }) => {
+                warn!("Rejected block {}: {}", hex::encode(&block.hash()[..4]), e);
+                return Ok(false);
+            }
+            Err(e) => return Err(e),
+        };
+
+        // Coinbase may claim at most subsidy + fees and must fund the treasury
+        let treasury_share = self.calculate_treasury_share(total_fees);
+
+        if let Err(e) = consensus::validate_coinbase(block.transactions(), height, total_fees, treasury_share) {
+            warn!("Rejected block {}: {}", hex::encode(&block.hash()[..4]), e);
+            return Ok(false);
         }
 
         Ok(true)
     }
 
-    async fn validate_transaction(&self, tx: &Transaction) -> Result<bool, StorageError> {
-        let mut spent_outputs = HashSet::new();
+    /// Check a transaction against the current UTXO set and return the fee it pays
+    ///
+    /// Every input must spend an unspent, mature output exactly once and carry a valid
+    /// signature script for it. Used for block validation; mempool admission has
+    /// its own rules in [`crate::mempool::validation`].
+    pub fn check_transaction(&self, tx: &Transaction) -> Result<u64, StorageError> {
+        self.check_transaction_in_block(tx, self.current_height + 1, &mut BlockSpends::default())
+    }
+
+    /// Check every non-coinbase transaction of a block at `height` and return the fees they pay
+    ///
+    /// Transactions may spend outputs created earlier in the same block, but no output
+    /// may be spent twice and the block's own coinbase is never spendable.
+    pub fn check_block_transactions(&self, block: &Block, height: u64) -> Result<u64, StorageError> {
+        let mut spends = BlockSpends {
+            coinbase: block.transactions().first().filter(|tx| tx.is_coinbase()).map(|tx| tx.hash()),
+            ..BlockSpends::default()
+        };
+
+        let mut total_fees = 0u64;
+        for tx in block.transactions().iter().skip(1) {
+            let fee = self.check_transaction_in_block(tx, height, &mut spends)?;
+            total_fees = total_fees
+                .checked_add(fee)
+                .ok_or_else(|| StorageError::InvalidTransaction("block fees overflow".to_string()))?;
+        }
+        Ok(total_fees)
+    }
+
+    /// Check `tx` as part of a block at `height`, recording its spends and outputs in `spends`
+    fn check_transaction_in_block(
+        &self,
+        tx: &Transaction,
+        height: u64,
+        spends: &mut BlockSpends,
+    ) -> Result<u64, StorageError> {
+        if tx.is_coinbase() {
+            return Err(StorageError::InvalidTransaction("coinbase outside of a block".to_string()));
+        }
+        if tx.inputs().is_empty() || tx.outputs().is_empty() {
+            return Err(StorageError::InvalidTransaction("no inputs or outputs".to_string()));
+        }
+
+        let mut spent_outputs = HashMap::new();
         for input in tx.inputs() {
             let outpoint = (input.prev_tx_hash(), input.prev_output_index());
-            if !spent_outputs.insert(outpoint) {
-                return Ok(false);
+            if spent_outputs.contains_key(&outpoint) || spends.spent.contains(&outpoint) {
+                return Err(StorageError::InvalidTransaction(format!(
+                    "output {}:{} spent twice", hex::encode(&outpoint.0[..4]), outpoint.1)));
             }
 
-            if self.db.get_utxo(&input.prev_tx_hash(), input.prev_output_index())?.is_none() {
-                return Ok(false);
+            if spends.coinbase == Some(outpoint.0) {
+                return Err(StorageError::ImmatureCoinbase {
+                    created_height: height,
+                    mature_height: height + consensus::COINBASE_MATURITY,
+                });
             }
+
+            let output = match spends.created.get(&outpoint) {
+                Some(output) => output.clone(),
+                None => {
+                    let output = self.db.get_utxo(&outpoint.0, outpoint.1)?.ok_or_else(|| {
+                        StorageError::InvalidTransaction(format!(
+                            "output {}:{} is not unspent", hex::encode(&outpoint.0[..4]), outpoint.1))
+                    })?;
+                    if let Some(created_height) = self.coinbase_height(&outpoint.0)? {
+                        if !consensus::is_coinbase_mature(created_height, height) {
+                            return Err(StorageError::ImmatureCoinbase {
+                                created_height,
+                                mature_height: created_height + consensus::COINBASE_MATURITY,
+                            });
+                        }
+                    }
+                    output
+                }
+            };
+            spent_outputs.insert(outpoint, output);
         }
 
-        Ok(true)
+        tx.verify_signatures(|hash, index| spent_outputs.get(&(*hash, index)).cloned())
+            .map_err(|e| StorageError::InvalidTransaction(e.to_string()))?;
+
+        let total_in = spent_outputs
+            .values()
+            .try_fold(0u64, |acc, output| acc.checked_add(output.amount()))
+            .ok_or_else(|| StorageError::InvalidTransaction("input amounts overflow".to_string()))?;
+        let fee = total_in
+            .checked_sub(tx.total_output())
+            .ok_or_else(|| StorageError::InvalidTransaction("outputs exceed inputs".to_string()))?;
+
+        let tx_hash = tx.hash();
+        spends.spent.extend(spent_outputs.into_keys());
+        for (index, output) in tx.outputs().iter().enumerate() {
+            spends.created.insert((tx_hash, index as u32), output.clone());
+        }
+        Ok(fee)
     }
 
-    fn find_fork_point(&self, new_tip: &Block) -> Result<(Block, Vec<Block>, Vec<Block>), StorageError> {
-        let mut blocks_to_apply = Vec::new();
-        let mut blocks_to_disconnect = Vec::new();
-        let mut current = new_tip.clone();
-        let mut main_chain = self.get_block_at_height(self.current_height)?;
+    /// Height of the best-chain block whose coinbase is `tx_hash`, if it is one
+    ///
+    /// Recorded when the block is connected and dropped when it is disconnected, so a
+    /// coinbase identical to an earlier one takes over its entry along with its outputs.
+    pub fn coinbase_height(&self, tx_hash: &[u8; 32]) -> Result<Option<u64>, StorageError> {
+        self.db.get_coinbase_height(tx_hash)
+    }
 
-        while current.height() > 0 && main_chain.height() > 0 {
-            if current.hash() == main_chain.hash() {
-                return Ok((current, blocks_to_apply, blocks_to_disconnect));
+    /// Median timestamp of the last 11 blocks, against which time-based lock times are judged
+    pub fn median_time_past(&self) -> Result<u64, StorageError> {
+        let mut timestamps = Vec::with_capacity(11);
+        let mut current_hash = self.best_block_hash;
+        let mut height = self.current_height;
+        while timestamps.len() < 11 && height > 0 {
+            let block = match self.db.get_block(&current_hash)? {
+                Some(block) => block,
+                None => break,
+            };
+            timestamps.push(block.header().timestamp());
+            current_hash = block.prev_block_hash();
+            height -= 1;
+        }
+        timestamps.sort_unstable();
+        Ok(timestamps.get(timestamps.len() / 2).copied().unwrap_or(0))
+    }
+
+    /// Record a connected block's transactions and move its spent and created outputs in the UTXO set
+    fn apply_block_utxos(&self, block: &Block, height: u64) -> Result<(), StorageError> {
+        for tx in block.transactions() {
+            let tx_hash = tx.hash();
+            self.db.store_transaction(&tx_hash, &bincode::serialize(tx)?)?;
+            if tx.is_coinbase() {
+                self.db.store_coinbase_height(&tx_hash, height)?;
             }
 
-            if current.height() > main_chain.height() {
-                blocks_to_apply.push(current.clone());
-                current = self.db.get_block(&current.prev_block_hash())?.unwrap();
+            if !tx.is_coinbase() {
+                for input in tx.inputs() {
+                    self.db.remove_utxo(&input.prev_tx_hash(), input.prev_output_index())?;
+                }
+            }
+            for (index, output) in tx.outputs().iter().enumerate() {
+                self.db.store_utxo(&tx_hash, index as u32, &bincode::serialize(output)?)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Walk the new branch and the best chain back to their common ancestor
+    ///
+    /// Returns the ancestor's hash and height, then the blocks to connect and the
+    /// blocks to disconnect, both listed from their tips down.
+    fn find_fork_point(
+        &self,
+        new_tip: &Block,
+        new_tip_height: u64,
+    ) -> Result<(([u8; 32], u64), Vec<Block>, Vec<Block>), StorageError> {
+        let mut blocks_to_apply = Vec::new();
+        let mut blocks_to_disconnect = Vec::new();
+        let mut new_hash = new_tip.hash();
+        let mut new_height = new_tip_height;
+        let mut main_hash = self.best_block_hash;
+        let mut main_height = self.current_height;
+
+        while new_hash != main_hash {
+            if new_height > main_height {
+                let block = self.db.get_block(&new_hash)?
+                    .ok_or(StorageError::InvalidChainReorganization)?;
+                new_hash = block.prev_block_hash();
+                new_height -= 1;
+                blocks_to_apply.push(block);
             } else {
-                blocks_to_disconnect.push(main_chain.clone());
-                main_chain = self.db.get_block(&main_chain.prev_block_hash())?.unwrap();
+                let block = self.db.get_block(&main_hash)?
+                    .ok_or(StorageError::InvalidChainReorganization)?;
+                main_hash = block.prev_block_hash();
+                main_height -= 1;
+                blocks_to_disconnect.push(block);
             }
         }
 
-        Err(StorageError::InvalidChainReorganization)
+        Ok(((new_hash, new_height), blocks_to_apply, blocks_to_disconnect))
     }
 
     async fn handle_chain_reorganization(
         &mut self,
         new_tip: &Block,
-        fork_point: Block,
+        fork_point: ([u8; 32], u64),
         blocks_to_apply: Vec<Block>,
         blocks_to_disconnect: Vec<Block>,
         fork_choice_reason: ForkChoiceReason,
     ) -> Result<(), StorageError> {
+        let (fork_hash, fork_height) = fork_point;
         let old_tip = self.best_block_hash;
         let time_since_last_reorg = SystemTime::now()
             .duration_since(self.last_reorg_time)
@@ -389,8 +647,8 @@ impl ChainState {
         let reorg_event = ReorganizationEvent {
             old_tip,
             new_tip: new_tip.hash(),
-            fork_point: fork_point.hash(),
-            fork_height: fork_point.height(),
+            fork_point: fork_hash,
+            fork_height,
             blocks_disconnected: blocks_to_disconnect.len() as u64,
             blocks_connected: blocks_to_apply.len() as u64,
             timestamp: SystemTime::now(),
@@ -403,19 +661,19 @@ impl ChainState {
             info!("Chain reorganization: {} blocks disconnected, {} blocks connected at height {}. Reason: {:?}",
                 blocks_to_disconnect.len(),
                 blocks_to_apply.len(),
-                fork_point.height(),
+                fork_height,
                 fork_choice_reason);
         } else {
             debug!("Minor chain reorganization: {} blocks disconnected, {} blocks connected at height {}",
                 blocks_to_disconnect.len(),
                 blocks_to_apply.len(),
-                fork_point.height());
+                fork_height);
         }
 
         self.db.begin_transaction()?;
 
-        // Disconnect blocks from the current main chain
-        for block in blocks_to_disconnect.iter().rev() {
+        // Disconnect blocks from the current main chain, tip first
+        for block in blocks_to_disconnect.iter() {
             if let Err(e) = self.disconnect_block(block) {
                 error!("Error disconnecting block during reorganization: {:?}", e);
                 self.db.rollback_transaction()?;
@@ -423,29 +681,29 @@ impl ChainState {
             }
         }
 
-        // Connect blocks from the new chain
-        let mut total_difficulty_adjustment: u64 = 0;
-        for block in blocks_to_apply.iter() {
-            let block_difficulty = calculate_block_work(extract_target_from_block(block)) as u64;
-            total_difficulty_adjustment += block_difficulty;
-            
-            if let Err(e) = self.connect_block(block) {
-                error!("Error connecting block during reorganization: {:?}", e);
-                // Critical error - rollback the transaction
-                self.db.rollback_transaction()?;
+        // Connect the new branch from the fork point up. Each block is checked against
+        // the UTXO set of its own branch, which only exists once its parent is connected.
+        let mut connected = Vec::with_capacity(blocks_to_apply.len());
+        for block in blocks_to_apply.iter().rev() {
+            let height = self.current_height + 1;
+            let result = match self.check_block_contents(block, height) {
+                Ok(true) => self.connect_block(block, height),
+                Ok(false) => Err(StorageError::InvalidBlock),
+                Err(e) => Err(e),
+            };
+            if let Err(e) = result {
+                warn!("Abandoning reorganization: block {} at height {} is invalid: {:?}",
+                    hex::encode(&block.hash()[..4]), height, e);
+                self.restore_chain(&connected, &blocks_to_disconnect)?;
+                self.rejected_reorgs += 1;
                 return Err(e);
             }
+            connected.push(block);
         }
 
-        // Update chain state
-        self.best_block_hash = new_tip.hash();
-        self.current_height = new_tip.height();
         self.last_reorg_time = SystemTime::now();
         self.reorg_count += 1;
 
-        // Update total difficulty for the reorg
-        self.update_total_difficulty(total_difficulty_adjustment)?;
-
         // Commit the transaction
         if let Err(e) = self.db.commit_transaction() {
             error!("Failed to commit reorganization transaction: {:?}", e);
@@ -462,8 +720,35 @@ impl ChainState {
 
         // Log successful reorganization
         info!("Chain reorganization complete: Activated fork with tip {} at height {}",
-            hex::encode(&new_tip.hash()[..4]), new_tip.height());
+            hex::encode(&new_tip.hash()[..4]), self.current_height);
 
+        // Both lists were collected walking down from the tips
+        self.publish(NodeEvent::Reorg {
+            old_tip,
+            new_tip: new_tip.hash(),
+            fork_height,
+            disconnected: blocks_to_disconnect.iter().map(|block| block.hash()).collect(),
+            connected: blocks_to_apply.iter().rev().map(|block| block.hash()).collect(),
+        });
+        for (height, block) in (fork_height + 1..).zip(blocks_to_apply.into_iter().rev()) {
+            self.publish(NodeEvent::BlockConnected { block: Arc::new(block), height });
+        }
+
+        Ok(())
+    }
+
+    /// Put the best chain back after a reorganization failed part way through
+    ///
+    /// `connected` lists the new branch's blocks in the order they were connected,
+    /// `disconnected` the old branch's blocks from its tip down.
+    fn restore_chain(&mut self, connected: &[&Block], disconnected: &[Block]) -> Result<(), StorageError> {
+        for block in connected.iter().rev() {
+            self.disconnect_block(block)?;
+        }
+        for block in disconnected.iter().rev() {
+            let height = self.current_height + 1;
+            self.connect_block(block, height)?;
+        }
         Ok(())
     }
 
@@ -472,6 +757,9 @@ impl ChainState {
             for (index, _) in tx.outputs().iter().enumerate() {
                 self.db.remove_utxo(&tx.hash(), index as u32)?;
             }
+            if tx.is_coinbase() {
+                self.db.remove_coinbase_height(&tx.hash())?;
+            }
 
             for input in tx.inputs() {
                 if let Some(prev_tx) = self.db.get_transaction(&input.prev_tx_hash())? {
@@ -486,7 +774,7 @@ impl ChainState {
         }
 
         // Adjust total difficulty when disconnecting a block
-        let block_difficulty = calculate_block_work(extract_target_from_block(block)) as u64;
+        let block_difficulty = consensus::block_work(block.header().target()).saturating_u64();
         let current_difficulty = self.get_total_difficulty();
         let new_total = current_difficulty.saturating_sub(block_difficulty);
         
@@ -502,63 +790,38 @@ impl ChainState {
         Ok(())
     }
 
-    fn connect_block(&mut self, block: &Block) -> Result<(), StorageError> {
-        // Calculate total difficulty and block work
-        let block_difficulty = calculate_block_work(extract_target_from_block(block)) as u64;
-        
-        // Update chain work
-        let block_hash = block.hash();
-        self.chain_work.insert(block_hash, block_difficulty as u128);
+    fn connect_block(&mut self, block: &Block, height: u64) -> Result<(), StorageError> {
+        // Chain work was recorded when the block was stored on its branch
+        let block_difficulty = consensus::block_work(block.header().target()).saturating_u64();
         
         // Update total difficulty
         self.update_total_difficulty(block_difficulty)?;
+        self.apply_block_utxos(block, height)?;
         
         // Update best block hash and height
-        self.best_block_hash = block_hash;
-        self.current_height += 1;
+        self.best_block_hash = block.hash();
+        self.current_height = height;
+        self.db.store_metadata(b"height", &bincode::serialize(&self.current_height)?)?;
+        self.db.store_metadata(b"best_hash", &self.best_block_hash)?;
         
         Ok(())
     }
 
-    fn store_block(&mut self, block: Block) -> Result<(), StorageError> {
-        // Store the block in the database
-        let block_hash = block.hash();
+    /// Store a block and its height without connecting it
+    fn store_block(&self, block: Block, height: u64) -> Result<(), StorageError> {
         self.db.insert_block(&block)?;
-        
-        // Calculate block difficulty
-        let block_difficulty = calculate_block_work(extract_target_from_block(&block)) as u64;
-        
-        // Update chain work
-        self.chain_work.insert(block_hash, block_difficulty as u128);
-        
-        // Update total difficulty
-        self.update_total_difficulty(block_difficulty)?;
-        
-        // Update best block hash and height if higher than current
-        if self.current_height < block.height() {
-            self.best_block_hash = block_hash;
-            self.current_height = block.height();
-            self.db.set_metadata(b"height", &bincode::serialize(&self.current_height)?)?;
-            self.db.set_metadata(b"best_hash", &block_hash)?;
-        }
-        
+        self.db.store_block_height(&block.hash(), height)?;
         Ok(())
     }
 
-    fn calculate_chain_work(&self, block: &Block) -> Result<u128, StorageError> {
-        let mut total_work = 0_u128;
-        let mut current = block.clone();
+    /// Total work of the chain ending at `block`, back to the first ancestor we have
+    fn calculate_chain_work(&self, block: &Block) -> Result<U256, StorageError> {
+        let mut total_work = consensus::block_work(block.header().target());
+        let mut prev_hash = block.prev_block_hash();
         
-        while current.height() > 0 {
-            total_work += calculate_block_work(extract_target_from_block(&current));
-            
-            // Get previous block
-            let prev_hash = current.prev_block_hash();
-            if let Some(prev_block) = self.db.get_block(&prev_hash)? {
-                current = prev_block;
-            } else {
-                return Err("BlockNotFound".into());
-            }
+        while let Some(prev_block) = self.db.get_block(&prev_hash)? {
+            total_work = total_work.saturating_add(consensus::block_work(prev_block.header().target()));
+            prev_hash = prev_block.prev_block_hash();
         }
         
         Ok(total_work)
@@ -807,65 +1070,70 @@ impl ChainState {
     }
 }
 
-// Function to extract target from a block's bits field
-fn extract_target_from_block(block: &Block) -> u32 {
-    // BlockHeader doesn't expose target directly, so we need to extract it
-    // For our implementation, we'll use the hash of the block as a proxy for difficulty
-    let hash = block.hash();
-    let first_bytes = &hash[0..4];
-    
-    // Create a u32 from the first 4 bytes of the hash
-    let mut target = 0u32;
-    for (i, &byte) in first_bytes.iter().enumerate() {
-        target |= (byte as u32) << (8 * i);
-    }
-    
-    target
-}
-
-// Calculate work for a block based on its target (difficulty)
-fn calculate_block_work(target: u32) -> u128 {
-    // Use a more reasonable approach that doesn't overflow
-    // The actual Bitcoin formula is 2^256 / (target+1), but we'll use a simplified version
-    // that doesn't overflow u128
-    
-    // First ensure target is not 0 to avoid division by zero
-    let safe_target = target.max(1) as u128;
-    
-    // Use a large but safe max_target value that won't overflow
-    // 2^128 - 1 is the maximum value for u128
-    let max_target = u128::MAX / 1000; // Use a fraction of max to avoid overflow
-    
-    // Calculate difficulty - with safeguards against overflow
-    if safe_target <= 1 {
-        return max_target; // Avoid division by extremely small numbers
+/// Headers of the best chain, for retargeting
+impl HeaderLookup for ChainState {
+    fn header_at(&self, height: u64) -> Option<HeaderInfo> {
+        if height == 0 || height > self.current_height {
+            return None;
+        }
+        let block = self.get_block_at_height(height).ok()?;
+        Some(HeaderInfo::new(height, block.header()))
     }
-    
-    // Calculate work as max_target / target
-    max_target / safe_target
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use btclib::consensus::REGTEST_POW_LIMIT_BITS;
+    use btclib::types::block::BlockHeader;
+    use btclib::types::transaction::TransactionInput;
     use tempfile::tempdir;
 
+    /// Harder than the regtest limit; about one hash in 256 meets it
+    const HARDER_BITS: u32 = 0x2000ffff;
+
+    fn regtest_chain_state(db: Arc<BlockchainDB>) -> Result<ChainState, StorageError> {
+        let mut chain_state = ChainState::new(db)?;
+        chain_state.set_difficulty_params(DifficultyParams::regtest());
+        Ok(chain_state)
+    }
+
+    fn mined_block(version: u32, prev_hash: [u8; 32], bits: u32) -> Block {
+        let mut block = Block::new(version, prev_hash, Vec::new(), bits);
+        assert!(block.solve());
+        block
+    }
+
+    /// Block at `height` whose coinbase claims the subsidy; `tag` keeps coinbases apart
+    fn block_with_coinbase(prev_hash: [u8; 32], height: u64, tag: u8, mut transactions: Vec<Transaction>) -> Block {
+        let coinbase = Transaction::new(
+            1,
+            vec![TransactionInput::new([0u8; 32], 0xffffffff, vec![tag], 0xffffffff)],
+            vec![TransactionOutput::new(consensus::block_subsidy(height), vec![tag])],
+            0,
+        );
+        transactions.insert(0, coinbase);
+        let mut block = Block::new(1, prev_hash, transactions, REGTEST_POW_LIMIT_BITS);
+        assert!(block.solve());
+        block
+    }
+
     #[tokio::test]
     async fn test_chain_reorganization() -> Result<(), StorageError> {
         let temp_dir = tempdir().unwrap();
         let db = Arc::new(BlockchainDB::new(temp_dir.path())?);
-        let mut chain_state = ChainState::new(db)?;
+        let mut chain_state = regtest_chain_state(db)?;
 
         // Create a genesis block with a known hash
-        let genesis = Block::new(1, [0u8; 32], Vec::new(), u32::MAX);
-        chain_state.store_block(genesis.clone())?;
+        let genesis = mined_block(1, [0u8; 32], REGTEST_POW_LIMIT_BITS);
+        chain_state.store_block(genesis.clone(), 1)?;
         
         // Update initial chain state with the genesis block
         chain_state.current_height = 1;
         chain_state.best_block_hash = genesis.hash();
         
-        // First fork with higher difficulty (lower target = higher difficulty)
-        let fork_block = Block::new(1, genesis.hash(), Vec::new(), u32::MAX / 2);
+        // Extend the chain; regtest keeps every block at the genesis target
+        let fork_block = mined_block(1, genesis.hash(), REGTEST_POW_LIMIT_BITS);
         
         // Process the fork block and check that it becomes the new best block
         let reorg_successful = chain_state.process_block(fork_block.clone()).await?;
@@ -882,9 +1150,10 @@ mod tests {
                 (deep_fork.height() + 1) as u32,
                 prev_hash,
                 Vec::new(),
-                u32::MAX / 2
+                HARDER_BITS
             );
         }
+        assert!(deep_fork.solve());
         
         // This new fork should be too deep to be accepted
         let reorg_failed = !chain_state.process_block(deep_fork).await?;
@@ -897,12 +1166,12 @@ mod tests {
     async fn test_fork_validation() -> Result<(), StorageError> {
         let temp_dir = tempdir().unwrap();
         let db = Arc::new(BlockchainDB::new(temp_dir.path())?);
-        let mut chain_state = ChainState::new(db)?;
+        let mut chain_state = regtest_chain_state(db)?;
 
-        let genesis = Block::new(1, [0u8; 32], Vec::new(), u32::MAX);
-        chain_state.store_block(genesis.clone())?;
+        let genesis = mined_block(1, [0u8; 32], REGTEST_POW_LIMIT_BITS);
+        chain_state.store_block(genesis.clone(), 1)?;
 
-        let valid_fork = Block::new(2, genesis.hash(), Vec::new(), u32::MAX / 2);
+        let valid_fork = mined_block(2, genesis.hash(), HARDER_BITS);
         assert!(chain_state.validate_block(&valid_fork).await?);
 
         let mut invalid_fork = genesis.clone();
@@ -911,7 +1180,7 @@ mod tests {
                 (invalid_fork.height() + 1) as u32,
                 invalid_fork.hash(),
                 Vec::new(),
-                u32::MAX / 2,
+                HARDER_BITS,
             );
         }
         assert!(!chain_state.validate_block(&invalid_fork).await?);
@@ -923,12 +1192,12 @@ mod tests {
     async fn test_total_difficulty() -> Result<(), StorageError> {
         let temp_dir = tempdir().unwrap();
         let db = Arc::new(BlockchainDB::new(temp_dir.path())?);
-        let mut chain_state = ChainState::new(db)?;
+        let mut chain_state = regtest_chain_state(db)?;
 
         assert_eq!(chain_state.get_total_difficulty(), 0);
 
         // Create and add a block
-        let genesis = Block::new(1, [0u8; 32], Vec::new(), u32::MAX);
+        let genesis = mined_block(1, [0u8; 32], REGTEST_POW_LIMIT_BITS);
         chain_state.process_block(genesis.clone()).await?;
 
         // Total difficulty should be increased
@@ -936,4 +1205,74 @@ mod tests {
 
         Ok(())
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn test_median_time_past_spans_recent_blocks() -> Result<(), StorageError> {
+        let temp_dir = tempdir().unwrap();
+        let db = Arc::new(BlockchainDB::new(temp_dir.path())?);
+        let mut chain_state = regtest_chain_state(db)?;
+
+        let mut prev_hash = [0u8; 32];
+        for timestamp in [1_000, 2_000, 5_000] {
+            let mut header = BlockHeader::new(1, prev_hash, Block::calculate_merkle_root(&[]), REGTEST_POW_LIMIT_BITS);
+            header.set_timestamp(timestamp);
+            assert!(header.solve());
+            let block = Block::from_header(header, Vec::new());
+            prev_hash = block.hash();
+            assert!(chain_state.process_block(block).await?);
+        }
+
+        // The tip alone would give 5000
+        assert_eq!(chain_state.median_time_past()?, 2_000);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_reorg_checks_side_branch_at_connect() -> Result<(), StorageError> {
+        let temp_dir = tempdir().unwrap();
+        let db = Arc::new(BlockchainDB::new(temp_dir.path())?);
+        let mut chain_state = regtest_chain_state(db)?;
+
+        let genesis = mined_block(1, [0u8; 32], REGTEST_POW_LIMIT_BITS);
+        chain_state.store_block(genesis.clone(), 1)?;
+        chain_state.current_height = 1;
+        chain_state.best_block_hash = genesis.hash();
+
+        let main = block_with_coinbase(genesis.hash(), 2, 1, Vec::new());
+        assert!(chain_state.process_block(main.clone()).await?);
+        let main_coinbase = main.transactions()[0].hash();
+        assert_eq!(chain_state.coinbase_height(&main_coinbase)?, Some(2));
+
+        // Same work as the tip and seen later, so it stays a side branch
+        let side = block_with_coinbase(genesis.hash(), 2, 2, Vec::new());
+        assert!(!chain_state.process_block(side.clone()).await?);
+        assert_eq!(chain_state.block_height(&side.hash())?, Some(2));
+
+        // Spending an output that exists on neither branch is only caught when the
+        // heavier branch is connected, and the old chain must come back
+        let bad_spend = Transaction::new(
+            1,
+            vec![TransactionInput::new([7u8; 32], 0, Vec::new(), 0xffffffff)],
+            vec![TransactionOutput::new(1, Vec::new())],
+            0,
+        );
+        let invalid = block_with_coinbase(side.hash(), 3, 3, vec![bad_spend]);
+        assert!(matches!(
+            chain_state.process_block(invalid.clone()).await,
+            Err(StorageError::InvalidBlock)
+        ));
+        assert_eq!(chain_state.get_best_block_hash(), main.hash());
+        assert_eq!(chain_state.get_height(), 2);
+        assert_eq!(chain_state.coinbase_height(&main_coinbase)?, Some(2));
+
+        let valid = block_with_coinbase(side.hash(), 3, 4, Vec::new());
+        assert!(chain_state.process_block(valid.clone()).await?);
+        assert_eq!(chain_state.get_best_block_hash(), valid.hash());
+        assert_eq!(chain_state.get_height(), 3);
+        assert_eq!(chain_state.coinbase_height(&valid.transactions()[0].hash())?, Some(3));
+        assert_eq!(chain_state.coinbase_height(&main_coinbase)?, None);
+
+        Ok(())
+    }
+}
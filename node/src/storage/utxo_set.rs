@@ -8,6 +8,7 @@ use memmap2::{MmapMut, MmapOptions};
 use serde::{Serialize, Deserialize};
 use dashmap::DashMap;
 use btclib::types::transaction::{Transaction, TransactionOutput};
+use btclib::consensus::{is_coinbase_mature, COINBASE_MATURITY};
 use tracing::{debug, info, warn, error};
 
 use crate::storage::StorageError;
@@ -280,6 +281,24 @@ impl UtxoSet {
         self.utxos.get(outpoint).map(|utxo| utxo.clone())
     }
     
+    /// Get a UTXO that may be spent by a transaction in a block at `spend_height`
+    ///
+    /// Coinbase outputs are only returned once they have `COINBASE_MATURITY` confirmations.
+    pub fn get_spendable(&self, outpoint: &OutPoint, spend_height: u64) -> Result<UnspentOutput, StorageError> {
+        let utxo = self.get(outpoint).ok_or_else(|| {
+            StorageError::KeyNotFound(format!("{}:{}", hex::encode(outpoint.txid), outpoint.vout))
+        })?;
+
+        if utxo.is_coinbase && !is_coinbase_mature(utxo.height, spend_height) {
+            return Err(StorageError::ImmatureCoinbase {
+                created_height: utxo.height,
+                mature_height: utxo.height + COINBASE_MATURITY,
+            });
+        }
+
+        Ok(utxo)
+    }
+    
     /// Add a new UTXO
     pub fn add(&self, outpoint: OutPoint, output: UnspentOutput) {
         self.utxos.insert(outpoint, output);
@@ -299,6 +318,19 @@ impl UtxoSet {
     pub fn process_transaction(&self, tx: &Transaction, height: u64, is_coinbase: bool) -> Result<(), StorageError> {
         // Remove spent inputs (except for coinbase which has no real inputs)
         if !is_coinbase {
+            // Check every input first so an immature coinbase spend leaves the set untouched
+            for input in tx.inputs() {
+                let outpoint = OutPoint::new(input.prev_tx_hash(), input.prev_output_index());
+                if let Some(utxo) = self.get(&outpoint) {
+                    if utxo.is_coinbase && !is_coinbase_mature(utxo.height, height) {
+                        return Err(StorageError::ImmatureCoinbase {
+                            created_height: utxo.height,
+                            mature_height: utxo.height + COINBASE_MATURITY,
+                        });
+                    }
+                }
+            }
+
             for input in tx.inputs() {
                 let outpoint = OutPoint::new(input.prev_tx_hash(), input.prev_output_index());
                 self.remove(&outpoint);
@@ -534,6 +566,37 @@ mod tests {
         assert_eq!(utxo.unwrap().value, 1000);
     }
     
+    #[test]
+    fn test_coinbase_maturity() {
+        let temp_dir = tempdir().unwrap();
+        let db_path = temp_dir.path().join("utxo.db");
+        let utxo_set = UtxoSet::new(&db_path).unwrap();
+        
+        let coinbase = create_test_transaction(5000);
+        utxo_set.process_transaction(&coinbase, 10, true).unwrap();
+        let outpoint = OutPoint::new(coinbase.hash(), 0);
+        
+        // Coinbase cannot be spent before it matures
+        assert!(matches!(
+            utxo_set.get_spendable(&outpoint, 10 + COINBASE_MATURITY - 1),
+            Err(StorageError::ImmatureCoinbase { .. })
+        ));
+        
+        let spend = Transaction::new(
+            1,
+            vec![btclib::types::transaction::TransactionInput::new(coinbase.hash(), 0, vec![], 0)],
+            vec![TransactionOutput::new(4000, vec![5, 6, 7, 8])],
+            0,
+        );
+        assert!(utxo_set.process_transaction(&spend, 50, false).is_err());
+        assert!(utxo_set.get(&outpoint).is_some());
+        
+        // Once mature it is spendable
+        assert!(utxo_set.get_spendable(&outpoint, 10 + COINBASE_MATURITY).is_ok());
+        utxo_set.process_transaction(&spend, 10 + COINBASE_MATURITY, false).unwrap();
+        assert!(utxo_set.get(&outpoint).is_none());
+    }
+    
     #[test]
     fn test_utxo_commitment() {
         let temp_dir = tempdir().unwrap();
@@ -0,0 +1,109 @@
+//! Stratum mining server
+//!
+//! Serves block templates built from the mempool to external miners over
+//! Stratum V1 (JSON-RPC) and Stratum V2 (binary standard channels) on a
+//! single port. Shares are checked against a per-connection variable
+//! difficulty and any share meeting the network target is assembled into a
+//! full block and handed to the node.
+
+pub mod server;
+pub mod vardiff;
+
+pub use self::server::{StratumServer, ShareError};
+pub use self::vardiff::{VardiffConfig, VardiffState};
+pub use crate::mining::ChainTip;
+
+use crate::mempool::TransactionPool;
+use crate::mining::BlockTemplateService;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tracing::warn;
+
+/// Configuration for the Stratum server
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StratumConfig {
+    /// Whether the Stratum server is started with the node
+    pub enabled: bool,
+    /// Bind address for the Stratum server
+    pub bind_address: String,
+    /// Port for the Stratum server
+    pub port: u16,
+    /// Script paid by the coinbase of blocks found through the server
+    pub payout_script: Vec<u8>,
+    /// Password required from workers (any password is accepted if unset)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+    /// Share difficulty assigned to new connections
+    pub initial_difficulty: f64,
+    /// Lowest share difficulty vardiff may assign
+    pub min_difficulty: f64,
+    /// Highest share difficulty vardiff may assign
+    pub max_difficulty: f64,
+    /// Desired seconds between shares per connection
+    pub target_share_interval: u64,
+    /// Seconds between vardiff adjustments
+    pub retarget_interval: u64,
+    /// Seconds between job refreshes with updated mempool contents
+    pub job_refresh_interval: u64,
+}
+
+impl Default for StratumConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            bind_address: "127.0.0.1".to_string(),
+            port: miner::stratum::DEFAULT_STRATUM_PORT,
+            payout_script: Vec::new(),
+            password: None,
+            initial_difficulty: 16.0,
+            min_difficulty: 1.0,
+            max_difficulty: 1_000_000_000.0,
+            target_share_interval: 10,
+            retarget_interval: 60,
+            job_refresh_interval: 30,
+        }
+    }
+}
+
+impl StratumConfig {
+    /// Vardiff settings derived from this configuration
+    pub fn vardiff(&self) -> VardiffConfig {
+        VardiffConfig {
+            initial_difficulty: self.initial_difficulty,
+            min_difficulty: self.min_difficulty,
+            max_difficulty: self.max_difficulty,
+            target_share_interval: Duration::from_secs(self.target_share_interval.max(1)),
+            retarget_interval: Duration::from_secs(self.retarget_interval.max(1)),
+        }
+    }
+}
+
+/// Start the Stratum server if `config` enables it
+///
+/// Jobs follow the tip of `templates`, and blocks found by miners are
+/// submitted through it like `submitblock` calls.
+pub async fn spawn(
+    config: &StratumConfig,
+    templates: Arc<BlockTemplateService>,
+    mempool: Arc<TransactionPool>,
+) -> std::io::Result<()> {
+    if !config.enabled {
+        return Ok(());
+    }
+
+    let (block_tx, mut block_rx) = mpsc::channel(16);
+    let server = StratumServer::new(config.clone(), mempool, templates.subscribe_tip(), block_tx);
+    server.start().await?;
+
+    tokio::spawn(async move {
+        while let Some(block) = block_rx.recv().await {
+            let block_hash = block.hash();
+            if let Err(e) = templates.submit_block(block).await {
+                warn!("Block {} found by a Stratum miner was rejected: {}", hex::encode(block_hash), e);
+            }
+        }
+    });
+    Ok(())
+}
@@ -0,0 +1,613 @@
+use super::{ChainTip, StratumConfig, VardiffState};
+use btclib::types::block::Block;
+use miner::mining::{BlockTemplate, MempoolInterface};
+use miner::stratum::job::{difficulty_to_target, hash_value, MiningJob};
+use miner::stratum::v1::{self, StratumMessage, SubmitParams};
+use miner::stratum::v2::{self, Message};
+use miner::stratum::{StratumError, EXTRANONCE2_SIZE};
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use thiserror::Error;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{broadcast, mpsc, watch};
+use tokio::task::JoinHandle;
+use tracing::{debug, error, info, warn};
+
+/// Number of recent jobs kept for late share submissions
+const MAX_TRACKED_JOBS: usize = 16;
+
+/// How far in the future a share's ntime may be
+const MAX_NTIME_DRIFT: u64 = 2 * 60 * 60;
+
+/// Reasons a share is rejected
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum ShareError {
+    #[error("Job not found")]
+    JobNotFound,
+    #[error("Invalid extranonce2 size")]
+    InvalidExtranonce,
+    #[error("ntime out of range")]
+    NtimeOutOfRange,
+    #[error("Duplicate share")]
+    Duplicate,
+    #[error("Low difficulty share")]
+    LowDifficulty,
+}
+
+impl ShareError {
+    /// Stratum V1 error code
+    pub fn v1_code(&self) -> i32 {
+        match self {
+            ShareError::JobNotFound => v1::ERROR_JOB_NOT_FOUND,
+            ShareError::Duplicate => v1::ERROR_DUPLICATE_SHARE,
+            ShareError::LowDifficulty => v1::ERROR_LOW_DIFFICULTY,
+            ShareError::InvalidExtranonce | ShareError::NtimeOutOfRange => v1::ERROR_OTHER,
+        }
+    }
+
+    /// Stratum V2 error code string
+    pub fn v2_code(&self) -> &'static str {
+        match self {
+            ShareError::JobNotFound => "invalid-job-id",
+            ShareError::InvalidExtranonce => "invalid-extranonce",
+            ShareError::NtimeOutOfRange => "ntime-out-of-range",
+            ShareError::Duplicate => "duplicate-share",
+            ShareError::LowDifficulty => "difficulty-too-low",
+        }
+    }
+}
+
+/// A job together with the template it was cut from
+struct JobEntry {
+    job: MiningJob,
+    template: BlockTemplate,
+}
+
+/// A share as submitted by a miner
+struct Share {
+    job_id: u32,
+    extranonce1: Vec<u8>,
+    extranonce2: Vec<u8>,
+    ntime: u64,
+    nonce: u32,
+}
+
+/// State shared between the job manager and all connections
+struct Shared {
+    config: StratumConfig,
+    jobs: RwLock<HashMap<u32, Arc<JobEntry>>>,
+    current_job: RwLock<Option<Arc<JobEntry>>>,
+    submitted: Mutex<HashSet<(u32, Vec<u8>, u64, u32)>>,
+    job_tx: broadcast::Sender<Arc<JobEntry>>,
+    block_tx: mpsc::Sender<Block>,
+    next_extranonce1: AtomicU32,
+    next_channel_id: AtomicU32,
+}
+
+impl Shared {
+    fn current_job(&self) -> Option<Arc<JobEntry>> {
+        self.current_job.read().unwrap().clone()
+    }
+
+    fn check_password(&self, password: &str) -> bool {
+        match &self.config.password {
+            Some(expected) => expected == password,
+            None => true,
+        }
+    }
+
+    fn publish_job(&self, entry: Arc<JobEntry>) {
+        let job_id = entry.job.job_id;
+        {
+            let mut jobs = self.jobs.write().unwrap();
+            if entry.job.clean {
+                jobs.clear();
+                self.submitted.lock().unwrap().clear();
+            }
+            jobs.insert(job_id, entry.clone());
+            if jobs.len() > MAX_TRACKED_JOBS {
+                let oldest = jobs.keys().min().copied();
+                if let Some(oldest) = oldest {
+                    jobs.remove(&oldest);
+                }
+            }
+        }
+        *self.current_job.write().unwrap() = Some(entry.clone());
+        // No receivers just means no miners are connected
+        let _ = self.job_tx.send(entry);
+    }
+
+    /// Check a share and return the block it completes, if any
+    fn validate_share(&self, share: &Share, share_target: u32) -> Result<Option<Block>, ShareError> {
+        let entry = self
+            .jobs
+            .read()
+            .unwrap()
+            .get(&share.job_id)
+            .cloned()
+            .ok_or(ShareError::JobNotFound)?;
+
+        if share.extranonce2.len() != EXTRANONCE2_SIZE {
+            return Err(ShareError::InvalidExtranonce);
+        }
+        if share.ntime < entry.job.ntime || share.ntime > now() + MAX_NTIME_DRIFT {
+            return Err(ShareError::NtimeOutOfRange);
+        }
+
+        let merkle_root = entry.job.merkle_root(&share.extranonce1, &share.extranonce2);
+        let header = entry.job.header(merkle_root, share.ntime, share.nonce);
+        if hash_value(&header.hash()) > share_target {
+            return Err(ShareError::LowDifficulty);
+        }
+
+        let key = (share.job_id, [share.extranonce1.as_slice(), share.extranonce2.as_slice()].concat(), share.ntime, share.nonce);
+        if !self.submitted.lock().unwrap().insert(key) {
+            return Err(ShareError::Duplicate);
+        }
+
+        if !header.meets_target() {
+            return Ok(None);
+        }
+
+        let mut extranonce = share.extranonce1.clone();
+        extranonce.extend_from_slice(&share.extranonce2);
+        let block = entry.template.assemble_block(
+            entry.template.coinbase_with_extranonce(&extranonce),
+            share.ntime,
+            share.nonce,
+        );
+
+        if block.hash() != header.hash() || !block.validate() {
+            error!("Share for job {} met the network target but assembled an invalid block", share.job_id);
+            return Ok(None);
+        }
+
+        Ok(Some(block))
+    }
+
+    async fn submit_block(&self, block: Block) {
+        info!("Stratum share found block {}", hex::encode(block.hash()));
+        if self.block_tx.send(block).await.is_err() {
+            warn!("Block receiver dropped; found block discarded");
+        }
+    }
+}
+
+/// Stratum V1/V2 server distributing jobs built from the node's mempool
+pub struct StratumServer {
+    config: StratumConfig,
+    mempool: Arc<dyn MempoolInterface + Send + Sync>,
+    tip_rx: watch::Receiver<ChainTip>,
+    block_tx: mpsc::Sender<Block>,
+}
+
+impl StratumServer {
+    /// Create a new server
+    ///
+    /// `tip_rx` carries the tip to build on; blocks found by miners are sent on `block_tx`.
+    pub fn new(
+        config: StratumConfig,
+        mempool: Arc<dyn MempoolInterface + Send + Sync>,
+        tip_rx: watch::Receiver<ChainTip>,
+        block_tx: mpsc::Sender<Block>,
+    ) -> Self {
+        Self {
+            config,
+            mempool,
+            tip_rx,
+            block_tx,
+        }
+    }
+
+    /// Bind the listener and start serving
+    ///
+    /// Returns the bound address and the handle of the accept loop.
+    pub async fn start(self) -> std::io::Result<(SocketAddr, JoinHandle<()>)> {
+        let listener = TcpListener::bind((self.config.bind_address.as_str(), self.config.port)).await?;
+        let local_addr = listener.local_addr()?;
+        info!("Stratum server listening on {}", local_addr);
+
+        let (job_tx, _) = broadcast::channel(16);
+        let shared = Arc::new(Shared {
+            config: self.config.clone(),
+            jobs: RwLock::new(HashMap::new()),
+            current_job: RwLock::new(None),
+            submitted: Mutex::new(HashSet::new()),
+            job_tx,
+            block_tx: self.block_tx,
+            next_extranonce1: AtomicU32::new(1),
+            next_channel_id: AtomicU32::new(1),
+        });
+
+        // Have a job ready before the first miner connects
+        let tip = self.tip_rx.borrow().clone();
+        let entry = build_job(&shared.config, &*self.mempool, &tip, 0, true).await;
+        shared.publish_job(Arc::new(entry));
+
+        let job_manager = tokio::spawn(run_job_manager(shared.clone(), self.mempool, self.tip_rx, 0));
+
+        let handle = tokio::spawn(async move {
+            loop {
+                match listener.accept().await {
+                    Ok((stream, peer)) => {
+                        let shared = shared.clone();
+                        tokio::spawn(async move {
+                            if let Err(e) = handle_connection(shared, stream, peer).await {
+                                debug!("Stratum connection {} closed: {}", peer, e);
+                            }
+                        });
+                    }
+                    Err(e) => {
+                        error!("Stratum accept error: {}", e);
+                        break;
+                    }
+                }
+            }
+            job_manager.abort();
+        });
+
+        Ok((local_addr, handle))
+    }
+}
+
+async fn build_job(
+    config: &StratumConfig,
+    mempool: &(dyn MempoolInterface + Send + Sync),
+    tip: &ChainTip,
+    job_id: u32,
+    clean: bool,
+) -> JobEntry {
+    let template = BlockTemplate::new(
+        tip.version,
+        tip.prev_block_hash,
+        tip.height,
+        tip.target,
+        config.payout_script.clone(),
+        mempool,
+    )
+    .await;
+    let job = MiningJob::from_template(job_id, &template, now(), clean);
+    JobEntry { job, template }
+}
+
+/// Rebuild jobs on tip changes (clean) and periodically for new mempool contents
+async fn run_job_manager(
+    shared: Arc<Shared>,
+    mempool: Arc<dyn MempoolInterface + Send + Sync>,
+    mut tip_rx: watch::Receiver<ChainTip>,
+    last_job_id: u32,
+) {
+    let refresh = Duration::from_secs(shared.config.job_refresh_interval.max(1));
+    let mut interval = tokio::time::interval_at(tokio::time::Instant::now() + refresh, refresh);
+    let mut job_id = last_job_id;
+
+    loop {
+        let clean = tokio::select! {
+            changed = tip_rx.changed() => {
+                if changed.is_err() {
+                    debug!("Chain tip sender dropped; stopping job manager");
+                    break;
+                }
+                true
+            }
+            _ = interval.tick() => false,
+        };
+
+        job_id = job_id.wrapping_add(1);
+        let tip = tip_rx.borrow().clone();
+        let entry = build_job(&shared.config, &*mempool, &tip, job_id, clean).await;
+        debug!("Publishing job {} at height {} (clean: {})", job_id, tip.height, clean);
+        shared.publish_job(Arc::new(entry));
+    }
+}
+
+async fn handle_connection(shared: Arc<Shared>, stream: TcpStream, peer: SocketAddr) -> Result<(), StratumError> {
+    stream.set_nodelay(true)?;
+    let extranonce1 = shared.next_extranonce1.fetch_add(1, Ordering::Relaxed).to_be_bytes().to_vec();
+
+    // V1 is JSON text, V2 frames start with a binary extension type
+    let mut first = [0u8; 1];
+    if stream.peek(&mut first).await? == 0 {
+        return Err(StratumError::ConnectionClosed);
+    }
+
+    if first[0] == b'{' {
+        debug!("Stratum V1 connection from {}", peer);
+        handle_v1(shared, stream, extranonce1).await
+    } else {
+        debug!("Stratum V2 connection from {}", peer);
+        handle_v2(shared, stream, extranonce1).await
+    }
+}
+
+async fn handle_v1(shared: Arc<Shared>, stream: TcpStream, extranonce1: Vec<u8>) -> Result<(), StratumError> {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+    let mut job_rx = shared.job_tx.subscribe();
+
+    let mut subscribed = false;
+    let mut authorized = false;
+    let mut vardiff = VardiffState::new(shared.config.vardiff(), Instant::now());
+    // Shares for jobs sent before a difficulty change are checked against the previous target
+    let mut previous_target = difficulty_to_target(vardiff.difficulty());
+
+    loop {
+        let mut outgoing: Vec<StratumMessage> = Vec::new();
+
+        tokio::select! {
+            line = lines.next_line() => {
+                let line = match line? {
+                    Some(line) => line,
+                    None => return Ok(()),
+                };
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let request = StratumMessage::from_line(&line)?;
+                let params = request.params.clone().unwrap_or_default();
+
+                match request.method.as_deref() {
+                    Some("mining.subscribe") => {
+                        subscribed = true;
+                        let subscription_id = hex::encode(&extranonce1);
+                        outgoing.push(StratumMessage::response(request.id, json!([
+                            [["mining.set_difficulty", subscription_id], ["mining.notify", subscription_id]],
+                            hex::encode(&extranonce1),
+                            EXTRANONCE2_SIZE,
+                        ])));
+                        outgoing.push(StratumMessage::notification("mining.set_difficulty", vec![json!(vardiff.difficulty())]));
+                        if let Some(entry) = shared.current_job() {
+                            outgoing.push(StratumMessage::notification("mining.notify", v1::notify_params(&entry.job)?));
+                        }
+                    }
+                    Some("mining.authorize") => {
+                        let password = params.get(1).and_then(Value::as_str).unwrap_or_default();
+                        authorized = shared.check_password(password);
+                        if !authorized {
+                            warn!("Rejected Stratum worker {:?}", params.first());
+                        }
+                        outgoing.push(StratumMessage::response(request.id, json!(authorized)));
+                    }
+                    Some("mining.submit") => {
+                        let result = if !subscribed {
+                            Err((v1::ERROR_NOT_SUBSCRIBED, "Not subscribed".to_string()))
+                        } else if !authorized {
+                            Err((v1::ERROR_UNAUTHORIZED, "Unauthorized worker".to_string()))
+                        } else {
+                            match SubmitParams::from_params(&params) {
+                                Ok(submit) => {
+                                    let share = Share {
+                                        job_id: submit.job_id,
+                                        extranonce1: extranonce1.clone(),
+                                        extranonce2: submit.extranonce2,
+                                        ntime: submit.ntime,
+                                        nonce: submit.nonce,
+                                    };
+                                    let share_target = difficulty_to_target(vardiff.difficulty()).max(previous_target);
+                                    match shared.validate_share(&share, share_target) {
+                                        Ok(block) => {
+                                            vardiff.record_share();
+                                            if let Some(block) = block {
+                                                shared.submit_block(block).await;
+                                            }
+                                            Ok(())
+                                        }
+                                        Err(e) => Err((e.v1_code(), e.to_string())),
+                                    }
+                                }
+                                Err(e) => Err((v1::ERROR_OTHER, e.to_string())),
+                            }
+                        };
+
+                        outgoing.push(match result {
+                            Ok(()) => StratumMessage::response(request.id, json!(true)),
+                            Err((code, message)) => StratumMessage::error_response(request.id, code, &message),
+                        });
+
+                        if let Some(difficulty) = vardiff.maybe_retarget(Instant::now()) {
+                            outgoing.push(StratumMessage::notification("mining.set_difficulty", vec![json!(difficulty)]));
+                        }
+                    }
+                    Some("mining.extranonce.subscribe") => {
+                        outgoing.push(StratumMessage::response(request.id, json!(false)));
+                    }
+                    _ => {
+                        outgoing.push(StratumMessage::error_response(request.id, v1::ERROR_OTHER, "Unknown method"));
+                    }
+                }
+            }
+            job = job_rx.recv(), if subscribed => {
+                let entry = match job {
+                    Ok(entry) => entry,
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => return Ok(()),
+                };
+
+                // Idle miners get their difficulty lowered with each new job
+                if let Some(difficulty) = vardiff.maybe_retarget(Instant::now()) {
+                    outgoing.push(StratumMessage::notification("mining.set_difficulty", vec![json!(difficulty)]));
+                }
+                previous_target = difficulty_to_target(vardiff.difficulty());
+                outgoing.push(StratumMessage::notification("mining.notify", v1::notify_params(&entry.job)?));
+            }
+        }
+
+        for message in outgoing {
+            writer.write_all(message.to_line()?.as_bytes()).await?;
+        }
+    }
+}
+
+async fn handle_v2(shared: Arc<Shared>, stream: TcpStream, extranonce1: Vec<u8>) -> Result<(), StratumError> {
+    let (mut reader, mut writer) = stream.into_split();
+    let mut job_rx = shared.job_tx.subscribe();
+
+    // Frame reads are not cancellation safe, so decode on a separate task
+    let (message_tx, mut message_rx) = mpsc::channel(32);
+    let reader_handle = tokio::spawn(async move {
+        loop {
+            let message = v2::read_message(&mut reader).await;
+            let closed = message.is_err();
+            if message_tx.send(message).await.is_err() || closed {
+                break;
+            }
+        }
+    });
+
+    // Standard channels have no miner-rolled extranonce
+    let extranonce2 = vec![0u8; EXTRANONCE2_SIZE];
+    let mut channel_id: Option<u32> = None;
+    let mut vardiff = VardiffState::new(shared.config.vardiff(), Instant::now());
+    let mut previous_target = difficulty_to_target(vardiff.difficulty());
+    let mut accepted_shares = 0u32;
+
+    let new_mining_job = |channel_id: u32, job: &MiningJob| {
+        Message::NewMiningJob(v2::NewMiningJob {
+            channel_id,
+            job_id: job.job_id,
+            version: job.version,
+            prev_block_hash: job.prev_block_hash,
+            merkle_root: job.merkle_root(&extranonce1, &extranonce2),
+            nbits: job.target,
+            min_ntime: job.ntime,
+            clean: job.clean,
+        })
+    };
+
+    let result = loop {
+        let mut outgoing: Vec<Message> = Vec::new();
+
+        tokio::select! {
+            message = message_rx.recv() => {
+                let message = match message {
+                    Some(Ok(message)) => message,
+                    Some(Err(StratumError::ConnectionClosed)) | None => break Ok(()),
+                    Some(Err(e)) => break Err(e),
+                };
+
+                match message {
+                    Message::SetupConnection(setup) => {
+                        if (setup.min_version..=setup.max_version).contains(&v2::PROTOCOL_VERSION) {
+                            outgoing.push(Message::SetupConnectionSuccess(v2::SetupConnectionSuccess {
+                                used_version: v2::PROTOCOL_VERSION,
+                            }));
+                        } else {
+                            outgoing.push(Message::SetupConnectionError(v2::ErrorMessage {
+                                request_id: 0,
+                                error_code: "protocol-version-mismatch".to_string(),
+                            }));
+                        }
+                    }
+                    Message::OpenStandardMiningChannel(open) => {
+                        if !shared.check_password(&open.password) {
+                            warn!("Rejected Stratum V2 worker {}", open.user_identity);
+                            outgoing.push(Message::OpenMiningChannelError(v2::ErrorMessage {
+                                request_id: open.request_id,
+                                error_code: "unauthorized".to_string(),
+                            }));
+                        } else {
+                            let id = shared.next_channel_id.fetch_add(1, Ordering::Relaxed);
+                            channel_id = Some(id);
+                            outgoing.push(Message::OpenStandardMiningChannelSuccess(v2::OpenStandardMiningChannelSuccess {
+                                request_id: open.request_id,
+                                channel_id: id,
+                                target: difficulty_to_target(vardiff.difficulty()),
+                                extranonce_prefix: extranonce1.clone(),
+                            }));
+                            if let Some(entry) = shared.current_job() {
+                                outgoing.push(new_mining_job(id, &entry.job));
+                            }
+                        }
+                    }
+                    Message::SubmitSharesStandard(submit) if Some(submit.channel_id) == channel_id => {
+                        let share = Share {
+                            job_id: submit.job_id,
+                            extranonce1: extranonce1.clone(),
+                            extranonce2: extranonce2.clone(),
+                            ntime: submit.ntime,
+                            nonce: submit.nonce,
+                        };
+                        let share_target = difficulty_to_target(vardiff.difficulty()).max(previous_target);
+                        match shared.validate_share(&share, share_target) {
+                            Ok(block) => {
+                                vardiff.record_share();
+                                accepted_shares += 1;
+                                if let Some(block) = block {
+                                    shared.submit_block(block).await;
+                                }
+                                outgoing.push(Message::SubmitSharesSuccess(v2::SubmitSharesSuccess {
+                                    channel_id: submit.channel_id,
+                                    last_sequence_number: submit.sequence_number,
+                                    accepted_shares,
+                                }));
+                            }
+                            Err(e) => outgoing.push(Message::SubmitSharesError(v2::SubmitSharesError {
+                                channel_id: submit.channel_id,
+                                sequence_number: submit.sequence_number,
+                                error_code: e.v2_code().to_string(),
+                            })),
+                        }
+
+                        if let Some(difficulty) = vardiff.maybe_retarget(Instant::now()) {
+                            outgoing.push(Message::SetTarget(v2::SetTarget {
+                                channel_id: submit.channel_id,
+                                target: difficulty_to_target(difficulty),
+                            }));
+                        }
+                    }
+                    Message::SubmitSharesStandard(submit) => {
+                        outgoing.push(Message::SubmitSharesError(v2::SubmitSharesError {
+                            channel_id: submit.channel_id,
+                            sequence_number: submit.sequence_number,
+                            error_code: "invalid-channel-id".to_string(),
+                        }));
+                    }
+                    other => debug!("Ignoring unexpected Stratum V2 message {:?}", other),
+                }
+            }
+            job = job_rx.recv(), if channel_id.is_some() => {
+                let entry = match job {
+                    Ok(entry) => entry,
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => break Ok(()),
+                };
+                let id = channel_id.unwrap_or_default();
+
+                if let Some(difficulty) = vardiff.maybe_retarget(Instant::now()) {
+                    outgoing.push(Message::SetTarget(v2::SetTarget {
+                        channel_id: id,
+                        target: difficulty_to_target(difficulty),
+                    }));
+                }
+                previous_target = difficulty_to_target(vardiff.difficulty());
+                outgoing.push(new_mining_job(id, &entry.job));
+            }
+        }
+
+        let mut write_result = Ok(());
+        for message in &outgoing {
+            write_result = v2::write_message(&mut writer, message).await;
+            if write_result.is_err() {
+                break;
+            }
+        }
+        if let Err(e) = write_result {
+            break Err(e);
+        }
+    };
+
+    reader_handle.abort();
+    result
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or(Duration::from_secs(0))
+        .as_secs()
+}
@@ -0,0 +1,144 @@
+use std::time::{Duration, Instant};
+
+/// Largest factor a single retarget may move the difficulty by
+const MAX_ADJUSTMENT_FACTOR: f64 = 4.0;
+
+/// Relative change below which a retarget is skipped
+const MIN_ADJUSTMENT: f64 = 0.1;
+
+/// Variable difficulty settings
+#[derive(Debug, Clone)]
+pub struct VardiffConfig {
+    pub initial_difficulty: f64,
+    pub min_difficulty: f64,
+    pub max_difficulty: f64,
+    /// Desired time between shares
+    pub target_share_interval: Duration,
+    /// Time between adjustments
+    pub retarget_interval: Duration,
+}
+
+impl Default for VardiffConfig {
+    fn default() -> Self {
+        Self {
+            initial_difficulty: 16.0,
+            min_difficulty: 1.0,
+            max_difficulty: 1_000_000_000.0,
+            target_share_interval: Duration::from_secs(10),
+            retarget_interval: Duration::from_secs(60),
+        }
+    }
+}
+
+/// Per-connection share difficulty that tracks the miner's hash rate
+#[derive(Debug, Clone)]
+pub struct VardiffState {
+    config: VardiffConfig,
+    difficulty: f64,
+    shares_since_retarget: u32,
+    last_retarget: Instant,
+}
+
+impl VardiffState {
+    pub fn new(config: VardiffConfig, now: Instant) -> Self {
+        let difficulty = config
+            .initial_difficulty
+            .clamp(config.min_difficulty, config.max_difficulty);
+        Self {
+            config,
+            difficulty,
+            shares_since_retarget: 0,
+            last_retarget: now,
+        }
+    }
+
+    /// Current share difficulty
+    pub fn difficulty(&self) -> f64 {
+        self.difficulty
+    }
+
+    /// Record an accepted share
+    pub fn record_share(&mut self) {
+        self.shares_since_retarget += 1;
+    }
+
+    /// Adjust the difficulty if a retarget interval has passed
+    ///
+    /// Returns the new difficulty when it changed.
+    pub fn maybe_retarget(&mut self, now: Instant) -> Option<f64> {
+        let elapsed = now.saturating_duration_since(self.last_retarget);
+        if elapsed < self.config.retarget_interval {
+            return None;
+        }
+
+        let expected_shares = elapsed.as_secs_f64() / self.config.target_share_interval.as_secs_f64();
+        let factor = (self.shares_since_retarget as f64 / expected_shares)
+            .clamp(1.0 / MAX_ADJUSTMENT_FACTOR, MAX_ADJUSTMENT_FACTOR);
+        let new_difficulty = (self.difficulty * factor)
+            .clamp(self.config.min_difficulty, self.config.max_difficulty);
+
+        self.shares_since_retarget = 0;
+        self.last_retarget = now;
+
+        if (new_difficulty - self.difficulty).abs() / self.difficulty < MIN_ADJUSTMENT {
+            return None;
+        }
+
+        self.difficulty = new_difficulty;
+        Some(new_difficulty)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> VardiffConfig {
+        VardiffConfig {
+            initial_difficulty: 8.0,
+            min_difficulty: 1.0,
+            max_difficulty: 64.0,
+            target_share_interval: Duration::from_secs(10),
+            retarget_interval: Duration::from_secs(60),
+        }
+    }
+
+    #[test]
+    fn test_vardiff_adjustment() {
+        let start = Instant::now();
+        let mut state = VardiffState::new(config(), start);
+
+        // Nothing happens before the retarget interval
+        state.record_share();
+        assert_eq!(state.maybe_retarget(start + Duration::from_secs(30)), None);
+
+        // 12 shares in 60s against an expected 6 doubles the difficulty
+        for _ in 0..11 {
+            state.record_share();
+        }
+        assert_eq!(state.maybe_retarget(start + Duration::from_secs(60)), Some(16.0));
+
+        // A flood of shares is clamped to a 4x step and the configured maximum
+        for _ in 0..1000 {
+            state.record_share();
+        }
+        assert_eq!(state.maybe_retarget(start + Duration::from_secs(120)), Some(64.0));
+
+        // Silence drops the difficulty by at most 4x
+        assert_eq!(state.maybe_retarget(start + Duration::from_secs(180)), Some(16.0));
+
+        // On-target share rates leave the difficulty alone
+        for _ in 0..6 {
+            state.record_share();
+        }
+        assert_eq!(state.maybe_retarget(start + Duration::from_secs(240)), None);
+        assert_eq!(state.difficulty(), 16.0);
+    }
+
+    #[test]
+    fn test_vardiff_initial_clamped() {
+        let mut cfg = config();
+        cfg.initial_difficulty = 0.5;
+        assert_eq!(VardiffState::new(cfg, Instant::now()).difficulty(), 1.0);
+    }
+}
@@ -1,21 +1,33 @@
 use crate::storage::persistence::{ChainState, ForkChoiceReason};
 use crate::storage::database::BlockchainDB;
 use crate::network::sync::ChainSync;
+use btclib::consensus::{DifficultyParams, REGTEST_POW_LIMIT_BITS};
 use btclib::types::block::Block;
 use tempfile::tempdir;
 use std::sync::Arc;
 use std::time::Duration;
-use tokio::sync::mpsc;
+use tokio::sync::{mpsc, RwLock};
+
+/// Harder than the regtest limit, so each block carries more work
+const HARDER_BITS: u32 = 0x2000ffff;
+
+/// Build a block and grind its nonce until it meets its target
+fn mined_block(version: u32, prev_hash: [u8; 32], bits: u32) -> Block {
+    let mut block = Block::new(version, prev_hash, vec![], bits);
+    assert!(block.solve());
+    block
+}
 
 /// Create a test chain with a specific number of blocks
 async fn create_test_chain(block_count: u64) -> (Arc<BlockchainDB>, ChainState, Vec<Block>) {
     let temp_dir = tempdir().unwrap();
     let db = Arc::new(BlockchainDB::new(temp_dir.path()).unwrap());
     let mut chain_state = ChainState::new(db.clone()).unwrap();
+    chain_state.set_difficulty_params(DifficultyParams::regtest());
     
     // Create a genesis block with a known hash
-    let genesis = Block::new(1, [0u8; 32], vec![], u32::MAX);
-    chain_state.store_block(genesis.clone()).unwrap();
+    let genesis = mined_block(1, [0u8; 32], REGTEST_POW_LIMIT_BITS);
+    chain_state.store_block(genesis.clone(), 1).unwrap();
     
     // Update initial chain state with the genesis block
     chain_state.current_height = 1;
@@ -26,12 +38,7 @@ async fn create_test_chain(block_count: u64) -> (Arc<BlockchainDB>, ChainState,
     // Add blocks to the chain
     for i in 1..block_count {
         let prev_block = &blocks[i as usize - 1];
-        let new_block = Block::new(
-            (i + 1) as u32,
-            prev_block.hash(),
-            vec![],
-            u32::MAX
-        );
+        let new_block = mined_block((i + 1) as u32, prev_block.hash(), REGTEST_POW_LIMIT_BITS);
         
         // Store and process the block
         chain_state.process_block(new_block.clone()).await.unwrap();
@@ -48,30 +55,20 @@ async fn create_fork(chain_state: &mut ChainState, main_chain: &[Block], fork_he
     
     let mut fork_blocks = vec![];
     let target = if lower_difficulty {
-        u32::MAX  // Lower difficulty (easier target)
+        REGTEST_POW_LIMIT_BITS  // Lower difficulty (easier target)
     } else {
-        u32::MAX / 2  // Higher difficulty (harder target)
+        HARDER_BITS  // Higher difficulty (harder target)
     };
     
     // Create first block in fork with different target
-    let mut current = Block::new(
-        (fork_height + 1) as u32,
-        fork_base.hash(),
-        vec![],
-        target
-    );
+    let mut current = mined_block((fork_height + 1) as u32, fork_base.hash(), target);
     
     chain_state.process_block(current.clone()).await.unwrap();
     fork_blocks.push(current.clone());
     
     // Add more blocks to the fork
     for i in 1..blocks {
-        current = Block::new(
-            (fork_height + 1 + i) as u32,
-            current.hash(),
-            vec![],
-            target
-        );
+        current = mined_block((fork_height + 1 + i) as u32, current.hash(), target);
         
         chain_state.process_block(current.clone()).await.unwrap();
         fork_blocks.push(current.clone());
@@ -108,17 +105,11 @@ async fn test_fork_choice_reasons() {
     // The fork should be selected due to higher chain work
     assert_eq!(chain_state.get_best_block_hash(), fork_blocks.last().unwrap().hash());
     
-    // Create another fork with equal difficulty but different data to avoid hash collisions
+    // Create a sibling of the tip with equal difficulty but a different version to avoid hash collisions
     let current_height = chain_state.get_height();
-    let prev_hash = chain_state.get_best_block_hash();
+    let prev_hash = fork_blocks[fork_blocks.len() - 2].hash();
     
-    // First block has different transactions to avoid hash collision
-    let mut equal_work_block = Block::new(
-        (current_height + 1) as u32,
-        prev_hash,
-        vec![],  // Empty tx list to make different hash
-        u32::MAX / 2
-    );
+    let equal_work_block = mined_block((current_height + 100) as u32, prev_hash, HARDER_BITS);
     
     // This should not trigger a reorg since it's equal work but our fork was seen first
     chain_state.process_block(equal_work_block.clone()).await.unwrap();
@@ -126,13 +117,8 @@ async fn test_fork_choice_reasons() {
     // The original fork should still be selected
     assert_eq!(chain_state.get_best_block_hash(), fork_blocks.last().unwrap().hash());
     
-    // Now create a higher difficulty fork
-    let higher_work_block = Block::new(
-        (current_height + 1) as u32,
-        prev_hash,
-        vec![],
-        u32::MAX / 4  // Even higher difficulty
-    );
+    // Extending the sibling gives its branch more chain work
+    let higher_work_block = mined_block((current_height + 101) as u32, equal_work_block.hash(), HARDER_BITS);
     
     // This should trigger a reorg due to higher chain work
     chain_state.process_block(higher_work_block.clone()).await.unwrap();
@@ -164,16 +150,17 @@ async fn test_deep_reorganization_limits() {
 #[tokio::test]
 async fn test_fork_synchronization() {
     // Create a chain with 10 blocks
-    let (db, mut chain_state, main_chain) = create_test_chain(10).await;
+    let (db, chain_state, main_chain) = create_test_chain(10).await;
+    let chain_state = Arc::new(RwLock::new(chain_state));
     
     // Create a command channel for the ChainSync
     let (tx, _rx) = mpsc::channel(10);
     
     // Create ChainSync instance
-    let mut sync = ChainSync::new(chain_state.clone(), db.clone(), tx);
+    let mut sync = ChainSync::new(Arc::clone(&chain_state), db.clone(), tx);
     
     // Create a competing fork at height 5 with 6 blocks
-    let fork_blocks = create_fork(&mut chain_state, &main_chain, 5, 6, false).await;
+    let fork_blocks = create_fork(&mut *chain_state.write().await, &main_chain, 5, 6, false).await;
     
     // Process blocks from the fork with the sync system
     for block in &fork_blocks {
@@ -183,11 +170,11 @@ async fn test_fork_synchronization() {
     }
     
     // Check if sync detected and adjusted to the fork
-    let sync_stats = sync.get_stats();
+    let sync_stats = sync.get_stats().await;
     assert_eq!(sync_stats.current_height, 11); // Height 5 + 6 fork blocks
     
     // Check fork metrics
-    let fork_stats = sync.get_fork_stats();
+    let fork_stats = sync.get_fork_stats().await;
     assert!(fork_stats.get("active_forks").unwrap() > &0);
     assert!(fork_stats.get("max_fork_length").unwrap() > &0);
 }
@@ -195,42 +182,43 @@ async fn test_fork_synchronization() {
 #[tokio::test]
 async fn test_stale_tip_detection() {
     // Create a chain with 5 blocks
-    let (db, mut chain_state, _main_chain) = create_test_chain(5).await;
+    let (db, chain_state, _main_chain) = create_test_chain(5).await;
     
     // Create a command channel for the ChainSync
     let (tx, _rx) = mpsc::channel(10);
     
     // Create ChainSync instance
-    let sync = ChainSync::new(chain_state.clone(), db.clone(), tx);
+    let sync = ChainSync::new(Arc::new(RwLock::new(chain_state)), db.clone(), tx);
     
     // No new blocks created, so tip shouldn't be stale yet
-    assert!(!sync.check_for_stale_tip());
+    assert!(!sync.check_for_stale_tip().await);
     
     // However, we can manually check the time since last block
     // (this will be very small since we just created the chain)
-    let time_since_last = sync.time_since_last_block();
+    let time_since_last = sync.time_since_last_block().await;
     assert!(time_since_last < Duration::from_secs(10));
 }
 
 #[tokio::test]
 async fn test_metrics_tracking() {
     // Create a chain with 5 blocks
-    let (db, mut chain_state, main_chain) = create_test_chain(5).await;
+    let (db, chain_state, main_chain) = create_test_chain(5).await;
+    let chain_state = Arc::new(RwLock::new(chain_state));
     
     // Create a command channel for the ChainSync
     let (tx, _rx) = mpsc::channel(10);
     
     // Create ChainSync instance
-    let sync = ChainSync::new(chain_state.clone(), db.clone(), tx);
+    let sync = ChainSync::new(Arc::clone(&chain_state), db.clone(), tx);
     
     // Initial height
-    let initial_height = chain_state.get_height();
+    let initial_height = chain_state.read().await.get_height();
     
     // Create a competing fork
-    let _fork_blocks = create_fork(&mut chain_state, &main_chain, 3, 3, false).await;
+    let _fork_blocks = create_fork(&mut *chain_state.write().await, &main_chain, 3, 3, false).await;
     
     // Get metrics
-    let metrics = sync.get_fork_stats();
+    let metrics = sync.get_fork_stats().await;
     
     // Check various metrics
     assert!(metrics.contains_key("main_chain_height"));
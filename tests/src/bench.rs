@@ -2,6 +2,7 @@
 extern crate test;
 
 use test::Bencher;
+use btclib::consensus::REGTEST_POW_LIMIT_BITS;
 use btclib::types::{Block, Transaction, TransactionInput, TransactionOutput};
 use btclib::util::merkle::MerkleTree;
 use node::mempool::{TransactionPool, MempoolConfig};
@@ -124,6 +125,6 @@ fn create_benchmark_block() -> Block {
         1,
         [0u8; 32],
         transactions,
-        u32::MAX / 2,
+        REGTEST_POW_LIMIT_BITS,
     )
 }
\ No newline at end of file
@@ -5,10 +5,12 @@ use std::sync::Arc;
 use tokio::sync::mpsc;
 use tokio::time::timeout;
 use std::time::{Duration, Instant};
+use btclib::consensus::REGTEST_POW_LIMIT_BITS;
 use btclib::types::block::Block;
 use btclib::types::transaction::Transaction;
 use btclib::types::transaction::{TransactionInput, TransactionOutput};
 use tempfile::tempdir;
+use crate::test_chain::FundedChain;
 use tracing::{info, warn, error, debug};
 
 #[cfg(test)]
@@ -28,17 +30,13 @@ mod tests {
         let mempool_config = MempoolConfig::default();
         let mempool = Arc::new(TransactionPool::new(mempool_config));
         
-        // Create a simple test transaction
-        let tx = Transaction::new(
-            1,
-            vec![TransactionInput::new([0u8; 32], 0, vec![], 0xffffffff)],
-            vec![TransactionOutput::new(1_000_000, vec![1, 2, 3, 4, 5])],
-            0,
-        );
+        // Create a simple test transaction spending a funded output
+        let chain = FundedChain::new();
+        let tx = chain.spend([0u8; 32], 1_000_000, vec![1, 2, 3, 4, 5]);
         let tx_hash = tx.hash();
         
         // Add to mempool
-        mempool.add_transaction(tx.clone(), 1).unwrap();
+        mempool.accept_transaction(tx.clone(), &chain).unwrap();
         
         // Verify transaction is in mempool
         let tx_from_mempool = mempool.get_transaction(&tx_hash).unwrap();
@@ -92,7 +90,7 @@ mod tests {
             1, // version
             genesis_hash, // previous hash
             transactions, // transactions
-            u32::MAX / 10, // target difficulty
+            REGTEST_POW_LIMIT_BITS, // target difficulty
         );
         
         // Increment the nonce a few times to simulate mining
@@ -121,16 +119,18 @@ mod tests {
         let mempool_config = MempoolConfig::default();
         let mempool = Arc::new(TransactionPool::new(mempool_config));
         
-        // Benchmark transaction addition
-        let start_time = Instant::now();
+        // Create test transactions with varying inputs, signed before timing starts
         const NUM_TRANSACTIONS: usize = 100;
+        let chain = FundedChain::new();
+        let transactions: Vec<Transaction> = (0..NUM_TRANSACTIONS)
+            .map(|i| chain.spend([i as u8; 32], 1000 + i as u64, vec![1, 2, 3, 4]))
+            .collect();
         
-        for i in 0..NUM_TRANSACTIONS {
-            // Create a test transaction with varying inputs
-            let tx = create_test_transaction([i as u8; 32], 1000 + i as u64);
-            
+        // Benchmark transaction addition
+        let start_time = Instant::now();
+        for tx in transactions {
             // Add to mempool
-            if let Err(e) = mempool.add_transaction(tx, 2) {
+            if let Err(e) = mempool.accept_transaction(tx, &chain) {
                 debug!("Failed to add transaction: {}", e);
             }
         }
@@ -153,13 +153,4 @@ mod tests {
         info!("Sorted {} transactions in {:?}", 
             txs.len(), sort_duration);
     }
-    
-    fn create_test_transaction(prev_hash: [u8; 32], amount: u64) -> Transaction {
-        Transaction::new(
-            1, // version
-            vec![TransactionInput::new(prev_hash, 0, vec![], 0xffffffff)],
-            vec![TransactionOutput::new(amount, vec![1, 2, 3, 4])],
-            0, // locktime
-        )
-    }
 }
\ No newline at end of file
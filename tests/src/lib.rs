@@ -3,4 +3,10 @@ pub mod network_tests;
 #[cfg(test)]
 pub mod integration_tests;
 #[cfg(test)]
-pub mod performance_tests;
\ No newline at end of file
+pub mod performance_tests;
+#[cfg(test)]
+pub mod stratum_tests;
+#[cfg(test)]
+pub mod wallet_tests;
+#[cfg(test)]
+pub mod test_chain;
@@ -1,4 +1,5 @@
 use node::network::{P2PNetwork, NetworkCommand, NetworkEvent};
+use btclib::consensus::REGTEST_POW_LIMIT_BITS;
 use btclib::types::block::Block;
 use tokio::sync::mpsc;
 use tokio::time::Duration;
@@ -33,7 +34,7 @@ mod tests {
         
         // Create a test block
         let prev_hash = chain_state.get_best_block_hash();
-        let block = Block::new(1, prev_hash, Vec::new(), u32::MAX);
+        let block = Block::new(1, prev_hash, Vec::new(), REGTEST_POW_LIMIT_BITS);
         
         // This is a simple smoke test that doesn't need to fully propagate
         assert!(block.hash() != [0u8; 32]);
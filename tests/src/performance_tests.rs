@@ -1,9 +1,10 @@
 use std::time::{Duration, Instant};
 use node::mempool::{TransactionPool, MempoolConfig};
 use node::storage::{BlockchainDB, ChainState};
-use btclib::types::transaction::{Transaction, TransactionInput, TransactionOutput};
+use btclib::types::transaction::Transaction;
 use std::sync::Arc;
 use tempfile::tempdir;
+use crate::test_chain::FundedChain;
 use tracing::info;
 
 #[cfg(test)]
@@ -21,16 +22,18 @@ mod tests {
         let mempool_config = MempoolConfig::default();
         let mempool = Arc::new(TransactionPool::new(mempool_config));
         
-        // Benchmark transaction addition
-        let start_time = Instant::now();
+        // Create test transactions, signed before timing starts
         const NUM_TRANSACTIONS: usize = 100;
+        let chain = FundedChain::new();
+        let transactions: Vec<Transaction> = (0..NUM_TRANSACTIONS)
+            .map(|i| chain.spend([i as u8; 32], 1000, vec![1, 2, 3, 4]))
+            .collect();
         
-        for i in 0..NUM_TRANSACTIONS {
-            // Create a test transaction
-            let tx = create_test_transaction([i as u8; 32], 1000);
-            
+        // Benchmark transaction addition
+        let start_time = Instant::now();
+        for tx in transactions {
             // Add to mempool
-            if let Err(e) = mempool.add_transaction(tx, 2) {
+            if let Err(e) = mempool.accept_transaction(tx, &chain) {
                 println!("Failed to add transaction: {}", e);
             }
         }
@@ -45,13 +48,4 @@ mod tests {
         let txs = mempool.get_sorted_transactions();
         assert!(txs.len() <= NUM_TRANSACTIONS);
     }
-    
-    fn create_test_transaction(prev_hash: [u8; 32], amount: u64) -> Transaction {
-        Transaction::new(
-            1, // version
-            vec![TransactionInput::new(prev_hash, 0, vec![], 0xffffffff)],
-            vec![TransactionOutput::new(amount, vec![1, 2, 3, 4])],
-            0, // locktime
-        )
-    }
 }
\ No newline at end of file
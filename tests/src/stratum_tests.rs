@@ -0,0 +1,167 @@
+use node::mempool::{TransactionPool, MempoolConfig};
+use node::stratum::{ChainTip, StratumConfig, StratumServer};
+use miner::stratum::{StratumClient, StratumClientConfig, StratumProtocol};
+use btclib::consensus::REGTEST_POW_LIMIT_BITS;
+use btclib::types::block::Block;
+use crate::test_chain::FundedChain;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use tokio::sync::{mpsc, watch};
+use tokio::task::JoinHandle;
+use tokio::time::timeout;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn start_server(password: Option<String>) -> (SocketAddr, JoinHandle<()>, watch::Sender<ChainTip>, mpsc::Receiver<Block>, [u8; 32]) {
+        let mempool = Arc::new(TransactionPool::new(MempoolConfig::default()));
+        let chain = FundedChain::new();
+        let tx = chain.spend([9u8; 32], 1_000_000, vec![1, 2, 3]);
+        let tx_hash = tx.hash();
+        mempool.accept_transaction(tx, &chain).unwrap();
+
+        let config = StratumConfig {
+            enabled: true,
+            bind_address: "127.0.0.1".to_string(),
+            port: 0,
+            payout_script: vec![0xaa; 20],
+            password,
+            initial_difficulty: 1.0,
+            min_difficulty: 1.0,
+            ..StratumConfig::default()
+        };
+
+        // At the regtest limit roughly every other share is a valid block
+        let (tip_tx, tip_rx) = watch::channel(ChainTip {
+            version: 1,
+            prev_block_hash: [7u8; 32],
+            height: 101,
+            target: REGTEST_POW_LIMIT_BITS,
+        });
+        let (block_tx, block_rx) = mpsc::channel(16);
+
+        let server = StratumServer::new(config, mempool, tip_rx, block_tx);
+        let (addr, handle) = server.start().await.unwrap();
+        (addr, handle, tip_tx, block_rx, tx_hash)
+    }
+
+    async fn mine_block_with(protocol: StratumProtocol) {
+        let (addr, server_handle, _tip_tx, mut block_rx, tx_hash) = start_server(Some("secret".to_string())).await;
+
+        let client = Arc::new(StratumClient::new(StratumClientConfig {
+            address: addr.to_string(),
+            worker: "rig1".to_string(),
+            password: "secret".to_string(),
+            protocol,
+            batch_size: 16,
+        }));
+        let stop = Arc::new(AtomicBool::new(false));
+        let client_handle = tokio::spawn({
+            let client = client.clone();
+            let stop = stop.clone();
+            async move { client.run(stop).await }
+        });
+
+        let block = timeout(Duration::from_secs(10), block_rx.recv())
+            .await
+            .expect("no block found within timeout")
+            .expect("block channel closed");
+
+        assert!(block.validate());
+        assert_eq!(block.prev_block_hash(), [7u8; 32]);
+        assert!(block.transactions()[0].is_coinbase());
+        assert!(block.transactions().iter().any(|tx| tx.hash() == tx_hash));
+
+        // The share that produced the block is acknowledged
+        timeout(Duration::from_secs(5), async {
+            while client.stats().shares_accepted == 0 {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+        })
+        .await
+        .expect("share was never accepted");
+        assert_eq!(client.stats().shares_rejected, 0);
+
+        stop.store(true, Ordering::Relaxed);
+        let _ = timeout(Duration::from_secs(5), client_handle).await;
+        server_handle.abort();
+    }
+
+    #[tokio::test]
+    async fn test_stratum_v1_client_finds_block() {
+        mine_block_with(StratumProtocol::V1).await;
+    }
+
+    #[tokio::test]
+    async fn test_stratum_v2_client_finds_block() {
+        mine_block_with(StratumProtocol::V2).await;
+    }
+
+    #[tokio::test]
+    async fn test_stratum_rejects_bad_password() {
+        let (addr, server_handle, _tip_tx, _block_rx, _) = start_server(Some("secret".to_string())).await;
+
+        for protocol in [StratumProtocol::V1, StratumProtocol::V2] {
+            let client = StratumClient::new(StratumClientConfig {
+                address: addr.to_string(),
+                worker: "rig1".to_string(),
+                password: "wrong".to_string(),
+                protocol,
+                batch_size: 16,
+            });
+            let result = timeout(Duration::from_secs(5), client.run(Arc::new(AtomicBool::new(false))))
+                .await
+                .expect("client did not give up");
+            assert!(result.is_err());
+        }
+
+        server_handle.abort();
+    }
+
+    #[tokio::test]
+    async fn test_stratum_new_tip_produces_clean_job() {
+        let (addr, server_handle, tip_tx, mut block_rx, _) = start_server(None).await;
+
+        let client = Arc::new(StratumClient::new(StratumClientConfig {
+            address: addr.to_string(),
+            protocol: StratumProtocol::V1,
+            batch_size: 16,
+            ..StratumClientConfig::default()
+        }));
+        let stop = Arc::new(AtomicBool::new(false));
+        let client_handle = tokio::spawn({
+            let client = client.clone();
+            let stop = stop.clone();
+            async move { client.run(stop).await }
+        });
+
+        timeout(Duration::from_secs(10), block_rx.recv()).await.unwrap().unwrap();
+
+        tip_tx.send(ChainTip {
+            version: 1,
+            prev_block_hash: [8u8; 32],
+            height: 102,
+            target: REGTEST_POW_LIMIT_BITS,
+        }).unwrap();
+
+        // Blocks on the new tip arrive once the clean job reaches the miner
+        let block = timeout(Duration::from_secs(10), async {
+            loop {
+                let block = block_rx.recv().await.expect("block channel closed");
+                if block.prev_block_hash() == [8u8; 32] {
+                    return block;
+                }
+            }
+        })
+        .await
+        .expect("no block on the new tip");
+        assert!(block.validate());
+
+        stop.store(true, Ordering::Relaxed);
+        let _ = timeout(Duration::from_secs(5), client_handle).await;
+        server_handle.abort();
+    }
+}
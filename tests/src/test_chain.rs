@@ -0,0 +1,59 @@
+//! A chain view that funds any output 0, for tests filling a mempool without mining blocks
+
+use btclib::crypto::quantum::{QuantumKeyPair, QuantumParameters, QuantumScheme};
+use btclib::crypto::signature::SignatureType;
+use btclib::types::script::{pay_to_pubkey_hash, signature_hash, InputWitness};
+use btclib::types::transaction::{Transaction, TransactionInput, TransactionOutput};
+use node::mempool::{ChainView, Coin};
+
+/// Value of every coin the chain holds
+pub const COIN_VALUE: u64 = 1_100_000;
+
+/// Chain in which output 0 of every transaction is unspent, worth [`COIN_VALUE`] and locked to one key
+pub struct FundedChain {
+    key: QuantumKeyPair,
+}
+
+impl FundedChain {
+    pub fn new() -> Self {
+        let parameters = QuantumParameters { scheme: QuantumScheme::Dilithium, security_level: 3 };
+        Self { key: QuantumKeyPair::from_seed(&[7u8; 32], parameters).unwrap() }
+    }
+
+    /// Signed transaction spending output 0 of `prev_hash` and paying `value` to `script`
+    pub fn spend(&self, prev_hash: [u8; 32], value: u64, script: Vec<u8>) -> Transaction {
+        let mut tx = Transaction::new(
+            1,
+            vec![TransactionInput::new(prev_hash, 0, vec![], 0xffffffff)],
+            vec![TransactionOutput::new(value, script)],
+            0,
+        );
+        let sighash = signature_hash(&tx, 0, &self.output()).unwrap();
+        let witness = InputWitness {
+            sig_type: SignatureType::Dilithium,
+            security_level: 3,
+            public_key: self.key.public_key.clone(),
+            signature: self.key.sign(&sighash).unwrap(),
+        };
+        tx.set_signature_script(0, witness.to_script());
+        tx
+    }
+
+    fn output(&self) -> TransactionOutput {
+        TransactionOutput::new(COIN_VALUE, pay_to_pubkey_hash(SignatureType::Dilithium, 3, &self.key.public_key))
+    }
+}
+
+impl ChainView for FundedChain {
+    fn coin(&self, _tx_hash: &[u8; 32], index: u32) -> Option<Coin> {
+        (index == 0).then(|| Coin { output: self.output(), coinbase_height: None })
+    }
+
+    fn tip_height(&self) -> u64 {
+        100
+    }
+
+    fn median_time_past(&self) -> u64 {
+        0
+    }
+}
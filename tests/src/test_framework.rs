@@ -7,6 +7,7 @@ use tokio::sync::{mpsc, oneshot};
 use tokio::time::timeout;
 use tracing::{debug, info, warn, error};
 
+use btclib::consensus::{DifficultyParams, REGTEST_POW_LIMIT_BITS};
 use btclib::types::block::Block;
 use btclib::types::transaction::Transaction;
 use node::network::{P2PNetwork, NetworkCommand, NetworkEvent};
@@ -58,7 +59,9 @@ impl TestNode {
         let db = Arc::new(BlockchainDB::new(&db_path)?);
         
         // Initialize chain state
-        let chain_state = Arc::new(ChainState::new(Arc::clone(&db))?);
+        let mut chain_state = ChainState::new(Arc::clone(&db))?;
+        chain_state.set_difficulty_params(DifficultyParams::regtest());
+        let chain_state = Arc::new(chain_state);
         
         // Initialize mempool
         let mempool_config = MempoolConfig::default();
@@ -138,12 +141,13 @@ impl TestNode {
         let txs = self.mempool.get_sorted_transactions();
         
         // Create a new block
-        let block = Block::new(
+        let mut block = Block::new(
             1, // Version
             prev_hash,
             txs,
-            u32::MAX / 2, // Target difficulty
+            REGTEST_POW_LIMIT_BITS, // Target difficulty
         );
+        block.solve();
         
         // Add the block to our chain
         self.add_block(block.clone()).await?;
@@ -0,0 +1,288 @@
+use async_trait::async_trait;
+use btclib::config::NetworkType;
+use btclib::consensus::{
+    block_subsidy, DifficultyParams, COIN, COINBASE_MATURITY, ENVIRONMENTAL_TREASURY_SCRIPT,
+};
+use btclib::types::block::Block;
+use btclib::types::transaction::{Transaction, TransactionInput, TransactionOutput};
+use node::mempool::{MempoolConfig, TransactionPool};
+use node::storage::{BlockchainDB, ChainState, StorageError};
+use std::sync::Arc;
+use tempfile::TempDir;
+use tokio::sync::RwLock;
+use wallet::{NetworkError, TransactionBroadcaster, Wallet, UTXO};
+
+/// Chain state and mempool of a single node, driven directly by the test
+struct InProcessNode {
+    chain_state: RwLock<ChainState>,
+    mempool: TransactionPool,
+    _data_dir: TempDir,
+}
+
+impl InProcessNode {
+    fn new() -> Self {
+        let data_dir = tempfile::tempdir().unwrap();
+        let db = Arc::new(BlockchainDB::new(data_dir.path()).unwrap());
+        let mut chain_state = ChainState::new(db).unwrap();
+        chain_state.set_difficulty_params(DifficultyParams::regtest());
+
+        Self {
+            chain_state: RwLock::new(chain_state),
+            mempool: TransactionPool::new(MempoolConfig::default()),
+            _data_dir: data_dir,
+        }
+    }
+
+    /// Mine the mempool into a block whose reward pays `reward_script`
+    async fn mine_block(&self, reward_script: &[u8]) -> Block {
+        let block = self.build_block(reward_script, self.mempool.get_sorted_transactions()).await;
+        assert!(self.chain_state.write().await.process_block(block.clone()).await.unwrap());
+        for tx in block.transactions().iter().skip(1) {
+            self.mempool.remove_transaction(&tx.hash());
+        }
+        block
+    }
+
+    /// Mine enough empty blocks for every coinbase so far to mature
+    async fn mature_coinbases(&self) {
+        for _ in 0..COINBASE_MATURITY {
+            self.mine_block(&[9u8; 33]).await;
+        }
+    }
+
+    /// Solve a block on the tip holding `transactions`, without submitting it
+    ///
+    /// Fees are computed without validating the transactions, so blocks that
+    /// break consensus rules can be built too.
+    async fn build_block(&self, reward_script: &[u8], transactions: Vec<Transaction>) -> Block {
+        let chain_state = self.chain_state.read().await;
+        let height = chain_state.get_height() + 1;
+
+        let mut fees = 0;
+        for (position, tx) in transactions.iter().enumerate() {
+            let total_in: u64 = tx
+                .inputs()
+                .iter()
+                .map(|input| {
+                    let earlier = transactions[..position]
+                        .iter()
+                        .find(|prev| prev.hash() == input.prev_tx_hash());
+                    match earlier {
+                        Some(prev) => prev.outputs()[input.prev_output_index() as usize].amount(),
+                        None => chain_state
+                            .get_db()
+                            .get_utxo(&input.prev_tx_hash(), input.prev_output_index())
+                            .unwrap()
+                            .map_or(0, |output| output.amount()),
+                    }
+                })
+                .sum();
+            fees += total_in.saturating_sub(tx.total_output());
+        }
+        let treasury = chain_state.calculate_treasury_share(fees);
+
+        let mut outputs = vec![TransactionOutput::new(
+            block_subsidy(height) + fees - treasury,
+            reward_script.to_vec(),
+        )];
+        if treasury > 0 {
+            outputs.push(TransactionOutput::new(treasury, ENVIRONMENTAL_TREASURY_SCRIPT.to_vec()));
+        }
+        let coinbase = Transaction::new(
+            1,
+            vec![TransactionInput::new([0u8; 32], 0xffffffff, height.to_le_bytes().to_vec(), 0)],
+            outputs,
+            0,
+        );
+
+        let mut block_transactions = vec![coinbase];
+        block_transactions.extend(transactions);
+        let mut block = Block::new(
+            1,
+            chain_state.get_best_block_hash(),
+            block_transactions,
+            chain_state.next_work_required().unwrap(),
+        );
+        block.solve();
+        block
+    }
+
+    async fn unspent(&self, tx_hash: &[u8; 32], index: u32) -> Option<TransactionOutput> {
+        self.chain_state.read().await.get_db().get_utxo(tx_hash, index).unwrap()
+    }
+}
+
+#[async_trait]
+impl TransactionBroadcaster for InProcessNode {
+    async fn broadcast(&self, transaction: &Transaction) -> Result<(), NetworkError> {
+        self.mempool
+            .accept_transaction(transaction.clone(), &*self.chain_state.read().await)
+            .map(|_| ())
+            .map_err(|e| NetworkError::Rejected(e.to_string()))
+    }
+}
+
+/// Give `wallet` the reward output of a block mined to it
+fn receive_coinbase(wallet: &mut Wallet, block: &Block) {
+    let coinbase = &block.transactions()[0];
+    wallet.add_utxo(UTXO {
+        tx_hash: coinbase.hash(),
+        output_index: 0,
+        amount: coinbase.outputs()[0].amount(),
+        script_pubkey: coinbase.outputs()[0].pub_key_script().to_vec(),
+        account: None,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FEE: u64 = 100_000;
+
+    #[tokio::test]
+    async fn test_wallet_spends_through_node() {
+        let node = InProcessNode::new();
+        let mut alice = Wallet::new(NetworkType::Regtest).unwrap();
+        let mut bob = Wallet::new_quantum(NetworkType::Regtest).unwrap();
+
+        let funding = node.mine_block(&alice.locking_script()).await;
+        receive_coinbase(&mut alice, &funding);
+        node.mature_coinbases().await;
+        let reward = alice.get_balance();
+
+        // Alice pays Bob with a secp256k1-signed transaction
+        let payment = 10 * COIN;
+        let tx_hash = alice
+            .send_transaction(&node, &bob.get_address().unwrap(), payment, FEE)
+            .await
+            .unwrap();
+        assert!(node.mempool.get_transaction(&tx_hash).is_some());
+        assert_eq!(alice.get_balance(), reward - payment - FEE);
+
+        let block = node.mine_block(&[9u8; 33]).await;
+        assert!(block.transactions().iter().any(|tx| tx.hash() == tx_hash));
+        let treasury_output = node.unspent(&block.transactions()[0].hash(), 1).await.unwrap();
+        assert_eq!(treasury_output.pub_key_script(), ENVIRONMENTAL_TREASURY_SCRIPT);
+
+        // The node now holds Bob's output and Alice's change, and the coinbase is spent
+        let received = node.unspent(&tx_hash, 0).await.unwrap();
+        assert_eq!(received.amount(), payment);
+        assert_eq!(node.unspent(&tx_hash, 1).await.unwrap().amount(), reward - payment - FEE);
+        assert!(node.unspent(&funding.transactions()[0].hash(), 0).await.is_none());
+        bob.add_utxo(UTXO {
+            tx_hash,
+            output_index: 0,
+            amount: received.amount(),
+            script_pubkey: received.pub_key_script().to_vec(),
+            account: None,
+        });
+
+        // Bob spends his coins back to Alice with a Dilithium signature
+        let refund = 4 * COIN;
+        let refund_hash = bob
+            .send_transaction(&node, &alice.get_address().unwrap(), refund, FEE)
+            .await
+            .unwrap();
+        node.mine_block(&[9u8; 33]).await;
+        assert_eq!(node.unspent(&refund_hash, 0).await.unwrap().amount(), refund);
+        assert_eq!(node.unspent(&refund_hash, 1).await.unwrap().amount(), payment - refund - FEE);
+        assert!(node.unspent(&tx_hash, 0).await.is_none());
+        assert_eq!(node.chain_state.read().await.get_height(), 3 + COINBASE_MATURITY);
+    }
+
+    #[tokio::test]
+    async fn test_node_rejects_bad_spends() {
+        let node = InProcessNode::new();
+        let mut alice = Wallet::new(NetworkType::Regtest).unwrap();
+        let bob = Wallet::new(NetworkType::Regtest).unwrap();
+
+        let block = node.mine_block(&alice.locking_script()).await;
+        receive_coinbase(&mut alice, &block);
+        node.mature_coinbases().await;
+
+        // Redirecting a signed payment breaks its signature
+        let signed = alice.create_transaction(&bob.get_address().unwrap(), COIN, FEE).unwrap();
+        let mut outputs = signed.outputs().to_vec();
+        outputs[0] = TransactionOutput::new(COIN, alice.locking_script());
+        outputs[1] = TransactionOutput::new(signed.outputs()[1].amount(), bob.locking_script());
+        let redirected = Transaction::new(1, signed.inputs().to_vec(), outputs, 0);
+        assert!(matches!(node.broadcast(&redirected).await, Err(NetworkError::Rejected(_))));
+
+        // Unsigned inputs are rejected
+        let unsigned = signed.without_signature_scripts();
+        assert!(matches!(node.broadcast(&unsigned).await, Err(NetworkError::Rejected(_))));
+
+        // Once the payment is confirmed its inputs cannot be spent again
+        node.broadcast(&signed).await.unwrap();
+        node.mine_block(&[9u8; 33]).await;
+        assert!(matches!(node.broadcast(&signed).await, Err(NetworkError::Rejected(_))));
+    }
+
+    #[tokio::test]
+    async fn test_node_rejects_premature_coinbase_spend() {
+        let node = InProcessNode::new();
+        let mut alice = Wallet::new(NetworkType::Regtest).unwrap();
+        let bob = Wallet::new(NetworkType::Regtest).unwrap();
+
+        let funding = node.mine_block(&alice.locking_script()).await;
+        receive_coinbase(&mut alice, &funding);
+        let spend = alice.create_transaction(&bob.get_address().unwrap(), COIN, FEE).unwrap();
+
+        // Neither relay nor a block may spend the coinbase before it matures
+        assert!(matches!(node.broadcast(&spend).await, Err(NetworkError::Rejected(_))));
+        let block = node.build_block(&[9u8; 33], vec![spend.clone()]).await;
+        assert!(matches!(
+            node.chain_state.write().await.process_block(block).await,
+            Err(StorageError::InvalidBlock)
+        ));
+
+        // One block short of maturity
+        for _ in 2..COINBASE_MATURITY {
+            node.mine_block(&[9u8; 33]).await;
+        }
+        let block = node.build_block(&[9u8; 33], vec![spend.clone()]).await;
+        assert!(matches!(
+            node.chain_state.write().await.process_block(block).await,
+            Err(StorageError::InvalidBlock)
+        ));
+
+        // At exactly COINBASE_MATURITY confirmations the spend is valid
+        node.mine_block(&[9u8; 33]).await;
+        let block = node.build_block(&[9u8; 33], vec![spend]).await;
+        assert!(node.chain_state.write().await.process_block(block).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_block_spends_are_checked_together() {
+        let node = InProcessNode::new();
+        let mut alice = Wallet::new(NetworkType::Regtest).unwrap();
+        let bob = Wallet::new(NetworkType::Regtest).unwrap();
+
+        let funding = node.mine_block(&alice.locking_script()).await;
+        receive_coinbase(&mut alice, &funding);
+        node.mature_coinbases().await;
+
+        // Two payments from the same coin cannot share a block
+        let first = alice.create_transaction(&bob.get_address().unwrap(), COIN, FEE).unwrap();
+        let second = alice.create_transaction(&bob.get_address().unwrap(), 2 * COIN, FEE).unwrap();
+        let block = node.build_block(&[9u8; 33], vec![first.clone(), second]).await;
+        assert!(matches!(
+            node.chain_state.write().await.process_block(block).await,
+            Err(StorageError::InvalidBlock)
+        ));
+
+        // A transaction may spend the change of one earlier in the same block
+        alice.remove_utxo(funding.transactions()[0].hash(), 0);
+        alice.add_utxo(UTXO {
+            tx_hash: first.hash(),
+            output_index: 1,
+            amount: first.outputs()[1].amount(),
+            script_pubkey: first.outputs()[1].pub_key_script().to_vec(),
+            account: None,
+        });
+        let child = alice.create_transaction(&bob.get_address().unwrap(), COIN, FEE).unwrap();
+        let block = node.build_block(&[9u8; 33], vec![first, child]).await;
+        assert!(node.chain_state.write().await.process_block(block).await.unwrap());
+    }
+}
@@ -1,9 +1,19 @@
 use clap::{Parser, Subcommand, CommandFactory};
-use std::path::PathBuf;
-use bitcoin::network::Network;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use btclib::config::NetworkType;
+use btclib::types::address::Address;
+use btclib::types::psbt::PartiallySignedTransaction;
+use btclib::types::transaction::{Transaction, TransactionInput, TransactionOutput};
 use std::str::FromStr;
 use crate::{
+    descriptor::Descriptor,
+    fee_bump::{FeeBumper, DEFAULT_CONFIRMATION_TARGET},
     hdwallet::{AccountType, HDWallet},
+    keystore::KdfParams,
+    multisig::{CosignerKey, DEFAULT_EXPORTED_KEYS},
+    network::{RpcClient, DEFAULT_RPC_URL},
+    sync::{WalletSync, DEFAULT_GAP_LIMIT},
     ui::tui::WalletTui,
     history::TransactionHistory,
 };
@@ -36,6 +46,9 @@ enum Commands {
         mnemonic: String,
     },
 
+    /// Create a wallet without keys that watches imported descriptors
+    NewWatchOnly,
+
     /// List all accounts in the wallet
     ListAccounts,
 
@@ -44,11 +57,74 @@ enum Commands {
         /// Account name
         name: String,
 
-        /// Account type (legacy, segwit, native_segwit)
-        #[arg(short, long, default_value = "native_segwit")]
+        /// Account type (classical, dilithium)
+        #[arg(short, long, default_value = "classical")]
         account_type: String,
     },
 
+    /// Create an m-of-n account shared with other wallets
+    CreateMultisigAccount {
+        /// Account name
+        name: String,
+
+        /// Type of this wallet's key (classical, dilithium)
+        #[arg(short, long, default_value = "classical")]
+        account_type: String,
+
+        /// Signatures needed to spend
+        #[arg(short, long)]
+        threshold: u8,
+
+        /// Participants, this wallet included
+        #[arg(long)]
+        total: u8,
+    },
+
+    /// Export this wallet's public keys for a multisig account
+    ExportCosigner {
+        /// Multisig account name
+        #[arg(short, long)]
+        account: String,
+
+        /// Addresses covered by an export of post-quantum keys
+        #[arg(short, long, default_value_t = DEFAULT_EXPORTED_KEYS)]
+        keys: u32,
+
+        /// File to write the export to
+        #[arg(long)]
+        out: PathBuf,
+    },
+
+    /// Add a participant to a multisig account
+    AddCosigner {
+        /// Multisig account name
+        #[arg(short, long)]
+        account: String,
+
+        /// File written by the participant's 'export-cosigner'
+        file: PathBuf,
+    },
+
+    /// Watch the addresses of an output descriptor in a new account
+    ImportDescriptor {
+        /// Account name
+        name: String,
+
+        /// Descriptor, as printed by 'export-descriptor'
+        descriptor: String,
+    },
+
+    /// Print the descriptor of an account, for watching it elsewhere
+    ExportDescriptor {
+        /// Account name
+        #[arg(short, long)]
+        account: String,
+
+        /// Addresses covered by post-quantum keys
+        #[arg(short, long, default_value_t = DEFAULT_EXPORTED_KEYS)]
+        keys: u32,
+    },
+
     /// Get a new address for an account
     GetNewAddress {
         /// Account index or name
@@ -63,8 +139,64 @@ enum Commands {
         account: String,
     },
 
+    /// Scan the node's chain and mempool for the wallet's transactions
+    Sync {
+        /// JSON-RPC endpoint of the node
+        #[arg(long, default_value = DEFAULT_RPC_URL)]
+        node: String,
+
+        /// Unused addresses to look ahead in each account
+        #[arg(long, default_value_t = DEFAULT_GAP_LIMIT)]
+        gap_limit: u32,
+
+        /// Forget what was found from this height on and scan again
+        #[arg(long)]
+        rescan_from: Option<u64>,
+    },
+
+    /// Speed up a pending transaction by replacing it or spending its outputs
+    BumpFee {
+        /// Hash of the pending transaction
+        #[arg(long)]
+        txid: String,
+
+        /// Fee rate in satoshis per byte; estimated by the node if not given
+        #[arg(long)]
+        fee_rate: Option<u64>,
+
+        /// Blocks to confirm within when estimating the fee rate
+        #[arg(long, default_value_t = DEFAULT_CONFIRMATION_TARGET)]
+        target: u32,
+
+        /// Spend the transaction's outputs to the wallet instead of replacing it
+        #[arg(long)]
+        cpfp: bool,
+
+        /// JSON-RPC endpoint of the node
+        #[arg(long, default_value = DEFAULT_RPC_URL)]
+        node: String,
+
+        /// Write the partially signed transaction here for other signers instead of broadcasting it
+        #[arg(long)]
+        out: Option<PathBuf>,
+    },
+
     /// Run the TUI
-    Tui,
+    Tui {
+        /// Seconds an unlocked wallet stays unlocked in the TUI
+        #[arg(long, default_value_t = 300)]
+        lock_timeout: u64,
+    },
+
+    /// Encrypt a wallet file written without a passphrase
+    Encrypt,
+
+    /// Change the wallet passphrase
+    ChangePassphrase,
+
+    /// Build and sign partially signed transactions with other parties
+    #[command(subcommand)]
+    Psbt(PsbtCommand),
     
     /// Create a test transaction (for development/demo)
     #[cfg(debug_assertions)]
@@ -79,15 +211,234 @@ enum Commands {
     },
 }
 
+#[derive(Subcommand)]
+enum PsbtCommand {
+    /// Create an unsigned transaction, noting which inputs and outputs belong to this wallet
+    Create {
+        /// Coin to spend, as txid:vout:amount:address
+        #[arg(short, long = "input", required = true)]
+        inputs: Vec<String>,
+
+        /// Payment to make, as address:amount
+        #[arg(short, long = "output", required = true)]
+        outputs: Vec<String>,
+
+        /// File to write the partially signed transaction to
+        #[arg(long)]
+        out: PathBuf,
+    },
+
+    /// Add this wallet's signatures
+    Sign {
+        /// Partially signed transaction file, updated in place
+        file: PathBuf,
+    },
+
+    /// Merge copies signed by different parties
+    Combine {
+        /// Partially signed transaction files
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+
+        /// File to write the combined transaction to
+        #[arg(long)]
+        out: PathBuf,
+    },
+
+    /// Turn collected signatures into signature scripts
+    Finalize {
+        /// Partially signed transaction file, updated in place
+        file: PathBuf,
+    },
+
+    /// Print the signed transaction ready for broadcast
+    Extract {
+        /// Finalized partially signed transaction file
+        file: PathBuf,
+    },
+}
+
+/// Environment variable that supplies the passphrase instead of a prompt
+const PASSPHRASE_ENV: &str = "SUPERNOVA_WALLET_PASSPHRASE";
+
+fn read_passphrase(prompt: &str) -> Result<String, String> {
+    if let Ok(passphrase) = std::env::var(PASSPHRASE_ENV) {
+        return Ok(passphrase);
+    }
+    rpassword::prompt_password(prompt).map_err(|e| format!("Failed to read passphrase: {}", e))
+}
+
+/// Ask for a new passphrase twice
+fn read_new_passphrase() -> Result<String, String> {
+    if let Ok(passphrase) = std::env::var(PASSPHRASE_ENV) {
+        return Ok(passphrase);
+    }
+    let passphrase = read_passphrase("New wallet passphrase: ")?;
+    if passphrase.is_empty() {
+        return Err("Passphrase must not be empty".to_string());
+    }
+    if read_passphrase("Repeat passphrase: ")? != passphrase {
+        return Err("Passphrases do not match".to_string());
+    }
+    Ok(passphrase)
+}
+
+/// Load the wallet, asking for its passphrase if the file is encrypted
+///
+/// The wallet stays unlocked only for the command being run.
+fn load_wallet(wallet_path: &Path) -> Result<HDWallet, String> {
+    if !wallet_path.exists() {
+        return Err("No wallet found. Create one first with 'new' command.".to_string());
+    }
+    let encrypted = HDWallet::is_encrypted_file(wallet_path)
+        .map_err(|e| format!("Failed to read wallet: {}", e))?;
+
+    if encrypted {
+        let passphrase = read_passphrase("Wallet passphrase: ")?;
+        HDWallet::open(wallet_path.to_path_buf(), &passphrase)
+            .map_err(|e| format!("Failed to unlock wallet: {}", e))
+    } else {
+        eprintln!("Warning: the wallet file is not encrypted. Run the 'encrypt' command to protect it.");
+        HDWallet::load(wallet_path.to_path_buf())
+            .map_err(|e| format!("Failed to load wallet: {}", e))
+    }
+}
+
+fn read_psbt(path: &Path) -> Result<PartiallySignedTransaction, String> {
+    std::fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?
+        .parse()
+        .map_err(|e| format!("Invalid partially signed transaction in {}: {}", path.display(), e))
+}
+
+fn write_psbt(path: &Path, psbt: &PartiallySignedTransaction) -> Result<(), String> {
+    std::fs::write(path, format!("{}\n", psbt))
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+/// Parse `txid:vout:amount:address` into the input and the output it spends
+fn parse_psbt_input(spec: &str, network: NetworkType) -> Result<(TransactionInput, TransactionOutput), String> {
+    let invalid = || format!("Invalid input '{}', expected txid:vout:amount:address", spec);
+    let parts: Vec<&str> = spec.split(':').collect();
+    let [txid, vout, amount, address] = parts.as_slice() else {
+        return Err(invalid());
+    };
+
+    let txid: [u8; 32] = hex::decode(txid)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(invalid)?;
+    let vout: u32 = vout.parse().map_err(|_| invalid())?;
+    let amount: u64 = amount.parse().map_err(|_| invalid())?;
+    let address = Address::parse_for_network(address, network).map_err(|e| format!("{}: {}", invalid(), e))?;
+
+    Ok((
+        TransactionInput::new(txid, vout, vec![], 0xffffffff),
+        TransactionOutput::new(amount, address.script_pubkey()),
+    ))
+}
+
+/// Parse `address:amount` into an output
+fn parse_psbt_output(spec: &str, network: NetworkType) -> Result<TransactionOutput, String> {
+    let invalid = || format!("Invalid output '{}', expected address:amount", spec);
+    let (address, amount) = spec.split_once(':').ok_or_else(invalid)?;
+    let address = Address::parse_for_network(address, network).map_err(|e| format!("{}: {}", invalid(), e))?;
+    let amount: u64 = amount.parse().map_err(|_| invalid())?;
+    Ok(TransactionOutput::new(amount, address.script_pubkey()))
+}
+
+fn run_psbt_command(command: PsbtCommand, wallet_path: &Path, network: NetworkType) -> Result<(), String> {
+    match command {
+        PsbtCommand::Create { inputs, outputs, out } => {
+            let (inputs, spent): (Vec<_>, Vec<_>) = inputs
+                .iter()
+                .map(|spec| parse_psbt_input(spec, network))
+                .collect::<Result<Vec<_>, _>>()?
+                .into_iter()
+                .unzip();
+            let outputs = outputs
+                .iter()
+                .map(|spec| parse_psbt_output(spec, network))
+                .collect::<Result<Vec<_>, _>>()?;
+
+            let total_in: u64 = spent.iter().map(|output| output.amount()).sum();
+            let total_out: u64 = outputs.iter().map(|output| output.amount()).sum();
+            if total_out > total_in {
+                return Err(format!("Outputs spend {} but inputs only hold {}", total_out, total_in));
+            }
+
+            let mut psbt = PartiallySignedTransaction::new(&Transaction::new(1, inputs, outputs, 0));
+            for (input, spent) in psbt.inputs.iter_mut().zip(spent) {
+                input.spent_output = Some(spent);
+            }
+
+            let wallet = load_wallet(wallet_path)?;
+            let origins = wallet.update_psbt(&mut psbt)
+                .map_err(|e| format!("Failed to add key origins: {}", e))?;
+            write_psbt(&out, &psbt)?;
+
+            println!("Created {} with a fee of {} satoshis.", out.display(), total_in - total_out);
+            println!("{} input(s) and output(s) belong to this wallet.", origins);
+            Ok(())
+        },
+
+        PsbtCommand::Sign { file } => {
+            let mut psbt = read_psbt(&file)?;
+            let wallet = load_wallet(wallet_path)?;
+            let signed = wallet.sign_psbt(&mut psbt)
+                .map_err(|e| format!("Failed to sign: {}", e))?;
+            write_psbt(&file, &psbt)?;
+
+            println!("Added {} signature(s) to {}.", signed, file.display());
+            Ok(())
+        },
+
+        PsbtCommand::Combine { files, out } => {
+            let mut psbts = files.iter().map(|file| read_psbt(file));
+            let mut combined = psbts.next().ok_or("No files to combine")??;
+            for psbt in psbts {
+                combined.combine(&psbt?)
+                    .map_err(|e| format!("Failed to combine: {}", e))?;
+            }
+            write_psbt(&out, &combined)?;
+
+            println!("Combined {} file(s) into {}.", files.len(), out.display());
+            Ok(())
+        },
+
+        PsbtCommand::Finalize { file } => {
+            let mut psbt = read_psbt(&file)?;
+            let result = psbt.finalize();
+            // Inputs completed before a failure are kept
+            write_psbt(&file, &psbt)?;
+            result.map_err(|e| format!("Failed to finalize: {}", e))?;
+
+            println!("All inputs of {} are finalized.", file.display());
+            Ok(())
+        },
+
+        PsbtCommand::Extract { file } => {
+            let tx = read_psbt(&file)?
+                .extract()
+                .map_err(|e| format!("Failed to extract: {}", e))?;
+            let encoded = bincode::serialize(&tx)
+                .map_err(|e| format!("Failed to encode transaction: {}", e))?;
+
+            println!("Transaction {}:", hex::encode(tx.hash()));
+            println!("{}", hex::encode(encoded));
+            Ok(())
+        },
+    }
+}
+
 pub fn run_cli() -> Result<(), String> {
     let cli = Cli::parse();
     
-    // Parse network string to Network enum
+    // Parse network string to NetworkType enum
     let network = match cli.network.to_lowercase().as_str() {
-        "mainnet" | "bitcoin" => Network::Bitcoin,
-        "testnet" => Network::Testnet,
-        "regtest" => Network::Regtest,
-        "signet" => Network::Signet,
+        "mainnet" => NetworkType::Mainnet,
+        "testnet" => NetworkType::Testnet,
+        "regtest" => NetworkType::Regtest,
         _ => return Err(format!("Invalid network: {}", cli.network)),
     };
     
@@ -107,18 +458,17 @@ pub fn run_cli() -> Result<(), String> {
     match cli.command {
         Some(Commands::New) => {
             println!("Creating new wallet...");
-            let wallet = HDWallet::new(network, wallet_path)
+            let mut wallet = HDWallet::new(network, wallet_path)
                 .map_err(|e| format!("Failed to create wallet: {}", e))?;
+            wallet.encrypt(&read_new_passphrase()?, KdfParams::default())
+                .map_err(|e| format!("Failed to encrypt wallet: {}", e))?;
             
             println!("Wallet created successfully.");
             println!("Your mnemonic phrase (keep this safe!):");
-            // In a real implementation, we'd get the mnemonic from the created wallet
-            // Here we're just showing the phrase would be displayed
-            println!("{}", wallet.get_mnemonic());
+            println!("{}", wallet.get_mnemonic().map_err(|e| e.to_string())?);
             
             // Create default account
-            let mut wallet = wallet;
-            wallet.create_account("default".to_string(), AccountType::NativeSegWit)
+            wallet.create_account("default".to_string(), AccountType::Classical)
                 .map_err(|e| format!("Failed to create default account: {}", e))?;
             
             println!("Default account created.");
@@ -126,12 +476,7 @@ pub fn run_cli() -> Result<(), String> {
         },
         
         Some(Commands::Load) => {
-            if !wallet_path.exists() {
-                return Err("No wallet found. Create one first with 'new' command.".to_string());
-            }
-            
-            let wallet = HDWallet::load(wallet_path)
-                .map_err(|e| format!("Failed to load wallet: {}", e))?;
+            let wallet = load_wallet(&wallet_path)?;
             
             println!("Wallet loaded successfully.");
             Ok(())
@@ -139,27 +484,33 @@ pub fn run_cli() -> Result<(), String> {
         
         Some(Commands::FromMnemonic { mnemonic }) => {
             println!("Creating wallet from mnemonic...");
-            let wallet = HDWallet::from_mnemonic(&mnemonic, network, wallet_path)
+            let mut wallet = HDWallet::from_mnemonic(&mnemonic, network, wallet_path)
                 .map_err(|e| format!("Failed to create wallet from mnemonic: {}", e))?;
+            wallet.encrypt(&read_new_passphrase()?, KdfParams::default())
+                .map_err(|e| format!("Failed to encrypt wallet: {}", e))?;
             
             println!("Wallet created successfully.");
             
             // Create default account
-            let mut wallet = wallet;
-            wallet.create_account("default".to_string(), AccountType::NativeSegWit)
+            wallet.create_account("default".to_string(), AccountType::Classical)
                 .map_err(|e| format!("Failed to create default account: {}", e))?;
             
             println!("Default account created.");
             Ok(())
         },
         
-        Some(Commands::ListAccounts) => {
-            if !wallet_path.exists() {
-                return Err("No wallet found. Create one first with 'new' command.".to_string());
-            }
+        Some(Commands::NewWatchOnly) => {
+            println!("Creating watch-only wallet...");
+            let mut wallet = HDWallet::new_watch_only(network, wallet_path);
+            wallet.encrypt(&read_new_passphrase()?, KdfParams::default())
+                .map_err(|e| format!("Failed to encrypt wallet: {}", e))?;
             
-            let wallet = HDWallet::load(wallet_path)
-                .map_err(|e| format!("Failed to load wallet: {}", e))?;
+            println!("Watch-only wallet created. Add accounts with 'import-descriptor'.");
+            Ok(())
+        },
+        
+        Some(Commands::ListAccounts) => {
+            let wallet = load_wallet(&wallet_path)?;
             
             let accounts = wallet.list_accounts();
             if accounts.is_empty() {
@@ -167,23 +518,25 @@ pub fn run_cli() -> Result<(), String> {
             } else {
                 println!("Accounts:");
                 for (idx, account) in accounts {
-                    println!("{}. {} (type: {:?}, addresses: {})", 
+                    let multisig = account.multisig.as_ref()
+                        .map(|setup| format!(", {}-of-{} multisig with {} cosigner(s)",
+                                             setup.threshold, setup.total, setup.cosigners.len()))
+                        .unwrap_or_default();
+                    let watch_only = if account.is_watch_only() { ", watch-only" } else { "" };
+                    println!("{}. {} (type: {:?}, addresses: {}{}{})", 
                             idx, 
                             account.name, 
                             account.account_type,
-                            account.addresses.len());
+                            account.addresses.len(),
+                            multisig,
+                            watch_only);
                 }
             }
             Ok(())
         },
         
         Some(Commands::CreateAccount { name, account_type }) => {
-            if !wallet_path.exists() {
-                return Err("No wallet found. Create one first with 'new' command.".to_string());
-            }
-            
-            let mut wallet = HDWallet::load(wallet_path)
-                .map_err(|e| format!("Failed to load wallet: {}", e))?;
+            let mut wallet = load_wallet(&wallet_path)?;
             
             let acc_type = AccountType::from_str(&account_type)
                 .map_err(|e| format!("Invalid account type: {}", e))?;
@@ -195,13 +548,70 @@ pub fn run_cli() -> Result<(), String> {
             Ok(())
         },
         
-        Some(Commands::GetNewAddress { account }) => {
-            if !wallet_path.exists() {
-                return Err("No wallet found. Create one first with 'new' command.".to_string());
-            }
+        Some(Commands::CreateMultisigAccount { name, account_type, threshold, total }) => {
+            let mut wallet = load_wallet(&wallet_path)?;
+            
+            let acc_type = AccountType::from_str(&account_type)
+                .map_err(|e| format!("Invalid account type: {}", e))?;
+            
+            wallet.create_multisig_account(name.clone(), acc_type, threshold, total)
+                .map_err(|e| format!("Failed to create account: {}", e))?;
+            
+            println!("Account '{}' ({}-of-{}) created successfully.", name, threshold, total);
+            println!("Export this wallet's keys with 'export-cosigner' and add the other {} participant(s) with 'add-cosigner'.", total - 1);
+            Ok(())
+        },
+        
+        Some(Commands::ExportCosigner { account, keys, out }) => {
+            let wallet = load_wallet(&wallet_path)?;
+            
+            let cosigner = wallet.export_cosigner_key(&account, keys)
+                .map_err(|e| format!("Failed to export keys: {}", e))?;
+            std::fs::write(&out, format!("{}\n", cosigner))
+                .map_err(|e| format!("Failed to write {}: {}", out.display(), e))?;
+            
+            println!("Keys for '{}' written to {}.", account, out.display());
+            Ok(())
+        },
+        
+        Some(Commands::AddCosigner { account, file }) => {
+            let mut wallet = load_wallet(&wallet_path)?;
+            
+            let cosigner: CosignerKey = std::fs::read_to_string(&file)
+                .map_err(|e| format!("Failed to read {}: {}", file.display(), e))?
+                .parse()
+                .map_err(|e| format!("Invalid cosigner keys in {}: {}", file.display(), e))?;
+            wallet.add_cosigner(&account, cosigner)
+                .map_err(|e| format!("Failed to add cosigner: {}", e))?;
             
-            let mut wallet = HDWallet::load(wallet_path)
-                .map_err(|e| format!("Failed to load wallet: {}", e))?;
+            println!("Cosigner added to '{}'.", account);
+            Ok(())
+        },
+        
+        Some(Commands::ImportDescriptor { name, descriptor }) => {
+            let mut wallet = load_wallet(&wallet_path)?;
+            
+            let descriptor = Descriptor::parse(&descriptor, network)
+                .map_err(|e| format!("Invalid descriptor: {}", e))?;
+            wallet.import_descriptor(name.clone(), descriptor)
+                .map_err(|e| format!("Failed to import descriptor: {}", e))?;
+            
+            println!("Watch-only account '{}' created. Run 'sync' to find its coins.", name);
+            Ok(())
+        },
+        
+        Some(Commands::ExportDescriptor { account, keys }) => {
+            let wallet = load_wallet(&wallet_path)?;
+            
+            let descriptor = wallet.export_descriptor(&account, keys)
+                .map_err(|e| format!("Failed to export descriptor: {}", e))?;
+            
+            println!("{}", descriptor);
+            Ok(())
+        },
+        
+        Some(Commands::GetNewAddress { account }) => {
+            let mut wallet = load_wallet(&wallet_path)?;
             
             let address = wallet.get_new_address(&account)
                 .map_err(|e| format!("Failed to get new address: {}", e))?;
@@ -211,12 +621,7 @@ pub fn run_cli() -> Result<(), String> {
         },
         
         Some(Commands::GetBalance { account }) => {
-            if !wallet_path.exists() {
-                return Err("No wallet found. Create one first with 'new' command.".to_string());
-            }
-            
-            let wallet = HDWallet::load(wallet_path)
-                .map_err(|e| format!("Failed to load wallet: {}", e))?;
+            let wallet = load_wallet(&wallet_path)?;
             
             let balance = wallet.get_balance(&account)
                 .map_err(|e| format!("Failed to get balance: {}", e))?;
@@ -225,32 +630,125 @@ pub fn run_cli() -> Result<(), String> {
             Ok(())
         },
         
-        Some(Commands::Tui) => {
-            if !wallet_path.exists() {
-                return Err("No wallet found. Create one first with 'new' command.".to_string());
-            }
-            
-            let wallet = HDWallet::load(wallet_path)
-                .map_err(|e| format!("Failed to load wallet: {}", e))?;
+        Some(Commands::Tui { lock_timeout }) => {
+            let wallet = load_wallet(&wallet_path)?;
             
             let history = TransactionHistory::new(history_path)
                 .map_err(|e| format!("Failed to load transaction history: {}", e))?;
             
             let mut tui = WalletTui::new(wallet, history)
-                .map_err(|e| format!("Failed to create TUI: {}", e))?;
+                .map_err(|e| format!("Failed to create TUI: {}", e))?
+                .with_lock_timeout(Duration::from_secs(lock_timeout));
             
             tui.run().map_err(|e| format!("TUI error: {}", e))?;
             Ok(())
         },
         
-        #[cfg(debug_assertions)]
-        Some(Commands::CreateTestTransaction { account, amount }) => {
-            if !wallet_path.exists() {
-                return Err("No wallet found. Create one first with 'new' command.".to_string());
+        Some(Commands::Encrypt) => {
+            let mut wallet = load_wallet(&wallet_path)?;
+            if wallet.is_encrypted() {
+                return Err("Wallet is already encrypted.".to_string());
+            }
+            
+            wallet.encrypt(&read_new_passphrase()?, KdfParams::default())
+                .map_err(|e| format!("Failed to encrypt wallet: {}", e))?;
+            
+            println!("Wallet encrypted successfully.");
+            Ok(())
+        },
+        
+        Some(Commands::ChangePassphrase) => {
+            if !HDWallet::is_encrypted_file(&wallet_path).unwrap_or(false) {
+                return Err("Wallet is not encrypted. Use the 'encrypt' command instead.".to_string());
             }
             
-            let wallet = HDWallet::load(wallet_path)
-                .map_err(|e| format!("Failed to load wallet: {}", e))?;
+            let current = read_passphrase("Current passphrase: ")?;
+            let mut wallet = HDWallet::open(wallet_path, &current)
+                .map_err(|e| format!("Failed to unlock wallet: {}", e))?;
+            let new = read_new_passphrase()?;
+            
+            wallet.change_passphrase(&current, &new, KdfParams::default())
+                .map_err(|e| format!("Failed to change passphrase: {}", e))?;
+            
+            println!("Passphrase changed successfully.");
+            Ok(())
+        },
+        
+        Some(Commands::Psbt(command)) => run_psbt_command(command, &wallet_path, network),
+
+        Some(Commands::Sync { node, gap_limit, rescan_from }) => {
+            let mut wallet = load_wallet(&wallet_path)?;
+            let mut history = TransactionHistory::new(history_path)
+                .map_err(|e| format!("Failed to load transaction history: {}", e))?;
+            let sync = WalletSync::new(RpcClient::new(node)).with_gap_limit(gap_limit);
+
+            let runtime = tokio::runtime::Runtime::new()
+                .map_err(|e| format!("Failed to start runtime: {}", e))?;
+            let report = runtime
+                .block_on(async {
+                    match rescan_from {
+                        Some(height) => sync.rescan(&mut wallet, &mut history, height).await,
+                        None => sync.sync(&mut wallet, &mut history).await,
+                    }
+                })
+                .map_err(|e| format!("Sync failed: {}", e))?;
+
+            match report.tip_height {
+                Some(height) => println!("Synced to height {}.", height),
+                None => println!("The node has no blocks yet."),
+            }
+            println!("Scanned {} block(s), found {} new transaction(s).", report.blocks_scanned, report.new_transactions);
+            if report.blocks_disconnected > 0 {
+                println!("Rolled back {} block(s) that left the best chain.", report.blocks_disconnected);
+            }
+            println!("Total balance: {} satoshis", wallet.get_total_balance().map_err(|e| e.to_string())?);
+            Ok(())
+        },
+        
+        Some(Commands::BumpFee { txid, fee_rate, target, cpfp, node, out }) => {
+            let tx_hash: [u8; 32] = hex::decode(&txid)
+                .ok()
+                .and_then(|bytes| bytes.try_into().ok())
+                .ok_or_else(|| format!("Invalid transaction hash: {}", txid))?;
+            let mut wallet = load_wallet(&wallet_path)?;
+            let mut history = TransactionHistory::new(history_path)
+                .map_err(|e| format!("Failed to load transaction history: {}", e))?;
+            let bumper = FeeBumper::new(RpcClient::new(node));
+
+            let runtime = tokio::runtime::Runtime::new()
+                .map_err(|e| format!("Failed to start runtime: {}", e))?;
+            runtime.block_on(async {
+                let fee_rate = match fee_rate {
+                    Some(fee_rate) => fee_rate,
+                    None => bumper.fee_rate(target).await.map_err(|e| e.to_string())?,
+                };
+                let bump = if cpfp {
+                    bumper.child_pays_for_parent(&mut wallet, &history, &tx_hash, fee_rate).await
+                } else {
+                    bumper.replace_by_fee(&wallet, &history, &tx_hash, fee_rate).await
+                }
+                .map_err(|e| format!("Failed to bump fee: {}", e))?;
+                // A child pays to a new address, which must be kept
+                wallet.save().map_err(|e| format!("Failed to save wallet: {}", e))?;
+
+                println!("New fee: {} satoshis at {} sat/byte", bump.fee, fee_rate);
+                if let Some(path) = out {
+                    write_psbt(&path, &bump.psbt)?;
+                    println!("Wrote the partially signed transaction to {}.", path.display());
+                    return Ok(());
+                }
+                let hash = bumper
+                    .broadcast(bump, &mut history)
+                    .await
+                    .map_err(|e| format!("Failed to broadcast: {}", e))?;
+                println!("Broadcast transaction {}", hex::encode(hash));
+                Ok::<(), String>(())
+            })
+        },
+
+        #[cfg(debug_assertions)]
+        Some(Commands::CreateTestTransaction { account, amount }) => {
+            let wallet = load_wallet(&wallet_path)?;
             
             let mut history = TransactionHistory::new(history_path)
                 .map_err(|e| format!("Failed to load transaction history: {}", e))?;
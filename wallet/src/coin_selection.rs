@@ -0,0 +1,544 @@
+//! Coin selection
+//!
+//! Coins are compared by their effective value: the amount minus the fee their
+//! input adds at the target fee rate. Sizes follow
+//! [`Transaction::calculate_size`](btclib::types::transaction::Transaction::calculate_size),
+//! with each input costing its outpoint, sequence and signed witness.
+//!
+//! Selection first looks for a changeless set with branch and bound, then
+//! builds sets with change by knapsack and single random draw, and keeps the
+//! candidate with the least waste, as Bitcoin Core does.
+
+use btclib::crypto::signature::SignatureType;
+use btclib::types::script::{InputWitness, MultisigPolicy, MultisigWitness};
+use rand::seq::SliceRandom;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use thiserror::Error;
+
+use crate::core::UTXO;
+
+/// Change below this amount is left to the fee instead
+pub const DEFAULT_DUST_LIMIT: u64 = 546;
+
+/// Bytes an input adds before its signature script: outpoint, sequence and script length
+pub const INPUT_BASE_SIZE: usize = 40;
+
+/// Bytes an output adds before its locking script: amount and script length
+pub const OUTPUT_BASE_SIZE: usize = 9;
+
+const BNB_MAX_TRIES: usize = 100_000;
+const KNAPSACK_ITERATIONS: usize = 1_000;
+
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum CoinSelectionError {
+    #[error("Insufficient funds: need {needed}, {available} available")]
+    InsufficientFunds { needed: u64, available: u64 },
+}
+
+/// Wallet-wide preferences for coin selection
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CoinSelectionOptions {
+    /// Fee rate, in satoshis per byte, expected when change is spent later
+    pub long_term_fee_rate: u64,
+    /// Smallest change output worth creating
+    pub dust_limit: u64,
+    /// Fund each payment from coins of one account only
+    pub avoid_mixing_accounts: bool,
+}
+
+impl Default for CoinSelectionOptions {
+    fn default() -> Self {
+        Self {
+            long_term_fee_rate: 1,
+            dust_limit: DEFAULT_DUST_LIMIT,
+            avoid_mixing_accounts: false,
+        }
+    }
+}
+
+/// What a selection has to pay for
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SelectionTarget {
+    /// Sum of the payment outputs
+    pub amount: u64,
+    /// Fee rate in satoshis per byte
+    pub fee_rate: u64,
+    /// Size of the transaction without inputs or change
+    pub base_size: usize,
+    /// Size a change output adds
+    pub change_output_size: usize,
+    /// Size of an input spending the change later
+    pub change_input_size: usize,
+}
+
+/// A coin that may be spent, with the size of the input spending it
+#[derive(Debug, Clone)]
+pub struct Candidate {
+    pub utxo: UTXO,
+    pub input_size: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Algorithm {
+    BranchAndBound,
+    Knapsack,
+    SingleRandomDraw,
+}
+
+/// Coins chosen for a payment and how the remainder is split between change and fee
+#[derive(Debug, Clone)]
+pub struct Selection {
+    pub coins: Vec<UTXO>,
+    /// Change output amount; 0 when the payment has no change
+    pub change: u64,
+    pub fee: u64,
+    pub algorithm: Algorithm,
+    /// Fee paid now beyond what the same inputs would cost at the long-term rate,
+    /// plus either the cost of creating and spending change or the excess given up to the fee
+    pub waste: i64,
+}
+
+/// Bytes of a signature made with a key of `sig_type` at `security_level`
+pub fn signature_size(sig_type: SignatureType, security_level: u8) -> usize {
+    match (sig_type, security_level) {
+        (SignatureType::Secp256k1, _) => 64,
+        (SignatureType::Dilithium, 2) => 2420,
+        (SignatureType::Dilithium, 5) => 4595,
+        (SignatureType::Dilithium, _) => 3293,
+        (SignatureType::Falcon, 5) => 1280,
+        (SignatureType::Falcon, _) => 690,
+        _ => 64,
+    }
+}
+
+/// Bytes of the signature script spending a single-key output
+pub fn witness_size(sig_type: SignatureType, security_level: u8, public_key_len: usize) -> usize {
+    InputWitness {
+        sig_type,
+        security_level,
+        public_key: vec![0; public_key_len],
+        signature: vec![0; signature_size(sig_type, security_level)],
+    }
+    .to_script()
+    .len()
+}
+
+/// Bytes of the signature script spending a multisig output, sized for the largest signatures
+pub fn multisig_witness_size(policy: &MultisigPolicy) -> usize {
+    let mut sizes: Vec<usize> = policy
+        .keys()
+        .iter()
+        .map(|key| signature_size(key.sig_type, key.security_level))
+        .collect();
+    sizes.sort_unstable_by(|a, b| b.cmp(a));
+    MultisigWitness {
+        policy: policy.clone(),
+        signatures: sizes
+            .into_iter()
+            .take(policy.threshold() as usize)
+            .enumerate()
+            .map(|(index, size)| (index as u8, vec![0; size]))
+            .collect(),
+    }
+    .to_script()
+    .len()
+}
+
+/// Pick coins from `candidates` for `target`
+///
+/// With `avoid_mixing_accounts` set, coins of different accounts never fund
+/// the same payment; the account whose coins give the least waste is used.
+pub fn select_coins<R: Rng + ?Sized>(
+    candidates: &[Candidate],
+    target: &SelectionTarget,
+    options: &CoinSelectionOptions,
+    rng: &mut R,
+) -> Result<Selection, CoinSelectionError> {
+    if !options.avoid_mixing_accounts {
+        return select_from(candidates, target, options, rng);
+    }
+
+    let mut accounts: BTreeMap<Option<&str>, Vec<Candidate>> = BTreeMap::new();
+    for candidate in candidates {
+        accounts
+            .entry(candidate.utxo.account.as_deref())
+            .or_default()
+            .push(candidate.clone());
+    }
+
+    let mut best: Option<Selection> = None;
+    let mut shortfall = None;
+    for group in accounts.values() {
+        match select_from(group, target, options, rng) {
+            Ok(selection) => {
+                if best.as_ref().map_or(true, |best| selection.waste < best.waste) {
+                    best = Some(selection);
+                }
+            }
+            // Report the account that came closest
+            Err(CoinSelectionError::InsufficientFunds { needed, available }) => {
+                if shortfall.map_or(true, |(_, best_available)| available > best_available) {
+                    shortfall = Some((needed, available));
+                }
+            }
+        }
+    }
+
+    best.ok_or_else(|| {
+        let (needed, available) = shortfall.unwrap_or((target.amount, 0));
+        CoinSelectionError::InsufficientFunds { needed, available }
+    })
+}
+
+/// Fee and effective-value arithmetic shared by the algorithms
+struct Costs<'a> {
+    target: &'a SelectionTarget,
+    options: &'a CoinSelectionOptions,
+    /// Effective value needed without change
+    needed: i64,
+    /// Fee for adding a change output
+    change_fee: i64,
+    /// Fee for adding a change output and spending it later
+    cost_of_change: i64,
+}
+
+impl<'a> Costs<'a> {
+    fn new(target: &'a SelectionTarget, options: &'a CoinSelectionOptions) -> Self {
+        let rate = target.fee_rate as i64;
+        let change_fee = rate * target.change_output_size as i64;
+        Self {
+            target,
+            options,
+            needed: target.amount as i64 + rate * target.base_size as i64,
+            change_fee,
+            cost_of_change: change_fee + options.long_term_fee_rate as i64 * target.change_input_size as i64,
+        }
+    }
+
+    fn input_fee(&self, candidate: &Candidate) -> i64 {
+        self.target.fee_rate as i64 * candidate.input_size as i64
+    }
+
+    fn effective_value(&self, candidate: &Candidate) -> i64 {
+        candidate.utxo.amount as i64 - self.input_fee(candidate)
+    }
+
+    /// Extra fee the inputs cost now compared with spending them at the long-term rate
+    fn input_waste(&self, coins: &[&Candidate]) -> i64 {
+        let rate_difference = self.target.fee_rate as i64 - self.options.long_term_fee_rate as i64;
+        coins.iter().map(|coin| rate_difference * coin.input_size as i64).sum()
+    }
+
+    /// Effective value a selection with change must reach
+    fn needed_with_change(&self) -> i64 {
+        self.needed + self.change_fee + self.options.dust_limit as i64
+    }
+
+    /// Split what `coins` hold beyond the payment into change and fee
+    fn finish(&self, coins: &[&Candidate], algorithm: Algorithm) -> Selection {
+        let total: i64 = coins.iter().map(|coin| self.effective_value(coin)).sum();
+        let input_fees: i64 = coins.iter().map(|coin| self.input_fee(coin)).sum();
+        let base_fee = self.needed - self.target.amount as i64;
+        let excess = total - self.needed;
+
+        let (change, fee, waste) = if excess - self.change_fee >= self.options.dust_limit as i64 {
+            let change = excess - self.change_fee;
+            (change, base_fee + input_fees + self.change_fee, self.cost_of_change)
+        } else {
+            (0, base_fee + input_fees + excess, excess)
+        };
+
+        Selection {
+            coins: coins.iter().map(|coin| coin.utxo.clone()).collect(),
+            change: change as u64,
+            fee: fee as u64,
+            algorithm,
+            waste: self.input_waste(coins) + waste,
+        }
+    }
+}
+
+fn select_from<R: Rng + ?Sized>(
+    candidates: &[Candidate],
+    target: &SelectionTarget,
+    options: &CoinSelectionOptions,
+    rng: &mut R,
+) -> Result<Selection, CoinSelectionError> {
+    let costs = Costs::new(target, options);
+
+    // Coins worth less than the fee to spend them only lower the payment
+    let mut usable: Vec<&Candidate> = candidates
+        .iter()
+        .filter(|candidate| costs.effective_value(candidate) > 0)
+        .collect();
+    usable.sort_by_key(|candidate| std::cmp::Reverse(costs.effective_value(candidate)));
+
+    let available: i64 = usable.iter().map(|candidate| costs.effective_value(candidate)).sum();
+    if available < costs.needed {
+        return Err(CoinSelectionError::InsufficientFunds {
+            needed: costs.needed as u64,
+            available: available.max(0) as u64,
+        });
+    }
+
+    let results = [
+        branch_and_bound(&usable, &costs).map(|coins| costs.finish(&coins, Algorithm::BranchAndBound)),
+        knapsack(&usable, &costs, rng).map(|coins| costs.finish(&coins, Algorithm::Knapsack)),
+        single_random_draw(&usable, &costs, rng).map(|coins| costs.finish(&coins, Algorithm::SingleRandomDraw)),
+    ];
+
+    // Enough value for the payment but not for change: spend everything
+    let fallback = || costs.finish(&usable, Algorithm::Knapsack);
+    Ok(results
+        .into_iter()
+        .flatten()
+        .min_by_key(|selection| selection.waste)
+        .unwrap_or_else(fallback))
+}
+
+/// Depth-first search for a set landing between the payment and the payment plus
+/// the cost of change, so that leaving out change is cheaper than creating it
+///
+/// `coins` must be sorted by decreasing effective value.
+fn branch_and_bound<'a>(coins: &[&'a Candidate], costs: &Costs) -> Option<Vec<&'a Candidate>> {
+    let values: Vec<i64> = coins.iter().map(|coin| costs.effective_value(coin)).collect();
+    let upper = costs.needed + costs.cost_of_change;
+    let mut remaining: i64 = values.iter().sum();
+
+    let mut included = vec![false; coins.len()];
+    let mut best: Option<(i64, Vec<bool>)> = None;
+    let mut value = 0i64;
+    let mut depth = 0usize;
+
+    for _ in 0..BNB_MAX_TRIES {
+        let backtrack = if value + remaining < costs.needed || value > upper {
+            true
+        } else if value >= costs.needed {
+            // Prefer the set giving up the least excess
+            let waste = costs.input_waste(&selected(coins, &included)) + value - costs.needed;
+            if best.as_ref().map_or(true, |(best_waste, _)| waste < *best_waste) {
+                best = Some((waste, included.clone()));
+            }
+            true
+        } else {
+            depth >= coins.len()
+        };
+
+        if backtrack {
+            // Walk back to the last included coin and try leaving it out
+            while depth > 0 && !included[depth - 1] {
+                depth -= 1;
+                remaining += values[depth];
+            }
+            if depth == 0 {
+                break;
+            }
+            included[depth - 1] = false;
+            value -= values[depth - 1];
+        } else {
+            remaining -= values[depth];
+            included[depth] = true;
+            value += values[depth];
+            depth += 1;
+        }
+    }
+
+    best.map(|(_, included)| selected(coins, &included))
+}
+
+fn selected<'a>(coins: &[&'a Candidate], included: &[bool]) -> Vec<&'a Candidate> {
+    coins
+        .iter()
+        .zip(included)
+        .filter(|(_, included)| **included)
+        .map(|(coin, _)| *coin)
+        .collect()
+}
+
+/// Smallest total found by random subsets reaching the payment plus change,
+/// or the smallest single coin that does so alone if that is smaller
+fn knapsack<'a, R: Rng + ?Sized>(
+    coins: &[&'a Candidate],
+    costs: &Costs,
+    rng: &mut R,
+) -> Option<Vec<&'a Candidate>> {
+    let needed = costs.needed_with_change();
+    let values: Vec<i64> = coins.iter().map(|coin| costs.effective_value(coin)).collect();
+    if values.iter().sum::<i64>() < needed {
+        return None;
+    }
+
+    let mut best: Vec<bool> = vec![true; coins.len()];
+    let mut best_value: i64 = values.iter().sum();
+    for _ in 0..KNAPSACK_ITERATIONS {
+        if best_value == needed {
+            break;
+        }
+        let mut included = vec![false; coins.len()];
+        let mut value = 0;
+        let mut reached = false;
+        // A random pass, then, if that fell short, a pass adding whatever it skipped
+        for pass in 0..2 {
+            if reached {
+                break;
+            }
+            for i in 0..coins.len() {
+                let take = if pass == 0 { rng.gen_bool(0.5) } else { !included[i] };
+                if !take {
+                    continue;
+                }
+                included[i] = true;
+                value += values[i];
+                if value >= needed {
+                    reached = true;
+                    if value < best_value {
+                        best_value = value;
+                        best = included.clone();
+                    }
+                    // Drop the coin again to look for a closer total
+                    included[i] = false;
+                    value -= values[i];
+                }
+            }
+        }
+    }
+
+    let single = values
+        .iter()
+        .enumerate()
+        .filter(|(_, value)| **value >= needed)
+        .min_by_key(|(_, value)| **value);
+    if let Some((i, value)) = single {
+        if *value <= best_value {
+            return Some(vec![coins[i]]);
+        }
+    }
+
+    Some(selected(coins, &best))
+}
+
+/// Coins in random order until the payment plus change is covered
+fn single_random_draw<'a, R: Rng + ?Sized>(
+    coins: &[&'a Candidate],
+    costs: &Costs,
+    rng: &mut R,
+) -> Option<Vec<&'a Candidate>> {
+    let mut shuffled = coins.to_vec();
+    shuffled.shuffle(rng);
+
+    let needed = costs.needed_with_change();
+    let mut value = 0;
+    let mut chosen = Vec::new();
+    for coin in shuffled {
+        value += costs.effective_value(coin);
+        chosen.push(coin);
+        if value >= needed {
+            return Some(chosen);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn candidate(seed: u8, amount: u64, account: Option<&str>) -> Candidate {
+        Candidate {
+            utxo: UTXO {
+                tx_hash: [seed; 32],
+                output_index: 0,
+                amount,
+                script_pubkey: vec![],
+                account: account.map(str::to_string),
+            },
+            input_size: 150,
+        }
+    }
+
+    fn target(amount: u64, fee_rate: u64) -> SelectionTarget {
+        SelectionTarget {
+            amount,
+            fee_rate,
+            base_size: 60,
+            change_output_size: 43,
+            change_input_size: 150,
+        }
+    }
+
+    #[test]
+    fn test_changeless_match() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let coins = [
+            candidate(1, 100_000, None),
+            candidate(2, 30_150, None),
+            candidate(3, 20_150, None),
+            candidate(4, 7_000, None),
+        ];
+
+        // Coins 2 and 3 cover the payment plus the fee at 1 sat/byte exactly
+        let selection = select_coins(&coins, &target(50_000 - 60, 1), &CoinSelectionOptions::default(), &mut rng).unwrap();
+        assert_eq!(selection.algorithm, Algorithm::BranchAndBound);
+        assert_eq!(selection.change, 0);
+        let mut amounts: Vec<u64> = selection.coins.iter().map(|coin| coin.amount).collect();
+        amounts.sort();
+        assert_eq!(amounts, vec![20_150, 30_150]);
+        assert_eq!(selection.fee, 300 + 60);
+    }
+
+    #[test]
+    fn test_change_and_dust() {
+        let mut rng = StdRng::seed_from_u64(2);
+        let options = CoinSelectionOptions::default();
+        let coins = [candidate(1, 100_000, None)];
+
+        // Plenty left over: pay change, and account for every byte in the fee
+        let selection = select_coins(&coins, &target(40_000, 2), &options, &mut rng).unwrap();
+        assert_eq!(selection.fee, 2 * (60 + 150 + 43));
+        assert_eq!(selection.change + selection.fee + 40_000, 100_000);
+
+        // Less than the dust limit over: no change output, the rest goes to the fee
+        let amount = 100_000 - 2 * (60 + 150) - 500;
+        let selection = select_coins(&coins, &target(amount, 2), &options, &mut rng).unwrap();
+        assert_eq!(selection.change, 0);
+        assert_eq!(selection.fee, 100_000 - amount);
+
+        // Coins costing more to spend than they hold are ignored
+        let dusty = [candidate(1, 100, None), candidate(2, 1_000, None)];
+        let selection = select_coins(&dusty, &target(500, 2), &options, &mut rng).unwrap();
+        assert_eq!(selection.coins.len(), 1);
+        assert_eq!(
+            select_coins(&dusty, &target(600, 2), &options, &mut rng).unwrap_err(),
+            CoinSelectionError::InsufficientFunds { needed: 720, available: 700 }
+        );
+    }
+
+    #[test]
+    fn test_avoid_mixing_accounts() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let coins = [
+            candidate(1, 30_000, Some("savings")),
+            candidate(2, 30_000, Some("spending")),
+            candidate(3, 80_000, Some("spending")),
+        ];
+        let options = CoinSelectionOptions { avoid_mixing_accounts: true, ..Default::default() };
+
+        let selection = select_coins(&coins, &target(50_000, 1), &options, &mut rng).unwrap();
+        assert!(selection.coins.iter().all(|coin| coin.account.as_deref() == Some("spending")));
+
+        // No single account can pay, even though the wallet as a whole could
+        assert!(select_coins(&coins, &target(115_000, 1), &options, &mut rng).is_err());
+        let mixed = CoinSelectionOptions::default();
+        assert!(select_coins(&coins, &target(115_000, 1), &mixed, &mut rng).is_ok());
+    }
+
+    #[test]
+    fn test_witness_size() {
+        assert!(witness_size(SignatureType::Secp256k1, 0, 33) < 150);
+        assert!(witness_size(SignatureType::Dilithium, 3, 1952) > 5_000);
+    }
+}
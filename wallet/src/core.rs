@@ -1,21 +1,32 @@
 use bitcoin::{
-    network::Network,
-    secp256k1::{Secp256k1},
-    Address, PrivateKey, PublicKey, Transaction,
-    hashes::Hash,
-    sighash::{SighashCache, EcdsaSighashType},
-    Amount,
+    secp256k1::{Message, Secp256k1, SecretKey},
+    PrivateKey, PublicKey,
 };
-use std::str::FromStr;
+use btclib::config::NetworkType;
+use btclib::crypto::quantum::{QuantumError, QuantumKeyPair, QuantumParameters, QuantumScheme};
+use btclib::crypto::signature::SignatureType;
+use btclib::types::address::{Address, AddressError};
+use btclib::types::psbt::{PartiallySignedTransaction, PsbtError};
+use btclib::types::script::{self, InputWitness};
+use btclib::types::transaction::{Transaction, TransactionInput, TransactionOutput};
 use std::collections::HashMap;
+use std::fmt;
 use std::path::PathBuf;
-use sha2::Digest;
 use serde::{Serialize, Deserialize};
 
+use crate::coin_selection::{
+    self, Candidate, CoinSelectionError, CoinSelectionOptions, SelectionTarget, INPUT_BASE_SIZE, OUTPUT_BASE_SIZE,
+};
+use crate::keystore::{self, KdfParams, KeystoreError, WalletKey};
+use crate::network::TransactionBroadcaster;
+
+/// Security level used for Dilithium keys generated by the wallet
+pub(crate) const DILITHIUM_SECURITY_LEVEL: u8 = 3;
+
 #[derive(Debug, thiserror::Error)]
 pub enum WalletError {
-    #[error("Bitcoin error")]
-    Bitcoin(String),
+    #[error("Address error: {0}")]
+    Address(#[from] AddressError),
     #[error("IO error: {0}")]
     Io(#[from] std::io::Error),
     #[error("Invalid address: {0}")]
@@ -38,14 +49,45 @@ pub enum WalletError {
     BincodeError(#[from] bincode::Error),
     #[error("Network error: {0}")]
     NetworkError(String),
+    #[error("Keystore error: {0}")]
+    Keystore(#[from] KeystoreError),
+    #[error("Wallet file is encrypted; a passphrase is required")]
+    Encrypted,
+    #[error("Partially signed transaction error: {0}")]
+    Psbt(#[from] PsbtError),
 }
 
-#[derive(Debug)]
 pub struct Wallet {
     private_key: PrivateKey,
-    network: Network,
+    /// Post-quantum key; when present, new coins are received to it
+    quantum_key: Option<QuantumKeyPair>,
+    network: NetworkType,
     utxos: HashMap<[u8; 32], Vec<UTXO>>,
     wallet_path: PathBuf,
+    /// Key the wallet file is encrypted with, if any
+    file_key: Option<WalletKey>,
+    coin_selection: CoinSelectionOptions,
+}
+
+/// Network tag used when exporting the secp256k1 key as WIF
+fn wif_network(network: NetworkType) -> bitcoin::Network {
+    match network {
+        NetworkType::Mainnet => bitcoin::Network::Bitcoin,
+        NetworkType::Testnet => bitcoin::Network::Testnet,
+        NetworkType::Regtest => bitcoin::Network::Regtest,
+    }
+}
+
+impl fmt::Debug for Wallet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Key material is deliberately left out
+        f.debug_struct("Wallet")
+            .field("network", &self.network)
+            .field("quantum", &self.quantum_key.is_some())
+            .field("utxos", &self.utxos)
+            .field("wallet_path", &self.wallet_path)
+            .finish_non_exhaustive()
+    }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -54,28 +96,53 @@ pub struct UTXO {
     pub output_index: u32,
     pub amount: u64,
     pub script_pubkey: Vec<u8>,
+    /// Account the coin was received to, if the wallet tracks accounts
+    #[serde(default)]
+    pub account: Option<String>,
 }
 
 impl Wallet {
-    pub fn new(network: Network) -> Result<Self, WalletError> {
+    pub fn new(network: NetworkType) -> Result<Self, WalletError> {
         let secp = Secp256k1::new();
         let (secret_key, _) = secp.generate_keypair(&mut rand::thread_rng());
-        let private_key = PrivateKey::new(secret_key, network);
+        let private_key = PrivateKey::new(secret_key, wif_network(network));
 
         Ok(Self {
             private_key,
+            quantum_key: None,
             network,
             utxos: HashMap::new(),
             wallet_path: PathBuf::new(),
+            file_key: None,
+            coin_selection: CoinSelectionOptions::default(),
         })
     }
 
-    pub fn from_private_key(private_key: PrivateKey, network: Network) -> Result<Self, WalletError> {
+    /// Create a wallet that receives to a Dilithium key
+    ///
+    /// The secp256k1 key is still generated so classical coins sent to it remain spendable.
+    pub fn new_quantum(network: NetworkType) -> Result<Self, WalletError> {
+        let mut wallet = Self::new(network)?;
+        let quantum_key = QuantumKeyPair::generate(
+            &mut rand::rngs::OsRng,
+            QuantumParameters {
+                scheme: QuantumScheme::Dilithium,
+                security_level: DILITHIUM_SECURITY_LEVEL,
+            },
+        ).map_err(|e| WalletError::SigningError(e.to_string()))?;
+        wallet.quantum_key = Some(quantum_key);
+        Ok(wallet)
+    }
+
+    pub fn from_private_key(private_key: PrivateKey, network: NetworkType) -> Result<Self, WalletError> {
         Ok(Self {
             private_key,
+            quantum_key: None,
             network,
             utxos: HashMap::new(),
             wallet_path: PathBuf::new(),
+            file_key: None,
+            coin_selection: CoinSelectionOptions::default(),
         })
     }
 
@@ -84,27 +151,193 @@ impl Wallet {
         self.private_key.public_key(&secp)
     }
 
+    pub fn network(&self) -> NetworkType {
+        self.network
+    }
+
+    pub fn coin_selection(&self) -> &CoinSelectionOptions {
+        &self.coin_selection
+    }
+
+    /// Change how coins are chosen for future payments
+    pub fn set_coin_selection(&mut self, options: CoinSelectionOptions) {
+        self.coin_selection = options;
+    }
+
+    /// Address this wallet receives payments to
     pub fn get_address(&self) -> Result<Address, WalletError> {
-        Address::p2wpkh(&self.get_public_key(), self.network)
-            .map_err(|e| WalletError::Bitcoin(e.to_string()))
+        Ok(Address::from_script(self.network, &self.locking_script())?)
+    }
+
+    /// Parse a recipient address, rejecting addresses for other networks
+    pub fn parse_address(&self, address: &str) -> Result<Address, WalletError> {
+        Address::parse_for_network(address, self.network)
+            .map_err(|e| WalletError::InvalidAddress(format!("{}: {}", address, e)))
+    }
+
+    /// Locking script for coins sent to this wallet's secp256k1 key
+    pub fn secp256k1_script(&self) -> Vec<u8> {
+        script::pay_to_pubkey_hash(SignatureType::Secp256k1, 0, &self.get_public_key().inner.serialize())
+    }
+
+    /// Locking script for coins sent to this wallet's Dilithium key, if it has one
+    pub fn dilithium_script(&self) -> Option<Vec<u8>> {
+        self.quantum_key.as_ref().map(|key| {
+            script::pay_to_pubkey_hash(SignatureType::Dilithium, key.parameters.security_level, &key.public_key)
+        })
+    }
+
+    /// Locking script this wallet receives payments and change to
+    pub fn locking_script(&self) -> Vec<u8> {
+        self.dilithium_script().unwrap_or_else(|| self.secp256k1_script())
     }
 
+    /// Sign every input of `tx` with the key that controls the output it spends
     pub fn sign_transaction(&self, tx: &mut Transaction) -> Result<(), WalletError> {
-        // TODO: Implement transaction signing
+        let spent_outputs = tx
+            .inputs()
+            .iter()
+            .map(|input| {
+                self.find_utxo(input.prev_tx_hash(), input.prev_output_index())
+                    .map(|utxo| TransactionOutput::new(utxo.amount, utxo.script_pubkey.clone()))
+                    .ok_or(WalletError::UTXONotFound)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        for (index, spent) in spent_outputs.iter().enumerate() {
+            let witness = self.sign_input(tx, index, spent)?;
+            tx.set_signature_script(index, witness.to_script());
+        }
+
         Ok(())
     }
 
+    /// Build and sign a transaction paying `amount` to `recipient` with a fixed `fee`
+    ///
+    /// Whatever the selected coins hold beyond `amount + fee` is returned to the
+    /// wallet's locking script as a change output after the payment, unless it
+    /// is below the dust limit, in which case it is added to the fee.
     pub fn create_transaction(
         &self,
-        recipient: &str,
+        recipient: &Address,
         amount: u64,
         fee: u64,
     ) -> Result<Transaction, WalletError> {
-        let recipient_address = Address::from_str(recipient)
-            .map_err(|_| WalletError::InvalidAddress(recipient.to_string()))?;
+        let psbt = self.create_psbt(recipient, amount, fee)?;
+        self.sign_and_extract(psbt)
+    }
 
-        // TODO: Implement transaction creation
-        unimplemented!()
+    /// Build and sign a transaction paying `amount` to `recipient` at `fee_rate` satoshis per byte
+    pub fn create_transaction_at_fee_rate(
+        &self,
+        recipient: &Address,
+        amount: u64,
+        fee_rate: u64,
+    ) -> Result<Transaction, WalletError> {
+        let psbt = self.create_psbt_at_fee_rate(recipient, amount, fee_rate)?;
+        self.sign_and_extract(psbt)
+    }
+
+    fn sign_and_extract(&self, mut psbt: PartiallySignedTransaction) -> Result<Transaction, WalletError> {
+        self.sign_psbt(&mut psbt)?;
+        psbt.finalize()?;
+        Ok(psbt.extract()?)
+    }
+
+    /// Build the same payment as [`Wallet::create_transaction`] without signing it
+    ///
+    /// Every input carries the output it spends, so the result can be signed
+    /// by parties that do not track this wallet's coins.
+    pub fn create_psbt(
+        &self,
+        recipient: &Address,
+        amount: u64,
+        fee: u64,
+    ) -> Result<PartiallySignedTransaction, WalletError> {
+        self.build_psbt(recipient, amount, fee, 0)
+    }
+
+    /// Build the same payment as [`Wallet::create_transaction_at_fee_rate`] without signing it
+    pub fn create_psbt_at_fee_rate(
+        &self,
+        recipient: &Address,
+        amount: u64,
+        fee_rate: u64,
+    ) -> Result<PartiallySignedTransaction, WalletError> {
+        self.build_psbt(recipient, amount, 0, fee_rate)
+    }
+
+    /// Select coins paying `amount` plus `fee`, plus `fee_rate` for every byte of the transaction
+    fn build_psbt(
+        &self,
+        recipient: &Address,
+        amount: u64,
+        fee: u64,
+        fee_rate: u64,
+    ) -> Result<PartiallySignedTransaction, WalletError> {
+        if amount == 0 {
+            return Err(WalletError::InvalidAmount(amount));
+        }
+        if recipient.network() != self.network {
+            return Err(WalletError::InvalidAddress(format!(
+                "{} is not a {:?} address", recipient, self.network
+            )));
+        }
+
+        let mut outputs = vec![TransactionOutput::new(amount, recipient.script_pubkey())];
+        let change_script = self.locking_script();
+        let target = SelectionTarget {
+            amount: amount.checked_add(fee).ok_or(WalletError::InvalidAmount(amount))?,
+            fee_rate,
+            base_size: Transaction::new(1, vec![], outputs.clone(), 0).calculate_size(),
+            change_output_size: OUTPUT_BASE_SIZE + change_script.len(),
+            change_input_size: self.input_size(&change_script),
+        };
+        let candidates: Vec<Candidate> = self
+            .utxos
+            .values()
+            .flatten()
+            .map(|utxo| Candidate {
+                utxo: utxo.clone(),
+                input_size: self.input_size(&utxo.script_pubkey),
+            })
+            .collect();
+        let selection = coin_selection::select_coins(&candidates, &target, &self.coin_selection, &mut rand::thread_rng())
+            .map_err(|e| match e {
+                CoinSelectionError::InsufficientFunds { needed, .. } => WalletError::InsufficientFunds(needed),
+            })?;
+
+        let inputs = selection
+            .coins
+            .iter()
+            .map(|utxo| TransactionInput::new(utxo.tx_hash, utxo.output_index, vec![], 0xffffffff))
+            .collect();
+        if selection.change > 0 {
+            outputs.push(TransactionOutput::new(selection.change, change_script));
+        }
+
+        let mut psbt = PartiallySignedTransaction::new(&Transaction::new(1, inputs, outputs, 0));
+        for (input, utxo) in psbt.inputs.iter_mut().zip(&selection.coins) {
+            input.spent_output = Some(TransactionOutput::new(utxo.amount, utxo.script_pubkey.clone()));
+        }
+        Ok(psbt)
+    }
+
+    /// Add this wallet's signatures to `psbt`, returning how many inputs it signed
+    ///
+    /// Inputs spending outputs this wallet holds no key for are left for other signers.
+    pub fn sign_psbt(&self, psbt: &mut PartiallySignedTransaction) -> Result<usize, WalletError> {
+        let mut signed = 0;
+        for index in 0..psbt.inputs.len() {
+            let spent = match &psbt.inputs[index].spent_output {
+                Some(spent) if self.holds_key_for(spent.pub_key_script()) => spent.clone(),
+                _ => continue,
+            };
+            let witness = self.sign_input(psbt.unsigned_tx(), index, &spent)?;
+            psbt.add_signature(index, witness)?;
+            signed += 1;
+        }
+        Ok(signed)
     }
 
     /// Get wallet balance
@@ -115,37 +348,73 @@ impl Wallet {
             .sum()
     }
 
-    /// Send a transaction
-    pub fn send_transaction(
-        &self,
-        recipient: &str,
+    /// Create, sign and broadcast a payment, then update the wallet's coins
+    ///
+    /// Spent UTXOs are dropped and the change output, if any, is added once the
+    /// broadcaster has accepted the transaction.
+    pub async fn send_transaction<B: TransactionBroadcaster + ?Sized>(
+        &mut self,
+        broadcaster: &B,
+        recipient: &Address,
         amount: u64,
         fee: u64,
     ) -> Result<[u8; 32], WalletError> {
-        // Create and sign transaction
         let transaction = self.create_transaction(recipient, amount, fee)?;
-        let tx_hash = transaction.txid().to_raw_hash().to_byte_array();
-        
-        // For now, just return the transaction hash
-        // In a real implementation, this would broadcast to the network
+        broadcaster
+            .broadcast(&transaction)
+            .await
+            .map_err(|e| WalletError::NetworkError(e.to_string()))?;
+
+        let tx_hash = transaction.hash();
+        for input in transaction.inputs() {
+            self.remove_utxo(input.prev_tx_hash(), input.prev_output_index());
+        }
+        let own_script = self.locking_script();
+        for (index, output) in transaction.outputs().iter().enumerate() {
+            if output.pub_key_script() == own_script.as_slice() {
+                self.add_utxo(UTXO {
+                    tx_hash,
+                    output_index: index as u32,
+                    amount: output.amount(),
+                    script_pubkey: output.pub_key_script().to_vec(),
+                    account: None,
+                });
+            }
+        }
+
         Ok(tx_hash)
     }
 
-    /// Create signature hash for an input
-    fn create_signature_hash(&self, transaction: &Transaction, input_index: usize) -> Result<[u8; 32], WalletError> {
-        let mut sighash_cache = SighashCache::new(transaction);
-        let total_amount = Amount::from_sat(self.utxos.values().flatten().map(|utxo| utxo.amount).sum());
-        
-        let sighash = sighash_cache
-            .p2wpkh_signature_hash(
-                input_index,
-                &self.get_address()?.script_pubkey(),
-                total_amount,
-                EcdsaSighashType::All,
-            )
-            .map_err(|e| WalletError::Transaction(e.to_string()))?;
+    /// Produce the witness for input `index` of `tx` spending `spent`
+    fn sign_input(
+        &self,
+        tx: &Transaction,
+        index: usize,
+        spent: &TransactionOutput,
+    ) -> Result<InputWitness, WalletError> {
+        let sighash = script::signature_hash(tx, index, spent)
+            .map_err(|e| WalletError::SigningError(e.to_string()))?;
+        let spent_script = spent.pub_key_script();
+
+        if spent_script == self.secp256k1_script().as_slice() {
+            return Ok(secp256k1_witness(&self.private_key.inner, &sighash));
+        }
+
+        if let Some(key) = &self.quantum_key {
+            if Some(spent_script) == self.dilithium_script().as_deref() {
+                return quantum_witness(key, &sighash)
+                    .map_err(|e| WalletError::SigningError(e.to_string()));
+            }
+        }
 
-        Ok(sighash.to_raw_hash().to_byte_array())
+        Err(WalletError::SigningError(format!(
+            "input {} spends an output this wallet holds no key for", index
+        )))
+    }
+
+    /// Whether the wallet can sign for outputs locked by `script`
+    fn holds_key_for(&self, script: &[u8]) -> bool {
+        script == self.secp256k1_script().as_slice() || Some(script) == self.dilithium_script().as_deref()
     }
 
     /// Find a UTXO by transaction hash and output index
@@ -155,22 +424,19 @@ impl Wallet {
                 .find(|utxo| utxo.output_index == output_index))
     }
 
-    /// Select UTXOs for spending
-    fn select_utxos(&self, amount: u64) -> Result<Vec<UTXO>, WalletError> {
-        let mut selected = Vec::new();
-        let mut selected_amount = 0;
-
-        for utxos in self.utxos.values() {
-            for utxo in utxos {
-                selected.push(utxo.clone());
-                selected_amount += utxo.amount;
-                if selected_amount >= amount {
-                    return Ok(selected);
-                }
-            }
-        }
-
-        Err(WalletError::InsufficientFunds(amount))
+    /// Bytes an input spending `script` adds once signed
+    ///
+    /// Scripts the wallet holds no key for are sized as secp256k1 spends.
+    fn input_size(&self, script: &[u8]) -> usize {
+        let witness = match &self.quantum_key {
+            Some(key) if Some(script) == self.dilithium_script().as_deref() => coin_selection::witness_size(
+                SignatureType::Dilithium,
+                key.parameters.security_level,
+                key.public_key.len(),
+            ),
+            _ => coin_selection::witness_size(SignatureType::Secp256k1, 0, 33),
+        };
+        INPUT_BASE_SIZE + witness
     }
 
     /// Add a new UTXO to the wallet
@@ -190,12 +456,66 @@ impl Wallet {
         }
     }
     
-    /// Save wallet to file
+    /// Save wallet to file, encrypted if `encrypt` has been called
     pub fn save(&self) -> Result<(), WalletError> {
-        let json = serde_json::to_string_pretty(&self)?;
-        std::fs::write(&self.wallet_path, json)?;
+        let json = zeroize::Zeroizing::new(serde_json::to_vec_pretty(&self)?);
+        let contents = match &self.file_key {
+            Some(key) => key.seal(&json)?,
+            None => json.to_vec(),
+        };
+        std::fs::write(&self.wallet_path, contents)?;
         Ok(())
     }
+
+    /// Encrypt the wallet file under `passphrase` from now on and rewrite it
+    pub fn encrypt(&mut self, passphrase: &str, params: KdfParams) -> Result<(), WalletError> {
+        self.file_key = Some(WalletKey::derive(passphrase, params)?);
+        self.save()
+    }
+
+    /// Load a wallet file; encrypted files need `passphrase`
+    pub fn load(wallet_path: PathBuf, passphrase: Option<&str>) -> Result<Self, WalletError> {
+        let data = zeroize::Zeroizing::new(std::fs::read(&wallet_path)?);
+        let mut wallet: Self = if keystore::is_encrypted(&data) {
+            let passphrase = passphrase.ok_or(WalletError::Encrypted)?;
+            let (json, key) = keystore::open(&data, passphrase)?;
+            let mut wallet: Self = serde_json::from_slice(&json)?;
+            wallet.file_key = Some(key);
+            wallet
+        } else {
+            serde_json::from_slice(&data)?
+        };
+        wallet.wallet_path = wallet_path;
+        Ok(wallet)
+    }
+}
+
+/// Witness of a secp256k1 key over `sighash`
+pub(crate) fn secp256k1_witness(secret_key: &SecretKey, sighash: &[u8; 32]) -> InputWitness {
+    let secp = Secp256k1::new();
+    let signature = secp.sign_ecdsa(&Message::from_digest(*sighash), secret_key);
+    InputWitness {
+        sig_type: SignatureType::Secp256k1,
+        security_level: 0,
+        public_key: secret_key.public_key(&secp).serialize().to_vec(),
+        signature: signature.serialize_compact().to_vec(),
+    }
+}
+
+/// Witness of a post-quantum key over `sighash`
+pub(crate) fn quantum_witness(key: &QuantumKeyPair, sighash: &[u8; 32]) -> Result<InputWitness, QuantumError> {
+    let sig_type = match key.parameters.scheme {
+        QuantumScheme::Dilithium => SignatureType::Dilithium,
+        QuantumScheme::Falcon => SignatureType::Falcon,
+        QuantumScheme::Sphincs => SignatureType::Sphincs,
+        QuantumScheme::Hybrid(_) => SignatureType::Hybrid,
+    };
+    Ok(InputWitness {
+        sig_type,
+        security_level: key.parameters.security_level,
+        public_key: key.public_key.clone(),
+        signature: key.sign(sighash)?,
+    })
 }
 
 // Custom serialization for Wallet
@@ -207,11 +527,15 @@ impl Serialize for Wallet {
         use serde::ser::SerializeStruct;
         
         // Create a serializable structure
-        let mut state = serializer.serialize_struct("Wallet", 4)?;
+        let mut state = serializer.serialize_struct("Wallet", 6)?;
         state.serialize_field("private_key", &self.private_key.to_wif())?;
+        state.serialize_field("quantum_key", &self.quantum_key)?;
         state.serialize_field("network", &self.network)?;
-        state.serialize_field("utxos", &self.utxos)?;
+        // JSON maps need string keys, so coins are stored as a flat list
+        let utxos: Vec<&UTXO> = self.utxos.values().flatten().collect();
+        state.serialize_field("utxos", &utxos)?;
         state.serialize_field("wallet_path", &self.wallet_path.to_string_lossy().to_string())?;
+        state.serialize_field("coin_selection", &self.coin_selection)?;
         state.end()
     }
 }
@@ -225,9 +549,13 @@ impl<'de> Deserialize<'de> for Wallet {
         #[derive(Deserialize)]
         struct WalletData {
             private_key: String,
-            network: Network,
-            utxos: HashMap<[u8; 32], Vec<UTXO>>,
+            #[serde(default)]
+            quantum_key: Option<QuantumKeyPair>,
+            network: NetworkType,
+            utxos: Vec<UTXO>,
             wallet_path: String,
+            #[serde(default)]
+            coin_selection: CoinSelectionOptions,
         }
         
         let data = WalletData::deserialize(deserializer)?;
@@ -236,11 +564,19 @@ impl<'de> Deserialize<'de> for Wallet {
         let private_key = PrivateKey::from_wif(&data.private_key)
             .map_err(serde::de::Error::custom)?;
         
+        let mut utxos: HashMap<[u8; 32], Vec<UTXO>> = HashMap::new();
+        for utxo in data.utxos {
+            utxos.entry(utxo.tx_hash).or_default().push(utxo);
+        }
+        
         Ok(Wallet {
             private_key,
+            quantum_key: data.quantum_key,
             network: data.network,
-            utxos: data.utxos,
+            utxos,
             wallet_path: PathBuf::from(data.wallet_path),
+            file_key: None,
+            coin_selection: data.coin_selection,
         })
     }
 }
@@ -252,16 +588,22 @@ mod tests {
 
     #[test]
     fn test_wallet_creation() {
-        let wallet = Wallet::new(Network::Testnet).unwrap();
-        assert!(!wallet.get_address().unwrap().to_string().is_empty());
+        let wallet = Wallet::new(NetworkType::Testnet).unwrap();
+        let address = wallet.get_address().unwrap();
+        assert!(address.to_string().starts_with("tnova1q"));
+        assert_eq!(address.script_pubkey(), wallet.locking_script());
+
+        let quantum = Wallet::new_quantum(NetworkType::Testnet).unwrap().get_address().unwrap();
+        assert!(quantum.to_string().starts_with("tnova1p"));
+        assert!(quantum.is_quantum_resistant());
     }
 
     #[test]
     fn test_wallet_from_private_key() {
         let secp = Secp256k1::new();
         let (secret_key, _) = secp.generate_keypair(&mut rand::thread_rng());
-        let private_key = PrivateKey::new(secret_key, Network::Testnet);
-        let wallet = Wallet::from_private_key(private_key, Network::Testnet).unwrap();
+        let private_key = PrivateKey::new(secret_key, bitcoin::Network::Testnet);
+        let wallet = Wallet::from_private_key(private_key, NetworkType::Testnet).unwrap();
         assert!(!wallet.get_address().unwrap().to_string().is_empty());
     }
 
@@ -269,13 +611,14 @@ mod tests {
     fn test_utxo_management() {
         let dir = tempdir().unwrap();
         let wallet_path = dir.path().join("wallet.json");
-        let mut wallet = Wallet::new(Network::Testnet).unwrap();
+        let mut wallet = Wallet::new(NetworkType::Testnet).unwrap();
 
         let utxo = UTXO {
             tx_hash: [0u8; 32],
             output_index: 0,
             amount: 100_000,
             script_pubkey: vec![],
+            account: None,
         };
 
         wallet.add_utxo(utxo.clone());
@@ -286,13 +629,154 @@ mod tests {
     }
 
     #[test]
-    #[should_panic(expected = "not implemented")]
     fn test_insufficient_funds() {
-        let wallet = Wallet::new(Network::Testnet).unwrap();
-        let recipient_address = "tb1q7cy0njxmsxfj7qx282t0h499w6apaul6xuson5";
-        
-        // Should panic with unimplemented!()
-        wallet.create_transaction(recipient_address, 100_000, 1_000).unwrap();
+        let wallet = Wallet::new(NetworkType::Testnet).unwrap();
+        let recipient = Wallet::new(NetworkType::Testnet).unwrap().get_address().unwrap();
+
+        let result = wallet.create_transaction(&recipient, 100_000, 1_000);
+        assert!(matches!(result, Err(WalletError::InsufficientFunds(101_000))));
+    }
+
+    #[test]
+    fn test_create_transaction() {
+        let mut wallet = Wallet::new(NetworkType::Testnet).unwrap();
+        let recipient = Wallet::new_quantum(NetworkType::Testnet).unwrap().get_address().unwrap();
+        wallet.add_utxo(UTXO {
+            tx_hash: [2u8; 32],
+            output_index: 1,
+            amount: 100_000,
+            script_pubkey: wallet.secp256k1_script(),
+            account: None,
+        });
+
+        let tx = wallet.create_transaction(&recipient, 60_000, 1_000).unwrap();
+        assert_eq!(tx.outputs()[0].amount(), 60_000);
+        assert_eq!(tx.outputs()[0].pub_key_script(), recipient.script_pubkey().as_slice());
+        assert_eq!(tx.outputs()[1].amount(), 39_000);
+        assert_eq!(tx.outputs()[1].pub_key_script(), wallet.locking_script().as_slice());
+
+        let spent = TransactionOutput::new(100_000, wallet.secp256k1_script());
+        assert!(tx.verify_signatures(|_, _| Some(spent.clone())).is_ok());
+
+        // The signature does not carry over to a different payment
+        let forged = Transaction::new(1, tx.inputs().to_vec(), vec![tx.outputs()[0].clone()], 0);
+        assert!(forged.verify_signatures(|_, _| Some(spent.clone())).is_err());
+
+        // Recipients must be on the wallet's network
+        let mainnet = Wallet::new(NetworkType::Mainnet).unwrap().get_address().unwrap();
+        assert!(matches!(
+            wallet.create_transaction(&mainnet, 60_000, 1_000),
+            Err(WalletError::InvalidAddress(_))
+        ));
+        assert!(matches!(
+            wallet.parse_address(&mainnet.to_string()),
+            Err(WalletError::InvalidAddress(_))
+        ));
+        assert_eq!(wallet.parse_address(&recipient.to_string()).unwrap(), recipient);
+    }
+
+    #[test]
+    fn test_create_transaction_at_fee_rate() {
+        let mut wallet = Wallet::new(NetworkType::Testnet).unwrap();
+        let recipient = Wallet::new(NetworkType::Testnet).unwrap().get_address().unwrap();
+        for (seed, amount) in [(1u8, 50_000), (2, 20_000), (3, 10_000)] {
+            wallet.add_utxo(UTXO {
+                tx_hash: [seed; 32],
+                output_index: 0,
+                amount,
+                script_pubkey: wallet.secp256k1_script(),
+                account: None,
+            });
+        }
+
+        // The fee covers every byte of the signed transaction
+        let tx = wallet.create_transaction_at_fee_rate(&recipient, 25_000, 5).unwrap();
+        let spent: u64 = tx
+            .inputs()
+            .iter()
+            .map(|input| wallet.find_utxo(input.prev_tx_hash(), input.prev_output_index()).unwrap().amount)
+            .sum();
+        let paid: u64 = tx.outputs().iter().map(|output| output.amount()).sum();
+        assert_eq!(tx.outputs()[0].amount(), 25_000);
+        assert!(spent - paid >= 5 * tx.calculate_size() as u64);
+
+        // Change below the dust limit is left to the fee
+        let tx = wallet.create_transaction(&recipient, 79_700, 0).unwrap();
+        assert_eq!(tx.inputs().len(), 3);
+        assert_eq!(tx.outputs().len(), 1);
+
+        // A higher dust limit drops change that would otherwise be kept
+        let tx = wallet.create_transaction(&recipient, 79_000, 0).unwrap();
+        assert_eq!(tx.outputs().len(), 2);
+        wallet.set_coin_selection(CoinSelectionOptions { dust_limit: 2_000, ..Default::default() });
+        let tx = wallet.create_transaction(&recipient, 79_000, 0).unwrap();
+        assert_eq!(tx.outputs().len(), 1);
+    }
+
+    #[test]
+    fn test_sign_dilithium_inputs() {
+        let mut wallet = Wallet::new_quantum(NetworkType::Testnet).unwrap();
+        let recipient = Wallet::new(NetworkType::Testnet).unwrap().get_address().unwrap();
+        let classical = TransactionOutput::new(30_000, wallet.secp256k1_script());
+        let quantum = TransactionOutput::new(50_000, wallet.dilithium_script().unwrap());
+        for (tx_hash, output) in [([3u8; 32], &classical), ([4u8; 32], &quantum)] {
+            wallet.add_utxo(UTXO {
+                tx_hash,
+                output_index: 0,
+                amount: output.amount(),
+                script_pubkey: output.pub_key_script().to_vec(),
+                account: None,
+            });
+        }
+
+        // Spending both coins signs each input with its own scheme
+        let tx = wallet.create_transaction(&recipient, 70_000, 1_000).unwrap();
+        assert_eq!(tx.inputs().len(), 2);
+        let lookup = |hash: &[u8; 32], _| match hash[0] {
+            3 => Some(classical.clone()),
+            4 => Some(quantum.clone()),
+            _ => None,
+        };
+        assert!(tx.verify_signatures(lookup).is_ok());
+        assert_eq!(tx.outputs()[1].pub_key_script(), wallet.dilithium_script().unwrap().as_slice());
+    }
+
+    #[test]
+    fn test_multi_party_psbt() {
+        let alice = Wallet::new(NetworkType::Testnet).unwrap();
+        let bob = Wallet::new_quantum(NetworkType::Testnet).unwrap();
+        let recipient = Wallet::new(NetworkType::Testnet).unwrap().get_address().unwrap();
+        let alice_coin = TransactionOutput::new(40_000, alice.locking_script());
+        let bob_coin = TransactionOutput::new(60_000, bob.locking_script());
+
+        // A joint payment spending one coin from each wallet
+        let tx = Transaction::new(
+            1,
+            vec![
+                TransactionInput::new([5u8; 32], 0, vec![], 0xffffffff),
+                TransactionInput::new([6u8; 32], 0, vec![], 0xffffffff),
+            ],
+            vec![TransactionOutput::new(99_000, recipient.script_pubkey())],
+            0,
+        );
+        let mut psbt = PartiallySignedTransaction::new(&tx);
+        psbt.inputs[0].spent_output = Some(alice_coin.clone());
+        psbt.inputs[1].spent_output = Some(bob_coin.clone());
+
+        let mut bobs_copy: PartiallySignedTransaction = psbt.to_string().parse().unwrap();
+        assert_eq!(alice.sign_psbt(&mut psbt).unwrap(), 1);
+        assert_eq!(bob.sign_psbt(&mut bobs_copy).unwrap(), 1);
+        assert!(matches!(psbt.clone().finalize(), Err(PsbtError::MissingSignature(1))));
+
+        psbt.combine(&bobs_copy).unwrap();
+        psbt.finalize().unwrap();
+        let signed = psbt.extract().unwrap();
+        let lookup = |hash: &[u8; 32], _| match hash[0] {
+            5 => Some(alice_coin.clone()),
+            6 => Some(bob_coin.clone()),
+            _ => None,
+        };
+        assert!(signed.verify_signatures(lookup).is_ok());
     }
 
     #[test]
@@ -301,7 +785,7 @@ mod tests {
         let wallet_path = dir.path().join("wallet.json");
         
         // Create and save wallet
-        let mut wallet = Wallet::new(Network::Testnet).unwrap();
+        let mut wallet = Wallet::new(NetworkType::Testnet).unwrap();
         let address = wallet.get_address().unwrap().to_string();
         
         let utxo = UTXO {
@@ -309,13 +793,28 @@ mod tests {
             output_index: 0,
             amount: 100_000,
             script_pubkey: vec![],
+            account: None,
         };
         wallet.add_utxo(utxo);
         wallet.wallet_path = wallet_path.clone();
         wallet.save().unwrap();
 
-        // In a real implementation, we would load the wallet from file
-        // For now, just verifying that the address is correct
-        assert!(!address.is_empty());
+        let loaded = Wallet::load(wallet_path.clone(), None).unwrap();
+        assert_eq!(loaded.get_address().unwrap().to_string(), address);
+        assert_eq!(loaded.get_balance(), 100_000);
+
+        // Once encrypted the private key no longer appears in the file
+        let wif = wallet.private_key.to_wif();
+        wallet.encrypt("passphrase", keystore::test_params()).unwrap();
+        assert!(!std::fs::read_to_string(&wallet_path).unwrap().contains(&wif));
+        assert!(matches!(Wallet::load(wallet_path.clone(), None), Err(WalletError::Encrypted)));
+        assert!(matches!(
+            Wallet::load(wallet_path.clone(), Some("wrong")),
+            Err(WalletError::Keystore(KeystoreError::Decryption))
+        ));
+
+        let decrypted = Wallet::load(wallet_path, Some("passphrase")).unwrap();
+        assert_eq!(decrypted.get_address().unwrap().to_string(), address);
+        assert_eq!(decrypted.get_balance(), 100_000);
     }
 }
\ No newline at end of file
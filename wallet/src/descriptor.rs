@@ -0,0 +1,303 @@
+//! Output descriptors
+//!
+//! A descriptor names every address of an account from public data alone, so
+//! a wallet without the keys can watch the account's coins. Two forms exist:
+//!
+//! ```text
+//! pkh([d34db33f/44'/1'/0']xpub6C.../0/*)
+//! multi(2,[d34db33f/44'/1'/0']xpub6C.../0/*,[0badf00d/8844'/1'/0'/7']dilithium:3:<key 0>.<key 1>...)
+//! ```
+//!
+//! Key expressions are [`CosignerKey`]s: an optional origin in brackets, then
+//! either a BIP-32 extended public key whose child `0/i` is the key of address
+//! `i`, or, for post-quantum and hybrid keys, which have no public derivation,
+//! the scheme, the security level and the hex keys of addresses `0..n`
+//! separated by dots.
+
+use bitcoin::bip32::Xpub;
+use btclib::config::NetworkType;
+use btclib::crypto::signature::SignatureType;
+use btclib::types::address::{Address, AddressError};
+use btclib::types::psbt::KeyOrigin;
+use btclib::types::script::{MultisigPolicy, MAX_MULTISIG_KEYS};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+use thiserror::Error;
+
+use crate::multisig::{self, CosignerKey, CosignerKeys, MultisigError};
+
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum DescriptorError {
+    #[error("Invalid descriptor: {0}")]
+    Syntax(String),
+    #[error("Descriptor key error: {0}")]
+    Key(#[from] MultisigError),
+    #[error("Descriptor address error: {0}")]
+    Address(#[from] AddressError),
+}
+
+/// The addresses of an account, described by its public keys
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Descriptor {
+    /// `pkh(KEY)`: address `i` pays to key `i`
+    KeyHash(CosignerKey),
+    /// `multi(M,KEY,...)`: address `i` pays to an M-of-n policy over key `i` of each participant
+    Multisig { threshold: u8, keys: Vec<CosignerKey> },
+}
+
+impl Descriptor {
+    /// Parse a descriptor whose addresses belong to `network`
+    pub fn parse(s: &str, network: NetworkType) -> Result<Self, DescriptorError> {
+        let s: String = s.split_whitespace().collect();
+        let (function, args) = s
+            .strip_suffix(')')
+            .and_then(|s| s.split_once('('))
+            .ok_or_else(|| syntax("expected pkh(...) or multi(...)"))?;
+
+        let descriptor = match function {
+            "pkh" => Descriptor::KeyHash(parse_key(args, network)?),
+            "multi" => {
+                let (threshold, keys) = args.split_once(',').ok_or_else(|| syntax("multi needs a threshold and keys"))?;
+                let threshold = threshold
+                    .parse()
+                    .map_err(|_| syntax(&format!("invalid threshold {}", threshold)))?;
+                let keys = keys
+                    .split(',')
+                    .map(|key| parse_key(key, network))
+                    .collect::<Result<Vec<_>, _>>()?;
+                Descriptor::Multisig { threshold, keys }
+            }
+            other => return Err(syntax(&format!("unknown function {}", other))),
+        };
+        // Rules spanning several keys can only be checked once all are read
+        descriptor.validate()?;
+        Ok(descriptor)
+    }
+
+    fn validate(&self) -> Result<(), DescriptorError> {
+        match self {
+            Descriptor::KeyHash(key) => {
+                if !Address::supports(key.key_type()) {
+                    return Err(AddressError::UnsupportedKeyType(key.key_type()).into());
+                }
+            }
+            Descriptor::Multisig { threshold, keys } => {
+                if keys.is_empty() || keys.len() > MAX_MULTISIG_KEYS {
+                    return Err(MultisigError::InvalidSetup(format!(
+                        "{} keys, expected 1 to {}", keys.len(), MAX_MULTISIG_KEYS
+                    )).into());
+                }
+                if *threshold == 0 || *threshold as usize > keys.len() {
+                    return Err(MultisigError::InvalidSetup(format!("threshold {} of {}", threshold, keys.len())).into());
+                }
+                if let Some(key) = keys.iter().find(|key| !multisig::can_sign(key.key_type())) {
+                    return Err(MultisigError::UnsupportedKeyType(key.key_type()).into());
+                }
+                if let Some(key) = keys.iter().find(|key| key.network != keys[0].network) {
+                    return Err(MultisigError::WrongNetwork { expected: keys[0].network, found: key.network }.into());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn network(&self) -> NetworkType {
+        match self {
+            Descriptor::KeyHash(key) => key.network,
+            // Validation leaves at least one key, all on the same network
+            Descriptor::Multisig { keys, .. } => keys[0].network,
+        }
+    }
+
+    /// Scheme of the keys paid to; `None` for multisig descriptors
+    pub fn key_type(&self) -> Option<SignatureType> {
+        match self {
+            Descriptor::KeyHash(key) => Some(key.key_type()),
+            Descriptor::Multisig { .. } => None,
+        }
+    }
+
+    /// Every key expression, in the order written
+    pub fn keys(&self) -> &[CosignerKey] {
+        match self {
+            Descriptor::KeyHash(key) => std::slice::from_ref(key),
+            Descriptor::Multisig { keys, .. } => keys,
+        }
+    }
+
+    /// Policy behind address `index`; `None` for single-key descriptors
+    pub fn policy(&self, index: u32) -> Result<Option<MultisigPolicy>, DescriptorError> {
+        let Descriptor::Multisig { threshold, keys } = self else {
+            return Ok(None);
+        };
+        let keys = keys
+            .iter()
+            .map(|key| key.policy_key(index))
+            .collect::<Result<Vec<_>, _>>()?;
+        let policy = MultisigPolicy::new(*threshold, keys).map_err(|e| MultisigError::InvalidSetup(e.to_string()))?;
+        Ok(Some(policy))
+    }
+
+    /// Address `index`
+    pub fn address(&self, index: u32) -> Result<Address, DescriptorError> {
+        match self {
+            Descriptor::KeyHash(key) => {
+                let key = key.policy_key(index)?;
+                Ok(Address::from_public_key(self.network(), key.sig_type, key.security_level, &key.public_key)?)
+            }
+            Descriptor::Multisig { .. } => {
+                let policy = self.policy(index)?.expect("multisig descriptors have a policy");
+                Ok(Address::multisig(self.network(), policy.hash()))
+            }
+        }
+    }
+
+    /// Origin of every key behind address `index`, for partially signed transactions
+    pub fn key_origins(&self, index: u32) -> Vec<KeyOrigin> {
+        self.keys().iter().map(|key| key.key_origin(index)).collect()
+    }
+}
+
+impl fmt::Display for Descriptor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Descriptor::KeyHash(key) => write!(f, "pkh({})", KeyExpression(key)),
+            Descriptor::Multisig { threshold, keys } => {
+                write!(f, "multi({}", threshold)?;
+                for key in keys {
+                    write!(f, ",{}", KeyExpression(key))?;
+                }
+                f.write_str(")")
+            }
+        }
+    }
+}
+
+/// Descriptor text of one key
+struct KeyExpression<'a>(&'a CosignerKey);
+
+impl fmt::Display for KeyExpression<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}]", self.0.origin)?;
+        match &self.0.keys {
+            CosignerKeys::Classical(xpub) => write!(f, "{}/0/*", xpub),
+            CosignerKeys::Quantum { key_type, security_level, public_keys } => {
+                let keys: Vec<String> = public_keys.iter().map(hex::encode).collect();
+                write!(f, "{}:{}:{}", scheme_name(*key_type), security_level, keys.join("."))
+            }
+        }
+    }
+}
+
+fn parse_key(s: &str, network: NetworkType) -> Result<CosignerKey, DescriptorError> {
+    let (origin, body) = match s.strip_prefix('[') {
+        Some(rest) => {
+            let (origin, body) = rest.split_once(']').ok_or_else(|| syntax("unterminated key origin"))?;
+            let origin = KeyOrigin::from_str(origin).map_err(|e| syntax(&e.to_string()))?;
+            (Some(origin), body)
+        }
+        None => (None, s),
+    };
+
+    let (keys, fingerprint) = if let Some(xpub) = body.strip_suffix("/0/*") {
+        let xpub = Xpub::from_str(xpub).map_err(|e| syntax(&format!("invalid extended key: {}", e)))?;
+        (CosignerKeys::Classical(xpub), xpub.fingerprint().to_bytes())
+    } else {
+        let mut parts = body.splitn(3, ':');
+        let (scheme, level, public_keys) = match (parts.next(), parts.next(), parts.next()) {
+            (Some(scheme), Some(level), Some(public_keys)) => (scheme, level, public_keys),
+            _ => return Err(syntax(&format!("expected xpub/0/* or scheme:level:keys, found {}", body))),
+        };
+        let key_type = scheme_from_name(scheme).ok_or_else(|| syntax(&format!("unknown key scheme {}", scheme)))?;
+        let security_level = level.parse().map_err(|_| syntax(&format!("invalid security level {}", level)))?;
+        let public_keys = public_keys
+            .split('.')
+            .map(|key| hex::decode(key).map_err(|e| syntax(&format!("invalid public key: {}", e))))
+            .collect::<Result<Vec<_>, _>>()?;
+        (CosignerKeys::Quantum { key_type, security_level, public_keys }, [0u8; 4])
+    };
+
+    // Without an origin the key stands for itself
+    let origin = origin.unwrap_or(KeyOrigin { fingerprint, path: Vec::new() });
+    Ok(CosignerKey { network, origin, keys })
+}
+
+fn scheme_name(key_type: SignatureType) -> &'static str {
+    match key_type {
+        SignatureType::Dilithium => "dilithium",
+        SignatureType::Falcon => "falcon",
+        SignatureType::Hybrid => "hybrid",
+        SignatureType::Secp256k1 => "secp256k1",
+        _ => "unknown",
+    }
+}
+
+fn scheme_from_name(name: &str) -> Option<SignatureType> {
+    [SignatureType::Dilithium, SignatureType::Falcon, SignatureType::Hybrid]
+        .into_iter()
+        .find(|key_type| scheme_name(*key_type) == name.to_lowercase())
+}
+
+fn syntax(message: &str) -> DescriptorError {
+    DescriptorError::Syntax(message.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::bip32::Xpriv;
+    use bitcoin::secp256k1::Secp256k1;
+    use btclib::types::psbt::HARDENED;
+
+    fn xpub(seed: u8) -> Xpub {
+        let master = Xpriv::new_master(bitcoin::Network::Bitcoin, &[seed; 32]).unwrap();
+        Xpub::from_priv(&Secp256k1::new(), &master)
+    }
+
+    #[test]
+    fn test_descriptor_roundtrip() {
+        let single = format!("pkh([d34db33f/44'/1'/0']{}/0/*)", xpub(1));
+        let descriptor = Descriptor::parse(&single, NetworkType::Testnet).unwrap();
+        assert_eq!(descriptor.to_string(), single);
+        assert_eq!(descriptor.key_type(), Some(SignatureType::Secp256k1));
+        assert_eq!(descriptor.key_origins(4)[0].path, vec![44 | HARDENED, 1 | HARDENED, HARDENED, 0, 4]);
+        assert_ne!(descriptor.address(0).unwrap(), descriptor.address(1).unwrap());
+
+        let multi = format!(
+            "multi(2,[d34db33f/44'/1'/0']{}/0/*,[0badf00d/8844'/1'/0'/7']dilithium:3:{}.{})",
+            xpub(1), hex::encode([7u8; 16]), hex::encode([8u8; 16]),
+        );
+        let descriptor = Descriptor::parse(&multi, NetworkType::Testnet).unwrap();
+        assert_eq!(descriptor.to_string(), multi);
+        assert_eq!(Descriptor::parse(&descriptor.to_string(), NetworkType::Testnet).unwrap(), descriptor);
+        assert!(descriptor.address(1).unwrap().to_string().starts_with("tnova1m"));
+        assert!(matches!(
+            descriptor.address(2),
+            Err(DescriptorError::Key(MultisigError::KeysExhausted { available: 2, index: 2 }))
+        ));
+
+        // A key without an origin is named by its own fingerprint
+        let bare = Descriptor::parse(&format!("pkh({}/0/*)", xpub(2)), NetworkType::Testnet).unwrap();
+        assert_eq!(bare.keys()[0].origin.fingerprint, xpub(2).fingerprint().to_bytes());
+        assert!(bare.keys()[0].origin.path.is_empty());
+    }
+
+    #[test]
+    fn test_descriptor_validation() {
+        let key = format!("{}/0/*", xpub(1));
+        assert!(matches!(Descriptor::parse(&format!("sh({})", key), NetworkType::Testnet), Err(DescriptorError::Syntax(_))));
+        assert!(matches!(Descriptor::parse(&format!("pkh({}", key), NetworkType::Testnet), Err(DescriptorError::Syntax(_))));
+        assert!(Descriptor::parse(&format!("pkh({}/1/*)", xpub(1)), NetworkType::Testnet).is_err());
+        assert!(Descriptor::parse(&format!("multi(3,{},{}/0/*)", key, xpub(2)), NetworkType::Testnet).is_err());
+        assert!(Descriptor::parse(&format!("multi(0,{})", key), NetworkType::Testnet).is_err());
+
+        // Falcon keys can be watched on their own but cannot take part in a policy
+        let falcon = format!("falcon:1:{}", hex::encode([3u8; 16]));
+        assert!(Descriptor::parse(&format!("pkh({})", falcon), NetworkType::Testnet).is_ok());
+        assert!(matches!(
+            Descriptor::parse(&format!("multi(1,{},{})", key, falcon), NetworkType::Testnet),
+            Err(DescriptorError::Key(MultisigError::UnsupportedKeyType(SignatureType::Falcon)))
+        ));
+    }
+}
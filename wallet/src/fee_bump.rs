@@ -0,0 +1,443 @@
+//! Fee bumping for stuck transactions
+//!
+//! A pending transaction spending only the wallet's coins can be replaced
+//! (replace-by-fee): the new version spends the same coins and pays the same
+//! recipients, taking the extra fee out of its change. A transaction that
+//! cannot be replaced, such as a payment received from someone else, can
+//! still be pulled along by a child spending the wallet's outputs of it with a
+//! fee high enough for both (child-pays-for-parent).
+//!
+//! Either way [`FeeBumper`] returns the new transaction as a partially signed
+//! transaction, signed by the wallet unless it is watch-only, so other signers
+//! can add theirs before it is broadcast.
+
+use btclib::types::address::Address;
+use btclib::types::psbt::{PartiallySignedTransaction, PsbtError};
+use btclib::types::transaction::{Transaction, TransactionInput, TransactionOutput};
+use chrono::Utc;
+use thiserror::Error;
+
+use crate::coin_selection::{DEFAULT_DUST_LIMIT, INPUT_BASE_SIZE};
+use crate::core::UTXO;
+use crate::hdwallet::{HDWallet, HDWalletError};
+use crate::history::{
+    HistoryError, TransactionDirection, TransactionHistory, TransactionRecord, TransactionStatus,
+};
+use crate::network::{ChainSource, FeeSource, NetworkError, TransactionBroadcaster};
+
+/// Share by which a replacement must raise the fee, as nodes require by default
+pub const MIN_RBF_FEE_INCREASE_PERCENT: u64 = 10;
+
+/// Confirmation target used when no fee rate is given
+pub const DEFAULT_CONFIRMATION_TARGET: u32 = 6;
+
+#[derive(Debug, Error)]
+pub enum FeeBumpError {
+    #[error("Network error: {0}")]
+    Network(#[from] NetworkError),
+    #[error("HD wallet error: {0}")]
+    Wallet(#[from] HDWalletError),
+    #[error("History error: {0}")]
+    History(#[from] HistoryError),
+    #[error("Partially signed transaction error: {0}")]
+    Psbt(#[from] PsbtError),
+    #[error("Transaction {0} is not in the wallet's history")]
+    UnknownTransaction(String),
+    #[error("Transaction {0} is no longer pending")]
+    NotPending(String),
+    #[error("The node does not know transaction {0}")]
+    NotFound(String),
+    #[error("Input {0} does not spend one of the wallet's coins")]
+    ForeignInput(usize),
+    #[error("Transaction has no change output to take a higher fee from")]
+    NoChange,
+    #[error("Transaction has no unspent output paying the wallet")]
+    NothingToSpend,
+    #[error("Insufficient funds: needed {needed} satoshis, {available} available")]
+    InsufficientFunds { needed: u64, available: u64 },
+    #[error("The node has no fee estimate for {0} blocks yet")]
+    NoEstimate(u32),
+}
+
+/// How a fee bump speeds up a transaction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BumpKind {
+    /// Replaces `original`, spending the same coins
+    Replace { original: [u8; 32] },
+    /// Spends outputs of `parent`, paying for both
+    ChildPaysForParent { parent: [u8; 32] },
+}
+
+/// A transaction raising the fee paid for a stuck one
+#[derive(Debug, Clone)]
+pub struct FeeBump {
+    pub kind: BumpKind,
+    pub psbt: PartiallySignedTransaction,
+    /// Fee of the new transaction alone
+    pub fee: u64,
+}
+
+/// Builds and relays fee bumps with the help of a node
+pub struct FeeBumper<S> {
+    source: S,
+}
+
+impl<S> FeeBumper<S> {
+    pub fn new(source: S) -> Self {
+        Self { source }
+    }
+
+    pub fn source(&self) -> &S {
+        &self.source
+    }
+}
+
+impl<S: FeeSource> FeeBumper<S> {
+    /// Fee rate the node expects to confirm within `target` blocks
+    pub async fn fee_rate(&self, target: u32) -> Result<u64, FeeBumpError> {
+        self.source
+            .estimate_fee_rate(target)
+            .await?
+            .ok_or(FeeBumpError::NoEstimate(target))
+    }
+}
+
+impl<S: ChainSource> FeeBumper<S> {
+    /// Replace pending transaction `tx_hash` with one paying `fee_rate` satoshis per byte
+    ///
+    /// The fee also rises by at least [`MIN_RBF_FEE_INCREASE_PERCENT`], and the
+    /// change output, the last one paying the wallet, must stay above dust.
+    pub async fn replace_by_fee(
+        &self,
+        wallet: &HDWallet,
+        history: &TransactionHistory,
+        tx_hash: &[u8; 32],
+        fee_rate: u64,
+    ) -> Result<FeeBump, FeeBumpError> {
+        ensure_pending(history, tx_hash)?;
+        let original = self.fetch(tx_hash).await?;
+
+        let mut spent = Vec::with_capacity(original.inputs().len());
+        for (index, input) in original.inputs().iter().enumerate() {
+            let coin = wallet
+                .sync_state()
+                .outputs()
+                .iter()
+                .map(|output| &output.utxo)
+                .find(|utxo| utxo.tx_hash == input.prev_tx_hash() && utxo.output_index == input.prev_output_index())
+                .ok_or(FeeBumpError::ForeignInput(index))?;
+            spent.push(TransactionOutput::new(coin.amount, coin.script_pubkey.clone()));
+        }
+
+        let scripts = wallet.watched_scripts();
+        let change = original
+            .outputs()
+            .iter()
+            .rposition(|output| scripts.contains_key(output.pub_key_script()))
+            .ok_or(FeeBumpError::NoChange)?;
+
+        let old_fee = spent
+            .iter()
+            .map(|output| output.amount())
+            .sum::<u64>()
+            .saturating_sub(original.total_output());
+        let inputs: Vec<TransactionInput> = original
+            .inputs()
+            .iter()
+            .map(|input| TransactionInput::new(input.prev_tx_hash(), input.prev_output_index(), vec![], input.sequence()))
+            .collect();
+        let size = signed_size(
+            wallet,
+            &Transaction::new(original.version(), inputs.clone(), original.outputs().to_vec(), original.lock_time()),
+            &spent,
+        )?;
+        let min_fee = old_fee + (old_fee * MIN_RBF_FEE_INCREASE_PERCENT).div_ceil(100);
+        let fee = (fee_rate * size as u64).max(min_fee);
+
+        let change_amount = original.outputs()[change].amount();
+        let remaining = (change_amount + old_fee)
+            .checked_sub(fee)
+            .filter(|remaining| *remaining >= DEFAULT_DUST_LIMIT)
+            .ok_or(FeeBumpError::InsufficientFunds {
+                needed: fee - old_fee + DEFAULT_DUST_LIMIT,
+                available: change_amount,
+            })?;
+        let mut outputs = original.outputs().to_vec();
+        outputs[change] = TransactionOutput::new(remaining, outputs[change].pub_key_script().to_vec());
+
+        let tx = Transaction::new(original.version(), inputs, outputs, original.lock_time());
+        let psbt = sign(wallet, &tx, spent)?;
+        Ok(FeeBump { kind: BumpKind::Replace { original: *tx_hash }, psbt, fee })
+    }
+
+    /// Spend the wallet's outputs of pending transaction `parent` so both pay `fee_rate` together
+    ///
+    /// The child sends everything to a new address of the account owning the
+    /// first output, paying at least `fee_rate` for its own bytes.
+    pub async fn child_pays_for_parent(
+        &self,
+        wallet: &mut HDWallet,
+        history: &TransactionHistory,
+        parent: &[u8; 32],
+        fee_rate: u64,
+    ) -> Result<FeeBump, FeeBumpError> {
+        ensure_pending(history, parent)?;
+        let parent_tx = self.fetch(parent).await?;
+
+        let coins: Vec<UTXO> = wallet
+            .sync_state()
+            .unspent()
+            .filter(|utxo| utxo.tx_hash == *parent)
+            .cloned()
+            .collect();
+        let account = coins
+            .iter()
+            .find_map(|utxo| utxo.account.clone())
+            .ok_or(FeeBumpError::NothingToSpend)?;
+
+        let mut parent_input = 0;
+        for input in parent_tx.inputs() {
+            let previous = self.fetch(&input.prev_tx_hash()).await?;
+            parent_input += previous
+                .outputs()
+                .get(input.prev_output_index() as usize)
+                .map(|output| output.amount())
+                .ok_or_else(|| FeeBumpError::NotFound(hex::encode(input.prev_tx_hash())))?;
+        }
+        let parent_fee = parent_input.saturating_sub(parent_tx.total_output());
+
+        let address = wallet.get_new_address(&account)?;
+        let script = address.get_address().parse::<Address>().map_err(HDWalletError::from)?.script_pubkey();
+        let inputs: Vec<TransactionInput> = coins
+            .iter()
+            .map(|utxo| TransactionInput::new(utxo.tx_hash, utxo.output_index, vec![], 0xffffffff))
+            .collect();
+        let spent: Vec<TransactionOutput> = coins
+            .iter()
+            .map(|utxo| TransactionOutput::new(utxo.amount, utxo.script_pubkey.clone()))
+            .collect();
+        let child_size = signed_size(
+            wallet,
+            &Transaction::new(1, inputs.clone(), vec![TransactionOutput::new(0, script.clone())], 0),
+            &spent,
+        )? as u64;
+
+        let package_fee = fee_rate * (parent_tx.calculate_size() as u64 + child_size);
+        let fee = package_fee.saturating_sub(parent_fee).max(fee_rate * child_size);
+        let value: u64 = coins.iter().map(|utxo| utxo.amount).sum();
+        let amount = value
+            .checked_sub(fee)
+            .filter(|amount| *amount >= DEFAULT_DUST_LIMIT)
+            .ok_or(FeeBumpError::InsufficientFunds { needed: fee + DEFAULT_DUST_LIMIT, available: value })?;
+
+        let tx = Transaction::new(1, inputs, vec![TransactionOutput::new(amount, script)], 0);
+        let psbt = sign(wallet, &tx, spent)?;
+        Ok(FeeBump { kind: BumpKind::ChildPaysForParent { parent: *parent }, psbt, fee })
+    }
+
+    async fn fetch(&self, tx_hash: &[u8; 32]) -> Result<Transaction, FeeBumpError> {
+        self.source
+            .transaction(tx_hash)
+            .await?
+            .ok_or_else(|| FeeBumpError::NotFound(hex::encode(tx_hash)))
+    }
+}
+
+impl<S: TransactionBroadcaster> FeeBumper<S> {
+    /// Finalize and relay a fully signed fee bump, then record it in the history
+    ///
+    /// A replacement takes over the label, category and tags of the transaction
+    /// it replaces, which is marked failed.
+    pub async fn broadcast(
+        &self,
+        mut bump: FeeBump,
+        history: &mut TransactionHistory,
+    ) -> Result<[u8; 32], FeeBumpError> {
+        bump.psbt.finalize()?;
+        let tx = bump.psbt.extract()?;
+        self.source.broadcast(&tx).await?;
+
+        let tx_hash = tx.hash();
+        let mut record = TransactionRecord {
+            hash: hex::encode(tx_hash),
+            timestamp: Utc::now(),
+            direction: TransactionDirection::Sent,
+            amount: 0,
+            fee: bump.fee,
+            status: TransactionStatus::Pending,
+            label: None,
+            category: None,
+            tags: vec![],
+        };
+        match bump.kind {
+            BumpKind::Replace { original } => {
+                let original = hex::encode(original);
+                if let Some(replaced) = history.get_transaction(&original) {
+                    record.direction = replaced.direction.clone();
+                    record.amount = replaced.amount;
+                    record.label = replaced.label.clone();
+                    record.category = replaced.category.clone();
+                    record.tags = replaced.tags.clone();
+                    history.update_transaction_status(&original, TransactionStatus::Failed)?;
+                }
+            }
+            BumpKind::ChildPaysForParent { parent } => {
+                record.label = Some(format!("Fee bump for {}", hex::encode(parent)));
+            }
+        }
+        history.add_transaction(record)?;
+        Ok(tx_hash)
+    }
+}
+
+fn ensure_pending(history: &TransactionHistory, tx_hash: &[u8; 32]) -> Result<(), FeeBumpError> {
+    let hash = hex::encode(tx_hash);
+    match history.get_transaction(&hash) {
+        Some(record) if record.status == TransactionStatus::Pending => Ok(()),
+        Some(_) => Err(FeeBumpError::NotPending(hash)),
+        None => Err(FeeBumpError::UnknownTransaction(hash)),
+    }
+}
+
+/// Size of `unsigned` once every input spending `spent` is signed
+fn signed_size(wallet: &HDWallet, unsigned: &Transaction, spent: &[TransactionOutput]) -> Result<usize, FeeBumpError> {
+    let mut size = unsigned.calculate_size();
+    for output in spent {
+        size += wallet.input_size(output.pub_key_script())? - INPUT_BASE_SIZE;
+    }
+    Ok(size)
+}
+
+/// Partially signed `tx` with the wallet's key origins and, unless watch-only, its signatures
+fn sign(
+    wallet: &HDWallet,
+    tx: &Transaction,
+    spent: Vec<TransactionOutput>,
+) -> Result<PartiallySignedTransaction, FeeBumpError> {
+    let mut psbt = PartiallySignedTransaction::new(tx);
+    for (input, output) in psbt.inputs.iter_mut().zip(spent) {
+        input.spent_output = Some(output);
+    }
+    wallet.update_psbt(&mut psbt)?;
+    if !wallet.is_watch_only() {
+        wallet.sign_psbt(&mut psbt)?;
+    }
+    Ok(psbt)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::coin_selection::witness_size;
+    use crate::hdwallet::AccountType;
+    use crate::network::ChainSource as _;
+    use crate::sync::tests::{pay, TestChain, MNEMONIC};
+    use crate::sync::WalletSync;
+    use async_trait::async_trait;
+    use btclib::config::NetworkType;
+    use btclib::crypto::signature::SignatureType;
+    use tempfile::tempdir;
+
+    /// Relays into the mempool, dropping the transactions a replacement conflicts with
+    #[async_trait]
+    impl TransactionBroadcaster for TestChain {
+        async fn broadcast(&self, transaction: &Transaction) -> Result<(), NetworkError> {
+            let mut mempool = self.mempool.lock().unwrap();
+            mempool.retain(|tx| {
+                !tx.inputs().iter().any(|input| {
+                    transaction.inputs().iter().any(|new| {
+                        new.prev_tx_hash() == input.prev_tx_hash() && new.prev_output_index() == input.prev_output_index()
+                    })
+                })
+            });
+            mempool.push(transaction.clone());
+            Ok(())
+        }
+    }
+
+    /// Signed payment of `amount` to an outside script from `coin`, with change to `change`
+    fn spend(wallet: &HDWallet, coin: &Transaction, change: &str, amount: u64, fee: u64) -> Transaction {
+        let value = coin.outputs()[0].amount();
+        let tx = Transaction::new(
+            1,
+            vec![TransactionInput::new(coin.hash(), 0, vec![], 0xffffffff)],
+            vec![
+                TransactionOutput::new(amount, vec![1, 2, 3]),
+                TransactionOutput::new(value - amount - fee, change.parse::<Address>().unwrap().script_pubkey()),
+            ],
+            0,
+        );
+        let mut psbt = sign(wallet, &tx, vec![coin.outputs()[0].clone()]).unwrap();
+        psbt.finalize().unwrap();
+        psbt.extract().unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_replace_and_child_pays_for_parent() {
+        let dir = tempdir().unwrap();
+        let mut wallet = HDWallet::from_mnemonic(MNEMONIC, NetworkType::Testnet, dir.path().join("wallet.json")).unwrap();
+        wallet.create_account("main".to_string(), AccountType::Classical).unwrap();
+        let mut history = TransactionHistory::new(dir.path().join("history.json")).unwrap();
+        let receive = wallet.get_new_address("main").unwrap().address;
+        let change = wallet.get_new_address("main").unwrap().address;
+
+        let chain = TestChain::default();
+        chain.mine(vec![]);
+        let coin = chain.mine(vec![pay(1, &receive, 100_000)]).transactions()[0].clone();
+        let sync = WalletSync::new(chain);
+        sync.sync(&mut wallet, &mut history).await.unwrap();
+
+        let stuck = spend(&wallet, &coin, &change, 30_000, 200);
+        sync.source().mempool.lock().unwrap().push(stuck.clone());
+        sync.sync(&mut wallet, &mut history).await.unwrap();
+        history.add_transaction_label(&hex::encode(stuck.hash()), "rent".to_string()).unwrap();
+
+        let bumper = FeeBumper::new(sync.source());
+        let bump = bumper.replace_by_fee(&wallet, &history, &stuck.hash(), 20).await.unwrap();
+        let replacement = bump.psbt.unsigned_tx().clone();
+        assert_eq!(bump.fee, 20 * stuck.calculate_size() as u64);
+        assert_eq!(replacement.inputs()[0].prev_tx_hash(), coin.hash());
+        assert_eq!(replacement.outputs()[0], stuck.outputs()[0]);
+        assert_eq!(replacement.outputs()[1].amount(), 100_000 - 30_000 - bump.fee);
+
+        // A low rate still raises the fee by the minimum increase
+        let minimal = bumper.replace_by_fee(&wallet, &history, &stuck.hash(), 0).await.unwrap();
+        assert_eq!(minimal.fee, 220);
+        assert!(matches!(
+            bumper.replace_by_fee(&wallet, &history, &stuck.hash(), 1_000).await,
+            Err(FeeBumpError::InsufficientFunds { .. })
+        ));
+
+        let replaced = bumper.broadcast(bump, &mut history).await.unwrap();
+        let record = history.get_transaction(&hex::encode(replaced)).unwrap();
+        assert_eq!(record.label.as_deref(), Some("rent"));
+        assert_eq!(record.amount, 30_000);
+
+        // The replaced transaction stays failed once the node has dropped it
+        sync.sync(&mut wallet, &mut history).await.unwrap();
+        assert_eq!(history.get_transaction(&hex::encode(stuck.hash())).unwrap().status, TransactionStatus::Failed);
+        assert!(matches!(
+            bumper.replace_by_fee(&wallet, &history, &stuck.hash(), 40).await,
+            Err(FeeBumpError::NotPending(_))
+        ));
+
+        // A child spending the change lifts the package to the requested rate
+        let parent_fee = 100_000 - replacement.total_output();
+        let parent_size = sync.source().transaction(&replaced).await.unwrap().unwrap().calculate_size() as u64;
+        let child = bumper.child_pays_for_parent(&mut wallet, &history, &replaced, 50).await.unwrap();
+        let child_size = (child.psbt.unsigned_tx().calculate_size() + witness_size(SignatureType::Secp256k1, 0, 33)) as u64;
+        assert_eq!(child.fee, 50 * (parent_size + child_size) - parent_fee);
+        assert_eq!(child.psbt.unsigned_tx().inputs()[0].prev_tx_hash(), replaced);
+        let child_hash = bumper.broadcast(child, &mut history).await.unwrap();
+        assert!(sync.source().mempool.lock().unwrap().iter().any(|tx| tx.hash() == child_hash));
+
+        // Confirmed transactions need no bump
+        let confirmed = sync.source().mempool.lock().unwrap().drain(..).collect();
+        sync.source().mine(confirmed);
+        sync.sync(&mut wallet, &mut history).await.unwrap();
+        assert!(matches!(
+            bumper.child_pays_for_parent(&mut wallet, &history, &replaced, 50).await,
+            Err(FeeBumpError::NotPending(_))
+        ));
+    }
+}
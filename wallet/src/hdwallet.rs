@@ -1,13 +1,39 @@
 use bip39::{Language, Mnemonic};
-use bitcoin::{
-    network::Network,
-    secp256k1::{Secp256k1, SecretKey},
-    Address, PrivateKey,
-};
+use bitcoin::bip32::{ChildNumber, Xpriv, Xpub};
+use bitcoin::secp256k1::{PublicKey, Secp256k1, SecretKey};
+use btclib::config::NetworkType;
+use btclib::crypto::quantum::{QuantumKeyPair, QuantumParameters, QuantumScheme};
+use btclib::crypto::signature::SignatureType;
+use btclib::types::address::{Address, AddressError};
+use btclib::types::psbt::{KeyOrigin, PartiallySignedTransaction, PsbtError, HARDENED};
+use btclib::types::script::{self, InputWitness, MultisigPolicy, PolicyKey};
+use hmac::{Hmac, Mac};
 use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, path::PathBuf};
+use sha2::Sha512;
+use std::path::{Path, PathBuf};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
 use thiserror::Error;
 use rand::RngCore;
+use zeroize::{Zeroize, Zeroizing};
+
+use crate::coin_selection;
+use crate::core::{quantum_witness, secp256k1_witness, DILITHIUM_SECURITY_LEVEL};
+use crate::descriptor::{Descriptor, DescriptorError};
+use crate::keystore::{self, KdfParams, KeystoreError, WalletKey};
+use crate::multisig::{self, CosignerKey, CosignerKeys, MultisigError, MultisigSetup};
+use crate::sync::SyncState;
+
+/// BIP-44 purpose for classical secp256k1 paths
+const CLASSICAL_PURPOSE: u32 = 44;
+/// Purpose for post-quantum paths, kept apart from BIP-44 so the two trees never overlap
+const QUANTUM_PURPOSE: u32 = 8844;
+/// Coin type on mainnet (unregistered in SLIP-44)
+const MAINNET_COIN_TYPE: u32 = 8844;
+/// Coin type on testnet and regtest, as in BIP-44
+const TESTNET_COIN_TYPE: u32 = 1;
+/// HMAC key for the post-quantum master node
+const QUANTUM_MASTER_KEY: &[u8] = b"Supernova post-quantum seed";
 
 #[derive(Error, Debug)]
 pub enum HDWalletError {
@@ -21,23 +47,76 @@ pub enum HDWalletError {
     AccountNotFound(String),
     #[error("Address not found: {0}")]
     AddressNotFound(String),
-    #[error("Bitcoin error: {0}")]
-    Bitcoin(String),
+    #[error("Address error: {0}")]
+    Address(#[from] AddressError),
+    #[error("Key derivation failed: {0}")]
+    Derivation(String),
+    #[error("Keystore error: {0}")]
+    Keystore(#[from] KeystoreError),
+    #[error("Wallet is locked")]
+    Locked,
+    #[error("Wallet file is encrypted; a passphrase is required")]
+    Encrypted,
+    #[error("Wallet is not encrypted")]
+    NotEncrypted,
+    #[error("Wallet is already encrypted")]
+    AlreadyEncrypted,
+    #[error("Signing failed: {0}")]
+    Signing(String),
+    #[error("Partially signed transaction error: {0}")]
+    Psbt(#[from] PsbtError),
+    #[error("Multisig error: {0}")]
+    Multisig(#[from] MultisigError),
+    #[error("Account {0} is not a multisig account")]
+    NotMultisig(String),
+    #[error("Descriptor error: {0}")]
+    Descriptor(#[from] DescriptorError),
+    #[error("Wallet is watch-only and holds no keys")]
+    WatchOnly,
+    #[error("Account {0} is watch-only")]
+    WatchOnlyAccount(String),
+    #[error("{0:?} keys cannot sign yet")]
+    CannotSign(SignatureType),
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct HDWallet {
     mnemonic: String,
-    network: Network,
+    network: NetworkType,
     accounts: HashMap<String, HDAccount>,
     wallet_path: PathBuf,
+    /// Key the wallet file is encrypted with; `None` for plaintext wallets and while locked
+    #[serde(skip)]
+    key: Option<WalletKey>,
+    /// Set once an encrypted wallet has wiped its secrets from memory
+    #[serde(skip)]
+    locked: bool,
+    /// When an unlock with a timeout expires
+    #[serde(skip)]
+    unlocked_until: Option<Instant>,
+    /// Coins and transactions found on chain
+    #[serde(default)]
+    sync: SyncState,
+    /// Set for wallets created without a mnemonic, which only hold imported descriptors
+    #[serde(default)]
+    watch_only: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct HDAccount {
     pub name: String,
+    /// Account level of the derivation path
+    #[serde(default)]
+    pub index: u32,
+    /// Scheme of this wallet's keys in the account
     pub account_type: AccountType,
     pub addresses: Vec<HDAddress>,
+    /// Cosigners and threshold when addresses pay to a multisig policy
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub multisig: Option<MultisigSetup>,
+    /// Public keys of a watch-only account; the wallet holds none of its keys
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub descriptor: Option<Descriptor>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -46,15 +125,85 @@ pub struct HDAddress {
     pub is_used: bool,
 }
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum AccountType {
-    Legacy,
-    SegWit,
-    NativeSegWit,
+    /// One secp256k1 key per address
+    // Accounts saved before Nova addresses used Bitcoin script types
+    #[serde(alias = "Legacy", alias = "SegWit", alias = "NativeSegWit")]
+    Classical,
+    /// One Dilithium key per address
+    Dilithium,
+    /// One Falcon key per address
+    Falcon,
+    /// A secp256k1 key and a Dilithium key per address
+    Hybrid,
+}
+
+impl AccountType {
+    /// Key scheme behind the account's addresses
+    pub fn key_type(&self) -> SignatureType {
+        match self {
+            AccountType::Classical => SignatureType::Secp256k1,
+            AccountType::Dilithium => SignatureType::Dilithium,
+            AccountType::Falcon => SignatureType::Falcon,
+            AccountType::Hybrid => SignatureType::Hybrid,
+        }
+    }
+
+    /// Whether the account's addresses need a post-quantum signature
+    pub fn is_quantum_resistant(&self) -> bool {
+        *self != AccountType::Classical
+    }
+
+    /// Account type whose addresses pay to keys of `key_type`
+    pub fn from_key_type(key_type: SignatureType) -> Option<Self> {
+        match key_type {
+            SignatureType::Secp256k1 => Some(AccountType::Classical),
+            SignatureType::Dilithium => Some(AccountType::Dilithium),
+            SignatureType::Falcon => Some(AccountType::Falcon),
+            SignatureType::Hybrid => Some(AccountType::Hybrid),
+            _ => None,
+        }
+    }
+}
+
+/// Keys behind one address of an account
+pub enum DerivedKeys {
+    Classical(SecretKey),
+    Quantum(QuantumKeyPair),
+}
+
+impl DerivedKeys {
+    /// Security level recorded in the address's key hash
+    pub fn security_level(&self) -> u8 {
+        match self {
+            DerivedKeys::Classical(_) => 0,
+            DerivedKeys::Quantum(key) => key.parameters.security_level,
+        }
+    }
+
+    /// Public key the address commits to
+    pub fn public_key(&self) -> Vec<u8> {
+        match self {
+            DerivedKeys::Classical(secret) => {
+                PublicKey::from_secret_key(&Secp256k1::new(), secret).serialize().to_vec()
+            }
+            DerivedKeys::Quantum(key) => key.public_key.clone(),
+        }
+    }
+
+    /// Witness spending an output locked to these keys
+    pub fn sign(&self, sighash: &[u8; 32]) -> Result<InputWitness, HDWalletError> {
+        match self {
+            DerivedKeys::Classical(secret) => Ok(secp256k1_witness(secret, sighash)),
+            DerivedKeys::Quantum(key) => quantum_witness(key, sighash)
+                .map_err(|e| HDWalletError::Signing(e.to_string())),
+        }
+    }
 }
 
 impl HDWallet {
-    pub fn new(network: Network, wallet_path: PathBuf) -> Result<Self, HDWalletError> {
+    pub fn new(network: NetworkType, wallet_path: PathBuf) -> Result<Self, HDWalletError> {
         // Generate entropy for a 12-word mnemonic (128 bits = 16 bytes)
         let mut entropy = [0u8; 16];
         rand::thread_rng().fill_bytes(&mut entropy);
@@ -68,10 +217,15 @@ impl HDWallet {
             network,
             accounts: HashMap::new(),
             wallet_path,
+            key: None,
+            locked: false,
+            unlocked_until: None,
+            sync: SyncState::default(),
+            watch_only: false,
         })
     }
 
-    pub fn from_mnemonic(mnemonic: &str, network: Network, wallet_path: PathBuf) -> Result<Self, HDWalletError> {
+    pub fn from_mnemonic(mnemonic: &str, network: NetworkType, wallet_path: PathBuf) -> Result<Self, HDWalletError> {
         Mnemonic::parse_in_normalized(Language::English, mnemonic)
             .map_err(|e| HDWalletError::InvalidMnemonic(e.to_string()))?;
         Ok(Self {
@@ -79,26 +233,240 @@ impl HDWallet {
             network,
             accounts: HashMap::new(),
             wallet_path,
+            key: None,
+            locked: false,
+            unlocked_until: None,
+            sync: SyncState::default(),
+            watch_only: false,
         })
     }
 
+    /// Wallet without a mnemonic, for accounts imported with [`HDWallet::import_descriptor`]
+    pub fn new_watch_only(network: NetworkType, wallet_path: PathBuf) -> Self {
+        Self {
+            mnemonic: String::new(),
+            network,
+            accounts: HashMap::new(),
+            wallet_path,
+            key: None,
+            locked: false,
+            unlocked_until: None,
+            sync: SyncState::default(),
+            watch_only: true,
+        }
+    }
+
+    pub fn is_watch_only(&self) -> bool {
+        self.watch_only
+    }
+
+    /// Write the wallet file, sealed with the wallet key if it is encrypted
     pub fn save(&self) -> Result<(), HDWalletError> {
-        let json = serde_json::to_string_pretty(self)?;
-        std::fs::write(&self.wallet_path, json)?;
+        self.ensure_unlocked()?;
+        let json = Zeroizing::new(serde_json::to_vec_pretty(self)?);
+        let contents = match &self.key {
+            Some(key) => key.seal(&json)?,
+            None => json.to_vec(),
+        };
+
+        // Replace the file in one step so a crash never leaves half a wallet behind
+        let tmp_path = self.wallet_path.with_extension("tmp");
+        std::fs::write(&tmp_path, contents)?;
+        std::fs::rename(&tmp_path, &self.wallet_path)?;
         Ok(())
     }
     
+    /// Load a plaintext wallet file
     pub fn load(wallet_path: PathBuf) -> Result<Self, HDWalletError> {
-        let json = std::fs::read_to_string(&wallet_path)?;
-        let wallet: Self = serde_json::from_str(&json)?;
+        let data = Zeroizing::new(std::fs::read(&wallet_path)?);
+        if keystore::is_encrypted(&data) {
+            return Err(HDWalletError::Encrypted);
+        }
+        let wallet: Self = serde_json::from_slice(&data)?;
         Ok(wallet)
     }
 
+    /// Load an encrypted wallet file, unlocked
+    pub fn open(wallet_path: PathBuf, passphrase: &str) -> Result<Self, HDWalletError> {
+        let data = std::fs::read(&wallet_path)?;
+        if !keystore::is_encrypted(&data) {
+            return Err(HDWalletError::NotEncrypted);
+        }
+        let (json, key) = keystore::open(&data, passphrase)?;
+        let mut wallet: Self = serde_json::from_slice(&json)?;
+        wallet.key = Some(key);
+        Ok(wallet)
+    }
+
+    /// Whether the file at `wallet_path` is an encrypted wallet
+    pub fn is_encrypted_file(wallet_path: &Path) -> Result<bool, HDWalletError> {
+        Ok(keystore::is_encrypted(&std::fs::read(wallet_path)?))
+    }
+
+    /// Encrypt a plaintext wallet under `passphrase` and rewrite its file
+    ///
+    /// This is also the migration path for wallet files written before
+    /// encryption existed: load them with `load`, then call this.
+    pub fn encrypt(&mut self, passphrase: &str, params: KdfParams) -> Result<(), HDWalletError> {
+        if self.is_encrypted() {
+            return Err(HDWalletError::AlreadyEncrypted);
+        }
+        self.key = Some(WalletKey::derive(passphrase, params)?);
+        self.save()
+    }
+
+    /// Re-encrypt the wallet under a new passphrase
+    ///
+    /// The current passphrase is checked against the file on disk, so an
+    /// unattended unlocked wallet cannot have its passphrase changed.
+    pub fn change_passphrase(&mut self, current: &str, new: &str, params: KdfParams) -> Result<(), HDWalletError> {
+        if !self.is_encrypted() {
+            return Err(HDWalletError::NotEncrypted);
+        }
+        self.ensure_unlocked()?;
+        keystore::open(&std::fs::read(&self.wallet_path)?, current)?;
+
+        self.key = Some(WalletKey::derive(new, params)?);
+        self.save()
+    }
+
+    pub fn is_encrypted(&self) -> bool {
+        self.key.is_some() || self.locked
+    }
+
+    /// Whether the wallet's secrets are unavailable
+    pub fn is_locked(&self) -> bool {
+        self.locked || self.unlocked_until.map_or(false, |until| Instant::now() >= until)
+    }
+
+    /// Wipe the mnemonic and the wallet key from memory
+    ///
+    /// Account and address data stays available; anything that needs the
+    /// seed fails with `Locked` until `unlock` is called.
+    pub fn lock(&mut self) -> Result<(), HDWalletError> {
+        if !self.is_encrypted() {
+            return Err(HDWalletError::NotEncrypted);
+        }
+        self.mnemonic.zeroize();
+        self.key = None;
+        self.locked = true;
+        self.unlocked_until = None;
+        Ok(())
+    }
+
+    /// Lock the wallet if its unlock timeout has passed; returns whether it did
+    pub fn lock_if_expired(&mut self) -> bool {
+        if !self.locked && self.is_locked() {
+            // Only encrypted wallets can have a timeout, so this cannot fail
+            let _ = self.lock();
+            return true;
+        }
+        false
+    }
+
+    /// Restore the secrets of a locked wallet from its file
+    ///
+    /// With a `timeout` the wallet locks itself again once it elapses.
+    pub fn unlock(&mut self, passphrase: &str, timeout: Option<Duration>) -> Result<(), HDWalletError> {
+        if !self.is_encrypted() {
+            return Err(HDWalletError::NotEncrypted);
+        }
+        let (json, key) = keystore::open(&std::fs::read(&self.wallet_path)?, passphrase)?;
+        let mut stored: Self = serde_json::from_slice(&json)?;
+
+        self.mnemonic = std::mem::take(&mut stored.mnemonic);
+        self.key = Some(key);
+        self.locked = false;
+        self.unlocked_until = timeout.map(|timeout| Instant::now() + timeout);
+        Ok(())
+    }
+
+    /// Lock an unlocked encrypted wallet once `timeout` has passed
+    pub fn set_lock_timeout(&mut self, timeout: Duration) -> Result<(), HDWalletError> {
+        if !self.is_encrypted() {
+            return Err(HDWalletError::NotEncrypted);
+        }
+        self.ensure_unlocked()?;
+        self.unlocked_until = Some(Instant::now() + timeout);
+        Ok(())
+    }
+
+    fn ensure_unlocked(&self) -> Result<(), HDWalletError> {
+        if self.is_locked() {
+            return Err(HDWalletError::Locked);
+        }
+        Ok(())
+    }
+
+    /// Create an account holding keys of type `account_type`
+    ///
+    /// Only key types the wallet can sign with are accepted, so every coin an
+    /// account receives can be spent again.
     pub fn create_account(&mut self, name: String, account_type: AccountType) -> Result<(), HDWalletError> {
+        if !multisig::can_sign(account_type.key_type()) {
+            return Err(HDWalletError::CannotSign(account_type.key_type()));
+        }
+        self.insert_account(name, account_type, None, None)
+    }
+
+    /// Create an m-of-n account in which this wallet holds one key of type `account_type`
+    ///
+    /// Addresses can be derived once [`HDWallet::add_cosigner`] has added the
+    /// other `total - 1` participants.
+    pub fn create_multisig_account(
+        &mut self,
+        name: String,
+        account_type: AccountType,
+        threshold: u8,
+        total: u8,
+    ) -> Result<(), HDWalletError> {
+        if !multisig::can_sign(account_type.key_type()) {
+            return Err(MultisigError::UnsupportedKeyType(account_type.key_type()).into());
+        }
+        let setup = MultisigSetup::new(threshold, total)?;
+        self.insert_account(name, account_type, Some(setup), None)
+    }
+
+    /// Add a watch-only account whose addresses come from `descriptor`
+    ///
+    /// The account's addresses, balance and history are tracked like those of
+    /// any other account, but nothing in it can be signed by this wallet.
+    pub fn import_descriptor(&mut self, name: String, descriptor: Descriptor) -> Result<(), HDWalletError> {
+        if descriptor.network() != self.network {
+            return Err(MultisigError::WrongNetwork { expected: self.network, found: descriptor.network() }.into());
+        }
+        // Multisig accounts are typed by their first key, as no key of theirs is this wallet's
+        let key_type = descriptor.key_type().unwrap_or_else(|| descriptor.keys()[0].key_type());
+        let account_type = AccountType::from_key_type(key_type)
+            .ok_or(MultisigError::UnsupportedKeyType(key_type))?;
+        self.insert_account(name, account_type, None, Some(descriptor))
+    }
+
+    fn insert_account(
+        &mut self,
+        name: String,
+        account_type: AccountType,
+        multisig: Option<MultisigSetup>,
+        descriptor: Option<Descriptor>,
+    ) -> Result<(), HDWalletError> {
+        self.ensure_unlocked()?;
+        if self.watch_only && descriptor.is_none() {
+            return Err(HDWalletError::WatchOnly);
+        }
+        // Accounts are numbered in creation order so a restored wallet walks the same paths;
+        // imported accounts derive nothing from the seed and take no number from it
+        let index = self.accounts.values()
+            .filter(|account| account.descriptor.is_none())
+            .map(|account| account.index + 1)
+            .max()
+            .unwrap_or(0);
         let account = HDAccount {
             name: name.clone(),
+            index,
             account_type,
             addresses: Vec::new(),
+            multisig,
+            descriptor,
         };
 
         self.accounts.insert(name, account);
@@ -107,21 +475,20 @@ impl HDWallet {
     }
 
     pub fn get_new_address(&mut self, account_name: &str) -> Result<HDAddress, HDWalletError> {
+        let hd_address = self.push_address(account_name)?;
+        self.save()?;
+        Ok(hd_address)
+    }
+
+    /// Derive the account's next address and add it, without saving
+    fn push_address(&mut self, account_name: &str) -> Result<HDAddress, HDWalletError> {
+        let account = self.accounts.get(account_name)
+            .ok_or_else(|| HDWalletError::AccountNotFound(account_name.to_string()))?;
+        let index = account.addresses.len() as u32;
+        let address = self.derive_address(account_name, index)?;
+
         let account = self.accounts.get_mut(account_name)
             .ok_or_else(|| HDWalletError::AccountNotFound(account_name.to_string()))?;
-            
-        let secp = Secp256k1::new();
-        let secret_key = SecretKey::new(&mut rand::thread_rng());
-        let private_key = PrivateKey::new(secret_key, self.network);
-        let public_key = private_key.public_key(&secp);
-
-        let address = match account.account_type {
-            AccountType::Legacy => Address::p2pkh(&public_key, self.network),
-            AccountType::SegWit => Address::p2shwpkh(&public_key, self.network)
-                .map_err(|e| HDWalletError::Bitcoin(e.to_string()))?,
-            AccountType::NativeSegWit => Address::p2wpkh(&public_key, self.network)
-                .map_err(|e| HDWalletError::Bitcoin(e.to_string()))?,
-        };
 
         let hd_address = HDAddress {
             address: address.to_string(),
@@ -129,16 +496,99 @@ impl HDWallet {
         };
 
         account.addresses.push(hd_address.clone());
-        self.save()?;
         Ok(hd_address)
     }
 
-    pub fn get_balance(&self, account_name: &str) -> Result<u64, HDWalletError> {
-        let _account = self.accounts.get(account_name)
+    /// Address `index` of an account
+    fn derive_address(&self, account_name: &str, index: u32) -> Result<Address, HDWalletError> {
+        let account = self.accounts.get(account_name)
             .ok_or_else(|| HDWalletError::AccountNotFound(account_name.to_string()))?;
 
-        // TODO: Implement actual balance calculation from blockchain
-        Ok(0)
+        let address = if let Some(descriptor) = &account.descriptor {
+            descriptor.address(index)?
+        } else if account.multisig.is_some() {
+            Address::multisig(self.network, self.multisig_policy(account_name, index)?.hash())
+        } else {
+            let keys = self.derive_keys(account_name, index)?;
+            Address::from_public_key(
+                self.network,
+                account.account_type.key_type(),
+                keys.security_level(),
+                &keys.public_key(),
+            )?
+        };
+        Ok(address)
+    }
+
+    /// Unspent coins of an account found by the last sync, unconfirmed ones included
+    pub fn get_balance(&self, account_name: &str) -> Result<u64, HDWalletError> {
+        if !self.accounts.contains_key(account_name) {
+            return Err(HDWalletError::AccountNotFound(account_name.to_string()));
+        }
+        Ok(self.sync.balance(account_name))
+    }
+
+    /// Chain state the wallet has synchronized to
+    pub fn sync_state(&self) -> &SyncState {
+        &self.sync
+    }
+
+    pub(crate) fn sync_state_mut(&mut self) -> &mut SyncState {
+        &mut self.sync
+    }
+
+    /// Locking script of every address, with the account and index it belongs to
+    pub(crate) fn watched_scripts(&self) -> HashMap<Vec<u8>, (String, u32)> {
+        let mut scripts = HashMap::new();
+        for account in self.accounts.values() {
+            for (index, hd_address) in account.addresses.iter().enumerate() {
+                if let Ok(address) = hd_address.address.parse::<Address>() {
+                    scripts.insert(address.script_pubkey(), (account.name.clone(), index as u32));
+                }
+            }
+        }
+        scripts
+    }
+
+    /// Record that address `index` of an account has received coins
+    pub(crate) fn mark_used(&mut self, account_name: &str, index: u32) {
+        if let Some(hd_address) = self.accounts
+            .get_mut(account_name)
+            .and_then(|account| account.addresses.get_mut(index as usize))
+        {
+            hd_address.is_used = true;
+        }
+    }
+
+    /// Derive addresses until each account ends with `gap_limit` unused ones
+    ///
+    /// Multisig accounts still waiting for cosigners are skipped, and accounts
+    /// built on post-quantum keys stop at the last key they were given. Returns
+    /// the scripts of the new addresses, as for [`HDWallet::watched_scripts`].
+    pub(crate) fn fill_address_gap(&mut self, gap_limit: u32) -> Result<Vec<(Vec<u8>, (String, u32))>, HDWalletError> {
+        let mut added = Vec::new();
+        let mut names: Vec<String> = self.accounts.keys().cloned().collect();
+        names.sort();
+
+        for name in names {
+            let account = &self.accounts[&name];
+            if account.multisig.as_ref().map_or(false, |setup| !setup.is_complete()) {
+                continue;
+            }
+            let unused = account.addresses.iter().rev().take_while(|address| !address.is_used).count();
+            for _ in unused..gap_limit as usize {
+                let index = self.accounts[&name].addresses.len() as u32;
+                let hd_address = match self.push_address(&name) {
+                    Ok(hd_address) => hd_address,
+                    Err(HDWalletError::Multisig(MultisigError::KeysExhausted { .. }))
+                    | Err(HDWalletError::Descriptor(DescriptorError::Key(MultisigError::KeysExhausted { .. }))) => break,
+                    Err(e) => return Err(e),
+                };
+                let address: Address = hd_address.address.parse()?;
+                added.push((address.script_pubkey(), (name.clone(), index)));
+            }
+        }
+        Ok(added)
     }
 
     pub fn get_total_balance(&self) -> Result<u64, HDWalletError> {
@@ -162,15 +612,412 @@ impl HDWallet {
             .sum()
     }
 
-    pub fn get_mnemonic(&self) -> &str {
-        &self.mnemonic
+    pub fn get_mnemonic(&self) -> Result<&str, HDWalletError> {
+        self.ensure_unlocked()?;
+        if self.watch_only {
+            return Err(HDWalletError::WatchOnly);
+        }
+        Ok(&self.mnemonic)
+    }
+
+    pub fn network(&self) -> NetworkType {
+        self.network
+    }
+
+    /// Keys for address `index` of an account
+    pub fn derive_keys(&self, account_name: &str, index: u32) -> Result<DerivedKeys, HDWalletError> {
+        self.derive_path(&self.key_path(account_name, index)?)
+    }
+
+    /// Derivation path of address `index` of an account
+    ///
+    /// Classical keys follow BIP-32 at `m/44'/coin'/account'/0/index`.
+    /// Post-quantum keys have no public derivation, so every step is hardened:
+    /// `m/8844'/coin'/account'/scheme'/index'`, where `scheme` is the key
+    /// scheme's script tag.
+    pub fn key_path(&self, account_name: &str, index: u32) -> Result<Vec<u32>, HDWalletError> {
+        let account = self.accounts.get(account_name)
+            .ok_or_else(|| HDWalletError::AccountNotFound(account_name.to_string()))?;
+        if account.descriptor.is_some() {
+            return Err(HDWalletError::WatchOnlyAccount(account_name.to_string()));
+        }
+
+        if account.account_type == AccountType::Classical {
+            return Ok(vec![
+                CLASSICAL_PURPOSE | HARDENED,
+                self.coin_type() | HARDENED,
+                account.index | HARDENED,
+                0,
+                index,
+            ]);
+        }
+
+        let scheme = script::scheme_tag(account.account_type.key_type()) as u32;
+        Ok([QUANTUM_PURPOSE, self.coin_type(), account.index, scheme, index]
+            .iter()
+            .map(|step| step | HARDENED)
+            .collect())
+    }
+
+    /// Key origin of address `index` of an account, as recorded in partially signed transactions
+    pub fn key_origin(&self, account_name: &str, index: u32) -> Result<KeyOrigin, HDWalletError> {
+        Ok(KeyOrigin {
+            fingerprint: self.fingerprint()?,
+            path: self.key_path(account_name, index)?,
+        })
+    }
+
+    /// BIP-32 fingerprint of the master key, which names this wallet in key origins
+    pub fn fingerprint(&self) -> Result<[u8; 4], HDWalletError> {
+        let master = Xpriv::new_master(bitcoin::Network::Bitcoin, &self.seed()?)
+            .map_err(derivation_error)?;
+        Ok(master.fingerprint(&Secp256k1::new()).to_bytes())
+    }
+
+    /// Keys at `path`, a path produced by [`HDWallet::key_path`]
+    ///
+    /// The node reached by a post-quantum path seeds the key pair. Only paths
+    /// to keys the wallet can sign with are derived.
+    fn derive_path(&self, path: &[u32]) -> Result<DerivedKeys, HDWalletError> {
+        let seed = self.seed()?;
+
+        match path {
+            [purpose, ..] if *purpose == CLASSICAL_PURPOSE | HARDENED => {
+                let path: Vec<ChildNumber> = path.iter().map(|&step| ChildNumber::from(step)).collect();
+                // The network only matters when serialising the extended key, which never happens
+                let secp = Secp256k1::new();
+                let xpriv = Xpriv::new_master(bitcoin::Network::Bitcoin, &seed)
+                    .and_then(|master| master.derive_priv(&secp, &path))
+                    .map_err(derivation_error)?;
+                Ok(DerivedKeys::Classical(xpriv.private_key))
+            }
+            [purpose, _, _, scheme, _]
+                if *purpose == QUANTUM_PURPOSE | HARDENED && path.iter().all(|step| step & HARDENED != 0) =>
+            {
+                let node = path.iter().fold(QuantumNode::master(&seed), |node, &step| node.child(step));
+                let scheme = u8::try_from(scheme & !HARDENED).ok().and_then(script::scheme_from_tag);
+
+                match scheme {
+                    Some(SignatureType::Dilithium) => Ok(DerivedKeys::Quantum(dilithium_key(&node)?)),
+                    Some(key_type) if !multisig::can_sign(key_type) => Err(HDWalletError::CannotSign(key_type)),
+                    _ => Err(HDWalletError::Derivation("unknown key scheme in derivation path".to_string())),
+                }
+            }
+            _ => Err(HDWalletError::Derivation("unsupported derivation path".to_string())),
+        }
+    }
+
+    /// Account and index of one of the wallet's addresses
+    pub fn find_address(&self, address: &str) -> Option<(&str, u32)> {
+        self.accounts.values().find_map(|account| {
+            account.addresses
+                .iter()
+                .position(|hd_address| hd_address.address == address)
+                .map(|index| (account.name.as_str(), index as u32))
+        })
+    }
+
+    /// Bytes an input spending one of the wallet's addresses adds once signed
+    ///
+    /// Multisig inputs are sized for the largest signatures their policy allows.
+    pub fn input_size(&self, script: &[u8]) -> Result<usize, HDWalletError> {
+        let address = Address::from_script(self.network, script)?;
+        let (name, index) = self.find_address(&address.to_string())
+            .ok_or_else(|| HDWalletError::AddressNotFound(address.to_string()))?;
+        let account = &self.accounts[name];
+
+        let witness = if account.is_multisig() {
+            coin_selection::multisig_witness_size(&self.multisig_policy(name, index)?)
+        } else {
+            let key = match &account.descriptor {
+                Some(descriptor) => descriptor.keys()[0].policy_key(index)?,
+                None => {
+                    let keys = self.derive_keys(name, index)?;
+                    PolicyKey {
+                        sig_type: account.account_type.key_type(),
+                        security_level: keys.security_level(),
+                        public_key: keys.public_key(),
+                    }
+                }
+            };
+            coin_selection::witness_size(key.sig_type, key.security_level, key.public_key.len())
+        };
+        Ok(coin_selection::INPUT_BASE_SIZE + witness)
+    }
+
+    /// Record the key origins of inputs and outputs that pay this wallet
+    ///
+    /// Signers holding the same mnemonic can then find their keys without
+    /// scanning every account. Returns how many origins were added.
+    pub fn update_psbt(&self, psbt: &mut PartiallySignedTransaction) -> Result<usize, HDWalletError> {
+        // Looked up on first use, as watch-only wallets have no fingerprint
+        let mut fingerprint = None;
+        let output_scripts: Vec<Vec<u8>> = psbt.unsigned_tx().outputs()
+            .iter()
+            .map(|output| output.pub_key_script().to_vec())
+            .collect();
+        let inputs = psbt.inputs.iter_mut().filter_map(|input| {
+            let script = input.spent_output.as_ref()?.pub_key_script().to_vec();
+            Some((script, &mut input.key_origins, Some(&mut input.multisig)))
+        });
+        let outputs = output_scripts.into_iter()
+            .zip(psbt.outputs.iter_mut())
+            .map(|(script, output)| (script, &mut output.key_origins, None));
+
+        let mut added = 0;
+        for (script, origins, policy) in inputs.chain(outputs) {
+            let owner = Address::from_script(self.network, &script)
+                .ok()
+                .and_then(|address| self.find_address(&address.to_string()));
+            let (name, index) = match owner {
+                Some(owner) => owner,
+                None => continue,
+            };
+            let account = &self.accounts[name];
+
+            // Multisig outputs need every participant's origin so each can find its key
+            let found = match &account.descriptor {
+                Some(descriptor) => descriptor.key_origins(index),
+                None => {
+                    let fingerprint = match fingerprint {
+                        Some(fingerprint) => fingerprint,
+                        None => *fingerprint.insert(self.fingerprint()?),
+                    };
+                    let mut found = vec![KeyOrigin {
+                        fingerprint,
+                        path: self.key_path(name, index)?,
+                    }];
+                    if let Some(setup) = &account.multisig {
+                        found.extend(setup.cosigners.iter().map(|cosigner| cosigner.key_origin(index)));
+                    }
+                    found
+                }
+            };
+            if let Some(policy) = policy.filter(|_| account.is_multisig()) {
+                *policy = Some(self.multisig_policy(name, index)?);
+            }
+
+            for origin in found {
+                if !origins.contains(&origin) {
+                    origins.push(origin);
+                    added += 1;
+                }
+            }
+        }
+        Ok(added)
+    }
+
+    /// Sign every input whose key origins name this wallet, returning how many signatures were added
+    pub fn sign_psbt(&self, psbt: &mut PartiallySignedTransaction) -> Result<usize, HDWalletError> {
+        let fingerprint = self.fingerprint()?;
+        let mut signed = 0;
+
+        for index in 0..psbt.inputs.len() {
+            let paths: Vec<Vec<u32>> = psbt.inputs[index].key_origins
+                .iter()
+                .filter(|origin| origin.fingerprint == fingerprint)
+                .map(|origin| origin.path.clone())
+                .collect();
+
+            for path in paths {
+                let witness = self.derive_path(&path)?.sign(&psbt.sighash(index)?)?;
+                psbt.add_signature(index, witness)?;
+                signed += 1;
+            }
+        }
+        Ok(signed)
+    }
+
+    /// This wallet's share of a multisig account, to hand to the other participants
+    ///
+    /// Classical accounts share their BIP-32 account public key. Post-quantum
+    /// accounts share the keys of the first `count` addresses, which bounds how
+    /// many addresses the other participants can derive until they import a
+    /// larger export.
+    pub fn export_cosigner_key(&self, account_name: &str, count: u32) -> Result<CosignerKey, HDWalletError> {
+        self.multisig_account(account_name)?;
+        self.account_public_key(account_name, count)
+    }
+
+    /// Descriptor of an account, for watching it from a wallet without its keys
+    ///
+    /// `count` bounds the addresses covered by post-quantum keys, as for
+    /// [`HDWallet::export_cosigner_key`]. Multisig accounts need all their
+    /// cosigners first.
+    pub fn export_descriptor(&self, account_name: &str, count: u32) -> Result<Descriptor, HDWalletError> {
+        let account = self.accounts.get(account_name)
+            .ok_or_else(|| HDWalletError::AccountNotFound(account_name.to_string()))?;
+        if let Some(descriptor) = &account.descriptor {
+            return Ok(descriptor.clone());
+        }
+
+        let own = self.account_public_key(account_name, count)?;
+        let descriptor = match &account.multisig {
+            None => Descriptor::KeyHash(own),
+            Some(setup) => {
+                setup.ensure_complete()?;
+                let mut keys = vec![own];
+                keys.extend(setup.cosigners.iter().cloned());
+                Descriptor::Multisig { threshold: setup.threshold, keys }
+            }
+        };
+        Ok(descriptor)
+    }
+
+    /// This wallet's public keys in an account, covering `count` post-quantum addresses
+    fn account_public_key(&self, account_name: &str, count: u32) -> Result<CosignerKey, HDWalletError> {
+        let account = self.accounts.get(account_name)
+            .ok_or_else(|| HDWalletError::AccountNotFound(account_name.to_string()))?;
+        if count == 0 {
+            return Err(MultisigError::InvalidSetup("at least one key must be exported".to_string()).into());
+        }
+        let mut origin = self.key_origin(account_name, 0)?;
+
+        let keys = if account.account_type == AccountType::Classical {
+            // Drop the change and index steps to reach the account node
+            origin.path.truncate(origin.path.len() - 2);
+            let path: Vec<ChildNumber> = origin.path.iter().map(|&step| ChildNumber::from(step)).collect();
+            let secp = Secp256k1::new();
+            let xpriv = Xpriv::new_master(bitcoin::Network::Bitcoin, &self.seed()?)
+                .and_then(|master| master.derive_priv(&secp, &path))
+                .map_err(derivation_error)?;
+            CosignerKeys::Classical(Xpub::from_priv(&secp, &xpriv))
+        } else {
+            origin.path.pop();
+            let mut security_level = 0;
+            let public_keys = (0..count)
+                .map(|index| {
+                    let keys = self.derive_keys(account_name, index)?;
+                    security_level = keys.security_level();
+                    Ok(keys.public_key())
+                })
+                .collect::<Result<Vec<_>, HDWalletError>>()?;
+            CosignerKeys::Quantum {
+                key_type: account.account_type.key_type(),
+                security_level,
+                public_keys,
+            }
+        };
+
+        Ok(CosignerKey { network: self.network, origin, keys })
+    }
+
+    /// Add another participant's [`CosignerKey`] to a multisig account
+    pub fn add_cosigner(&mut self, account_name: &str, cosigner: CosignerKey) -> Result<(), HDWalletError> {
+        let own = self.export_cosigner_key(account_name, 1)?;
+        let setup = self.accounts.get_mut(account_name)
+            .and_then(|account| account.multisig.as_mut())
+            .ok_or_else(|| HDWalletError::NotMultisig(account_name.to_string()))?;
+        setup.add_cosigner(cosigner, &own)?;
+        self.save()
+    }
+
+    /// Policy behind address `index` of a multisig account
+    pub fn multisig_policy(&self, account_name: &str, index: u32) -> Result<MultisigPolicy, HDWalletError> {
+        if let Some(descriptor) = self.accounts.get(account_name).and_then(|account| account.descriptor.as_ref()) {
+            return descriptor.policy(index)?.ok_or_else(|| HDWalletError::NotMultisig(account_name.to_string()));
+        }
+        let account = self.multisig_account(account_name)?;
+        let setup = account.multisig.as_ref().expect("multisig_account checks the setup");
+        setup.ensure_complete()?;
+
+        let own = self.derive_keys(account_name, index)?;
+        let mut keys = vec![PolicyKey {
+            sig_type: account.account_type.key_type(),
+            security_level: own.security_level(),
+            public_key: own.public_key(),
+        }];
+        for cosigner in &setup.cosigners {
+            keys.push(cosigner.policy_key(index)?);
+        }
+        Ok(MultisigPolicy::new(setup.threshold, keys).map_err(|e| MultisigError::InvalidSetup(e.to_string()))?)
+    }
+
+    fn multisig_account(&self, account_name: &str) -> Result<&HDAccount, HDWalletError> {
+        let account = self.accounts.get(account_name)
+            .ok_or_else(|| HDWalletError::AccountNotFound(account_name.to_string()))?;
+        if account.multisig.is_none() {
+            return Err(HDWalletError::NotMultisig(account_name.to_string()));
+        }
+        Ok(account)
+    }
+
+    /// BIP-39 seed of the wallet's mnemonic, without a passphrase
+    fn seed(&self) -> Result<[u8; 64], HDWalletError> {
+        self.ensure_unlocked()?;
+        if self.watch_only {
+            return Err(HDWalletError::WatchOnly);
+        }
+        let mnemonic = Mnemonic::parse_in_normalized(Language::English, &self.mnemonic)
+            .map_err(|e| HDWalletError::InvalidMnemonic(e.to_string()))?;
+        Ok(mnemonic.to_seed(""))
+    }
+
+    fn coin_type(&self) -> u32 {
+        match self.network {
+            NetworkType::Mainnet => MAINNET_COIN_TYPE,
+            NetworkType::Testnet | NetworkType::Regtest => TESTNET_COIN_TYPE,
+        }
     }
 }
 
+/// Node of the hardened-only tree post-quantum keys are derived from
+///
+/// Follows SLIP-0010's hardened derivation: each node is an HMAC-SHA512 of its
+/// parent's key under the parent's chain code.
+struct QuantumNode {
+    key: [u8; 32],
+    chain_code: [u8; 32],
+}
+
+impl QuantumNode {
+    fn master(seed: &[u8]) -> Self {
+        Self::from_hmac(QUANTUM_MASTER_KEY, &[seed])
+    }
+
+    /// Hardened child `index'`
+    fn child(&self, index: u32) -> Self {
+        let index = (index | 0x8000_0000).to_be_bytes();
+        Self::from_hmac(&self.chain_code, &[&[0u8], &self.key, &index])
+    }
+
+    fn from_hmac(key: &[u8], data: &[&[u8]]) -> Self {
+        let mut mac = Hmac::<Sha512>::new_from_slice(key).expect("HMAC accepts keys of any length");
+        for part in data {
+            mac.update(part);
+        }
+        let output = mac.finalize().into_bytes();
+
+        let mut node = Self { key: [0u8; 32], chain_code: [0u8; 32] };
+        node.key.copy_from_slice(&output[..32]);
+        node.chain_code.copy_from_slice(&output[32..]);
+        node
+    }
+}
+
+fn dilithium_key(node: &QuantumNode) -> Result<QuantumKeyPair, HDWalletError> {
+    let parameters = QuantumParameters::with_security_level(QuantumScheme::Dilithium, DILITHIUM_SECURITY_LEVEL);
+    QuantumKeyPair::from_seed(&node.key, parameters).map_err(derivation_error)
+}
+
+fn derivation_error(e: impl std::fmt::Display) -> HDWalletError {
+    HDWalletError::Derivation(e.to_string())
+}
+
 impl HDAccount {
     pub fn add_address(&mut self, address: HDAddress) {
         self.addresses.push(address);
     }
+
+    /// Whether the account's addresses pay to a multisig policy
+    pub fn is_multisig(&self) -> bool {
+        self.multisig.is_some() || matches!(self.descriptor, Some(Descriptor::Multisig { .. }))
+    }
+
+    /// Whether the wallet holds none of the account's keys
+    pub fn is_watch_only(&self) -> bool {
+        self.descriptor.is_some()
+    }
 }
 
 impl HDAddress {
@@ -183,11 +1030,351 @@ impl std::str::FromStr for AccountType {
     type Err = String;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s.to_lowercase().as_str() {
-            "legacy" => Ok(AccountType::Legacy),
-            "segwit" => Ok(AccountType::SegWit),
-            "native_segwit" => Ok(AccountType::NativeSegWit),
-            _ => Err(format!("Invalid account type: {}", s)),
+        let account_type = match s.to_lowercase().as_str() {
+            "classical" => AccountType::Classical,
+            "dilithium" | "quantum" => AccountType::Dilithium,
+            "falcon" => AccountType::Falcon,
+            "hybrid" => AccountType::Hybrid,
+            _ => return Err(format!("Invalid account type: {}", s)),
+        };
+        // Accounts are only created for keys the wallet can spend with
+        if !multisig::can_sign(account_type.key_type()) {
+            return Err(format!("{} accounts cannot sign yet", s));
+        }
+        Ok(account_type)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    const MNEMONIC: &str = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+
+    fn wallet_with_accounts(dir: &std::path::Path, file: &str) -> HDWallet {
+        let mut wallet = HDWallet::from_mnemonic(MNEMONIC, NetworkType::Testnet, dir.join(file)).unwrap();
+        for (name, account_type) in [("classical", AccountType::Classical), ("dilithium", AccountType::Dilithium)] {
+            wallet.create_account(name.to_string(), account_type).unwrap();
+        }
+        wallet
+    }
+
+    #[test]
+    fn test_recover_quantum_keys_from_mnemonic() {
+        let dir = tempdir().unwrap();
+        let mut original = wallet_with_accounts(dir.path(), "original.json");
+        let mut restored = wallet_with_accounts(dir.path(), "restored.json");
+
+        for name in ["classical", "dilithium"] {
+            let first = original.get_new_address(name).unwrap();
+            let second = original.get_new_address(name).unwrap();
+            assert_ne!(first.address, second.address);
+
+            // The restored wallet walks the same paths to the same addresses
+            assert_eq!(restored.get_new_address(name).unwrap().address, first.address);
+            assert_eq!(restored.get_new_address(name).unwrap().address, second.address);
+        }
+
+        // Post-quantum key pairs come back byte for byte, including their secrets
+        let (DerivedKeys::Quantum(key), DerivedKeys::Quantum(recovered)) =
+            (original.derive_keys("dilithium", 0).unwrap(), restored.derive_keys("dilithium", 0).unwrap())
+        else {
+            panic!("dilithium account should derive post-quantum keys");
+        };
+        assert_eq!(recovered.public_key, key.public_key);
+        assert_eq!(recovered.sign(b"recovered").unwrap(), key.sign(b"recovered").unwrap());
+
+        // A different mnemonic derives different keys
+        let mut other = HDWallet::new(NetworkType::Testnet, dir.path().join("other.json")).unwrap();
+        other.create_account("dilithium".to_string(), AccountType::Dilithium).unwrap();
+        let address = original.accounts["dilithium"].addresses[0].address.clone();
+        assert_ne!(other.get_new_address("dilithium").unwrap().address, address);
+    }
+
+    #[test]
+    fn test_sign_psbt_with_key_origins() {
+        use btclib::types::transaction::{Transaction, TransactionInput, TransactionOutput};
+
+        let dir = tempdir().unwrap();
+        let mut online = wallet_with_accounts(dir.path(), "online.json");
+        let classical: Address = online.get_new_address("classical").unwrap().address.parse().unwrap();
+        let dilithium: Address = online.get_new_address("dilithium").unwrap().address.parse().unwrap();
+        let change: Address = online.get_new_address("classical").unwrap().address.parse().unwrap();
+        let coins = [
+            TransactionOutput::new(30_000, classical.script_pubkey()),
+            TransactionOutput::new(70_000, dilithium.script_pubkey()),
+        ];
+
+        let tx = Transaction::new(
+            1,
+            vec![
+                TransactionInput::new([1u8; 32], 0, vec![], 0xffffffff),
+                TransactionInput::new([2u8; 32], 0, vec![], 0xffffffff),
+            ],
+            vec![
+                TransactionOutput::new(90_000, vec![0u8; script::P2PKH_SCRIPT_LEN]),
+                TransactionOutput::new(9_000, change.script_pubkey()),
+            ],
+            0,
+        );
+        let mut psbt = PartiallySignedTransaction::new(&tx);
+        for (input, coin) in psbt.inputs.iter_mut().zip(&coins) {
+            input.spent_output = Some(coin.clone());
+        }
+
+        // The online wallet knows which of its keys are involved
+        assert_eq!(online.update_psbt(&mut psbt).unwrap(), 3);
+        assert_eq!(psbt.inputs[1].key_origins[0], online.key_origin("dilithium", 0).unwrap());
+        assert_eq!(psbt.outputs[1].key_origins[0].path, online.key_path("classical", 1).unwrap());
+        assert!(psbt.outputs[0].key_origins.is_empty());
+        assert_eq!(online.update_psbt(&mut psbt).unwrap(), 0);
+
+        // A wallet with another mnemonic has nothing to sign
+        let stranger = HDWallet::new(NetworkType::Testnet, dir.path().join("stranger.json")).unwrap();
+        assert_eq!(stranger.sign_psbt(&mut psbt.clone()).unwrap(), 0);
+
+        // An offline copy restored from the mnemonic signs from the origins alone
+        let offline = HDWallet::from_mnemonic(MNEMONIC, NetworkType::Testnet, dir.path().join("offline.json")).unwrap();
+        assert_eq!(offline.sign_psbt(&mut psbt).unwrap(), 2);
+        psbt.finalize().unwrap();
+        let signed = psbt.extract().unwrap();
+        assert!(signed.verify_signatures(|hash, _| Some(coins[hash[0] as usize - 1].clone())).is_ok());
+    }
+
+    #[test]
+    fn test_multisig_account_with_quantum_cosigner() {
+        use btclib::types::transaction::{Transaction, TransactionInput, TransactionOutput};
+
+        let dir = tempdir().unwrap();
+        let mut participants: Vec<HDWallet> = [AccountType::Classical, AccountType::Dilithium, AccountType::Classical]
+            .iter()
+            .enumerate()
+            .map(|(i, account_type)| {
+                let path = dir.path().join(format!("participant{}.json", i));
+                let mut wallet = HDWallet::new(NetworkType::Testnet, path).unwrap();
+                wallet.create_multisig_account("treasury".to_string(), *account_type, 2, 3).unwrap();
+                wallet
+            })
+            .collect();
+
+        assert!(matches!(
+            participants[0].get_new_address("treasury"),
+            Err(HDWalletError::Multisig(MultisigError::Incomplete { have: 1, need: 3 }))
+        ));
+
+        // Every participant imports the others' exported keys
+        let exports: Vec<String> = participants
+            .iter()
+            .map(|wallet| wallet.export_cosigner_key("treasury", 5).unwrap().to_string())
+            .collect();
+        for (i, wallet) in participants.iter_mut().enumerate() {
+            for (j, export) in exports.iter().enumerate() {
+                if i != j {
+                    wallet.add_cosigner("treasury", export.parse().unwrap()).unwrap();
+                }
+            }
+        }
+
+        // ...and derives the same addresses
+        let addresses: Vec<Address> = participants
+            .iter_mut()
+            .map(|wallet| wallet.get_new_address("treasury").unwrap().address.parse().unwrap())
+            .collect();
+        assert!(addresses.iter().all(|address| *address == addresses[0]));
+        assert!(addresses[0].to_string().starts_with("tnova1m"));
+
+        let coin = TransactionOutput::new(100_000, addresses[0].script_pubkey());
+        let tx = Transaction::new(
+            1,
+            vec![TransactionInput::new([9u8; 32], 0, vec![], 0xffffffff)],
+            vec![TransactionOutput::new(99_000, vec![0u8; script::P2PKH_SCRIPT_LEN])],
+            0,
+        );
+        let mut psbt = PartiallySignedTransaction::new(&tx);
+        psbt.inputs[0].spent_output = Some(coin.clone());
+        assert_eq!(participants[0].update_psbt(&mut psbt).unwrap(), 3);
+        assert!(psbt.inputs[0].multisig.is_some());
+
+        // The post-quantum and one classical participant sign separately
+        let mut second = psbt.clone();
+        assert_eq!(participants[1].sign_psbt(&mut psbt).unwrap(), 1);
+        assert!(matches!(psbt.clone().finalize(), Err(PsbtError::MissingSignature(0))));
+        assert_eq!(participants[2].sign_psbt(&mut second).unwrap(), 1);
+
+        psbt.combine(&second).unwrap();
+        psbt.finalize().unwrap();
+        let signed = psbt.extract().unwrap();
+        assert!(signed.verify_signatures(|_, _| Some(coin.clone())).is_ok());
+    }
+
+    #[test]
+    fn test_watch_only_wallet_tracks_exported_descriptors() {
+        use btclib::types::transaction::{Transaction, TransactionInput, TransactionOutput};
+
+        let dir = tempdir().unwrap();
+        let mut spending = wallet_with_accounts(dir.path(), "spending.json");
+        let mut watching = HDWallet::new_watch_only(NetworkType::Testnet, dir.path().join("watching.json"));
+        assert!(matches!(watching.get_mnemonic(), Err(HDWalletError::WatchOnly)));
+        assert!(matches!(
+            watching.create_account("own".to_string(), AccountType::Classical),
+            Err(HDWalletError::WatchOnly)
+        ));
+
+        for name in ["classical", "dilithium"] {
+            let descriptor = spending.export_descriptor(name, 3).unwrap();
+            let parsed = Descriptor::parse(&descriptor.to_string(), NetworkType::Testnet).unwrap();
+            assert_eq!(parsed, descriptor);
+            watching.import_descriptor(name.to_string(), parsed).unwrap();
+            assert_eq!(watching.accounts[name].account_type, spending.accounts[name].account_type);
+
+            for _ in 0..3 {
+                assert_eq!(
+                    watching.get_new_address(name).unwrap().address,
+                    spending.get_new_address(name).unwrap().address
+                );
+            }
         }
+
+        // The extended key covers every classical address, post-quantum keys only those exported
+        let added = watching.fill_address_gap(5).unwrap();
+        assert_eq!(added.len(), 2);
+        assert!(added.iter().all(|(_, (name, _))| name == "classical"));
+
+        // Key origins match what the spending wallet records, so it can sign what the watcher prepares
+        let coin = TransactionOutput::new(50_000, spending.derive_address("dilithium", 1).unwrap().script_pubkey());
+        let tx = Transaction::new(
+            1,
+            vec![TransactionInput::new([3u8; 32], 0, vec![], 0xffffffff)],
+            vec![TransactionOutput::new(49_000, vec![0u8; script::P2PKH_SCRIPT_LEN])],
+            0,
+        );
+        let mut psbt = PartiallySignedTransaction::new(&tx);
+        psbt.inputs[0].spent_output = Some(coin);
+        let mut expected = psbt.clone();
+        assert_eq!(watching.update_psbt(&mut psbt).unwrap(), 1);
+        spending.update_psbt(&mut expected).unwrap();
+        assert_eq!(psbt.inputs[0].key_origins, expected.inputs[0].key_origins);
+        assert!(matches!(watching.sign_psbt(&mut psbt), Err(HDWalletError::WatchOnly)));
+        assert_eq!(spending.sign_psbt(&mut psbt).unwrap(), 1);
+
+        // Nothing secret is needed to reload the watcher
+        let reloaded = HDWallet::load(dir.path().join("watching.json")).unwrap();
+        assert!(reloaded.is_watch_only());
+        assert!(reloaded.accounts["dilithium"].is_watch_only());
+        assert_eq!(reloaded.get_address_count(), watching.get_address_count());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_quantum_account_addresses() {
+        let dir = tempdir().unwrap();
+        let mut wallet = wallet_with_accounts(dir.path(), "wallet.json");
+
+        for (name, key_type) in [("classical", SignatureType::Secp256k1), ("dilithium", SignatureType::Dilithium)] {
+            let address: Address = wallet.get_new_address(name).unwrap().address.parse().unwrap();
+            assert_eq!(address.key_type(), Some(key_type));
+            assert_eq!(address.network(), NetworkType::Testnet);
+        }
+
+        // Keys the wallet cannot sign with get no account, so nothing is paid to them
+        for account_type in [AccountType::Falcon, AccountType::Hybrid] {
+            assert!(matches!(
+                wallet.create_account("unspendable".to_string(), account_type),
+                Err(HDWalletError::CannotSign(_))
+            ));
+        }
+        assert!("falcon".parse::<AccountType>().is_err());
+        assert_eq!("quantum".parse::<AccountType>(), Ok(AccountType::Dilithium));
+
+        // Accounts of the same type on different indexes do not share keys
+        wallet.create_account("dilithium 2".to_string(), AccountType::Dilithium).unwrap();
+        assert_eq!(wallet.accounts["dilithium 2"].index, 2);
+        assert_ne!(
+            wallet.derive_keys("dilithium 2", 0).unwrap().public_key(),
+            wallet.derive_keys("dilithium", 0).unwrap().public_key()
+        );
+        assert!(matches!(
+            wallet.derive_keys("missing", 0),
+            Err(HDWalletError::AccountNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_migrate_plaintext_wallet_to_encrypted() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("wallet.json");
+        let mut wallet = wallet_with_accounts(dir.path(), "wallet.json");
+        let address = wallet.get_new_address("dilithium").unwrap().address;
+
+        // Files written before encryption load as they are, then get encrypted in place
+        let mut legacy = HDWallet::load(path.clone()).unwrap();
+        assert!(!legacy.is_encrypted());
+        legacy.encrypt("hunter2", keystore::test_params()).unwrap();
+        assert!(matches!(legacy.encrypt("again", keystore::test_params()), Err(HDWalletError::AlreadyEncrypted)));
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        assert!(!contents.contains("abandon"));
+        assert!(!contents.contains(&address));
+        assert!(matches!(HDWallet::load(path.clone()), Err(HDWalletError::Encrypted)));
+        assert!(HDWallet::is_encrypted_file(&path).unwrap());
+
+        assert!(matches!(
+            HDWallet::open(path.clone(), "hunter3"),
+            Err(HDWalletError::Keystore(KeystoreError::Decryption))
+        ));
+        let mut opened = HDWallet::open(path.clone(), "hunter2").unwrap();
+        assert_eq!(opened.get_mnemonic().unwrap(), MNEMONIC);
+        assert_eq!(opened.accounts["dilithium"].addresses[0].address, address);
+
+        // Saves keep the file encrypted
+        opened.get_new_address("classical").unwrap();
+        let reopened = HDWallet::open(path.clone(), "hunter2").unwrap();
+        assert_eq!(reopened.accounts["classical"].addresses.len(), 1);
+
+        // Changing the passphrase needs the current one
+        assert!(matches!(
+            opened.change_passphrase("wrong", "new secret", keystore::test_params()),
+            Err(HDWalletError::Keystore(KeystoreError::Decryption))
+        ));
+        opened.change_passphrase("hunter2", "new secret", keystore::test_params()).unwrap();
+        assert!(HDWallet::open(path.clone(), "hunter2").is_err());
+        assert!(HDWallet::open(path, "new secret").is_ok());
+    }
+
+    #[test]
+    fn test_lock_and_unlock() {
+        let dir = tempdir().unwrap();
+        let mut wallet = wallet_with_accounts(dir.path(), "wallet.json");
+        assert!(matches!(wallet.lock(), Err(HDWalletError::NotEncrypted)));
+        wallet.encrypt("passphrase", keystore::test_params()).unwrap();
+
+        // A locked wallet keeps its accounts but not its secrets
+        wallet.lock().unwrap();
+        assert!(wallet.is_locked());
+        assert!(wallet.get_mnemonic().is_err());
+        assert_eq!(wallet.list_accounts().len(), 4);
+        assert!(matches!(wallet.get_new_address("dilithium"), Err(HDWalletError::Locked)));
+        assert!(matches!(wallet.create_account("new".to_string(), AccountType::Classical), Err(HDWalletError::Locked)));
+        assert!(matches!(wallet.save(), Err(HDWalletError::Locked)));
+
+        assert!(wallet.unlock("wrong", None).is_err());
+        assert!(wallet.is_locked());
+        wallet.unlock("passphrase", None).unwrap();
+        assert_eq!(wallet.get_mnemonic().unwrap(), MNEMONIC);
+        wallet.get_new_address("dilithium").unwrap();
+
+        // An unlock with a timeout lapses on its own
+        wallet.lock().unwrap();
+        wallet.unlock("passphrase", Some(Duration::ZERO)).unwrap();
+        assert!(wallet.is_locked());
+        assert!(matches!(wallet.derive_keys("dilithium", 0), Err(HDWalletError::Locked)));
+        assert!(wallet.lock_if_expired());
+        assert!(!wallet.lock_if_expired());
+
+        wallet.unlock("passphrase", Some(Duration::from_secs(3600))).unwrap();
+        assert!(!wallet.is_locked());
+        assert!(!wallet.lock_if_expired());
+        wallet.set_lock_timeout(Duration::ZERO).unwrap();
+        assert!(wallet.lock_if_expired());
+    }
+}
@@ -24,7 +24,7 @@ pub enum TransactionDirection {
 }
 
 /// Transaction status
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum TransactionStatus {
     Pending,
     Confirmed(u32),  // Number of confirmations
@@ -0,0 +1,267 @@
+//! Encrypted wallet files
+//!
+//! A wallet is sealed in a JSON container: a versioned header naming the key
+//! derivation and cipher along with their parameters, then the ciphertext. The
+//! passphrase is stretched with Argon2id and the wallet is encrypted with
+//! XChaCha20-Poly1305. The header is authenticated as associated data, so its
+//! parameters cannot be altered without decryption failing.
+
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{Key, XChaCha20Poly1305, XNonce};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use thiserror::Error;
+use zeroize::Zeroizing;
+
+/// Container format written by this version of the wallet
+pub const KEYSTORE_VERSION: u32 = 1;
+
+const KDF_ARGON2ID: &str = "argon2id";
+const CIPHER_XCHACHA20POLY1305: &str = "xchacha20poly1305";
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+const KEY_LEN: usize = 32;
+
+#[derive(Debug, Error)]
+pub enum KeystoreError {
+    #[error("Unsupported wallet file version {0}")]
+    UnsupportedVersion(u32),
+    #[error("Unsupported key derivation: {0}")]
+    UnsupportedKdf(String),
+    #[error("Unsupported cipher: {0}")]
+    UnsupportedCipher(String),
+    #[error("Invalid key derivation parameters: {0}")]
+    InvalidParams(String),
+    #[error("Wrong passphrase or corrupted wallet file")]
+    Decryption,
+    #[error("Malformed wallet file: {0}")]
+    Malformed(String),
+}
+
+/// Argon2id cost parameters
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KdfParams {
+    /// Memory cost in KiB
+    pub memory_kib: u32,
+    /// Number of passes over the memory
+    pub iterations: u32,
+    /// Degree of parallelism
+    pub parallelism: u32,
+}
+
+impl Default for KdfParams {
+    fn default() -> Self {
+        Self {
+            memory_kib: 64 * 1024,
+            iterations: 3,
+            parallelism: 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct KdfHeader {
+    algorithm: String,
+    #[serde(flatten)]
+    params: KdfParams,
+    salt: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CipherHeader {
+    algorithm: String,
+    nonce: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Header {
+    version: u32,
+    kdf: KdfHeader,
+    cipher: CipherHeader,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct EncryptedFile {
+    #[serde(flatten)]
+    header: Header,
+    ciphertext: String,
+}
+
+/// Encryption key stretched from a passphrase
+///
+/// Kept by an unlocked wallet so it can be saved again without asking for the
+/// passphrase; every save uses a fresh nonce.
+#[derive(Clone)]
+pub struct WalletKey {
+    key: Zeroizing<[u8; KEY_LEN]>,
+    params: KdfParams,
+    salt: [u8; SALT_LEN],
+}
+
+impl WalletKey {
+    /// Stretch `passphrase` under a fresh random salt
+    pub fn derive(passphrase: &str, params: KdfParams) -> Result<Self, KeystoreError> {
+        let mut salt = [0u8; SALT_LEN];
+        rand::thread_rng().fill_bytes(&mut salt);
+        Self::derive_with_salt(passphrase, params, salt)
+    }
+
+    fn derive_with_salt(passphrase: &str, params: KdfParams, salt: [u8; SALT_LEN]) -> Result<Self, KeystoreError> {
+        let argon2_params = Params::new(params.memory_kib, params.iterations, params.parallelism, Some(KEY_LEN))
+            .map_err(|e| KeystoreError::InvalidParams(e.to_string()))?;
+        let mut key = Zeroizing::new([0u8; KEY_LEN]);
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, argon2_params)
+            .hash_password_into(passphrase.as_bytes(), &salt, key.as_mut())
+            .map_err(|e| KeystoreError::InvalidParams(e.to_string()))?;
+
+        Ok(Self { key, params, salt })
+    }
+
+    pub fn params(&self) -> KdfParams {
+        self.params
+    }
+
+    /// Encrypt `plaintext` into a serialized wallet file
+    pub fn seal(&self, plaintext: &[u8]) -> Result<Vec<u8>, KeystoreError> {
+        let mut nonce = [0u8; NONCE_LEN];
+        rand::thread_rng().fill_bytes(&mut nonce);
+
+        let header = Header {
+            version: KEYSTORE_VERSION,
+            kdf: KdfHeader {
+                algorithm: KDF_ARGON2ID.to_string(),
+                params: self.params,
+                salt: hex::encode(self.salt),
+            },
+            cipher: CipherHeader {
+                algorithm: CIPHER_XCHACHA20POLY1305.to_string(),
+                nonce: hex::encode(nonce),
+            },
+        };
+        let aad = associated_data(&header)?;
+        let ciphertext = self
+            .cipher()
+            .encrypt(XNonce::from_slice(&nonce), Payload { msg: plaintext, aad: &aad })
+            .map_err(|_| KeystoreError::Malformed("encryption failed".to_string()))?;
+
+        let file = EncryptedFile {
+            header,
+            ciphertext: hex::encode(ciphertext),
+        };
+        serde_json::to_vec_pretty(&file).map_err(|e| KeystoreError::Malformed(e.to_string()))
+    }
+
+    fn cipher(&self) -> XChaCha20Poly1305 {
+        XChaCha20Poly1305::new(Key::from_slice(self.key.as_ref()))
+    }
+}
+
+impl fmt::Debug for WalletKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WalletKey")
+            .field("key", &"[REDACTED]")
+            .field("params", &self.params)
+            .finish()
+    }
+}
+
+/// Whether `data` is an encrypted wallet file rather than a plaintext one
+pub fn is_encrypted(data: &[u8]) -> bool {
+    serde_json::from_slice::<EncryptedFile>(data).is_ok()
+}
+
+/// Decrypt a wallet file, returning its contents and the key that opened it
+pub fn open(data: &[u8], passphrase: &str) -> Result<(Zeroizing<Vec<u8>>, WalletKey), KeystoreError> {
+    let file: EncryptedFile = serde_json::from_slice(data)
+        .map_err(|e| KeystoreError::Malformed(e.to_string()))?;
+    let header = &file.header;
+
+    if header.version != KEYSTORE_VERSION {
+        return Err(KeystoreError::UnsupportedVersion(header.version));
+    }
+    if header.kdf.algorithm != KDF_ARGON2ID {
+        return Err(KeystoreError::UnsupportedKdf(header.kdf.algorithm.clone()));
+    }
+    if header.cipher.algorithm != CIPHER_XCHACHA20POLY1305 {
+        return Err(KeystoreError::UnsupportedCipher(header.cipher.algorithm.clone()));
+    }
+
+    let salt: [u8; SALT_LEN] = decode_fixed(&header.kdf.salt, "salt")?;
+    let nonce: [u8; NONCE_LEN] = decode_fixed(&header.cipher.nonce, "nonce")?;
+    let ciphertext = hex::decode(&file.ciphertext)
+        .map_err(|e| KeystoreError::Malformed(format!("ciphertext: {}", e)))?;
+
+    let key = WalletKey::derive_with_salt(passphrase, header.kdf.params, salt)?;
+    let aad = associated_data(header)?;
+    let plaintext = key
+        .cipher()
+        .decrypt(XNonce::from_slice(&nonce), Payload { msg: &ciphertext, aad: &aad })
+        .map_err(|_| KeystoreError::Decryption)?;
+
+    Ok((Zeroizing::new(plaintext), key))
+}
+
+fn associated_data(header: &Header) -> Result<Vec<u8>, KeystoreError> {
+    serde_json::to_vec(header).map_err(|e| KeystoreError::Malformed(e.to_string()))
+}
+
+fn decode_fixed<const N: usize>(value: &str, field: &str) -> Result<[u8; N], KeystoreError> {
+    hex::decode(value)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| KeystoreError::Malformed(format!("invalid {}", field)))
+}
+
+#[cfg(test)]
+pub(crate) fn test_params() -> KdfParams {
+    // Cheap enough to run many times in tests
+    KdfParams {
+        memory_kib: 1024,
+        iterations: 1,
+        parallelism: 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seal_and_open() {
+        let key = WalletKey::derive("correct horse", test_params()).unwrap();
+        let sealed = key.seal(b"secret wallet").unwrap();
+
+        assert!(is_encrypted(&sealed));
+        assert!(!is_encrypted(b"{\"mnemonic\": \"plain\"}"));
+        assert!(!String::from_utf8_lossy(&sealed).contains("secret wallet"));
+
+        let (plaintext, reopened) = open(&sealed, "correct horse").unwrap();
+        assert_eq!(plaintext.as_slice(), b"secret wallet");
+        assert_eq!(reopened.params(), test_params());
+
+        // Each save uses a new nonce
+        assert_ne!(key.seal(b"secret wallet").unwrap(), sealed);
+
+        assert!(matches!(open(&sealed, "wrong horse"), Err(KeystoreError::Decryption)));
+    }
+
+    #[test]
+    fn test_header_is_authenticated() {
+        let key = WalletKey::derive("passphrase", test_params()).unwrap();
+        let sealed = key.seal(b"payload").unwrap();
+
+        // Weakening the stored cost parameters breaks decryption
+        let mut file: serde_json::Value = serde_json::from_slice(&sealed).unwrap();
+        file["kdf"]["iterations"] = 2.into();
+        let tampered = serde_json::to_vec(&file).unwrap();
+        assert!(matches!(open(&tampered, "passphrase"), Err(KeystoreError::Decryption)));
+
+        // Unknown versions are refused rather than misread
+        let mut file: serde_json::Value = serde_json::from_slice(&sealed).unwrap();
+        file["version"] = 2.into();
+        let future = serde_json::to_vec(&file).unwrap();
+        assert!(matches!(open(&future, "passphrase"), Err(KeystoreError::UnsupportedVersion(2))));
+    }
+}
@@ -1,15 +1,30 @@
 mod core;
+pub mod coin_selection;
 mod hdwallet;
 mod history;
 mod ui;
 pub mod cli;
+pub mod network;
+pub mod keystore;
+pub mod multisig;
+pub mod descriptor;
+pub mod sync;
+pub mod fee_bump;
 
 use std::path::PathBuf;
+use std::time::Duration;
 use thiserror::Error;
-use bitcoin::network::Network;
-
-pub use core::Wallet;
-pub use hdwallet::{HDWallet, HDAddress, AccountType};
+use btclib::config::NetworkType;
+
+pub use core::{Wallet, UTXO};
+pub use coin_selection::{CoinSelectionError, CoinSelectionOptions};
+pub use network::{ChainSource, FeeSource, NetworkClient, NetworkError, RpcClient, TransactionBroadcaster};
+pub use keystore::{KdfParams, KeystoreError};
+pub use multisig::{CosignerKey, MultisigError, MultisigSetup};
+pub use descriptor::{Descriptor, DescriptorError};
+pub use sync::{SyncError, SyncReport, SyncState, WalletSync};
+pub use fee_bump::{BumpKind, FeeBump, FeeBumpError, FeeBumper};
+pub use hdwallet::{HDWallet, HDAddress, AccountType, DerivedKeys};
 pub use history::{TransactionHistory, TransactionRecord, TransactionDirection, TransactionStatus};
 pub use ui::tui::WalletTui;
 
@@ -21,6 +36,10 @@ pub enum WalletError {
     HDWallet(#[from] hdwallet::HDWalletError),
     #[error("History error: {0}")]
     History(#[from] history::HistoryError),
+    #[error("Sync error: {0}")]
+    Sync(#[from] SyncError),
+    #[error("Fee bump error: {0}")]
+    FeeBump(#[from] FeeBumpError),
     #[error("UI error: {0}")]
     UI(String),
 }
@@ -31,7 +50,7 @@ pub struct WalletManager {
 }
 
 impl WalletManager {
-    pub fn new(wallet_dir: PathBuf, network: Network) -> Result<Self, WalletError> {
+    pub fn new(wallet_dir: PathBuf, network: NetworkType) -> Result<Self, WalletError> {
         let wallet_path = wallet_dir.join("wallet.json");
         let history_path = wallet_dir.join("history.json");
 
@@ -57,7 +76,21 @@ impl WalletManager {
         })
     }
 
-    pub fn from_mnemonic(mnemonic: &str, wallet_dir: PathBuf, network: Network) -> Result<Self, WalletError> {
+    /// Load a wallet whose file is encrypted under `passphrase`
+    pub fn open(wallet_dir: PathBuf, passphrase: &str) -> Result<Self, WalletError> {
+        let wallet_path = wallet_dir.join("wallet.json");
+        let history_path = wallet_dir.join("history.json");
+
+        let hd_wallet = HDWallet::open(wallet_path, passphrase)?;
+        let transaction_history = TransactionHistory::new(history_path)?;
+
+        Ok(Self {
+            hd_wallet,
+            transaction_history,
+        })
+    }
+
+    pub fn from_mnemonic(mnemonic: &str, wallet_dir: PathBuf, network: NetworkType) -> Result<Self, WalletError> {
         let wallet_path = wallet_dir.join("wallet.json");
         let history_path = wallet_dir.join("history.json");
 
@@ -78,6 +111,18 @@ impl WalletManager {
         Ok(())
     }
 
+    pub fn encrypt(&mut self, passphrase: &str, params: KdfParams) -> Result<(), WalletError> {
+        self.hd_wallet.encrypt(passphrase, params).map_err(WalletError::HDWallet)
+    }
+
+    pub fn lock(&mut self) -> Result<(), WalletError> {
+        self.hd_wallet.lock().map_err(WalletError::HDWallet)
+    }
+
+    pub fn unlock(&mut self, passphrase: &str, timeout: Option<Duration>) -> Result<(), WalletError> {
+        self.hd_wallet.unlock(passphrase, timeout).map_err(WalletError::HDWallet)
+    }
+
     pub fn create_account(&mut self, name: String, account_type: AccountType) -> Result<(), WalletError> {
         self.hd_wallet.create_account(name, account_type).map_err(WalletError::HDWallet)
     }
@@ -102,6 +147,45 @@ impl WalletManager {
         self.hd_wallet.get_address_count()
     }
 
+    /// Catch up with the node behind `sync`
+    pub async fn sync<C: ChainSource>(&mut self, sync: &WalletSync<C>) -> Result<SyncReport, WalletError> {
+        Ok(sync.sync(&mut self.hd_wallet, &mut self.transaction_history).await?)
+    }
+
+    /// Scan the chain again from `height`
+    pub async fn rescan<C: ChainSource>(&mut self, sync: &WalletSync<C>, height: u64) -> Result<SyncReport, WalletError> {
+        Ok(sync.rescan(&mut self.hd_wallet, &mut self.transaction_history, height).await?)
+    }
+
+    /// Replace pending transaction `tx_hash` with one paying `fee_rate` satoshis per byte
+    pub async fn replace_by_fee<S: ChainSource>(
+        &self,
+        bumper: &FeeBumper<S>,
+        tx_hash: &[u8; 32],
+        fee_rate: u64,
+    ) -> Result<FeeBump, WalletError> {
+        Ok(bumper.replace_by_fee(&self.hd_wallet, &self.transaction_history, tx_hash, fee_rate).await?)
+    }
+
+    /// Spend the wallet's outputs of pending transaction `parent` so both pay `fee_rate` together
+    pub async fn child_pays_for_parent<S: ChainSource>(
+        &mut self,
+        bumper: &FeeBumper<S>,
+        parent: &[u8; 32],
+        fee_rate: u64,
+    ) -> Result<FeeBump, WalletError> {
+        Ok(bumper.child_pays_for_parent(&mut self.hd_wallet, &self.transaction_history, parent, fee_rate).await?)
+    }
+
+    /// Relay a fully signed fee bump and record it in the history
+    pub async fn broadcast_fee_bump<S: TransactionBroadcaster>(
+        &mut self,
+        bumper: &FeeBumper<S>,
+        bump: FeeBump,
+    ) -> Result<[u8; 32], WalletError> {
+        Ok(bumper.broadcast(bump, &mut self.transaction_history).await?)
+    }
+
     pub fn add_transaction(&mut self, record: TransactionRecord) -> Result<(), WalletError> {
         self.transaction_history.add_transaction(record).map_err(WalletError::History)
     }
@@ -167,10 +251,10 @@ mod tests {
     #[test]
     fn test_wallet_manager() {
         let dir = tempdir().unwrap();
-        let mut manager = WalletManager::new(dir.path().to_path_buf(), Network::Testnet).unwrap();
+        let mut manager = WalletManager::new(dir.path().to_path_buf(), NetworkType::Testnet).unwrap();
 
         // Create an account
-        manager.create_account("Test Account".to_string(), AccountType::NativeSegWit).unwrap();
+        manager.create_account("Test Account".to_string(), AccountType::Classical).unwrap();
 
         // Get a new address
         let address = manager.get_new_address("Test Account").unwrap();
@@ -1,8 +1,15 @@
 mod core;
+mod coin_selection;
 mod hdwallet;
 mod history;
 mod ui;
 mod cli;
+mod network;
+mod keystore;
+mod multisig;
+mod descriptor;
+mod sync;
+mod fee_bump;
 
 fn main() {
     env_logger::init();
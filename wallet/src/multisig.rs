@@ -0,0 +1,274 @@
+//! Multisig accounts
+//!
+//! A multisig account pays to an m-of-n [`MultisigPolicy`] holding one key of
+//! this wallet and one key of each cosigner for every address index. Cosigners
+//! exchange [`CosignerKey`]s, the equivalent of extended public keys: a
+//! classical cosigner shares the BIP-32 public key of its account, from which
+//! every address key follows, while post-quantum keys have no public
+//! derivation, so a post-quantum cosigner shares a batch of public keys
+//! computed ahead of time.
+
+use bitcoin::bip32::{ChildNumber, Xpub};
+use bitcoin::secp256k1::Secp256k1;
+use btclib::config::NetworkType;
+use btclib::crypto::signature::SignatureType;
+use btclib::types::psbt::{KeyOrigin, HARDENED};
+use btclib::types::script::{PolicyKey, MAX_MULTISIG_KEYS};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+use thiserror::Error;
+
+/// Format version of exported cosigner keys
+pub const COSIGNER_KEY_VERSION: u8 = 1;
+
+/// Post-quantum public keys exported when no count is given
+pub const DEFAULT_EXPORTED_KEYS: u32 = 20;
+
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum MultisigError {
+    #[error("Invalid cosigner key: {0}")]
+    Encoding(String),
+    #[error("Unsupported cosigner key version {0}")]
+    UnsupportedVersion(u8),
+    #[error("Cosigner key is for {found:?}, expected {expected:?}")]
+    WrongNetwork { expected: NetworkType, found: NetworkType },
+    #[error("Key scheme {0:?} cannot sign multisig inputs")]
+    UnsupportedKeyType(SignatureType),
+    #[error("Invalid multisig setup: {0}")]
+    InvalidSetup(String),
+    #[error("Account has {have} of {need} keys; add the remaining cosigners first")]
+    Incomplete { have: usize, need: usize },
+    #[error("Cosigner shared {available} keys, none for address {index}; import a larger export")]
+    KeysExhausted { available: usize, index: u32 },
+    #[error("Cosigner key derivation failed: {0}")]
+    Derivation(String),
+}
+
+/// Public keys a cosigner shares for a multisig account
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CosignerKeys {
+    /// Extended public key of the cosigner's account; address `i` uses child `0/i`
+    Classical(Xpub),
+    /// Keys for addresses `0..n`, in order
+    Quantum {
+        key_type: SignatureType,
+        security_level: u8,
+        public_keys: Vec<Vec<u8>>,
+    },
+}
+
+/// One participant's share of a multisig account
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CosignerKey {
+    pub network: NetworkType,
+    /// Fingerprint of the participant's wallet and the path of its account
+    pub origin: KeyOrigin,
+    pub keys: CosignerKeys,
+}
+
+impl CosignerKey {
+    pub fn key_type(&self) -> SignatureType {
+        match &self.keys {
+            CosignerKeys::Classical(_) => SignatureType::Secp256k1,
+            CosignerKeys::Quantum { key_type, .. } => *key_type,
+        }
+    }
+
+    /// The participant's key for address `index`
+    pub fn policy_key(&self, index: u32) -> Result<PolicyKey, MultisigError> {
+        match &self.keys {
+            CosignerKeys::Classical(xpub) => {
+                let path = [ChildNumber::from(0), ChildNumber::from(index)];
+                let child = xpub
+                    .derive_pub(&Secp256k1::verification_only(), &path)
+                    .map_err(|e| MultisigError::Derivation(e.to_string()))?;
+                Ok(PolicyKey {
+                    sig_type: SignatureType::Secp256k1,
+                    security_level: 0,
+                    public_key: child.public_key.serialize().to_vec(),
+                })
+            }
+            CosignerKeys::Quantum { key_type, security_level, public_keys } => {
+                let public_key = public_keys.get(index as usize).ok_or(MultisigError::KeysExhausted {
+                    available: public_keys.len(),
+                    index,
+                })?;
+                Ok(PolicyKey {
+                    sig_type: *key_type,
+                    security_level: *security_level,
+                    public_key: public_key.clone(),
+                })
+            }
+        }
+    }
+
+    /// Where the participant's key for address `index` comes from, for partially signed transactions
+    pub fn key_origin(&self, index: u32) -> KeyOrigin {
+        let mut origin = self.origin.clone();
+        match self.keys {
+            CosignerKeys::Classical(_) => origin.path.extend([0, index]),
+            CosignerKeys::Quantum { .. } => origin.path.push(index | HARDENED),
+        }
+        origin
+    }
+}
+
+/// Hex of the version byte followed by the bincode encoding, for passing between participants
+impl fmt::Display for CosignerKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut data = vec![COSIGNER_KEY_VERSION];
+        data.extend(bincode::serialize(self).map_err(|_| fmt::Error)?);
+        f.write_str(&hex::encode(data))
+    }
+}
+
+impl FromStr for CosignerKey {
+    type Err = MultisigError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let data = hex::decode(s.trim()).map_err(|e| MultisigError::Encoding(e.to_string()))?;
+        let (&version, body) = data
+            .split_first()
+            .ok_or_else(|| MultisigError::Encoding("empty key".to_string()))?;
+        if version != COSIGNER_KEY_VERSION {
+            return Err(MultisigError::UnsupportedVersion(version));
+        }
+        bincode::deserialize(body).map_err(|e| MultisigError::Encoding(e.to_string()))
+    }
+}
+
+/// Cosigners and threshold of a multisig account
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MultisigSetup {
+    /// Signatures needed to spend
+    pub threshold: u8,
+    /// Participants, this wallet included
+    pub total: u8,
+    /// Every participant except this wallet
+    pub cosigners: Vec<CosignerKey>,
+}
+
+impl MultisigSetup {
+    pub fn new(threshold: u8, total: u8) -> Result<Self, MultisigError> {
+        if total < 2 || total as usize > MAX_MULTISIG_KEYS {
+            return Err(MultisigError::InvalidSetup(format!(
+                "{} participants, expected 2 to {}", total, MAX_MULTISIG_KEYS
+            )));
+        }
+        if threshold == 0 || threshold > total {
+            return Err(MultisigError::InvalidSetup(format!("threshold {} of {}", threshold, total)));
+        }
+        Ok(Self { threshold, total, cosigners: Vec::new() })
+    }
+
+    /// Whether every cosigner has been added, so addresses can be derived
+    pub fn is_complete(&self) -> bool {
+        self.cosigners.len() + 1 == self.total as usize
+    }
+
+    pub(crate) fn ensure_complete(&self) -> Result<(), MultisigError> {
+        if !self.is_complete() {
+            return Err(MultisigError::Incomplete {
+                have: self.cosigners.len() + 1,
+                need: self.total as usize,
+            });
+        }
+        Ok(())
+    }
+
+    /// Add a participant, refusing duplicates and keys that cannot sign
+    pub(crate) fn add_cosigner(&mut self, cosigner: CosignerKey, own: &CosignerKey) -> Result<(), MultisigError> {
+        if cosigner.network != own.network {
+            return Err(MultisigError::WrongNetwork { expected: own.network, found: cosigner.network });
+        }
+        if !can_sign(cosigner.key_type()) {
+            return Err(MultisigError::UnsupportedKeyType(cosigner.key_type()));
+        }
+        if self.is_complete() {
+            return Err(MultisigError::InvalidSetup(format!("all {} participants are already present", self.total)));
+        }
+        let first_key = cosigner.policy_key(0)?;
+        let duplicate = std::iter::once(own)
+            .chain(&self.cosigners)
+            .any(|existing| existing.origin == cosigner.origin || existing.policy_key(0).ok() == Some(first_key.clone()));
+        if duplicate {
+            return Err(MultisigError::InvalidSetup("cosigner is already part of the account".to_string()));
+        }
+
+        self.cosigners.push(cosigner);
+        Ok(())
+    }
+}
+
+/// Whether keys of `key_type` can produce signatures that unlock coins
+pub(crate) fn can_sign(key_type: SignatureType) -> bool {
+    matches!(key_type, SignatureType::Secp256k1 | SignatureType::Dilithium)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::bip32::Xpriv;
+
+    fn classical_cosigner(seed: u8) -> CosignerKey {
+        let secp = Secp256k1::new();
+        let master = Xpriv::new_master(bitcoin::Network::Bitcoin, &[seed; 32]).unwrap();
+        CosignerKey {
+            network: NetworkType::Testnet,
+            origin: KeyOrigin {
+                fingerprint: master.fingerprint(&secp).to_bytes(),
+                path: vec![44 | HARDENED, 1 | HARDENED, HARDENED],
+            },
+            keys: CosignerKeys::Classical(Xpub::from_priv(&secp, &master)),
+        }
+    }
+
+    #[test]
+    fn test_cosigner_key_roundtrip() {
+        let classical = classical_cosigner(1);
+        let parsed: CosignerKey = classical.to_string().parse().unwrap();
+        assert_eq!(parsed, classical);
+        assert_eq!(parsed.key_origin(5).path, vec![44 | HARDENED, 1 | HARDENED, HARDENED, 0, 5]);
+        assert_ne!(parsed.policy_key(0).unwrap(), parsed.policy_key(1).unwrap());
+
+        let quantum = CosignerKey {
+            network: NetworkType::Testnet,
+            origin: KeyOrigin { fingerprint: [1; 4], path: vec![8844 | HARDENED] },
+            keys: CosignerKeys::Quantum {
+                key_type: SignatureType::Dilithium,
+                security_level: 3,
+                public_keys: vec![vec![7; 16], vec![8; 16]],
+            },
+        };
+        assert_eq!(quantum.policy_key(1).unwrap().public_key, vec![8; 16]);
+        assert_eq!(quantum.key_origin(1).path, vec![8844 | HARDENED, 1 | HARDENED]);
+        assert_eq!(
+            quantum.policy_key(2),
+            Err(MultisigError::KeysExhausted { available: 2, index: 2 })
+        );
+        assert!(matches!("00ff".parse::<CosignerKey>(), Err(MultisigError::UnsupportedVersion(0))));
+    }
+
+    #[test]
+    fn test_setup_rules() {
+        assert!(MultisigSetup::new(2, 1).is_err());
+        assert!(MultisigSetup::new(0, 2).is_err());
+        assert!(MultisigSetup::new(3, 2).is_err());
+
+        let own = classical_cosigner(1);
+        let mut setup = MultisigSetup::new(2, 3).unwrap();
+        assert!(setup.ensure_complete().is_err());
+        assert!(setup.add_cosigner(own.clone(), &own).is_err());
+
+        setup.add_cosigner(classical_cosigner(2), &own).unwrap();
+        assert!(setup.add_cosigner(classical_cosigner(2), &own).is_err());
+        let mut mainnet = classical_cosigner(3);
+        mainnet.network = NetworkType::Mainnet;
+        assert!(matches!(setup.add_cosigner(mainnet, &own), Err(MultisigError::WrongNetwork { .. })));
+
+        setup.add_cosigner(classical_cosigner(3), &own).unwrap();
+        assert!(setup.is_complete());
+        assert!(setup.add_cosigner(classical_cosigner(4), &own).is_err());
+    }
+}
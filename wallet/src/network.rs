@@ -1,8 +1,102 @@
-use btclib::types::Transaction;
+use async_trait::async_trait;
+use btclib::types::block::Block;
+use btclib::types::transaction::Transaction;
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+use std::sync::atomic::{AtomicU64, Ordering};
+use thiserror::Error;
+use tokio::io::AsyncWriteExt;
 use tokio::net::TcpStream;
-use std::error::Error;
 use serde::{Serialize, Deserialize};
 
+/// JSON-RPC endpoint of a node running with the default API settings
+pub const DEFAULT_RPC_URL: &str = "http://127.0.0.1:8080/rpc";
+
+#[derive(Debug, Error)]
+pub enum NetworkError {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Serialization error: {0}")]
+    Serialization(#[from] bincode::Error),
+    #[error("Transaction rejected: {0}")]
+    Rejected(String),
+    #[error("HTTP error: {0}")]
+    Http(#[from] reqwest::Error),
+    #[error("RPC error {code}: {message}")]
+    Rpc { code: i32, message: String },
+    #[error("Invalid response from node: {0}")]
+    InvalidResponse(String),
+}
+
+/// Something that can relay a signed transaction to the network
+#[async_trait]
+pub trait TransactionBroadcaster: Send + Sync {
+    async fn broadcast(&self, transaction: &Transaction) -> Result<(), NetworkError>;
+}
+
+/// Read access to a node's best chain and mempool, used to synchronize the wallet
+#[async_trait]
+pub trait ChainSource: Send + Sync {
+    /// Height of the node's best block
+    async fn tip_height(&self) -> Result<u64, NetworkError>;
+
+    /// Hash of the best chain's block at `height`, or `None` above the tip
+    async fn block_hash(&self, height: u64) -> Result<Option<[u8; 32]>, NetworkError>;
+
+    async fn block(&self, hash: &[u8; 32]) -> Result<Block, NetworkError>;
+
+    /// Transactions waiting in the node's mempool
+    async fn mempool(&self) -> Result<Vec<Transaction>, NetworkError>;
+
+    /// A transaction from the mempool or the chain, or `None` if the node does not know it
+    async fn transaction(&self, hash: &[u8; 32]) -> Result<Option<Transaction>, NetworkError>;
+}
+
+/// Something that can suggest fee rates, such as a node's fee estimator
+#[async_trait]
+pub trait FeeSource: Send + Sync {
+    /// Fee rate in satoshis per byte expected to confirm within `target` blocks,
+    /// or `None` while there is too little data for an estimate
+    async fn estimate_fee_rate(&self, target: u32) -> Result<Option<u64>, NetworkError>;
+}
+
+#[async_trait]
+impl<T: TransactionBroadcaster + ?Sized> TransactionBroadcaster for &T {
+    async fn broadcast(&self, transaction: &Transaction) -> Result<(), NetworkError> {
+        (**self).broadcast(transaction).await
+    }
+}
+
+#[async_trait]
+impl<T: ChainSource + ?Sized> ChainSource for &T {
+    async fn tip_height(&self) -> Result<u64, NetworkError> {
+        (**self).tip_height().await
+    }
+
+    async fn block_hash(&self, height: u64) -> Result<Option<[u8; 32]>, NetworkError> {
+        (**self).block_hash(height).await
+    }
+
+    async fn block(&self, hash: &[u8; 32]) -> Result<Block, NetworkError> {
+        (**self).block(hash).await
+    }
+
+    async fn mempool(&self) -> Result<Vec<Transaction>, NetworkError> {
+        (**self).mempool().await
+    }
+
+    async fn transaction(&self, hash: &[u8; 32]) -> Result<Option<Transaction>, NetworkError> {
+        (**self).transaction(hash).await
+    }
+}
+
+#[async_trait]
+impl<T: FeeSource + ?Sized> FeeSource for &T {
+    async fn estimate_fee_rate(&self, target: u32) -> Result<Option<u64>, NetworkError> {
+        (**self).estimate_fee_rate(target).await
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 enum NetworkMessage {
     BroadcastTransaction(Vec<u8>),
@@ -17,16 +111,168 @@ impl NetworkClient {
         Self { node_address }
     }
 
-    pub async fn broadcast_transaction(&self, transaction: Transaction) -> Result<(), Box<dyn Error>> {
-        let stream = TcpStream::connect(&self.node_address).await?;
-        
+    pub async fn broadcast_transaction(&self, transaction: &Transaction) -> Result<(), NetworkError> {
+        let mut stream = TcpStream::connect(&self.node_address).await?;
+
         // Serialize transaction
-        let tx_data = bincode::serialize(&transaction)?;
+        let tx_data = bincode::serialize(transaction)?;
         let message = NetworkMessage::BroadcastTransaction(tx_data);
-        
+
         // Send to node
-        bincode::serialize_into(&stream, &message)?;
-        
+        stream.write_all(&bincode::serialize(&message)?).await?;
+
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+#[async_trait]
+impl TransactionBroadcaster for NetworkClient {
+    async fn broadcast(&self, transaction: &Transaction) -> Result<(), NetworkError> {
+        self.broadcast_transaction(transaction).await
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct RpcErrorObject {
+    code: i32,
+    message: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct RpcResponse {
+    #[serde(default)]
+    result: Option<Value>,
+    #[serde(default)]
+    error: Option<RpcErrorObject>,
+}
+
+/// Client for a node's JSON-RPC API
+///
+/// Blocks and transactions travel as hex-encoded bincode, the same encoding
+/// the node stores them in.
+pub struct RpcClient {
+    url: String,
+    http: reqwest::Client,
+    next_id: AtomicU64,
+}
+
+impl RpcClient {
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            http: reqwest::Client::new(),
+            next_id: AtomicU64::new(1),
+        }
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Call `method` and decode its result
+    pub async fn call<T: DeserializeOwned>(&self, method: &str, params: Value) -> Result<T, NetworkError> {
+        let request = json!({
+            "jsonrpc": "2.0",
+            "method": method,
+            "params": params,
+            "id": self.next_id.fetch_add(1, Ordering::Relaxed),
+        });
+        let response: RpcResponse = self.http
+            .post(&self.url)
+            .json(&request)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        if let Some(error) = response.error {
+            return Err(NetworkError::Rpc { code: error.code, message: error.message });
+        }
+        serde_json::from_value(response.result.unwrap_or(Value::Null))
+            .map_err(|e| NetworkError::InvalidResponse(format!("{}: {}", method, e)))
+    }
+}
+
+/// Decode a hex-encoded bincode value returned by the node
+fn decode_hex<T: DeserializeOwned>(data: &str) -> Result<T, NetworkError> {
+    let bytes = hex::decode(data).map_err(|e| NetworkError::InvalidResponse(e.to_string()))?;
+    Ok(bincode::deserialize(&bytes)?)
+}
+
+fn decode_hash(data: &str) -> Result<[u8; 32], NetworkError> {
+    hex::decode(data)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| NetworkError::InvalidResponse(format!("invalid hash {}", data)))
+}
+
+#[async_trait]
+impl ChainSource for RpcClient {
+    async fn tip_height(&self) -> Result<u64, NetworkError> {
+        self.call("getblockcount", json!([])).await
+    }
+
+    async fn block_hash(&self, height: u64) -> Result<Option<[u8; 32]>, NetworkError> {
+        if height > self.tip_height().await? {
+            return Ok(None);
+        }
+        let hash: String = self.call("getblockhash", json!([height])).await?;
+        decode_hash(&hash).map(Some)
+    }
+
+    async fn block(&self, hash: &[u8; 32]) -> Result<Block, NetworkError> {
+        let data: String = self
+            .call("getblock", json!({ "blockhash": hex::encode(hash), "verbosity": 0 }))
+            .await?;
+        decode_hex(&data)
+    }
+
+    async fn mempool(&self) -> Result<Vec<Transaction>, NetworkError> {
+        let txids: Vec<String> = self.call("getrawmempool", json!([false])).await?;
+        let mut transactions = Vec::with_capacity(txids.len());
+        for txid in txids {
+            // Skips transactions mined or evicted since the mempool was listed
+            if let Some(transaction) = self.transaction(&decode_hash(&txid)?).await? {
+                transactions.push(transaction);
+            }
+        }
+        Ok(transactions)
+    }
+
+    async fn transaction(&self, hash: &[u8; 32]) -> Result<Option<Transaction>, NetworkError> {
+        match self.call::<String>("getrawtransaction", json!([hex::encode(hash)])).await {
+            Ok(data) => decode_hex(&data).map(Some),
+            Err(NetworkError::Rpc { .. }) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct SmartFeeResponse {
+    /// NOVA per kilobyte
+    #[serde(default)]
+    feerate: Option<f64>,
+}
+
+#[async_trait]
+impl FeeSource for RpcClient {
+    async fn estimate_fee_rate(&self, target: u32) -> Result<Option<u64>, NetworkError> {
+        let response: SmartFeeResponse = self.call("estimatesmartfee", json!([target])).await?;
+        // 1 NOVA/kB = 10^8 satoshis per 1000 bytes
+        Ok(response.feerate.map(|rate| (rate * 100_000.0).round() as u64))
+    }
+}
+
+#[async_trait]
+impl TransactionBroadcaster for RpcClient {
+    async fn broadcast(&self, transaction: &Transaction) -> Result<(), NetworkError> {
+        let data = hex::encode(bincode::serialize(transaction)?);
+        match self.call::<String>("sendrawtransaction", json!([data])).await {
+            Ok(_) => Ok(()),
+            Err(NetworkError::Rpc { message, .. }) => Err(NetworkError::Rejected(message)),
+            Err(e) => Err(e),
+        }
+    }
+}
@@ -0,0 +1,608 @@
+//! Chain synchronization
+//!
+//! [`WalletSync`] follows a node's best chain through a [`ChainSource`],
+//! scanning every new block, then the mempool, for transactions that pay the
+//! wallet's addresses or spend its coins. What it finds is kept in the
+//! wallet's [`SyncState`] and recorded in the [`TransactionHistory`].
+//!
+//! The hashes of recently scanned blocks are kept so a reorganization can be
+//! noticed: blocks the node no longer has are disconnected, undoing the coins
+//! and spends learned from them and returning their transactions to pending,
+//! before the new branch is scanned.
+//!
+//! Addresses are discovered with a gap limit: each account derives addresses
+//! ahead until it ends with `gap_limit` that have never received coins.
+
+use btclib::types::block::Block;
+use btclib::types::transaction::Transaction;
+use chrono::{DateTime, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use thiserror::Error;
+
+use crate::core::UTXO;
+use crate::hdwallet::{HDWallet, HDWalletError};
+use crate::history::{HistoryError, TransactionDirection, TransactionHistory, TransactionRecord, TransactionStatus};
+use crate::network::{ChainSource, NetworkError};
+
+/// Unused addresses each account keeps ahead of its last used one
+pub const DEFAULT_GAP_LIMIT: u32 = 20;
+
+/// Scanned blocks whose hashes are kept to detect reorganizations
+pub const REORG_DEPTH: u64 = 100;
+
+#[derive(Debug, Error)]
+pub enum SyncError {
+    #[error("Network error: {0}")]
+    Network(#[from] NetworkError),
+    #[error("HD wallet error: {0}")]
+    Wallet(#[from] HDWalletError),
+    #[error("History error: {0}")]
+    History(#[from] HistoryError),
+    #[error("Node returned the wrong block for height {0}")]
+    WrongBlock(u64),
+}
+
+/// A transaction spending one of the wallet's coins
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Spend {
+    pub tx_hash: [u8; 32],
+    /// Height of the block confirming the spend; `None` while in the mempool
+    pub height: Option<u64>,
+}
+
+/// An output paying one of the wallet's addresses
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WalletOutput {
+    pub utxo: UTXO,
+    /// Height of the block confirming the output; `None` while in the mempool
+    pub height: Option<u64>,
+    pub spent_by: Option<Spend>,
+}
+
+/// What the wallet has learned from the chain
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SyncState {
+    /// Height scanning starts from when no scanned block is kept
+    #[serde(default)]
+    start_height: u64,
+    /// Hashes of the most recently scanned blocks; the highest is the wallet's tip
+    blocks: BTreeMap<u64, [u8; 32]>,
+    outputs: Vec<WalletOutput>,
+    /// Transactions touching the wallet, by hex hash, with the height confirming them
+    transactions: BTreeMap<String, Option<u64>>,
+}
+
+impl SyncState {
+    /// Height and hash of the last scanned block
+    pub fn tip(&self) -> Option<(u64, [u8; 32])> {
+        self.blocks.iter().next_back().map(|(height, hash)| (*height, *hash))
+    }
+
+    /// Every output paying the wallet, spent or not
+    pub fn outputs(&self) -> &[WalletOutput] {
+        &self.outputs
+    }
+
+    /// Coins not spent on chain or in the mempool
+    pub fn unspent(&self) -> impl Iterator<Item = &UTXO> {
+        self.outputs.iter().filter(|output| output.spent_by.is_none()).map(|output| &output.utxo)
+    }
+
+    pub fn balance(&self, account: &str) -> u64 {
+        self.unspent()
+            .filter(|utxo| utxo.account.as_deref() == Some(account))
+            .map(|utxo| utxo.amount)
+            .sum()
+    }
+
+    /// Confirmations of a wallet transaction; 0 while unconfirmed, `None` if unknown
+    pub fn confirmations(&self, tx_hash: &[u8; 32]) -> Option<u64> {
+        let height = self.transactions.get(&hex::encode(tx_hash))?;
+        Some(match (height, self.tip()) {
+            (Some(height), Some((tip, _))) => tip.saturating_sub(*height) + 1,
+            _ => 0,
+        })
+    }
+
+    fn next_height(&self) -> u64 {
+        self.tip().map_or(self.start_height, |(height, _)| height + 1)
+    }
+
+    /// Forget everything learned from blocks at `height` and above
+    fn truncate(&mut self, height: u64) -> u64 {
+        let removed = self.blocks.split_off(&height).len() as u64;
+        self.outputs.retain(|output| output.height.map_or(true, |h| h < height));
+        for output in &mut self.outputs {
+            if output.spent_by.as_ref().map_or(false, |spend| spend.height.map_or(false, |h| h >= height)) {
+                output.spent_by = None;
+            }
+        }
+        for confirmed in self.transactions.values_mut() {
+            if confirmed.map_or(false, |h| h >= height) {
+                *confirmed = None;
+            }
+        }
+        removed
+    }
+
+    /// Disconnect the blocks above `fork`, or every kept block if the node has none of them
+    fn disconnect(&mut self, fork: Option<u64>) -> u64 {
+        match fork {
+            Some(height) => self.truncate(height + 1),
+            None => {
+                let removed = self.blocks.len() as u64;
+                self.blocks.clear();
+                self.truncate(self.start_height);
+                removed
+            }
+        }
+    }
+
+    /// Forget what was learned from the mempool, which is fetched again on every sync
+    fn clear_unconfirmed(&mut self) {
+        self.outputs.retain(|output| output.height.is_some());
+        for output in &mut self.outputs {
+            if output.spent_by.as_ref().map_or(false, |spend| spend.height.is_none()) {
+                output.spent_by = None;
+            }
+        }
+    }
+
+    fn connect_block(&mut self, height: u64, hash: [u8; 32]) {
+        self.blocks.insert(height, hash);
+        if let Some(oldest) = height.checked_sub(REORG_DEPTH) {
+            self.blocks = self.blocks.split_off(&oldest);
+        }
+    }
+}
+
+/// Outcome of a sync
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SyncReport {
+    /// Height of the wallet's tip afterwards
+    pub tip_height: Option<u64>,
+    pub blocks_scanned: u64,
+    /// Previously scanned blocks that had left the node's best chain
+    pub blocks_disconnected: u64,
+    /// Transactions recorded for the first time
+    pub new_transactions: usize,
+}
+
+/// How a transaction changes the wallet's coins
+#[derive(Debug, Default)]
+struct Effect {
+    received: u64,
+    spent: u64,
+    /// Known only when every input was the wallet's
+    fee: Option<u64>,
+}
+
+/// Keeps an [`HDWallet`] in step with a node
+pub struct WalletSync<C> {
+    source: C,
+    gap_limit: u32,
+}
+
+impl<C: ChainSource> WalletSync<C> {
+    pub fn new(source: C) -> Self {
+        Self { source, gap_limit: DEFAULT_GAP_LIMIT }
+    }
+
+    pub fn with_gap_limit(mut self, gap_limit: u32) -> Self {
+        self.gap_limit = gap_limit;
+        self
+    }
+
+    pub fn source(&self) -> &C {
+        &self.source
+    }
+
+    /// Catch up with the node's best chain and mempool, then save the wallet
+    ///
+    /// The wallet must be unlocked, since new addresses may need deriving.
+    pub async fn sync(
+        &self,
+        wallet: &mut HDWallet,
+        history: &mut TransactionHistory,
+    ) -> Result<SyncReport, SyncError> {
+        let mut report = SyncReport::default();
+        let mut scripts = wallet.watched_scripts();
+        scripts.extend(wallet.fill_address_gap(self.gap_limit)?);
+        wallet.sync_state_mut().clear_unconfirmed();
+
+        // A reorganization can happen while scanning; start over from the new fork point
+        loop {
+            let fork = self.find_fork(wallet.sync_state()).await?;
+            report.blocks_disconnected += wallet.sync_state_mut().disconnect(fork);
+            if self.scan_blocks(wallet, history, &mut scripts, &mut report).await? {
+                break;
+            }
+        }
+
+        for tx in self.source.mempool().await? {
+            self.scan_transaction(wallet, history, &mut scripts, &tx, None, Utc::now(), &mut report)?;
+        }
+
+        self.update_statuses(wallet.sync_state(), history)?;
+        report.tip_height = wallet.sync_state().tip().map(|(height, _)| height);
+        wallet.save()?;
+        Ok(report)
+    }
+
+    /// Forget what was found from `height` on and scan the chain again from there
+    pub async fn rescan(
+        &self,
+        wallet: &mut HDWallet,
+        history: &mut TransactionHistory,
+        height: u64,
+    ) -> Result<SyncReport, SyncError> {
+        let state = wallet.sync_state_mut();
+        let disconnected = state.truncate(height);
+        state.start_height = height;
+
+        let mut report = self.sync(wallet, history).await?;
+        report.blocks_disconnected += disconnected;
+        Ok(report)
+    }
+
+    /// Highest kept block that is still in the node's best chain
+    async fn find_fork(&self, state: &SyncState) -> Result<Option<u64>, SyncError> {
+        for (height, hash) in state.blocks.iter().rev() {
+            if self.source.block_hash(*height).await? == Some(*hash) {
+                return Ok(Some(*height));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Scan blocks up to the node's tip; returns false if the chain changed underneath
+    async fn scan_blocks(
+        &self,
+        wallet: &mut HDWallet,
+        history: &mut TransactionHistory,
+        scripts: &mut HashMap<Vec<u8>, (String, u32)>,
+        report: &mut SyncReport,
+    ) -> Result<bool, SyncError> {
+        let tip = self.source.tip_height().await?;
+        for height in wallet.sync_state().next_height()..=tip {
+            let hash = match self.source.block_hash(height).await? {
+                Some(hash) => hash,
+                // The node's chain got shorter
+                None => return Ok(false),
+            };
+            let block = self.source.block(&hash).await?;
+            if block.hash() != hash {
+                return Err(SyncError::WrongBlock(height));
+            }
+            if let Some((_, tip_hash)) = wallet.sync_state().tip() {
+                if block.prev_block_hash() != tip_hash {
+                    return Ok(false);
+                }
+            }
+
+            self.scan_block(wallet, history, scripts, &block, height, report)?;
+            wallet.sync_state_mut().connect_block(height, hash);
+            report.blocks_scanned += 1;
+        }
+        Ok(true)
+    }
+
+    fn scan_block(
+        &self,
+        wallet: &mut HDWallet,
+        history: &mut TransactionHistory,
+        scripts: &mut HashMap<Vec<u8>, (String, u32)>,
+        block: &Block,
+        height: u64,
+        report: &mut SyncReport,
+    ) -> Result<(), SyncError> {
+        let time = Utc
+            .timestamp_opt(block.header().timestamp() as i64, 0)
+            .single()
+            .unwrap_or_else(Utc::now);
+        for tx in block.transactions() {
+            self.scan_transaction(wallet, history, scripts, tx, Some(height), time, report)?;
+        }
+        Ok(())
+    }
+
+    /// Apply `tx` to the wallet's coins and record it if it touches them
+    #[allow(clippy::too_many_arguments)]
+    fn scan_transaction(
+        &self,
+        wallet: &mut HDWallet,
+        history: &mut TransactionHistory,
+        scripts: &mut HashMap<Vec<u8>, (String, u32)>,
+        tx: &Transaction,
+        height: Option<u64>,
+        time: DateTime<Utc>,
+        report: &mut SyncReport,
+    ) -> Result<(), SyncError> {
+        let tx_hash = tx.hash();
+        let mut effect = Effect::default();
+        let mut used = Vec::new();
+        let state = wallet.sync_state_mut();
+
+        let mut own_inputs = 0;
+        for input in tx.inputs() {
+            let spent = state.outputs.iter_mut().find(|output| {
+                output.spent_by.is_none()
+                    && output.utxo.tx_hash == input.prev_tx_hash()
+                    && output.utxo.output_index == input.prev_output_index()
+            });
+            if let Some(output) = spent {
+                output.spent_by = Some(Spend { tx_hash, height });
+                effect.spent += output.utxo.amount;
+                own_inputs += 1;
+            }
+        }
+
+        for (index, output) in tx.outputs().iter().enumerate() {
+            let Some((account, address_index)) = scripts.get(output.pub_key_script()) else {
+                continue;
+            };
+            let known = state.outputs.iter().any(|known| {
+                known.utxo.tx_hash == tx_hash && known.utxo.output_index == index as u32
+            });
+            if !known {
+                state.outputs.push(WalletOutput {
+                    utxo: UTXO {
+                        tx_hash,
+                        output_index: index as u32,
+                        amount: output.amount(),
+                        script_pubkey: output.pub_key_script().to_vec(),
+                        account: Some(account.clone()),
+                    },
+                    height,
+                    spent_by: None,
+                });
+            }
+            effect.received += output.amount();
+            used.push((account.clone(), *address_index));
+        }
+
+        if effect.received == 0 && effect.spent == 0 {
+            return Ok(());
+        }
+        if own_inputs == tx.inputs().len() && !tx.is_coinbase() {
+            let total_out: u64 = tx.outputs().iter().map(|output| output.amount()).sum();
+            effect.fee = effect.spent.checked_sub(total_out);
+        }
+        state.transactions.insert(hex::encode(tx_hash), height);
+
+        if !used.is_empty() {
+            for (account, index) in &used {
+                wallet.mark_used(account, *index);
+            }
+            scripts.extend(wallet.fill_address_gap(self.gap_limit)?);
+        }
+
+        if history.get_transaction(&hex::encode(tx_hash)).is_none() {
+            history.add_transaction(record(tx_hash, &effect, time))?;
+            report.new_transactions += 1;
+        }
+        Ok(())
+    }
+
+    /// Bring the confirmation counts in the history in line with the wallet's tip
+    fn update_statuses(&self, state: &SyncState, history: &mut TransactionHistory) -> Result<(), SyncError> {
+        let tip = state.tip().map(|(height, _)| height);
+        for (hash, height) in &state.transactions {
+            let status = match (height, tip) {
+                (Some(height), Some(tip)) => {
+                    TransactionStatus::Confirmed((tip.saturating_sub(*height) + 1) as u32)
+                }
+                _ => TransactionStatus::Pending,
+            };
+            // A replaced transaction stays failed unless it confirms after all
+            let changed = history.get_transaction(hash).map_or(false, |record| {
+                record.status != status
+                    && !(record.status == TransactionStatus::Failed && status == TransactionStatus::Pending)
+            });
+            if changed {
+                history.update_transaction_status(hash, status)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// History entry for a transaction first seen at `time`
+fn record(tx_hash: [u8; 32], effect: &Effect, time: DateTime<Utc>) -> TransactionRecord {
+    let fee = effect.fee.unwrap_or(0);
+    let (direction, amount) = if effect.received >= effect.spent {
+        (TransactionDirection::Received, effect.received - effect.spent)
+    } else {
+        // What left the wallet, less the fee
+        (TransactionDirection::Sent, (effect.spent - effect.received).saturating_sub(fee))
+    };
+    TransactionRecord {
+        hash: hex::encode(tx_hash),
+        timestamp: time,
+        direction,
+        amount,
+        fee,
+        status: TransactionStatus::Pending,
+        label: None,
+        category: None,
+        tags: vec![],
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::hdwallet::AccountType;
+    use async_trait::async_trait;
+    use btclib::config::NetworkType;
+    use btclib::types::address::Address;
+    use btclib::types::transaction::{TransactionInput, TransactionOutput};
+    use std::sync::Mutex;
+    use tempfile::tempdir;
+
+    pub(crate) const MNEMONIC: &str = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+
+    /// A node whose best chain and mempool the test sets directly
+    #[derive(Default)]
+    pub(crate) struct TestChain {
+        pub(crate) blocks: Mutex<Vec<Block>>,
+        pub(crate) mempool: Mutex<Vec<Transaction>>,
+    }
+
+    impl TestChain {
+        pub(crate) fn mine(&self, transactions: Vec<Transaction>) -> Block {
+            let mut blocks = self.blocks.lock().unwrap();
+            let prev = blocks.last().map_or([0u8; 32], |block| block.hash());
+            let block = Block::new(1, prev, transactions, u32::MAX);
+            blocks.push(block.clone());
+            block
+        }
+
+        /// Replace every block from `height` on with an empty block
+        fn reorg(&self, height: usize) {
+            self.blocks.lock().unwrap().truncate(height);
+            let coinbase = Transaction::new(1, vec![TransactionInput::new([0u8; 32], 0xffffffff, vec![9], 0)], vec![], 0);
+            self.mine(vec![coinbase]);
+        }
+    }
+
+    #[async_trait]
+    impl ChainSource for TestChain {
+        async fn tip_height(&self) -> Result<u64, NetworkError> {
+            Ok(self.blocks.lock().unwrap().len() as u64 - 1)
+        }
+
+        async fn block_hash(&self, height: u64) -> Result<Option<[u8; 32]>, NetworkError> {
+            Ok(self.blocks.lock().unwrap().get(height as usize).map(|block| block.hash()))
+        }
+
+        async fn block(&self, hash: &[u8; 32]) -> Result<Block, NetworkError> {
+            let blocks = self.blocks.lock().unwrap();
+            blocks
+                .iter()
+                .find(|block| block.hash() == *hash)
+                .cloned()
+                .ok_or_else(|| NetworkError::Rejected("unknown block".to_string()))
+        }
+
+        async fn mempool(&self) -> Result<Vec<Transaction>, NetworkError> {
+            Ok(self.mempool.lock().unwrap().clone())
+        }
+
+        async fn transaction(&self, hash: &[u8; 32]) -> Result<Option<Transaction>, NetworkError> {
+            let mempool = self.mempool.lock().unwrap();
+            let blocks = self.blocks.lock().unwrap();
+            Ok(mempool
+                .iter()
+                .chain(blocks.iter().flat_map(|block| block.transactions()))
+                .find(|tx| tx.hash() == *hash)
+                .cloned())
+        }
+    }
+
+    pub(crate) fn pay(seed: u8, address: &str, amount: u64) -> Transaction {
+        let script = address.parse::<Address>().unwrap().script_pubkey();
+        Transaction::new(
+            1,
+            vec![TransactionInput::new([seed; 32], 0, vec![], 0xffffffff)],
+            vec![TransactionOutput::new(amount, script)],
+            0,
+        )
+    }
+
+    fn setup(dir: &std::path::Path) -> (HDWallet, TransactionHistory) {
+        let mut wallet = HDWallet::from_mnemonic(MNEMONIC, NetworkType::Testnet, dir.join("wallet.json")).unwrap();
+        wallet.create_account("main".to_string(), AccountType::Classical).unwrap();
+        let history = TransactionHistory::new(dir.join("history.json")).unwrap();
+        (wallet, history)
+    }
+
+    #[tokio::test]
+    async fn test_sync_discovers_addresses_within_gap() {
+        let dir = tempdir().unwrap();
+        let (mut wallet, mut history) = setup(dir.path());
+
+        // A restored wallet has no addresses yet; the payments sit at indexes 2 and 5
+        let mut scout = HDWallet::from_mnemonic(MNEMONIC, NetworkType::Testnet, dir.path().join("scout.json")).unwrap();
+        scout.create_account("main".to_string(), AccountType::Classical).unwrap();
+        let addresses: Vec<String> = (0..6).map(|_| scout.get_new_address("main").unwrap().address).collect();
+
+        let chain = TestChain::default();
+        chain.mine(vec![]);
+        let first = chain.mine(vec![pay(1, &addresses[2], 5_000)]).transactions()[0].clone();
+        chain.mine(vec![pay(2, &addresses[5], 7_000)]);
+
+        // Index 5 is beyond a gap of 3 from the start, but within it once index 2 is used
+        let sync = WalletSync::new(chain).with_gap_limit(3);
+        let report = sync.sync(&mut wallet, &mut history).await.unwrap();
+        assert_eq!(report.tip_height, Some(2));
+        assert_eq!(report.blocks_scanned, 3);
+        assert_eq!(report.new_transactions, 2);
+        assert_eq!(wallet.get_balance("main").unwrap(), 12_000);
+        assert_eq!(wallet.get_address_count(), 9);
+
+        // Spending a coin in the mempool is reflected at once and confirmed later
+        let spend = Transaction::new(
+            1,
+            vec![TransactionInput::new(first.hash(), 0, vec![], 0xffffffff)],
+            vec![TransactionOutput::new(4_000, vec![1, 2, 3])],
+            0,
+        );
+        sync.source().mempool.lock().unwrap().push(spend.clone());
+        sync.sync(&mut wallet, &mut history).await.unwrap();
+        assert_eq!(wallet.get_balance("main").unwrap(), 7_000);
+        let record = history.get_transaction(&hex::encode(spend.hash())).unwrap();
+        assert!(matches!(record.direction, TransactionDirection::Sent));
+        assert_eq!((record.amount, record.fee), (4_000, 1_000));
+        assert_eq!(record.status, TransactionStatus::Pending);
+
+        sync.source().mempool.lock().unwrap().clear();
+        sync.source().mine(vec![spend.clone()]);
+        sync.sync(&mut wallet, &mut history).await.unwrap();
+        assert_eq!(wallet.sync_state().confirmations(&spend.hash()), Some(1));
+        assert_eq!(
+            history.get_transaction(&hex::encode(first.hash())).unwrap().status,
+            TransactionStatus::Confirmed(3)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_reorg_and_rescan() {
+        let dir = tempdir().unwrap();
+        let (mut wallet, mut history) = setup(dir.path());
+        let address = wallet.get_new_address("main").unwrap().address;
+
+        let chain = TestChain::default();
+        chain.mine(vec![]);
+        chain.mine(vec![pay(1, &address, 5_000)]);
+        let orphaned = chain.mine(vec![pay(2, &address, 8_000)]).transactions()[0].clone();
+        let sync = WalletSync::new(chain);
+        sync.sync(&mut wallet, &mut history).await.unwrap();
+        assert_eq!(wallet.get_balance("main").unwrap(), 13_000);
+
+        // Block 2 is replaced: its payment no longer counts and goes back to pending
+        sync.source().reorg(2);
+        let report = sync.sync(&mut wallet, &mut history).await.unwrap();
+        assert_eq!(report.blocks_disconnected, 1);
+        assert_eq!(report.blocks_scanned, 1);
+        assert_eq!(wallet.get_balance("main").unwrap(), 5_000);
+        assert_eq!(wallet.sync_state().tip().unwrap().1, sync.source().blocks.lock().unwrap()[2].hash());
+        assert_eq!(
+            history.get_transaction(&hex::encode(orphaned.hash())).unwrap().status,
+            TransactionStatus::Pending
+        );
+
+        // Rescanning from a height rebuilds what was found above it
+        let report = sync.rescan(&mut wallet, &mut history, 1).await.unwrap();
+        assert_eq!(report.blocks_disconnected, 2);
+        assert_eq!(report.blocks_scanned, 2);
+        assert_eq!(report.new_transactions, 0);
+        assert_eq!(wallet.get_balance("main").unwrap(), 5_000);
+
+        // The found coins survive a reload of the wallet file
+        let reloaded = HDWallet::load(dir.path().join("wallet.json")).unwrap();
+        assert_eq!(reloaded.get_balance("main").unwrap(), 5_000);
+        assert_eq!(reloaded.sync_state().tip(), wallet.sync_state().tip());
+    }
+}
@@ -1,4 +1,5 @@
 use std::io;
+use std::time::Duration;
 use ratatui::{
     backend::CrosstermBackend,
     layout::{Constraint, Direction, Layout, Rect},
@@ -14,6 +15,8 @@ use crossterm::{
 };
 use chrono::Utc;
 
+use zeroize::Zeroize;
+
 use crate::{
     hdwallet::{HDWallet, AccountType, HDAddress},
     history::{TransactionHistory, TransactionDirection, TransactionStatus, TransactionRecord},
@@ -25,6 +28,7 @@ pub enum InputMode {
     AccountCreation,
     TransactionLabeling,
     AddressDisplay,
+    Unlock,
 }
 
 #[derive(Debug)]
@@ -45,8 +49,12 @@ pub struct WalletTui {
     message: Option<Message>,
     last_generated_address: Option<HDAddress>,
     selected_transaction: Option<String>, // Transaction hash
+    lock_timeout: Option<Duration>,
 }
 
+/// How often the event loop wakes up to check the unlock timeout
+const TICK_INTERVAL: Duration = Duration::from_millis(500);
+
 #[derive(PartialEq, Clone, Copy)]
 enum Tab {
     Overview,
@@ -71,9 +79,16 @@ impl WalletTui {
             message: None,
             last_generated_address: None,
             selected_transaction: None,
+            lock_timeout: None,
         })
     }
 
+    /// Lock an encrypted wallet again `timeout` after each unlock
+    pub fn with_lock_timeout(mut self, timeout: Duration) -> Self {
+        self.lock_timeout = Some(timeout);
+        self
+    }
+
     pub fn run(&mut self) -> Result<(), io::Error> {
         enable_raw_mode()?;
         let mut stdout = io::stdout();
@@ -95,15 +110,30 @@ impl WalletTui {
     }
 
     fn run_app<B: ratatui::backend::Backend>(&mut self, terminal: &mut Terminal<B>) -> Result<(), io::Error> {
+        // The wallet was unlocked to start the TUI, so the timeout starts now
+        if let Some(timeout) = self.lock_timeout {
+            if self.wallet.is_encrypted() {
+                let _ = self.wallet.set_lock_timeout(timeout);
+            }
+        }
+
         loop {
             terminal.draw(|f| self.render(f))?;
 
+            if self.wallet.lock_if_expired() {
+                self.message = Some(Message::Info("Wallet locked after timeout. Press u to unlock".to_string()));
+            }
+
+            if !event::poll(TICK_INTERVAL)? {
+                continue;
+            }
             if let Event::Key(key) = event::read()? {
                 match self.input_mode {
                     InputMode::Normal => self.handle_normal_mode(key)?,
                     InputMode::AccountCreation => self.handle_account_creation_mode(key)?,
                     InputMode::TransactionLabeling => self.handle_transaction_labeling_mode(key)?,
                     InputMode::AddressDisplay => self.handle_address_display_mode(key)?,
+                    InputMode::Unlock => self.handle_unlock_mode(key)?,
                 }
             }
         }
@@ -150,6 +180,7 @@ impl WalletTui {
             InputMode::AccountCreation => self.render_input_prompt(f, chunks[2], "Enter account name: "),
             InputMode::TransactionLabeling => self.render_input_prompt(f, chunks[2], "Enter transaction label: "),
             InputMode::AddressDisplay => self.render_address_display(f, chunks[2]),
+            InputMode::Unlock => self.render_passphrase_prompt(f, chunks[2]),
         }
     }
 
@@ -167,6 +198,10 @@ impl WalletTui {
                 Span::styled("ERROR: ", Style::default().fg(Color::Red).add_modifier(Modifier::BOLD)),
                 Span::raw(msg)
             ]),
+            None if self.wallet.is_locked() => Line::from(vec![
+                Span::styled("LOCKED: ", Style::default().fg(Color::Red).add_modifier(Modifier::BOLD)),
+                Span::raw("Press u to unlock the wallet"),
+            ]),
             None => {
                 let help_text = match self.current_tab {
                     Tab::Overview => "Press ? for help",
@@ -198,6 +233,22 @@ impl WalletTui {
         );
     }
 
+    fn render_passphrase_prompt<B: ratatui::backend::Backend>(&self, f: &mut Frame<B>, area: Rect) {
+        let prompt = "Enter passphrase: ";
+        let masked = "*".repeat(self.input_text.chars().count());
+        let input = Paragraph::new(Line::from(vec![
+            Span::raw(prompt),
+            Span::styled(masked.clone(), Style::default().fg(Color::Yellow))
+        ]))
+        .block(Block::default().borders(Borders::ALL).title("Unlock Wallet"));
+        f.render_widget(input, area);
+        
+        f.set_cursor(
+            area.x + prompt.len() as u16 + masked.len() as u16 + 1,
+            area.y + 1,
+        );
+    }
+
     fn render_address_display<B: ratatui::backend::Backend>(&self, f: &mut Frame<B>, area: Rect) {
         let address_text = if let Some(address) = &self.last_generated_address {
             Line::from(vec![
@@ -393,6 +444,8 @@ impl WalletTui {
             Line::from("  q         - Quit application"),
             Line::from("  ?         - Show/hide help"),
             Line::from("  Esc       - Cancel current operation"),
+            Line::from("  L         - Lock wallet"),
+            Line::from("  u         - Unlock wallet"),
             Line::from(""),
             Line::from(vec![
                 Span::styled("Accounts Tab:", Style::default().add_modifier(Modifier::BOLD))
@@ -410,9 +463,10 @@ impl WalletTui {
             Line::from(vec![
                 Span::styled("Account Types:", Style::default().add_modifier(Modifier::BOLD))
             ]),
-            Line::from("  Legacy       - Traditional Bitcoin addresses (1...)"),
-            Line::from("  SegWit       - Segregated Witness addresses (3...)"),
-            Line::from("  NativeSegWit - Bech32 addresses (bc1...)"),
+            Line::from("  Classical    - secp256k1 key addresses (nova1q...)"),
+            Line::from("  Dilithium    - Dilithium key addresses (nova1p...)"),
+            Line::from("  Falcon       - Falcon key addresses (nova1z...)"),
+            Line::from("  Hybrid       - secp256k1 + Dilithium addresses (nova1r...)"),
         ];
 
         let help_text = Paragraph::new(text)
@@ -452,6 +506,20 @@ impl WalletTui {
                 }
             },
             KeyCode::Char('t') => self.current_tab = Tab::Transactions,
+            KeyCode::Char('L') => {
+                self.message = Some(match self.wallet.lock() {
+                    Ok(()) => Message::Info("Wallet locked".to_string()),
+                    Err(e) => Message::Error(format!("Failed to lock wallet: {}", e)),
+                });
+            },
+            KeyCode::Char('u') => {
+                if self.wallet.is_locked() {
+                    self.input_mode = InputMode::Unlock;
+                    self.input_text.clear();
+                } else {
+                    self.message = Some(Message::Info("Wallet is already unlocked".to_string()));
+                }
+            },
             KeyCode::Char('n') => {
                 if self.current_tab == Tab::Accounts {
                     self.input_mode = InputMode::AccountCreation;
@@ -588,6 +656,31 @@ impl WalletTui {
         Ok(())
     }
 
+    fn handle_unlock_mode(&mut self, key: KeyEvent) -> Result<(), io::Error> {
+        match key.code {
+            KeyCode::Enter => {
+                self.message = Some(match self.wallet.unlock(&self.input_text, self.lock_timeout) {
+                    Ok(()) => Message::Success("Wallet unlocked".to_string()),
+                    Err(e) => Message::Error(format!("Failed to unlock wallet: {}", e)),
+                });
+                self.input_text.zeroize();
+                self.input_mode = InputMode::Normal;
+            },
+            KeyCode::Esc => {
+                self.input_text.zeroize();
+                self.input_mode = InputMode::Normal;
+            },
+            KeyCode::Char(c) => {
+                self.input_text.push(c);
+            },
+            KeyCode::Backspace => {
+                self.input_text.pop();
+            },
+            _ => {}
+        }
+        Ok(())
+    }
+
     fn handle_address_display_mode(&mut self, key: KeyEvent) -> Result<(), io::Error> {
         match key.code {
             KeyCode::Enter | KeyCode::Esc => {
@@ -609,7 +702,7 @@ impl WalletTui {
 
     fn create_account(&mut self) {
         let account_name = self.input_text.trim().to_string();
-        match self.wallet.create_account(account_name.clone(), AccountType::NativeSegWit) {
+        match self.wallet.create_account(account_name.clone(), AccountType::Classical) {
             Ok(_) => {
                 self.message = Some(Message::Success(format!("Account '{}' created successfully", account_name)));
                 // Select the newly created account